@@ -0,0 +1,40 @@
+// Talks to the browser bridge of the desktop app (src-tauri/src/browser_bridge.rs)
+
+const DEFAULT_URL = 'http://127.0.0.1:3031';
+
+export async function loadPairing() {
+  const { url, token } = await chrome.storage.local.get(['url', 'token']);
+  return { url: url || DEFAULT_URL, token: token || '' };
+}
+
+export async function savePairing(url, token) {
+  await chrome.storage.local.set({ url: url || DEFAULT_URL, token });
+}
+
+async function call(method, path, body) {
+  const { url, token } = await loadPairing();
+  if (!token) throw new Error('Not paired yet: paste the pairing token from the app in the extension options');
+
+  let response;
+  try {
+    response = await fetch(`${url}${path}`, {
+      method,
+      headers: { Authorization: `Bearer ${token}`, 'Content-Type': 'application/json' },
+      body: body && JSON.stringify(body),
+    });
+  } catch {
+    throw new Error('The Hybrid LLM Platform app is not running');
+  }
+
+  const reply = await response.json().catch(() => ({}));
+  if (!response.ok) throw new Error(reply.error || `Request failed with ${response.status}`);
+  return reply;
+}
+
+export const getStatus = () => call('GET', '/v1/status');
+
+// `page` is { url, title, selection, text, action: 'summarize' | 'ask', question? }
+export const sendPage = (page) => call('POST', '/v1/page', page);
+
+// `page` is { url, title, html, conversation_id? }; indexes it and attaches it to the app's latest conversation
+export const ingestPage = (page) => call('POST', '/v1/ingest', page);
@@ -0,0 +1,22 @@
+import { getStatus, loadPairing, savePairing } from './bridge.js';
+
+const token = document.getElementById('token');
+const status = document.getElementById('status');
+
+loadPairing().then((pairing) => (token.value = pairing.token));
+
+document.getElementById('pairing').addEventListener('submit', async (event) => {
+  event.preventDefault();
+  const { url } = await loadPairing();
+  await savePairing(url, token.value.trim());
+  try {
+    const state = await getStatus();
+    status.className = 'muted';
+    status.textContent = state.default_llm
+      ? `Paired; pages go to ${state.default_llm}`
+      : 'Paired, but no default LLM is set in the app yet';
+  } catch (err) {
+    status.className = 'error';
+    status.textContent = err.message;
+  }
+});
@@ -0,0 +1,79 @@
+import { ingestPage, sendPage } from './bridge.js';
+
+// Same cap as MAX_PAGE_CHARS in the app, so large pages aren't sent only to be cut off there
+const MAX_PAGE_CHARS = 100000;
+
+// Keeps the HTML under the app's 4 MiB request limit
+const MAX_HTML_CHARS = 1000000;
+
+const status = document.getElementById('status');
+const answer = document.getElementById('answer');
+const buttons = document.querySelectorAll('button');
+
+// Runs in the page; only reads it
+function readPage() {
+  return {
+    selection: String(window.getSelection() || ''),
+    text: document.body ? document.body.innerText : '',
+    html: document.documentElement.outerHTML,
+  };
+}
+
+async function currentPage() {
+  const [tab] = await chrome.tabs.query({ active: true, currentWindow: true });
+  const [{ result }] = await chrome.scripting.executeScript({ target: { tabId: tab.id }, func: readPage });
+  return {
+    url: tab.url,
+    title: tab.title,
+    selection: result.selection,
+    text: result.text.slice(0, MAX_PAGE_CHARS),
+    html: result.html.slice(0, MAX_HTML_CHARS),
+  };
+}
+
+async function run(action, question) {
+  buttons.forEach((button) => (button.disabled = true));
+  status.className = 'muted';
+  status.textContent = action === 'summarize' ? 'Summarizing…' : 'Asking…';
+  answer.textContent = '';
+  try {
+    const { html, ...page } = await currentPage();
+    const reply = await sendPage({ ...page, action, question });
+    status.textContent = `${reply.llm_id} · saved as a conversation in the app`;
+    answer.textContent = reply.answer;
+  } catch (err) {
+    status.className = 'error';
+    status.textContent = err.message;
+  } finally {
+    buttons.forEach((button) => (button.disabled = false));
+  }
+}
+
+chrome.tabs.query({ active: true, currentWindow: true }).then(([tab]) => {
+  document.getElementById('source').textContent = tab.title || tab.url;
+});
+
+async function addToChat() {
+  buttons.forEach((button) => (button.disabled = true));
+  status.className = 'muted';
+  status.textContent = 'Adding…';
+  answer.textContent = '';
+  try {
+    const { url, title, html } = await currentPage();
+    const reply = await ingestPage({ url, title, html });
+    status.textContent = `Added as ${reply.chunk_count} passages to your latest conversation in the app`;
+  } catch (err) {
+    status.className = 'error';
+    status.textContent = err.message;
+  } finally {
+    buttons.forEach((button) => (button.disabled = false));
+  }
+}
+
+document.getElementById('summarize').addEventListener('click', () => run('summarize'));
+document.getElementById('add').addEventListener('click', addToChat);
+document.getElementById('ask').addEventListener('submit', (event) => {
+  event.preventDefault();
+  const question = document.getElementById('question').value.trim();
+  if (question) run('ask', question);
+});
@@ -1,13 +1,15 @@
 use common::{
     errors::{Result, HybridLLMError},
+    CancellationToken, Completion, CompletionMessage, CompletionRequest, StreamChunk, Usage,
     traits::LLMProvider,
-    types::{Capability, LLMInstance, LLMProvider as LLMProviderType},
+    types::{Capability, LLMInstance, LLMProvider as LLMProviderType, MessageRole},
 };
 use async_trait::async_trait;
 use reqwest::Client;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
-use tracing::{debug, error};
+use tracing::debug;
+
+use crate::{request_error, response_error, stream_completion};
 
 /// Claude API adapter
 pub struct ClaudeAdapter {
@@ -16,6 +18,9 @@ pub struct ClaudeAdapter {
     instance: LLMInstance,
 }
 
+/// Generated tokens when the request doesn't cap them; the API requires a cap
+const DEFAULT_MAX_TOKENS: u32 = 4096;
+
 #[derive(Serialize)]
 struct ClaudeRequest {
     model: String,
@@ -23,12 +28,57 @@ struct ClaudeRequest {
     max_tokens: u32,
     #[serde(skip_serializing_if = "Option::is_none")]
     system: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    temperature: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    top_p: Option<f32>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    stop_sequences: Vec<String>,
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize)]
 struct ClaudeMessage {
     role: String,
-    content: String,
+    content: ClaudeContent,
+}
+
+/// Plain text, or blocks when the message has images
+#[derive(Serialize)]
+#[serde(untagged)]
+enum ClaudeContent {
+    Text(String),
+    Blocks(Vec<ClaudeBlock>),
+}
+
+#[derive(Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum ClaudeBlock {
+    Image { source: ImageSource },
+    Text { text: String },
+}
+
+#[derive(Serialize)]
+struct ImageSource {
+    #[serde(rename = "type")]
+    source_type: &'static str,
+    media_type: String,
+    data: String,
+}
+
+impl From<&CompletionMessage> for ClaudeContent {
+    fn from(message: &CompletionMessage) -> Self {
+        if message.images.is_empty() {
+            return Self::Text(message.content.clone());
+        }
+        // Images go first, as Anthropic recommends
+        let images = message.images.iter().map(|image| ClaudeBlock::Image {
+            source: ImageSource { source_type: "base64", media_type: image.media_type.clone(), data: image.data.clone() },
+        });
+        let text = Some(message.content.clone())
+            .filter(|text| !text.is_empty())
+            .map(|text| ClaudeBlock::Text { text });
+        Self::Blocks(images.chain(text).collect())
+    }
 }
 
 #[derive(Deserialize)]
@@ -36,6 +86,14 @@ struct ClaudeResponse {
     content: Vec<ContentBlock>,
     #[serde(default)]
     stop_reason: Option<String>,
+    #[serde(default)]
+    usage: ClaudeUsage,
+}
+
+#[derive(Deserialize, Default)]
+struct ClaudeUsage {
+    input_tokens: u64,
+    output_tokens: u64,
 }
 
 #[derive(Deserialize)]
@@ -55,6 +113,7 @@ impl ClaudeAdapter {
                 Capability::General,
                 Capability::Analysis,
                 Capability::Creative,
+                Capability::Vision,
             ],
             model_name: model,
             max_context: 200_000, // Claude 3.5 Sonnet context window
@@ -79,26 +138,28 @@ impl LLMProvider for ClaudeAdapter {
         &self.instance
     }
 
-    async fn complete(
-        &self,
-        prompt: &str,
-        context: HashMap<String, serde_json::Value>,
-    ) -> Result<String> {
-        debug!("🤖 Calling Claude API...");
-
-        let system_prompt = context
-            .get("system")
-            .and_then(|v| v.as_str())
-            .map(|s| s.to_string());
+    async fn complete(&self, request: CompletionRequest) -> Result<Completion> {
+        debug!("🤖 Calling Claude API... (trace {:?})", request.trace_id());
 
+        // System turns have no place among the messages, so they join the system prompt
+        let system: Vec<&str> = request.system.iter().map(String::as_str)
+            .chain(request.messages.iter().filter(|m| m.role == MessageRole::System).map(|m| m.content.as_str()))
+            .collect();
         let request = ClaudeRequest {
             model: self.instance.model_name.clone(),
-            messages: vec![ClaudeMessage {
-                role: "user".to_string(),
-                content: prompt.to_string(),
-            }],
-            max_tokens: 4096,
-            system: system_prompt,
+            messages: request.messages
+                .iter()
+                .filter(|message| message.role != MessageRole::System)
+                .map(|message| ClaudeMessage {
+                    role: if message.role == MessageRole::Assistant { "assistant" } else { "user" }.to_string(),
+                    content: message.into(),
+                })
+                .collect(),
+            max_tokens: request.options.max_tokens.unwrap_or(DEFAULT_MAX_TOKENS),
+            system: Some(system.join("\n\n")).filter(|system| !system.is_empty()),
+            temperature: request.options.temperature,
+            top_p: request.options.top_p,
+            stop_sequences: request.options.stop.clone(),
         };
 
         let response = self
@@ -110,15 +171,10 @@ impl LLMProvider for ClaudeAdapter {
             .json(&request)
             .send()
             .await
-            .map_err(|e| HybridLLMError::NetworkError(e.to_string()))?;
+            .map_err(request_error)?;
 
         if !response.status().is_success() {
-            let error_text = response.text().await.unwrap_or_default();
-            error!("Claude API error: {}", error_text);
-            return Err(HybridLLMError::LLMError(format!(
-                "Claude API error: {}",
-                error_text
-            )));
+            return Err(response_error("Claude", response).await);
         }
 
         let claude_response: ClaudeResponse = response
@@ -131,38 +187,54 @@ impl LLMProvider for ClaudeAdapter {
             .first()
             .map(|block| block.text.clone())
             .unwrap_or_default();
+        let usage = Usage {
+            input_tokens: claude_response.usage.input_tokens,
+            output_tokens: claude_response.usage.output_tokens,
+            ..Usage::default()
+        };
 
-        Ok(text)
+        Ok(Completion { content: text, usage })
     }
 
     async fn complete_stream(
         &self,
-        prompt: &str,
-        context: HashMap<String, serde_json::Value>,
-    ) -> Result<tokio::sync::mpsc::Receiver<Result<String>>> {
+        request: CompletionRequest,
+        cancel: CancellationToken,
+    ) -> Result<tokio::sync::mpsc::Receiver<Result<StreamChunk>>> {
         // TODO: Implement streaming
         // For now, return non-streaming response
-        let (tx, rx) = tokio::sync::mpsc::channel(1);
-        let result = self.complete(prompt, context).await;
-
-        tokio::spawn(async move {
-            let _ = tx.send(result).await;
-        });
+        // Dropping the request future aborts the HTTP request
+        let result = tokio::select! {
+            result = self.complete(request) => result,
+            _ = cancel.cancelled() => return Ok(tokio::sync::mpsc::channel(1).1),
+        };
 
-        Ok(rx)
+        Ok(stream_completion(result?))
     }
 
     async fn health_check(&self) -> Result<bool> {
-        // Simple health check - could ping the API
-        Ok(true)
+        // Looking up the model checks the key and the model name without spending tokens
+        let response = self
+            .client
+            .get(format!("https://api.anthropic.com/v1/models/{}", self.instance.model_name))
+            .header("x-api-key", &self.api_key)
+            .header("anthropic-version", "2023-06-01")
+            .send()
+            .await
+            .map_err(request_error)?;
+
+        if !response.status().is_success() {
+            debug!("Claude health check failed: {}", response.status());
+        }
+        Ok(response.status().is_success())
     }
 
-    async fn load(&mut self) -> Result<()> {
+    async fn load(&self) -> Result<()> {
         // Cloud models don't need loading
         Ok(())
     }
 
-    async fn unload(&mut self) -> Result<()> {
+    async fn unload(&self) -> Result<()> {
         // Cloud models don't need unloading
         Ok(())
     }
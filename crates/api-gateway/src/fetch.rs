@@ -0,0 +1,275 @@
+use common::errors::{HybridLLMError, Result};
+use reqwest::{redirect, Client, Url};
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tracing::{debug, info};
+
+use crate::request_error;
+
+/// Name sites can address in their robots.txt
+const ROBOTS_AGENT: &str = "HybridLLM";
+
+/// How long a site's robots.txt is trusted before it is fetched again
+const ROBOTS_TTL: Duration = Duration::from_secs(60 * 60);
+
+/// Redirects followed within one site; leaving it needs a new, separately permitted fetch
+const MAX_REDIRECTS: usize = 5;
+
+/// Content types worth reading as text
+const TEXT_TYPES: &[&str] = &["application/xhtml+xml", "application/json", "application/xml", "application/rss+xml"];
+
+/// A downloaded page
+#[derive(Debug, Clone)]
+pub struct FetchedPage {
+    /// Where the page ended up after redirects
+    pub url: String,
+    /// Without parameters, e.g. "text/html"
+    pub content_type: String,
+    pub body: String,
+    /// The page was longer than the size limit and was cut off
+    pub truncated: bool,
+}
+
+impl FetchedPage {
+    pub fn is_html(&self) -> bool {
+        matches!(self.content_type.as_str(), "text/html" | "application/xhtml+xml")
+    }
+}
+
+/// Downloads text pages within a size and time limit, optionally honouring robots.txt
+pub struct PageFetcher {
+    client: Client,
+    max_bytes: usize,
+    respect_robots: bool,
+    /// robots.txt by origin; empty when the site has none
+    robots: Mutex<HashMap<String, (Instant, String)>>,
+}
+
+impl PageFetcher {
+    pub fn new(max_bytes: usize, timeout: Duration, respect_robots: bool) -> Self {
+        // Redirects to another host would skip the permission check of that host
+        let policy = redirect::Policy::custom(|attempt| {
+            let same_host = attempt.previous().first().map(Url::host_str) == Some(attempt.url().host_str());
+            if same_host && attempt.previous().len() <= MAX_REDIRECTS {
+                attempt.follow()
+            } else {
+                attempt.stop()
+            }
+        });
+        let client = Client::builder()
+            .timeout(timeout)
+            .redirect(policy)
+            .user_agent(concat!("HybridLLM/", env!("CARGO_PKG_VERSION"), " (fetch_url)"))
+            .build()
+            .unwrap_or_else(|_| Client::new());
+        Self { client, max_bytes, respect_robots, robots: Mutex::new(HashMap::new()) }
+    }
+
+    /// Download `url`; refuses non-text content, pages robots.txt disallows and redirects to other hosts
+    pub async fn fetch(&self, url: &str) -> Result<FetchedPage> {
+        let url = Url::parse(url).map_err(|e| HybridLLMError::InvalidRequest(format!("Invalid URL {}: {}", url, e)))?;
+        if !matches!(url.scheme(), "http" | "https") {
+            return Err(HybridLLMError::InvalidRequest(format!("Only http(s) URLs can be fetched, not {}", url)));
+        }
+        if self.respect_robots && !self.robots_allow(&url).await {
+            return Err(HybridLLMError::PermissionDenied(format!("{}'s robots.txt disallows {}", origin(&url), url.path())));
+        }
+
+        let mut response = self.client.get(url.clone()).send().await.map_err(request_error)?;
+        let status = response.status();
+        if status.is_redirection() {
+            let location = response
+                .headers()
+                .get(reqwest::header::LOCATION)
+                .and_then(|value| value.to_str().ok())
+                .and_then(|location| response.url().join(location).ok());
+            return Err(HybridLLMError::InvalidRequest(match location {
+                Some(location) => format!("{} redirects to {}; fetch that URL to follow it", url, location),
+                None => format!("{} redirects too often", url),
+            }));
+        }
+        if !status.is_success() {
+            return Err(HybridLLMError::NetworkError(format!("{} returned {}", url, status)));
+        }
+
+        let content_type = response
+            .headers()
+            .get(reqwest::header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.split(';').next())
+            .map(|essence| essence.trim().to_ascii_lowercase())
+            .unwrap_or_else(|| "text/html".to_string());
+        if !(content_type.starts_with("text/") || TEXT_TYPES.contains(&content_type.as_str())) {
+            return Err(HybridLLMError::InvalidRequest(format!("{} is {}, not a text page", url, content_type)));
+        }
+
+        let final_url = response.url().to_string();
+        let mut body = Vec::new();
+        let mut truncated = false;
+        while let Some(chunk) = response.chunk().await.map_err(request_error)? {
+            let room = self.max_bytes - body.len();
+            if chunk.len() > room {
+                body.extend_from_slice(&chunk[..room]);
+                truncated = true;
+                break;
+            }
+            body.extend_from_slice(&chunk);
+        }
+
+        info!(
+            "🌐 Fetched {} ({}, {} bytes{})",
+            final_url,
+            content_type,
+            body.len(),
+            if truncated { ", truncated" } else { "" }
+        );
+        Ok(FetchedPage { url: final_url, content_type, body: String::from_utf8_lossy(&body).into_owned(), truncated })
+    }
+
+    async fn robots_allow(&self, url: &Url) -> bool {
+        let origin = origin(url);
+        let cached = self
+            .robots
+            .lock()
+            .unwrap()
+            .get(&origin)
+            .filter(|(at, _)| at.elapsed() < ROBOTS_TTL)
+            .map(|(_, robots)| robots.clone());
+        let robots = match cached {
+            Some(robots) => robots,
+            None => {
+                // Sites without a readable robots.txt allow everything
+                let robots = match self.client.get(format!("{}/robots.txt", origin)).send().await {
+                    Ok(response) if response.status().is_success() => response.text().await.unwrap_or_default(),
+                    Ok(_) => String::new(),
+                    Err(e) => {
+                        debug!("robots.txt of {} unavailable: {}", origin, e.without_url());
+                        String::new()
+                    }
+                };
+                self.robots.lock().unwrap().insert(origin, (Instant::now(), robots.clone()));
+                robots
+            }
+        };
+
+        let path = match url.query() {
+            Some(query) => format!("{}?{}", url.path(), query),
+            None => url.path().to_string(),
+        };
+        robots_allow(&robots, ROBOTS_AGENT, &path)
+    }
+}
+
+fn origin(url: &Url) -> String {
+    url.origin().ascii_serialization()
+}
+
+/// User agents of a robots.txt group and its rules as (allow, pattern)
+type RobotsGroup = (Vec<String>, Vec<(bool, String)>);
+
+/// Whether robots.txt lets `agent` fetch `path`; the longest matching rule wins, `Allow` on ties
+fn robots_allow(robots: &str, agent: &str, path: &str) -> bool {
+    let mut groups: Vec<RobotsGroup> = Vec::new();
+    for line in robots.lines() {
+        let line = line.split('#').next().unwrap_or_default();
+        let Some((key, value)) = line.split_once(':') else {
+            continue;
+        };
+        let value = value.trim();
+        match key.trim().to_ascii_lowercase().as_str() {
+            "user-agent" => match groups.last_mut() {
+                // Consecutive user-agent lines share the rules that follow
+                Some((agents, rules)) if rules.is_empty() => agents.push(value.to_ascii_lowercase()),
+                _ => groups.push((vec![value.to_ascii_lowercase()], Vec::new())),
+            },
+            key @ ("allow" | "disallow") => {
+                if let Some((_, rules)) = groups.last_mut() {
+                    rules.push((key == "allow", value.to_string()));
+                }
+            }
+            _ => {}
+        }
+    }
+
+    let agent = agent.to_ascii_lowercase();
+    let named: Vec<_> = groups
+        .iter()
+        .filter(|(agents, _)| agents.iter().any(|name| name != "*" && agent.contains(name.as_str())))
+        .collect();
+    let applicable = if named.is_empty() {
+        groups.iter().filter(|(agents, _)| agents.iter().any(|name| name == "*")).collect()
+    } else {
+        named
+    };
+
+    applicable
+        .into_iter()
+        .flat_map(|(_, rules)| rules)
+        .filter(|(_, pattern)| !pattern.is_empty() && robots_match(pattern, path))
+        .max_by_key(|(allow, pattern)| (pattern.len(), *allow))
+        .is_none_or(|(allow, _)| *allow)
+}
+
+/// Match a robots.txt path pattern, where `*` is any run of characters and a final `$` anchors the end
+fn robots_match(pattern: &str, path: &str) -> bool {
+    let (pattern, anchored) = match pattern.strip_suffix('$') {
+        Some(pattern) => (pattern, true),
+        None => (pattern, false),
+    };
+    let mut parts = pattern.split('*');
+    let Some(mut rest) = path.strip_prefix(parts.next().unwrap_or_default()) else {
+        return false;
+    };
+    let parts: Vec<_> = parts.collect();
+    for (i, part) in parts.iter().enumerate() {
+        if anchored && i == parts.len() - 1 {
+            return rest.ends_with(part);
+        }
+        match rest.find(part) {
+            Some(at) => rest = &rest[at + part.len()..],
+            None => return false,
+        }
+    }
+    !anchored || rest.is_empty()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_robots_match() {
+        assert!(robots_match("/private", "/private/a"));
+        assert!(!robots_match("/private", "/public"));
+        assert!(robots_match("/*.pdf$", "/docs/a.pdf"));
+        assert!(!robots_match("/*.pdf$", "/docs/a.pdf?x=1"));
+        assert!(robots_match("/a*/c", "/ab/c/d"));
+        assert!(robots_match("/exact$", "/exact"));
+        assert!(!robots_match("/exact$", "/exactly"));
+    }
+
+    #[test]
+    fn test_robots_allow() {
+        let robots = "
+            # Everyone
+            User-agent: *
+            Disallow: /private
+            Allow: /private/open
+
+            User-agent: BadBot
+            User-agent: HybridLLM
+            Disallow: /
+            Allow: /docs
+        ";
+        assert!(robots_allow(robots, "SomeBot", "/"));
+        assert!(!robots_allow(robots, "SomeBot", "/private/x"));
+        assert!(robots_allow(robots, "SomeBot", "/private/open/x"));
+
+        assert!(!robots_allow(robots, ROBOTS_AGENT, "/blog"));
+        assert!(robots_allow(robots, ROBOTS_AGENT, "/docs/intro"));
+
+        assert!(robots_allow("", ROBOTS_AGENT, "/anything"));
+        assert!(robots_allow("User-agent: *\nDisallow:\n", ROBOTS_AGENT, "/anything"));
+    }
+}
@@ -1,13 +1,15 @@
 use common::{
     errors::{Result, HybridLLMError},
+    CancellationToken, Completion, CompletionMessage, CompletionRequest, StreamChunk, Usage,
     traits::LLMProvider,
-    types::{Capability, LLMInstance, LLMProvider as LLMProviderType},
+    types::{Capability, LLMInstance, LLMProvider as LLMProviderType, MessageRole},
 };
 use async_trait::async_trait;
 use reqwest::Client;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
-use tracing::{debug, error};
+use tracing::debug;
+
+use crate::{request_error, response_error, stream_completion};
 
 /// Google Gemini API adapter
 pub struct GeminiAdapter {
@@ -17,23 +19,82 @@ pub struct GeminiAdapter {
 }
 
 #[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
 struct GeminiRequest {
     contents: Vec<Content>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    system_instruction: Option<Content>,
+    generation_config: GenerationConfig,
 }
 
 #[derive(Serialize, Deserialize)]
 struct Content {
+    /// `user` or `model`; absent on system instructions
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    role: Option<String>,
     parts: Vec<Part>,
 }
 
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct GenerationConfig {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    max_output_tokens: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    temperature: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    top_p: Option<f32>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    stop_sequences: Vec<String>,
+}
+
+/// Text or an image; one of the two is set
 #[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
 struct Part {
-    text: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    text: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    inline_data: Option<InlineData>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct InlineData {
+    mime_type: String,
+    /// Base64 encoded bytes
+    data: String,
+}
+
+impl Part {
+    fn text(text: &str) -> Self {
+        Self { text: Some(text.to_string()), inline_data: None }
+    }
+
+    /// The message's text followed by its images
+    fn of(message: &CompletionMessage) -> Vec<Self> {
+        let text = Some(message.content.as_str()).filter(|text| !text.is_empty() || message.images.is_empty());
+        let images = message.images.iter().map(|image| Self {
+            text: None,
+            inline_data: Some(InlineData { mime_type: image.media_type.clone(), data: image.data.clone() }),
+        });
+        text.map(Self::text).into_iter().chain(images).collect()
+    }
 }
 
 #[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
 struct GeminiResponse {
     candidates: Vec<Candidate>,
+    #[serde(default)]
+    usage_metadata: GeminiUsage,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(rename_all = "camelCase", default)]
+struct GeminiUsage {
+    prompt_token_count: u64,
+    candidates_token_count: u64,
 }
 
 #[derive(Deserialize)]
@@ -51,6 +112,7 @@ impl GeminiAdapter {
                 Capability::General,
                 Capability::Analysis,
                 Capability::Creative,
+                Capability::Vision,
             ],
             model_name: model,
             max_context: 1_000_000, // Gemini 1.5 Pro context
@@ -75,19 +137,31 @@ impl LLMProvider for GeminiAdapter {
         &self.instance
     }
 
-    async fn complete(
-        &self,
-        prompt: &str,
-        _context: HashMap<String, serde_json::Value>,
-    ) -> Result<String> {
-        debug!("🤖 Calling Gemini API...");
+    async fn complete(&self, request: CompletionRequest) -> Result<Completion> {
+        debug!("🤖 Calling Gemini API... (trace {:?})", request.trace_id());
 
+        // System turns have no place among the contents, so they join the system instruction
+        let system: Vec<Part> = request.system.iter()
+            .chain(request.messages.iter().filter(|m| m.role == MessageRole::System).map(|m| &m.content))
+            .map(|text| Part::text(text))
+            .collect();
+        let options = &request.options;
         let request = GeminiRequest {
-            contents: vec![Content {
-                parts: vec![Part {
-                    text: prompt.to_string(),
-                }],
-            }],
+            contents: request.messages
+                .iter()
+                .filter(|message| message.role != MessageRole::System)
+                .map(|message| Content {
+                    role: Some(if message.role == MessageRole::Assistant { "model" } else { "user" }.to_string()),
+                    parts: Part::of(message),
+                })
+                .collect(),
+            system_instruction: Some(Content { role: None, parts: system }).filter(|content| !content.parts.is_empty()),
+            generation_config: GenerationConfig {
+                max_output_tokens: options.max_tokens,
+                temperature: options.temperature,
+                top_p: options.top_p,
+                stop_sequences: options.stop.clone(),
+            },
         };
 
         let url = format!(
@@ -102,15 +176,10 @@ impl LLMProvider for GeminiAdapter {
             .json(&request)
             .send()
             .await
-            .map_err(|e| HybridLLMError::NetworkError(e.to_string()))?;
+            .map_err(request_error)?;
 
         if !response.status().is_success() {
-            let error_text = response.text().await.unwrap_or_default();
-            error!("Gemini API error: {}", error_text);
-            return Err(HybridLLMError::LLMError(format!(
-                "Gemini API error: {}",
-                error_text
-            )));
+            return Err(response_error("Gemini", response).await);
         }
 
         let gemini_response: GeminiResponse = response
@@ -122,37 +191,56 @@ impl LLMProvider for GeminiAdapter {
             .candidates
             .first()
             .and_then(|c| c.content.parts.first())
-            .map(|p| p.text.clone())
+            .and_then(|p| p.text.clone())
             .unwrap_or_default();
+        let usage = Usage {
+            input_tokens: gemini_response.usage_metadata.prompt_token_count,
+            output_tokens: gemini_response.usage_metadata.candidates_token_count,
+            ..Usage::default()
+        };
 
-        Ok(text)
+        Ok(Completion { content: text, usage })
     }
 
     async fn complete_stream(
         &self,
-        prompt: &str,
-        context: HashMap<String, serde_json::Value>,
-    ) -> Result<tokio::sync::mpsc::Receiver<Result<String>>> {
+        request: CompletionRequest,
+        cancel: CancellationToken,
+    ) -> Result<tokio::sync::mpsc::Receiver<Result<StreamChunk>>> {
         // TODO: Implement streaming
-        let (tx, rx) = tokio::sync::mpsc::channel(1);
-        let result = self.complete(prompt, context).await;
-
-        tokio::spawn(async move {
-            let _ = tx.send(result).await;
-        });
+        // Dropping the request future aborts the HTTP request
+        let result = tokio::select! {
+            result = self.complete(request) => result,
+            _ = cancel.cancelled() => return Ok(tokio::sync::mpsc::channel(1).1),
+        };
 
-        Ok(rx)
+        Ok(stream_completion(result?))
     }
 
     async fn health_check(&self) -> Result<bool> {
-        Ok(true)
+        // Looking up the model checks the key and the model name without spending tokens
+        let url = format!(
+            "https://generativelanguage.googleapis.com/v1beta/models/{}?key={}",
+            self.instance.model_name, self.api_key
+        );
+        let response = self
+            .client
+            .get(&url)
+            .send()
+            .await
+            .map_err(request_error)?;
+
+        if !response.status().is_success() {
+            debug!("Gemini health check failed: {}", response.status());
+        }
+        Ok(response.status().is_success())
     }
 
-    async fn load(&mut self) -> Result<()> {
+    async fn load(&self) -> Result<()> {
         Ok(())
     }
 
-    async fn unload(&mut self) -> Result<()> {
+    async fn unload(&self) -> Result<()> {
         Ok(())
     }
 }
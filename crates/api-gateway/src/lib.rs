@@ -1,7 +1,81 @@
+use common::{
+    errors::{HybridLLMError, Result},
+    Completion, StreamChunk,
+};
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tracing::error;
+
 mod claude;
 mod openai;
 mod gemini;
+mod fetch;
+mod web_search;
 
 pub use claude::ClaudeAdapter;
 pub use openai::OpenAIAdapter;
 pub use gemini::GeminiAdapter;
+pub use fetch::{FetchedPage, PageFetcher};
+pub use web_search::{SearchEngine, SearchHit, WebSearch};
+
+/// Stream a finished completion as its text followed by its usage
+fn stream_completion(completion: Completion) -> mpsc::Receiver<Result<StreamChunk>> {
+    let (tx, rx) = mpsc::channel(2);
+    tokio::spawn(async move {
+        if tx.send(Ok(StreamChunk::Text(completion.content))).await.is_ok() {
+            let _ = tx.send(Ok(StreamChunk::Usage(completion.usage))).await;
+        }
+    });
+    rx
+}
+
+/// A request that never got a response; the URL is left out as it may carry the key
+fn request_error(e: reqwest::Error) -> HybridLLMError {
+    if e.is_timeout() {
+        HybridLLMError::Timeout(e.without_url().to_string())
+    } else {
+        HybridLLMError::NetworkError(e.without_url().to_string())
+    }
+}
+
+/// The error for an API response that isn't a success, classified by its status
+async fn response_error(provider: &str, response: reqwest::Response) -> HybridLLMError {
+    let status = response.status().as_u16();
+    let retry_after = response
+        .headers()
+        .get(reqwest::header::RETRY_AFTER)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.trim().parse().ok())
+        .map(Duration::from_secs);
+    let message = response.text().await.unwrap_or_default();
+    error!("{} API error {}: {}", provider, status, message);
+    status_error(provider, status, retry_after, message)
+}
+
+fn status_error(provider: &str, status: u16, retry_after: Option<Duration>, message: String) -> HybridLLMError {
+    let provider = provider.to_string();
+    match status {
+        429 => HybridLLMError::RateLimited { provider, message, retry_after },
+        // 529 is Anthropic's "overloaded"
+        500 | 502 | 503 | 504 | 529 => HybridLLMError::ProviderOverloaded { provider, message, retry_after },
+        401 | 403 => HybridLLMError::AuthenticationFailed { provider, message },
+        400 | 404 | 413 | 422 => HybridLLMError::InvalidRequest(format!("{} API error {}: {}", provider, status, message)),
+        _ => HybridLLMError::LLMError(format!("{} API error {}: {}", provider, status, message)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_status_error() {
+        let limited = status_error("OpenAI", 429, Some(Duration::from_secs(7)), "slow down".to_string());
+        assert!(limited.is_retryable());
+        assert_eq!(limited.retry_after(), Some(Duration::from_secs(7)));
+
+        assert!(status_error("Claude", 529, None, "overloaded".to_string()).is_retryable());
+        assert!(!status_error("Claude", 401, None, "invalid x-api-key".to_string()).is_retryable());
+        assert!(!status_error("Gemini", 400, None, "bad field".to_string()).is_retryable());
+    }
+}
@@ -1,13 +1,15 @@
 use common::{
     errors::{Result, HybridLLMError},
+    CancellationToken, Completion, CompletionMessage, CompletionRequest, StreamChunk, Usage,
     traits::LLMProvider,
-    types::{Capability, LLMInstance, LLMProvider as LLMProviderType},
+    types::{Capability, LLMInstance, LLMProvider as LLMProviderType, MessageRole},
 };
 use async_trait::async_trait;
 use reqwest::Client;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
-use tracing::{debug, error};
+use tracing::debug;
+
+use crate::{request_error, response_error, stream_completion};
 
 /// OpenAI API adapter
 pub struct OpenAIAdapter {
@@ -16,22 +18,79 @@ pub struct OpenAIAdapter {
     instance: LLMInstance,
 }
 
+/// Generated tokens when the request doesn't cap them
+const DEFAULT_MAX_TOKENS: u32 = 4096;
+
 #[derive(Serialize)]
 struct OpenAIRequest {
     model: String,
-    messages: Vec<OpenAIMessage>,
+    messages: Vec<OpenAIRequestMessage>,
     max_tokens: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    temperature: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    top_p: Option<f32>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    stop: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    seed: Option<u64>,
 }
 
-#[derive(Serialize, Deserialize)]
-struct OpenAIMessage {
+#[derive(Serialize)]
+struct OpenAIRequestMessage {
     role: String,
+    content: OpenAIContent,
+}
+
+/// Plain text, or parts when the message has images
+#[derive(Serialize)]
+#[serde(untagged)]
+enum OpenAIContent {
+    Text(String),
+    Parts(Vec<OpenAIPart>),
+}
+
+#[derive(Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum OpenAIPart {
+    Text { text: String },
+    ImageUrl { image_url: ImageUrl },
+}
+
+#[derive(Serialize)]
+struct ImageUrl {
+    url: String,
+}
+
+impl From<&CompletionMessage> for OpenAIContent {
+    fn from(message: &CompletionMessage) -> Self {
+        if message.images.is_empty() {
+            return Self::Text(message.content.clone());
+        }
+        let text = Some(message.content.clone())
+            .filter(|text| !text.is_empty())
+            .map(|text| OpenAIPart::Text { text });
+        let images = message.images.iter().map(|image| OpenAIPart::ImageUrl { image_url: ImageUrl { url: image.data_uri() } });
+        Self::Parts(text.into_iter().chain(images).collect())
+    }
+}
+
+#[derive(Deserialize)]
+struct OpenAIMessage {
     content: String,
 }
 
 #[derive(Deserialize)]
 struct OpenAIResponse {
     choices: Vec<Choice>,
+    #[serde(default)]
+    usage: OpenAIUsage,
+}
+
+#[derive(Deserialize, Default)]
+struct OpenAIUsage {
+    prompt_tokens: u64,
+    completion_tokens: u64,
 }
 
 #[derive(Deserialize)]
@@ -49,6 +108,7 @@ impl OpenAIAdapter {
                 Capability::General,
                 Capability::Analysis,
                 Capability::Creative,
+                Capability::Vision,
             ],
             model_name: model,
             max_context: 128_000, // GPT-4 Turbo context
@@ -73,31 +133,32 @@ impl LLMProvider for OpenAIAdapter {
         &self.instance
     }
 
-    async fn complete(
-        &self,
-        prompt: &str,
-        context: HashMap<String, serde_json::Value>,
-    ) -> Result<String> {
-        debug!("🤖 Calling OpenAI API...");
-
-        let mut messages = Vec::new();
-
-        if let Some(system) = context.get("system").and_then(|v| v.as_str()) {
-            messages.push(OpenAIMessage {
-                role: "system".to_string(),
-                content: system.to_string(),
-            });
-        }
+    async fn complete(&self, request: CompletionRequest) -> Result<Completion> {
+        debug!("🤖 Calling OpenAI API... (trace {:?})", request.trace_id());
 
-        messages.push(OpenAIMessage {
-            role: "user".to_string(),
-            content: prompt.to_string(),
+        let system = request.system.iter().map(|system| OpenAIRequestMessage {
+            role: "system".to_string(),
+            content: OpenAIContent::Text(system.clone()),
+        });
+        let turns = request.messages.iter().map(|message| OpenAIRequestMessage {
+            role: match message.role {
+                MessageRole::User => "user",
+                MessageRole::Assistant => "assistant",
+                MessageRole::System => "system",
+            }
+            .to_string(),
+            content: message.into(),
         });
 
+        let options = &request.options;
         let request = OpenAIRequest {
             model: self.instance.model_name.clone(),
-            messages,
-            max_tokens: Some(4096),
+            messages: system.chain(turns).collect(),
+            max_tokens: Some(options.max_tokens.unwrap_or(DEFAULT_MAX_TOKENS)),
+            temperature: options.temperature,
+            top_p: options.top_p,
+            stop: options.stop.clone(),
+            seed: options.seed,
         };
 
         let response = self
@@ -108,15 +169,10 @@ impl LLMProvider for OpenAIAdapter {
             .json(&request)
             .send()
             .await
-            .map_err(|e| HybridLLMError::NetworkError(e.to_string()))?;
+            .map_err(request_error)?;
 
         if !response.status().is_success() {
-            let error_text = response.text().await.unwrap_or_default();
-            error!("OpenAI API error: {}", error_text);
-            return Err(HybridLLMError::LLMError(format!(
-                "OpenAI API error: {}",
-                error_text
-            )));
+            return Err(response_error("OpenAI", response).await);
         }
 
         let openai_response: OpenAIResponse = response
@@ -129,35 +185,51 @@ impl LLMProvider for OpenAIAdapter {
             .first()
             .map(|choice| choice.message.content.clone())
             .unwrap_or_default();
+        let usage = Usage {
+            input_tokens: openai_response.usage.prompt_tokens,
+            output_tokens: openai_response.usage.completion_tokens,
+            ..Usage::default()
+        };
 
-        Ok(text)
+        Ok(Completion { content: text, usage })
     }
 
     async fn complete_stream(
         &self,
-        prompt: &str,
-        context: HashMap<String, serde_json::Value>,
-    ) -> Result<tokio::sync::mpsc::Receiver<Result<String>>> {
+        request: CompletionRequest,
+        cancel: CancellationToken,
+    ) -> Result<tokio::sync::mpsc::Receiver<Result<StreamChunk>>> {
         // TODO: Implement streaming
-        let (tx, rx) = tokio::sync::mpsc::channel(1);
-        let result = self.complete(prompt, context).await;
-
-        tokio::spawn(async move {
-            let _ = tx.send(result).await;
-        });
+        // Dropping the request future aborts the HTTP request
+        let result = tokio::select! {
+            result = self.complete(request) => result,
+            _ = cancel.cancelled() => return Ok(tokio::sync::mpsc::channel(1).1),
+        };
 
-        Ok(rx)
+        Ok(stream_completion(result?))
     }
 
     async fn health_check(&self) -> Result<bool> {
-        Ok(true)
+        // Looking up the model checks the key and the model name without spending tokens
+        let response = self
+            .client
+            .get(format!("https://api.openai.com/v1/models/{}", self.instance.model_name))
+            .header("Authorization", format!("Bearer {}", self.api_key))
+            .send()
+            .await
+            .map_err(request_error)?;
+
+        if !response.status().is_success() {
+            debug!("OpenAI health check failed: {}", response.status());
+        }
+        Ok(response.status().is_success())
     }
 
-    async fn load(&mut self) -> Result<()> {
+    async fn load(&self) -> Result<()> {
         Ok(())
     }
 
-    async fn unload(&mut self) -> Result<()> {
+    async fn unload(&self) -> Result<()> {
         Ok(())
     }
 }
@@ -0,0 +1,270 @@
+use common::errors::{HybridLLMError, Result};
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tracing::debug;
+
+use crate::{request_error, response_error};
+
+const BRAVE_ENDPOINT: &str = "https://api.search.brave.com/res/v1/web/search";
+
+/// Most queries whose results are kept at once
+const MAX_CACHED_QUERIES: usize = 256;
+
+/// Where web searches are sent
+#[derive(Debug, Clone)]
+pub enum SearchEngine {
+    /// A SearxNG instance, by its base URL
+    Searxng { url: String },
+    Brave { api_key: String },
+}
+
+/// One web search result
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SearchHit {
+    pub title: String,
+    pub url: String,
+    /// Plain text, without the engine's highlighting markup
+    pub snippet: String,
+}
+
+/// Web search client that reuses results for repeated queries
+pub struct WebSearch {
+    client: Client,
+    engine: SearchEngine,
+    cache_ttl: Duration,
+    cache: Mutex<HashMap<String, (Instant, Vec<SearchHit>)>>,
+}
+
+#[derive(Deserialize)]
+struct SearxngResponse {
+    #[serde(default)]
+    results: Vec<SearxngResult>,
+}
+
+#[derive(Deserialize)]
+struct SearxngResult {
+    title: String,
+    url: String,
+    #[serde(default)]
+    content: String,
+}
+
+#[derive(Deserialize)]
+struct BraveResponse {
+    web: Option<BraveResults>,
+}
+
+#[derive(Deserialize)]
+struct BraveResults {
+    #[serde(default)]
+    results: Vec<BraveResult>,
+}
+
+#[derive(Deserialize)]
+struct BraveResult {
+    title: String,
+    url: String,
+    #[serde(default)]
+    description: String,
+}
+
+impl WebSearch {
+    /// A `cache_ttl` of zero sends every query to the engine
+    pub fn new(engine: SearchEngine, cache_ttl: Duration) -> Self {
+        let client = Client::builder()
+            .timeout(Duration::from_secs(15))
+            .build()
+            .unwrap_or_else(|_| Client::new());
+        Self { client, engine, cache_ttl, cache: Mutex::new(HashMap::new()) }
+    }
+
+    /// URL queries are sent to, for checking against the network allowlist
+    pub fn endpoint(&self) -> String {
+        match &self.engine {
+            SearchEngine::Searxng { url } => format!("{}/search", url.trim_end_matches('/')),
+            SearchEngine::Brave { .. } => BRAVE_ENDPOINT.to_string(),
+        }
+    }
+
+    /// Up to `count` results for `query`, from the cache when the same query was made recently
+    pub async fn search(&self, query: &str, count: usize) -> Result<Vec<SearchHit>> {
+        let query = query.split_whitespace().collect::<Vec<_>>().join(" ");
+        if query.is_empty() {
+            return Err(HybridLLMError::InvalidRequest("Search query is empty".to_string()));
+        }
+
+        let key = cache_key(&query);
+        if let Some(hits) = self.cached(&key) {
+            debug!("🔎 Web search cache hit: {}", query);
+            return Ok(hits.into_iter().take(count).collect());
+        }
+
+        let hits = match &self.engine {
+            SearchEngine::Searxng { .. } => self.searxng(&query).await?,
+            SearchEngine::Brave { api_key } => self.brave(api_key, &query, count).await?,
+        };
+        self.store(key, hits.clone());
+        Ok(hits.into_iter().take(count).collect())
+    }
+
+    async fn searxng(&self, query: &str) -> Result<Vec<SearchHit>> {
+        let response = self
+            .client
+            .get(self.endpoint())
+            .query(&[("q", query), ("format", "json")])
+            .send()
+            .await
+            .map_err(request_error)?;
+        if !response.status().is_success() {
+            return Err(response_error("SearxNG", response).await);
+        }
+
+        let body: SearxngResponse = response.json().await.map_err(request_error)?;
+        Ok(body
+            .results
+            .into_iter()
+            .map(|result| SearchHit { title: result.title, url: result.url, snippet: plain_text(&result.content) })
+            .collect())
+    }
+
+    async fn brave(&self, api_key: &str, query: &str, count: usize) -> Result<Vec<SearchHit>> {
+        // Brave serves at most 20 per page; asking for the most lets the cache answer smaller counts
+        let response = self
+            .client
+            .get(BRAVE_ENDPOINT)
+            .header("X-Subscription-Token", api_key)
+            .header("Accept", "application/json")
+            .query(&[("q", query), ("count", &count.clamp(10, 20).to_string())])
+            .send()
+            .await
+            .map_err(request_error)?;
+        if !response.status().is_success() {
+            return Err(response_error("Brave Search", response).await);
+        }
+
+        let body: BraveResponse = response.json().await.map_err(request_error)?;
+        Ok(body
+            .web
+            .map(|web| web.results)
+            .unwrap_or_default()
+            .into_iter()
+            .map(|result| SearchHit {
+                title: plain_text(&result.title),
+                url: result.url,
+                snippet: plain_text(&result.description),
+            })
+            .collect())
+    }
+
+    fn cached(&self, key: &str) -> Option<Vec<SearchHit>> {
+        let cache = self.cache.lock().unwrap();
+        cache
+            .get(key)
+            .filter(|(at, _)| at.elapsed() < self.cache_ttl)
+            .map(|(_, hits)| hits.clone())
+    }
+
+    fn store(&self, key: String, hits: Vec<SearchHit>) {
+        if self.cache_ttl.is_zero() {
+            return;
+        }
+        let mut cache = self.cache.lock().unwrap();
+        cache.retain(|_, (at, _)| at.elapsed() < self.cache_ttl);
+        if cache.len() >= MAX_CACHED_QUERIES {
+            let oldest = cache.iter().min_by_key(|(_, (at, _))| *at).map(|(key, _)| key.clone());
+            if let Some(oldest) = oldest {
+                cache.remove(&oldest);
+            }
+        }
+        cache.insert(key, (Instant::now(), hits));
+    }
+}
+
+/// Queries differing only in case or spacing share results
+fn cache_key(query: &str) -> String {
+    query.split_whitespace().collect::<Vec<_>>().join(" ").to_lowercase()
+}
+
+/// Drop tags like Brave's `<strong>` highlighting and decode the common entities
+fn plain_text(snippet: &str) -> String {
+    let mut text = String::with_capacity(snippet.len());
+    let mut rest = snippet;
+    while let Some(start) = rest.find('<') {
+        text.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let is_tag = rest[1..].starts_with(|c: char| c.is_ascii_alphabetic() || c == '/');
+        match rest.find('>').filter(|_| is_tag) {
+            Some(end) => rest = &rest[end + 1..],
+            None => {
+                text.push('<');
+                rest = &rest[1..];
+            }
+        }
+    }
+    text.push_str(rest);
+    text.replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&#x27;", "'")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&nbsp;", " ")
+        .replace("&amp;", "&")
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_plain_text() {
+        assert_eq!(
+            plain_text("The <strong>Rust</strong> book &amp; <em>more</em>&nbsp; here"),
+            "The Rust book & more here"
+        );
+        assert_eq!(plain_text("1 < 2 &lt; 3"), "1 < 2 < 3");
+        assert_eq!(cache_key("  Rust   Async "), cache_key("rust async"));
+    }
+
+    #[test]
+    fn test_parse_responses() {
+        let brave: BraveResponse = serde_json::from_value(serde_json::json!({
+            "query": { "original": "rust" },
+            "web": { "results": [
+                { "title": "Rust", "url": "https://www.rust-lang.org/", "description": "A <strong>language</strong>" },
+                { "title": "No description", "url": "https://example.com/" }
+            ] }
+        }))
+        .unwrap();
+        assert_eq!(brave.web.unwrap().results.len(), 2);
+
+        let empty: BraveResponse = serde_json::from_value(serde_json::json!({ "query": {} })).unwrap();
+        assert!(empty.web.is_none());
+
+        let searxng: SearxngResponse = serde_json::from_value(serde_json::json!({
+            "query": "rust",
+            "results": [{ "title": "Rust", "url": "https://www.rust-lang.org/", "content": "A language", "engine": "ddg" }]
+        }))
+        .unwrap();
+        assert_eq!(searxng.results[0].content, "A language");
+    }
+
+    #[test]
+    fn test_cache() {
+        let search = WebSearch::new(SearchEngine::Searxng { url: "http://localhost:8888/".to_string() }, Duration::from_secs(60));
+        assert_eq!(search.endpoint(), "http://localhost:8888/search");
+
+        let hit = SearchHit { title: "Rust".to_string(), url: "https://www.rust-lang.org/".to_string(), snippet: String::new() };
+        search.store(cache_key("Rust"), vec![hit.clone()]);
+        assert_eq!(search.cached(&cache_key("rust ")), Some(vec![hit.clone()]));
+
+        let uncached = WebSearch::new(SearchEngine::Brave { api_key: "key".to_string() }, Duration::ZERO);
+        uncached.store(cache_key("Rust"), vec![hit]);
+        assert_eq!(uncached.cached(&cache_key("Rust")), None);
+    }
+}
@@ -0,0 +1,360 @@
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::types::{MessageRole, RequestContext};
+
+/// Sampling and length settings for one completion; unset fields take the provider's defaults
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GenerationOptions {
+    /// Cap on generated tokens
+    pub max_tokens: Option<u32>,
+    pub temperature: Option<f32>,
+    pub top_p: Option<f32>,
+    /// Generation stops before any of these
+    pub stop: Vec<String>,
+    /// For repeatable sampling, where the provider supports it
+    pub seed: Option<u64>,
+    /// Shape the reply must take; providers that can't constrain sampling ignore it
+    pub response_format: Option<ResponseFormat>,
+}
+
+/// A shape a reply is held to while it is sampled, so it always parses
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ResponseFormat {
+    /// Any JSON object
+    JsonObject,
+    /// JSON valid against a JSON Schema
+    JsonSchema { schema: serde_json::Value },
+    /// A GBNF grammar, as llama.cpp reads them, starting at its `root` rule
+    Grammar { grammar: String },
+}
+
+/// One turn of the conversation sent to a provider
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CompletionMessage {
+    pub role: MessageRole,
+    pub content: String,
+    /// Images shown to the model with the text; only vision-capable providers take them
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub images: Vec<ImageInput>,
+    /// Built by the platform around text it didn't write, such as a web page or another LLM's answer
+    /// Screened for jailbreak scaffolding before it goes to a cloud provider
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub constructed: bool,
+}
+
+impl CompletionMessage {
+    pub fn new(role: MessageRole, content: impl Into<String>) -> Self {
+        Self { role, content: content.into(), images: Vec::new(), constructed: false }
+    }
+}
+
+/// An image attached to a message
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ImageInput {
+    /// e.g. "image/png"
+    pub media_type: String,
+    /// Base64 encoded bytes
+    pub data: String,
+}
+
+impl ImageInput {
+    /// As a `data:` URI, the form OpenAI-style APIs take images in
+    pub fn data_uri(&self) -> String {
+        format!("data:{};base64,{}", self.media_type, self.data)
+    }
+}
+
+/// A tool the model may call, its arguments described by a JSON schema
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ToolDefinition {
+    pub name: String,
+    pub description: String,
+    pub parameters: serde_json::Value,
+}
+
+/// A model's request to run one of the tools it was offered
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ToolCall {
+    /// Pairs the call with its result; assigned by the provider when it has one
+    pub id: String,
+    pub name: String,
+    pub arguments: serde_json::Value,
+}
+
+/// What running a tool call produced, sent back to the model
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ToolResult {
+    pub call_id: String,
+    pub name: String,
+    /// Absent when the tool failed
+    pub output: Option<serde_json::Value>,
+    pub error: Option<String>,
+}
+
+impl ToolResult {
+    pub fn success(call: &ToolCall, output: serde_json::Value) -> Self {
+        Self { call_id: call.id.clone(), name: call.name.clone(), output: Some(output), error: None }
+    }
+
+    pub fn failure(call: &ToolCall, error: impl Into<String>) -> Self {
+        Self { call_id: call.id.clone(), name: call.name.clone(), output: None, error: Some(error.into()) }
+    }
+
+    pub fn is_error(&self) -> bool {
+        self.error.is_some()
+    }
+}
+
+/// Tokens, cost and time one or more completions took
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Usage {
+    /// Tokens of the prompt, including the system prompt and earlier turns
+    pub input_tokens: u64,
+    pub output_tokens: u64,
+    /// Free for local models; `None` when the provider doesn't say what it charged
+    pub cost_usd: Option<f64>,
+    /// From the request to the end of the reply
+    pub latency_ms: u64,
+}
+
+impl Usage {
+    pub fn total_tokens(&self) -> u64 {
+        self.input_tokens + self.output_tokens
+    }
+}
+
+/// Sums usages; the cost is what the ones that know it cost, `None` if none of them do
+impl std::ops::AddAssign for Usage {
+    fn add_assign(&mut self, other: Self) {
+        self.input_tokens += other.input_tokens;
+        self.output_tokens += other.output_tokens;
+        self.cost_usd = match (self.cost_usd, other.cost_usd) {
+            (Some(a), Some(b)) => Some(a + b),
+            (a, b) => a.or(b),
+        };
+        self.latency_ms += other.latency_ms;
+    }
+}
+
+impl std::iter::Sum for Usage {
+    fn sum<I: Iterator<Item = Self>>(iter: I) -> Self {
+        iter.fold(Self::default(), |mut total, usage| {
+            total += usage;
+            total
+        })
+    }
+}
+
+/// A finished completion
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Completion {
+    pub content: String,
+    pub usage: Usage,
+}
+
+/// One item of a streamed completion
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", content = "data", rename_all = "snake_case")]
+pub enum StreamChunk {
+    Text(String),
+    /// Sent once, after the last text; the pool adds the latency
+    Usage(Usage),
+}
+
+/// How soon a request runs when the model is busy; queued requests of a higher priority go first
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Priority {
+    /// Background work such as summaries and evaluations
+    Low,
+    #[default]
+    Normal,
+    /// Someone is waiting on the answer
+    High,
+}
+
+/// Everything a provider needs for one completion
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CompletionRequest {
+    /// The conversation so far, oldest first; a single user message for a plain prompt
+    pub messages: Vec<CompletionMessage>,
+    /// Instructions for the model, kept apart from the conversation
+    pub system: Option<String>,
+    pub options: GenerationOptions,
+    /// Providers that can't call tools ignore these
+    pub tools: Vec<ToolDefinition>,
+    /// Ties provider calls to the logs and audit entries of the action that caused them
+    pub context: Option<RequestContext>,
+    /// Place in the queue of providers that queue requests themselves
+    pub priority: Priority,
+}
+
+impl CompletionRequest {
+    /// A request for a single user prompt
+    pub fn prompt(prompt: impl Into<String>) -> Self {
+        Self {
+            messages: vec![CompletionMessage::new(MessageRole::User, prompt)],
+            ..Self::default()
+        }
+    }
+
+    /// A request continuing a conversation
+    pub fn messages(messages: Vec<CompletionMessage>) -> Self {
+        Self { messages, ..Self::default() }
+    }
+
+    pub fn with_system(mut self, system: impl Into<String>) -> Self {
+        self.system = Some(system.into());
+        self
+    }
+
+    pub fn with_options(mut self, options: GenerationOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    pub fn with_context(mut self, context: RequestContext) -> Self {
+        self.context = Some(context);
+        self
+    }
+
+    pub fn with_priority(mut self, priority: Priority) -> Self {
+        self.priority = priority;
+        self
+    }
+
+    /// Mark every message as constructed by the platform rather than typed by the user
+    pub fn constructed(mut self) -> Self {
+        for message in &mut self.messages {
+            message.constructed = true;
+        }
+        self
+    }
+
+    /// Attach images to the last user message
+    pub fn with_images(mut self, images: Vec<ImageInput>) -> Self {
+        if let Some(message) = self.messages.iter_mut().rev().find(|message| message.role == MessageRole::User) {
+            message.images.extend(images);
+        }
+        self
+    }
+
+    /// Whether any message carries images, which needs a vision-capable provider
+    pub fn has_images(&self) -> bool {
+        self.messages.iter().any(|message| !message.images.is_empty())
+    }
+
+    /// Trace of the action the request is for, if it came with a context
+    pub fn trace_id(&self) -> Option<Uuid> {
+        self.context.as_ref().map(|context| context.trace_id)
+    }
+
+    /// The system prompt and conversation as one text, for models that take a plain prompt
+    pub fn to_prompt(&self) -> String {
+        // A bare prompt stays as it is
+        if let ([message], None) = (self.messages.as_slice(), &self.system) {
+            if matches!(message.role, MessageRole::User) {
+                return message.content.clone();
+            }
+        }
+
+        let system = self.system.iter().map(|system| format!("System: {}", system));
+        let turns = self.messages.iter().map(|message| {
+            let speaker = match message.role {
+                MessageRole::User => "User",
+                MessageRole::Assistant => "Assistant",
+                MessageRole::System => "System",
+            };
+            format!("{}: {}", speaker, message.content)
+        });
+        system.chain(turns).collect::<Vec<_>>().join("\n\n")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_to_prompt() {
+        assert_eq!(CompletionRequest::prompt("hi").to_prompt(), "hi");
+        assert_eq!(
+            CompletionRequest::prompt("hi").with_system("Be brief").to_prompt(),
+            "System: Be brief\n\nUser: hi"
+        );
+
+        let conversation = CompletionRequest::messages(vec![
+            CompletionMessage::new(MessageRole::User, "hi"),
+            CompletionMessage::new(MessageRole::Assistant, "hello"),
+            CompletionMessage::new(MessageRole::User, "bye"),
+        ]);
+        assert_eq!(conversation.to_prompt(), "User: hi\n\nAssistant: hello\n\nUser: bye");
+    }
+
+    #[test]
+    fn test_with_images() {
+        let image = ImageInput { media_type: "image/png".to_string(), data: "iVBORw0KGgo=".to_string() };
+        assert_eq!(image.data_uri(), "data:image/png;base64,iVBORw0KGgo=");
+
+        let request = CompletionRequest::messages(vec![
+            CompletionMessage::new(MessageRole::User, "hi"),
+            CompletionMessage::new(MessageRole::Assistant, "hello"),
+        ])
+        .with_images(vec![image.clone()]);
+        assert!(request.has_images());
+        assert_eq!(request.messages[0].images, vec![image]);
+        assert!(request.messages[1].images.is_empty());
+
+        // Messages without images serialize as they did before
+        let json = serde_json::to_value(&request.messages[1]).unwrap();
+        assert!(json.get("images").is_none());
+        assert!(!CompletionRequest::prompt("hi").has_images());
+    }
+
+    #[test]
+    fn test_tool_metadata_round_trip() {
+        let mut message = crate::types::Message {
+            id: Uuid::new_v4(),
+            role: MessageRole::Assistant,
+            content: "Searching".into(),
+            timestamp: chrono::Utc::now(),
+            metadata: Default::default(),
+        };
+        assert!(message.tool_calls().is_empty());
+        assert!(message.tool_result().is_none());
+
+        let call = ToolCall {
+            id: "call_1".to_string(),
+            name: "web_search".to_string(),
+            arguments: serde_json::json!({ "query": "rust" }),
+        };
+        message.set_tool_calls(std::slice::from_ref(&call));
+        assert_eq!(message.tool_calls(), vec![call.clone()]);
+
+        let result = ToolResult::failure(&call, "offline");
+        message.set_tool_result(&result);
+        assert!(message.tool_result().unwrap().is_error());
+
+        message.set_tool_calls(&[]);
+        assert!(message.tool_calls().is_empty());
+        assert_eq!(message.content.text(), "Searching");
+    }
+
+    #[test]
+    fn test_usage_sum() {
+        let local = Usage { input_tokens: 10, output_tokens: 5, cost_usd: Some(0.0), latency_ms: 100 };
+        let unpriced = Usage { input_tokens: 20, output_tokens: 10, cost_usd: None, latency_ms: 300 };
+        let cloud = Usage { cost_usd: Some(0.25), ..unpriced };
+
+        let total: Usage = [local, unpriced, cloud].into_iter().sum();
+        assert_eq!(total.total_tokens(), 75);
+        assert_eq!(total.cost_usd, Some(0.25));
+        assert_eq!(total.latency_ms, 700);
+        assert_eq!([unpriced].into_iter().sum::<Usage>().cost_usd, None);
+    }
+}
@@ -0,0 +1,708 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tracing::info;
+
+use crate::completion::GenerationOptions;
+use crate::errors::{HybridLLMError, Result};
+use crate::types::SandboxTemplate;
+
+/// Version of the config layout this build writes; files from newer builds are refused
+pub const CONFIG_VERSION: u32 = 1;
+
+/// Where the app and the orchestrator read their config from, next to `config.toml`
+pub const DEFAULT_CONFIG_FILE: &str = "./settings.toml";
+
+/// Denied permission requests from one LLM before the system locks down
+pub const DEFAULT_MAX_FAILED_REQUESTS: usize = 5;
+
+/// Sandboxes, warm ones included, that may exist at once
+pub const DEFAULT_MAX_SANDBOXES: usize = 8;
+
+/// Sandbox creations that may wait for a slot before further ones are refused
+pub const DEFAULT_MAX_QUEUED_SANDBOXES: usize = 32;
+
+/// Where local models are downloaded to unless `paths.models_dir` says otherwise
+pub const DEFAULT_MODELS_DIR: &str = "./models";
+
+/// Where WASM plugins are discovered unless `paths.plugins_dir` says otherwise
+pub const DEFAULT_PLUGINS_DIR: &str = "./plugins";
+
+/// How long web search results are reused for the same query
+pub const DEFAULT_SEARCH_CACHE_SECS: u64 = 15 * 60;
+
+/// Largest page the `fetch_url` tool downloads, in KiB
+pub const DEFAULT_FETCH_MAX_KB: u64 = 2048;
+
+/// Port of the OpenAI-compatible API on localhost
+pub const DEFAULT_OPENAI_API_PORT: u16 = 3032;
+
+/// Scheduled backups kept unless `backup.keep` says otherwise
+pub const DEFAULT_BACKUPS_KEPT: usize = 7;
+
+/// Watched sources not re-crawled for this long are stale unless `recrawl.stale_after_hours` says otherwise
+pub const DEFAULT_STALE_AFTER_HOURS: u64 = 72;
+
+/// Configuration shared by the desktop app and the headless orchestrator, persisted as TOML
+/// Missing sections and fields take their defaults, so older files keep loading
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PlatformConfig {
+    /// Layout version the file was written with; files without one predate versioning and read as 1
+    pub version: u32,
+    /// Cloud providers take no requests and network access is refused; only local models answer
+    pub offline: bool,
+    pub providers: ProviderSettings,
+    pub models: ModelSettings,
+    pub paths: PathSettings,
+    pub budgets: BudgetSettings,
+    pub security: SecuritySettings,
+    pub sandbox: SandboxSettings,
+    pub search: SearchSettings,
+    pub fetch: FetchSettings,
+    pub openai_api: OpenAiApiSettings,
+    pub telemetry: TelemetrySettings,
+    pub backup: BackupSettings,
+    pub recrawl: RecrawlSettings,
+}
+
+impl Default for PlatformConfig {
+    fn default() -> Self {
+        Self {
+            version: CONFIG_VERSION,
+            offline: false,
+            providers: ProviderSettings::default(),
+            models: ModelSettings::default(),
+            paths: PathSettings::default(),
+            budgets: BudgetSettings::default(),
+            security: SecuritySettings::default(),
+            sandbox: SandboxSettings::default(),
+            search: SearchSettings::default(),
+            fetch: FetchSettings::default(),
+            openai_api: OpenAiApiSettings::default(),
+            telemetry: TelemetrySettings::default(),
+            backup: BackupSettings::default(),
+            recrawl: RecrawlSettings::default(),
+        }
+    }
+}
+
+/// Cloud providers; keys are never stored here, they live in the OS keyring or the environment
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ProviderSettings {
+    pub claude: ProviderKey,
+    pub openai: ProviderKey,
+    pub gemini: ProviderKey,
+}
+
+impl Default for ProviderSettings {
+    fn default() -> Self {
+        Self {
+            claude: ProviderKey::env("ANTHROPIC_API_KEY"),
+            openai: ProviderKey::env("OPENAI_API_KEY"),
+            gemini: ProviderKey::env("GOOGLE_API_KEY"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ProviderKey {
+    pub enabled: bool,
+    /// Environment variable holding the API key, used when the keyring has none
+    pub api_key_env: String,
+    /// Model to register; the provider's default when unset
+    pub model: Option<String>,
+}
+
+impl ProviderKey {
+    fn env(name: &str) -> Self {
+        Self { enabled: true, api_key_env: name.to_string(), model: None }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Default)]
+#[serde(default)]
+pub struct ModelSettings {
+    /// LLM used when a message doesn't name one
+    pub default_llm: Option<String>,
+    /// Whisper GGML model for voice input, e.g. `ggml-base.en.bin`; relative to `paths.models_dir`
+    pub speech_model: Option<PathBuf>,
+    /// Spoken language as an ISO 639-1 code; detected when unset
+    pub speech_language: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PathSettings {
+    /// Managed folders, sandboxes and indexes; only read at startup
+    pub data_dir: PathBuf,
+    /// Where local models are downloaded to and listed from
+    pub models_dir: PathBuf,
+    /// Searched for WASM plugins at startup, one directory per plugin
+    pub plugins_dir: PathBuf,
+}
+
+impl Default for PathSettings {
+    fn default() -> Self {
+        Self {
+            data_dir: PathBuf::from("./data"),
+            models_dir: PathBuf::from(DEFAULT_MODELS_DIR),
+            plugins_dir: PathBuf::from(DEFAULT_PLUGINS_DIR),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Default)]
+#[serde(default)]
+pub struct BudgetSettings {
+    /// Cloud spending cap per calendar month, in US dollars
+    pub monthly_cloud_usd: Option<f64>,
+    /// Cap on tokens generated for a single request
+    pub max_tokens_per_request: Option<u32>,
+}
+
+impl BudgetSettings {
+    /// Generation options that keep a request within these budgets
+    pub fn generation_options(&self) -> GenerationOptions {
+        GenerationOptions { max_tokens: self.max_tokens_per_request, ..GenerationOptions::default() }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SecuritySettings {
+    /// Denied permission requests from one LLM before the system locks down
+    pub max_failed_requests: usize,
+    /// OS-wide shortcut for a panic lockdown, e.g. "CmdOrCtrl+Alt+Shift+P"; empty disables it
+    pub panic_hotkey: String,
+    /// What happens to jailbreak scaffolding found in prompts the platform constructs for cloud providers
+    pub jailbreak_action: JailbreakAction,
+}
+
+impl Default for SecuritySettings {
+    fn default() -> Self {
+        Self {
+            max_failed_requests: DEFAULT_MAX_FAILED_REQUESTS,
+            panic_hotkey: "CmdOrCtrl+Alt+Shift+P".to_string(),
+            jailbreak_action: JailbreakAction::default(),
+        }
+    }
+}
+
+/// How the jailbreak guardrail treats a constructed prompt it flags; the user is alerted either way
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum JailbreakAction {
+    /// Send the prompt as it is
+    Flag,
+    /// Remove the flagged lines before sending it
+    #[default]
+    Strip,
+}
+
+impl SecuritySettings {
+    /// The panic hotkey, unless disabled
+    pub fn panic_hotkey(&self) -> Option<&str> {
+        Some(self.panic_hotkey.trim()).filter(|hotkey| !hotkey.is_empty())
+    }
+}
+
+/// How code runs in sandboxes
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum SandboxBackend {
+    /// Confined host processes, one working directory per sandbox
+    #[default]
+    Process,
+    /// Firecracker microVMs; Linux with KVM only
+    Firecracker,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SandboxSettings {
+    pub backend: SandboxBackend,
+    /// Sandboxes, warm ones included, that may exist at once
+    pub max_sandboxes: usize,
+    /// Creations that may wait for a free slot; zero refuses instead of queueing
+    pub max_queued: usize,
+    /// Sandboxes kept booted per template so code starts instantly; zero disables the warm pool
+    pub warm_per_template: usize,
+    pub wasm: WasmSettings,
+}
+
+impl Default for SandboxSettings {
+    fn default() -> Self {
+        Self {
+            backend: SandboxBackend::default(),
+            max_sandboxes: DEFAULT_MAX_SANDBOXES,
+            max_queued: DEFAULT_MAX_QUEUED_SANDBOXES,
+            warm_per_template: 1,
+            wasm: WasmSettings::default(),
+        }
+    }
+}
+
+/// The WASM micro-sandbox short snippets run in when eligible
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct WasmSettings {
+    /// Holds the interpreter modules, python.wasm and quickjs.wasm
+    pub runtimes_dir: PathBuf,
+    /// Instruction budget per run
+    pub fuel: u64,
+    pub max_memory_mb: u64,
+    pub timeout_secs: u64,
+    /// Larger snippets run in a full sandbox
+    pub max_snippet_kb: u64,
+}
+
+impl Default for WasmSettings {
+    fn default() -> Self {
+        Self {
+            runtimes_dir: PathBuf::from("./data/wasm-runtimes"),
+            fuel: 2_000_000_000,
+            max_memory_mb: 256,
+            timeout_secs: 5,
+            max_snippet_kb: 16,
+        }
+    }
+}
+
+/// Search engines the `web_search` tool can use
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SearchBackend {
+    /// A SearxNG instance with the JSON format enabled
+    Searxng,
+    /// The Brave Search API
+    Brave,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SearchSettings {
+    /// Engine behind the `web_search` tool; LLMs get no web search when unset
+    pub backend: Option<SearchBackend>,
+    /// SearxNG instance, e.g. "http://localhost:8888"
+    pub searxng_url: Option<String>,
+    /// Environment variable holding the Brave Search API key
+    pub brave_api_key_env: String,
+    /// How long results are reused for the same query; zero turns the cache off
+    pub cache_secs: u64,
+}
+
+impl Default for SearchSettings {
+    fn default() -> Self {
+        Self {
+            backend: None,
+            searxng_url: None,
+            brave_api_key_env: "BRAVE_API_KEY".to_string(),
+            cache_secs: DEFAULT_SEARCH_CACHE_SECS,
+        }
+    }
+}
+
+/// Limits of the `fetch_url` tool
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FetchSettings {
+    /// Pages are cut off past this many KiB
+    pub max_kb: u64,
+    /// Time one fetch may take, redirects included
+    pub timeout_secs: u64,
+    /// Refuse pages the site's robots.txt disallows
+    pub respect_robots: bool,
+}
+
+impl Default for FetchSettings {
+    fn default() -> Self {
+        Self { max_kb: DEFAULT_FETCH_MAX_KB, timeout_secs: 20, respect_robots: true }
+    }
+}
+
+/// The local server that speaks the OpenAI chat-completions API, for editors and scripts
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct OpenAiApiSettings {
+    /// Off unless asked for; anything on this machine holding the token can use the LLMs
+    pub enabled: bool,
+    /// Listens on 127.0.0.1 only
+    pub port: u16,
+}
+
+impl Default for OpenAiApiSettings {
+    fn default() -> Self {
+        Self { enabled: false, port: DEFAULT_OPENAI_API_PORT }
+    }
+}
+
+/// OpenTelemetry export of traces and metrics to a collector such as Grafana Alloy or Jaeger
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TelemetrySettings {
+    /// OTLP/HTTP collector, e.g. "http://localhost:4318"; nothing is exported when unset
+    pub otlp_endpoint: Option<String>,
+    /// Sent with every export; secrets belong in `OTEL_EXPORTER_OTLP_HEADERS`, which is added to these
+    pub headers: BTreeMap<String, String>,
+    /// Export the spans of user actions, tool calls and generations
+    pub traces: bool,
+    /// Export token, latency and request counts
+    pub metrics: bool,
+    /// Share of traces kept, from 0 to 1
+    pub sample_ratio: f64,
+    pub metrics_interval_secs: u64,
+}
+
+impl Default for TelemetrySettings {
+    fn default() -> Self {
+        Self {
+            otlp_endpoint: None,
+            headers: BTreeMap::new(),
+            traces: true,
+            metrics: true,
+            sample_ratio: 1.0,
+            metrics_interval_secs: 60,
+        }
+    }
+}
+
+/// Archives of the database, settings, managed folders and logs, made on demand or on a schedule
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BackupSettings {
+    /// Hours between scheduled backups; none are made on a schedule when unset
+    pub interval_hours: Option<u64>,
+    /// Scheduled backups kept, oldest removed first; backups made on demand are never removed
+    pub keep: usize,
+    /// Where archives are written; `backups` in `paths.data_dir` when unset
+    pub dir: Option<PathBuf>,
+}
+
+impl Default for BackupSettings {
+    fn default() -> Self {
+        Self { interval_hours: None, keep: DEFAULT_BACKUPS_KEPT, dir: None }
+    }
+}
+
+/// Folders and pages indexed for RAG and re-indexed on a schedule when their content changes
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RecrawlSettings {
+    /// Minutes between re-crawls; watched sources are only re-crawled on demand when unset
+    pub interval_minutes: Option<u64>,
+    /// Folders whose text files are indexed into the uploads collection, subfolders included
+    pub folders: Vec<PathBuf>,
+    /// Pages indexed into the browsing collection
+    pub urls: Vec<String>,
+    /// Results from a source not re-crawled for this long, e.g. because it stopped loading, are flagged stale
+    pub stale_after_hours: u64,
+}
+
+impl Default for RecrawlSettings {
+    fn default() -> Self {
+        Self {
+            interval_minutes: None,
+            folders: Vec::new(),
+            urls: Vec::new(),
+            stale_after_hours: DEFAULT_STALE_AFTER_HOURS,
+        }
+    }
+}
+
+impl RecrawlSettings {
+    pub fn stale_after(&self) -> Duration {
+        Duration::from_secs(self.stale_after_hours * 3600)
+    }
+}
+
+/// Whether `name` looks like an environment variable name
+fn is_env_name(name: &str) -> bool {
+    !name.is_empty() && name.chars().all(|c| c.is_ascii_uppercase() || c.is_ascii_digit() || c == '_')
+}
+
+impl PlatformConfig {
+    /// Read the config from `path`, falling back to defaults when the file doesn't exist yet
+    pub fn load(path: &Path) -> Result<Self> {
+        let text = match std::fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                info!("📝 No config at {:?}, using defaults", path);
+                return Ok(Self::default());
+            }
+            Err(e) => return Err(HybridLLMError::FileSystemError(e.to_string())),
+        };
+
+        let config: Self = toml::from_str(&text)
+            .map_err(|e| HybridLLMError::ConfigError(format!("{}: {}", path.display(), e)))?;
+        config
+            .validate()
+            .map_err(|e| HybridLLMError::ConfigError(format!("{}: {}", path.display(), e)))?;
+        Ok(config)
+    }
+
+    /// Validate and write the config to `path`, replacing the old file only once the new one is complete
+    pub fn save(&self, path: &Path) -> Result<()> {
+        self.validate()?;
+        let text = toml::to_string_pretty(self)
+            .map_err(|e| HybridLLMError::ConfigError(e.to_string()))?;
+
+        let fs_err = |e: std::io::Error| HybridLLMError::FileSystemError(e.to_string());
+        let mut partial = path.as_os_str().to_os_string();
+        partial.push(".tmp");
+        std::fs::write(&partial, text).map_err(fs_err)?;
+        std::fs::rename(&partial, path).map_err(fs_err)
+    }
+
+    /// Reject a config the platform couldn't run with, naming the field and what it takes
+    pub fn validate(&self) -> Result<()> {
+        let invalid = |message: String| Err(HybridLLMError::ConfigError(message));
+
+        if self.version == 0 || self.version > CONFIG_VERSION {
+            return invalid(format!(
+                "version {} is not supported; this build reads config versions 1 to {}",
+                self.version, CONFIG_VERSION
+            ));
+        }
+        for (name, provider) in [
+            ("claude", &self.providers.claude),
+            ("openai", &self.providers.openai),
+            ("gemini", &self.providers.gemini),
+        ] {
+            let env = &provider.api_key_env;
+            if !is_env_name(env) {
+                return invalid(format!(
+                    "providers.{}.api_key_env must be an environment variable name like \"ANTHROPIC_API_KEY\", got {:?}",
+                    name, env
+                ));
+            }
+            if provider.model.as_deref().is_some_and(|model| model.trim().is_empty()) {
+                return invalid(format!("providers.{}.model must not be empty; remove it to use the provider's default", name));
+            }
+        }
+        if self.models.default_llm.as_deref().is_some_and(|id| id.trim().is_empty()) {
+            return invalid("models.default_llm must not be empty; remove it to pick an LLM per message".to_string());
+        }
+        if self.models.speech_model.as_ref().is_some_and(|path| path.as_os_str().is_empty()) {
+            return invalid("models.speech_model must not be empty; remove it to disable voice input".to_string());
+        }
+        if let Some(language) = &self.models.speech_language {
+            if language.len() != 2 || !language.chars().all(|c| c.is_ascii_lowercase()) {
+                return invalid(format!(
+                    "models.speech_language must be a two-letter language code like \"en\", got {:?}",
+                    language
+                ));
+            }
+        }
+        for (name, path) in [
+            ("data_dir", &self.paths.data_dir),
+            ("models_dir", &self.paths.models_dir),
+            ("plugins_dir", &self.paths.plugins_dir),
+        ] {
+            if path.as_os_str().is_empty() {
+                return invalid(format!("paths.{} must not be empty", name));
+            }
+        }
+        if let Some(usd) = self.budgets.monthly_cloud_usd {
+            if !usd.is_finite() || usd < 0.0 {
+                return invalid(format!("budgets.monthly_cloud_usd must be a non-negative amount, got {}", usd));
+            }
+        }
+        if self.budgets.max_tokens_per_request == Some(0) {
+            return invalid("budgets.max_tokens_per_request must be at least 1; remove it for no cap".to_string());
+        }
+        if self.security.max_failed_requests == 0 {
+            return invalid("security.max_failed_requests must be at least 1".to_string());
+        }
+        if self.sandbox.backend == SandboxBackend::Firecracker && !cfg!(target_os = "linux") {
+            return invalid("sandbox.backend \"firecracker\" needs Linux with KVM; use \"process\" here".to_string());
+        }
+        if self.sandbox.max_sandboxes == 0 {
+            return invalid("sandbox.max_sandboxes must be at least 1".to_string());
+        }
+        // At most an empty sandbox and one of each template are kept warm
+        let warm = self.sandbox.warm_per_template * (SandboxTemplate::ALL.len() + 1);
+        if warm > self.sandbox.max_sandboxes {
+            return invalid(format!(
+                "sandbox.warm_per_template of {} keeps {} sandboxes warm, more than sandbox.max_sandboxes ({})",
+                self.sandbox.warm_per_template, warm, self.sandbox.max_sandboxes
+            ));
+        }
+        if self.sandbox.wasm.runtimes_dir.as_os_str().is_empty() {
+            return invalid("sandbox.wasm.runtimes_dir must not be empty".to_string());
+        }
+        for (name, value) in [
+            ("fuel", self.sandbox.wasm.fuel),
+            ("max_memory_mb", self.sandbox.wasm.max_memory_mb),
+            ("timeout_secs", self.sandbox.wasm.timeout_secs),
+        ] {
+            if value == 0 {
+                return invalid(format!("sandbox.wasm.{} must be at least 1", name));
+            }
+        }
+        if self.search.backend == Some(SearchBackend::Searxng) {
+            let url = self.search.searxng_url.as_deref().unwrap_or_default();
+            if !(url.starts_with("http://") || url.starts_with("https://")) {
+                return invalid(format!(
+                    "search.searxng_url must be the http(s) URL of a SearxNG instance when search.backend is \"searxng\", got {:?}",
+                    url
+                ));
+            }
+        }
+        if !is_env_name(&self.search.brave_api_key_env) {
+            return invalid(format!(
+                "search.brave_api_key_env must be an environment variable name like \"BRAVE_API_KEY\", got {:?}",
+                self.search.brave_api_key_env
+            ));
+        }
+        if self.fetch.max_kb == 0 {
+            return invalid("fetch.max_kb must be at least 1".to_string());
+        }
+        if self.fetch.timeout_secs == 0 {
+            return invalid("fetch.timeout_secs must be at least 1".to_string());
+        }
+        if self.openai_api.port == 0 {
+            return invalid(format!("openai_api.port must be a port number like {}", DEFAULT_OPENAI_API_PORT));
+        }
+        if let Some(endpoint) = &self.telemetry.otlp_endpoint {
+            if !(endpoint.starts_with("http://") || endpoint.starts_with("https://")) {
+                return invalid(format!(
+                    "telemetry.otlp_endpoint must be an http(s) URL like \"http://localhost:4318\", got {:?}",
+                    endpoint
+                ));
+            }
+        }
+        if !(0.0..=1.0).contains(&self.telemetry.sample_ratio) {
+            return invalid(format!("telemetry.sample_ratio must be between 0 and 1, got {}", self.telemetry.sample_ratio));
+        }
+        if self.telemetry.metrics_interval_secs == 0 {
+            return invalid("telemetry.metrics_interval_secs must be at least 1".to_string());
+        }
+        if self.backup.interval_hours == Some(0) {
+            return invalid("backup.interval_hours must be at least 1; remove it to back up on demand only".to_string());
+        }
+        if self.backup.keep == 0 {
+            return invalid("backup.keep must be at least 1".to_string());
+        }
+        if self.backup.dir.as_ref().is_some_and(|path| path.as_os_str().is_empty()) {
+            return invalid("backup.dir must not be empty; remove it to keep backups in the data directory".to_string());
+        }
+        if self.recrawl.interval_minutes == Some(0) {
+            return invalid("recrawl.interval_minutes must be at least 1; remove it to re-crawl on demand only".to_string());
+        }
+        if self.recrawl.folders.iter().any(|path| !path.is_absolute()) {
+            return invalid("recrawl.folders must be absolute paths".to_string());
+        }
+        if let Some(url) = self.recrawl.urls.iter().find(|url| !(url.starts_with("http://") || url.starts_with("https://"))) {
+            return invalid(format!("recrawl.urls must be http(s) URLs, got {:?}", url));
+        }
+        if self.recrawl.stale_after_hours == 0 {
+            return invalid("recrawl.stale_after_hours must be at least 1".to_string());
+        }
+        Ok(())
+    }
+
+    /// Where the speech model for voice input is, if one is configured
+    pub fn speech_model_path(&self) -> Option<PathBuf> {
+        self.models.speech_model.as_ref().map(|path| self.paths.models_dir.join(path))
+    }
+
+    /// Where backups are written
+    pub fn backup_dir(&self) -> PathBuf {
+        self.backup.dir.clone().unwrap_or_else(|| self.paths.data_dir.join("backups"))
+    }
+
+    /// Whether moving from `self` to `new` only takes full effect after a restart
+    pub fn requires_restart(&self, new: &PlatformConfig) -> bool {
+        self.paths.data_dir != new.paths.data_dir
+            || self.paths.plugins_dir != new.paths.plugins_dir
+            || self.sandbox != new.sandbox
+            || self.search != new.search
+            || self.fetch != new.fetch
+            || self.openai_api != new.openai_api
+            || self.telemetry != new.telemetry
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_unversioned_file_loads_as_current() {
+        let config: PlatformConfig = toml::from_str("[budgets]\nmonthly_cloud_usd = 20.0\n").unwrap();
+        assert_eq!(config.version, CONFIG_VERSION);
+        assert_eq!(config.budgets.monthly_cloud_usd, Some(20.0));
+        assert_eq!(config.sandbox, SandboxSettings::default());
+        config.validate().unwrap();
+    }
+
+    #[test]
+    fn test_wasm_settings() {
+        let config: PlatformConfig = toml::from_str("[sandbox.wasm]\ntimeout_secs = 2\n").unwrap();
+        assert_eq!(config.sandbox.wasm.timeout_secs, 2);
+        assert_eq!(config.sandbox.wasm.fuel, WasmSettings::default().fuel);
+        config.validate().unwrap();
+    }
+
+    #[test]
+    fn test_validate() {
+        PlatformConfig::default().validate().unwrap();
+
+        let newer = PlatformConfig { version: CONFIG_VERSION + 1, ..Default::default() };
+        assert!(newer.validate().unwrap_err().to_string().contains("config versions 1 to"));
+
+        let mut crowded = PlatformConfig::default();
+        crowded.sandbox.warm_per_template = 4;
+        assert!(crowded.validate().unwrap_err().to_string().contains("sandbox.warm_per_template"));
+
+        let mut fuelless = PlatformConfig::default();
+        fuelless.sandbox.wasm.fuel = 0;
+        assert!(fuelless.validate().unwrap_err().to_string().contains("sandbox.wasm.fuel"));
+
+        let mut searxng = PlatformConfig::default();
+        searxng.search.backend = Some(SearchBackend::Searxng);
+        assert!(searxng.validate().unwrap_err().to_string().contains("search.searxng_url"));
+        searxng.search.searxng_url = Some("http://localhost:8888".to_string());
+        searxng.validate().unwrap();
+
+        let mut unlimited = PlatformConfig::default();
+        unlimited.fetch.timeout_secs = 0;
+        assert!(unlimited.validate().unwrap_err().to_string().contains("fetch.timeout_secs"));
+
+        let mut portless = PlatformConfig::default();
+        portless.openai_api.port = 0;
+        assert!(portless.validate().unwrap_err().to_string().contains("openai_api.port"));
+
+        let mut grpc = PlatformConfig::default();
+        grpc.telemetry.otlp_endpoint = Some("localhost:4317".to_string());
+        assert!(grpc.validate().unwrap_err().to_string().contains("telemetry.otlp_endpoint"));
+        grpc.telemetry.otlp_endpoint = Some("http://localhost:4318".to_string());
+        grpc.telemetry.sample_ratio = 1.5;
+        assert!(grpc.validate().unwrap_err().to_string().contains("telemetry.sample_ratio"));
+
+        let mut unnamed = PlatformConfig::default();
+        unnamed.providers.openai.api_key_env = "openai key".to_string();
+        assert!(unnamed.validate().unwrap_err().to_string().contains("providers.openai.api_key_env"));
+
+        let mut hourly = PlatformConfig::default();
+        hourly.backup.interval_hours = Some(0);
+        assert!(hourly.validate().unwrap_err().to_string().contains("backup.interval_hours"));
+        hourly.backup.interval_hours = Some(24);
+        hourly.backup.keep = 0;
+        assert!(hourly.validate().unwrap_err().to_string().contains("backup.keep"));
+
+        let mut pluginless = PlatformConfig::default();
+        pluginless.paths.plugins_dir = PathBuf::new();
+        assert!(pluginless.validate().unwrap_err().to_string().contains("paths.plugins_dir"));
+
+        let mut watching = PlatformConfig::default();
+        watching.recrawl.urls = vec!["ftp://example.com/notes".to_string()];
+        assert!(watching.validate().unwrap_err().to_string().contains("recrawl.urls"));
+        watching.recrawl.urls = vec!["https://example.com/notes".to_string()];
+        watching.recrawl.folders = vec![PathBuf::from("notes")];
+        assert!(watching.validate().unwrap_err().to_string().contains("recrawl.folders"));
+    }
+}
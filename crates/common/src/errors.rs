@@ -1,3 +1,5 @@
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
 use thiserror::Error;
 
 #[derive(Error, Debug)]
@@ -42,11 +44,152 @@ pub enum HybridLLMError {
         actual: f32,
     },
 
+    #[error("File rejected: {filename} ({mime_type}): {reason}")]
+    FileRejected {
+        filename: String,
+        mime_type: String,
+        reason: String,
+    },
+
+    #[error("{filename} was already uploaded as {existing}")]
+    DuplicateFile {
+        filename: String,
+        existing: String,
+        sha256: String,
+    },
+
+    #[error("Quota exceeded for {folder}: {used_bytes} of {quota_bytes} bytes used, {requested_bytes} more requested")]
+    QuotaExceeded {
+        folder: String,
+        quota_bytes: u64,
+        used_bytes: u64,
+        requested_bytes: u64,
+    },
+
     #[error("Timeout: {0}")]
     Timeout(String),
 
+    #[error("{provider} rate limit reached: {message}")]
+    RateLimited {
+        provider: String,
+        message: String,
+        /// How long the provider asked to wait, when it said
+        retry_after: Option<Duration>,
+    },
+
+    #[error("{provider} is overloaded: {message}")]
+    ProviderOverloaded {
+        provider: String,
+        message: String,
+        retry_after: Option<Duration>,
+    },
+
+    #[error("{provider} rejected the credentials: {message}")]
+    AuthenticationFailed {
+        provider: String,
+        message: String,
+    },
+
     #[error(transparent)]
     Other(#[from] anyhow::Error),
 }
 
+/// Stable, machine-readable name of an error, for callers that branch on it or show it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorCode {
+    LlmError,
+    PermissionDenied,
+    SecurityViolation,
+    LockdownActive,
+    SandboxError,
+    DatabaseError,
+    FileSystemError,
+    NetworkError,
+    ConfigError,
+    LlmNotFound,
+    InvalidRequest,
+    ResourceLimitExceeded,
+    FileRejected,
+    DuplicateFile,
+    QuotaExceeded,
+    Timeout,
+    RateLimited,
+    ProviderOverloaded,
+    AuthenticationFailed,
+    Other,
+}
+
+impl HybridLLMError {
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            Self::LLMError(_) => ErrorCode::LlmError,
+            Self::PermissionDenied(_) => ErrorCode::PermissionDenied,
+            Self::SecurityViolation(_) => ErrorCode::SecurityViolation,
+            Self::LockdownActive(_) => ErrorCode::LockdownActive,
+            Self::SandboxError(_) => ErrorCode::SandboxError,
+            Self::DatabaseError(_) => ErrorCode::DatabaseError,
+            Self::FileSystemError(_) => ErrorCode::FileSystemError,
+            Self::NetworkError(_) => ErrorCode::NetworkError,
+            Self::ConfigError(_) => ErrorCode::ConfigError,
+            Self::LLMNotFound(_) => ErrorCode::LlmNotFound,
+            Self::InvalidRequest(_) => ErrorCode::InvalidRequest,
+            Self::ResourceLimitExceeded { .. } => ErrorCode::ResourceLimitExceeded,
+            Self::FileRejected { .. } => ErrorCode::FileRejected,
+            Self::DuplicateFile { .. } => ErrorCode::DuplicateFile,
+            Self::QuotaExceeded { .. } => ErrorCode::QuotaExceeded,
+            Self::Timeout(_) => ErrorCode::Timeout,
+            Self::RateLimited { .. } => ErrorCode::RateLimited,
+            Self::ProviderOverloaded { .. } => ErrorCode::ProviderOverloaded,
+            Self::AuthenticationFailed { .. } => ErrorCode::AuthenticationFailed,
+            Self::Other(_) => ErrorCode::Other,
+        }
+    }
+
+    /// Whether the same request may succeed if sent again later
+    /// Rate limits, overload and transient network failures pass; bad credentials or requests never do
+    pub fn is_retryable(&self) -> bool {
+        matches!(
+            self,
+            Self::NetworkError(_) | Self::Timeout(_) | Self::RateLimited { .. } | Self::ProviderOverloaded { .. }
+        )
+    }
+
+    /// How long the provider asked to wait before retrying, when it said
+    pub fn retry_after(&self) -> Option<Duration> {
+        match self {
+            Self::RateLimited { retry_after, .. } | Self::ProviderOverloaded { retry_after, .. } => *retry_after,
+            _ => None,
+        }
+    }
+}
+
 pub type Result<T> = std::result::Result<T, HybridLLMError>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_retry_classification() {
+        let limited = HybridLLMError::RateLimited {
+            provider: "OpenAI".to_string(),
+            message: "slow down".to_string(),
+            retry_after: Some(Duration::from_secs(20)),
+        };
+        assert!(limited.is_retryable());
+        assert_eq!(limited.retry_after(), Some(Duration::from_secs(20)));
+        assert_eq!(serde_json::to_value(limited.code()).unwrap(), "rate_limited");
+
+        assert!(HybridLLMError::NetworkError("connection reset".to_string()).is_retryable());
+        assert_eq!(HybridLLMError::Timeout("slow".to_string()).retry_after(), None);
+
+        let unauthorized = HybridLLMError::AuthenticationFailed {
+            provider: "Claude".to_string(),
+            message: "invalid x-api-key".to_string(),
+        };
+        assert!(!unauthorized.is_retryable());
+        assert!(!HybridLLMError::ConfigError("no models".to_string()).is_retryable());
+        assert_eq!(HybridLLMError::LLMNotFound("x".to_string()).code(), ErrorCode::LlmNotFound);
+    }
+}
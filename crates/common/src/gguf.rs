@@ -0,0 +1,294 @@
+//! Reading the headers of GGUF model files, e.g. to count tokens with the vocabulary a local model ships with
+
+use std::collections::HashMap;
+use std::io::{BufReader, Read};
+use std::path::Path;
+
+use crate::errors::{HybridLLMError, Result};
+
+/// "GGUF" read as a little-endian u32
+const GGUF_MAGIC: u32 = 0x4655_4747;
+
+/// Longest string and array a metadata value may hold; anything longer means a corrupt file
+const MAX_STRING_BYTES: u64 = 1 << 24;
+const MAX_ARRAY_LEN: u64 = 1 << 24;
+
+/// Most dimensions ggml gives a tensor
+const MAX_DIMENSIONS: u32 = 4;
+
+/// One metadata value of a GGUF file
+#[derive(Debug, Clone, PartialEq)]
+pub enum GgufValue {
+    UInt(u64),
+    Int(i64),
+    Float(f64),
+    Bool(bool),
+    String(String),
+    Array(Vec<GgufValue>),
+}
+
+impl GgufValue {
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Self::String(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    pub fn as_u64(&self) -> Option<u64> {
+        match self {
+            Self::UInt(value) => Some(*value),
+            Self::Int(value) => u64::try_from(*value).ok(),
+            _ => None,
+        }
+    }
+
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            Self::Float(value) => Some(*value),
+            Self::UInt(value) => Some(*value as f64),
+            Self::Int(value) => Some(*value as f64),
+            _ => None,
+        }
+    }
+
+    pub fn as_array(&self) -> Option<&[GgufValue]> {
+        match self {
+            Self::Array(values) => Some(values),
+            _ => None,
+        }
+    }
+}
+
+/// A tensor listed in a GGUF header; its data isn't read
+#[derive(Debug, Clone, PartialEq)]
+pub struct GgufTensor {
+    pub name: String,
+    pub shape: Vec<u64>,
+    /// ggml type of the weights, e.g. 0 for F32 and 12 for Q4_K
+    pub ggml_type: u32,
+}
+
+impl GgufTensor {
+    pub fn element_count(&self) -> u64 {
+        self.shape.iter().product()
+    }
+}
+
+/// Everything in a GGUF file before the tensor data
+#[derive(Debug, Clone, PartialEq)]
+pub struct GgufHeader {
+    pub version: u32,
+    pub metadata: HashMap<String, GgufValue>,
+    pub tensors: Vec<GgufTensor>,
+}
+
+/// The key-value metadata at the start of a GGUF file, such as `general.architecture` and `tokenizer.ggml.tokens`
+/// The tensors after it aren't read
+pub fn read_metadata(path: &Path) -> Result<HashMap<String, GgufValue>> {
+    let file = std::fs::File::open(path).map_err(|e| HybridLLMError::FileSystemError(format!("{}: {}", path.display(), e)))?;
+    let invalid = |e: HybridLLMError| HybridLLMError::InvalidRequest(format!("{} is not a readable GGUF file: {}", path.display(), e));
+    parse_metadata(&mut BufReader::new(file)).map_err(invalid)
+}
+
+/// The metadata and tensor list of a GGUF file, without reading the weights
+pub fn read_header(path: &Path) -> Result<GgufHeader> {
+    let file = std::fs::File::open(path).map_err(|e| HybridLLMError::FileSystemError(format!("{}: {}", path.display(), e)))?;
+    let invalid = |e: HybridLLMError| HybridLLMError::InvalidRequest(format!("{} is not a readable GGUF file: {}", path.display(), e));
+    parse_header(&mut BufReader::new(file)).map_err(invalid)
+}
+
+pub(crate) fn parse_metadata(reader: &mut impl Read) -> Result<HashMap<String, GgufValue>> {
+    parse_start(reader).map(|(_, _, metadata)| metadata)
+}
+
+pub(crate) fn parse_header(reader: &mut impl Read) -> Result<GgufHeader> {
+    let (version, tensor_count, metadata) = parse_start(reader)?;
+    if tensor_count > MAX_ARRAY_LEN {
+        return Err(HybridLLMError::InvalidRequest(format!("{} tensors", tensor_count)));
+    }
+    let mut tensors = Vec::new();
+    for _ in 0..tensor_count {
+        let name = read_string(reader)?;
+        let dimensions = read_u32(reader)?;
+        if dimensions > MAX_DIMENSIONS {
+            return Err(HybridLLMError::InvalidRequest(format!("tensor {} has {} dimensions", name, dimensions)));
+        }
+        let shape = (0..dimensions).map(|_| read_u64(reader)).collect::<Result<_>>()?;
+        let ggml_type = read_u32(reader)?;
+        let _offset = read_u64(reader)?;
+        tensors.push(GgufTensor { name, shape, ggml_type });
+    }
+    Ok(GgufHeader { version, metadata, tensors })
+}
+
+/// Version, tensor count and metadata
+fn parse_start(reader: &mut impl Read) -> Result<(u32, u64, HashMap<String, GgufValue>)> {
+    if read_u32(reader)? != GGUF_MAGIC {
+        return Err(HybridLLMError::InvalidRequest("missing GGUF magic".to_string()));
+    }
+    let version = read_u32(reader)?;
+    if !(2..=3).contains(&version) {
+        return Err(HybridLLMError::InvalidRequest(format!("unsupported GGUF version {}", version)));
+    }
+    let tensor_count = read_u64(reader)?;
+    let kv_count = read_u64(reader)?;
+
+    let mut metadata = HashMap::new();
+    for _ in 0..kv_count {
+        let key = read_string(reader)?;
+        let value_type = read_u32(reader)?;
+        metadata.insert(key, read_value(reader, value_type)?);
+    }
+    Ok((version, tensor_count, metadata))
+}
+
+fn read_value(reader: &mut impl Read, value_type: u32) -> Result<GgufValue> {
+    Ok(match value_type {
+        0 => GgufValue::UInt(read_bytes::<1>(reader)?[0] as u64),
+        1 => GgufValue::Int(read_bytes::<1>(reader)?[0] as i8 as i64),
+        2 => GgufValue::UInt(u16::from_le_bytes(read_bytes(reader)?) as u64),
+        3 => GgufValue::Int(i16::from_le_bytes(read_bytes(reader)?) as i64),
+        4 => GgufValue::UInt(read_u32(reader)? as u64),
+        5 => GgufValue::Int(i32::from_le_bytes(read_bytes(reader)?) as i64),
+        6 => GgufValue::Float(f32::from_le_bytes(read_bytes(reader)?) as f64),
+        7 => GgufValue::Bool(read_bytes::<1>(reader)?[0] != 0),
+        8 => GgufValue::String(read_string(reader)?),
+        9 => {
+            let element_type = read_u32(reader)?;
+            let len = read_u64(reader)?;
+            if len > MAX_ARRAY_LEN {
+                return Err(HybridLLMError::InvalidRequest(format!("array of {} values", len)));
+            }
+            let values = (0..len).map(|_| read_value(reader, element_type)).collect::<Result<_>>()?;
+            GgufValue::Array(values)
+        }
+        10 => GgufValue::UInt(read_u64(reader)?),
+        11 => GgufValue::Int(i64::from_le_bytes(read_bytes(reader)?)),
+        12 => GgufValue::Float(f64::from_le_bytes(read_bytes(reader)?)),
+        other => return Err(HybridLLMError::InvalidRequest(format!("unknown value type {}", other))),
+    })
+}
+
+fn read_bytes<const N: usize>(reader: &mut impl Read) -> Result<[u8; N]> {
+    let mut bytes = [0; N];
+    reader.read_exact(&mut bytes).map_err(|e| HybridLLMError::InvalidRequest(e.to_string()))?;
+    Ok(bytes)
+}
+
+fn read_u32(reader: &mut impl Read) -> Result<u32> {
+    Ok(u32::from_le_bytes(read_bytes(reader)?))
+}
+
+fn read_u64(reader: &mut impl Read) -> Result<u64> {
+    Ok(u64::from_le_bytes(read_bytes(reader)?))
+}
+
+fn read_string(reader: &mut impl Read) -> Result<String> {
+    let len = read_u64(reader)?;
+    if len > MAX_STRING_BYTES {
+        return Err(HybridLLMError::InvalidRequest(format!("string of {} bytes", len)));
+    }
+    let mut bytes = vec![0; len as usize];
+    reader.read_exact(&mut bytes).map_err(|e| HybridLLMError::InvalidRequest(e.to_string()))?;
+    // Vocabularies hold partial UTF-8 sequences as tokens; those are kept lossily
+    Ok(String::from_utf8(bytes).unwrap_or_else(|e| String::from_utf8_lossy(e.as_bytes()).into_owned()))
+}
+
+/// A GGUF file's bytes with the given metadata and no tensors, for tests
+#[cfg(test)]
+pub(crate) fn write_metadata(metadata: &[(&str, GgufValue)]) -> Vec<u8> {
+    write_header(metadata, &[])
+}
+
+/// A GGUF file's bytes with the given metadata and tensor list but no tensor data, for tests
+#[cfg(test)]
+pub(crate) fn write_header(metadata: &[(&str, GgufValue)], tensors: &[GgufTensor]) -> Vec<u8> {
+    fn write_string(out: &mut Vec<u8>, value: &str) {
+        out.extend((value.len() as u64).to_le_bytes());
+        out.extend(value.as_bytes());
+    }
+    fn type_of(value: &GgufValue) -> u32 {
+        match value {
+            GgufValue::UInt(_) => 10,
+            GgufValue::Int(_) => 11,
+            GgufValue::Float(_) => 12,
+            GgufValue::Bool(_) => 7,
+            GgufValue::String(_) => 8,
+            GgufValue::Array(_) => 9,
+        }
+    }
+    fn write_value(out: &mut Vec<u8>, value: &GgufValue) {
+        match value {
+            GgufValue::UInt(value) => out.extend(value.to_le_bytes()),
+            GgufValue::Int(value) => out.extend(value.to_le_bytes()),
+            GgufValue::Float(value) => out.extend(value.to_le_bytes()),
+            GgufValue::Bool(value) => out.push(*value as u8),
+            GgufValue::String(value) => write_string(out, value),
+            GgufValue::Array(values) => {
+                out.extend(values.first().map_or(8, type_of).to_le_bytes());
+                out.extend((values.len() as u64).to_le_bytes());
+                values.iter().for_each(|value| write_value(out, value));
+            }
+        }
+    }
+
+    let mut out = Vec::new();
+    out.extend(GGUF_MAGIC.to_le_bytes());
+    out.extend(3u32.to_le_bytes());
+    out.extend((tensors.len() as u64).to_le_bytes());
+    out.extend((metadata.len() as u64).to_le_bytes());
+    for (key, value) in metadata {
+        write_string(&mut out, key);
+        out.extend(type_of(value).to_le_bytes());
+        write_value(&mut out, value);
+    }
+    let mut offset = 0u64;
+    for tensor in tensors {
+        write_string(&mut out, &tensor.name);
+        out.extend((tensor.shape.len() as u32).to_le_bytes());
+        tensor.shape.iter().for_each(|dimension| out.extend(dimension.to_le_bytes()));
+        out.extend(tensor.ggml_type.to_le_bytes());
+        out.extend(offset.to_le_bytes());
+        offset += tensor.element_count();
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_read_metadata() {
+        let bytes = write_metadata(&[
+            ("general.architecture", GgufValue::String("llama".to_string())),
+            ("llama.context_length", GgufValue::UInt(8192)),
+            ("tokenizer.ggml.scores", GgufValue::Array(vec![GgufValue::Float(-1.5), GgufValue::Float(0.0)])),
+        ]);
+        let metadata = parse_metadata(&mut bytes.as_slice()).unwrap();
+        assert_eq!(metadata["general.architecture"].as_str(), Some("llama"));
+        assert_eq!(metadata["llama.context_length"].as_u64(), Some(8192));
+        assert_eq!(metadata["tokenizer.ggml.scores"].as_array().unwrap()[0].as_f64(), Some(-1.5));
+
+        assert!(parse_metadata(&mut &b"GGML\x03\0\0\0"[..]).is_err());
+        // Cut off in the middle of a value
+        assert!(parse_metadata(&mut &bytes[..bytes.len() - 4]).is_err());
+    }
+
+    #[test]
+    fn test_read_header() {
+        let tensors = vec![
+            GgufTensor { name: "token_embd.weight".to_string(), shape: vec![4096, 32000], ggml_type: 12 },
+            GgufTensor { name: "output_norm.weight".to_string(), shape: vec![4096], ggml_type: 0 },
+        ];
+        let bytes = write_header(&[("general.architecture", GgufValue::String("llama".to_string()))], &tensors);
+        let header = parse_header(&mut bytes.as_slice()).unwrap();
+        assert_eq!(header.version, 3);
+        assert_eq!(header.metadata["general.architecture"].as_str(), Some("llama"));
+        assert_eq!(header.tensors, tensors);
+        assert_eq!(header.tensors.iter().map(GgufTensor::element_count).sum::<u64>(), 4096 * 32000 + 4096);
+
+        assert!(parse_header(&mut &bytes[..bytes.len() - 10]).is_err());
+    }
+}
@@ -0,0 +1,47 @@
+//! Telling which language a message or document is written in, for routing and retrieval
+
+/// Metadata key a message's or document's detected language is kept under
+pub const LANGUAGE_KEY: &str = "language";
+
+/// Every model is taken to handle English
+pub const ENGLISH: &str = "eng";
+
+/// Capability tag prefix marking a model as suited to one language, e.g. `lang:jpn`
+pub const LANGUAGE_TAG_PREFIX: &str = "lang:";
+
+/// Only the start of long texts is looked at; a document's opening tells its language well enough
+const SAMPLE_CHARS: usize = 2000;
+
+/// Detections less certain than this are ignored
+const MIN_CONFIDENCE: f64 = 0.5;
+
+/// Language of `text` as an ISO 639-3 code such as "jpn", when it can be told reliably
+/// Short or mixed texts give `None`, so nothing is routed on a guess
+pub fn detect_language(text: &str) -> Option<&'static str> {
+    let sample = match text.char_indices().nth(SAMPLE_CHARS) {
+        Some((end, _)) => &text[..end],
+        None => text,
+    };
+    let info = whatlang::detect(sample)?;
+    (info.confidence() >= MIN_CONFIDENCE).then(|| info.lang().code())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detect_language() {
+        assert_eq!(detect_language("東京で一番おいしいラーメン屋はどこですか？教えてください。"), Some("jpn"));
+        assert_eq!(
+            detect_language("The quick brown fox jumps over the lazy dog while the farmer watches from the porch."),
+            Some(ENGLISH)
+        );
+        assert_eq!(
+            detect_language("Der schnelle braune Fuchs springt über den faulen Hund, während der Bauer zuschaut."),
+            Some("deu")
+        );
+        assert_eq!(detect_language("ok"), None);
+        assert_eq!(detect_language(""), None);
+    }
+}
@@ -1,16 +1,35 @@
 pub mod types;
+pub mod completion;
+pub mod config;
 pub mod messages;
 pub mod errors;
 pub mod traits;
+pub mod telemetry;
+pub mod language;
+pub mod gguf;
+pub mod tokens;
 
 // Re-export specific items to avoid ambiguity
 pub use types::{
     LLMProvider as LLMProviderType, Capability, LLMInstance, ContextType,
-    Message, MessageRole, PermissionScope, FileSystemPermissions,
-    NetworkPermissions, CommandPermissions, ResourceLimits,
-    LockdownState, LockdownReason, AuditLogEntry, TaskType,
-    SandboxConfig, ArtifactTransfer,
+    Message, MessageContent, ContentPart, MessageRole, Conversation, PermissionScope, FileSystemPermissions,
+    NewDocument, IndexedDocument, NewPromptTemplate, PromptTemplate, PromptTemplateRef,
+    NetworkPermissions, CommandPermissions, PackagePermissions, ResourceLimits,
+    LockdownState, LockdownLevel, LockdownReason, AuditLogEntry, RequestContext, TaskType,
+    SandboxConfig, SandboxTemplate, GpuRequest, VolumeMount, ArtifactTransfer, PortForwardRequest, CodeLanguage, NetworkMode,
+    ArtifactScanReport, ScanFinding, ScanFindingKind, ScanVerdict, SandboxUsage,
+};
+pub use completion::{
+    Completion, CompletionMessage, CompletionRequest, GenerationOptions, ImageInput, Priority, ResponseFormat, StreamChunk,
+    ToolCall, ToolDefinition, ToolResult, Usage,
 };
 pub use messages::*;
 pub use errors::*;
-pub use traits::{LLMProvider, SecurityEngine, ContextManager, SecurityAnalysis, RiskLevel, RAGResult};
+pub use config::PlatformConfig;
+pub use traits::{
+    LLMProvider, SecurityEngine, ContextManager, GpuAllocator, PlannedAction, PromptGuard, QueueDepth, ScreenedPrompt,
+    SecurityAnalysis, RiskLevel, RAGResult, SimulatedDecision, Tool,
+};
+
+// Providers, the pool and the app share one token type for stopping generations
+pub use tokio_util::sync::CancellationToken;
@@ -1,8 +1,12 @@
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 use std::collections::HashMap;
 
-use crate::types::{Capability, TaskType};
+use crate::completion::{CompletionRequest, ResponseFormat, ToolCall, ToolResult, Usage};
+use crate::errors::{HybridLLMError, Result};
+use crate::traits::SimulatedDecision;
+use crate::types::{ArtifactScanReport, Capability, CodeLanguage, LockdownLevel, RequestContext, TaskType};
 
 /// Messages passed through the orchestrator's message bus
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -11,8 +15,7 @@ pub enum OrchestratorMessage {
     /// User request to be routed to appropriate LLM
     UserRequest {
         id: Uuid,
-        content: String,
-        context: HashMap<String, serde_json::Value>,
+        request: CompletionRequest,
     },
 
     /// LLM delegation to another LLM
@@ -22,6 +25,13 @@ pub enum OrchestratorMessage {
         to: Option<String>, // None = orchestrator decides
         task: TaskDescription,
         callback: bool,
+        context: RequestContext,
+    },
+
+    /// Stop generating the response to a request and free the LLM working on it
+    CancelGeneration {
+        id: Uuid,
+        request_id: Uuid,
     },
 
     /// LLM response
@@ -30,15 +40,53 @@ pub enum OrchestratorMessage {
         request_id: Uuid,
         llm_id: String,
         content: String,
+        usage: Usage,
         metadata: HashMap<String, serde_json::Value>,
     },
 
+    /// LLM asking to run a tool while answering a request
+    ToolCall {
+        id: Uuid,
+        request_id: Uuid,
+        llm_id: String,
+        call: ToolCall,
+        context: RequestContext,
+    },
+
+    /// Outcome of a tool call, to be handed back to the LLM that made it
+    ToolResult {
+        id: Uuid,
+        request_id: Uuid,
+        llm_id: String,
+        result: ToolResult,
+        context: RequestContext,
+    },
+
+    /// LLM asking whether the tool calls of a multi-step plan would be allowed, before making any
+    PlanCheck {
+        id: Uuid,
+        request_id: Uuid,
+        llm_id: String,
+        calls: Vec<ToolCall>,
+        context: RequestContext,
+    },
+
+    /// What the security engine would decide on each call of a checked plan, in order
+    PlanChecked {
+        id: Uuid,
+        request_id: Uuid,
+        llm_id: String,
+        decisions: Vec<SimulatedDecision>,
+        context: RequestContext,
+    },
+
     /// Permission request
     PermissionRequest {
         id: Uuid,
         llm_id: String,
         permission_type: PermissionType,
         explanation: String,
+        context: RequestContext,
     },
 
     /// Permission response
@@ -65,13 +113,85 @@ pub enum OrchestratorMessage {
         purpose: String,
     },
 
+    /// Short code snippet to evaluate (WASM micro-sandbox when eligible)
+    CodeEvaluation {
+        id: Uuid,
+        llm_id: String,
+        language: CodeLanguage,
+        code: String,
+        context: RequestContext,
+    },
+
+    /// Result of a code evaluation
+    CodeEvaluationResult {
+        id: Uuid,
+        request_id: Uuid,
+        stdout: String,
+        stderr: String,
+        exit_code: i32,
+    },
+
+    /// LLM request to move a file out of a sandbox
+    ArtifactTransferRequest {
+        id: Uuid,
+        llm_id: String,
+        sandbox_id: Uuid,
+        file_path: String,
+        destination: String,
+        explanation: String,
+    },
+
     /// Sandbox artifact approval request
+    /// Answered with a `PermissionResponse` whose `request_id` is this `id`
     ArtifactApproval {
         id: Uuid,
         sandbox_id: Uuid,
         file_path: String,
         destination: String,
         explanation: String,
+        size_bytes: u64,
+        sha256: String,
+        scan: ArtifactScanReport,
+    },
+
+    /// Outcome of an artifact transfer request
+    ArtifactTransferResult {
+        id: Uuid,
+        request_id: Uuid,
+        approved: bool,
+        path: Option<String>,
+        error: Option<String>,
+    },
+
+    /// LLM request to expose a sandbox port on localhost
+    PortForwardRequest {
+        id: Uuid,
+        llm_id: String,
+        sandbox_id: Uuid,
+        sandbox_port: u16,
+        duration_secs: u64,
+        explanation: String,
+    },
+
+    /// Port forward approval request
+    /// Answered with a `PermissionResponse` whose `request_id` is this `id`
+    PortForwardApproval {
+        id: Uuid,
+        llm_id: String,
+        sandbox_id: Uuid,
+        sandbox_port: u16,
+        duration_secs: u64,
+        explanation: String,
+    },
+
+    /// Outcome of a port forward request
+    PortForwardResult {
+        id: Uuid,
+        request_id: Uuid,
+        approved: bool,
+        host_port: Option<u16>,
+        expires_at: Option<DateTime<Utc>>,
+        error: Option<String>,
     },
 
     /// System state change
@@ -82,6 +202,9 @@ pub enum OrchestratorMessage {
     },
 }
 
+/// Key of `TaskDescription::context` holding the `ResponseFormat` the delegated LLM must answer in
+pub const RESPONSE_FORMAT_KEY: &str = "response_format";
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct TaskDescription {
     pub description: String,
@@ -91,6 +214,17 @@ pub struct TaskDescription {
     pub constraints: Vec<String>,
 }
 
+impl TaskDescription {
+    /// The format the answer must take, e.g. `{"type": "json_schema", "schema": {...}}` under `RESPONSE_FORMAT_KEY`
+    pub fn response_format(&self) -> Result<Option<ResponseFormat>> {
+        self.context
+            .get(RESPONSE_FORMAT_KEY)
+            .map(|format| serde_json::from_value(format.clone()))
+            .transpose()
+            .map_err(|e| HybridLLMError::InvalidRequest(format!("Invalid {}: {}", RESPONSE_FORMAT_KEY, e)))
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "snake_case")]
 pub enum PermissionType {
@@ -115,7 +249,8 @@ pub enum AlertSeverity {
 pub enum SuggestedAction {
     Allow,
     Deny,
-    Lockdown,
+    /// Restrict the system to `level`; an alert never relaxes a stricter lockdown already in place
+    Lockdown { level: LockdownLevel },
     RequestHumanReview,
 }
 
@@ -124,8 +259,41 @@ pub enum SuggestedAction {
 pub enum StateChangeType {
     LockdownTriggered,
     LockdownReleased,
+    ReadOnlyEntered,
+    ReadOnlyExited,
     LLMLoaded,
     LLMUnloaded,
     PermissionGranted,
     PermissionDenied,
+    SandboxReclaimed,
+    SandboxQueued,
+    PortForwardOpened,
+    PortForwardClosed,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_response_format() {
+        let task = |context: HashMap<String, serde_json::Value>| TaskDescription {
+            description: "List the files".to_string(),
+            task_type: TaskType::General,
+            required_capabilities: vec![],
+            context,
+            constraints: vec![],
+        };
+        let schema = json!({ "type": "array", "items": { "type": "string" } });
+        let structured = task(HashMap::from([(
+            RESPONSE_FORMAT_KEY.to_string(),
+            json!({ "type": "json_schema", "schema": schema }),
+        )]));
+        assert_eq!(structured.response_format().unwrap(), Some(ResponseFormat::JsonSchema { schema }));
+        assert_eq!(task(HashMap::new()).response_format().unwrap(), None);
+
+        let unknown = task(HashMap::from([(RESPONSE_FORMAT_KEY.to_string(), json!({ "type": "yaml" }))]));
+        assert!(matches!(unknown.response_format(), Err(HybridLLMError::InvalidRequest(_))));
+    }
 }
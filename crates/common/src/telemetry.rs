@@ -0,0 +1,122 @@
+use opentelemetry::trace::TracerProvider as _;
+use opentelemetry_otlp::{MetricExporter, Protocol, SpanExporter, WithExportConfig, WithHttpConfig};
+use opentelemetry_sdk::metrics::{PeriodicReader, SdkMeterProvider};
+use opentelemetry_sdk::trace::{Sampler, SdkTracerProvider};
+use opentelemetry_sdk::Resource;
+use std::collections::HashMap;
+use std::time::Duration;
+use tracing::{info, warn};
+use tracing_subscriber::fmt::MakeWriter;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::EnvFilter;
+
+use crate::config::TelemetrySettings;
+use crate::errors::{HybridLLMError, Result};
+
+/// Log levels unless `RUST_LOG` says otherwise
+const DEFAULT_FILTER: &str = "hybrid_llm=debug,info";
+
+/// Longest an export may take before it is dropped
+const EXPORT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Exporters started by `init`; dropping it sends what is still buffered
+#[derive(Default)]
+pub struct Telemetry {
+    tracer_provider: Option<SdkTracerProvider>,
+    meter_provider: Option<SdkMeterProvider>,
+}
+
+impl Drop for Telemetry {
+    fn drop(&mut self) {
+        if let Some(provider) = self.tracer_provider.take() {
+            let _ = provider.shutdown();
+        }
+        if let Some(provider) = self.meter_provider.take() {
+            let _ = provider.shutdown();
+        }
+    }
+}
+
+/// Install the global subscriber: logs to `writer` and, with `otlp_endpoint` set, OTLP export of
+/// spans and of the `monotonic_counter.*` and `histogram.*` fields of events as metrics
+/// Export failures are logged and leave logging working, so a down collector never stops the platform
+pub fn init<W>(service_name: &'static str, settings: &TelemetrySettings, writer: W) -> Telemetry
+where
+    W: for<'w> MakeWriter<'w> + Send + Sync + 'static,
+{
+    let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new(DEFAULT_FILTER));
+    let (telemetry, error) = match exporters(service_name, settings) {
+        Ok(telemetry) => (telemetry, None),
+        Err(e) => (Telemetry::default(), Some(e)),
+    };
+
+    let traces = telemetry
+        .tracer_provider
+        .as_ref()
+        .map(|provider| tracing_opentelemetry::layer().with_tracer(provider.tracer(service_name)));
+    let metrics = telemetry.meter_provider.clone().map(tracing_opentelemetry::MetricsLayer::new);
+    // One filter for logs and export, so dependencies' own spans stay out of the traces too
+    tracing_subscriber::registry()
+        .with(filter)
+        .with(tracing_subscriber::fmt::layer().with_writer(writer))
+        .with(traces)
+        .with(metrics)
+        .init();
+
+    match (&settings.otlp_endpoint, error) {
+        (_, Some(e)) => warn!("⚠️  Telemetry not exported: {}", e),
+        (Some(endpoint), None) => info!("📡 Exporting telemetry to {}", endpoint),
+        (None, None) => {}
+    }
+    telemetry
+}
+
+fn exporters(service_name: &'static str, settings: &TelemetrySettings) -> Result<Telemetry> {
+    let Some(endpoint) = settings.otlp_endpoint.as_deref() else {
+        return Ok(Telemetry::default());
+    };
+    let endpoint = endpoint.trim_end_matches('/');
+    let headers: HashMap<String, String> = settings.headers.clone().into_iter().collect();
+    let resource = Resource::builder().with_service_name(service_name).build();
+    let export_error = |e: opentelemetry_otlp::ExporterBuildError| HybridLLMError::ConfigError(format!("OTLP exporter: {}", e));
+
+    let tracer_provider = if settings.traces {
+        let exporter = SpanExporter::builder()
+            .with_http()
+            .with_protocol(Protocol::HttpBinary)
+            .with_endpoint(format!("{}/v1/traces", endpoint))
+            .with_timeout(EXPORT_TIMEOUT)
+            .with_headers(headers.clone())
+            .build()
+            .map_err(export_error)?;
+        Some(
+            SdkTracerProvider::builder()
+                .with_batch_exporter(exporter)
+                .with_sampler(Sampler::ParentBased(Box::new(Sampler::TraceIdRatioBased(settings.sample_ratio))))
+                .with_resource(resource.clone())
+                .build(),
+        )
+    } else {
+        None
+    };
+
+    let meter_provider = if settings.metrics {
+        let exporter = MetricExporter::builder()
+            .with_http()
+            .with_protocol(Protocol::HttpBinary)
+            .with_endpoint(format!("{}/v1/metrics", endpoint))
+            .with_timeout(EXPORT_TIMEOUT)
+            .with_headers(headers)
+            .build()
+            .map_err(export_error)?;
+        let reader = PeriodicReader::builder(exporter)
+            .with_interval(Duration::from_secs(settings.metrics_interval_secs))
+            .build();
+        Some(SdkMeterProvider::builder().with_reader(reader).with_resource(resource).build())
+    } else {
+        None
+    };
+
+    Ok(Telemetry { tracer_provider, meter_provider })
+}
@@ -0,0 +1,207 @@
+//! Counting tokens the way each model does, so routing, context limits and budgets agree on one number
+
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::Arc;
+
+use tiktoken_rs::CoreBPE;
+use tokenizers::models::bpe::{Vocab, BPE};
+use tokenizers::models::unigram::Unigram;
+use tokenizers::normalizers::{NormalizerWrapper, Prepend, Replace, Sequence};
+use tokenizers::pre_tokenizers::byte_level::ByteLevel;
+
+use crate::completion::CompletionRequest;
+use crate::errors::{HybridLLMError, Result};
+use crate::gguf::{self, GgufValue};
+use crate::types::{LLMInstance, LLMProvider};
+
+/// Tokens chat APIs wrap each turn in
+const CHAT_TURN_TOKENS: usize = 4;
+
+/// Tokens chat APIs add to prime the reply
+const CHAT_REPLY_TOKENS: usize = 3;
+
+/// Word boundary marker of SentencePiece vocabularies
+const SPM_SPACE: &str = "\u{2581}";
+
+/// Splits text into tokens for one model
+#[derive(Clone)]
+pub enum Tokenizer {
+    /// OpenAI's byte-pair encodings; also stands in for cloud models whose tokenizer isn't published, such as Claude
+    Tiktoken(&'static CoreBPE),
+    /// A Hugging Face tokenizer, from a local model's `tokenizer.json` or its GGUF vocabulary
+    HuggingFace(Arc<tokenizers::Tokenizer>),
+}
+
+impl std::fmt::Debug for Tokenizer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Tiktoken(_) => f.write_str("Tokenizer::Tiktoken"),
+            Self::HuggingFace(_) => f.write_str("Tokenizer::HuggingFace"),
+        }
+    }
+}
+
+impl Tokenizer {
+    /// The best tokenizer known from the instance alone: the model's own for OpenAI, cl100k otherwise
+    /// Local providers that can read their model's vocabulary give a closer one through `LLMProvider::tokenizer`
+    pub fn for_instance(instance: &LLMInstance) -> Self {
+        use tiktoken_rs::tokenizer::{get_tokenizer, Tokenizer as Encoding};
+
+        let encoding = match instance.provider {
+            LLMProvider::OpenAI => get_tokenizer(&instance.model_name),
+            _ => None,
+        };
+        Self::Tiktoken(match encoding {
+            Some(Encoding::O200kBase) => tiktoken_rs::o200k_base_singleton(),
+            Some(Encoding::P50kBase) => tiktoken_rs::p50k_base_singleton(),
+            Some(Encoding::P50kEdit) => tiktoken_rs::p50k_edit_singleton(),
+            Some(Encoding::R50kBase | Encoding::Gpt2) => tiktoken_rs::r50k_base_singleton(),
+            Some(Encoding::Cl100kBase) | None => tiktoken_rs::cl100k_base_singleton(),
+        })
+    }
+
+    /// A Hugging Face `tokenizer.json`
+    pub fn from_file(path: &Path) -> Result<Self> {
+        let tokenizer = tokenizers::Tokenizer::from_file(path)
+            .map_err(|e| HybridLLMError::ConfigError(format!("Could not read tokenizer {}: {}", path.display(), e)))?;
+        Ok(Self::HuggingFace(Arc::new(tokenizer)))
+    }
+
+    /// The tokenizer a model ships with: `tokenizer.json` next to it, else the vocabulary in its GGUF metadata
+    /// Byte-level BPE (`gpt2`) and SentencePiece (`llama`) vocabularies are understood
+    pub fn for_model_file(path: &Path) -> Result<Self> {
+        let beside = path.with_file_name("tokenizer.json");
+        if beside.is_file() {
+            return Self::from_file(&beside);
+        }
+        Self::from_gguf_metadata(&gguf::read_metadata(path)?)
+    }
+
+    fn from_gguf_metadata(metadata: &HashMap<String, GgufValue>) -> Result<Self> {
+        let missing = |key: &str| HybridLLMError::ConfigError(format!("GGUF file has no {}", key));
+        let strings = |key: &str| -> Result<Vec<String>> {
+            let values = metadata.get(key).and_then(GgufValue::as_array).ok_or_else(|| missing(key))?;
+            Ok(values.iter().map(|value| value.as_str().unwrap_or_default().to_string()).collect())
+        };
+        let tokens = strings("tokenizer.ggml.tokens")?;
+        let model = metadata.get("tokenizer.ggml.model").and_then(GgufValue::as_str).unwrap_or_default();
+        let build_err = |e: tokenizers::Error| HybridLLMError::ConfigError(format!("Could not build the {} tokenizer: {}", model, e));
+
+        let tokenizer = match model {
+            "gpt2" => {
+                let vocab: Vocab = tokens.into_iter().zip(0..).collect();
+                let merges = strings("tokenizer.ggml.merges")?
+                    .into_iter()
+                    .filter_map(|merge| merge.split_once(' ').map(|(a, b)| (a.to_string(), b.to_string())))
+                    .collect();
+                let bpe = BPE::builder().vocab_and_merges(vocab, merges).build().map_err(build_err)?;
+                let mut tokenizer = tokenizers::Tokenizer::new(bpe);
+                tokenizer.with_pre_tokenizer(Some(ByteLevel::new(false, true, true)));
+                tokenizer
+            }
+            "llama" => {
+                let scores = metadata
+                    .get("tokenizer.ggml.scores")
+                    .and_then(GgufValue::as_array)
+                    .ok_or_else(|| missing("tokenizer.ggml.scores"))?;
+                let vocab = tokens.into_iter().zip(scores.iter().map(|score| score.as_f64().unwrap_or(0.0))).collect();
+                let unknown = metadata.get("tokenizer.ggml.unknown_token_id").and_then(GgufValue::as_u64);
+                let unigram = Unigram::from(vocab, unknown.map(|id| id as usize), true).map_err(build_err)?;
+                let mut tokenizer = tokenizers::Tokenizer::new(unigram);
+                let space = Replace::new(" ", SPM_SPACE).map_err(build_err)?;
+                tokenizer.with_normalizer(Some(Sequence::new(vec![
+                    NormalizerWrapper::Prepend(Prepend::new(SPM_SPACE.to_string())),
+                    NormalizerWrapper::Replace(space),
+                ])));
+                tokenizer
+            }
+            other => return Err(HybridLLMError::ConfigError(format!("Unsupported GGUF tokenizer {:?}", other))),
+        };
+        Ok(Self::HuggingFace(Arc::new(tokenizer)))
+    }
+
+    /// Tokens in `text`, special tokens left out
+    pub fn count(&self, text: &str) -> usize {
+        match self {
+            Self::Tiktoken(bpe) => bpe.encode_ordinary(text).len(),
+            Self::HuggingFace(tokenizer) => match tokenizer.encode(text, false) {
+                Ok(encoding) => encoding.len(),
+                // Only a broken vocabulary fails; counting by OpenAI's is closer than giving up
+                Err(_) => tiktoken_rs::cl100k_base_singleton().encode_ordinary(text).len(),
+            },
+        }
+    }
+
+    /// Tokens the prompt of `request` takes, as the model will see it
+    pub fn count_request(&self, request: &CompletionRequest) -> usize {
+        match self {
+            // Local models are sent the flattened prompt
+            Self::HuggingFace(_) => self.count(&request.to_prompt()),
+            Self::Tiktoken(_) => {
+                let turns = request.system.iter().chain(request.messages.iter().map(|message| &message.content));
+                turns.map(|text| self.count(text) + CHAT_TURN_TOKENS).sum::<usize>() + CHAT_REPLY_TOKENS
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::Capability;
+
+    fn instance(provider: LLMProvider, model_name: &str) -> LLMInstance {
+        LLMInstance {
+            id: model_name.to_string(),
+            provider,
+            capabilities: vec![Capability::General],
+            model_name: model_name.to_string(),
+            max_context: 8192,
+            is_loaded: true,
+        }
+    }
+
+    #[test]
+    fn test_tiktoken() {
+        let gpt4o = Tokenizer::for_instance(&instance(LLMProvider::OpenAI, "gpt-4o"));
+        assert_eq!(gpt4o.count("hello world"), 2);
+        assert_eq!(gpt4o.count(""), 0);
+
+        let claude = Tokenizer::for_instance(&instance(LLMProvider::Claude, "claude-3-5-sonnet"));
+        let request = CompletionRequest::prompt("hello world").with_system("Be brief");
+        // Two turns of their own tokens, their wrappers and the reply priming
+        assert_eq!(claude.count_request(&request), 2 + 2 + 2 * CHAT_TURN_TOKENS + CHAT_REPLY_TOKENS);
+    }
+
+    #[test]
+    fn test_gguf_vocabulary() {
+        let strings = |values: &[&str]| GgufValue::Array(values.iter().map(|value| GgufValue::String(value.to_string())).collect());
+        let spm = gguf::write_metadata(&[
+            ("tokenizer.ggml.model", GgufValue::String("llama".to_string())),
+            ("tokenizer.ggml.tokens", strings(&["<unk>", "\u{2581}hello", "\u{2581}world", "\u{2581}", "h", "e", "l", "o"])),
+            ("tokenizer.ggml.scores", GgufValue::Array([0.0, -1.0, -1.0, -3.0, -5.0, -5.0, -5.0, -5.0].map(GgufValue::Float).to_vec())),
+            ("tokenizer.ggml.unknown_token_id", GgufValue::UInt(0)),
+        ]);
+        let path = std::env::temp_dir().join(format!("tokens-{}.gguf", uuid::Uuid::new_v4()));
+        std::fs::write(&path, spm).unwrap();
+        let tokenizer = Tokenizer::for_model_file(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(tokenizer.count("hello world"), 2);
+        assert_eq!(tokenizer.count("hello hello world"), 3);
+
+        let bpe = gguf::write_metadata(&[
+            ("tokenizer.ggml.model", GgufValue::String("gpt2".to_string())),
+            ("tokenizer.ggml.tokens", strings(&["h", "i", "Ġ", "hi", "Ġhi"])),
+            ("tokenizer.ggml.merges", strings(&["h i", "Ġ hi"])),
+        ]);
+        let metadata = gguf::parse_metadata(&mut bpe.as_slice()).unwrap();
+        assert_eq!(Tokenizer::from_gguf_metadata(&metadata).unwrap().count("hi hi"), 2);
+
+        let bert = gguf::write_metadata(&[
+            ("tokenizer.ggml.model", GgufValue::String("bert".to_string())),
+            ("tokenizer.ggml.tokens", strings(&["a"])),
+        ]);
+        assert!(Tokenizer::from_gguf_metadata(&gguf::parse_metadata(&mut bert.as_slice()).unwrap()).is_err());
+    }
+}
@@ -1,9 +1,18 @@
 use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use std::path::Path;
+use tokio_util::sync::CancellationToken;
 
 use crate::{
-    errors::Result,
-    types::{Capability, LLMInstance, Message},
+    completion::{Completion, CompletionRequest, StreamChunk, ToolDefinition, Usage},
+    errors::{HybridLLMError, Result},
+    messages::PermissionType,
+    tokens::Tokenizer,
+    types::{
+        Capability, Conversation, IndexedDocument, LLMInstance, MalwareScan, Message, NewDocument, NewPromptTemplate,
+        PromptTemplate, RefreshedDocument, RequestContext,
+    },
 };
 
 /// Trait that all LLM providers must implement
@@ -15,37 +24,66 @@ pub trait LLMProvider: Send + Sync {
     /// Get provider instance info
     fn instance(&self) -> &LLMInstance;
 
-    /// Complete a prompt with context
-    async fn complete(
-        &self,
-        prompt: &str,
-        context: HashMap<String, serde_json::Value>,
-    ) -> Result<String>;
+    /// Complete a request
+    async fn complete(&self, request: CompletionRequest) -> Result<Completion>;
 
-    /// Stream a completion (returns chunks)
+    /// Stream a completion: text chunks, then its usage
+    /// Once `cancel` fires the provider stops generating and the stream ends without an error
     async fn complete_stream(
         &self,
-        prompt: &str,
-        context: HashMap<String, serde_json::Value>,
-    ) -> Result<tokio::sync::mpsc::Receiver<Result<String>>>;
+        request: CompletionRequest,
+        cancel: CancellationToken,
+    ) -> Result<tokio::sync::mpsc::Receiver<Result<StreamChunk>>>;
 
     /// Check if the provider is healthy
     async fn health_check(&self) -> Result<bool>;
 
+    /// Memory the model takes while loaded, in bytes; `None` when it runs elsewhere
+    fn memory_footprint_bytes(&self) -> Option<u64> {
+        None
+    }
+
+    /// Counts tokens as the model does; providers that can read their model's vocabulary override it
+    fn tokenizer(&self) -> Tokenizer {
+        Tokenizer::for_instance(self.instance())
+    }
+
+    /// Vectors for `texts`, in order, from models with the `Embedding` capability
+    async fn embed(&self, _texts: &[String]) -> Result<Vec<Vec<f32>>> {
+        Err(HybridLLMError::LLMError(format!("{} doesn't embed text", self.instance().id)))
+    }
+
+    /// Requests of a provider that queues them itself; `None` leaves queueing to the pool
+    fn queue_depth(&self) -> Option<QueueDepth> {
+        None
+    }
+
+    /// Whether the model is in memory; providers that load and unload at runtime override it
+    fn is_loaded(&self) -> bool {
+        self.instance().is_loaded
+    }
+
     /// Load the model (for local models)
-    async fn load(&mut self) -> Result<()>;
+    async fn load(&self) -> Result<()>;
 
     /// Unload the model (to free memory)
-    async fn unload(&mut self) -> Result<()>;
+    async fn unload(&self) -> Result<()>;
+}
+
+/// Requests a provider is running and holding back, for routing to the least busy one
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct QueueDepth {
+    pub in_flight: usize,
+    pub queued: usize,
 }
 
 /// Trait for the security engine
 #[async_trait]
 pub trait SecurityEngine: Send + Sync {
-    /// Check if a permission request should be granted
+    /// Check if a permission request should be granted; the decision is audited under `context`
     async fn check_permission(
         &self,
-        llm_id: &str,
+        context: &RequestContext,
         permission: &crate::messages::PermissionType,
         explanation: &str,
     ) -> Result<bool>;
@@ -61,6 +99,79 @@ pub trait SecurityEngine: Send + Sync {
 
     /// Get current lockdown state
     async fn lockdown_state(&self) -> Result<crate::types::LockdownState>;
+
+    /// What the engine would decide on `action`, without auditing it or counting a denial against the LLM
+    async fn simulate(&self, context: &RequestContext, action: &PlannedAction) -> Result<SimulatedDecision>;
+
+    /// Each step of a plan, simulated in order
+    async fn simulate_plan(&self, context: &RequestContext, plan: &[PlannedAction]) -> Result<Vec<SimulatedDecision>> {
+        let mut decisions = Vec::with_capacity(plan.len());
+        for action in plan {
+            decisions.push(self.simulate(context, action).await?);
+        }
+        Ok(decisions)
+    }
+}
+
+/// A step of a plan, checked before anything runs
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum PlannedAction {
+    Permission { permission: PermissionType },
+    /// A shell command, checked by the guardrails and the command policy
+    Command { command: String },
+}
+
+/// What the security engine would decide on a planned action
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "outcome", rename_all = "snake_case")]
+pub enum SimulatedDecision {
+    Allowed,
+    /// The policy doesn't grant it, so the user would be asked
+    NeedsApproval { reason: String },
+    /// Refused whatever the user says, e.g. during a lockdown or for a dangerous command
+    Denied { reason: String },
+}
+
+/// Hands out GPU memory shared between local models and sandboxes
+pub trait GpuAllocator: Send + Sync {
+    /// Reserve VRAM for `owner`, returning the device index it was placed on
+    /// Fails with `ResourceLimitExceeded` when no device has enough free VRAM
+    fn reserve(&self, owner: &str, request: &crate::types::GpuRequest) -> Result<u32>;
+
+    /// Release everything reserved by `owner`
+    fn release(&self, owner: &str);
+}
+
+/// Scans files for malware, e.g. through ClamAV
+/// Fails when the scanner itself could not run, so callers can tell that apart from a clean file
+#[async_trait]
+pub trait MalwareScanner: Send + Sync {
+    async fn scan_file(&self, path: &Path) -> Result<MalwareScan>;
+}
+
+/// Checks shell commands alongside the built-in guardrails, e.g. a plugin
+#[async_trait]
+pub trait CommandAnalyzer: Send + Sync {
+    /// Named in the issues it reports
+    fn name(&self) -> &str;
+
+    async fn analyze(&self, command: &str) -> Result<SecurityAnalysis>;
+}
+
+/// Screens prompts the platform constructs, e.g. around a web page or another LLM's answer, before they go to a cloud provider
+#[async_trait]
+pub trait PromptGuard: Send + Sync {
+    /// `text` as it may be sent to `llm_id`, with the jailbreak scaffolding found in it
+    async fn screen(&self, context: Option<&RequestContext>, llm_id: &str, text: &str) -> Result<ScreenedPrompt>;
+}
+
+/// A prompt after screening
+#[derive(Debug, Clone)]
+pub struct ScreenedPrompt {
+    /// The prompt, with flagged lines removed when the guard strips them
+    pub text: String,
+    pub analysis: SecurityAnalysis,
 }
 
 #[derive(Debug, Clone)]
@@ -109,14 +220,125 @@ pub trait ContextManager: Send + Sync {
     /// Add message to conversation
     async fn add_message(&self, conversation_id: &uuid::Uuid, message: Message) -> Result<()>;
 
-    /// Search RAG context
-    async fn search_rag(&self, query: &str, llm_id: Option<&str>, limit: usize) -> Result<Vec<RAGResult>>;
+    /// Start an empty conversation
+    async fn create_conversation(&self, title: Option<&str>) -> Result<Conversation>;
+
+    /// Conversations, most recently active first; `trashed` lists the trash instead
+    async fn list_conversations(&self, trashed: bool) -> Result<Vec<Conversation>>;
+
+    /// Set a conversation's title
+    async fn rename_conversation(&self, conversation_id: &uuid::Uuid, title: &str) -> Result<Conversation>;
+
+    /// Move a conversation to the trash; its messages are kept until it is purged
+    async fn trash_conversation(&self, conversation_id: &uuid::Uuid) -> Result<Conversation>;
+
+    /// Take a conversation back out of the trash
+    async fn restore_conversation(&self, conversation_id: &uuid::Uuid) -> Result<Conversation>;
+
+    /// Permanently delete conversations trashed before `before`, returning how many were removed
+    async fn purge_conversations(&self, before: chrono::DateTime<chrono::Utc>) -> Result<usize>;
+
+    /// Link an uploaded document to a conversation so its chunks rank first when retrieving for it
+    async fn attach_document(&self, conversation_id: &uuid::Uuid, document_id: &uuid::Uuid) -> Result<Conversation>;
+
+    /// Unlink a document from a conversation; attaching it again is a no-op until then
+    async fn detach_document(&self, conversation_id: &uuid::Uuid, document_id: &uuid::Uuid) -> Result<Conversation>;
+
+    /// Tokens, cost and time of the replies in a conversation
+    async fn conversation_usage(&self, conversation_id: &uuid::Uuid) -> Result<Usage> {
+        Ok(self.get_conversation(conversation_id).await?.iter().filter_map(Message::usage).sum())
+    }
+
+    /// Chunk and embed a document so searches find it straight away
+    async fn index_document(&self, document: NewDocument) -> Result<IndexedDocument>;
+
+    /// Index a document re-crawled from `source`, a watched file path or URL, and record the check
+    /// A source seen before keeps its document: changed content becomes its next version
+    async fn refresh_document(&self, source: &str, document: NewDocument) -> Result<RefreshedDocument>;
+
+    /// Search RAG context; with a conversation, chunks of its attached documents come first
+    async fn search_rag(
+        &self,
+        query: &str,
+        llm_id: Option<&str>,
+        conversation_id: Option<&uuid::Uuid>,
+        limit: usize,
+    ) -> Result<Vec<RAGResult>>;
+
+    /// Save a new prompt template as version 1; names are unique
+    async fn create_prompt_template(&self, template: NewPromptTemplate) -> Result<PromptTemplate>;
+
+    /// Save an edit as the template's next version, keeping the earlier ones
+    async fn update_prompt_template(&self, template_id: &uuid::Uuid, template: NewPromptTemplate) -> Result<PromptTemplate>;
+
+    /// A template at `version`, or its latest version
+    async fn get_prompt_template(&self, template_id: &uuid::Uuid, version: Option<i32>) -> Result<PromptTemplate>;
+
+    /// The latest version of every template, by name
+    async fn list_prompt_templates(&self) -> Result<Vec<PromptTemplate>>;
+
+    /// Every version of a template, newest first
+    async fn prompt_template_history(&self, template_id: &uuid::Uuid) -> Result<Vec<PromptTemplate>>;
+
+    /// Delete a template and all its versions
+    async fn delete_prompt_template(&self, template_id: &uuid::Uuid) -> Result<()>;
 }
 
 #[derive(Debug, Clone)]
 pub struct RAGResult {
     pub id: uuid::Uuid,
+    /// Document the chunk was taken from
+    pub document_id: Option<uuid::Uuid>,
     pub content: String,
     pub similarity: f32,
+    /// The document's own, plus `collection` and `name`, and `source` and `checked_at` when it is re-crawled
     pub metadata: HashMap<String, serde_json::Value>,
 }
+
+impl RAGResult {
+    /// When the chunk's source was last re-crawled; `None` unless it is watched
+    pub fn checked_at(&self) -> Option<chrono::DateTime<chrono::Utc>> {
+        self.metadata.get("checked_at").and_then(|at| serde_json::from_value(at.clone()).ok())
+    }
+
+    /// Whether the chunk comes from a watched source not re-crawled for `max_age`, e.g. as it stopped loading
+    pub fn is_stale(&self, max_age: std::time::Duration) -> bool {
+        let max_age = chrono::Duration::from_std(max_age).unwrap_or(chrono::Duration::MAX);
+        self.checked_at().is_some_and(|at| chrono::Utc::now() - at > max_age)
+    }
+}
+
+/// A tool LLMs can call; the orchestrator checks the permission it needs before running it
+#[async_trait]
+pub trait Tool: Send + Sync {
+    /// Name the model calls the tool by
+    fn name(&self) -> &str;
+
+    /// Tells the model what the tool does and when to use it
+    fn description(&self) -> &str;
+
+    /// JSON schema of the arguments
+    fn parameters(&self) -> serde_json::Value;
+
+    /// Whether the tool only reads; only these run in read-only mode
+    fn read_only(&self) -> bool {
+        false
+    }
+
+    /// Permission a call with these arguments needs; `None` for tools that stay within indexed or sandboxed data
+    fn permission(&self, _arguments: &serde_json::Value) -> Result<Option<PermissionType>> {
+        Ok(None)
+    }
+
+    /// Run a call whose arguments have been checked against the schema's required fields
+    async fn execute(&self, context: &RequestContext, arguments: serde_json::Value) -> Result<serde_json::Value>;
+
+    /// How the tool is offered to a model
+    fn definition(&self) -> ToolDefinition {
+        ToolDefinition {
+            name: self.name().to_string(),
+            description: self.description().to_string(),
+            parameters: self.parameters(),
+        }
+    }
+}
@@ -1,8 +1,12 @@
 use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
 use std::collections::HashMap;
 use uuid::Uuid;
 use chrono::{DateTime, Utc};
 
+use crate::completion::{ImageInput, ToolCall, ToolResult, Usage};
+use crate::errors::HybridLLMError;
+
 /// Represents the different types of LLM providers
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, Hash)]
 #[serde(rename_all = "snake_case")]
@@ -17,15 +21,83 @@ pub enum LLMProvider {
     Gemini,
 }
 
+impl LLMProvider {
+    /// Whether requests leave the machine
+    pub fn is_cloud(&self) -> bool {
+        !matches!(self, Self::Local(_))
+    }
+}
+
 /// Capabilities that an LLM can have
+/// Stored as plain lowercase names; names this version doesn't know load as `Custom` rather than failing
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, Hash)]
-#[serde(rename_all = "snake_case")]
+#[serde(from = "String", into = "String")]
 pub enum Capability {
     Code,
     Security,
     General,
     Analysis,
     Creative,
+    /// Turns text into vectors for retrieval
+    Embedding,
+    /// Takes images as input
+    Vision,
+    /// Transcribes or speaks audio
+    Audio,
+    /// Understands and writes languages other than English; `lang:<ISO 639-3 code>` tags mark single languages
+    Multilingual,
+    /// A tag of the user's own, for routing to models picked by hand
+    Custom(String),
+}
+
+impl Capability {
+    pub fn as_str(&self) -> &str {
+        match self {
+            Self::Code => "code",
+            Self::Security => "security",
+            Self::General => "general",
+            Self::Analysis => "analysis",
+            Self::Creative => "creative",
+            Self::Embedding => "embedding",
+            Self::Vision => "vision",
+            Self::Audio => "audio",
+            Self::Multilingual => "multilingual",
+            Self::Custom(tag) => tag,
+        }
+    }
+}
+
+impl From<String> for Capability {
+    fn from(name: String) -> Self {
+        let name = name.trim().to_lowercase();
+        match name.as_str() {
+            "code" => Self::Code,
+            "security" => Self::Security,
+            "general" => Self::General,
+            "analysis" => Self::Analysis,
+            "creative" => Self::Creative,
+            "embedding" | "embeddings" => Self::Embedding,
+            "vision" | "image" | "images" => Self::Vision,
+            "audio" | "speech" | "stt" | "tts" => Self::Audio,
+            "multilingual" => Self::Multilingual,
+            _ => Self::Custom(name),
+        }
+    }
+}
+
+impl From<Capability> for String {
+    fn from(capability: Capability) -> Self {
+        match capability {
+            Capability::Custom(tag) => tag,
+            known => known.as_str().to_string(),
+        }
+    }
+}
+
+impl std::fmt::Display for Capability {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
 }
 
 /// LLM instance identifier and metadata
@@ -39,6 +111,18 @@ pub struct LLMInstance {
     pub is_loaded: bool,
 }
 
+impl LLMInstance {
+    /// Whether the model suits text in `language`, an ISO 639-3 code; every model is taken to handle English
+    pub fn supports_language(&self, language: &str) -> bool {
+        language == crate::language::ENGLISH
+            || self.capabilities.iter().any(|capability| match capability {
+                Capability::Multilingual => true,
+                Capability::Custom(tag) => tag.strip_prefix(crate::language::LANGUAGE_TAG_PREFIX) == Some(language),
+                _ => false,
+            })
+    }
+}
+
 /// Context types for LLM operations
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(tag = "type", rename_all = "snake_case")]
@@ -52,12 +136,176 @@ pub enum ContextType {
 pub struct Message {
     pub id: Uuid,
     pub role: MessageRole,
-    pub content: String,
+    pub content: MessageContent,
     pub timestamp: DateTime<Utc>,
     pub metadata: HashMap<String, serde_json::Value>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+impl Message {
+    /// Metadata key for what generating the message took
+    pub const USAGE_KEY: &'static str = "usage";
+
+    /// Tool calls the message made; empty for most messages
+    pub fn tool_calls(&self) -> Vec<ToolCall> {
+        self.content
+            .parts()
+            .into_iter()
+            .filter_map(|part| match part {
+                ContentPart::ToolCall(call) => Some(call),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Replace the message's tool calls, keeping its other parts
+    pub fn set_tool_calls(&mut self, calls: &[ToolCall]) {
+        self.content.retain(|part| !matches!(part, ContentPart::ToolCall(_)));
+        for call in calls {
+            self.content.push(ContentPart::ToolCall(call.clone()));
+        }
+    }
+
+    /// The tool result this message carries, if it answers a tool call
+    pub fn tool_result(&self) -> Option<ToolResult> {
+        self.content.parts().into_iter().find_map(|part| match part {
+            ContentPart::ToolResult(result) => Some(result),
+            _ => None,
+        })
+    }
+
+    pub fn set_tool_result(&mut self, result: &ToolResult) {
+        self.content.retain(|part| !matches!(part, ContentPart::ToolResult(_)));
+        self.content.push(ContentPart::ToolResult(result.clone()));
+    }
+
+    /// Tokens, cost and time of a generated reply; `None` for the user's messages
+    pub fn usage(&self) -> Option<Usage> {
+        self.metadata
+            .get(Self::USAGE_KEY)
+            .and_then(|usage| serde_json::from_value(usage.clone()).ok())
+    }
+
+    pub fn set_usage(&mut self, usage: &Usage) {
+        self.metadata.insert(Self::USAGE_KEY.to_string(), serde_json::json!(usage));
+    }
+}
+
+/// What a message says: plain text, or parts for images, tool calls and attached files
+/// Plain text serializes as a bare string, so messages stored before parts existed still load
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum MessageContent {
+    Text(String),
+    Parts(Vec<ContentPart>),
+}
+
+/// One part of a multi-part message
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ContentPart {
+    Text { text: String },
+    /// An image sent with the message, base64 encoded
+    Image { media_type: String, data: String },
+    ToolCall(ToolCall),
+    ToolResult(ToolResult),
+    /// An uploaded document the message refers to, by id rather than by copy
+    File { document_id: Uuid, name: String },
+}
+
+impl MessageContent {
+    /// The message's text, its text parts joined by blank lines; images, tool calls and files are left out
+    pub fn text(&self) -> Cow<'_, str> {
+        match self {
+            Self::Text(text) => Cow::Borrowed(text),
+            Self::Parts(parts) => {
+                let texts: Vec<&str> = parts
+                    .iter()
+                    .filter_map(|part| match part {
+                        ContentPart::Text { text } => Some(text.as_str()),
+                        _ => None,
+                    })
+                    .collect();
+                match texts.as_slice() {
+                    [text] => Cow::Borrowed(text),
+                    _ => Cow::Owned(texts.join("\n\n")),
+                }
+            }
+        }
+    }
+
+    /// The content as parts; plain text is a single text part, empty text none
+    pub fn parts(&self) -> Vec<ContentPart> {
+        match self {
+            Self::Text(text) if text.is_empty() => Vec::new(),
+            Self::Text(text) => vec![ContentPart::Text { text: text.clone() }],
+            Self::Parts(parts) => parts.clone(),
+        }
+    }
+
+    /// The message's images, in the form providers take them
+    pub fn images(&self) -> Vec<ImageInput> {
+        match self {
+            Self::Text(_) => Vec::new(),
+            Self::Parts(parts) => parts
+                .iter()
+                .filter_map(|part| match part {
+                    ContentPart::Image { media_type, data } => {
+                        Some(ImageInput { media_type: media_type.clone(), data: data.clone() })
+                    }
+                    _ => None,
+                })
+                .collect(),
+        }
+    }
+
+    /// Whether the message is made of more than text
+    pub fn is_multipart(&self) -> bool {
+        matches!(self, Self::Parts(_))
+    }
+
+    pub fn push(&mut self, part: ContentPart) {
+        let mut parts = self.parts();
+        parts.push(part);
+        *self = Self::Parts(parts);
+    }
+
+    /// Keep only the parts `keep` accepts; what's left is plain text again if it's only text
+    pub fn retain(&mut self, keep: impl FnMut(&ContentPart) -> bool) {
+        let mut parts = self.parts();
+        parts.retain(keep);
+        *self = match parts.as_slice() {
+            [] => Self::default(),
+            [ContentPart::Text { text }] => Self::Text(text.clone()),
+            _ => Self::Parts(parts),
+        };
+    }
+}
+
+impl Default for MessageContent {
+    fn default() -> Self {
+        Self::Text(String::new())
+    }
+}
+
+impl From<String> for MessageContent {
+    fn from(text: String) -> Self {
+        Self::Text(text)
+    }
+}
+
+impl From<&str> for MessageContent {
+    fn from(text: &str) -> Self {
+        Self::Text(text.to_string())
+    }
+}
+
+impl From<Vec<ContentPart>> for MessageContent {
+    fn from(parts: Vec<ContentPart>) -> Self {
+        Self::Parts(parts)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 #[serde(rename_all = "lowercase")]
 pub enum MessageRole {
     User,
@@ -65,6 +313,219 @@ pub enum MessageRole {
     System,
 }
 
+/// RAG collection of the documents the user uploads
+pub const UPLOADS_COLLECTION: &str = "uploads";
+
+/// RAG collection of the pages the user sends over from the browser
+pub const BROWSING_COLLECTION: &str = "browsing";
+
+/// Text to index for retrieval
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct NewDocument {
+    /// Where the document came from, such as `BROWSING_COLLECTION`
+    pub collection: String,
+    /// File name or page title
+    pub name: String,
+    pub content: String,
+    /// Kept with the document and returned with its chunks, such as a page's URL
+    #[serde(default)]
+    pub metadata: HashMap<String, serde_json::Value>,
+}
+
+impl NewDocument {
+    /// Record the language the content is written in under `LANGUAGE_KEY`, unless the document already names one
+    pub fn detect_language(&mut self) {
+        if self.metadata.contains_key(crate::language::LANGUAGE_KEY) {
+            return;
+        }
+        if let Some(language) = crate::language::detect_language(&self.content) {
+            self.metadata.insert(crate::language::LANGUAGE_KEY.to_string(), language.into());
+        }
+    }
+}
+
+/// A document once indexed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IndexedDocument {
+    pub id: Uuid,
+    pub chunk_count: usize,
+}
+
+/// What re-crawling a watched source found
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RefreshStatus {
+    /// First seen, and indexed
+    Added,
+    /// Re-indexed as the document's next version
+    Changed,
+    Unchanged,
+}
+
+/// A watched document after a re-crawl
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RefreshedDocument {
+    pub id: Uuid,
+    pub status: RefreshStatus,
+    /// Chunks that had to be embedded; none when unchanged
+    pub chunk_count: usize,
+}
+
+/// A conversation as listed to the user
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Conversation {
+    pub id: Uuid,
+    /// Set by the user, otherwise taken from the first user message
+    pub title: String,
+    pub created_at: DateTime<Utc>,
+    /// Time of the latest message, or creation if there are none
+    pub last_activity: DateTime<Utc>,
+    pub message_count: usize,
+    /// Set while the conversation is in the trash
+    pub trashed_at: Option<DateTime<Utc>>,
+    /// Documents attached for retrieval, in the order they were attached
+    #[serde(default)]
+    pub document_ids: Vec<Uuid>,
+}
+
+impl Conversation {
+    /// Longest title derived from a message
+    pub const MAX_DERIVED_TITLE_CHARS: usize = 60;
+
+    /// Title shown for a conversation: its own, else the start of its first user message
+    pub fn display_title(title: Option<&str>, first_user_message: Option<&str>) -> String {
+        if let Some(title) = title.map(str::trim).filter(|t| !t.is_empty()) {
+            return title.to_string();
+        }
+
+        let line = first_user_message
+            .and_then(|m| m.lines().map(str::trim).find(|l| !l.is_empty()))
+            .unwrap_or_default();
+        if line.is_empty() {
+            return "New conversation".to_string();
+        }
+        if line.chars().count() <= Self::MAX_DERIVED_TITLE_CHARS {
+            return line.to_string();
+        }
+        let cut: String = line.chars().take(Self::MAX_DERIVED_TITLE_CHARS - 1).collect();
+        format!("{}…", cut.trim_end())
+    }
+}
+
+/// Contents of a prompt template, as saved by the user
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct NewPromptTemplate {
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    /// Prompt text with `{{variable}}` placeholders
+    pub body: String,
+    /// Bodies used instead for particular LLMs, keyed by LLM id
+    #[serde(default)]
+    pub model_overrides: HashMap<String, String>,
+}
+
+impl NewPromptTemplate {
+    /// Fail on an empty name or body
+    pub fn validate(&self) -> crate::errors::Result<()> {
+        if self.name.trim().is_empty() {
+            return Err(HybridLLMError::InvalidRequest("Prompt template needs a name".to_string()));
+        }
+        if self.body.trim().is_empty() || self.model_overrides.values().any(|body| body.trim().is_empty()) {
+            return Err(HybridLLMError::InvalidRequest(format!("Prompt template {} has an empty body", self.name)));
+        }
+        Ok(())
+    }
+}
+
+/// One saved version of a prompt template
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PromptTemplate {
+    pub id: Uuid,
+    /// 1 when first saved, then one more per edit
+    pub version: i32,
+    #[serde(flatten)]
+    pub template: NewPromptTemplate,
+    /// When this version was saved
+    pub created_at: DateTime<Utc>,
+}
+
+/// A prompt template picked by a message or a workflow step; without a version the latest is used
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PromptTemplateRef {
+    pub id: Uuid,
+    #[serde(default)]
+    pub version: Option<i32>,
+}
+
+impl PromptTemplate {
+    /// Variable that `render` fills with the text the user typed, unless given explicitly
+    pub const INPUT_VARIABLE: &'static str = "input";
+
+    /// Body for `llm_id`: its override, else the template's own
+    pub fn body_for(&self, llm_id: &str) -> &str {
+        self.template.model_overrides.get(llm_id).unwrap_or(&self.template.body)
+    }
+
+    /// Names of the placeholders in the body and overrides, in order of first use
+    pub fn variables(&self) -> Vec<String> {
+        let mut overrides: Vec<_> = self.template.model_overrides.iter().collect();
+        overrides.sort();
+        let mut names: Vec<String> = Vec::new();
+        for body in std::iter::once(&self.template.body).chain(overrides.into_iter().map(|(_, body)| body)) {
+            for (_, name) in placeholders(body) {
+                if !names.iter().any(|n| n == name) {
+                    names.push(name.to_string());
+                }
+            }
+        }
+        names
+    }
+
+    /// The prompt to send to `llm_id`, failing if a placeholder has no value
+    pub fn render(&self, llm_id: &str, variables: &HashMap<String, String>) -> crate::errors::Result<String> {
+        let body = self.body_for(llm_id);
+        let missing: Vec<&str> = placeholders(body)
+            .map(|(_, name)| name)
+            .filter(|name| !variables.contains_key(*name))
+            .collect();
+        if !missing.is_empty() {
+            return Err(HybridLLMError::InvalidRequest(format!(
+                "Prompt template {} needs a value for {}",
+                self.template.name,
+                missing.join(", ")
+            )));
+        }
+
+        let mut rendered = String::with_capacity(body.len());
+        let mut rest = 0;
+        for (range, name) in placeholders(body) {
+            rendered.push_str(&body[rest..range.start]);
+            rendered.push_str(&variables[name]);
+            rest = range.end;
+        }
+        rendered.push_str(&body[rest..]);
+        Ok(rendered)
+    }
+}
+
+/// `{{name}}` placeholders in `body` with their byte ranges; braces around anything else are left as text
+fn placeholders(body: &str) -> impl Iterator<Item = (std::ops::Range<usize>, &str)> {
+    let mut from = 0;
+    std::iter::from_fn(move || {
+        while let Some(open) = body[from..].find("{{").map(|i| from + i) {
+            let close = body[open + 2..].find("}}").map(|i| open + 2 + i)?;
+            let name = body[open + 2..close].trim();
+            if !name.is_empty() && name.chars().all(|c| c.is_alphanumeric() || c == '_') {
+                from = close + 2;
+                return Some((open..close + 2, name));
+            }
+            from = open + 1;
+        }
+        None
+    })
+}
+
 /// Permission scope
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct PermissionScope {
@@ -72,6 +533,8 @@ pub struct PermissionScope {
     pub network: NetworkPermissions,
     pub commands: CommandPermissions,
     pub resources: ResourceLimits,
+    #[serde(default)]
+    pub packages: PackagePermissions,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -86,6 +549,41 @@ pub struct NetworkPermissions {
     pub inbound: bool,
     pub outbound: bool,
     pub require_approval: Vec<String>, // glob patterns
+    /// Domains sandboxes may reach in proxy-only mode, and tools may call (`*.example.com` matches subdomains)
+    #[serde(default)]
+    pub allowed_domains: Vec<String>,
+}
+
+impl NetworkPermissions {
+    /// Whether outbound requests may go to `url`, by its host
+    pub fn allows_url(&self, url: &str) -> bool {
+        self.outbound
+            && url_host(url).is_some_and(|host| self.allowed_domains.iter().any(|pattern| domain_matches(pattern, &host)))
+    }
+}
+
+/// Match a host against an allowlist entry; `*.example.com` covers subdomains only
+pub fn domain_matches(pattern: &str, host: &str) -> bool {
+    let pattern = pattern.to_lowercase();
+    let host = host.trim_end_matches('.').to_lowercase();
+
+    match pattern.strip_prefix("*.") {
+        Some(suffix) => host.ends_with(&format!(".{}", suffix)),
+        None => host == pattern,
+    }
+}
+
+/// Lowercased host of an `http` or `https` URL
+pub fn url_host(url: &str) -> Option<String> {
+    let rest = url.strip_prefix("https://").or_else(|| url.strip_prefix("http://"))?;
+    let authority = rest.split(['/', '?', '#']).next()?;
+    let host = authority.rsplit('@').next()?;
+    let host = match host.strip_prefix('[') {
+        // IPv6 literals keep their colons
+        Some(literal) => literal.split(']').next()?,
+        None => host.split(':').next()?,
+    };
+    Some(host.to_lowercase()).filter(|host| !host.is_empty())
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -95,6 +593,17 @@ pub struct CommandPermissions {
     pub require_explanation: bool,
 }
 
+/// Dependencies sandbox language runners may install
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct PackagePermissions {
+    /// Installable package names per language; `*` allows any package
+    #[serde(default)]
+    pub allowed: HashMap<CodeLanguage, Vec<String>>,
+    /// Registry each language installs from; the public registry when unset
+    #[serde(default)]
+    pub registries: HashMap<CodeLanguage, String>,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ResourceLimits {
     pub max_cpu_percent: f32,
@@ -102,15 +611,50 @@ pub struct ResourceLimits {
     pub max_disk_gb: f32,
 }
 
-/// Security lockdown state
-#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+/// Security lockdown state, ordered from least to most restrictive
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord)]
 #[serde(rename_all = "snake_case")]
 pub enum LockdownState {
     Normal,
+    /// Reading, searching and answering go on; nothing is written, executed or sent out
     ReadOnly,
     Locked,
 }
 
+impl LockdownState {
+    /// Whether reads such as RAG search, file reads and answering from existing context may run
+    pub fn allows_reads(self) -> bool {
+        self != LockdownState::Locked
+    }
+
+    /// Whether anything that writes files, runs code or reaches the network may run
+    pub fn allows_writes(self) -> bool {
+        self == LockdownState::Normal
+    }
+
+    /// The state after a lockdown of `level`; a lockdown never relaxes the current state
+    pub fn escalate(self, level: LockdownLevel) -> Self {
+        self.max(level.into())
+    }
+}
+
+/// How far a lockdown goes
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum LockdownLevel {
+    ReadOnly,
+    Locked,
+}
+
+impl From<LockdownLevel> for LockdownState {
+    fn from(level: LockdownLevel) -> Self {
+        match level {
+            LockdownLevel::ReadOnly => LockdownState::ReadOnly,
+            LockdownLevel::Locked => LockdownState::Locked,
+        }
+    }
+}
+
 /// Lockdown trigger reasons
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "snake_case")]
@@ -119,6 +663,8 @@ pub enum LockdownReason {
     SuspiciousPattern { pattern: String },
     ResourceExceeded { resource: String, limit: f32, actual: f32 },
     UserPanicButton,
+    /// The user chose to restrict the system, e.g. entering read-only mode from the dashboard
+    UserRequested { details: String },
     MultipleFailedRequests { count: usize },
 }
 
@@ -132,6 +678,65 @@ pub struct AuditLogEntry {
     pub details: serde_json::Value,
     pub approved: bool,
     pub reason: Option<String>,
+    /// The user action or background job the entry belongs to; absent from entries logged outside one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub context: Option<RequestContext>,
+}
+
+/// Who and what a piece of work is for, passed along so logs and audit entries trace back to what started it
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RequestContext {
+    /// Shared by everything one user action or background job causes, delegations and tool calls included
+    pub trace_id: Uuid,
+    pub conversation_id: Option<Uuid>,
+    /// LLM doing the work, once one has been picked
+    pub llm_id: Option<String>,
+    /// Whether the user started it, rather than an LLM or a background job
+    pub user_initiated: bool,
+}
+
+impl RequestContext {
+    /// Work the user asked for
+    pub fn user() -> Self {
+        Self { trace_id: Uuid::new_v4(), conversation_id: None, llm_id: None, user_initiated: true }
+    }
+
+    /// Work nobody asked for directly, such as health checks or indexing
+    pub fn background() -> Self {
+        Self { user_initiated: false, ..Self::user() }
+    }
+
+    pub fn with_trace_id(mut self, trace_id: Uuid) -> Self {
+        self.trace_id = trace_id;
+        self
+    }
+
+    pub fn with_conversation(mut self, conversation_id: Uuid) -> Self {
+        self.conversation_id = Some(conversation_id);
+        self
+    }
+
+    /// The same work, handed to `llm_id`
+    pub fn with_llm(mut self, llm_id: impl Into<String>) -> Self {
+        self.llm_id = Some(llm_id.into());
+        self
+    }
+
+    /// Who is acting: the LLM, or the user when no LLM is involved
+    pub fn actor(&self) -> &str {
+        self.llm_id.as_deref().unwrap_or("user")
+    }
+
+    /// A span that tags the log lines emitted inside it with this context
+    pub fn span(&self) -> tracing::Span {
+        tracing::info_span!(
+            "request",
+            trace_id = %self.trace_id,
+            conversation_id = self.conversation_id.map(tracing::field::display),
+            llm_id = self.llm_id.as_deref(),
+            user_initiated = self.user_initiated,
+        )
+    }
 }
 
 /// Task classification for routing
@@ -149,11 +754,114 @@ pub enum TaskType {
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SandboxConfig {
     pub id: Uuid,
-    pub network_enabled: bool,
+    pub network_mode: NetworkMode,
     pub cpu_limit: f32,
     pub memory_limit_gb: f32,
     pub disk_limit_gb: f32,
     pub allowed_commands: Vec<String>,
+    /// Prebuilt environment to start from; empty sandbox when unset
+    #[serde(default)]
+    pub template: Option<SandboxTemplate>,
+    /// Reclaim the sandbox this many seconds after creation
+    #[serde(default)]
+    pub max_lifetime_secs: Option<u64>,
+    /// Reclaim the sandbox after this many seconds without a running command
+    #[serde(default)]
+    pub idle_timeout_secs: Option<u64>,
+    /// Kill a single command after this many seconds; the manager default when unset
+    #[serde(default)]
+    pub execution_timeout_secs: Option<u64>,
+    /// LLM the sandbox was created for, recorded with its audit entries
+    #[serde(default)]
+    pub llm_id: Option<String>,
+    /// GPU memory to reserve for the sandbox; no GPU when unset
+    #[serde(default)]
+    pub gpu: Option<GpuRequest>,
+    /// Persistent volumes mounted under `volumes/<name>` in the sandbox
+    #[serde(default)]
+    pub volumes: Vec<VolumeMount>,
+}
+
+/// GPU access for a sandbox, reserved from the same VRAM budget as local models
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub struct GpuRequest {
+    pub vram_mb: u64,
+    /// Device index to use; any device with enough free VRAM when unset
+    #[serde(default)]
+    pub device: Option<u32>,
+}
+
+/// A named persistent volume attached to a sandbox
+/// Volumes outlive the sandboxes they are mounted into
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct VolumeMount {
+    pub name: String,
+    #[serde(default)]
+    pub read_only: bool,
+}
+
+/// Prebuilt sandbox environments with preinstalled toolchains
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Hash)]
+#[serde(rename_all = "kebab-case")]
+pub enum SandboxTemplate {
+    /// Python with numpy, pandas, matplotlib and friends
+    PythonData,
+    /// Node.js with npm
+    Node,
+    /// Rust stable toolchain with cargo
+    Rust,
+    /// POSIX shell and coreutils only
+    ShellMinimal,
+}
+
+impl SandboxTemplate {
+    pub const ALL: [SandboxTemplate; 4] = [
+        SandboxTemplate::PythonData,
+        SandboxTemplate::Node,
+        SandboxTemplate::Rust,
+        SandboxTemplate::ShellMinimal,
+    ];
+
+    /// Directory name of the template's prebuilt image
+    pub fn name(&self) -> &'static str {
+        match self {
+            SandboxTemplate::PythonData => "python-data",
+            SandboxTemplate::Node => "node",
+            SandboxTemplate::Rust => "rust",
+            SandboxTemplate::ShellMinimal => "shell-minimal",
+        }
+    }
+
+    /// Template with the toolchain for running code in `language`
+    pub fn for_language(language: CodeLanguage) -> Self {
+        match language {
+            CodeLanguage::Python => SandboxTemplate::PythonData,
+            CodeLanguage::JavaScript => SandboxTemplate::Node,
+            CodeLanguage::Rust => SandboxTemplate::Rust,
+        }
+    }
+}
+
+/// Network access granted to a sandbox
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum NetworkMode {
+    /// Isolated network namespace, loopback only
+    #[default]
+    None,
+    /// Egress only through the filtering proxy, limited to allowed domains
+    ProxyOnly,
+    /// Unrestricted egress, every proxied connection is audited
+    Full,
+}
+
+/// Languages supported for sandboxed code evaluation
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum CodeLanguage {
+    Python,
+    JavaScript,
+    Rust,
 }
 
 /// Artifact transfer request from sandbox
@@ -166,6 +874,84 @@ pub struct ArtifactTransfer {
     pub approved: Option<bool>,
 }
 
+/// Request to expose a sandbox port on the host's loopback interface
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PortForwardRequest {
+    pub sandbox_id: Uuid,
+    pub sandbox_port: u16,
+    /// The forward is torn down after this many seconds
+    pub duration_secs: u64,
+    pub explanation: String,
+}
+
+/// Result of scanning an artifact before it may leave a sandbox
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ArtifactScanReport {
+    /// MIME type detected from the file contents
+    pub mime_type: String,
+    pub size_bytes: u64,
+    pub findings: Vec<ScanFinding>,
+    /// Whether ClamAV was available and ran
+    pub malware_scanned: bool,
+    pub verdict: ScanVerdict,
+}
+
+/// A single issue found while scanning an artifact
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScanFinding {
+    pub kind: ScanFindingKind,
+    pub description: String,
+    /// 1-based line number for findings in text files
+    pub line: Option<usize>,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ScanFindingKind {
+    Secret,
+    DisallowedType,
+    TypeMismatch,
+    SizeLimit,
+    Malware,
+}
+
+/// Overall outcome of an artifact scan
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "snake_case")]
+pub enum ScanVerdict {
+    /// Nothing found
+    Clean,
+    /// Findings the user should review before approving
+    Warning,
+    /// Transfer refused without asking the user
+    Blocked,
+}
+
+/// Outcome of a malware scan of one file
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum MalwareScan {
+    Clean,
+    Infected { signature: String },
+}
+
+/// Resource usage of one sandbox, sampled by the sandbox watchdog
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct SandboxUsage {
+    pub sandbox_id: Uuid,
+    pub timestamp: DateTime<Utc>,
+    /// CPU over the last sample interval, as a percentage of one core
+    pub cpu_percent: f32,
+    pub memory_bytes: u64,
+    pub disk_bytes: u64,
+    /// Bytes sent through the egress proxy since the sandbox was created
+    pub network_sent_bytes: u64,
+    /// Bytes received through the egress proxy since the sandbox was created
+    pub network_received_bytes: u64,
+    /// Running processes
+    pub processes: usize,
+}
+
 impl Default for PermissionScope {
     fn default() -> Self {
         Self {
@@ -181,6 +967,14 @@ impl Default for PermissionScope {
                 inbound: true,
                 outbound: true,
                 require_approval: vec!["*".to_string()],
+                allowed_domains: vec![
+                    "pypi.org".to_string(),
+                    "files.pythonhosted.org".to_string(),
+                    "registry.npmjs.org".to_string(),
+                    "crates.io".to_string(),
+                    "static.crates.io".to_string(),
+                    "index.crates.io".to_string(),
+                ],
             },
             commands: CommandPermissions {
                 whitelist: vec![
@@ -204,6 +998,139 @@ impl Default for PermissionScope {
                 max_memory_gb: 8.0,
                 max_disk_gb: 50.0,
             },
+            packages: PackagePermissions {
+                allowed: HashMap::from([
+                    (
+                        CodeLanguage::Python,
+                        ["numpy", "pandas", "matplotlib", "scipy", "requests"]
+                            .map(String::from)
+                            .to_vec(),
+                    ),
+                    (
+                        CodeLanguage::JavaScript,
+                        ["lodash", "axios", "date-fns"].map(String::from).to_vec(),
+                    ),
+                    (
+                        CodeLanguage::Rust,
+                        ["serde", "serde_json", "rand", "regex"].map(String::from).to_vec(),
+                    ),
+                ]),
+                registries: HashMap::new(),
+            },
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_allows_url() {
+        let network = NetworkPermissions {
+            inbound: false,
+            outbound: true,
+            require_approval: vec![],
+            allowed_domains: vec!["api.search.brave.com".to_string(), "*.example.com".to_string()],
+        };
+        assert!(network.allows_url("https://api.search.brave.com/res/v1/web/search?q=rust"));
+        assert!(network.allows_url("http://user@docs.example.com:8080/a"));
+        assert!(!network.allows_url("https://example.com/"));
+        assert!(!network.allows_url("https://api.search.brave.com.evil.net/"));
+        assert!(!network.allows_url("ftp://api.search.brave.com/"));
+        assert_eq!(url_host("http://[::1]:8888/search").as_deref(), Some("::1"));
+
+        let offline = NetworkPermissions { outbound: false, ..network };
+        assert!(!offline.allows_url("https://api.search.brave.com/"));
+    }
+
+    #[test]
+    fn test_lockdown_escalate() {
+        assert_eq!(LockdownState::Normal.escalate(LockdownLevel::ReadOnly), LockdownState::ReadOnly);
+        assert_eq!(LockdownState::Locked.escalate(LockdownLevel::ReadOnly), LockdownState::Locked);
+        assert!(LockdownState::ReadOnly.allows_reads());
+        assert!(!LockdownState::ReadOnly.allows_writes());
+        assert!(!LockdownState::Locked.allows_reads());
+    }
+
+    #[test]
+    fn test_message_content_serde() {
+        // Messages stored before parts existed hold a bare string
+        let old: MessageContent = serde_json::from_value(serde_json::json!("hello")).unwrap();
+        assert_eq!(old, MessageContent::Text("hello".to_string()));
+        assert_eq!(serde_json::to_value(&old).unwrap(), serde_json::json!("hello"));
+
+        let parts: MessageContent = serde_json::from_value(serde_json::json!([
+            { "type": "text", "text": "What is this?" },
+            { "type": "image", "media_type": "image/png", "data": "iVBORw0KGgo=" },
+            { "type": "text", "text": "And this?" },
+        ]))
+        .unwrap();
+        assert!(parts.is_multipart());
+        assert_eq!(parts.text(), "What is this?\n\nAnd this?");
+        assert_eq!(parts.parts().len(), 3);
+    }
+
+    #[test]
+    fn test_prompt_template_render() {
+        let template = PromptTemplate {
+            id: Uuid::new_v4(),
+            version: 2,
+            template: NewPromptTemplate {
+                name: "Review".to_string(),
+                description: String::new(),
+                body: "Review this {{ language }} code:\n{{input}}\nKeep {{ not a var }} and {{language}}".to_string(),
+                model_overrides: HashMap::from([("local-small".to_string(), "{{input}} ({{focus}})".to_string())]),
+            },
+            created_at: Utc::now(),
+        };
+        assert_eq!(template.variables(), vec!["language", "input", "focus"]);
+
+        let mut variables = HashMap::from([("input".to_string(), "fn main() {}".to_string())]);
+        let missing = template.render("claude", &variables).unwrap_err();
+        assert!(missing.to_string().contains("language"));
+
+        variables.insert("language".to_string(), "Rust".to_string());
+        assert_eq!(
+            template.render("claude", &variables).unwrap(),
+            "Review this Rust code:\nfn main() {}\nKeep {{ not a var }} and Rust"
+        );
+        variables.insert("focus".to_string(), "{{language}}".to_string());
+        assert_eq!(template.render("local-small", &variables).unwrap(), "fn main() {} ({{language}})");
+
+        assert!(NewPromptTemplate { body: " ".to_string(), ..template.template.clone() }.validate().is_err());
+        assert!(template.template.validate().is_ok());
+    }
+
+    #[test]
+    fn test_supports_language() {
+        let llm = |capabilities: Vec<Capability>| LLMInstance {
+            id: "llm".to_string(),
+            provider: LLMProvider::Local("llm".to_string()),
+            capabilities,
+            model_name: "llm".to_string(),
+            max_context: 4096,
+            is_loaded: true,
+        };
+
+        let coder = llm(vec![Capability::Code]);
+        assert!(coder.supports_language("eng") && !coder.supports_language("jpn"));
+        assert!(llm(vec![Capability::Multilingual]).supports_language("jpn"));
+        let japanese = llm(vec![Capability::from("lang:jpn".to_string())]);
+        assert!(japanese.supports_language("jpn") && !japanese.supports_language("deu"));
+    }
+
+    #[test]
+    fn test_capability_serde() {
+        let parsed: Vec<Capability> =
+            serde_json::from_value(serde_json::json!(["code", "Embeddings", "stt", "legal-review"])).unwrap();
+        assert_eq!(
+            parsed,
+            vec![Capability::Code, Capability::Embedding, Capability::Audio, Capability::Custom("legal-review".to_string())]
+        );
+        assert_eq!(
+            serde_json::to_value(&parsed).unwrap(),
+            serde_json::json!(["code", "embedding", "audio", "legal-review"])
+        );
+    }
+}
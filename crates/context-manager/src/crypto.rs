@@ -0,0 +1,150 @@
+use aes_gcm::aead::rand_core::RngCore;
+use aes_gcm::aead::{Aead, AeadCore, KeyInit, OsRng};
+use aes_gcm::{Aes256Gcm, Key, Nonce};
+use argon2::Argon2;
+use base64::{engine::general_purpose::STANDARD, Engine};
+use common::errors::{HybridLLMError, Result};
+
+/// Marks stored text as ciphertext; anything without it was written before encryption was turned on
+pub const ENCRYPTED_PREFIX: &str = "enc:v1:";
+
+/// Bytes of the random salt the key is derived with
+pub const SALT_LEN: usize = 16;
+
+/// Plaintext encrypted into the verifier, to tell a wrong passphrase from damaged data
+const VERIFIER_PLAINTEXT: &str = "hybrid-llm content key";
+
+const NONCE_LEN: usize = 12;
+
+/// AES-256-GCM over stored text, with a key derived from a passphrase by Argon2id
+#[derive(Clone)]
+pub struct ContentCipher {
+    key: [u8; 32],
+}
+
+impl std::fmt::Debug for ContentCipher {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("ContentCipher(..)")
+    }
+}
+
+impl ContentCipher {
+    /// Derive the key from a passphrase; slow on purpose
+    pub fn derive(passphrase: &str, salt: &[u8]) -> Result<Self> {
+        let mut key = [0u8; 32];
+        Argon2::default()
+            .hash_password_into(passphrase.as_bytes(), salt, &mut key)
+            .map_err(|e| HybridLLMError::InvalidRequest(format!("Cannot derive a key: {}", e)))?;
+        Ok(Self { key })
+    }
+
+    /// A key kept elsewhere, e.g. in the OS keyring, as returned by `key_base64`
+    pub fn from_key_base64(key: &str) -> Result<Self> {
+        let key: [u8; 32] = STANDARD
+            .decode(key)
+            .ok()
+            .and_then(|key| key.try_into().ok())
+            .ok_or_else(|| HybridLLMError::ConfigError("Content key must be 32 bytes in base64".to_string()))?;
+        Ok(Self { key })
+    }
+
+    pub fn key_base64(&self) -> String {
+        STANDARD.encode(self.key)
+    }
+
+    pub fn random_salt() -> [u8; SALT_LEN] {
+        let mut salt = [0u8; SALT_LEN];
+        OsRng.fill_bytes(&mut salt);
+        salt
+    }
+
+    /// Ciphertext stored next to the salt; `verify` tells whether a derived key matches it
+    pub fn verifier(&self) -> String {
+        self.encrypt(VERIFIER_PLAINTEXT)
+    }
+
+    pub fn verify(&self, verifier: &str) -> bool {
+        self.decrypt(verifier).is_ok_and(|plaintext| plaintext == VERIFIER_PLAINTEXT)
+    }
+
+    /// `enc:v1:` and the base64 of a random nonce followed by the ciphertext
+    pub fn encrypt(&self, plaintext: &str) -> String {
+        let cipher = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&self.key));
+        let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+        // Encrypting into a Vec only fails when the plaintext is larger than GCM allows
+        let ciphertext = cipher.encrypt(&nonce, plaintext.as_bytes()).expect("plaintext too large for AES-GCM");
+
+        let mut sealed = nonce.to_vec();
+        sealed.extend(ciphertext);
+        format!("{}{}", ENCRYPTED_PREFIX, STANDARD.encode(sealed))
+    }
+
+    /// Text written with `encrypt`; text without the prefix is returned as it is
+    pub fn decrypt(&self, stored: &str) -> Result<String> {
+        let Some(encoded) = stored.strip_prefix(ENCRYPTED_PREFIX) else {
+            return Ok(stored.to_string());
+        };
+        let undecryptable = || HybridLLMError::SecurityViolation("Cannot decrypt stored content: wrong key or damaged data".to_string());
+
+        let sealed = STANDARD.decode(encoded).map_err(|_| undecryptable())?;
+        if sealed.len() < NONCE_LEN {
+            return Err(undecryptable());
+        }
+        let (nonce, ciphertext) = sealed.split_at(NONCE_LEN);
+        let cipher = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&self.key));
+        let plaintext = cipher.decrypt(Nonce::from_slice(nonce), ciphertext).map_err(|_| undecryptable())?;
+        String::from_utf8(plaintext).map_err(|_| undecryptable())
+    }
+
+    /// A JSON value stored as the encrypted string of its serialization
+    pub fn encrypt_json(&self, value: &serde_json::Value) -> serde_json::Value {
+        serde_json::Value::String(self.encrypt(&value.to_string()))
+    }
+
+    /// A value written with `encrypt_json`; other values are returned as they are
+    pub fn decrypt_json(&self, stored: serde_json::Value) -> Result<serde_json::Value> {
+        match stored.as_str() {
+            Some(text) if is_encrypted(text) => serde_json::from_str(&self.decrypt(text)?)
+                .map_err(|e| HybridLLMError::DatabaseError(format!("Decrypted value is not JSON: {}", e))),
+            _ => Ok(stored),
+        }
+    }
+}
+
+pub fn is_encrypted(stored: &str) -> bool {
+    stored.starts_with(ENCRYPTED_PREFIX)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_content_cipher() {
+        let salt = ContentCipher::random_salt();
+        let cipher = ContentCipher::derive("correct horse battery staple", &salt).unwrap();
+
+        let sealed = cipher.encrypt("The launch moves to Friday");
+        assert!(is_encrypted(&sealed));
+        assert!(!sealed.contains("Friday"));
+        assert_ne!(sealed, cipher.encrypt("The launch moves to Friday"), "nonces must differ");
+        assert_eq!(cipher.decrypt(&sealed).unwrap(), "The launch moves to Friday");
+        // Rows written before encryption was turned on
+        assert_eq!(cipher.decrypt("plain text").unwrap(), "plain text");
+
+        let value = json!({ "tone": "formal", "projects": ["apollo"] });
+        assert_eq!(cipher.decrypt_json(cipher.encrypt_json(&value)).unwrap(), value);
+        assert_eq!(cipher.decrypt_json(json!(42)).unwrap(), json!(42));
+
+        // The same passphrase and salt give the same key; the keyring copy round-trips
+        let again = ContentCipher::derive("correct horse battery staple", &salt).unwrap();
+        assert!(again.verify(&cipher.verifier()));
+        let restored = ContentCipher::from_key_base64(&cipher.key_base64()).unwrap();
+        assert_eq!(restored.decrypt(&sealed).unwrap(), "The launch moves to Friday");
+
+        let wrong = ContentCipher::derive("wrong passphrase", &salt).unwrap();
+        assert!(!wrong.verify(&cipher.verifier()));
+        assert!(wrong.decrypt(&sealed).is_err());
+    }
+}
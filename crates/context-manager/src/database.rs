@@ -1,16 +1,80 @@
 use common::{
     errors::{Result, HybridLLMError},
     traits::{ContextManager, RAGResult},
-    types::Message,
+    types::{
+        Conversation, IndexedDocument, Message, MessageContent, NewDocument, NewPromptTemplate, PromptTemplate,
+        RefreshStatus, RefreshedDocument,
+    },
 };
 use async_trait::async_trait;
-use sqlx::{PgPool, postgres::PgPoolOptions, Row};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::{PgPool, postgres::{PgPoolOptions, PgRow}, Row};
 use std::collections::HashMap;
-use tracing::{info, debug, error};
+use std::sync::{Arc, RwLock};
+use tracing::{info, debug, warn};
+use uuid::Uuid;
+
+use crate::crypto::{ContentCipher, ENCRYPTED_PREFIX, is_encrypted};
+use crate::embeddings::EmbeddingGenerator;
+use crate::retrieval::{lexical_similarity, prefer_language, prioritize_attached};
+use crate::versioning::{ChunkDiff, chunk_hash, diff_chunks, section_chunks};
+
+/// Conversation columns shared by the queries that return `Conversation`s; callers add WHERE/GROUP BY
+const CONVERSATION_COLUMNS: &str = "\
+    SELECT c.id, c.title, c.created_at, c.trashed_at, \
+           COUNT(m.id) AS message_count, \
+           COALESCE(MAX(m.timestamp), c.created_at) AS last_activity, \
+           (SELECT f.content FROM messages f \
+            WHERE f.conversation_id = c.id AND f.role = 'user' \
+            ORDER BY f.timestamp LIMIT 1) AS first_user_message, \
+           ARRAY(SELECT d.document_id FROM conversation_documents d \
+                 WHERE d.conversation_id = c.id ORDER BY d.attached_at) AS document_ids \
+    FROM conversations c LEFT JOIN messages m ON m.conversation_id = c.id";
+
+/// Template columns shared by the queries that return `PromptTemplate`s; callers add WHERE/ORDER BY
+const PROMPT_TEMPLATE_COLUMNS: &str = "\
+    SELECT t.id, t.name, v.version, v.description, v.body, v.model_overrides, v.created_at \
+    FROM prompt_templates t JOIN prompt_template_versions v ON v.template_id = t.id";
+
+/// Longest document name the `filename` column takes
+const MAX_DOCUMENT_NAME_CHARS: usize = 255;
+
+/// Columns of the chunks a RAG search returns; callers add WHERE/ORDER BY
+const RAG_CHUNK_COLUMNS: &str = "\
+    SELECT c.id, c.document_id, c.chunk_text, d.filename, d.collection, d.metadata, d.source, d.checked_at \
+    FROM document_chunks c JOIN documents d ON d.id = c.document_id";
+
+/// Tables a backup holds, tables referenced by others first
+pub const BACKUP_TABLES: [&str; 12] = [
+    "conversations",
+    "messages",
+    "conversation_documents",
+    "documents",
+    "document_chunks",
+    "llm_contexts",
+    "global_context",
+    "prompt_templates",
+    "prompt_template_versions",
+    "content_encryption",
+    "audit_log",
+    "lockdown_events",
+];
+
+/// The rows of one table, each as the JSON object of its columns
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TableDump {
+    pub table: String,
+    pub rows: Vec<serde_json::Value>,
+}
 
 /// PostgreSQL-backed context manager with RAG support
 pub struct DatabaseContextManager {
     pool: PgPool,
+    /// Embeds chunks as they are indexed and queries as they are searched
+    embeddings: RwLock<Arc<EmbeddingGenerator>>,
+    /// Encrypts message content, titles, context values and document text when set
+    cipher: RwLock<Option<ContentCipher>>,
 }
 
 impl DatabaseContextManager {
@@ -26,13 +90,535 @@ impl DatabaseContextManager {
 
         info!("✅ Connected to PostgreSQL");
 
-        Ok(Self { pool })
+        Ok(Self { pool, embeddings: RwLock::default(), cipher: RwLock::new(None) })
+    }
+
+    /// Create a database context manager that connects on first use
+    pub fn connect_lazy(database_url: &str) -> Result<Self> {
+        let pool = PgPoolOptions::new()
+            .max_connections(10)
+            .connect_lazy(database_url)
+            .map_err(|e| HybridLLMError::DatabaseError(format!("Invalid database URL: {}", e)))?;
+
+        Ok(Self { pool, embeddings: RwLock::default(), cipher: RwLock::new(None) })
+    }
+
+    /// Encrypt what is written from now on, and read what was, with a key kept e.g. in the OS keyring
+    pub fn with_cipher(self, cipher: ContentCipher) -> Self {
+        *self.cipher.write().unwrap() = Some(cipher);
+        self
+    }
+
+    /// Embed what is indexed or searched from now on with `embeddings`, e.g. one backed by a local model
+    pub fn set_embeddings(&self, embeddings: EmbeddingGenerator) {
+        *self.embeddings.write().unwrap() = Arc::new(embeddings);
+    }
+
+    pub fn embeddings(&self) -> Arc<EmbeddingGenerator> {
+        Arc::clone(&self.embeddings.read().unwrap())
     }
 
     /// Get the database pool for direct access
     pub fn pool(&self) -> &PgPool {
         &self.pool
     }
+
+    /// Whether a passphrase has been set for this database
+    pub async fn encryption_enabled(&self) -> Result<bool> {
+        let row = sqlx::query("SELECT 1 FROM content_encryption")
+            .fetch_optional(&self.pool)
+            .await
+            .map_err(|e| HybridLLMError::DatabaseError(e.to_string()))?;
+        Ok(row.is_some())
+    }
+
+    /// Whether encrypted content can be read and new content is encrypted
+    pub fn is_unlocked(&self) -> bool {
+        self.cipher.read().unwrap().is_some()
+    }
+
+    /// Derive the key from the passphrase, setting it the first time, and encrypt everything still stored in
+    /// the clear; returns the cipher so its key can be kept for the next start
+    pub async fn enable_encryption(&self, passphrase: &str) -> Result<ContentCipher> {
+        let db_err = |e: sqlx::Error| HybridLLMError::DatabaseError(e.to_string());
+
+        let stored = sqlx::query("SELECT salt, verifier FROM content_encryption")
+            .fetch_optional(&self.pool)
+            .await
+            .map_err(db_err)?;
+        let cipher = match stored {
+            Some(row) => {
+                let salt: Vec<u8> = row.try_get("salt").map_err(db_err)?;
+                let verifier: String = row.try_get("verifier").map_err(db_err)?;
+                let cipher = ContentCipher::derive(passphrase, &salt)?;
+                if !cipher.verify(&verifier) {
+                    return Err(HybridLLMError::PermissionDenied("Wrong passphrase for the stored content".to_string()));
+                }
+                cipher
+            }
+            None => {
+                let salt = ContentCipher::random_salt();
+                let cipher = ContentCipher::derive(passphrase, &salt)?;
+                sqlx::query("INSERT INTO content_encryption (salt, verifier) VALUES ($1, $2)")
+                    .bind(&salt[..])
+                    .bind(cipher.verifier())
+                    .execute(&self.pool)
+                    .await
+                    .map_err(db_err)?;
+                info!("🔐 Encryption at rest turned on");
+                cipher
+            }
+        };
+
+        *self.cipher.write().unwrap() = Some(cipher.clone());
+        let encrypted = self.encrypt_stored_content().await?;
+        if encrypted > 0 {
+            info!("🔐 Encrypted {} rows stored in the clear", encrypted);
+        }
+        Ok(cipher)
+    }
+
+    /// Rewrite rows written before encryption was turned on; returns how many were rewritten
+    async fn encrypt_stored_content(&self) -> Result<usize> {
+        let db_err = |e: sqlx::Error| HybridLLMError::DatabaseError(e.to_string());
+        let like = format!("{}%", ENCRYPTED_PREFIX);
+        let mut tx = self.pool.begin().await.map_err(db_err)?;
+        let mut rewritten = 0;
+
+        let rows = sqlx::query("SELECT id, content, parts FROM messages WHERE content NOT LIKE $1")
+            .bind(&like)
+            .fetch_all(&mut *tx)
+            .await
+            .map_err(db_err)?;
+        for row in &rows {
+            let content: String = row.try_get("content").map_err(db_err)?;
+            let parts: Option<serde_json::Value> = row.try_get("parts").map_err(db_err)?;
+            sqlx::query("UPDATE messages SET content = $2, parts = $3 WHERE id = $1")
+                .bind(row.try_get::<Uuid, _>("id").map_err(db_err)?)
+                .bind(self.seal(&content))
+                .bind(parts.map(|parts| self.seal_json(&parts)))
+                .execute(&mut *tx)
+                .await
+                .map_err(db_err)?;
+        }
+        rewritten += rows.len();
+
+        // Columns rewritten in place, by table, key column and text column
+        for (table, key, column) in [
+            ("conversations", "id", "title"),
+            ("documents", "id", "content"),
+            ("document_chunks", "id", "chunk_text"),
+        ] {
+            let rows = sqlx::query(&format!(
+                "SELECT {key}, {column} FROM {table} WHERE {column} IS NOT NULL AND {column} NOT LIKE $1"
+            ))
+            .bind(&like)
+            .fetch_all(&mut *tx)
+            .await
+            .map_err(db_err)?;
+            for row in &rows {
+                let text: String = row.try_get(column).map_err(db_err)?;
+                sqlx::query(&format!("UPDATE {table} SET {column} = $2 WHERE {key} = $1"))
+                    .bind(row.try_get::<Uuid, _>(key).map_err(db_err)?)
+                    .bind(self.seal(&text))
+                    .execute(&mut *tx)
+                    .await
+                    .map_err(db_err)?;
+            }
+            rewritten += rows.len();
+        }
+
+        // Context rows are found again by their physical row id, which holds within the transaction
+        for table in ["global_context", "llm_contexts"] {
+            let rows = sqlx::query(&format!(
+                "SELECT ctid::TEXT AS key, context_value FROM {table} \
+                 WHERE jsonb_typeof(context_value) <> 'string' OR context_value #>> '{{}}' NOT LIKE $1"
+            ))
+            .bind(&like)
+            .fetch_all(&mut *tx)
+            .await
+            .map_err(db_err)?;
+            for row in &rows {
+                let value: serde_json::Value = row.try_get("context_value").map_err(db_err)?;
+                sqlx::query(&format!("UPDATE {table} SET context_value = $2 WHERE ctid = $1::TID"))
+                    .bind(row.try_get::<String, _>("key").map_err(db_err)?)
+                    .bind(self.seal_json(&value))
+                    .execute(&mut *tx)
+                    .await
+                    .map_err(db_err)?;
+            }
+            rewritten += rows.len();
+        }
+
+        tx.commit().await.map_err(db_err)?;
+        Ok(rewritten)
+    }
+
+    /// Text as it is written: encrypted once a cipher is set
+    fn seal(&self, text: &str) -> String {
+        match &*self.cipher.read().unwrap() {
+            Some(cipher) => cipher.encrypt(text),
+            None => text.to_string(),
+        }
+    }
+
+    fn seal_json(&self, value: &serde_json::Value) -> serde_json::Value {
+        match &*self.cipher.read().unwrap() {
+            Some(cipher) => cipher.encrypt_json(value),
+            None => value.clone(),
+        }
+    }
+
+    /// Text as it was written; encrypted text needs the cipher
+    fn open(&self, stored: String) -> Result<String> {
+        if !is_encrypted(&stored) {
+            return Ok(stored);
+        }
+        match &*self.cipher.read().unwrap() {
+            Some(cipher) => cipher.decrypt(&stored),
+            None => Err(locked()),
+        }
+    }
+
+    fn open_json(&self, stored: serde_json::Value) -> Result<serde_json::Value> {
+        if !stored.as_str().is_some_and(is_encrypted) {
+            return Ok(stored);
+        }
+        match &*self.cipher.read().unwrap() {
+            Some(cipher) => cipher.decrypt_json(stored),
+            None => Err(locked()),
+        }
+    }
+
+    /// Store and index a new document, watched from `source` when given
+    async fn insert_document(&self, mut document: NewDocument, source: Option<&str>) -> Result<IndexedDocument> {
+        document.detect_language();
+        let db_err = |e: sqlx::Error| HybridLLMError::DatabaseError(e.to_string());
+        let name: String = document.name.chars().take(MAX_DOCUMENT_NAME_CHARS).collect();
+        let checksum = chunk_hash(&document.content);
+        let metadata = serde_json::to_value(&document.metadata)
+            .map_err(|e| HybridLLMError::DatabaseError(e.to_string()))?;
+
+        let row = sqlx::query(
+            "INSERT INTO documents (filename, content, checksum, metadata, collection, source, checked_at) \
+             VALUES ($1, $2, $3, $4, $5, $6, CASE WHEN $6::TEXT IS NULL THEN NULL ELSE NOW() END) RETURNING id"
+        )
+        .bind(&name)
+        .bind(self.seal(&document.content))
+        .bind(&checksum)
+        .bind(metadata)
+        .bind(&document.collection)
+        .bind(source)
+        .fetch_one(&self.pool)
+        .await
+        .map_err(db_err)?;
+        let id: Uuid = row.try_get("id").map_err(db_err)?;
+
+        let diff = self.index_document_version(id, 1, &document.content, &checksum, &self.embeddings()).await?;
+        Ok(IndexedDocument { id, chunk_count: diff.added.len() })
+    }
+
+    /// Index a new version of a document, embedding only the chunks that changed
+    /// Chunks that disappear are closed at `version` rather than deleted, so older versions stay queryable
+    pub async fn index_document_version(
+        &self,
+        document_id: Uuid,
+        version: i32,
+        content: &str,
+        checksum: &str,
+        embeddings: &EmbeddingGenerator,
+    ) -> Result<ChunkDiff> {
+        debug!("📚 Indexing document {} version {}", document_id, version);
+        let db_err = |e: sqlx::Error| HybridLLMError::DatabaseError(e.to_string());
+
+        let rows = sqlx::query(
+            "SELECT id, chunk_hash FROM document_chunks \
+             WHERE document_id = $1 AND valid_to_version IS NULL \
+             ORDER BY chunk_index"
+        )
+        .bind(document_id)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(db_err)?;
+
+        let mut indexed = Vec::with_capacity(rows.len());
+        for row in rows {
+            let id: Uuid = row.try_get("id").map_err(db_err)?;
+            // Chunks indexed before versioning have no hash and are replaced
+            let hash: Option<String> = row.try_get("chunk_hash").map_err(db_err)?;
+            indexed.push((id, hash.unwrap_or_default()));
+        }
+
+        let chunks = section_chunks(content);
+        let diff = diff_chunks(&indexed, &chunks);
+        let added: Vec<String> = diff.added.iter().map(|&index| chunks[index].clone()).collect();
+        let vectors = embeddings.generate_batch(&added).await?;
+
+        let mut tx = self.pool.begin().await.map_err(db_err)?;
+
+        sqlx::query("UPDATE document_chunks SET valid_to_version = $2 WHERE id = ANY($1)")
+            .bind(&diff.removed)
+            .bind(version)
+            .execute(&mut *tx)
+            .await
+            .map_err(db_err)?;
+
+        for ((&index, text), vector) in diff.added.iter().zip(&added).zip(vectors) {
+            let vector = format!(
+                "[{}]",
+                vector.iter().map(f32::to_string).collect::<Vec<_>>().join(",")
+            );
+            sqlx::query(
+                "INSERT INTO document_chunks \
+                 (document_id, chunk_index, chunk_text, embedding, chunk_hash, valid_from_version) \
+                 VALUES ($1, $2, $3, $4::vector, $5, $6)"
+            )
+            .bind(document_id)
+            .bind(index as i32)
+            .bind(self.seal(text))
+            .bind(vector)
+            .bind(chunk_hash(text))
+            .bind(version)
+            .execute(&mut *tx)
+            .await
+            .map_err(db_err)?;
+        }
+
+        sqlx::query("UPDATE documents SET content = $2, checksum = $3, version = $4 WHERE id = $1")
+            .bind(document_id)
+            .bind(self.seal(content))
+            .bind(checksum)
+            .bind(version)
+            .execute(&mut *tx)
+            .await
+            .map_err(db_err)?;
+
+        tx.commit().await.map_err(db_err)?;
+
+        info!(
+            "📚 Indexed document {} version {}: {} unchanged, {} added, {} removed chunks",
+            document_id, version, diff.unchanged.len(), diff.added.len(), diff.removed.len()
+        );
+        Ok(diff)
+    }
+
+    /// Chunk texts of a document as they were at `version`
+    /// Chunks kept across versions keep their original row, so `chunk_index` is where they first appeared
+    pub async fn document_chunks_as_of(&self, document_id: Uuid, version: i32) -> Result<Vec<String>> {
+        debug!("📖 Reading document {} as of version {}", document_id, version);
+
+        let rows = sqlx::query(
+            "SELECT chunk_text FROM document_chunks \
+             WHERE document_id = $1 \
+               AND valid_from_version <= $2 \
+               AND (valid_to_version IS NULL OR valid_to_version > $2) \
+             ORDER BY chunk_index"
+        )
+        .bind(document_id)
+        .bind(version)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| HybridLLMError::DatabaseError(e.to_string()))?;
+
+        rows.iter()
+            .map(|row| self.open(row.try_get("chunk_text").map_err(|e| HybridLLMError::DatabaseError(e.to_string()))?))
+            .collect()
+    }
+
+    /// Every row of the platform's tables as JSON, for a backup; encrypted content stays encrypted
+    pub async fn export_tables(&self) -> Result<Vec<TableDump>> {
+        let db_err = |e: sqlx::Error| HybridLLMError::DatabaseError(e.to_string());
+        let mut dumps = Vec::with_capacity(BACKUP_TABLES.len());
+
+        for table in BACKUP_TABLES {
+            let rows = sqlx::query(&format!("SELECT to_jsonb(t) AS row FROM {table} t"))
+                .fetch_all(&self.pool)
+                .await
+                .map_err(db_err)?;
+            let rows = rows.iter().map(|row| row.try_get("row").map_err(db_err)).collect::<Result<_>>()?;
+            dumps.push(TableDump { table: table.to_string(), rows });
+        }
+
+        Ok(dumps)
+    }
+
+    /// Replace the contents of the platform's tables with rows from `export_tables`, all or nothing
+    /// Tables missing from `dumps` are left empty; returns how many rows were written
+    pub async fn import_tables(&self, dumps: &[TableDump]) -> Result<usize> {
+        let db_err = |e: sqlx::Error| HybridLLMError::DatabaseError(e.to_string());
+        if let Some(dump) = dumps.iter().find(|dump| !BACKUP_TABLES.contains(&dump.table.as_str())) {
+            return Err(HybridLLMError::InvalidRequest(format!("Unknown table {:?} in backup", dump.table)));
+        }
+
+        let mut tx = self.pool.begin().await.map_err(db_err)?;
+        for table in BACKUP_TABLES.iter().rev() {
+            sqlx::query(&format!("DELETE FROM {table}")).execute(&mut *tx).await.map_err(db_err)?;
+        }
+        let mut imported = 0;
+        // Parents first; columns the dump lacks take NULL and columns the table lacks are dropped
+        for table in BACKUP_TABLES {
+            let rows: Vec<serde_json::Value> = dumps
+                .iter()
+                .filter(|dump| dump.table == table)
+                .flat_map(|dump| dump.rows.iter().cloned())
+                .collect();
+            if rows.is_empty() {
+                continue;
+            }
+            imported += rows.len();
+            sqlx::query(&format!("INSERT INTO {table} SELECT * FROM jsonb_populate_recordset(NULL::{table}, $1)"))
+                .bind(serde_json::Value::Array(rows))
+                .execute(&mut *tx)
+                .await
+                .map_err(db_err)?;
+        }
+        tx.commit().await.map_err(db_err)?;
+
+        // Content encrypted under another passphrase stays locked until that passphrase is given
+        let verifier: Option<String> = sqlx::query_scalar("SELECT verifier FROM content_encryption")
+            .fetch_optional(&self.pool)
+            .await
+            .map_err(db_err)?;
+        let mut cipher = self.cipher.write().unwrap();
+        if let (Some(verifier), Some(current)) = (verifier, cipher.as_ref()) {
+            if !current.verify(&verifier) {
+                warn!("⚠️  Restored content was encrypted with another passphrase, unlock it to read it");
+                *cipher = None;
+            }
+        }
+
+        Ok(imported)
+    }
+
+    async fn fetch_conversation(&self, conversation_id: &Uuid) -> Result<Conversation> {
+        let row = sqlx::query(&format!("{} WHERE c.id = $1 GROUP BY c.id", CONVERSATION_COLUMNS))
+            .bind(conversation_id)
+            .fetch_optional(&self.pool)
+            .await
+            .map_err(|e| HybridLLMError::DatabaseError(e.to_string()))?
+            .ok_or_else(|| HybridLLMError::InvalidRequest(format!("No conversation {}", conversation_id)))?;
+
+        self.conversation_from_row(&row)
+    }
+
+    /// Run an UPDATE on one conversation and return it afterwards
+    async fn update_conversation(
+        &self,
+        conversation_id: &Uuid,
+        query: sqlx::query::Query<'_, sqlx::Postgres, sqlx::postgres::PgArguments>,
+    ) -> Result<Conversation> {
+        let updated = query
+            .execute(&self.pool)
+            .await
+            .map_err(|e| HybridLLMError::DatabaseError(e.to_string()))?
+            .rows_affected();
+        if updated == 0 {
+            return Err(HybridLLMError::InvalidRequest(format!("No conversation {}", conversation_id)));
+        }
+
+        self.fetch_conversation(conversation_id).await
+    }
+
+    fn conversation_from_row(&self, row: &PgRow) -> Result<Conversation> {
+        let db_err = |e: sqlx::Error| HybridLLMError::DatabaseError(e.to_string());
+        let title: Option<String> = row.try_get("title").map_err(db_err)?;
+        let first_user_message: Option<String> = row.try_get("first_user_message").map_err(db_err)?;
+        let title = title.map(|title| self.open(title)).transpose()?;
+        let first_user_message = first_user_message.map(|message| self.open(message)).transpose()?;
+        let message_count: i64 = row.try_get("message_count").map_err(db_err)?;
+
+        Ok(Conversation {
+            id: row.try_get("id").map_err(db_err)?,
+            title: Conversation::display_title(title.as_deref(), first_user_message.as_deref()),
+            created_at: row.try_get("created_at").map_err(db_err)?,
+            last_activity: row.try_get("last_activity").map_err(db_err)?,
+            message_count: message_count as usize,
+            trashed_at: row.try_get("trashed_at").map_err(db_err)?,
+            document_ids: row.try_get("document_ids").map_err(db_err)?,
+        })
+    }
+
+    /// Add the template's next version within `tx`
+    async fn insert_template_version(
+        &self,
+        tx: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+        template_id: &Uuid,
+        template: &NewPromptTemplate,
+    ) -> Result<()> {
+        let model_overrides = serde_json::to_value(&template.model_overrides)
+            .map_err(|e| HybridLLMError::DatabaseError(e.to_string()))?;
+
+        sqlx::query(
+            "INSERT INTO prompt_template_versions (template_id, version, description, body, model_overrides) \
+             SELECT $1, COALESCE(MAX(version), 0) + 1, $2, $3, $4 \
+             FROM prompt_template_versions WHERE template_id = $1"
+        )
+        .bind(template_id)
+        .bind(&template.description)
+        .bind(&template.body)
+        .bind(model_overrides)
+        .execute(&mut **tx)
+        .await
+        .map_err(|e| HybridLLMError::DatabaseError(e.to_string()))?;
+        Ok(())
+    }
+}
+
+/// A template insert or rename failing on the unique name is the user's error, not the database's
+fn template_db_err(name: &str) -> impl Fn(sqlx::Error) -> HybridLLMError + '_ {
+    move |e| match e.as_database_error() {
+        Some(db) if db.is_unique_violation() => {
+            HybridLLMError::InvalidRequest(format!("A prompt template is already named {}", name))
+        }
+        _ => HybridLLMError::DatabaseError(e.to_string()),
+    }
+}
+
+fn prompt_template_from_row(row: &PgRow) -> Result<PromptTemplate> {
+    let db_err = |e: sqlx::Error| HybridLLMError::DatabaseError(e.to_string());
+    let model_overrides: serde_json::Value = row.try_get("model_overrides").map_err(db_err)?;
+
+    Ok(PromptTemplate {
+        id: row.try_get("id").map_err(db_err)?,
+        version: row.try_get("version").map_err(db_err)?,
+        template: NewPromptTemplate {
+            name: row.try_get("name").map_err(db_err)?,
+            description: row.try_get("description").map_err(db_err)?,
+            body: row.try_get("body").map_err(db_err)?,
+            model_overrides: serde_json::from_value(model_overrides)
+                .map_err(|e| HybridLLMError::DatabaseError(e.to_string()))?,
+        },
+        created_at: row.try_get("created_at").map_err(db_err)?,
+    })
+}
+
+/// Refusal to read encrypted content before the passphrase is given
+fn locked() -> HybridLLMError {
+    HybridLLMError::PermissionDenied("Stored content is encrypted; unlock it with its passphrase".to_string())
+}
+
+/// A RAG result from a row of `RAG_CHUNK_COLUMNS`, with its chunk text already readable
+fn rag_result_from_row(row: &PgRow, content: String, similarity: f32) -> Result<RAGResult> {
+    let db_err = |e: sqlx::Error| HybridLLMError::DatabaseError(e.to_string());
+    let mut metadata: HashMap<String, serde_json::Value> = row
+        .try_get::<Option<serde_json::Value>, _>("metadata")
+        .map_err(db_err)?
+        .and_then(|metadata| serde_json::from_value(metadata).ok())
+        .unwrap_or_default();
+    metadata.insert("collection".to_string(), row.try_get::<String, _>("collection").map_err(db_err)?.into());
+    metadata.insert("name".to_string(), row.try_get::<String, _>("filename").map_err(db_err)?.into());
+    if let Some(source) = row.try_get::<Option<String>, _>("source").map_err(db_err)? {
+        metadata.insert("source".to_string(), source.into());
+        let checked_at: Option<DateTime<Utc>> = row.try_get("checked_at").map_err(db_err)?;
+        metadata.insert("checked_at".to_string(), serde_json::json!(checked_at));
+    }
+    Ok(RAGResult {
+        id: row.try_get("id").map_err(db_err)?,
+        document_id: Some(row.try_get("document_id").map_err(db_err)?),
+        content,
+        similarity,
+        metadata,
+    })
 }
 
 #[async_trait]
@@ -51,7 +637,7 @@ impl ContextManager for DatabaseContextManager {
                 .map_err(|e| HybridLLMError::DatabaseError(e.to_string()))?;
             let value: serde_json::Value = row.try_get("context_value")
                 .map_err(|e| HybridLLMError::DatabaseError(e.to_string()))?;
-            context.insert(key, value);
+            context.insert(key, self.open_json(value)?);
         }
 
         Ok(context)
@@ -67,7 +653,7 @@ impl ContextManager for DatabaseContextManager {
              SET context_value = $2, updated_at = NOW()"
         )
         .bind(key)
-        .bind(value)
+        .bind(self.seal_json(&value))
         .execute(&self.pool)
         .await
         .map_err(|e| HybridLLMError::DatabaseError(e.to_string()))?;
@@ -90,7 +676,7 @@ impl ContextManager for DatabaseContextManager {
                 .map_err(|e| HybridLLMError::DatabaseError(e.to_string()))?;
             let value: serde_json::Value = row.try_get("context_value")
                 .map_err(|e| HybridLLMError::DatabaseError(e.to_string()))?;
-            context.insert(key, value);
+            context.insert(key, self.open_json(value)?);
         }
 
         Ok(context)
@@ -112,7 +698,7 @@ impl ContextManager for DatabaseContextManager {
         )
         .bind(llm_id)
         .bind(key)
-        .bind(value)
+        .bind(self.seal_json(&value))
         .execute(&self.pool)
         .await
         .map_err(|e| HybridLLMError::DatabaseError(e.to_string()))?;
@@ -124,7 +710,7 @@ impl ContextManager for DatabaseContextManager {
         debug!("📖 Reading conversation: {}", conversation_id);
 
         let rows = sqlx::query(
-            "SELECT id, role, content, timestamp, metadata \
+            "SELECT id, role, content, parts, timestamp, metadata \
              FROM messages \
              WHERE conversation_id = $1 \
              ORDER BY timestamp ASC"
@@ -142,6 +728,16 @@ impl ContextManager for DatabaseContextManager {
                 .map_err(|e| HybridLLMError::DatabaseError(e.to_string()))?;
             let content: String = row.try_get("content")
                 .map_err(|e| HybridLLMError::DatabaseError(e.to_string()))?;
+            // Only multi-part messages have parts; the rest are their text
+            let parts: Option<serde_json::Value> = row.try_get("parts")
+                .map_err(|e| HybridLLMError::DatabaseError(e.to_string()))?;
+            let content = self.open(content)?;
+            let parts = parts.map(|parts| self.open_json(parts)).transpose()?;
+            let content = match parts {
+                Some(parts) => MessageContent::Parts(serde_json::from_value(parts)
+                    .map_err(|e| HybridLLMError::DatabaseError(e.to_string()))?),
+                None => MessageContent::Text(content),
+            };
             let timestamp: chrono::NaiveDateTime = row.try_get("timestamp")
                 .map_err(|e| HybridLLMError::DatabaseError(e.to_string()))?;
             let metadata: Option<serde_json::Value> = row.try_get("metadata").ok();
@@ -190,15 +786,21 @@ impl ContextManager for DatabaseContextManager {
 
         let metadata_json = serde_json::to_value(&message.metadata)
             .map_err(|e| HybridLLMError::DatabaseError(e.to_string()))?;
+        let parts_json = match &message.content {
+            MessageContent::Parts(parts) => Some(self.seal_json(&serde_json::to_value(parts)
+                .map_err(|e| HybridLLMError::DatabaseError(e.to_string()))?)),
+            MessageContent::Text(_) => None,
+        };
 
         sqlx::query(
-            "INSERT INTO messages (id, conversation_id, role, content, timestamp, metadata) \
-             VALUES ($1, $2, $3, $4, $5, $6)"
+            "INSERT INTO messages (id, conversation_id, role, content, parts, timestamp, metadata) \
+             VALUES ($1, $2, $3, $4, $5, $6, $7)"
         )
         .bind(message.id)
         .bind(conversation_id)
         .bind(role_str)
-        .bind(message.content)
+        .bind(self.seal(&message.content.text()))
+        .bind(parts_json)
         .bind(message.timestamp.naive_utc())
         .bind(metadata_json)
         .execute(&self.pool)
@@ -208,19 +810,345 @@ impl ContextManager for DatabaseContextManager {
         Ok(())
     }
 
-    async fn search_rag(&self, query: &str, llm_id: Option<&str>, limit: usize) -> Result<Vec<RAGResult>> {
+    async fn create_conversation(&self, title: Option<&str>) -> Result<Conversation> {
+        let id = Uuid::new_v4();
+        debug!("💾 Creating conversation: {}", id);
+
+        sqlx::query("INSERT INTO conversations (id, title) VALUES ($1, $2)")
+            .bind(id)
+            .bind(title.map(|title| self.seal(title)))
+            .execute(&self.pool)
+            .await
+            .map_err(|e| HybridLLMError::DatabaseError(e.to_string()))?;
+
+        self.fetch_conversation(&id).await
+    }
+
+    async fn list_conversations(&self, trashed: bool) -> Result<Vec<Conversation>> {
+        debug!("📖 Listing conversations (trashed: {})", trashed);
+
+        let rows = sqlx::query(&format!(
+            "{} WHERE (c.trashed_at IS NOT NULL) = $1 GROUP BY c.id ORDER BY last_activity DESC",
+            CONVERSATION_COLUMNS
+        ))
+        .bind(trashed)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| HybridLLMError::DatabaseError(e.to_string()))?;
+
+        rows.iter().map(|row| self.conversation_from_row(row)).collect()
+    }
+
+    async fn rename_conversation(&self, conversation_id: &Uuid, title: &str) -> Result<Conversation> {
+        debug!("💾 Renaming conversation: {}", conversation_id);
+
+        let query = sqlx::query("UPDATE conversations SET title = $2 WHERE id = $1")
+            .bind(conversation_id)
+            .bind(self.seal(title));
+        self.update_conversation(conversation_id, query).await
+    }
+
+    async fn trash_conversation(&self, conversation_id: &Uuid) -> Result<Conversation> {
+        debug!("🗑️  Trashing conversation: {}", conversation_id);
+
+        let query = sqlx::query("UPDATE conversations SET trashed_at = COALESCE(trashed_at, NOW()) WHERE id = $1")
+            .bind(conversation_id);
+        self.update_conversation(conversation_id, query).await
+    }
+
+    async fn restore_conversation(&self, conversation_id: &Uuid) -> Result<Conversation> {
+        debug!("♻️  Restoring conversation: {}", conversation_id);
+
+        let query = sqlx::query("UPDATE conversations SET trashed_at = NULL WHERE id = $1")
+            .bind(conversation_id);
+        self.update_conversation(conversation_id, query).await
+    }
+
+    async fn purge_conversations(&self, before: DateTime<Utc>) -> Result<usize> {
+        // Messages go with their conversation (ON DELETE CASCADE)
+        let purged = sqlx::query("DELETE FROM conversations WHERE trashed_at < $1")
+            .bind(before)
+            .execute(&self.pool)
+            .await
+            .map_err(|e| HybridLLMError::DatabaseError(e.to_string()))?
+            .rows_affected();
+
+        if purged > 0 {
+            info!("🧹 Purged {} trashed conversations", purged);
+        }
+        Ok(purged as usize)
+    }
+
+    async fn attach_document(&self, conversation_id: &Uuid, document_id: &Uuid) -> Result<Conversation> {
+        debug!("📎 Attaching document {} to conversation {}", document_id, conversation_id);
+
+        // Checked first so a missing conversation isn't reported as a foreign key error
+        self.fetch_conversation(conversation_id).await?;
+        sqlx::query(
+            "INSERT INTO conversation_documents (conversation_id, document_id) VALUES ($1, $2) \
+             ON CONFLICT DO NOTHING",
+        )
+        .bind(conversation_id)
+        .bind(document_id)
+        .execute(&self.pool)
+        .await
+        .map_err(|e| HybridLLMError::DatabaseError(e.to_string()))?;
+
+        self.fetch_conversation(conversation_id).await
+    }
+
+    async fn detach_document(&self, conversation_id: &Uuid, document_id: &Uuid) -> Result<Conversation> {
+        debug!("📎 Detaching document {} from conversation {}", document_id, conversation_id);
+
+        sqlx::query("DELETE FROM conversation_documents WHERE conversation_id = $1 AND document_id = $2")
+            .bind(conversation_id)
+            .bind(document_id)
+            .execute(&self.pool)
+            .await
+            .map_err(|e| HybridLLMError::DatabaseError(e.to_string()))?;
+
+        self.fetch_conversation(conversation_id).await
+    }
+
+    async fn search_rag(
+        &self,
+        query: &str,
+        llm_id: Option<&str>,
+        conversation_id: Option<&Uuid>,
+        limit: usize,
+    ) -> Result<Vec<RAGResult>> {
         debug!("🔍 RAG search: {} (LLM: {:?}, limit: {})", query, llm_id, limit);
 
-        // TODO: Implement actual vector search
-        // For now, this is a placeholder
-        // Full implementation would:
-        // 1. Generate embedding for query
-        // 2. Perform vector similarity search
-        // 3. Filter by llm_visibility if llm_id is provided
-        // 4. Return top-k results
+        // Chunks are ranked by vector similarity when an embedding model is set, and by full-text
+        // match on any of the query's words otherwise, or when no chunk has a real embedding yet
+        let db_err = |e: sqlx::Error| HybridLLMError::DatabaseError(e.to_string());
+        let attached = match conversation_id {
+            Some(id) => self.fetch_conversation(id).await?.document_ids,
+            None => Vec::new(),
+        };
+
+        // Encrypted chunks can't be matched in SQL, so they are decrypted and ranked here
+        if self.is_unlocked() {
+            let rows = sqlx::query(&format!(
+                "{} WHERE c.valid_to_version IS NULL \
+                   AND ($1::TEXT IS NULL OR cardinality(d.llm_visibility) = 0 OR $1 = ANY(d.llm_visibility))",
+                RAG_CHUNK_COLUMNS
+            ))
+            .bind(llm_id)
+            .fetch_all(&self.pool)
+            .await
+            .map_err(db_err)?;
+
+            let mut results = Vec::new();
+            for row in &rows {
+                let content = self.open(row.try_get("chunk_text").map_err(db_err)?)?;
+                let similarity = lexical_similarity(query, &content);
+                if similarity > 0.0 {
+                    results.push(rag_result_from_row(row, content, similarity)?);
+                }
+            }
+            results.sort_by(|a, b| b.similarity.total_cmp(&a.similarity));
+            return Ok(prioritize_attached(prefer_language(results, query), &attached, limit));
+        }
+
+        let embeddings = self.embeddings();
+        if !embeddings.is_placeholder() {
+            let vector = embeddings.generate(query).await?;
+            let vector = format!("[{}]", vector.iter().map(f32::to_string).collect::<Vec<_>>().join(","));
+            // Chunks indexed before the model was set hold zero vectors, which have no direction to compare
+            let rows = sqlx::query(
+                "SELECT c.id, c.document_id, c.chunk_text, d.filename, d.collection, d.metadata, d.source, d.checked_at, \
+                        (1 - (c.embedding <=> $1::vector))::REAL AS similarity \
+                 FROM document_chunks c JOIN documents d ON d.id = c.document_id \
+                 WHERE c.valid_to_version IS NULL \
+                   AND vector_norm(c.embedding) > 0 \
+                   AND ($2::TEXT IS NULL OR cardinality(d.llm_visibility) = 0 OR $2 = ANY(d.llm_visibility)) \
+                 ORDER BY c.document_id = ANY($3) DESC, c.embedding <=> $1::vector \
+                 LIMIT $4"
+            )
+            .bind(&vector)
+            .bind(llm_id)
+            .bind(&attached)
+            .bind(limit as i64)
+            .fetch_all(&self.pool)
+            .await
+            .map_err(db_err)?;
+
+            if !rows.is_empty() {
+                let mut results = Vec::with_capacity(rows.len());
+                for row in &rows {
+                    let content = self.open(row.try_get("chunk_text").map_err(db_err)?)?;
+                    results.push(rag_result_from_row(row, content, row.try_get("similarity").map_err(db_err)?)?);
+                }
+                return Ok(prioritize_attached(prefer_language(results, query), &attached, limit));
+            }
+        }
+
+        let rows = sqlx::query(
+            "WITH q AS (SELECT replace(plainto_tsquery('simple', $1)::text, '&', '|')::tsquery AS query) \
+             SELECT c.id, c.document_id, c.chunk_text, d.filename, d.collection, d.metadata, \
+                    ts_rank(to_tsvector('simple', c.chunk_text), q.query) AS similarity \
+             FROM document_chunks c JOIN documents d ON d.id = c.document_id, q \
+             WHERE c.valid_to_version IS NULL \
+               AND to_tsvector('simple', c.chunk_text) @@ q.query \
+               AND ($2::TEXT IS NULL OR cardinality(d.llm_visibility) = 0 OR $2 = ANY(d.llm_visibility)) \
+             ORDER BY c.document_id = ANY($3) DESC, similarity DESC \
+             LIMIT $4"
+        )
+        .bind(query)
+        .bind(llm_id)
+        .bind(&attached)
+        .bind(limit as i64)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(db_err)?;
+
+        let mut results = Vec::with_capacity(rows.len());
+        for row in &rows {
+            let content = self.open(row.try_get("chunk_text").map_err(db_err)?)?;
+            results.push(rag_result_from_row(row, content, row.try_get("similarity").map_err(db_err)?)?);
+        }
+        Ok(prioritize_attached(prefer_language(results, query), &attached, limit))
+    }
+
+    async fn index_document(&self, document: NewDocument) -> Result<IndexedDocument> {
+        self.insert_document(document, None).await
+    }
 
-        error!("⚠️  RAG vector search not yet implemented (requires embeddings)");
+    async fn refresh_document(&self, source: &str, mut document: NewDocument) -> Result<RefreshedDocument> {
+        let db_err = |e: sqlx::Error| HybridLLMError::DatabaseError(e.to_string());
+        let known = sqlx::query("SELECT id, checksum, version FROM documents WHERE source = $1")
+            .bind(source)
+            .fetch_optional(&self.pool)
+            .await
+            .map_err(db_err)?;
+        let Some(row) = known else {
+            let indexed = self.insert_document(document, Some(source)).await?;
+            return Ok(RefreshedDocument { id: indexed.id, status: RefreshStatus::Added, chunk_count: indexed.chunk_count });
+        };
+
+        let id: Uuid = row.try_get("id").map_err(db_err)?;
+        let checksum = chunk_hash(&document.content);
+        if row.try_get::<String, _>("checksum").map_err(db_err)? == checksum {
+            sqlx::query("UPDATE documents SET checked_at = NOW() WHERE id = $1")
+                .bind(id)
+                .execute(&self.pool)
+                .await
+                .map_err(db_err)?;
+            return Ok(RefreshedDocument { id, status: RefreshStatus::Unchanged, chunk_count: 0 });
+        }
+
+        document.detect_language();
+        let version: i32 = row.try_get("version").map_err(db_err)?;
+        let diff = self.index_document_version(id, version + 1, &document.content, &checksum, &self.embeddings()).await?;
+        let name: String = document.name.chars().take(MAX_DOCUMENT_NAME_CHARS).collect();
+        let metadata = serde_json::to_value(&document.metadata)
+            .map_err(|e| HybridLLMError::DatabaseError(e.to_string()))?;
+        sqlx::query("UPDATE documents SET filename = $2, metadata = $3, checked_at = NOW() WHERE id = $1")
+            .bind(id)
+            .bind(&name)
+            .bind(metadata)
+            .execute(&self.pool)
+            .await
+            .map_err(db_err)?;
+        Ok(RefreshedDocument { id, status: RefreshStatus::Changed, chunk_count: diff.added.len() })
+    }
 
-        Ok(Vec::new())
+    async fn create_prompt_template(&self, template: NewPromptTemplate) -> Result<PromptTemplate> {
+        template.validate()?;
+        let id = Uuid::new_v4();
+        debug!("📝 Creating prompt template: {}", id);
+        let db_err = |e: sqlx::Error| HybridLLMError::DatabaseError(e.to_string());
+
+        let mut tx = self.pool.begin().await.map_err(db_err)?;
+        sqlx::query("INSERT INTO prompt_templates (id, name) VALUES ($1, $2)")
+            .bind(id)
+            .bind(&template.name)
+            .execute(&mut *tx)
+            .await
+            .map_err(template_db_err(&template.name))?;
+        self.insert_template_version(&mut tx, &id, &template).await?;
+        tx.commit().await.map_err(db_err)?;
+
+        self.get_prompt_template(&id, None).await
+    }
+
+    async fn update_prompt_template(&self, template_id: &Uuid, template: NewPromptTemplate) -> Result<PromptTemplate> {
+        template.validate()?;
+        debug!("📝 Saving a new version of prompt template: {}", template_id);
+        let db_err = |e: sqlx::Error| HybridLLMError::DatabaseError(e.to_string());
+
+        let mut tx = self.pool.begin().await.map_err(db_err)?;
+        let renamed = sqlx::query("UPDATE prompt_templates SET name = $2 WHERE id = $1")
+            .bind(template_id)
+            .bind(&template.name)
+            .execute(&mut *tx)
+            .await
+            .map_err(template_db_err(&template.name))?
+            .rows_affected();
+        if renamed == 0 {
+            return Err(HybridLLMError::InvalidRequest(format!("No prompt template {}", template_id)));
+        }
+        self.insert_template_version(&mut tx, template_id, &template).await?;
+        tx.commit().await.map_err(db_err)?;
+
+        self.get_prompt_template(template_id, None).await
+    }
+
+    async fn get_prompt_template(&self, template_id: &Uuid, version: Option<i32>) -> Result<PromptTemplate> {
+        let row = sqlx::query(&format!(
+            "{} WHERE t.id = $1 AND ($2::INTEGER IS NULL OR v.version = $2) ORDER BY v.version DESC LIMIT 1",
+            PROMPT_TEMPLATE_COLUMNS
+        ))
+        .bind(template_id)
+        .bind(version)
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|e| HybridLLMError::DatabaseError(e.to_string()))?
+        .ok_or_else(|| HybridLLMError::InvalidRequest(format!("No prompt template {} at that version", template_id)))?;
+
+        prompt_template_from_row(&row)
+    }
+
+    async fn list_prompt_templates(&self) -> Result<Vec<PromptTemplate>> {
+        let rows = sqlx::query(&format!(
+            "{} WHERE v.version = (SELECT MAX(l.version) FROM prompt_template_versions l WHERE l.template_id = t.id) \
+             ORDER BY t.name",
+            PROMPT_TEMPLATE_COLUMNS
+        ))
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| HybridLLMError::DatabaseError(e.to_string()))?;
+
+        rows.iter().map(prompt_template_from_row).collect()
+    }
+
+    async fn prompt_template_history(&self, template_id: &Uuid) -> Result<Vec<PromptTemplate>> {
+        let rows = sqlx::query(&format!("{} WHERE t.id = $1 ORDER BY v.version DESC", PROMPT_TEMPLATE_COLUMNS))
+            .bind(template_id)
+            .fetch_all(&self.pool)
+            .await
+            .map_err(|e| HybridLLMError::DatabaseError(e.to_string()))?;
+        if rows.is_empty() {
+            return Err(HybridLLMError::InvalidRequest(format!("No prompt template {}", template_id)));
+        }
+
+        rows.iter().map(prompt_template_from_row).collect()
+    }
+
+    async fn delete_prompt_template(&self, template_id: &Uuid) -> Result<()> {
+        // Versions go with their template (ON DELETE CASCADE)
+        let deleted = sqlx::query("DELETE FROM prompt_templates WHERE id = $1")
+            .bind(template_id)
+            .execute(&self.pool)
+            .await
+            .map_err(|e| HybridLLMError::DatabaseError(e.to_string()))?
+            .rows_affected();
+        if deleted == 0 {
+            return Err(HybridLLMError::InvalidRequest(format!("No prompt template {}", template_id)));
+        }
+
+        info!("🗑️  Deleted prompt template {}", template_id);
+        Ok(())
     }
 }
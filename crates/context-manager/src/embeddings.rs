@@ -1,41 +1,80 @@
+use common::traits::LLMProvider;
 use common::errors::{Result, HybridLLMError};
+use std::sync::Arc;
 use tracing::warn;
 
-/// Generates embeddings for text using sentence transformers
-/// In production, this would use a proper embedding model
+/// Size of the vectors `document_chunks.embedding` holds, as all-MiniLM-L6-v2 writes them
+pub const EMBEDDING_DIMENSIONS: usize = 384;
+
+/// Generates embeddings for text with an embedding LLM, or zero vectors when there is none
 pub struct EmbeddingGenerator {
     model_name: String,
+    /// Embeds the text when set
+    provider: Option<Arc<Box<dyn LLMProvider>>>,
 }
 
 impl EmbeddingGenerator {
     pub fn new(model_name: impl Into<String>) -> Self {
         Self {
             model_name: model_name.into(),
+            provider: None,
+        }
+    }
+
+    /// Embed with `provider`, e.g. a local embedding model from `LLMPool::get`
+    pub fn with_provider(provider: Arc<Box<dyn LLMProvider>>) -> Self {
+        Self {
+            model_name: provider.instance().id.clone(),
+            provider: Some(provider),
         }
     }
 
+    pub fn model_name(&self) -> &str {
+        &self.model_name
+    }
+
+    /// Whether vectors are zero placeholders rather than an LLM's embeddings
+    pub fn is_placeholder(&self) -> bool {
+        self.provider.is_none()
+    }
+
     /// Generate embeddings for text
     /// Returns a 384-dimensional vector (for all-MiniLM-L6-v2)
     pub async fn generate(&self, text: &str) -> Result<Vec<f32>> {
-        warn!("⚠️  Using placeholder embeddings (requires sentence-transformers integration)");
-
-        // TODO: Implement actual embedding generation
-        // This would use:
-        // - sentence-transformers library via Python binding
-        // - OR Rust-native embedding models via candle/burn
-        // - OR API call to embedding service
-
-        // Placeholder: return zero vector
-        Ok(vec![0.0; 384])
+        let mut vectors = self.generate_batch(&[text.to_string()]).await?;
+        Ok(vectors.pop().unwrap_or_else(|| vec![0.0; EMBEDDING_DIMENSIONS]))
     }
 
     /// Generate embeddings for multiple texts in batch
+    /// When the LLM fails, or embeds in another size than the index takes, zero vectors stand in,
+    /// so indexing goes on and the chunks are still found by full-text search
     pub async fn generate_batch(&self, texts: &[String]) -> Result<Vec<Vec<f32>>> {
-        let mut embeddings = Vec::new();
-        for text in texts {
-            embeddings.push(self.generate(text).await?);
+        let Some(provider) = &self.provider else {
+            warn!("⚠️  Using placeholder embeddings, no embedding model is registered");
+            return Ok(vec![vec![0.0; EMBEDDING_DIMENSIONS]; texts.len()]);
+        };
+        if texts.is_empty() {
+            return Ok(Vec::new());
         }
-        Ok(embeddings)
+
+        let checked = provider.embed(texts).await.and_then(|vectors| {
+            if vectors.len() != texts.len() {
+                return Err(HybridLLMError::LLMError(format!("{} vectors for {} texts", vectors.len(), texts.len())));
+            }
+            match vectors.iter().find(|vector| vector.len() != EMBEDDING_DIMENSIONS) {
+                Some(vector) => Err(HybridLLMError::ConfigError(format!(
+                    "{} embeds in {} dimensions, the index takes {}",
+                    self.model_name,
+                    vector.len(),
+                    EMBEDDING_DIMENSIONS
+                ))),
+                None => Ok(vectors),
+            }
+        });
+        Ok(checked.unwrap_or_else(|e| {
+            warn!("⚠️  Using placeholder embeddings, {} could not embed: {}", self.model_name, e);
+            vec![vec![0.0; EMBEDDING_DIMENSIONS]; texts.len()]
+        }))
     }
 }
 
@@ -69,6 +108,9 @@ pub fn chunk_text(text: &str, chunk_size: usize, overlap: usize) -> Vec<String>
 #[cfg(test)]
 mod tests {
     use super::*;
+    use async_trait::async_trait;
+    use common::types::{Capability, LLMInstance, LLMProvider as LLMProviderType};
+    use common::{CancellationToken, Completion, CompletionRequest, StreamChunk};
 
     #[test]
     fn test_chunk_text() {
@@ -85,5 +127,76 @@ mod tests {
         let embedding = generator.generate("test text").await.unwrap();
 
         assert_eq!(embedding.len(), 384);
+        assert!(generator.is_placeholder());
+    }
+
+    /// Embeds every text as `dimensions` copies of its length
+    struct Lengths {
+        instance: LLMInstance,
+        dimensions: usize,
+    }
+
+    #[async_trait]
+    impl LLMProvider for Lengths {
+        fn capabilities(&self) -> Vec<Capability> {
+            self.instance.capabilities.clone()
+        }
+
+        fn instance(&self) -> &LLMInstance {
+            &self.instance
+        }
+
+        async fn complete(&self, _request: CompletionRequest) -> Result<Completion> {
+            Err(HybridLLMError::LLMError("embeddings only".to_string()))
+        }
+
+        async fn complete_stream(
+            &self,
+            _request: CompletionRequest,
+            _cancel: CancellationToken,
+        ) -> Result<tokio::sync::mpsc::Receiver<Result<StreamChunk>>> {
+            Err(HybridLLMError::LLMError("embeddings only".to_string()))
+        }
+
+        async fn health_check(&self) -> Result<bool> {
+            Ok(true)
+        }
+
+        async fn embed(&self, texts: &[String]) -> Result<Vec<Vec<f32>>> {
+            Ok(texts.iter().map(|text| vec![text.len() as f32; self.dimensions]).collect())
+        }
+
+        async fn load(&self) -> Result<()> {
+            Ok(())
+        }
+
+        async fn unload(&self) -> Result<()> {
+            Ok(())
+        }
+    }
+
+    fn embedder(dimensions: usize) -> EmbeddingGenerator {
+        let instance = LLMInstance {
+            id: "minilm".to_string(),
+            provider: LLMProviderType::Local("minilm".to_string()),
+            capabilities: vec![Capability::Embedding],
+            model_name: "all-MiniLM-L6-v2".to_string(),
+            max_context: 512,
+            is_loaded: true,
+        };
+        EmbeddingGenerator::with_provider(Arc::new(Box::new(Lengths { instance, dimensions })))
+    }
+
+    #[tokio::test]
+    async fn test_provider_embeddings() {
+        let generator = embedder(EMBEDDING_DIMENSIONS);
+        assert!(!generator.is_placeholder());
+        let vectors = generator.generate_batch(&["ab".to_string(), "abc".to_string()]).await.unwrap();
+        assert_eq!(vectors, vec![vec![2.0; EMBEDDING_DIMENSIONS], vec![3.0; EMBEDDING_DIMENSIONS]]);
+        assert!(generator.generate_batch(&[]).await.unwrap().is_empty());
+
+        // Vectors the index can't hold give way to placeholders
+        let vectors = embedder(768).generate_batch(&["ab".to_string()]).await.unwrap();
+        assert_eq!(vectors, vec![vec![0.0; EMBEDDING_DIMENSIONS]]);
     }
 }
@@ -1,10 +1,20 @@
 mod memory;
 mod database;
+mod crypto;
 mod embeddings;
+mod versioning;
+mod retrieval;
+mod readability;
+mod recrawl;
 
 pub use memory::ContextManagerImpl as InMemoryContextManager;
-pub use database::DatabaseContextManager;
+pub use database::{DatabaseContextManager, TableDump, BACKUP_TABLES};
+pub use crypto::ContentCipher;
 pub use embeddings::EmbeddingGenerator;
+pub use versioning::{ChunkDiff, chunk_hash, diff_chunks, section_chunks};
+pub use retrieval::{lexical_similarity, prefer_language, prioritize_attached};
+pub use readability::{extract_readable, web_page_document, ReadablePage};
+pub use recrawl::{RecrawlReport, Recrawler};
 
 // Re-export for convenience
 pub use database::DatabaseContextManager as ContextManagerImpl;
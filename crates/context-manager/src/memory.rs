@@ -1,15 +1,22 @@
 // In-memory implementation (original)
 use common::{
-    errors::Result,
+    errors::{Result, HybridLLMError},
     traits::{ContextManager, RAGResult},
-    types::Message,
+    types::{
+        Conversation, IndexedDocument, Message, MessageRole, NewDocument, NewPromptTemplate, PromptTemplate,
+        RefreshStatus, RefreshedDocument,
+    },
 };
+use chrono::{DateTime, Utc};
 use async_trait::async_trait;
 use dashmap::DashMap;
 use std::collections::HashMap;
 use std::sync::Arc;
 use tracing::debug;
 
+use crate::retrieval::{lexical_similarity, prefer_language, prioritize_attached};
+use crate::versioning::{chunk_hash, section_chunks};
+
 /// In-memory context manager implementation (for testing or standalone mode)
 pub struct ContextManagerImpl {
     /// Global context shared across all LLMs
@@ -17,7 +24,71 @@ pub struct ContextManagerImpl {
     /// Per-LLM private context
     llm_contexts: Arc<DashMap<String, HashMap<String, serde_json::Value>>>,
     /// Conversation storage
-    conversations: Arc<DashMap<uuid::Uuid, Vec<Message>>>,
+    conversations: Arc<DashMap<uuid::Uuid, StoredConversation>>,
+    /// Indexed documents, searched by word overlap
+    documents: Arc<DashMap<uuid::Uuid, StoredDocument>>,
+    /// Prompt templates, each with its versions oldest first
+    prompt_templates: Arc<DashMap<uuid::Uuid, Vec<PromptTemplate>>>,
+}
+
+struct StoredDocument {
+    collection: String,
+    name: String,
+    metadata: HashMap<String, serde_json::Value>,
+    chunks: Vec<(uuid::Uuid, String)>,
+    checksum: String,
+    /// Watched file path or URL, with when it was last re-crawled
+    source: Option<(String, DateTime<Utc>)>,
+}
+
+impl StoredDocument {
+    fn new(mut document: NewDocument) -> Self {
+        document.detect_language();
+        let chunks = section_chunks(&document.content)
+            .into_iter()
+            .map(|chunk| (uuid::Uuid::new_v4(), chunk))
+            .collect();
+        Self {
+            collection: document.collection,
+            name: document.name,
+            metadata: document.metadata,
+            chunks,
+            checksum: chunk_hash(&document.content),
+            source: None,
+        }
+    }
+}
+
+struct StoredConversation {
+    title: Option<String>,
+    created_at: DateTime<Utc>,
+    trashed_at: Option<DateTime<Utc>>,
+    messages: Vec<Message>,
+    document_ids: Vec<uuid::Uuid>,
+}
+
+impl StoredConversation {
+    fn new(created_at: DateTime<Utc>) -> Self {
+        Self { title: None, created_at, trashed_at: None, messages: Vec::new(), document_ids: Vec::new() }
+    }
+
+    fn summary(&self, id: uuid::Uuid) -> Conversation {
+        let first_user_message = self
+            .messages
+            .iter()
+            .find(|m| matches!(m.role, MessageRole::User))
+            .map(|m| m.content.text());
+
+        Conversation {
+            id,
+            title: Conversation::display_title(self.title.as_deref(), first_user_message.as_deref()),
+            created_at: self.created_at,
+            last_activity: self.messages.iter().map(|m| m.timestamp).max().unwrap_or(self.created_at),
+            message_count: self.messages.len(),
+            trashed_at: self.trashed_at,
+            document_ids: self.document_ids.clone(),
+        }
+    }
 }
 
 impl ContextManagerImpl {
@@ -26,6 +97,8 @@ impl ContextManagerImpl {
             global_context: Arc::new(DashMap::new()),
             llm_contexts: Arc::new(DashMap::new()),
             conversations: Arc::new(DashMap::new()),
+            documents: Arc::new(DashMap::new()),
+            prompt_templates: Arc::new(DashMap::new()),
         }
     }
 }
@@ -75,7 +148,7 @@ impl ContextManager for ContextManagerImpl {
         Ok(self
             .conversations
             .get(conversation_id)
-            .map(|conv| conv.clone())
+            .map(|conv| conv.messages.clone())
             .unwrap_or_default())
     }
 
@@ -84,15 +157,238 @@ impl ContextManager for ContextManagerImpl {
 
         self.conversations
             .entry(*conversation_id)
-            .or_insert_with(Vec::new)
+            .or_insert_with(|| StoredConversation::new(message.timestamp))
+            .messages
             .push(message);
 
         Ok(())
     }
 
-    async fn search_rag(&self, query: &str, llm_id: Option<&str>, limit: usize) -> Result<Vec<RAGResult>> {
+    async fn create_conversation(&self, title: Option<&str>) -> Result<Conversation> {
+        let id = uuid::Uuid::new_v4();
+        let mut conversation = StoredConversation::new(Utc::now());
+        conversation.title = title.map(str::to_string);
+        let summary = conversation.summary(id);
+        self.conversations.insert(id, conversation);
+
+        debug!("💬 Created conversation {}", id);
+        Ok(summary)
+    }
+
+    async fn list_conversations(&self, trashed: bool) -> Result<Vec<Conversation>> {
+        let mut conversations: Vec<_> = self
+            .conversations
+            .iter()
+            .filter(|entry| entry.trashed_at.is_some() == trashed)
+            .map(|entry| entry.summary(*entry.key()))
+            .collect();
+        conversations.sort_by_key(|c| std::cmp::Reverse(c.last_activity));
+        Ok(conversations)
+    }
+
+    async fn rename_conversation(&self, conversation_id: &uuid::Uuid, title: &str) -> Result<Conversation> {
+        self.update_conversation(conversation_id, |conv| conv.title = Some(title.to_string()))
+    }
+
+    async fn trash_conversation(&self, conversation_id: &uuid::Uuid) -> Result<Conversation> {
+        self.update_conversation(conversation_id, |conv| {
+            conv.trashed_at.get_or_insert_with(Utc::now);
+        })
+    }
+
+    async fn restore_conversation(&self, conversation_id: &uuid::Uuid) -> Result<Conversation> {
+        self.update_conversation(conversation_id, |conv| conv.trashed_at = None)
+    }
+
+    async fn purge_conversations(&self, before: DateTime<Utc>) -> Result<usize> {
+        let count = self.conversations.len();
+        self.conversations
+            .retain(|_, conv| conv.trashed_at.is_none_or(|trashed_at| trashed_at >= before));
+        Ok(count - self.conversations.len())
+    }
+
+    async fn attach_document(&self, conversation_id: &uuid::Uuid, document_id: &uuid::Uuid) -> Result<Conversation> {
+        debug!("📎 Attaching document {} to conversation {}", document_id, conversation_id);
+        self.update_conversation(conversation_id, |conv| {
+            if !conv.document_ids.contains(document_id) {
+                conv.document_ids.push(*document_id);
+            }
+        })
+    }
+
+    async fn detach_document(&self, conversation_id: &uuid::Uuid, document_id: &uuid::Uuid) -> Result<Conversation> {
+        debug!("📎 Detaching document {} from conversation {}", document_id, conversation_id);
+        self.update_conversation(conversation_id, |conv| conv.document_ids.retain(|id| id != document_id))
+    }
+
+    async fn search_rag(
+        &self,
+        query: &str,
+        llm_id: Option<&str>,
+        conversation_id: Option<&uuid::Uuid>,
+        limit: usize,
+    ) -> Result<Vec<RAGResult>> {
         debug!("🔍 RAG search: {} (LLM: {:?}, limit: {})", query, llm_id, limit);
-        Ok(Vec::new())
+
+        let mut results: Vec<RAGResult> = self
+            .documents
+            .iter()
+            .flat_map(|document| {
+                let document_id = *document.key();
+                let mut metadata = document.metadata.clone();
+                metadata.insert("collection".to_string(), document.collection.clone().into());
+                metadata.insert("name".to_string(), document.name.clone().into());
+                if let Some((source, checked_at)) = &document.source {
+                    metadata.insert("source".to_string(), source.clone().into());
+                    metadata.insert("checked_at".to_string(), serde_json::json!(checked_at));
+                }
+                document
+                    .chunks
+                    .iter()
+                    .map(|(id, text)| (*id, text.clone(), lexical_similarity(query, text)))
+                    .filter(|(_, _, similarity)| *similarity > 0.0)
+                    .map(|(id, content, similarity)| RAGResult {
+                        id,
+                        document_id: Some(document_id),
+                        content,
+                        similarity,
+                        metadata: metadata.clone(),
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .collect();
+        results.sort_by(|a, b| b.similarity.total_cmp(&a.similarity));
+
+        let attached = conversation_id
+            .and_then(|id| self.conversations.get(id).map(|conv| conv.document_ids.clone()))
+            .unwrap_or_default();
+        Ok(prioritize_attached(prefer_language(results, query), &attached, limit))
+    }
+
+    async fn index_document(&self, document: NewDocument) -> Result<IndexedDocument> {
+        let id = uuid::Uuid::new_v4();
+        let document = StoredDocument::new(document);
+        debug!("📚 Indexing {} into {}: {} chunks", document.name, document.collection, document.chunks.len());
+
+        let chunk_count = document.chunks.len();
+        self.documents.insert(id, document);
+        Ok(IndexedDocument { id, chunk_count })
+    }
+
+    async fn refresh_document(&self, source: &str, document: NewDocument) -> Result<RefreshedDocument> {
+        let checked = Some((source.to_string(), Utc::now()));
+        let known = self
+            .documents
+            .iter()
+            .find(|stored| stored.source.as_ref().is_some_and(|(known, _)| known == source))
+            .map(|stored| *stored.key());
+
+        let Some(id) = known else {
+            let id = uuid::Uuid::new_v4();
+            let mut document = StoredDocument::new(document);
+            debug!("📚 Indexing {} from {}: {} chunks", document.name, source, document.chunks.len());
+            let chunk_count = document.chunks.len();
+            document.source = checked;
+            self.documents.insert(id, document);
+            return Ok(RefreshedDocument { id, status: RefreshStatus::Added, chunk_count });
+        };
+
+        let mut stored = self
+            .documents
+            .get_mut(&id)
+            .ok_or_else(|| HybridLLMError::InvalidRequest(format!("No document {}", id)))?;
+        if stored.checksum == chunk_hash(&document.content) {
+            stored.source = checked;
+            return Ok(RefreshedDocument { id, status: RefreshStatus::Unchanged, chunk_count: 0 });
+        }
+        debug!("📚 Re-indexing {} from {}", document.name, source);
+        *stored = StoredDocument { source: checked, ..StoredDocument::new(document) };
+        Ok(RefreshedDocument { id, status: RefreshStatus::Changed, chunk_count: stored.chunks.len() })
+    }
+
+    async fn create_prompt_template(&self, template: NewPromptTemplate) -> Result<PromptTemplate> {
+        template.validate()?;
+        self.check_template_name(None, &template.name)?;
+        let id = uuid::Uuid::new_v4();
+        let saved = PromptTemplate { id, version: 1, template, created_at: Utc::now() };
+        self.prompt_templates.insert(id, vec![saved.clone()]);
+
+        debug!("📝 Created prompt template {}", id);
+        Ok(saved)
+    }
+
+    async fn update_prompt_template(&self, template_id: &uuid::Uuid, template: NewPromptTemplate) -> Result<PromptTemplate> {
+        template.validate()?;
+        self.check_template_name(Some(template_id), &template.name)?;
+        let mut versions = self
+            .prompt_templates
+            .get_mut(template_id)
+            .ok_or_else(|| HybridLLMError::InvalidRequest(format!("No prompt template {}", template_id)))?;
+        let version = versions.last().map_or(0, |latest| latest.version) + 1;
+        let saved = PromptTemplate { id: *template_id, version, template, created_at: Utc::now() };
+        versions.push(saved.clone());
+        Ok(saved)
+    }
+
+    async fn get_prompt_template(&self, template_id: &uuid::Uuid, version: Option<i32>) -> Result<PromptTemplate> {
+        self.prompt_templates
+            .get(template_id)
+            .and_then(|versions| match version {
+                Some(version) => versions.iter().find(|t| t.version == version).cloned(),
+                None => versions.last().cloned(),
+            })
+            .ok_or_else(|| HybridLLMError::InvalidRequest(format!("No prompt template {} at that version", template_id)))
+    }
+
+    async fn list_prompt_templates(&self) -> Result<Vec<PromptTemplate>> {
+        let mut templates: Vec<_> = self
+            .prompt_templates
+            .iter()
+            .filter_map(|versions| versions.last().cloned())
+            .collect();
+        templates.sort_by(|a, b| a.template.name.cmp(&b.template.name));
+        Ok(templates)
+    }
+
+    async fn prompt_template_history(&self, template_id: &uuid::Uuid) -> Result<Vec<PromptTemplate>> {
+        let versions = self
+            .prompt_templates
+            .get(template_id)
+            .ok_or_else(|| HybridLLMError::InvalidRequest(format!("No prompt template {}", template_id)))?;
+        Ok(versions.iter().rev().cloned().collect())
+    }
+
+    async fn delete_prompt_template(&self, template_id: &uuid::Uuid) -> Result<()> {
+        self.prompt_templates
+            .remove(template_id)
+            .map(|_| ())
+            .ok_or_else(|| HybridLLMError::InvalidRequest(format!("No prompt template {}", template_id)))
+    }
+}
+
+impl ContextManagerImpl {
+    fn update_conversation(
+        &self,
+        conversation_id: &uuid::Uuid,
+        update: impl FnOnce(&mut StoredConversation),
+    ) -> Result<Conversation> {
+        let mut conversation = self
+            .conversations
+            .get_mut(conversation_id)
+            .ok_or_else(|| HybridLLMError::InvalidRequest(format!("No conversation {}", conversation_id)))?;
+        update(&mut conversation);
+        Ok(conversation.summary(*conversation_id))
+    }
+
+    /// Fail if another template already has `name`
+    fn check_template_name(&self, template_id: Option<&uuid::Uuid>, name: &str) -> Result<()> {
+        let taken = self.prompt_templates.iter().any(|entry| {
+            Some(entry.key()) != template_id && entry.last().is_some_and(|latest| latest.template.name == name)
+        });
+        if taken {
+            return Err(HybridLLMError::InvalidRequest(format!("A prompt template is already named {}", name)));
+        }
+        Ok(())
     }
 }
 
@@ -101,3 +397,172 @@ impl Default for ContextManagerImpl {
         Self::new()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn user_message(content: &str) -> Message {
+        Message {
+            id: uuid::Uuid::new_v4(),
+            role: MessageRole::User,
+            content: content.into(),
+            timestamp: Utc::now(),
+            metadata: HashMap::new(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_conversation_lifecycle() {
+        let context = ContextManagerImpl::new();
+
+        let untitled = context.create_conversation(None).await.unwrap();
+        assert_eq!(untitled.title, "New conversation");
+        context.add_message(&untitled.id, user_message("\nHow do I rotate logs?\nDetails...")).await.unwrap();
+        let named = context.create_conversation(Some("Planning")).await.unwrap();
+
+        // Most recently active first, untitled ones named after their first message
+        let listed = context.list_conversations(false).await.unwrap();
+        assert_eq!(listed.iter().map(|c| c.title.as_str()).collect::<Vec<_>>(), vec!["Planning", "How do I rotate logs?"]);
+        assert_eq!(listed[1].message_count, 1);
+
+        let renamed = context.rename_conversation(&untitled.id, "Log rotation").await.unwrap();
+        assert_eq!(renamed.title, "Log rotation");
+
+        context.trash_conversation(&named.id).await.unwrap();
+        assert_eq!(context.list_conversations(false).await.unwrap().len(), 1);
+        assert_eq!(context.list_conversations(true).await.unwrap()[0].id, named.id);
+
+        // Only conversations trashed before the cutoff are purged
+        let cutoff = Utc::now();
+        context.trash_conversation(&untitled.id).await.unwrap();
+        assert_eq!(context.purge_conversations(cutoff).await.unwrap(), 1);
+        assert!(context.restore_conversation(&named.id).await.is_err());
+
+        let restored = context.restore_conversation(&untitled.id).await.unwrap();
+        assert!(restored.trashed_at.is_none());
+        assert_eq!(context.get_conversation(&untitled.id).await.unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_attach_documents() {
+        let context = ContextManagerImpl::new();
+        let conversation = context.create_conversation(None).await.unwrap();
+        let (first, second) = (uuid::Uuid::new_v4(), uuid::Uuid::new_v4());
+
+        context.attach_document(&conversation.id, &first).await.unwrap();
+        context.attach_document(&conversation.id, &second).await.unwrap();
+        let attached = context.attach_document(&conversation.id, &first).await.unwrap();
+        assert_eq!(attached.document_ids, vec![first, second]);
+
+        let detached = context.detach_document(&conversation.id, &first).await.unwrap();
+        assert_eq!(detached.document_ids, vec![second]);
+        assert_eq!(context.list_conversations(false).await.unwrap()[0].document_ids, vec![second]);
+
+        assert!(context.attach_document(&uuid::Uuid::new_v4(), &first).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_index_and_search() {
+        let context = ContextManagerImpl::new();
+        let document = |name: &str, content: &str| NewDocument {
+            collection: common::types::BROWSING_COLLECTION.to_string(),
+            name: name.to_string(),
+            content: content.to_string(),
+            metadata: HashMap::from([("url".to_string(), serde_json::json!(format!("https://example.com/{}", name)))]),
+        };
+        let tokio = context
+            .index_document(document("tokio", "Tokio is an async runtime.\n\nIt schedules tasks on a thread pool."))
+            .await
+            .unwrap();
+        assert_eq!(tokio.chunk_count, 2);
+        let rayon = context.index_document(document("rayon", "Rayon runs work on a thread pool.")).await.unwrap();
+
+        let results = context.search_rag("thread pool", None, None, 10).await.unwrap();
+        assert_eq!(results.len(), 2);
+        assert!(context.search_rag("haskell", None, None, 10).await.unwrap().is_empty());
+
+        // Attaching a page to the conversation puts its chunks first
+        let conversation = context.create_conversation(None).await.unwrap();
+        context.attach_document(&conversation.id, &rayon.id).await.unwrap();
+        let results = context.search_rag("async thread pool", None, Some(&conversation.id), 1).await.unwrap();
+        assert_eq!(results[0].document_id, Some(rayon.id));
+        assert_eq!(results[0].metadata["url"], "https://example.com/rayon");
+        assert_eq!(results[0].metadata["collection"], "browsing");
+    }
+
+    #[tokio::test]
+    async fn test_refresh_document() {
+        let context = ContextManagerImpl::new();
+        let document = |content: &str| NewDocument {
+            collection: common::types::UPLOADS_COLLECTION.to_string(),
+            name: "notes.md".to_string(),
+            content: content.to_string(),
+            metadata: HashMap::new(),
+        };
+
+        let added = context.refresh_document("/watched/notes.md", document("Tokio runs tasks.")).await.unwrap();
+        assert_eq!((added.status, added.chunk_count), (RefreshStatus::Added, 1));
+        let unchanged = context.refresh_document("/watched/notes.md", document("Tokio runs tasks.")).await.unwrap();
+        assert_eq!((unchanged.id, unchanged.status), (added.id, RefreshStatus::Unchanged));
+        let changed = context.refresh_document("/watched/notes.md", document("Rayon runs work.")).await.unwrap();
+        assert_eq!((changed.id, changed.status), (added.id, RefreshStatus::Changed));
+
+        // Only the current content is found, with when it was last checked
+        assert!(context.search_rag("tokio", None, None, 10).await.unwrap().is_empty());
+        let results = context.search_rag("rayon", None, None, 10).await.unwrap();
+        assert_eq!(results[0].metadata["source"], "/watched/notes.md");
+        assert!(results[0].checked_at().is_some());
+        assert!(!results[0].is_stale(std::time::Duration::from_secs(3600)));
+        assert!(results[0].is_stale(std::time::Duration::ZERO));
+    }
+
+    #[tokio::test]
+    async fn test_conversation_usage() {
+        let context = ContextManagerImpl::new();
+        let conversation = context.create_conversation(None).await.unwrap();
+        context.add_message(&conversation.id, user_message("hi")).await.unwrap();
+        for (tokens, cost_usd) in [(10, Some(0.0)), (30, Some(0.02))] {
+            let mut reply = Message { role: MessageRole::Assistant, ..user_message("hello") };
+            reply.set_usage(&common::Usage { output_tokens: tokens, cost_usd, latency_ms: 100, ..Default::default() });
+            context.add_message(&conversation.id, reply).await.unwrap();
+        }
+
+        let usage = context.conversation_usage(&conversation.id).await.unwrap();
+        assert_eq!((usage.output_tokens, usage.cost_usd, usage.latency_ms), (40, Some(0.02), 200));
+    }
+
+    #[tokio::test]
+    async fn test_prompt_template_versions() {
+        let context = ContextManagerImpl::new();
+        let template = |name: &str, body: &str| NewPromptTemplate {
+            name: name.to_string(),
+            description: String::new(),
+            body: body.to_string(),
+            model_overrides: HashMap::new(),
+        };
+
+        let summary = context.create_prompt_template(template("Summary", "Summarize: {{input}}")).await.unwrap();
+        assert_eq!(summary.version, 1);
+        assert!(context.create_prompt_template(template("Summary", "Again")).await.is_err());
+        context.create_prompt_template(template("Explain", "Explain {{input}}")).await.unwrap();
+
+        let edited = context.update_prompt_template(&summary.id, template("Summary", "TL;DR: {{input}}")).await.unwrap();
+        assert_eq!(edited.version, 2);
+        let listed = context.list_prompt_templates().await.unwrap();
+        assert_eq!(listed.iter().map(|t| t.template.name.as_str()).collect::<Vec<_>>(), vec!["Explain", "Summary"]);
+        assert_eq!(listed[1].version, 2);
+
+        // Earlier versions stay available for whatever pinned them
+        let first = context.get_prompt_template(&summary.id, Some(1)).await.unwrap();
+        assert_eq!(first.template.body, "Summarize: {{input}}");
+        assert_eq!(context.get_prompt_template(&summary.id, None).await.unwrap().version, 2);
+        assert!(context.get_prompt_template(&summary.id, Some(3)).await.is_err());
+        let history = context.prompt_template_history(&summary.id).await.unwrap();
+        assert_eq!(history.iter().map(|t| t.version).collect::<Vec<_>>(), vec![2, 1]);
+
+        context.delete_prompt_template(&summary.id).await.unwrap();
+        assert!(context.get_prompt_template(&summary.id, None).await.is_err());
+        assert_eq!(context.list_prompt_templates().await.unwrap().len(), 1);
+    }
+}
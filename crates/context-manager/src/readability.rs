@@ -0,0 +1,303 @@
+use common::{
+    errors::{Result, HybridLLMError},
+    types::{NewDocument, BROWSING_COLLECTION},
+};
+use std::collections::HashMap;
+
+/// Elements whose content is never part of the readable text
+const SKIPPED: &[&str] = &[
+    "head", "script", "style", "noscript", "template", "svg", "canvas", "iframe", "nav", "header", "footer",
+    "aside", "form", "button", "select", "dialog",
+];
+
+/// Elements that start a new paragraph
+const BLOCKS: &[&str] = &[
+    "p", "div", "section", "article", "main", "h1", "h2", "h3", "h4", "h5", "h6", "li", "ul", "ol", "dl", "dt",
+    "dd", "table", "tr", "blockquote", "pre", "hr", "figure", "figcaption", "details", "summary",
+];
+
+/// Elements holding the page's own content, preferred over the rest when they have enough of it
+const CONTENT: &[&str] = &["article", "main"];
+
+/// Readable text of an `article` or `main` shorter than this is not trusted to be the whole page
+const MIN_CONTENT_CHARS: usize = 200;
+
+/// The readable part of an HTML page
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReadablePage {
+    /// From `<title>`, else the first `<h1>`
+    pub title: Option<String>,
+    /// Paragraphs separated by blank lines
+    pub text: String,
+}
+
+/// Pull the readable text out of a page, leaving out navigation, scripts and other chrome
+/// Prefers the page's `article` or `main` content when there is enough of it
+pub fn extract_readable(html: &str) -> ReadablePage {
+    let mut page = Text::default();
+    let mut content = Text::default();
+    let mut title = None;
+    let mut heading = None;
+    let (mut skip_depth, mut content_depth) = (0usize, 0usize);
+
+    let mut rest = html;
+    while let Some(start) = rest.find('<') {
+        if skip_depth == 0 {
+            page.push(&rest[..start]);
+            if content_depth > 0 {
+                content.push(&rest[..start]);
+            }
+        }
+        rest = &rest[start..];
+
+        // A `<` that starts no tag is text
+        if !rest[1..].starts_with(|c: char| c.is_ascii_alphabetic() || matches!(c, '/' | '!' | '?')) {
+            if skip_depth == 0 {
+                page.push("<");
+                if content_depth > 0 {
+                    content.push("<");
+                }
+            }
+            rest = &rest[1..];
+            continue;
+        }
+        if let Some(comment) = rest.strip_prefix("<!--") {
+            rest = comment.find("-->").map_or("", |end| &comment[end + 3..]);
+            continue;
+        }
+        let Some(end) = rest.find('>') else {
+            break;
+        };
+        let tag = &rest[1..end];
+        rest = &rest[end + 1..];
+
+        let closing = tag.starts_with('/');
+        let name: String = tag
+            .trim_start_matches('/')
+            .chars()
+            .take_while(char::is_ascii_alphanumeric)
+            .collect::<String>()
+            .to_ascii_lowercase();
+        if name.is_empty() {
+            // Doctypes and processing instructions
+            continue;
+        }
+
+        if !closing && matches!(name.as_str(), "title" | "script" | "style") {
+            let (inner, after) = raw_text(rest, &name);
+            if name == "title" && title.is_none() {
+                title = Some(collapse(&decode_entities(inner))).filter(|t| !t.is_empty());
+            }
+            rest = after;
+            continue;
+        }
+
+        let self_closing = tag.ends_with('/');
+        if SKIPPED.contains(&name.as_str()) && !self_closing {
+            if closing {
+                skip_depth = skip_depth.saturating_sub(1);
+            } else {
+                skip_depth += 1;
+            }
+            continue;
+        }
+        if skip_depth > 0 {
+            continue;
+        }
+
+        if name == "h1" && heading.is_none() && !closing {
+            let inner = rest.find("</").map_or(rest, |end| &rest[..end]);
+            heading = Some(collapse(&decode_entities(inner))).filter(|h| !h.is_empty());
+        }
+        if CONTENT.contains(&name.as_str()) {
+            if closing {
+                content_depth = content_depth.saturating_sub(1);
+            } else {
+                content_depth += 1;
+            }
+        }
+        if BLOCKS.contains(&name.as_str()) || name == "br" {
+            page.paragraph();
+            content.paragraph();
+        }
+    }
+    if skip_depth == 0 {
+        page.push(rest);
+    }
+
+    let content = content.finish();
+    let text = if content.chars().count() >= MIN_CONTENT_CHARS { content } else { page.finish() };
+    ReadablePage { title: title.or(heading), text }
+}
+
+/// A page's readable text as a document for the browsing collection, with its URL in the metadata
+pub fn web_page_document(url: &str, title: Option<&str>, html: &str) -> Result<NewDocument> {
+    let page = extract_readable(html);
+    if page.text.is_empty() {
+        return Err(HybridLLMError::InvalidRequest(format!("{} has no readable text", url)));
+    }
+
+    let title = title.map(str::trim).filter(|t| !t.is_empty()).map(str::to_string).or(page.title);
+    let metadata = HashMap::from([
+        ("url".to_string(), serde_json::json!(url)),
+        ("title".to_string(), serde_json::json!(title)),
+        ("indexed_at".to_string(), serde_json::json!(chrono::Utc::now())),
+    ]);
+    Ok(NewDocument {
+        collection: BROWSING_COLLECTION.to_string(),
+        name: title.unwrap_or_else(|| url.to_string()),
+        content: page.text,
+        metadata,
+    })
+}
+
+/// Paragraphs built up from text runs
+#[derive(Default)]
+struct Text {
+    paragraphs: Vec<String>,
+    current: String,
+    /// Whitespace was seen since the last word, so inline tags don't glue or split words
+    space: bool,
+}
+
+impl Text {
+    fn push(&mut self, raw: &str) {
+        let decoded = decode_entities(raw);
+        let text = collapse(&decoded);
+        if decoded.starts_with(char::is_whitespace) {
+            self.space = true;
+        }
+        if text.is_empty() {
+            return;
+        }
+        if self.space && !self.current.is_empty() {
+            self.current.push(' ');
+        }
+        self.current.push_str(&text);
+        self.space = decoded.ends_with(char::is_whitespace);
+    }
+
+    fn paragraph(&mut self) {
+        if !self.current.is_empty() {
+            self.paragraphs.push(std::mem::take(&mut self.current));
+        }
+        self.space = false;
+    }
+
+    fn finish(mut self) -> String {
+        self.paragraph();
+        self.paragraphs.join("\n\n")
+    }
+}
+
+/// Content of a raw text element up to its closing tag, and what follows it
+fn raw_text<'a>(html: &'a str, name: &str) -> (&'a str, &'a str) {
+    let closing = html
+        .match_indices("</")
+        .map(|(end, _)| end)
+        .find(|&end| html[end + 2..].get(..name.len()).is_some_and(|tag| tag.eq_ignore_ascii_case(name)));
+    match closing {
+        Some(end) => {
+            let after = &html[end..];
+            (&html[..end], after.find('>').map_or("", |close| &after[close + 1..]))
+        }
+        None => (html, ""),
+    }
+}
+
+fn collapse(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// Decode the character references pages commonly use; others are left as they are
+fn decode_entities(text: &str) -> String {
+    if !text.contains('&') {
+        return text.to_string();
+    }
+
+    let mut decoded = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find('&') {
+        decoded.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let entity = rest[1..].find(';').filter(|&end| end <= 10).map(|end| &rest[1..end + 1]);
+        let character = entity.and_then(|entity| match entity {
+            "amp" => Some('&'),
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            "nbsp" => Some(' '),
+            _ => entity
+                .strip_prefix("#x")
+                .or_else(|| entity.strip_prefix("#X"))
+                .map(|hex| u32::from_str_radix(hex, 16))
+                .or_else(|| entity.strip_prefix('#').map(str::parse))
+                .and_then(|code| code.ok())
+                .and_then(char::from_u32),
+        });
+        match (entity, character) {
+            (Some(entity), Some(character)) => {
+                decoded.push(character);
+                rest = &rest[entity.len() + 2..];
+            }
+            _ => {
+                decoded.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    decoded.push_str(rest);
+    decoded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_extract_readable() {
+        let html = r#"<!DOCTYPE html>
+            <html><head><title>Fish &amp; Chips</title><style>p { color: red }</style></head>
+            <body>
+              <nav><a href="/">Home</a> <a href="/about">About</a></nav>
+              <h1>A history</h1>
+              <p>Fried fish came to <b>England</b> in the <a href="/1860s">1860s</a>.<br>It was sold&nbsp;wrapped.</p>
+              <!-- <p>hidden</p> -->
+              <script>if (a < b) { document.write("<p>ad</p>") }</script>
+              <div>Prices start at &#163;5 &#x2014; cheap &amp; 2 < 3.</div>
+              <footer>© 2024</footer>
+            </body></html>"#;
+
+        let page = extract_readable(html);
+        assert_eq!(page.title.as_deref(), Some("Fish & Chips"));
+        assert_eq!(
+            page.text,
+            "A history\n\nFried fish came to England in the 1860s.\n\nIt was sold wrapped.\n\nPrices start at £5 — cheap & 2 < 3."
+        );
+    }
+
+    #[test]
+    fn test_prefers_article() {
+        let body = "Long read. ".repeat(30);
+        let html = format!("<div>Sign up for our newsletter</div><article><h1>Story</h1><p>{}</p></article>", body);
+        let page = extract_readable(&html);
+        assert!(page.text.starts_with("Story\n\nLong read."));
+        assert!(!page.text.contains("newsletter"));
+        assert_eq!(page.title.as_deref(), Some("Story"));
+
+        // A short article is more likely a teaser than the page
+        let page = extract_readable("<p>Intro</p><article>Teaser</article>");
+        assert_eq!(page.text, "Intro\n\nTeaser");
+    }
+
+    #[test]
+    fn test_web_page_document() {
+        let document = web_page_document("https://example.com/a", None, "<title>A</title><p>Body</p>").unwrap();
+        assert_eq!(document.collection, BROWSING_COLLECTION);
+        assert_eq!(document.name, "A");
+        assert_eq!(document.metadata["url"], "https://example.com/a");
+
+        assert!(web_page_document("https://example.com/b", None, "<script>x()</script>").is_err());
+    }
+}
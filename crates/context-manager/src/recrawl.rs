@@ -0,0 +1,185 @@
+use api_gateway::PageFetcher;
+use common::{
+    config::RecrawlSettings,
+    errors::{HybridLLMError, Result},
+    traits::ContextManager,
+    types::{NewDocument, RefreshStatus, BROWSING_COLLECTION, UPLOADS_COLLECTION},
+};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tracing::{debug, info, warn};
+
+use crate::readability::web_page_document;
+
+/// Files in watched folders past this size are left out
+const MAX_WATCHED_FILE_BYTES: u64 = 4 * 1024 * 1024;
+
+/// What one re-crawl of the watched folders and pages did
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RecrawlReport {
+    pub added: usize,
+    pub changed: usize,
+    pub unchanged: usize,
+    /// `<source>: <reason>` for sources that couldn't be read; their documents turn stale
+    pub failed: Vec<String>,
+}
+
+/// Keeps the documents of watched folders and pages current by indexing them again, by source
+/// Content that didn't change only has its check recorded, so a re-crawl embeds just what changed
+pub struct Recrawler {
+    fetcher: Arc<PageFetcher>,
+}
+
+impl Recrawler {
+    pub fn new(fetcher: Arc<PageFetcher>) -> Self {
+        Self { fetcher }
+    }
+
+    /// Read every watched file and page once and refresh its document
+    pub async fn recrawl(&self, context: &dyn ContextManager, settings: &RecrawlSettings) -> RecrawlReport {
+        let mut report = RecrawlReport::default();
+
+        for folder in &settings.folders {
+            let scanned = {
+                let folder = folder.clone();
+                tokio::task::spawn_blocking(move || watched_files(&folder)).await
+            };
+            let files = match scanned.map_err(|e| HybridLLMError::Other(e.into())).and_then(|files| files) {
+                Ok(files) => files,
+                Err(e) => {
+                    report.failed.push(format!("{}: {}", folder.display(), e));
+                    continue;
+                }
+            };
+            for path in files {
+                let source = path.display().to_string();
+                match file_document(&path).await {
+                    Ok(Some(document)) => report.record(&source, context.refresh_document(&source, document).await),
+                    // Not text; left out rather than reported on every re-crawl
+                    Ok(None) => debug!("📚 Skipping {:?}, not a text file", path),
+                    Err(e) => report.failed.push(format!("{}: {}", source, e)),
+                }
+            }
+        }
+
+        for url in &settings.urls {
+            match self.page_document(url).await {
+                Ok(document) => report.record(url, context.refresh_document(url, document).await),
+                Err(e) => report.failed.push(format!("{}: {}", url, e)),
+            }
+        }
+
+        info!(
+            "📚 Re-crawled watched sources: {} added, {} changed, {} unchanged, {} failed",
+            report.added, report.changed, report.unchanged, report.failed.len()
+        );
+        report
+    }
+
+    async fn page_document(&self, url: &str) -> Result<NewDocument> {
+        let page = self.fetcher.fetch(url).await?;
+        if page.is_html() {
+            return web_page_document(&page.url, None, &page.body);
+        }
+        Ok(NewDocument {
+            collection: BROWSING_COLLECTION.to_string(),
+            name: page.url.clone(),
+            content: page.body,
+            metadata: HashMap::from([("url".to_string(), serde_json::json!(page.url))]),
+        })
+    }
+}
+
+impl RecrawlReport {
+    fn record(&mut self, source: &str, refreshed: Result<common::types::RefreshedDocument>) {
+        match refreshed {
+            Ok(refreshed) => match refreshed.status {
+                RefreshStatus::Added => self.added += 1,
+                RefreshStatus::Changed => self.changed += 1,
+                RefreshStatus::Unchanged => self.unchanged += 1,
+            },
+            Err(e) => {
+                warn!("⚠️  Failed to re-index {}: {}", source, e);
+                self.failed.push(format!("{}: {}", source, e));
+            }
+        }
+    }
+}
+
+/// Files under `folder`, hidden files and folders left out; symlinks aren't followed
+fn watched_files(folder: &Path) -> Result<Vec<PathBuf>> {
+    if !folder.is_dir() {
+        return Err(HybridLLMError::FileSystemError(format!("{} is not a folder", folder.display())));
+    }
+    let is_hidden = |entry: &walkdir::DirEntry| entry.depth() > 0 && entry.file_name().to_string_lossy().starts_with('.');
+
+    let mut files = Vec::new();
+    for entry in walkdir::WalkDir::new(folder).sort_by_file_name().into_iter().filter_entry(|entry| !is_hidden(entry)) {
+        let entry = entry.map_err(|e| HybridLLMError::FileSystemError(e.to_string()))?;
+        if entry.file_type().is_file() {
+            files.push(entry.into_path());
+        }
+    }
+    Ok(files)
+}
+
+/// A watched file as a document for the uploads collection; `None` unless it is UTF-8 text within the size limit
+async fn file_document(path: &Path) -> Result<Option<NewDocument>> {
+    let fs_err = |e: std::io::Error| HybridLLMError::FileSystemError(e.to_string());
+    if tokio::fs::metadata(path).await.map_err(fs_err)?.len() > MAX_WATCHED_FILE_BYTES {
+        return Ok(None);
+    }
+    let content = match String::from_utf8(tokio::fs::read(path).await.map_err(fs_err)?) {
+        Ok(content) if !content.contains('\0') && !content.trim().is_empty() => content,
+        _ => return Ok(None),
+    };
+
+    Ok(Some(NewDocument {
+        collection: UPLOADS_COLLECTION.to_string(),
+        name: path.file_name().map_or_else(|| path.display().to_string(), |name| name.to_string_lossy().into_owned()),
+        content,
+        metadata: HashMap::new(),
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::InMemoryContextManager;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn test_recrawl_folders() {
+        let folder = std::env::temp_dir().join(format!("recrawl-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(folder.join("guides")).unwrap();
+        std::fs::create_dir_all(folder.join(".git")).unwrap();
+        std::fs::write(folder.join("notes.md"), "Tokio runs tasks on a thread pool.").unwrap();
+        std::fs::write(folder.join("guides/rayon.md"), "Rayon splits work across threads.").unwrap();
+        std::fs::write(folder.join(".git/HEAD"), "ref: refs/heads/main").unwrap();
+        std::fs::write(folder.join("logo.png"), [0x89, b'P', b'N', b'G', 0, 0xff]).unwrap();
+
+        let context = InMemoryContextManager::new();
+        let recrawler = Recrawler::new(Arc::new(PageFetcher::new(1024, Duration::from_secs(1), false)));
+        let settings = RecrawlSettings {
+            folders: vec![folder.clone(), folder.join("missing")],
+            ..Default::default()
+        };
+
+        let first = recrawler.recrawl(&context, &settings).await;
+        assert_eq!((first.added, first.changed, first.unchanged), (2, 0, 0));
+        assert_eq!(first.failed.len(), 1);
+        assert!(first.failed[0].contains("missing"));
+
+        std::fs::write(folder.join("notes.md"), "Tokio schedules async tasks.").unwrap();
+        let second = recrawler.recrawl(&context, &settings).await;
+        assert_eq!((second.added, second.changed, second.unchanged), (0, 1, 1));
+
+        let results = context.search_rag("async", None, None, 10).await.unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].metadata["source"], folder.join("notes.md").display().to_string());
+        assert!(context.search_rag("pool", None, None, 10).await.unwrap().is_empty());
+        std::fs::remove_dir_all(folder).unwrap();
+    }
+}
@@ -0,0 +1,110 @@
+use common::language::{detect_language, LANGUAGE_KEY};
+use common::traits::RAGResult;
+use std::collections::HashSet;
+use uuid::Uuid;
+
+/// Move chunks of `attached` documents ahead of the rest, keeping each group's ranking, and keep the best `limit`
+pub fn prioritize_attached(mut results: Vec<RAGResult>, attached: &[Uuid], limit: usize) -> Vec<RAGResult> {
+    results.sort_by_key(|result| !result.document_id.is_some_and(|id| attached.contains(&id)));
+    results.truncate(limit);
+    results
+}
+
+/// Share of their similarity chunks of documents in another language than the query are ranked by
+const OTHER_LANGUAGE_WEIGHT: f32 = 0.75;
+
+/// Rank chunks of documents written in another language than `query` lower, keeping the order otherwise
+/// Queries whose language can't be told, and documents without one, are left alone
+pub fn prefer_language(mut results: Vec<RAGResult>, query: &str) -> Vec<RAGResult> {
+    let Some(language) = detect_language(query) else {
+        return results;
+    };
+    let weighted = |result: &RAGResult| match result.metadata.get(LANGUAGE_KEY).and_then(|value| value.as_str()) {
+        Some(other) if other != language => result.similarity * OTHER_LANGUAGE_WEIGHT,
+        _ => result.similarity,
+    };
+    results.sort_by(|a, b| weighted(b).total_cmp(&weighted(a)));
+    results
+}
+
+/// Share of the query's words found in `text`, from 0 to 1
+/// Ranks chunks where there are no embeddings to compare
+pub fn lexical_similarity(query: &str, text: &str) -> f32 {
+    let query = words(query);
+    if query.is_empty() {
+        return 0.0;
+    }
+    let text = words(text);
+    query.intersection(&text).count() as f32 / query.len() as f32
+}
+
+/// Lowercased words of two or more characters
+fn words(text: &str) -> HashSet<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| word.chars().count() > 1)
+        .map(str::to_lowercase)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn result(content: &str, document_id: Option<Uuid>, similarity: f32) -> RAGResult {
+        RAGResult {
+            id: Uuid::new_v4(),
+            document_id,
+            content: content.to_string(),
+            similarity,
+            metadata: HashMap::new(),
+        }
+    }
+
+    #[test]
+    fn test_prioritize_attached() {
+        let (attached, other) = (Uuid::new_v4(), Uuid::new_v4());
+        let results = vec![
+            result("best", Some(other), 0.9),
+            result("loose", None, 0.8),
+            result("attached", Some(attached), 0.7),
+            result("attached too", Some(attached), 0.6),
+        ];
+
+        let ranked = prioritize_attached(results.clone(), &[attached], 3);
+        assert_eq!(ranked.iter().map(|r| r.content.as_str()).collect::<Vec<_>>(), ["attached", "attached too", "best"]);
+
+        // Nothing attached leaves the ranking alone
+        let ranked = prioritize_attached(results, &[], 10);
+        assert_eq!(ranked[0].content, "best");
+        assert_eq!(ranked.len(), 4);
+    }
+
+    #[test]
+    fn test_prefer_language() {
+        let in_language = |content: &str, language: &str, similarity: f32| RAGResult {
+            metadata: HashMap::from([(LANGUAGE_KEY.to_string(), serde_json::json!(language))]),
+            ..result(content, None, similarity)
+        };
+        let results = vec![
+            in_language("german", "deu", 0.9),
+            in_language("japanese", "jpn", 0.8),
+            result("unknown", None, 0.5),
+            in_language("english", "eng", 0.6),
+        ];
+
+        let ranked = prefer_language(results.clone(), "東京で一番おいしいラーメン屋はどこですか？");
+        assert_eq!(ranked.iter().map(|r| r.content.as_str()).collect::<Vec<_>>(), ["japanese", "german", "unknown", "english"]);
+
+        // A query too short to tell keeps the ranking
+        assert_eq!(prefer_language(results, "ok")[0].content, "german");
+    }
+
+    #[test]
+    fn test_lexical_similarity() {
+        assert_eq!(lexical_similarity("Rust borrow checker", "The borrow checker in rust"), 1.0);
+        assert_eq!(lexical_similarity("rust async", "Async Python"), 0.5);
+        assert_eq!(lexical_similarity("a", "a b c"), 0.0);
+        assert_eq!(lexical_similarity("tokio", ""), 0.0);
+    }
+}
@@ -0,0 +1,80 @@
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use uuid::Uuid;
+
+use crate::embeddings::chunk_text;
+
+/// Words per chunk when a section is too long to embed whole
+pub const SECTION_CHUNK_WORDS: usize = 200;
+
+/// Words shared between consecutive chunks of one long section
+pub const SECTION_CHUNK_OVERLAP: usize = 20;
+
+/// How a new document version's chunks relate to the chunks already indexed
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ChunkDiff {
+    /// Indexed chunks still present, which keep their embeddings
+    pub unchanged: Vec<Uuid>,
+    /// Positions in the new version's chunks that need embedding
+    pub added: Vec<usize>,
+    /// Indexed chunks no longer present
+    pub removed: Vec<Uuid>,
+}
+
+/// Split text into chunks along blank-line separated sections
+/// Chunk boundaries never cross a section, so an edit only changes the chunks of its own section
+pub fn section_chunks(text: &str) -> Vec<String> {
+    text.split("\n\n")
+        .map(str::trim)
+        .filter(|section| !section.is_empty())
+        .flat_map(|section| chunk_text(section, SECTION_CHUNK_WORDS, SECTION_CHUNK_OVERLAP))
+        .collect()
+}
+
+pub fn chunk_hash(chunk: &str) -> String {
+    format!("{:x}", Sha256::digest(chunk.as_bytes()))
+}
+
+/// Match a new version's chunks against indexed `(id, chunk_hash)` pairs
+/// Repeated chunks are matched one for one
+pub fn diff_chunks(indexed: &[(Uuid, String)], chunks: &[String]) -> ChunkDiff {
+    let mut available: HashMap<&str, Vec<Uuid>> = HashMap::new();
+    for (id, hash) in indexed.iter().rev() {
+        available.entry(hash.as_str()).or_default().push(*id);
+    }
+
+    let mut diff = ChunkDiff::default();
+    for (index, chunk) in chunks.iter().enumerate() {
+        match available.get_mut(chunk_hash(chunk).as_str()).and_then(Vec::pop) {
+            Some(id) => diff.unchanged.push(id),
+            None => diff.added.push(index),
+        }
+    }
+
+    diff.removed = indexed
+        .iter()
+        .map(|(id, _)| *id)
+        .filter(|id| available.values().any(|ids| ids.contains(id)))
+        .collect();
+    diff
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_only_changed_sections_are_reindexed() {
+        let v1 = "Opening hours\n\nMonday to Friday 9-5\n\nParking is free";
+        let v2 = "Opening hours\n\nMonday to Friday 8-6\n\nParking is free\n\nOpening hours";
+
+        let chunks = section_chunks(v1);
+        let indexed: Vec<_> = chunks.iter().map(|c| (Uuid::new_v4(), chunk_hash(c))).collect();
+
+        let diff = diff_chunks(&indexed, &section_chunks(v2));
+        assert_eq!(diff.unchanged, vec![indexed[0].0, indexed[2].0]);
+        // The edited section and the repeated heading are new; the old hours are gone
+        assert_eq!(diff.added, vec![1, 3]);
+        assert_eq!(diff.removed, vec![indexed[1].0]);
+    }
+}
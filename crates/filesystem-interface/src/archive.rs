@@ -0,0 +1,291 @@
+use common::errors::{Result, HybridLLMError};
+use serde::{Deserialize, Serialize};
+use std::io::{Cursor, Read};
+use std::path::{Path, PathBuf};
+
+use crate::hashes::FileHash;
+use crate::sanitize::sanitize_relative_path;
+
+const MB: u64 = 1024 * 1024;
+
+/// Bounds on what unpacking one uploaded archive may produce
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExtractLimits {
+    /// Files across the archive and everything nested in it
+    pub max_entries: usize,
+    /// Uncompressed bytes across the archive and everything nested in it
+    pub max_total_bytes: u64,
+    /// How many archives deep to unpack; deeper archives are kept as files
+    pub max_depth: usize,
+}
+
+impl Default for ExtractLimits {
+    fn default() -> Self {
+        Self {
+            max_entries: 10_000,
+            max_total_bytes: 512 * MB,
+            max_depth: 3,
+        }
+    }
+}
+
+/// An archive entry that was not extracted
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SkippedEntry {
+    pub path: String,
+    pub reason: String,
+}
+
+/// What unpacking an archive into a managed folder produced
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExtractionReport {
+    /// Directory the archive was unpacked into, relative to its folder
+    pub directory: String,
+    pub files: Vec<FileHash>,
+    pub skipped: Vec<SkippedEntry>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ArchiveKind {
+    Zip,
+    Tar,
+    TarGz,
+}
+
+impl ArchiveKind {
+    fn detect(content: &[u8]) -> Option<Self> {
+        match infer::get(content)?.mime_type() {
+            "application/zip" => Some(Self::Zip),
+            "application/x-tar" => Some(Self::Tar),
+            "application/gzip" => Some(Self::TarGz),
+            _ => None,
+        }
+    }
+}
+
+/// Whether `content` is a zip, tar or tar.gz archive `extract_archive` can unpack
+pub fn is_archive(content: &[u8]) -> bool {
+    ArchiveKind::detect(content).is_some()
+}
+
+/// Files unpacked from an archive, held in memory until they are written
+#[derive(Default)]
+pub(crate) struct Extracted {
+    pub(crate) files: Vec<(PathBuf, Vec<u8>)>,
+    pub(crate) skipped: Vec<SkippedEntry>,
+    total_bytes: u64,
+    entries: usize,
+}
+
+/// Unpack a zip, tar or tar.gz archive, recursing into nested archives
+/// Entry paths are sanitized component by component; any entry that would land
+/// outside the extraction root fails the whole archive
+pub(crate) fn extract(content: &[u8], limits: &ExtractLimits) -> Result<Extracted> {
+    let mut extracted = Extracted::default();
+    extracted.walk(content, Path::new(""), 0, limits)?;
+    Ok(extracted)
+}
+
+impl Extracted {
+    fn walk(&mut self, content: &[u8], prefix: &Path, depth: usize, limits: &ExtractLimits) -> Result<()> {
+        let invalid = |e: &dyn std::fmt::Display| {
+            HybridLLMError::InvalidRequest(format!("Unreadable archive {:?}: {}", prefix, e))
+        };
+
+        match ArchiveKind::detect(content) {
+            Some(ArchiveKind::Zip) => {
+                let mut zip = zip::ZipArchive::new(Cursor::new(content)).map_err(|e| invalid(&e))?;
+                for index in 0..zip.len() {
+                    let mut file = zip.by_index(index).map_err(|e| invalid(&e))?;
+                    let name = file.name().to_string();
+                    if file.is_dir() {
+                        continue;
+                    }
+                    if file.is_symlink() {
+                        self.skip(prefix.join(&name), "links are not extracted");
+                        continue;
+                    }
+                    let data = self.read_entry(&mut file, limits)?;
+                    self.add(prefix, &name, data, depth, limits)?;
+                }
+            }
+            Some(kind @ (ArchiveKind::Tar | ArchiveKind::TarGz)) => {
+                let reader: Box<dyn Read + '_> = match kind {
+                    ArchiveKind::TarGz => Box::new(flate2::read::GzDecoder::new(content)),
+                    _ => Box::new(content),
+                };
+                let mut tar = tar::Archive::new(reader);
+                for entry in tar.entries().map_err(|e| invalid(&e))? {
+                    let mut entry = entry.map_err(|e| invalid(&e))?;
+                    let name = String::from_utf8_lossy(&entry.path_bytes()).into_owned();
+                    match entry.header().entry_type() {
+                        tar::EntryType::Regular | tar::EntryType::Continuous => {}
+                        tar::EntryType::Directory => continue,
+                        _ => {
+                            self.skip(prefix.join(&name), "only regular files are extracted");
+                            continue;
+                        }
+                    }
+                    let data = self.read_entry(&mut entry, limits)?;
+                    self.add(prefix, &name, data, depth, limits)?;
+                }
+            }
+            None => return Err(HybridLLMError::InvalidRequest("Not a zip or tar archive".to_string())),
+        }
+
+        Ok(())
+    }
+
+    /// Read one entry, counting its real size rather than trusting the header
+    fn read_entry(&mut self, entry: &mut impl Read, limits: &ExtractLimits) -> Result<Vec<u8>> {
+        self.entries += 1;
+        if self.entries > limits.max_entries {
+            return Err(HybridLLMError::ResourceLimitExceeded {
+                resource: "archive_entries".to_string(),
+                limit: limits.max_entries as f32,
+                actual: self.entries as f32,
+            });
+        }
+
+        let remaining = limits.max_total_bytes.saturating_sub(self.total_bytes);
+        let mut data = Vec::new();
+        entry
+            .take(remaining + 1)
+            .read_to_end(&mut data)
+            .map_err(|e| HybridLLMError::InvalidRequest(format!("Unreadable archive entry: {}", e)))?;
+
+        self.total_bytes += data.len() as u64;
+        if self.total_bytes > limits.max_total_bytes {
+            return Err(HybridLLMError::ResourceLimitExceeded {
+                resource: "archive_bytes".to_string(),
+                limit: limits.max_total_bytes as f32,
+                actual: self.total_bytes as f32,
+            });
+        }
+
+        Ok(data)
+    }
+
+    fn add(&mut self, prefix: &Path, name: &str, data: Vec<u8>, depth: usize, limits: &ExtractLimits) -> Result<()> {
+        let Some(relative) = entry_path(name)? else {
+            return Ok(());
+        };
+        let path = prefix.join(relative);
+
+        if ArchiveKind::detect(&data).is_none() {
+            self.files.push((path, data));
+        } else if depth < limits.max_depth {
+            self.walk(&data, &strip_archive_extension(&path), depth + 1, limits)?;
+        } else {
+            self.skip(path, "nested too deeply to extract");
+        }
+
+        Ok(())
+    }
+
+    fn skip(&mut self, path: PathBuf, reason: &str) {
+        self.skipped.push(SkippedEntry {
+            path: path.to_string_lossy().into_owned(),
+            reason: reason.to_string(),
+        });
+    }
+}
+
+/// Sanitize an entry name into a relative path; `None` for names with nothing usable left
+/// Absolute paths and `..` are zip-slip attempts and fail the archive
+fn entry_path(name: &str) -> Result<Option<PathBuf>> {
+    match sanitize_relative_path(name) {
+        Ok(path) => Ok(Some(path)),
+        Err(HybridLLMError::SecurityViolation(_)) => Err(HybridLLMError::SecurityViolation(format!(
+            "Archive entry escapes the extraction directory: {}",
+            name
+        ))),
+        Err(_) => Ok(None),
+    }
+}
+
+/// `docs/site.tar.gz` unpacks into `docs/site`
+pub(crate) fn strip_archive_extension(path: &Path) -> PathBuf {
+    let name = path.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default();
+    let lower = name.to_lowercase();
+    let stem_len = [".tar.gz", ".tgz", ".tar", ".zip"]
+        .iter()
+        .find(|ext| lower.ends_with(*ext) && lower.len() > ext.len())
+        .map_or(name.len(), |ext| name.len() - ext.len());
+    path.with_file_name(&name[..stem_len])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    fn zip(entries: &[(&str, &[u8])]) -> Vec<u8> {
+        let mut writer = zip::ZipWriter::new(Cursor::new(Vec::new()));
+        for (name, data) in entries {
+            writer.start_file(*name, zip::write::SimpleFileOptions::default()).unwrap();
+            writer.write_all(data).unwrap();
+        }
+        writer.finish().unwrap().into_inner()
+    }
+
+    fn tar_gz(entries: &[(&str, &[u8])]) -> Vec<u8> {
+        let mut builder = tar::Builder::new(flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::fast()));
+        for (name, data) in entries {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(data.len() as u64);
+            header.set_mode(0o644);
+            // Written raw so traversal names reach the extractor unchanged
+            header.as_old_mut().name[..name.len()].copy_from_slice(name.as_bytes());
+            header.set_cksum();
+            builder.append(&header, *data).unwrap();
+        }
+        builder.into_inner().unwrap().finish().unwrap()
+    }
+
+    fn paths(extracted: &Extracted) -> Vec<String> {
+        extracted.files.iter().map(|(path, _)| path.to_string_lossy().into_owned()).collect()
+    }
+
+    #[test]
+    fn test_extracts_nested_archives() {
+        let inner = tar_gz(&[("lib/util.py", b"def f(): pass\n")]);
+        let outer = zip(&[("project/README.md", b"# Project\n"), ("./project/vendor.tar.gz", &inner)]);
+
+        let extracted = extract(&outer, &ExtractLimits::default()).unwrap();
+        assert_eq!(paths(&extracted), vec!["project/README.md", "project/vendor/lib/util.py"]);
+
+        let shallow = ExtractLimits { max_depth: 0, ..Default::default() };
+        let extracted = extract(&outer, &shallow).unwrap();
+        assert_eq!(paths(&extracted), vec!["project/README.md"]);
+        assert_eq!(extracted.skipped[0].path, "project/vendor.tar.gz");
+    }
+
+    #[test]
+    fn test_rejects_zip_slip() {
+        for name in ["../evil.sh", "docs/../../evil.sh", "/etc/cron.d/evil"] {
+            assert!(matches!(
+                extract(&zip(&[(name, b"x")]), &ExtractLimits::default()),
+                Err(HybridLLMError::SecurityViolation(_))
+            ), "zip entry {} was accepted", name);
+            assert!(matches!(
+                extract(&tar_gz(&[(name, b"x")]), &ExtractLimits::default()),
+                Err(HybridLLMError::SecurityViolation(_))
+            ), "tar entry {} was accepted", name);
+        }
+    }
+
+    #[test]
+    fn test_enforces_limits() {
+        let archive = zip(&[("a.txt", &[b'a'; 600]), ("b.txt", &[b'b'; 600])]);
+
+        let few = ExtractLimits { max_entries: 1, ..Default::default() };
+        assert!(matches!(extract(&archive, &few), Err(HybridLLMError::ResourceLimitExceeded { .. })));
+
+        // Highly compressible data can't get past the byte limit
+        let small = ExtractLimits { max_total_bytes: 1000, ..Default::default() };
+        assert!(matches!(extract(&archive, &small), Err(HybridLLMError::ResourceLimitExceeded { .. })));
+
+        assert!(extract(b"plain text", &ExtractLimits::default()).is_err());
+    }
+}
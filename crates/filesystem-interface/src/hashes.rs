@@ -0,0 +1,284 @@
+use common::errors::{Result, HybridLLMError};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::{HashMap, HashSet};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::SystemTime;
+use tracing::{debug, warn};
+
+use crate::metadata::{FileMetadata, FileQuery};
+use crate::ManagedFolder;
+
+/// Content hash of one file in a managed folder
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct FileHash {
+    pub folder: ManagedFolder,
+    /// Path relative to the folder
+    pub filename: String,
+    pub sha256: String,
+    pub size_bytes: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Entry {
+    #[serde(flatten)]
+    hash: FileHash,
+    /// Files whose size and mtime are unchanged are not rehashed
+    modified: SystemTime,
+    #[serde(default)]
+    indexed: bool,
+}
+
+impl Entry {
+    fn metadata(&self) -> FileMetadata {
+        FileMetadata {
+            folder: self.hash.folder,
+            filename: self.hash.filename.clone(),
+            size_bytes: self.hash.size_bytes,
+            modified: self.modified.into(),
+            sha256: self.hash.sha256.clone(),
+            indexed: self.indexed,
+        }
+    }
+}
+
+/// SHA-256 and metadata of every managed file, persisted so restarts don't rehash everything
+/// Files written through `FileSystemInterface` are recorded as they are written;
+/// anything written behind its back is picked up by `refresh` or a folder watcher
+pub(crate) struct HashIndex {
+    path: PathBuf,
+    entries: Mutex<HashMap<PathBuf, Entry>>,
+}
+
+impl HashIndex {
+    pub(crate) fn load(path: PathBuf) -> Self {
+        let entries = std::fs::read(&path)
+            .ok()
+            .and_then(|data| match serde_json::from_slice(&data) {
+                Ok(entries) => Some(entries),
+                Err(e) => {
+                    warn!("⚠️  Ignoring unreadable hash index {:?}: {}", path, e);
+                    None
+                }
+            })
+            .unwrap_or_default();
+
+        Self { path, entries: Mutex::new(entries) }
+    }
+
+    /// Record a file just written with `content`
+    pub(crate) fn record(&self, folder: ManagedFolder, root: &Path, path: &Path, content: &[u8]) -> Result<FileHash> {
+        self.record_hashed(folder, root, path, sha256(content), content.len() as u64)
+    }
+
+    /// Record a file just written whose hash was computed while writing it
+    pub(crate) fn record_hashed(
+        &self,
+        folder: ManagedFolder,
+        root: &Path,
+        path: &Path,
+        sha256: String,
+        size_bytes: u64,
+    ) -> Result<FileHash> {
+        let hash = FileHash {
+            folder,
+            filename: relative(root, path),
+            sha256,
+            size_bytes,
+        };
+        let modified = modified(path)?;
+
+        let mut entries = self.entries.lock().unwrap();
+        entries.insert(path.to_path_buf(), Entry { hash: hash.clone(), modified, indexed: false });
+        self.save(&entries)?;
+
+        Ok(hash)
+    }
+
+    /// Hash new and changed files under the given folders and forget deleted ones
+    pub(crate) fn refresh(&self, folders: &[(ManagedFolder, &Path)]) -> Result<()> {
+        let mut entries = self.entries.lock().unwrap();
+        let mut seen = HashSet::new();
+        let mut changed = false;
+
+        for (folder, root) in folders {
+            for file in walkdir::WalkDir::new(root)
+                .into_iter()
+                .filter_map(|e| e.ok())
+                .filter(|e| e.file_type().is_file())
+            {
+                let path = file.path().to_path_buf();
+                let Ok(metadata) = file.metadata() else {
+                    continue;
+                };
+                changed |= update_entry(&mut entries, *folder, root, &path, &metadata)? != Update::Unchanged;
+                seen.insert(path);
+            }
+        }
+
+        let before = entries.len();
+        entries.retain(|path, entry| {
+            seen.contains(path) || !folders.iter().any(|(folder, _)| *folder == entry.hash.folder)
+        });
+        if changed || entries.len() != before {
+            self.save(&entries)?;
+        }
+
+        Ok(())
+    }
+
+    /// Bring the entries under `path` in line with the disk, e.g. after a watcher event
+    /// Returns the files that are new or whose content changed
+    pub(crate) fn update(&self, folder: ManagedFolder, root: &Path, path: &Path) -> Result<Vec<PathBuf>> {
+        let mut entries = self.entries.lock().unwrap();
+        let mut updated = Vec::new();
+        let mut seen = HashSet::new();
+        let mut changed = false;
+
+        for file in walkdir::WalkDir::new(path)
+            .into_iter()
+            .filter_map(|e| e.ok())
+            .filter(|e| e.file_type().is_file())
+        {
+            let Ok(metadata) = file.metadata() else {
+                continue;
+            };
+            match update_entry(&mut entries, folder, root, file.path(), &metadata)? {
+                Update::Unchanged => {}
+                Update::Touched => changed = true,
+                Update::Changed => updated.push(file.path().to_path_buf()),
+            }
+            seen.insert(file.path().to_path_buf());
+        }
+
+        // Whatever was under `path` and is no longer there was deleted or renamed away
+        let before = entries.len();
+        entries.retain(|entry_path, _| !entry_path.starts_with(path) || seen.contains(entry_path));
+        if changed || !updated.is_empty() || entries.len() != before {
+            self.save(&entries)?;
+        }
+
+        Ok(updated)
+    }
+
+    /// Metadata of every indexed file matching `query`, sorted by folder and filename
+    pub(crate) fn query(&self, query: &FileQuery) -> Vec<FileMetadata> {
+        let mut files: Vec<_> = self
+            .entries
+            .lock()
+            .unwrap()
+            .values()
+            .map(Entry::metadata)
+            .filter(|file| query.matches(file))
+            .collect();
+        files.sort_by(|a, b| (a.folder.name(), &a.filename).cmp(&(b.folder.name(), &b.filename)));
+        files
+    }
+
+    /// Record whether a file's current content has been indexed for RAG
+    pub(crate) fn set_indexed(&self, path: &Path, indexed: bool) -> Result<()> {
+        let mut entries = self.entries.lock().unwrap();
+        let entry = entries
+            .get_mut(path)
+            .ok_or_else(|| HybridLLMError::FileSystemError(format!("No such file: {:?}", path)))?;
+        if entry.indexed != indexed {
+            entry.indexed = indexed;
+            self.save(&entries)?;
+        }
+        Ok(())
+    }
+
+    pub(crate) fn find(&self, sha256: &str) -> Vec<FileHash> {
+        let mut matches: Vec<_> = self
+            .entries
+            .lock()
+            .unwrap()
+            .values()
+            .filter(|entry| entry.hash.sha256 == sha256)
+            .map(|entry| entry.hash.clone())
+            .collect();
+        matches.sort_by(|a, b| a.filename.cmp(&b.filename));
+        matches
+    }
+
+    pub(crate) fn get(&self, path: &Path) -> Option<FileHash> {
+        self.entries.lock().unwrap().get(path).map(|entry| entry.hash.clone())
+    }
+
+    fn save(&self, entries: &HashMap<PathBuf, Entry>) -> Result<()> {
+        let data = serde_json::to_vec(entries).map_err(|e| HybridLLMError::Other(e.into()))?;
+        std::fs::write(&self.path, data).map_err(|e| HybridLLMError::FileSystemError(e.to_string()))
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum Update {
+    /// Size and mtime match the entry, so the file wasn't rehashed
+    Unchanged,
+    /// Rehashed, but the content is the same
+    Touched,
+    /// New file or new content
+    Changed,
+}
+
+/// Rehash `path` unless its size and mtime are unchanged
+fn update_entry(
+    entries: &mut HashMap<PathBuf, Entry>,
+    folder: ManagedFolder,
+    root: &Path,
+    path: &Path,
+    metadata: &std::fs::Metadata,
+) -> Result<Update> {
+    let modified = metadata.modified().map_err(|e| HybridLLMError::FileSystemError(e.to_string()))?;
+    let previous = entries.get(path);
+    if previous.is_some_and(|entry| entry.modified == modified && entry.hash.size_bytes == metadata.len()) {
+        return Ok(Update::Unchanged);
+    }
+
+    debug!("#️⃣  Hashing {:?}", path);
+    let (size_bytes, sha256) = checksum(path)?;
+    let same_content = previous.is_some_and(|entry| entry.hash.sha256 == sha256);
+    // Unchanged content keeps its indexing state through a touch
+    let indexed = same_content && previous.is_some_and(|entry| entry.indexed);
+
+    let hash = FileHash { folder, filename: relative(root, path), sha256, size_bytes };
+    entries.insert(path.to_path_buf(), Entry { hash, modified, indexed });
+    Ok(if same_content { Update::Touched } else { Update::Changed })
+}
+
+pub(crate) fn sha256(content: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(content))
+}
+
+/// Size and SHA-256 of a file, read in chunks
+fn checksum(path: &Path) -> Result<(u64, String)> {
+    let mut file = std::fs::File::open(path).map_err(|e| HybridLLMError::FileSystemError(e.to_string()))?;
+    let mut hasher = Sha256::new();
+    let mut buf = [0u8; 64 * 1024];
+    let mut size = 0u64;
+
+    loop {
+        let n = file
+            .read(&mut buf)
+            .map_err(|e| HybridLLMError::FileSystemError(e.to_string()))?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buf[..n]);
+        size += n as u64;
+    }
+
+    Ok((size, format!("{:x}", hasher.finalize())))
+}
+
+fn modified(path: &Path) -> Result<SystemTime> {
+    std::fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .map_err(|e| HybridLLMError::FileSystemError(e.to_string()))
+}
+
+fn relative(root: &Path, path: &Path) -> String {
+    path.strip_prefix(root).unwrap_or(path).to_string_lossy().into_owned()
+}
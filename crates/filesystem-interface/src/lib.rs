@@ -1,6 +1,43 @@
-use common::errors::{Result, HybridLLMError};
+mod archive;
+mod hashes;
+mod metadata;
+mod policy;
+mod quarantine;
+mod quota;
+mod sanitize;
+mod share;
+mod storage;
+mod trash;
+mod versions;
+
+pub use archive::{is_archive, ExtractLimits, ExtractionReport, SkippedEntry};
+pub use hashes::FileHash;
+pub use metadata::{FileMetadata, FileQuery};
+pub use policy::FilePolicy;
+pub use quarantine::QuarantinedFile;
+pub use quota::{FolderQuotas, FolderUsage, ManagedFolder, WARN_THRESHOLD};
+pub use sanitize::sanitize_filename;
+pub use share::{ShareLink, SharedFile, DEFAULT_SHARE_TTL, MAX_SHARE_TTL};
+pub use storage::{LocalStorage, ObjectStorage, S3Config, StorageBackend, StoredObject, S3_BUCKET_ENV, S3_PREFIX_ENV};
+pub use trash::{TrashEntry, DEFAULT_TRASH_RETENTION};
+pub use versions::FileVersion;
+
+use chrono::{DateTime, Utc};
+use common::{
+    errors::{Result, HybridLLMError},
+    traits::MalwareScanner,
+    types::MalwareScan,
+};
 use std::path::{Path, PathBuf};
-use tracing::{info, debug};
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Mutex;
+use uuid::Uuid;
+use tracing::{info, debug, warn};
+
+/// Bytes read and written at a time by `copy_upload`
+pub const UPLOAD_CHUNK_SIZE: usize = 1024 * 1024;
 
 /// File system interface for managing uploads/downloads and RAG
 pub struct FileSystemInterface {
@@ -8,6 +45,22 @@ pub struct FileSystemInterface {
     downloads_path: PathBuf,
     uploads_path: PathBuf,
     rag_path: PathBuf,
+    quotas: FolderQuotas,
+    policy: FilePolicy,
+    extract_limits: ExtractLimits,
+    malware_scanner: Option<Arc<dyn MalwareScanner>>,
+    /// Where managed files are persisted besides the local folders, which then act as a
+    /// working copy; `None` keeps files on local disk only
+    storage: Option<Arc<dyn StorageBackend>>,
+    /// Shared with folder watchers, which keep it current without rescanning
+    hashes: Arc<hashes::HashIndex>,
+    watchers: std::sync::Mutex<HashMap<ManagedFolder, notify::RecommendedWatcher>>,
+    trash: trash::Trash,
+    /// Previous contents of RAG files, kept when they are overwritten
+    rag_versions: versions::VersionStore,
+    share_signer: share::ShareSigner,
+    /// Held from quota check to write so concurrent writes cannot overshoot together
+    write_lock: Mutex<()>,
 }
 
 impl FileSystemInterface {
@@ -28,13 +81,61 @@ impl FileSystemInterface {
         info!("📁 File system interface initialized at {:?}", base_path);
 
         Ok(Self {
+            hashes: Arc::new(hashes::HashIndex::load(base_path.join("hashes.json"))),
+            watchers: std::sync::Mutex::new(HashMap::new()),
+            trash: trash::Trash::open(base_path.join("trash"), DEFAULT_TRASH_RETENTION)?,
+            rag_versions: versions::VersionStore::open(base_path.join("rag_versions"))?,
+            share_signer: share::ShareSigner::open(&base_path.join("share.key"))?,
             base_path,
             downloads_path,
             uploads_path,
             rag_path,
+            quotas: FolderQuotas::default(),
+            policy: FilePolicy::default(),
+            extract_limits: ExtractLimits::default(),
+            malware_scanner: None,
+            storage: None,
+            write_lock: Mutex::new(()),
         })
     }
 
+    /// Replace the per-folder quotas
+    pub fn with_quotas(mut self, quotas: FolderQuotas) -> Self {
+        self.quotas = quotas;
+        self
+    }
+
+    /// Replace the type and size policy applied to written files
+    pub fn with_file_policy(mut self, policy: FilePolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    /// Replace the bounds applied when unpacking uploaded archives
+    pub fn with_extract_limits(mut self, limits: ExtractLimits) -> Self {
+        self.extract_limits = limits;
+        self
+    }
+
+    /// How long deleted files stay restorable
+    pub fn with_trash_retention(mut self, retention: Duration) -> Self {
+        self.trash.set_retention(retention);
+        self
+    }
+
+    /// Scan written files for malware, quarantining positives
+    pub fn with_malware_scanner(mut self, scanner: Arc<dyn MalwareScanner>) -> Self {
+        self.malware_scanner = Some(scanner);
+        self
+    }
+
+    /// Persist managed files to a storage backend such as an S3 bucket
+    pub fn with_storage_backend(mut self, storage: Arc<dyn StorageBackend>) -> Self {
+        info!("🪣 Persisting managed files to {}", storage.describe());
+        self.storage = Some(storage);
+        self
+    }
+
     pub fn downloads_path(&self) -> &Path {
         &self.downloads_path
     }
@@ -47,42 +148,682 @@ impl FileSystemInterface {
         &self.rag_path
     }
 
-    /// Write a file to the downloads folder
-    pub async fn write_download(&self, filename: &str, content: &[u8]) -> Result<PathBuf> {
-        let path = self.downloads_path.join(filename);
+    /// Where files flagged by the malware scanner are moved; outside every managed folder
+    pub fn quarantine_path(&self) -> PathBuf {
+        self.base_path.join("quarantine")
+    }
+
+    pub fn folder_path(&self, folder: ManagedFolder) -> &Path {
+        match folder {
+            ManagedFolder::Downloads => &self.downloads_path,
+            ManagedFolder::Uploads => &self.uploads_path,
+            ManagedFolder::Rag => &self.rag_path,
+        }
+    }
+
+    /// Where `filename` lives in `folder`, after sanitizing it
+    /// Fails if the result would resolve outside the folder, e.g. through a symlink
+    pub fn managed_path(&self, folder: ManagedFolder, filename: &str) -> Result<PathBuf> {
+        let root = self.folder_path(folder);
+        let path = root.join(sanitize_filename(filename)?);
+        sanitize::ensure_contained(root, &path)?;
+        Ok(path)
+    }
+
+    /// Bytes used in one managed folder, measured on disk
+    /// Includes files written by other components, such as sandbox artifact transfers
+    pub fn folder_usage(&self, folder: ManagedFolder) -> FolderUsage {
+        FolderUsage::new(folder, quota::measure(self.folder_path(folder)), self.quotas.get(folder))
+    }
+
+    /// Bytes used in every managed folder
+    pub fn usage(&self) -> Vec<FolderUsage> {
+        ManagedFolder::ALL.into_iter().map(|folder| self.folder_usage(folder)).collect()
+    }
+
+    /// Fail with `QuotaExceeded` unless `bytes` more fit into `folder`
+    /// Callers that write into a managed folder themselves check this first
+    pub fn ensure_capacity(&self, folder: ManagedFolder, bytes: u64) -> Result<()> {
+        self.ensure_capacity_replacing(folder, bytes, 0)
+    }
+
+    /// As `ensure_capacity`, for a write that replaces `replaced` existing bytes
+    fn ensure_capacity_replacing(&self, folder: ManagedFolder, bytes: u64, replaced: u64) -> Result<()> {
+        let Some(quota_bytes) = self.quotas.get(folder) else {
+            return Ok(());
+        };
+
+        let used_bytes = self.folder_usage(folder).used_bytes.saturating_sub(replaced);
+        if used_bytes.saturating_add(bytes) > quota_bytes {
+            warn!("💽 {} quota exceeded: {} + {} > {} bytes", folder.name(), used_bytes, bytes, quota_bytes);
+            return Err(HybridLLMError::QuotaExceeded {
+                folder: folder.name().to_string(),
+                quota_bytes,
+                used_bytes,
+                requested_bytes: bytes,
+            });
+        }
+
+        Ok(())
+    }
+
+    /// Scan a file with the configured malware scanner, quarantining it if infected
+    /// Without a scanner, or when the scanner fails to run, the file is left in place
+    pub async fn scan(&self, path: &Path) -> Result<Option<QuarantinedFile>> {
+        let Some(scanner) = &self.malware_scanner else {
+            return Ok(None);
+        };
+
+        match scanner.scan_file(path).await {
+            Ok(MalwareScan::Clean) => Ok(None),
+            Ok(MalwareScan::Infected { signature }) => {
+                let quarantined = quarantine::quarantine(path, &self.quarantine_path(), signature).await?;
+                warn!("🦠 Quarantined {:?} ({}) to {:?}", path, quarantined.signature, quarantined.quarantine_path);
+                Ok(Some(quarantined))
+            }
+            Err(e) => {
+                warn!("⚠️  Malware scan of {:?} failed, leaving it unscanned: {}", path, e);
+                Ok(None)
+            }
+        }
+    }
+
+    /// Hash any managed files that changed since they were last hashed
+    /// Watched folders are already current and are not rescanned
+    pub fn refresh_hashes(&self) -> Result<()> {
+        let watchers = self.watchers.lock().unwrap();
+        let folders: Vec<_> = ManagedFolder::ALL
+            .into_iter()
+            .filter(|folder| !watchers.contains_key(folder))
+            .map(|folder| (folder, self.folder_path(folder)))
+            .collect();
+        drop(watchers);
+
+        if folders.is_empty() {
+            return Ok(());
+        }
+        self.hashes.refresh(&folders)
+    }
+
+    /// Size, mtime, hash and indexing state of the managed files matching `query`
+    pub fn list_files(&self, query: &FileQuery) -> Result<Vec<FileMetadata>> {
+        self.refresh_hashes()?;
+        Ok(self.hashes.query(query))
+    }
+
+    /// Record whether a managed file's current content has been indexed for RAG
+    /// The flag is cleared again when the content changes
+    pub fn set_indexed(&self, folder: ManagedFolder, filename: &str, indexed: bool) -> Result<()> {
+        let root = self.folder_path(folder);
+        let path = root.join(sanitize::sanitize_relative_path(filename)?);
+        if self.hashes.get(&path).is_none() {
+            self.hashes.update(folder, root, &path)?;
+        }
+        self.hashes.set_indexed(&path, indexed)
+    }
+
+    /// Managed files with the given SHA-256, across all folders
+    pub fn find_by_hash(&self, sha256: &str) -> Result<Vec<FileHash>> {
+        self.refresh_hashes()?;
+        Ok(self.hashes.find(sha256))
+    }
+
+    /// Content hash of one managed file, `None` if it doesn't exist
+    pub fn file_hash(&self, folder: ManagedFolder, filename: &str) -> Result<Option<FileHash>> {
+        self.refresh_hashes()?;
+        Ok(self.hashes.get(&self.folder_path(folder).join(filename)))
+    }
+
+    /// A file in `folder` with the same content, if there is one
+    pub fn find_duplicate(&self, folder: ManagedFolder, content: &[u8]) -> Result<Option<FileHash>> {
+        Ok(self
+            .find_by_hash(&hashes::sha256(content))?
+            .into_iter()
+            .find(|existing| existing.folder == folder))
+    }
+
+    /// Write a file into a managed folder
+    /// Refuses files the policy rejects and writes that would exceed the folder's quota,
+    /// and quarantines the file if the malware scanner flags it
+    pub async fn write_file(&self, folder: ManagedFolder, filename: &str, content: &[u8]) -> Result<PathBuf> {
+        let (path, _) = self.write(folder, filename, content, false).await?;
+        Ok(path)
+    }
+
+    async fn write(
+        &self,
+        folder: ManagedFolder,
+        filename: &str,
+        content: &[u8],
+        reject_duplicates: bool,
+    ) -> Result<(PathBuf, FileHash)> {
+        let filename = &sanitize_filename(filename)?;
+        let mime_type = self.policy.check(filename, content)?;
+        debug!("🔎 {} detected as {}", filename, mime_type);
+
+        let guard = self.write_lock.lock().await;
+        let path = self.managed_path(folder, filename)?;
+        if folder == ManagedFolder::Rag {
+            self.rag_versions.ensure_baseline(filename, &path)?;
+        }
+        if reject_duplicates {
+            if let Some(existing) = self.find_duplicate(folder, content)? {
+                return Err(HybridLLMError::DuplicateFile {
+                    filename: filename.to_string(),
+                    existing: existing.filename,
+                    sha256: existing.sha256,
+                });
+            }
+        }
+
+        let replaced = tokio::fs::metadata(&path).await.map_or(0, |m| m.len());
+        self.ensure_capacity_replacing(folder, content.len() as u64, replaced)?;
+
         tokio::fs::write(&path, content)
             .await
             .map_err(|e| HybridLLMError::FileSystemError(e.to_string()))?;
+        drop(guard);
+
+        if let Some(quarantined) = self.scan(&path).await? {
+            return Err(HybridLLMError::SecurityViolation(format!(
+                "{} contains malware ({}) and was quarantined",
+                filename, quarantined.signature
+            )));
+        }
+
+        self.persist(folder, filename, content).await?;
+        if folder == ManagedFolder::Rag {
+            self.rag_versions.record(filename, content)?;
+        }
+        let hash = self.hashes.record(folder, self.folder_path(folder), &path, content)?;
+        Ok((path, hash))
+    }
+
+    /// Write a file to the downloads folder
+    pub async fn write_download(&self, filename: &str, content: &[u8]) -> Result<PathBuf> {
+        let path = self.write_file(ManagedFolder::Downloads, filename, content).await?;
 
         info!("⬇️  Downloaded file: {:?}", path);
         Ok(path)
     }
 
-    /// Read a file from the uploads folder
-    pub async fn read_upload(&self, filename: &str) -> Result<Vec<u8>> {
-        let path = self.uploads_path.join(filename);
+    /// Write a file to the uploads folder, refusing content that was already uploaded
+    /// so the same document is never indexed twice
+    pub async fn write_upload(&self, filename: &str, content: &[u8]) -> Result<FileHash> {
+        let (path, hash) = self.write(ManagedFolder::Uploads, filename, content, true).await?;
+
+        info!("⬆️  Uploaded file: {:?}", path);
+        Ok(hash)
+    }
+
+    /// Copy a file from outside the managed folders into uploads `UPLOAD_CHUNK_SIZE` bytes at a time,
+    /// so large files never sit in memory whole; `on_chunk` gets the bytes copied so far
+    /// `filename` may be a path inside the folder, such as `papers/survey.pdf`
+    /// The copy goes through the same policy, duplicate, quota and malware checks as `write_upload`
+    pub async fn copy_upload(
+        &self,
+        source: &Path,
+        filename: &str,
+        mut on_chunk: impl FnMut(u64) + Send,
+    ) -> Result<FileHash> {
+        let folder = ManagedFolder::Uploads;
+        let relative = sanitize::sanitize_relative_path(filename)?;
+        let name = relative.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default();
+        let fs_err = |e: std::io::Error| HybridLLMError::FileSystemError(format!("{}: {}", source.display(), e));
+
+        let mut input = tokio::fs::File::open(source).await.map_err(fs_err)?;
+        let size_bytes = input.metadata().await.map_err(fs_err)?.len();
+        // Fail before copying anything when it can't fit anyway
+        self.ensure_capacity(folder, size_bytes)?;
+
+        // Staged beside the managed folders so moving it into place is a rename
+        let staging_dir = self.base_path.join("incoming");
+        tokio::fs::create_dir_all(&staging_dir).await.map_err(fs_err)?;
+        let staging = staging_dir.join(Uuid::new_v4().to_string());
+
+        let staged = self.stage(&mut input, &staging, &name, size_bytes, &mut on_chunk).await;
+        let (sha256, copied) = match staged {
+            Ok(staged) => staged,
+            Err(e) => {
+                let _ = tokio::fs::remove_file(&staging).await;
+                return Err(e);
+            }
+        };
+
+        let guard = self.write_lock.lock().await;
+        let placed = self.place_staged(folder, &staging, &relative, &sha256, copied).await;
+        drop(guard);
+        let path = match placed {
+            Ok(path) => path,
+            Err(e) => {
+                let _ = tokio::fs::remove_file(&staging).await;
+                return Err(e);
+            }
+        };
+
+        if let Some(quarantined) = self.scan(&path).await? {
+            return Err(HybridLLMError::SecurityViolation(format!(
+                "{} contains malware ({}) and was quarantined",
+                filename, quarantined.signature
+            )));
+        }
+
+        let relative = relative.to_string_lossy();
+        self.persist_file(folder, &relative).await?;
+        let hash = self.hashes.record_hashed(folder, self.folder_path(folder), &path, sha256, copied)?;
+
+        info!("⬆️  Uploaded file: {:?}", path);
+        Ok(hash)
+    }
+
+    /// Copy `input` to `staging`, hashing it on the way; the first chunk is checked against the policy
+    async fn stage(
+        &self,
+        input: &mut tokio::fs::File,
+        staging: &Path,
+        name: &str,
+        size_bytes: u64,
+        on_chunk: &mut (impl FnMut(u64) + Send),
+    ) -> Result<(String, u64)> {
+        use sha2::{Digest, Sha256};
+        use tokio::io::AsyncWriteExt;
+
+        let fs_err = |e: std::io::Error| HybridLLMError::FileSystemError(e.to_string());
+        let mut output = tokio::fs::File::create(staging).await.map_err(fs_err)?;
+        let mut hasher = Sha256::new();
+        let mut buf = vec![0u8; UPLOAD_CHUNK_SIZE];
+        let mut copied = 0u64;
+
+        loop {
+            let n = read_chunk(input, &mut buf).await.map_err(fs_err)?;
+            if copied == 0 {
+                let mime_type = self.policy.check_head(name, size_bytes, &buf[..n])?;
+                debug!("🔎 {} detected as {}", name, mime_type);
+            }
+            if n == 0 {
+                break;
+            }
+            copied += n as u64;
+            // The policy and quota were checked against the size it had when it was opened
+            if copied > size_bytes {
+                return Err(HybridLLMError::FileSystemError(format!("{} grew while it was being copied", name)));
+            }
+
+            hasher.update(&buf[..n]);
+            output.write_all(&buf[..n]).await.map_err(fs_err)?;
+            on_chunk(copied);
+        }
+        output.flush().await.map_err(fs_err)?;
+
+        Ok((format!("{:x}", hasher.finalize()), copied))
+    }
+
+    /// Move a staged upload to `relative` in `folder`; the caller holds the write lock
+    async fn place_staged(
+        &self,
+        folder: ManagedFolder,
+        staging: &Path,
+        relative: &Path,
+        sha256: &str,
+        size_bytes: u64,
+    ) -> Result<PathBuf> {
+        let fs_err = |e: std::io::Error| HybridLLMError::FileSystemError(e.to_string());
+
+        if let Some(existing) = self.find_by_hash(sha256)?.into_iter().find(|existing| existing.folder == folder) {
+            return Err(HybridLLMError::DuplicateFile {
+                filename: relative.to_string_lossy().into_owned(),
+                existing: existing.filename,
+                sha256: existing.sha256,
+            });
+        }
+
+        let root = self.folder_path(folder);
+        let path = root.join(relative);
+        if let Some(parent) = path.parent() {
+            tokio::fs::create_dir_all(parent).await.map_err(fs_err)?;
+        }
+        sanitize::ensure_contained(root, &path)?;
+
+        let replaced = tokio::fs::metadata(&path).await.map_or(0, |m| m.len());
+        self.ensure_capacity_replacing(folder, size_bytes, replaced)?;
+        tokio::fs::rename(staging, &path).await.map_err(fs_err)?;
+        Ok(path)
+    }
+
+    /// Unpack a zip, tar or tar.gz archive into a new directory of `folder` named after it
+    /// Every extracted file goes through the same policy, quota and malware checks as a
+    /// single write; files that fail them are skipped and reported rather than failing the archive
+    pub async fn extract_archive(&self, folder: ManagedFolder, archive_name: &str, content: &[u8]) -> Result<ExtractionReport> {
+        let archive_name = sanitize_filename(archive_name)?;
+        let directory = archive::strip_archive_extension(Path::new(&archive_name))
+            .to_string_lossy()
+            .into_owned();
+
+        let extracted = archive::extract(content, &self.extract_limits)?;
+        let mut skipped = extracted.skipped;
+        let mut accepted = Vec::new();
+        for (relative, data) in extracted.files {
+            let name = relative.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default();
+            match self.policy.check(&name, &data) {
+                Ok(_) => accepted.push((relative, data)),
+                Err(e) => skipped.push(SkippedEntry {
+                    path: relative.to_string_lossy().into_owned(),
+                    reason: e.to_string(),
+                }),
+            }
+        }
+
+        let fs_err = |e: std::io::Error| HybridLLMError::FileSystemError(e.to_string());
+        let guard = self.write_lock.lock().await;
+        let root = self.managed_path(folder, &directory)?;
+        if root.exists() {
+            return Err(HybridLLMError::InvalidRequest(format!(
+                "{} already exists in {}",
+                directory,
+                folder.name()
+            )));
+        }
+        let total_bytes = accepted.iter().map(|(_, data)| data.len() as u64).sum();
+        self.ensure_capacity(folder, total_bytes)?;
+
+        let mut written = Vec::with_capacity(accepted.len());
+        for (relative, data) in accepted {
+            let path = root.join(&relative);
+            if let Some(parent) = path.parent() {
+                tokio::fs::create_dir_all(parent).await.map_err(fs_err)?;
+            }
+            sanitize::ensure_contained(&root, &path)?;
+            tokio::fs::write(&path, &data).await.map_err(fs_err)?;
+            written.push((relative, path, data));
+        }
+        drop(guard);
+
+        let mut files = Vec::with_capacity(written.len());
+        for (relative, path, data) in written {
+            if let Some(quarantined) = self.scan(&path).await? {
+                skipped.push(SkippedEntry {
+                    path: relative.to_string_lossy().into_owned(),
+                    reason: format!("contains malware ({}) and was quarantined", quarantined.signature),
+                });
+                continue;
+            }
+            self.persist(folder, &relative.to_string_lossy(), &data).await?;
+            let hash = self.hashes.record(folder, self.folder_path(folder), &path, &data)?;
+            if folder == ManagedFolder::Rag {
+                self.rag_versions.record(&hash.filename, &data)?;
+            }
+            files.push(hash);
+        }
+
+        info!(
+            "📦 Extracted {} files from {} into {} ({} skipped)",
+            files.len(),
+            archive_name,
+            folder.name(),
+            skipped.len()
+        );
+        Ok(ExtractionReport { directory, files, skipped })
+    }
+
+    /// Copy written content to the storage backend, if one is configured
+    /// The local copy is kept either way, so a failed upload can be retried with `persist_file`
+    async fn persist(&self, folder: ManagedFolder, filename: &str, content: &[u8]) -> Result<()> {
+        let Some(storage) = &self.storage else {
+            return Ok(());
+        };
+        storage.put(&storage_key(folder, filename), content).await?;
+        debug!("🪣 Stored {} from {} in {}", filename, folder.name(), storage.describe());
+        Ok(())
+    }
+
+    /// Copy a file written straight into a managed folder, e.g. a sandbox artifact, to the storage backend
+    pub async fn persist_file(&self, folder: ManagedFolder, filename: &str) -> Result<()> {
+        if self.storage.is_none() {
+            return Ok(());
+        }
+        let relative = sanitize::sanitize_relative_path(filename)?;
+        let path = self.folder_path(folder).join(&relative);
+        sanitize::ensure_contained(self.folder_path(folder), &path)?;
+        let content = tokio::fs::read(&path)
+            .await
+            .map_err(|e| HybridLLMError::FileSystemError(e.to_string()))?;
+        self.persist(folder, &relative.to_string_lossy(), &content).await
+    }
+
+    /// Download files that are in the storage backend but missing locally, e.g. on a fresh node
+    /// Downloaded files are malware scanned like any other write; returns how many were fetched
+    pub async fn sync_from_storage(&self) -> Result<usize> {
+        let Some(storage) = &self.storage else {
+            return Ok(0);
+        };
+        let fs_err = |e: std::io::Error| HybridLLMError::FileSystemError(e.to_string());
+
+        let mut fetched = 0;
+        for folder in ManagedFolder::ALL {
+            let root = self.folder_path(folder);
+            for object in storage.list(folder.name()).await? {
+                let Some(relative) = object.key.strip_prefix(&format!("{}/", folder.name())) else {
+                    continue;
+                };
+                let path = root.join(sanitize::sanitize_relative_path(relative)?);
+                if path.exists() {
+                    continue;
+                }
+                let Some(content) = storage.get(&object.key).await? else {
+                    continue;
+                };
+
+                if let Some(parent) = path.parent() {
+                    tokio::fs::create_dir_all(parent).await.map_err(fs_err)?;
+                }
+                sanitize::ensure_contained(root, &path)?;
+                tokio::fs::write(&path, &content).await.map_err(fs_err)?;
+                if self.scan(&path).await?.is_none() {
+                    fetched += 1;
+                }
+            }
+        }
+
+        self.refresh_hashes()?;
+        info!("🪣 Fetched {} files from {}", fetched, storage.describe());
+        Ok(fetched)
+    }
+
+    /// Move a managed file to the trash, where it can be restored until its retention runs out
+    /// `filename` may be a path inside the folder, such as a file unpacked from an archive
+    pub async fn delete_file(&self, folder: ManagedFolder, filename: &str) -> Result<TrashEntry> {
+        let relative = sanitize::sanitize_relative_path(filename)?;
+        let filename = &relative.to_string_lossy();
+
+        let _guard = self.write_lock.lock().await;
+        let path = self.folder_path(folder).join(&relative);
+        sanitize::ensure_contained(self.folder_path(folder), &path)?;
+        if !path.exists() {
+            return Err(HybridLLMError::FileSystemError(format!("No such file: {}", filename)));
+        }
+
+        self.trash.purge(false);
+        let entry = self.trash.put(folder, filename, &path)?;
+        if let Some(storage) = &self.storage {
+            // The trash is local, so a restore puts the file back in storage
+            if let Err(e) = storage.delete(&storage_key(folder, filename)).await {
+                warn!("⚠️  Failed to delete {} from {}: {}", filename, storage.describe(), e);
+            }
+        }
+
+        info!("🗑️  Moved {} from {} to the trash", filename, folder.name());
+        Ok(entry)
+    }
+
+    /// Deleted files still in the trash, newest first
+    pub fn list_trash(&self) -> Vec<TrashEntry> {
+        self.trash.purge(false);
+        self.trash.list()
+    }
+
+    /// Put a trashed file back where it was deleted from
+    /// Fails if that name has been reused since, or the folder no longer has room
+    pub async fn restore_file(&self, id: Uuid) -> Result<(TrashEntry, PathBuf)> {
+        let _guard = self.write_lock.lock().await;
+        let entry = self
+            .trash
+            .get(id)
+            .ok_or_else(|| HybridLLMError::InvalidRequest(format!("No trash entry {}", id)))?;
+        self.ensure_capacity(entry.folder, entry.size_bytes)?;
+
+        let path = self.folder_path(entry.folder).join(&entry.filename);
+        let entry = self.trash.take(id, &path)?;
+        if self.storage.is_some() {
+            let content = tokio::fs::read(&path)
+                .await
+                .map_err(|e| HybridLLMError::FileSystemError(e.to_string()))?;
+            if let Err(e) = self.persist(entry.folder, &entry.filename, &content).await {
+                warn!("⚠️  Restored {} locally but not to storage: {}", entry.filename, e);
+            }
+        }
+
+        info!("♻️  Restored {} to {}", entry.filename, entry.folder.name());
+        Ok((entry, path))
+    }
+
+    /// Permanently delete everything in the trash, returning how many entries were removed
+    pub fn empty_trash(&self) -> usize {
+        self.trash.purge(true)
+    }
+
+    /// Version history key of a RAG file: its sanitized path inside the folder
+    fn rag_key(filename: &str) -> Result<String> {
+        Ok(sanitize::sanitize_relative_path(filename)?.to_string_lossy().into_owned())
+    }
+
+    /// Every stored version of a RAG file, oldest first
+    pub fn rag_versions(&self, filename: &str) -> Result<Vec<FileVersion>> {
+        Ok(self.rag_versions.versions(&Self::rag_key(filename)?))
+    }
+
+    /// Content of one version of a RAG file
+    pub fn read_rag_version(&self, filename: &str, version: u32) -> Result<Vec<u8>> {
+        let version = self.rag_versions.get(&Self::rag_key(filename)?, version)?;
+        self.rag_versions.read(&version)
+    }
+
+    /// The version of a RAG file that was current at `at`, with its content
+    /// `None` if the file didn't exist yet
+    pub fn read_rag_as_of(&self, filename: &str, at: DateTime<Utc>) -> Result<Option<(FileVersion, Vec<u8>)>> {
+        let Some(version) = self.rag_versions.as_of(&Self::rag_key(filename)?, at) else {
+            return Ok(None);
+        };
+        let content = self.rag_versions.read(&version)?;
+        Ok(Some((version, content)))
+    }
+
+    /// Unified diff between two versions of a RAG text file
+    pub fn diff_rag_versions(&self, filename: &str, from: u32, to: u32) -> Result<String> {
+        let filename = &Self::rag_key(filename)?;
+        let (old, new) = (self.rag_versions.get(filename, from)?, self.rag_versions.get(filename, to)?);
+        let (old_content, new_content) = (self.rag_versions.read(&old)?, self.rag_versions.read(&new)?);
+        versions::unified_diff(filename, (&old, &old_content), (&new, &new_content))
+    }
+
+    /// Signed link to a file in downloads, valid for `ttl` but never longer than `MAX_SHARE_TTL`
+    pub fn share_download(&self, filename: &str, ttl: Duration) -> Result<ShareLink> {
+        let filename = sanitize_filename(filename)?;
+        if !self.managed_path(ManagedFolder::Downloads, &filename)?.is_file() {
+            return Err(HybridLLMError::FileSystemError(format!("No such file: {}", filename)));
+        }
+
+        let ttl = chrono::Duration::from_std(ttl.min(MAX_SHARE_TTL)).map_err(|e| HybridLLMError::Other(e.into()))?;
+        let link = self.share_signer.sign(&filename, Utc::now() + ttl);
+        info!("🔗 Shared {} until {}", filename, link.expires_at);
+        Ok(link)
+    }
+
+    /// The download a share link points to
+    /// Fails if the link was tampered with, has expired or the file is gone
+    pub async fn open_share(&self, token: &str) -> Result<SharedFile> {
+        let filename = self.share_signer.verify(token, Utc::now())?;
+        let path = self.managed_path(ManagedFolder::Downloads, &filename)?;
         let content = tokio::fs::read(&path)
             .await
             .map_err(|e| HybridLLMError::FileSystemError(e.to_string()))?;
 
+        let mime_type = infer::get(&content)
+            .map_or("application/octet-stream", |kind| kind.mime_type())
+            .to_string();
+        debug!("🔗 Serving shared {}", filename);
+        Ok(SharedFile { filename, mime_type, content })
+    }
+
+    /// Read a file from the uploads folder
+    /// Falls back to the storage backend when there is no local copy, caching what it finds
+    pub async fn read_upload(&self, filename: &str) -> Result<Vec<u8>> {
+        let path = self.managed_path(ManagedFolder::Uploads, filename)?;
+        let content = match (tokio::fs::read(&path).await, &self.storage) {
+            (Ok(content), _) => content,
+            (Err(e), Some(storage)) if e.kind() == std::io::ErrorKind::NotFound => {
+                let key = storage_key(ManagedFolder::Uploads, &sanitize_filename(filename)?);
+                let content = storage
+                    .get(&key)
+                    .await?
+                    .ok_or_else(|| HybridLLMError::FileSystemError(format!("No such file: {}", filename)))?;
+                tokio::fs::write(&path, &content)
+                    .await
+                    .map_err(|e| HybridLLMError::FileSystemError(e.to_string()))?;
+                content
+            }
+            (Err(e), _) => return Err(HybridLLMError::FileSystemError(e.to_string())),
+        };
+
         info!("⬆️  Read uploaded file: {:?}", path);
         Ok(content)
     }
 
     /// List files in uploads folder
     pub fn list_uploads(&self) -> Result<Vec<PathBuf>> {
-        let mut files = Vec::new();
-
-        for entry in walkdir::WalkDir::new(&self.uploads_path)
+        Ok(self
+            .list_files(&FileQuery::folder(ManagedFolder::Uploads))?
             .into_iter()
-            .filter_map(|e| e.ok())
-        {
-            if entry.file_type().is_file() {
-                files.push(entry.path().to_path_buf());
+            .map(|file| self.uploads_path.join(file.filename))
+            .collect())
+    }
+
+    /// Keep the metadata index of `folder` current from filesystem events instead of rescanning it
+    /// `callback` gets every file that is new or whose content changed, e.g. to index it for RAG
+    pub fn watch_folder<F>(&self, folder: ManagedFolder, callback: F) -> Result<()>
+    where
+        F: Fn(PathBuf) + Send + 'static,
+    {
+        use notify::Watcher;
+
+        let watch_err = |e: notify::Error| HybridLLMError::FileSystemError(e.to_string());
+        let root = self.folder_path(folder).to_path_buf();
+        // Catch up on whatever changed while nothing was watching
+        self.hashes.refresh(&[(folder, &root)])?;
+
+        let hashes = Arc::clone(&self.hashes);
+        let events_root = root.clone();
+        let mut watcher = notify::recommended_watcher(move |event: notify::Result<notify::Event>| {
+            let event = match event {
+                Ok(event) if !matches!(event.kind, notify::EventKind::Access(_)) => event,
+                Ok(_) => return,
+                Err(e) => {
+                    warn!("⚠️  Watcher error in {:?}: {}", events_root, e);
+                    return;
+                }
+            };
+            for path in event.paths {
+                match hashes.update(folder, &events_root, &path) {
+                    Ok(changed) => changed.into_iter().for_each(&callback),
+                    Err(e) => warn!("⚠️  Failed to index {:?}: {}", path, e),
+                }
             }
-        }
+        })
+        .map_err(watch_err)?;
+        watcher.watch(&root, notify::RecursiveMode::Recursive).map_err(watch_err)?;
 
-        Ok(files)
+        self.watchers.lock().unwrap().insert(folder, watcher);
+        debug!("👀 Watching {} for changes", folder.name());
+        Ok(())
     }
 
     /// Watch uploads folder for changes (for RAG indexing)
@@ -90,8 +831,333 @@ impl FileSystemInterface {
     where
         F: Fn(PathBuf) + Send + 'static,
     {
-        // TODO: Implement file watcher using notify crate
-        debug!("👀 Watching uploads folder for changes");
-        Ok(())
+        self.watch_folder(ManagedFolder::Uploads, callback)
+    }
+}
+
+/// Fill `buf` from `input` unless it ends first; returns the bytes read
+async fn read_chunk(input: &mut tokio::fs::File, buf: &mut [u8]) -> std::io::Result<usize> {
+    use tokio::io::AsyncReadExt;
+
+    let mut filled = 0;
+    while filled < buf.len() {
+        match input.read(&mut buf[filled..]).await? {
+            0 => break,
+            n => filled += n,
+        }
+    }
+    Ok(filled)
+}
+
+/// Key of a managed file in the storage backend
+fn storage_key(folder: ManagedFolder, filename: &str) -> String {
+    format!("{}/{}", folder.name(), filename.replace('\\', "/"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Flags any file containing "EICAR"
+    struct FakeScanner;
+
+    #[async_trait::async_trait]
+    impl MalwareScanner for FakeScanner {
+        async fn scan_file(&self, path: &Path) -> Result<MalwareScan> {
+            let content = tokio::fs::read_to_string(path).await.unwrap();
+            if content.contains("EICAR") {
+                Ok(MalwareScan::Infected { signature: "Eicar-Test".to_string() })
+            } else {
+                Ok(MalwareScan::Clean)
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_quarantines_infected_writes() {
+        let base = std::env::temp_dir().join(format!("fs-scan-{}", std::process::id()));
+        let fs = FileSystemInterface::new(&base).unwrap().with_malware_scanner(Arc::new(FakeScanner));
+
+        assert!(fs.write_download("clean.txt", b"hello").await.unwrap().exists());
+        assert!(matches!(
+            fs.write_download("payload.txt", b"EICAR").await,
+            Err(HybridLLMError::SecurityViolation(_))
+        ));
+        assert!(!fs.downloads_path().join("payload.txt").exists());
+
+        let quarantined: Vec<_> = std::fs::read_dir(fs.quarantine_path()).unwrap().collect();
+        assert_eq!(quarantined.len(), 1);
+
+        let _ = std::fs::remove_dir_all(base);
+    }
+
+    #[tokio::test]
+    async fn test_detects_duplicate_uploads() {
+        let base = std::env::temp_dir().join(format!("fs-hash-{}", std::process::id()));
+        let fs = FileSystemInterface::new(&base).unwrap();
+
+        fs.write_upload("report_v1.txt", b"quarterly report").await.unwrap();
+        assert!(matches!(
+            fs.write_upload("report_final.txt", b"quarterly report").await,
+            Err(HybridLLMError::DuplicateFile { existing, .. }) if existing == "report_v1.txt"
+        ));
+        assert!(!fs.uploads_path().join("report_final.txt").exists());
+
+        // Files written behind the interface's back are hashed on lookup
+        std::fs::write(fs.downloads_path().join("copy.txt"), b"quarterly report").unwrap();
+        let sha256 = fs.file_hash(ManagedFolder::Uploads, "report_v1.txt").unwrap().unwrap().sha256;
+        let matches = fs.find_by_hash(&sha256).unwrap();
+        assert_eq!(
+            matches.iter().map(|m| (m.folder, m.filename.as_str())).collect::<Vec<_>>(),
+            vec![(ManagedFolder::Downloads, "copy.txt"), (ManagedFolder::Uploads, "report_v1.txt")]
+        );
+
+        // The index survives a restart and forgets deleted files
+        std::fs::remove_file(fs.downloads_path().join("copy.txt")).unwrap();
+        let fs = FileSystemInterface::new(&base).unwrap();
+        assert_eq!(fs.find_by_hash(&sha256).unwrap().len(), 1);
+
+        let _ = std::fs::remove_dir_all(base);
+    }
+
+    #[tokio::test]
+    async fn test_copy_upload_in_chunks() {
+        let base = std::env::temp_dir().join(format!("fs-copy-{}", std::process::id()));
+        let fs = FileSystemInterface::new(&base).unwrap();
+        let source = base.join("outside.txt");
+        let content = "line of text\n".repeat(UPLOAD_CHUNK_SIZE / 8);
+        std::fs::write(&source, &content).unwrap();
+
+        let mut progress = Vec::new();
+        let hash = fs
+            .copy_upload(&source, "papers/notes.txt", |copied| progress.push(copied))
+            .await
+            .unwrap();
+        assert_eq!(hash.filename, Path::new("papers").join("notes.txt").to_string_lossy());
+        assert_eq!(hash.sha256, hashes::sha256(content.as_bytes()));
+        assert_eq!(progress, vec![UPLOAD_CHUNK_SIZE as u64, content.len() as u64]);
+        assert_eq!(std::fs::read_to_string(fs.uploads_path().join("papers/notes.txt")).unwrap(), content);
+
+        // Same checks as a single write, and nothing is left staged
+        assert!(matches!(
+            fs.copy_upload(&source, "again.txt", |_| {}).await,
+            Err(HybridLLMError::DuplicateFile { .. })
+        ));
+        assert!(fs.copy_upload(&source, "../escape.txt", |_| {}).await.is_err());
+        assert_eq!(std::fs::read_dir(base.join("incoming")).unwrap().count(), 0);
+
+        let _ = std::fs::remove_dir_all(base);
+    }
+
+    #[tokio::test]
+    async fn test_trash_and_restore() {
+        let base = std::env::temp_dir().join(format!("fs-trash-{}", std::process::id()));
+        let fs = FileSystemInterface::new(&base).unwrap();
+
+        fs.write_upload("notes.txt", b"keep me").await.unwrap();
+        let entry = fs.delete_file(ManagedFolder::Uploads, "notes.txt").await.unwrap();
+        assert!(!fs.uploads_path().join("notes.txt").exists());
+        assert!(fs.delete_file(ManagedFolder::Uploads, "../hashes.json").await.is_err());
+
+        // The trash survives a restart
+        let fs = FileSystemInterface::new(&base).unwrap();
+        assert_eq!(fs.list_trash().iter().map(|e| e.id).collect::<Vec<_>>(), vec![entry.id]);
+
+        // A reused name blocks the restore rather than being overwritten
+        fs.write_upload("notes.txt", b"replacement").await.unwrap();
+        assert!(fs.restore_file(entry.id).await.is_err());
+        fs.delete_file(ManagedFolder::Uploads, "notes.txt").await.unwrap();
+
+        let (_, path) = fs.restore_file(entry.id).await.unwrap();
+        assert_eq!(std::fs::read(path).unwrap(), b"keep me");
+        assert_eq!(fs.list_trash().len(), 1);
+
+        let fs = fs.with_trash_retention(Duration::ZERO);
+        fs.write_upload("old.txt", b"expired").await.unwrap();
+        fs.delete_file(ManagedFolder::Uploads, "old.txt").await.unwrap();
+        assert!(fs.list_trash().iter().all(|e| e.filename == "notes.txt"));
+
+        let _ = std::fs::remove_dir_all(base);
+    }
+
+    #[tokio::test]
+    async fn test_extracts_archives_file_by_file() {
+        use std::io::Write;
+
+        let base = std::env::temp_dir().join(format!("fs-archive-{}", std::process::id()));
+        let fs = FileSystemInterface::new(&base).unwrap();
+
+        let mut elf = vec![0x7f, b'E', b'L', b'F', 2, 1, 1, 0];
+        elf.resize(64, 0);
+        let mut writer = zip::ZipWriter::new(std::io::Cursor::new(Vec::new()));
+        for (name, data) in [("src/main.rs", &b"fn main() {}\n"[..]), ("README.md", b"# Demo\n"), ("bin/tool", &elf)] {
+            writer.start_file(name, zip::write::SimpleFileOptions::default()).unwrap();
+            writer.write_all(data).unwrap();
+        }
+        let archive = writer.finish().unwrap().into_inner();
+
+        let report = fs.extract_archive(ManagedFolder::Uploads, "demo.zip", &archive).await.unwrap();
+        assert_eq!(report.directory, "demo");
+        assert_eq!(
+            report.files.iter().map(|f| f.filename.as_str()).collect::<Vec<_>>(),
+            vec!["demo/src/main.rs", "demo/README.md"]
+        );
+        // The executable is refused by the file policy without failing the rest
+        assert_eq!(report.skipped.len(), 1);
+        assert!(!fs.uploads_path().join("demo/bin/tool").exists());
+        assert_eq!(std::fs::read(fs.uploads_path().join("demo/src/main.rs")).unwrap(), b"fn main() {}\n");
+
+        // Unpacking the same archive again doesn't merge into the existing directory
+        assert!(fs.extract_archive(ManagedFolder::Uploads, "demo.zip", &archive).await.is_err());
+
+        // Unpacked files can be deleted and restored individually
+        let entry = fs.delete_file(ManagedFolder::Uploads, "demo/README.md").await.unwrap();
+        assert!(!fs.uploads_path().join("demo/README.md").exists());
+        fs.restore_file(entry.id).await.unwrap();
+        assert!(fs.uploads_path().join("demo/README.md").exists());
+
+        let _ = std::fs::remove_dir_all(base);
+    }
+
+    #[tokio::test]
+    async fn test_persists_to_storage_backend() {
+        let base = std::env::temp_dir().join(format!("fs-storage-{}", std::process::id()));
+        let storage: Arc<dyn StorageBackend> = Arc::new(LocalStorage::new(base.join("bucket")).unwrap());
+        let fs = FileSystemInterface::new(base.join("node-a")).unwrap().with_storage_backend(Arc::clone(&storage));
+
+        fs.write_upload("report.txt", b"quarterly report").await.unwrap();
+        fs.write_file(ManagedFolder::Rag, "faq.md", b"Q: hours?").await.unwrap();
+        fs.write_upload("draft.txt", b"scrap this").await.unwrap();
+        fs.delete_file(ManagedFolder::Uploads, "draft.txt").await.unwrap();
+        assert_eq!(storage.get("uploads/report.txt").await.unwrap().unwrap(), b"quarterly report");
+        assert!(storage.get("uploads/draft.txt").await.unwrap().is_none());
+
+        // A fresh node starts from what is in storage
+        let fs = FileSystemInterface::new(base.join("node-b")).unwrap().with_storage_backend(Arc::clone(&storage));
+        assert_eq!(fs.read_upload("report.txt").await.unwrap(), b"quarterly report");
+        assert_eq!(fs.sync_from_storage().await.unwrap(), 1);
+        assert_eq!(std::fs::read(fs.rag_path().join("faq.md")).unwrap(), b"Q: hours?");
+        assert!(!fs.uploads_path().join("draft.txt").exists());
+
+        let _ = std::fs::remove_dir_all(base);
+    }
+
+    #[tokio::test]
+    async fn test_metadata_index() {
+        let base = std::env::temp_dir().join(format!("fs-metadata-{}", std::process::id()));
+        let fs = FileSystemInterface::new(&base).unwrap();
+
+        fs.write_upload("notes.md", b"# Notes").await.unwrap();
+        fs.write_upload("data.CSV", b"a,b\n1,2").await.unwrap();
+        fs.write_file(ManagedFolder::Rag, "faq.md", b"Q: hours?").await.unwrap();
+
+        let markdown = fs.list_files(&FileQuery { extension: Some(".md".to_string()), ..Default::default() }).unwrap();
+        assert_eq!(
+            markdown.iter().map(|f| (f.folder, f.filename.as_str())).collect::<Vec<_>>(),
+            vec![(ManagedFolder::Rag, "faq.md"), (ManagedFolder::Uploads, "notes.md")]
+        );
+        let csv = FileQuery { extension: Some("csv".to_string()), ..FileQuery::folder(ManagedFolder::Uploads) };
+        assert_eq!(fs.list_files(&csv).unwrap()[0].size_bytes, 7);
+        let future = FileQuery { modified_after: Some(Utc::now() + chrono::Duration::hours(1)), ..Default::default() };
+        assert!(fs.list_files(&future).unwrap().is_empty());
+
+        fs.set_indexed(ManagedFolder::Uploads, "notes.md", true).unwrap();
+        let pending = FileQuery { indexed: Some(false), ..FileQuery::folder(ManagedFolder::Uploads) };
+        assert_eq!(fs.list_files(&pending).unwrap().iter().map(|f| f.filename.as_str()).collect::<Vec<_>>(), vec!["data.CSV"]);
+
+        // Once watched, changes made behind the interface's back reach the index from events
+        let (tx, rx) = std::sync::mpsc::channel();
+        fs.watch_uploads(move |path| tx.send(path).unwrap()).await.unwrap();
+        std::fs::write(fs.uploads_path().join("notes.md"), b"# Notes, revised").unwrap();
+        let changed = rx.recv_timeout(std::time::Duration::from_secs(5)).unwrap();
+        assert_eq!(changed, fs.uploads_path().join("notes.md"));
+        assert_eq!(fs.list_files(&pending).unwrap().len(), 2);
+
+        std::fs::remove_file(fs.uploads_path().join("data.CSV")).unwrap();
+        let deadline = std::time::Instant::now() + std::time::Duration::from_secs(5);
+        while fs.list_uploads().unwrap().len() != 1 && std::time::Instant::now() < deadline {
+            tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+        }
+        assert_eq!(fs.list_uploads().unwrap(), vec![fs.uploads_path().join("notes.md")]);
+
+        let _ = std::fs::remove_dir_all(base);
+    }
+
+    #[tokio::test]
+    async fn test_traversal_stays_in_folder() {
+        let base = std::env::temp_dir().join(format!("fs-traversal-{}", std::process::id()));
+        let fs = FileSystemInterface::new(&base).unwrap();
+
+        for name in ["../escape.txt", "../../escape.txt", "/tmp/escape.txt", "..\\escape.txt", "sub/../../escape.txt"] {
+            let path = fs.write_download(name, b"data").await.unwrap();
+            assert_eq!(path.parent().unwrap(), fs.downloads_path(), "{} escaped", name);
+        }
+        assert!(!base.join("escape.txt").exists());
+        assert!(fs.write_download("..", b"data").await.is_err());
+
+        // A symlink planted in the folder can't redirect writes or reads
+        std::os::unix::fs::symlink(base.join("hashes.json"), fs.uploads_path().join("link.txt")).unwrap();
+        assert!(matches!(
+            fs.write_file(ManagedFolder::Uploads, "link.txt", b"data").await,
+            Err(HybridLLMError::SecurityViolation(_))
+        ));
+        assert!(fs.read_upload("link.txt").await.is_err());
+
+        let _ = std::fs::remove_dir_all(base);
+    }
+
+    #[tokio::test]
+    async fn test_rag_files_keep_history() {
+        let base = std::env::temp_dir().join(format!("fs-versions-{}", std::process::id()));
+        let fs = FileSystemInterface::new(&base).unwrap();
+
+        // A file that existed before versioning becomes version 1 when overwritten
+        std::fs::write(fs.rag_path().join("faq.md"), "Q: hours?\nA: 9-5\n").unwrap();
+        fs.write_file(ManagedFolder::Rag, "faq.md", b"Q: hours?\nA: 8-6\n").await.unwrap();
+        let before_second = Utc::now();
+        fs.write_file(ManagedFolder::Rag, "faq.md", b"Q: hours?\nA: 8-6\n").await.unwrap();
+        fs.write_file(ManagedFolder::Rag, "faq.md", b"Q: hours?\nA: 8-6\nQ: parking?\n").await.unwrap();
+
+        // Rewriting identical content doesn't add a version
+        let versions = fs.rag_versions("faq.md").unwrap();
+        assert_eq!(versions.iter().map(|v| v.version).collect::<Vec<_>>(), vec![1, 2, 3]);
+        assert_eq!(fs.read_rag_version("faq.md", 1).unwrap(), b"Q: hours?\nA: 9-5\n");
+
+        let (as_of, content) = fs.read_rag_as_of("faq.md", before_second).unwrap().unwrap();
+        assert_eq!((as_of.version, content.as_slice()), (2, &b"Q: hours?\nA: 8-6\n"[..]));
+
+        let diff = fs.diff_rag_versions("faq.md", 1, 2).unwrap();
+        assert!(diff.contains("-A: 9-5") && diff.contains("+A: 8-6"));
+        assert!(fs.diff_rag_versions("faq.md", 1, 9).is_err());
+
+        let _ = std::fs::remove_dir_all(base);
+    }
+
+    #[tokio::test]
+    async fn test_quota_refuses_writes() {
+        let base = std::env::temp_dir().join(format!("fs-{}", std::process::id()));
+        let fs = FileSystemInterface::new(&base).unwrap().with_quotas(FolderQuotas {
+            downloads_bytes: Some(100),
+            uploads_bytes: None,
+            rag_bytes: Some(10),
+        });
+
+        fs.write_download("a.txt", &[0; 60]).await.unwrap();
+        assert!(matches!(
+            fs.write_download("b.txt", &[0; 60]).await,
+            Err(HybridLLMError::QuotaExceeded { used_bytes: 60, requested_bytes: 60, .. })
+        ));
+        // Overwriting only counts the difference
+        fs.write_download("a.txt", &[0; 95]).await.unwrap();
+
+        let downloads = fs.folder_usage(ManagedFolder::Downloads);
+        assert_eq!((downloads.used_bytes, downloads.file_count), (95, 1));
+        assert!(downloads.near_quota);
+
+        fs.write_file(ManagedFolder::Uploads, "big.bin", &[0; 1000]).await.unwrap();
+        assert!(fs.ensure_capacity(ManagedFolder::Rag, 11).is_err());
+        assert!(!fs.folder_usage(ManagedFolder::Uploads).near_quota);
+
+        let _ = std::fs::remove_dir_all(base);
     }
 }
@@ -0,0 +1,58 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+use crate::ManagedFolder;
+
+/// Cached metadata of one managed file
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct FileMetadata {
+    pub folder: ManagedFolder,
+    /// Path relative to the folder
+    pub filename: String,
+    pub size_bytes: u64,
+    pub modified: DateTime<Utc>,
+    pub sha256: String,
+    /// Whether the current content has been indexed for RAG; reset whenever it changes
+    pub indexed: bool,
+}
+
+impl FileMetadata {
+    /// Lowercased extension without the dot
+    pub fn extension(&self) -> Option<String> {
+        Path::new(&self.filename)
+            .extension()
+            .map(|ext| ext.to_string_lossy().to_lowercase())
+    }
+}
+
+/// Filters for listing managed files; unset fields match everything
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct FileQuery {
+    pub folder: Option<ManagedFolder>,
+    /// Matched case-insensitively, with or without the leading dot
+    pub extension: Option<String>,
+    pub modified_after: Option<DateTime<Utc>>,
+    pub modified_before: Option<DateTime<Utc>>,
+    pub indexed: Option<bool>,
+}
+
+impl FileQuery {
+    /// Every file in one folder
+    pub fn folder(folder: ManagedFolder) -> Self {
+        Self { folder: Some(folder), ..Default::default() }
+    }
+
+    pub fn matches(&self, file: &FileMetadata) -> bool {
+        let extension = self
+            .extension
+            .as_deref()
+            .map(|ext| ext.trim_start_matches('.').to_lowercase());
+
+        self.folder.is_none_or(|folder| folder == file.folder)
+            && extension.is_none_or(|ext| file.extension().as_deref() == Some(ext.as_str()))
+            && self.modified_after.is_none_or(|after| file.modified >= after)
+            && self.modified_before.is_none_or(|before| file.modified < before)
+            && self.indexed.is_none_or(|indexed| indexed == file.indexed)
+    }
+}
@@ -0,0 +1,142 @@
+use common::errors::{Result, HybridLLMError};
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+const MB: u64 = 1024 * 1024;
+
+/// How much of a file is inspected to tell its type, and text from binary
+pub(crate) const SNIFF_BYTES: usize = 8192;
+
+/// Which files may be written into a managed folder
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FilePolicy {
+    /// Files larger than this are rejected
+    pub max_size_bytes: u64,
+    /// Detected MIME types that are accepted; empty accepts anything not blocked
+    pub allowed_mime_types: Vec<String>,
+    /// Detected MIME types that are always rejected, whatever the file is named
+    pub blocked_mime_types: Vec<String>,
+    /// Reject files whose content is a different known type than their extension claims
+    pub reject_mismatched_extension: bool,
+}
+
+impl Default for FilePolicy {
+    fn default() -> Self {
+        Self {
+            max_size_bytes: 100 * MB,
+            allowed_mime_types: vec![],
+            blocked_mime_types: vec![
+                "application/x-executable".to_string(),
+                "application/vnd.microsoft.portable-executable".to_string(),
+                "application/x-mach-binary".to_string(),
+                "application/vnd.android.dex".to_string(),
+                "application/x-msdownload".to_string(),
+            ],
+            reject_mismatched_extension: true,
+        }
+    }
+}
+
+impl FilePolicy {
+    /// Check a file before it is written, returning its detected MIME type
+    /// Size is checked first so oversized content is never sniffed
+    pub fn check(&self, filename: &str, content: &[u8]) -> Result<String> {
+        self.check_head(filename, content.len() as u64, content)
+    }
+
+    /// As `check`, for a file of `size_bytes` of which only the first bytes are at hand
+    /// `head` should hold the first `SNIFF_BYTES` (8 KiB), or the whole file if it is shorter
+    pub fn check_head(&self, filename: &str, size_bytes: u64, head: &[u8]) -> Result<String> {
+        let reject = |mime_type: &str, reason: String| {
+            warn!("🚫 Rejected {} ({}): {}", filename, mime_type, reason);
+            Err(HybridLLMError::FileRejected {
+                filename: filename.to_string(),
+                mime_type: mime_type.to_string(),
+                reason,
+            })
+        };
+
+        if size_bytes > self.max_size_bytes {
+            return reject(
+                "unknown",
+                format!("{} bytes exceeds the {} byte limit", size_bytes, self.max_size_bytes),
+            );
+        }
+
+        let detected = infer::get(head);
+        let mime_type = match detected {
+            Some(kind) => kind.mime_type(),
+            None if is_text(head) => "text/plain",
+            None => "application/octet-stream",
+        };
+
+        if self.blocked_mime_types.iter().any(|blocked| blocked == mime_type) {
+            return reject(mime_type, "file type is not allowed".to_string());
+        }
+
+        if !self.allowed_mime_types.is_empty() && !self.allowed_mime_types.iter().any(|allowed| allowed == mime_type) {
+            return reject(mime_type, "file type is not in the allowed list".to_string());
+        }
+
+        if let (true, Some(kind), Some(extension)) = (self.reject_mismatched_extension, detected, extension(filename)) {
+            if !extension_matches(&extension, kind.extension()) {
+                return reject(mime_type, format!("content does not match the .{} extension", extension));
+            }
+        }
+
+        Ok(mime_type.to_string())
+    }
+}
+
+fn extension(filename: &str) -> Option<String> {
+    std::path::Path::new(filename)
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(|e| e.to_lowercase())
+}
+
+fn extension_matches(extension: &str, detected: &str) -> bool {
+    extension == detected || matches!((extension, detected), ("jpeg", "jpg") | ("tiff", "tif") | ("htm", "html"))
+}
+
+/// Text has no NUL bytes in its first block
+fn is_text(content: &[u8]) -> bool {
+    !content[..content.len().min(SNIFF_BYTES)].contains(&0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PNG: &[u8] = b"\x89PNG\r\n\x1a\n\x00\x00\x00\x0dIHDR";
+
+    #[test]
+    fn test_rejects_disguised_and_oversized_files() {
+        let policy = FilePolicy { max_size_bytes: 64, ..Default::default() };
+        let mut elf = b"\x7fELF\x02\x01\x01".to_vec();
+        elf.resize(64, 0);
+
+        assert_eq!(policy.check("notes.md", b"# Notes\n").unwrap(), "text/plain");
+        assert_eq!(policy.check("chart.png", PNG).unwrap(), "image/png");
+
+        // An executable is rejected whatever it is called
+        assert!(matches!(
+            policy.check("report.pdf", &elf),
+            Err(HybridLLMError::FileRejected { mime_type, .. }) if mime_type == "application/x-executable"
+        ));
+        assert!(policy.check("chart.jpg", PNG).is_err());
+        assert!(policy.check("big.txt", &[b'a'; 65]).is_err());
+
+        let text_only = FilePolicy { allowed_mime_types: vec!["text/plain".to_string()], ..policy };
+        assert!(text_only.check("chart.png", PNG).is_err());
+        assert!(text_only.check("notes.txt", b"hello").is_ok());
+    }
+
+    #[test]
+    fn test_check_head() {
+        let policy = FilePolicy { max_size_bytes: 64, ..Default::default() };
+        assert_eq!(policy.check_head("chart.png", 64, PNG).unwrap(), "image/png");
+        // The declared size counts, not the bytes seen
+        assert!(policy.check_head("chart.png", 65, PNG).is_err());
+    }
+}
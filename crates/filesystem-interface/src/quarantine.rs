@@ -0,0 +1,37 @@
+use common::errors::{Result, HybridLLMError};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// A file moved out of a managed folder after a malware scan flagged it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct QuarantinedFile {
+    pub original_path: PathBuf,
+    pub quarantine_path: PathBuf,
+    pub signature: String,
+}
+
+/// Move `path` into `quarantine_dir`, out of reach of RAG indexing and sandboxes
+/// Names are prefixed with the time so repeated quarantines of one name never collide
+pub(crate) async fn quarantine(path: &Path, quarantine_dir: &Path, signature: String) -> Result<QuarantinedFile> {
+    let fs_err = |e: std::io::Error| HybridLLMError::FileSystemError(e.to_string());
+
+    let filename = path
+        .file_name()
+        .ok_or_else(|| HybridLLMError::FileSystemError(format!("Not a file: {:?}", path)))?;
+    let stamp = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_nanos();
+    let quarantine_path = quarantine_dir.join(format!("{}-{}", stamp, filename.to_string_lossy()));
+
+    tokio::fs::create_dir_all(quarantine_dir).await.map_err(fs_err)?;
+    if tokio::fs::rename(path, &quarantine_path).await.is_err() {
+        // Different filesystem
+        tokio::fs::copy(path, &quarantine_path).await.map_err(fs_err)?;
+        tokio::fs::remove_file(path).await.map_err(fs_err)?;
+    }
+
+    Ok(QuarantinedFile {
+        original_path: path.to_path_buf(),
+        quarantine_path,
+        signature,
+    })
+}
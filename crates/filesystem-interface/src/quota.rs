@@ -0,0 +1,89 @@
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+const GB: u64 = 1024 * 1024 * 1024;
+
+/// Share of a quota in use at which the UI should start warning
+pub const WARN_THRESHOLD: f64 = 0.9;
+
+/// Folders whose disk usage is tracked and capped
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum ManagedFolder {
+    Downloads,
+    Uploads,
+    Rag,
+}
+
+impl ManagedFolder {
+    pub const ALL: [ManagedFolder; 3] = [ManagedFolder::Downloads, ManagedFolder::Uploads, ManagedFolder::Rag];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            ManagedFolder::Downloads => "downloads",
+            ManagedFolder::Uploads => "uploads",
+            ManagedFolder::Rag => "rag",
+        }
+    }
+}
+
+/// Maximum bytes each managed folder may hold; `None` is unlimited
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FolderQuotas {
+    pub downloads_bytes: Option<u64>,
+    pub uploads_bytes: Option<u64>,
+    pub rag_bytes: Option<u64>,
+}
+
+impl Default for FolderQuotas {
+    fn default() -> Self {
+        Self {
+            downloads_bytes: Some(10 * GB),
+            uploads_bytes: Some(10 * GB),
+            rag_bytes: Some(20 * GB),
+        }
+    }
+}
+
+impl FolderQuotas {
+    pub fn get(&self, folder: ManagedFolder) -> Option<u64> {
+        match folder {
+            ManagedFolder::Downloads => self.downloads_bytes,
+            ManagedFolder::Uploads => self.uploads_bytes,
+            ManagedFolder::Rag => self.rag_bytes,
+        }
+    }
+}
+
+/// Disk usage of one managed folder
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FolderUsage {
+    pub folder: ManagedFolder,
+    pub used_bytes: u64,
+    pub quota_bytes: Option<u64>,
+    pub file_count: usize,
+    /// At or above `WARN_THRESHOLD` of the quota
+    pub near_quota: bool,
+}
+
+impl FolderUsage {
+    pub(crate) fn new(folder: ManagedFolder, (used_bytes, file_count): (u64, usize), quota_bytes: Option<u64>) -> Self {
+        Self {
+            folder,
+            used_bytes,
+            quota_bytes,
+            file_count,
+            near_quota: quota_bytes.is_some_and(|quota| used_bytes as f64 >= quota as f64 * WARN_THRESHOLD),
+        }
+    }
+}
+
+/// Total size and number of files under `path`
+pub(crate) fn measure(path: &Path) -> (u64, usize) {
+    walkdir::WalkDir::new(path)
+        .into_iter()
+        .filter_map(|e| e.ok())
+        .filter(|e| e.file_type().is_file())
+        .filter_map(|e| e.metadata().ok())
+        .fold((0, 0), |(bytes, count), metadata| (bytes + metadata.len(), count + 1))
+}
@@ -0,0 +1,118 @@
+use common::errors::{Result, HybridLLMError};
+use std::path::{Component, Path, PathBuf};
+
+/// Longest filename most filesystems accept, in bytes
+const MAX_FILENAME_BYTES: usize = 255;
+
+/// Turn a user- or LLM-provided name into a single safe path component
+/// Path separators become `_`, control characters are dropped and leading dots are
+/// stripped, so `..`, `../x` and hidden dotfiles can never be produced
+pub fn sanitize_filename(name: &str) -> Result<String> {
+    let cleaned: String = name
+        .chars()
+        .filter(|c| !c.is_control())
+        .map(|c| if matches!(c, '/' | '\\') { '_' } else { c })
+        .collect();
+    let cleaned = cleaned.trim().trim_start_matches('.').trim_end_matches(['.', ' ']);
+
+    if cleaned.is_empty() || cleaned.chars().all(|c| c == '_') {
+        return Err(HybridLLMError::InvalidRequest(format!("Invalid filename: {:?}", name)));
+    }
+    if cleaned.len() > MAX_FILENAME_BYTES {
+        return Err(HybridLLMError::InvalidRequest(format!(
+            "Filename is longer than {} bytes",
+            MAX_FILENAME_BYTES
+        )));
+    }
+
+    Ok(cleaned.to_string())
+}
+
+/// Sanitize each component of a relative path such as `project/docs/guide.md`
+/// Absolute paths and `..` components are refused rather than rewritten
+pub(crate) fn sanitize_relative_path(path: &str) -> Result<PathBuf> {
+    let mut sanitized = PathBuf::new();
+    for component in Path::new(path).components() {
+        match component {
+            Component::Normal(part) => sanitized.push(sanitize_filename(&part.to_string_lossy())?),
+            Component::CurDir => {}
+            Component::ParentDir | Component::RootDir | Component::Prefix(_) => {
+                return Err(HybridLLMError::SecurityViolation(format!("Path escapes its folder: {:?}", path)));
+            }
+        }
+    }
+
+    if sanitized.as_os_str().is_empty() {
+        return Err(HybridLLMError::InvalidRequest(format!("Invalid filename: {:?}", path)));
+    }
+    Ok(sanitized)
+}
+
+/// Fail unless `path`, with any symlinks resolved, stays inside `root`
+/// Paths that don't exist yet are checked through their parent directory
+pub(crate) fn ensure_contained(root: &Path, path: &Path) -> Result<()> {
+    let canonical = |p: &Path| p.canonicalize().map_err(|e| HybridLLMError::FileSystemError(e.to_string()));
+
+    let root = canonical(root)?;
+    let resolved = if path.symlink_metadata().is_ok() {
+        canonical(path)?
+    } else {
+        let parent = path.parent().ok_or_else(|| {
+            HybridLLMError::SecurityViolation(format!("Path has no parent: {:?}", path))
+        })?;
+        canonical(parent)?
+    };
+
+    if !resolved.starts_with(&root) {
+        return Err(HybridLLMError::SecurityViolation(format!(
+            "Path resolves outside {:?}: {:?}",
+            root, path
+        )));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sanitize_filename() {
+        assert_eq!(sanitize_filename("report.pdf").unwrap(), "report.pdf");
+        assert_eq!(sanitize_filename("../../etc/passwd").unwrap(), "_.._etc_passwd");
+        assert_eq!(sanitize_filename("..\\..\\boot.ini").unwrap(), "_.._boot.ini");
+        assert_eq!(sanitize_filename("/etc/shadow").unwrap(), "_etc_shadow");
+        assert_eq!(sanitize_filename(".bashrc").unwrap(), "bashrc");
+        assert_eq!(sanitize_filename("evil\0name\n.txt").unwrap(), "evilname.txt");
+        assert_eq!(sanitize_filename("trailing. . ").unwrap(), "trailing");
+
+        for name in ["", "..", ".", "/", " ../ ", "\u{7}"] {
+            assert!(sanitize_filename(name).is_err(), "{:?} should be rejected", name);
+        }
+        assert!(sanitize_filename(&"a".repeat(300)).is_err());
+
+        assert_eq!(sanitize_relative_path("./docs/.env").unwrap(), Path::new("docs/env"));
+        for path in ["../x", "docs/../../x", "/etc/passwd", "./"] {
+            assert!(sanitize_relative_path(path).is_err(), "{:?} should be rejected", path);
+        }
+    }
+
+    #[test]
+    fn test_symlinks_cannot_escape() {
+        let base = std::env::temp_dir().join(format!("sanitize-{}", uuid::Uuid::new_v4()));
+        let (root, outside) = (base.join("root"), base.join("outside"));
+        std::fs::create_dir_all(&root).unwrap();
+        std::fs::create_dir_all(&outside).unwrap();
+        std::os::unix::fs::symlink(&outside, root.join("link")).unwrap();
+        std::os::unix::fs::symlink(outside.join("secret"), root.join("dangling")).unwrap();
+
+        assert!(ensure_contained(&root, &root.join("new.txt")).is_ok());
+        assert!(ensure_contained(&root, &root.join("link")).is_err());
+        assert!(ensure_contained(&root, &root.join("dangling")).is_err());
+        assert!(ensure_contained(&root, &root.join("link").join("x")).is_err());
+        assert!(ensure_contained(&root, &root.join("..").join("outside").join("x")).is_err());
+
+        let _ = std::fs::remove_dir_all(base);
+    }
+}
@@ -0,0 +1,156 @@
+use chrono::{DateTime, Utc};
+use common::errors::{Result, HybridLLMError};
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use std::path::Path;
+use std::time::Duration;
+use tracing::warn;
+
+/// How long a share link stays valid unless asked otherwise
+pub const DEFAULT_SHARE_TTL: Duration = Duration::from_secs(60 * 60);
+
+/// Longest a share link may stay valid
+pub const MAX_SHARE_TTL: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+
+/// A link that lets anyone holding it fetch one file from downloads until it expires
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ShareLink {
+    pub filename: String,
+    /// Opaque, URL-safe; carries the filename, expiry and signature
+    pub token: String,
+    pub expires_at: DateTime<Utc>,
+}
+
+/// A shared file, as served to whoever opened the link
+#[derive(Debug, Clone)]
+pub struct SharedFile {
+    pub filename: String,
+    pub mime_type: String,
+    pub content: Vec<u8>,
+}
+
+/// Signs share tokens with a key kept next to the managed folders, so links survive restarts
+pub(crate) struct ShareSigner {
+    key: Vec<u8>,
+}
+
+impl ShareSigner {
+    /// Load the signing key, creating it on first use
+    pub(crate) fn open(path: &Path) -> Result<Self> {
+        if let Ok(key) = std::fs::read(path) {
+            if key.len() >= 32 {
+                return Ok(Self { key });
+            }
+            warn!("⚠️  Share signing key {:?} is too short, replacing it", path);
+        }
+
+        let key: Vec<u8> = (0..2).flat_map(|_| uuid::Uuid::new_v4().into_bytes()).collect();
+        write_private(path, &key)?;
+        Ok(Self { key })
+    }
+
+    pub(crate) fn sign(&self, filename: &str, expires_at: DateTime<Utc>) -> ShareLink {
+        let expires = expires_at.timestamp();
+        let signature = hex(&self.mac(filename, expires).finalize().into_bytes());
+        ShareLink {
+            filename: filename.to_string(),
+            token: format!("{}.{}.{}", hex(filename.as_bytes()), expires, signature),
+            expires_at: DateTime::from_timestamp(expires, 0).unwrap_or(expires_at),
+        }
+    }
+
+    /// The filename a token grants access to, if it is genuine and not expired
+    pub(crate) fn verify(&self, token: &str, now: DateTime<Utc>) -> Result<String> {
+        let invalid = || HybridLLMError::PermissionDenied("Invalid share link".to_string());
+
+        let mut parts = token.split('.');
+        let (Some(filename), Some(expires), Some(signature), None) =
+            (parts.next(), parts.next(), parts.next(), parts.next())
+        else {
+            return Err(invalid());
+        };
+        let filename = unhex(filename).and_then(|bytes| String::from_utf8(bytes).ok()).ok_or_else(invalid)?;
+        let expires: i64 = expires.parse().map_err(|_| invalid())?;
+        let signature = unhex(signature).ok_or_else(invalid)?;
+
+        self.mac(&filename, expires).verify_slice(&signature).map_err(|_| invalid())?;
+        if now.timestamp() >= expires {
+            return Err(HybridLLMError::PermissionDenied("Share link has expired".to_string()));
+        }
+
+        Ok(filename)
+    }
+
+    fn mac(&self, filename: &str, expires: i64) -> Hmac<Sha256> {
+        let mut mac = Hmac::<Sha256>::new_from_slice(&self.key).expect("HMAC accepts any key length");
+        mac.update(filename.as_bytes());
+        mac.update(&[0]);
+        mac.update(expires.to_string().as_bytes());
+        mac
+    }
+}
+
+#[cfg(unix)]
+fn write_private(path: &Path, content: &[u8]) -> Result<()> {
+    use std::io::Write;
+    use std::os::unix::fs::OpenOptionsExt;
+
+    std::fs::OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .mode(0o600)
+        .open(path)
+        .and_then(|mut file| file.write_all(content))
+        .map_err(|e| HybridLLMError::FileSystemError(e.to_string()))
+}
+
+#[cfg(not(unix))]
+fn write_private(path: &Path, content: &[u8]) -> Result<()> {
+    std::fs::write(path, content).map_err(|e| HybridLLMError::FileSystemError(e.to_string()))
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn unhex(text: &str) -> Option<Vec<u8>> {
+    if !text.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..text.len())
+        .step_by(2)
+        .map(|i| text.get(i..i + 2).and_then(|byte| u8::from_str_radix(byte, 16).ok()))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_share_tokens() {
+        let path = std::env::temp_dir().join(format!("share-{}.key", uuid::Uuid::new_v4()));
+        let signer = ShareSigner::open(&path).unwrap();
+        let now = Utc::now();
+
+        let link = signer.sign("report final.pdf", now + chrono::Duration::minutes(5));
+        assert_eq!(signer.verify(&link.token, now).unwrap(), "report final.pdf");
+        assert!(link.token.chars().all(|c| c.is_ascii_alphanumeric() || c == '.'));
+
+        // Links outlive a restart, but not their expiry
+        let signer = ShareSigner::open(&path).unwrap();
+        assert!(signer.verify(&link.token, now).is_ok());
+        assert!(signer.verify(&link.token, now + chrono::Duration::minutes(6)).is_err());
+
+        // Pointing a token at another file or extending it breaks the signature
+        let (_, rest) = link.token.split_once('.').unwrap();
+        assert!(signer.verify(&format!("{}.{}", hex(b"secrets.txt"), rest), now).is_err());
+        let forged = link.token.replacen(&link.expires_at.timestamp().to_string(), "9999999999", 1);
+        assert!(signer.verify(&forged, now).is_err());
+        assert!(signer.verify("not-a-token", now).is_err());
+
+        let _ = std::fs::remove_file(path);
+    }
+}
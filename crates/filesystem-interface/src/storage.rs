@@ -0,0 +1,272 @@
+use async_trait::async_trait;
+use common::errors::{Result, HybridLLMError};
+use futures::TryStreamExt;
+use object_store::{path::Path as ObjectPath, ObjectStore, PutPayload};
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use crate::sanitize::sanitize_relative_path;
+
+/// Environment variable naming the bucket the headless orchestrator stores files in
+pub const S3_BUCKET_ENV: &str = "HYBRID_LLM_S3_BUCKET";
+
+/// Environment variable with an optional key prefix inside that bucket
+pub const S3_PREFIX_ENV: &str = "HYBRID_LLM_S3_PREFIX";
+
+/// An object held by a storage backend
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct StoredObject {
+    /// `<folder>/<path inside the folder>`, e.g. `uploads/project/README.md`
+    pub key: String,
+    pub size_bytes: u64,
+}
+
+/// Where managed files are persisted
+/// Keys are `/`-separated paths that have already been sanitized by `FileSystemInterface`
+#[async_trait]
+pub trait StorageBackend: Send + Sync {
+    /// Where the backend stores files, for logs
+    fn describe(&self) -> String;
+
+    async fn put(&self, key: &str, content: &[u8]) -> Result<()>;
+
+    /// `None` if there is no object under `key`
+    async fn get(&self, key: &str) -> Result<Option<Vec<u8>>>;
+
+    /// Deleting a missing object is not an error
+    async fn delete(&self, key: &str) -> Result<()>;
+
+    /// Every object whose key starts with `prefix/`
+    async fn list(&self, prefix: &str) -> Result<Vec<StoredObject>>;
+}
+
+/// Files kept in a directory, e.g. a mounted network share
+pub struct LocalStorage {
+    root: PathBuf,
+}
+
+impl LocalStorage {
+    pub fn new(root: impl Into<PathBuf>) -> Result<Self> {
+        let root = root.into();
+        std::fs::create_dir_all(&root)
+            .map_err(|e| HybridLLMError::FileSystemError(e.to_string()))?;
+        Ok(Self { root })
+    }
+
+    fn path(&self, key: &str) -> Result<PathBuf> {
+        Ok(self.root.join(sanitize_relative_path(key)?))
+    }
+}
+
+#[async_trait]
+impl StorageBackend for LocalStorage {
+    fn describe(&self) -> String {
+        self.root.display().to_string()
+    }
+
+    async fn put(&self, key: &str, content: &[u8]) -> Result<()> {
+        let fs_err = |e: std::io::Error| HybridLLMError::FileSystemError(e.to_string());
+        let path = self.path(key)?;
+        if let Some(parent) = path.parent() {
+            tokio::fs::create_dir_all(parent).await.map_err(fs_err)?;
+        }
+        tokio::fs::write(&path, content).await.map_err(fs_err)
+    }
+
+    async fn get(&self, key: &str) -> Result<Option<Vec<u8>>> {
+        match tokio::fs::read(self.path(key)?).await {
+            Ok(content) => Ok(Some(content)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(HybridLLMError::FileSystemError(e.to_string())),
+        }
+    }
+
+    async fn delete(&self, key: &str) -> Result<()> {
+        match tokio::fs::remove_file(self.path(key)?).await {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+                Err(HybridLLMError::FileSystemError(e.to_string()))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    async fn list(&self, prefix: &str) -> Result<Vec<StoredObject>> {
+        let root = self.root.clone();
+        let dir = self.path(prefix)?;
+        tokio::task::spawn_blocking(move || {
+            walkdir::WalkDir::new(&dir)
+                .into_iter()
+                .filter_map(|e| e.ok())
+                .filter(|e| e.file_type().is_file())
+                .filter_map(|e| {
+                    let key = e.path().strip_prefix(&root).ok()?.to_string_lossy().replace('\\', "/");
+                    Some(StoredObject { key, size_bytes: e.metadata().ok()?.len() })
+                })
+                .collect()
+        })
+        .await
+        .map_err(|e| HybridLLMError::Other(e.into()))
+    }
+}
+
+/// Connection settings for an S3-compatible bucket
+/// Credentials left unset are taken from the usual `AWS_*` environment variables
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct S3Config {
+    pub bucket: String,
+    pub region: Option<String>,
+    /// For S3-compatible services such as MinIO or R2
+    pub endpoint: Option<String>,
+    /// Key prefix, so several deployments can share a bucket
+    pub prefix: Option<String>,
+    pub access_key_id: Option<String>,
+    pub secret_access_key: Option<String>,
+    /// Allow a plain-HTTP endpoint, e.g. a local MinIO
+    pub allow_http: bool,
+}
+
+impl S3Config {
+    /// Settings from `HYBRID_LLM_S3_BUCKET` and `HYBRID_LLM_S3_PREFIX`; `None` when no bucket is set
+    pub fn from_env() -> Option<Self> {
+        let bucket = std::env::var(S3_BUCKET_ENV).ok().filter(|b| !b.is_empty())?;
+        Some(Self {
+            bucket,
+            prefix: std::env::var(S3_PREFIX_ENV).ok().filter(|p| !p.is_empty()),
+            ..Default::default()
+        })
+    }
+}
+
+/// Files kept in an object store such as an S3 bucket
+pub struct ObjectStorage {
+    store: Arc<dyn ObjectStore>,
+    prefix: Option<String>,
+    description: String,
+}
+
+impl ObjectStorage {
+    pub fn new(store: Arc<dyn ObjectStore>, description: impl Into<String>) -> Self {
+        Self { store, prefix: None, description: description.into() }
+    }
+
+    /// Keep every key under `prefix`, so several deployments can share a bucket
+    pub fn with_prefix(mut self, prefix: &str) -> Self {
+        let prefix = prefix.trim_matches('/');
+        if !prefix.is_empty() {
+            self.description = format!("{}/{}", self.description, prefix);
+            self.prefix = Some(prefix.to_string());
+        }
+        self
+    }
+
+    /// Connect to an S3-compatible bucket
+    pub fn s3(config: &S3Config) -> Result<Self> {
+        let mut builder = object_store::aws::AmazonS3Builder::from_env().with_bucket_name(&config.bucket);
+        if config.allow_http {
+            builder = builder.with_allow_http(true);
+        }
+        if let Some(region) = &config.region {
+            builder = builder.with_region(region);
+        }
+        if let Some(endpoint) = &config.endpoint {
+            builder = builder.with_endpoint(endpoint);
+        }
+        if let Some(access_key_id) = &config.access_key_id {
+            builder = builder.with_access_key_id(access_key_id);
+        }
+        if let Some(secret_access_key) = &config.secret_access_key {
+            builder = builder.with_secret_access_key(secret_access_key);
+        }
+        let store = builder.build().map_err(storage_err)?;
+
+        let storage = Self::new(Arc::new(store), format!("s3://{}", config.bucket));
+        Ok(match &config.prefix {
+            Some(prefix) => storage.with_prefix(prefix),
+            None => storage,
+        })
+    }
+
+    fn location(&self, key: &str) -> ObjectPath {
+        match &self.prefix {
+            Some(prefix) => ObjectPath::from(format!("{}/{}", prefix, key)),
+            None => ObjectPath::from(key),
+        }
+    }
+
+    fn key(&self, location: &ObjectPath) -> String {
+        let location = location.as_ref();
+        match &self.prefix {
+            Some(prefix) => location.strip_prefix(prefix.as_str()).unwrap_or(location).trim_start_matches('/').to_string(),
+            None => location.to_string(),
+        }
+    }
+}
+
+fn storage_err(e: object_store::Error) -> HybridLLMError {
+    HybridLLMError::FileSystemError(format!("Object storage: {}", e))
+}
+
+#[async_trait]
+impl StorageBackend for ObjectStorage {
+    fn describe(&self) -> String {
+        self.description.clone()
+    }
+
+    async fn put(&self, key: &str, content: &[u8]) -> Result<()> {
+        self.store
+            .put(&self.location(key), PutPayload::from(content.to_vec()))
+            .await
+            .map_err(storage_err)?;
+        Ok(())
+    }
+
+    async fn get(&self, key: &str) -> Result<Option<Vec<u8>>> {
+        match self.store.get(&self.location(key)).await {
+            Ok(result) => Ok(Some(result.bytes().await.map_err(storage_err)?.to_vec())),
+            Err(object_store::Error::NotFound { .. }) => Ok(None),
+            Err(e) => Err(storage_err(e)),
+        }
+    }
+
+    async fn delete(&self, key: &str) -> Result<()> {
+        match self.store.delete(&self.location(key)).await {
+            Ok(()) | Err(object_store::Error::NotFound { .. }) => Ok(()),
+            Err(e) => Err(storage_err(e)),
+        }
+    }
+
+    async fn list(&self, prefix: &str) -> Result<Vec<StoredObject>> {
+        self.store
+            .list(Some(&self.location(prefix)))
+            .map_ok(|meta| StoredObject { key: self.key(&meta.location), size_bytes: meta.size as u64 })
+            .try_collect()
+            .await
+            .map_err(storage_err)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_object_storage_prefix() {
+        let store: Arc<dyn ObjectStore> = Arc::new(object_store::memory::InMemory::new());
+        let storage = ObjectStorage::new(Arc::clone(&store), "memory").with_prefix("/team-a/");
+
+        storage.put("uploads/docs/a.md", b"# A").await.unwrap();
+        storage.put("rag/b.md", b"# B").await.unwrap();
+        assert!(store.head(&ObjectPath::from("team-a/uploads/docs/a.md")).await.is_ok());
+
+        assert_eq!(
+            storage.list("uploads").await.unwrap(),
+            vec![StoredObject { key: "uploads/docs/a.md".to_string(), size_bytes: 3 }]
+        );
+        assert_eq!(storage.get("uploads/docs/a.md").await.unwrap().unwrap(), b"# A");
+
+        storage.delete("uploads/docs/a.md").await.unwrap();
+        storage.delete("uploads/docs/a.md").await.unwrap();
+        assert!(storage.get("uploads/docs/a.md").await.unwrap().is_none());
+    }
+}
@@ -0,0 +1,167 @@
+use chrono::{DateTime, Utc};
+use common::errors::{Result, HybridLLMError};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::Duration;
+use tracing::{info, debug, warn};
+use uuid::Uuid;
+
+use crate::ManagedFolder;
+
+/// Metadata file stored alongside each trashed file
+const METADATA_FILE: &str = "entry.json";
+
+/// Name the trashed file or directory is kept under
+const DATA_NAME: &str = "data";
+
+/// How long deleted files can be restored by default
+pub const DEFAULT_TRASH_RETENTION: Duration = Duration::from_secs(30 * 24 * 60 * 60);
+
+/// A deleted file waiting in the trash
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TrashEntry {
+    pub id: Uuid,
+    pub folder: ManagedFolder,
+    /// Path relative to the folder it was deleted from
+    pub filename: String,
+    pub size_bytes: u64,
+    pub deleted_at: DateTime<Utc>,
+    /// Purged for good after this
+    pub expires_at: DateTime<Utc>,
+}
+
+/// Deleted files, one directory per entry, kept until their retention runs out
+pub(crate) struct Trash {
+    path: PathBuf,
+    retention: Duration,
+    entries: Mutex<HashMap<Uuid, TrashEntry>>,
+}
+
+impl Trash {
+    /// Open the trash, loading entries already on disk
+    pub(crate) fn open(path: PathBuf, retention: Duration) -> Result<Self> {
+        std::fs::create_dir_all(&path)
+            .map_err(|e| HybridLLMError::FileSystemError(e.to_string()))?;
+
+        let mut entries = HashMap::new();
+        let dirs = std::fs::read_dir(&path)
+            .map_err(|e| HybridLLMError::FileSystemError(e.to_string()))?;
+
+        for dir in dirs.filter_map(|e| e.ok()) {
+            let metadata = dir.path().join(METADATA_FILE);
+            match std::fs::read(&metadata)
+                .map_err(|e| e.to_string())
+                .and_then(|data| serde_json::from_slice::<TrashEntry>(&data).map_err(|e| e.to_string()))
+            {
+                Ok(entry) => {
+                    entries.insert(entry.id, entry);
+                }
+                Err(e) => warn!("⚠️  Skipping unreadable trash entry {:?}: {}", dir.path(), e),
+            }
+        }
+
+        Ok(Self { path, retention, entries: Mutex::new(entries) })
+    }
+
+    pub(crate) fn set_retention(&mut self, retention: Duration) {
+        self.retention = retention;
+    }
+
+    /// Move `source` into the trash
+    pub(crate) fn put(&self, folder: ManagedFolder, filename: &str, source: &Path) -> Result<TrashEntry> {
+        let fs_err = |e: std::io::Error| HybridLLMError::FileSystemError(e.to_string());
+
+        let size_bytes = crate::quota::measure(source).0;
+        let deleted_at = Utc::now();
+        let entry = TrashEntry {
+            id: Uuid::new_v4(),
+            folder,
+            filename: filename.to_string(),
+            size_bytes,
+            deleted_at,
+            expires_at: deleted_at + self.retention,
+        };
+
+        let dir = self.path.join(entry.id.to_string());
+        std::fs::create_dir_all(&dir).map_err(fs_err)?;
+        let data = serde_json::to_vec_pretty(&entry).map_err(|e| HybridLLMError::Other(e.into()))?;
+        std::fs::write(dir.join(METADATA_FILE), data).map_err(fs_err)?;
+        if let Err(e) = std::fs::rename(source, dir.join(DATA_NAME)) {
+            // Nothing was moved, so there is nothing to keep
+            let _ = std::fs::remove_dir_all(&dir);
+            return Err(fs_err(e));
+        }
+
+        debug!("🗑️  Trashed {} from {} as {}", filename, folder.name(), entry.id);
+        self.entries.lock().unwrap().insert(entry.id, entry.clone());
+        Ok(entry)
+    }
+
+    /// Move an entry back to `destination` and forget it
+    pub(crate) fn take(&self, id: Uuid, destination: &Path) -> Result<TrashEntry> {
+        let fs_err = |e: std::io::Error| HybridLLMError::FileSystemError(e.to_string());
+
+        let mut entries = self.entries.lock().unwrap();
+        let entry = entries
+            .get(&id)
+            .cloned()
+            .ok_or_else(|| HybridLLMError::InvalidRequest(format!("No trash entry {}", id)))?;
+
+        if destination.exists() {
+            return Err(HybridLLMError::InvalidRequest(format!(
+                "Cannot restore {}: a file with that name already exists",
+                entry.filename
+            )));
+        }
+
+        let dir = self.path.join(id.to_string());
+        if let Some(parent) = destination.parent() {
+            std::fs::create_dir_all(parent).map_err(fs_err)?;
+        }
+        std::fs::rename(dir.join(DATA_NAME), destination).map_err(fs_err)?;
+        let _ = std::fs::remove_dir_all(&dir);
+
+        entries.remove(&id);
+        Ok(entry)
+    }
+
+    pub(crate) fn get(&self, id: Uuid) -> Option<TrashEntry> {
+        self.entries.lock().unwrap().get(&id).cloned()
+    }
+
+    /// Entries newest first
+    pub(crate) fn list(&self) -> Vec<TrashEntry> {
+        let mut entries: Vec<_> = self.entries.lock().unwrap().values().cloned().collect();
+        entries.sort_by_key(|entry| std::cmp::Reverse(entry.deleted_at));
+        entries
+    }
+
+    /// Permanently delete entries past their retention, or all of them
+    /// Returns how many were removed
+    pub(crate) fn purge(&self, all: bool) -> usize {
+        let now = Utc::now();
+        let mut entries = self.entries.lock().unwrap();
+        let expired: Vec<Uuid> = entries
+            .values()
+            .filter(|entry| all || entry.expires_at <= now)
+            .map(|entry| entry.id)
+            .collect();
+
+        let mut purged = 0;
+        for id in expired {
+            if let Err(e) = std::fs::remove_dir_all(self.path.join(id.to_string())) {
+                warn!("⚠️  Failed to purge trash entry {}: {}", id, e);
+                continue;
+            }
+            entries.remove(&id);
+            purged += 1;
+        }
+
+        if purged > 0 {
+            info!("🧹 Purged {} trash entries", purged);
+        }
+        purged
+    }
+}
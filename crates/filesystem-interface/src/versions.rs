@@ -0,0 +1,153 @@
+use chrono::{DateTime, Utc};
+use common::errors::{Result, HybridLLMError};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use tracing::{debug, warn};
+
+use crate::hashes;
+
+/// Content-addressed blobs, named by their SHA-256
+const OBJECTS_DIR: &str = "objects";
+
+/// Version list of every file, keyed by filename
+const HISTORY_FILE: &str = "history.json";
+
+/// One stored version of a RAG source file
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct FileVersion {
+    /// 1-based, increasing with every change
+    pub version: u32,
+    pub sha256: String,
+    pub size_bytes: u64,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Every version of every RAG file; identical content is stored once
+pub(crate) struct VersionStore {
+    path: PathBuf,
+    history: Mutex<HashMap<String, Vec<FileVersion>>>,
+}
+
+impl VersionStore {
+    pub(crate) fn open(path: PathBuf) -> Result<Self> {
+        std::fs::create_dir_all(path.join(OBJECTS_DIR))
+            .map_err(|e| HybridLLMError::FileSystemError(e.to_string()))?;
+
+        let history = std::fs::read(path.join(HISTORY_FILE))
+            .ok()
+            .and_then(|data| match serde_json::from_slice(&data) {
+                Ok(history) => Some(history),
+                Err(e) => {
+                    warn!("⚠️  Ignoring unreadable version history in {:?}: {}", path, e);
+                    None
+                }
+            })
+            .unwrap_or_default();
+
+        Ok(Self { path, history: Mutex::new(history) })
+    }
+
+    /// Keep a file that predates versioning as version 1 before it is first overwritten
+    pub(crate) fn ensure_baseline(&self, filename: &str, existing: &Path) -> Result<()> {
+        if self.history.lock().unwrap().contains_key(filename) {
+            return Ok(());
+        }
+        let Ok(content) = std::fs::read(existing) else {
+            return Ok(());
+        };
+
+        let created_at = std::fs::metadata(existing)
+            .and_then(|metadata| metadata.modified())
+            .map(DateTime::<Utc>::from)
+            .unwrap_or_else(|_| Utc::now());
+        self.record_at(filename, &content, created_at).map(|_| ())
+    }
+
+    /// Store `content` as the newest version of `filename`
+    /// Returns `None` when it is identical to the current version
+    pub(crate) fn record(&self, filename: &str, content: &[u8]) -> Result<Option<FileVersion>> {
+        self.record_at(filename, content, Utc::now())
+    }
+
+    fn record_at(&self, filename: &str, content: &[u8], created_at: DateTime<Utc>) -> Result<Option<FileVersion>> {
+        let fs_err = |e: std::io::Error| HybridLLMError::FileSystemError(e.to_string());
+        let sha256 = hashes::sha256(content);
+
+        let mut history = self.history.lock().unwrap();
+        let versions = history.entry(filename.to_string()).or_default();
+        if versions.last().is_some_and(|latest| latest.sha256 == sha256) {
+            return Ok(None);
+        }
+
+        let object = self.object_path(&sha256);
+        if !object.exists() {
+            // Written aside and renamed so a crash never leaves a truncated object
+            let partial = object.with_extension("partial");
+            std::fs::write(&partial, content).map_err(fs_err)?;
+            std::fs::rename(&partial, &object).map_err(fs_err)?;
+        }
+
+        let version = FileVersion {
+            version: versions.last().map_or(1, |latest| latest.version + 1),
+            sha256,
+            size_bytes: content.len() as u64,
+            created_at,
+        };
+        versions.push(version.clone());
+        debug!("🕓 Stored {} version {}", filename, version.version);
+
+        self.save(&history)?;
+        Ok(Some(version))
+    }
+
+    pub(crate) fn versions(&self, filename: &str) -> Vec<FileVersion> {
+        self.history.lock().unwrap().get(filename).cloned().unwrap_or_default()
+    }
+
+    pub(crate) fn get(&self, filename: &str, version: u32) -> Result<FileVersion> {
+        self.versions(filename)
+            .into_iter()
+            .find(|v| v.version == version)
+            .ok_or_else(|| HybridLLMError::InvalidRequest(format!("{} has no version {}", filename, version)))
+    }
+
+    pub(crate) fn read(&self, version: &FileVersion) -> Result<Vec<u8>> {
+        std::fs::read(self.object_path(&version.sha256))
+            .map_err(|e| HybridLLMError::FileSystemError(e.to_string()))
+    }
+
+    /// The version that was current at `at`
+    pub(crate) fn as_of(&self, filename: &str, at: DateTime<Utc>) -> Option<FileVersion> {
+        self.versions(filename).into_iter().rev().find(|v| v.created_at <= at)
+    }
+
+    fn object_path(&self, sha256: &str) -> PathBuf {
+        self.path.join(OBJECTS_DIR).join(sha256)
+    }
+
+    fn save(&self, history: &HashMap<String, Vec<FileVersion>>) -> Result<()> {
+        let data = serde_json::to_vec(history).map_err(|e| HybridLLMError::Other(e.into()))?;
+        std::fs::write(self.path.join(HISTORY_FILE), data)
+            .map_err(|e| HybridLLMError::FileSystemError(e.to_string()))
+    }
+}
+
+/// Unified diff between two versions' text
+pub(crate) fn unified_diff(filename: &str, old: (&FileVersion, &[u8]), new: (&FileVersion, &[u8])) -> Result<String> {
+    let text = |content: &[u8]| {
+        std::str::from_utf8(content)
+            .map(str::to_string)
+            .map_err(|_| HybridLLMError::InvalidRequest(format!("{} is not a text file", filename)))
+    };
+    let (old_text, new_text) = (text(old.1)?, text(new.1)?);
+
+    Ok(similar::TextDiff::from_lines(&old_text, &new_text)
+        .unified_diff()
+        .header(
+            &format!("{} (version {})", filename, old.0.version),
+            &format!("{} (version {})", filename, new.0.version),
+        )
+        .to_string())
+}
@@ -0,0 +1,214 @@
+//! Formatting conversations the way each model family was trained to read them
+
+use common::completion::CompletionRequest;
+use common::types::MessageRole;
+use serde::{Deserialize, Serialize};
+
+use crate::gguf::ModelInfo;
+
+/// Model name fragments and the format their families use, for files that don't carry a template
+const REGISTRY: &[(&str, ChatTemplate)] = &[
+    ("llama-3", ChatTemplate::Llama3),
+    ("llama3", ChatTemplate::Llama3),
+    ("qwen", ChatTemplate::ChatMl),
+    ("hermes", ChatTemplate::ChatMl),
+    ("chatml", ChatTemplate::ChatMl),
+    ("mistral", ChatTemplate::Mistral),
+    ("mixtral", ChatTemplate::Mistral),
+    ("gemma", ChatTemplate::Gemma),
+];
+
+/// A chat format; the BOS token is left out, as the tokenizer adds it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ChatTemplate {
+    /// `<|im_start|>role ... <|im_end|>`, used by Qwen, Hermes and many fine-tunes
+    ChatMl,
+    /// `<|start_header_id|>role<|end_header_id|> ... <|eot_id|>`
+    Llama3,
+    /// `[INST] ... [/INST]`; the system prompt joins the first user turn
+    Mistral,
+    /// `<start_of_turn>user ... <end_of_turn>`; the system prompt joins the first user turn
+    Gemma,
+    /// "User: ..." lines, for base models without a chat format
+    Plain,
+}
+
+impl ChatTemplate {
+    /// The format a GGUF `tokenizer.chat_template` Jinja template writes, told by its markers
+    pub fn detect(jinja: &str) -> Option<Self> {
+        if jinja.contains("<|im_start|>") {
+            Some(Self::ChatMl)
+        } else if jinja.contains("<|start_header_id|>") {
+            Some(Self::Llama3)
+        } else if jinja.contains("<start_of_turn>") {
+            Some(Self::Gemma)
+        } else if jinja.contains("[INST]") {
+            Some(Self::Mistral)
+        } else {
+            None
+        }
+    }
+
+    /// The model's own template if it has a known one, else the registry entry matching its name,
+    /// file name or architecture, else `Plain`
+    pub fn for_model(info: Option<&ModelInfo>, file_name: &str) -> Self {
+        if let Some(template) = info.and_then(|info| info.chat_template.as_deref()).and_then(Self::detect) {
+            return template;
+        }
+        let names = info
+            .into_iter()
+            .flat_map(|info| [info.name.as_deref(), info.architecture.as_deref()])
+            .flatten()
+            .chain([file_name]);
+        names
+            .map(str::to_lowercase)
+            .find_map(|name| REGISTRY.iter().find(|(fragment, _)| name.contains(fragment)).map(|&(_, template)| template))
+            .unwrap_or(Self::Plain)
+    }
+
+    /// The prompt for `request`, ending where the assistant's reply begins
+    pub fn format(&self, request: &CompletionRequest) -> String {
+        let turns = request.system.iter().map(|system| (MessageRole::System, system.as_str()));
+        let turns = turns.chain(request.messages.iter().map(|message| (message.role, message.content.as_str())));
+        let mut prompt = String::new();
+
+        match self {
+            Self::ChatMl => {
+                for (role, content) in turns {
+                    prompt.push_str(&format!("<|im_start|>{}\n{}<|im_end|>\n", role_name(&role), content));
+                }
+                prompt.push_str("<|im_start|>assistant\n");
+            }
+            Self::Llama3 => {
+                for (role, content) in turns {
+                    prompt.push_str(&format!("<|start_header_id|>{}<|end_header_id|>\n\n{}<|eot_id|>", role_name(&role), content));
+                }
+                prompt.push_str("<|start_header_id|>assistant<|end_header_id|>\n\n");
+            }
+            Self::Mistral => {
+                for (role, content) in merge_system(turns) {
+                    match role {
+                        MessageRole::Assistant => prompt.push_str(&format!(" {}</s>", content)),
+                        _ => prompt.push_str(&format!("[INST] {} [/INST]", content)),
+                    }
+                }
+            }
+            Self::Gemma => {
+                for (role, content) in merge_system(turns) {
+                    let role = if matches!(role, MessageRole::Assistant) { "model" } else { "user" };
+                    prompt.push_str(&format!("<start_of_turn>{}\n{}<end_of_turn>\n", role, content));
+                }
+                prompt.push_str("<start_of_turn>model\n");
+            }
+            Self::Plain => return request.to_prompt(),
+        }
+        prompt
+    }
+
+    /// End-of-turn markers, in case the model writes one as text instead of ending generation
+    pub fn stop_sequences(&self) -> &'static [&'static str] {
+        match self {
+            Self::ChatMl => &["<|im_end|>", "<|im_start|>"],
+            Self::Llama3 => &["<|eot_id|>", "<|start_header_id|>"],
+            Self::Mistral => &["</s>", "[INST]"],
+            Self::Gemma => &["<end_of_turn>", "<start_of_turn>"],
+            Self::Plain => &[],
+        }
+    }
+}
+
+fn role_name(role: &MessageRole) -> &'static str {
+    match role {
+        MessageRole::System => "system",
+        MessageRole::User => "user",
+        MessageRole::Assistant => "assistant",
+    }
+}
+
+/// Turns for formats without a system role: system text goes ahead of the next user turn
+fn merge_system<'a>(turns: impl Iterator<Item = (MessageRole, &'a str)>) -> Vec<(MessageRole, String)> {
+    let mut merged = Vec::new();
+    let mut system = Vec::new();
+    for (role, content) in turns {
+        match role {
+            MessageRole::System => system.push(content),
+            MessageRole::User => {
+                system.push(content);
+                merged.push((MessageRole::User, system.join("\n\n")));
+                system.clear();
+            }
+            MessageRole::Assistant => merged.push((MessageRole::Assistant, content.to_string())),
+        }
+    }
+    // Instructions with nothing after them still reach the model
+    if !system.is_empty() {
+        merged.push((MessageRole::User, system.join("\n\n")));
+    }
+    merged
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use common::completion::CompletionMessage;
+
+    fn conversation() -> CompletionRequest {
+        CompletionRequest {
+            messages: vec![
+                CompletionMessage::new(MessageRole::User, "hi"),
+                CompletionMessage::new(MessageRole::Assistant, "hello"),
+                CompletionMessage::new(MessageRole::User, "bye"),
+            ],
+            ..CompletionRequest::default()
+        }
+        .with_system("Be brief")
+    }
+
+    #[test]
+    fn test_format() {
+        let request = conversation();
+        assert_eq!(
+            ChatTemplate::ChatMl.format(&request),
+            "<|im_start|>system\nBe brief<|im_end|>\n<|im_start|>user\nhi<|im_end|>\n\
+             <|im_start|>assistant\nhello<|im_end|>\n<|im_start|>user\nbye<|im_end|>\n<|im_start|>assistant\n"
+        );
+        assert_eq!(
+            ChatTemplate::Llama3.format(&CompletionRequest::prompt("hi")),
+            "<|start_header_id|>user<|end_header_id|>\n\nhi<|eot_id|><|start_header_id|>assistant<|end_header_id|>\n\n"
+        );
+        assert_eq!(ChatTemplate::Mistral.format(&request), "[INST] Be brief\n\nhi [/INST] hello</s>[INST] bye [/INST]");
+        assert_eq!(
+            ChatTemplate::Gemma.format(&request),
+            "<start_of_turn>user\nBe brief\n\nhi<end_of_turn>\n<start_of_turn>model\nhello<end_of_turn>\n\
+             <start_of_turn>user\nbye<end_of_turn>\n<start_of_turn>model\n"
+        );
+        assert_eq!(ChatTemplate::Plain.format(&request), request.to_prompt());
+    }
+
+    #[test]
+    fn test_for_model() {
+        let info = |name: Option<&str>, chat_template: Option<&str>| ModelInfo {
+            name: name.map(str::to_string),
+            architecture: Some("llama".to_string()),
+            quantization: None,
+            parameter_count: 0,
+            context_length: None,
+            layer_count: None,
+            embedding_length: None,
+            embedding_only: false,
+            chat_template: chat_template.map(str::to_string),
+            gguf_version: 3,
+            file_size_bytes: 0,
+        };
+
+        let qwen = info(Some("Qwen2.5 7B"), Some("{% for m in messages %}<|im_start|>{{ m.role }}{% endfor %}"));
+        assert_eq!(ChatTemplate::for_model(Some(&qwen), "model.gguf"), ChatTemplate::ChatMl);
+        // The file's own template wins over its name
+        let renamed = info(Some("Mistral 7B"), Some("<|start_header_id|>"));
+        assert_eq!(ChatTemplate::for_model(Some(&renamed), "mistral.gguf"), ChatTemplate::Llama3);
+        assert_eq!(ChatTemplate::for_model(Some(&info(Some("Mistral 7B"), None)), "model.gguf"), ChatTemplate::Mistral);
+        assert_eq!(ChatTemplate::for_model(None, "gemma-2-9b-it-Q4_K_M.gguf"), ChatTemplate::Gemma);
+        assert_eq!(ChatTemplate::for_model(Some(&info(None, None)), "base.gguf"), ChatTemplate::Plain);
+    }
+}
@@ -0,0 +1,215 @@
+//! Details of a GGUF model read from its header, so they can be shown before the weights are loaded
+
+use common::errors::Result;
+use common::gguf::{self, GgufHeader, GgufValue};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+/// Architectures that only encode text into vectors and can't generate
+const EMBEDDING_ARCHITECTURES: &[&str] = &["bert", "nomic-bert", "nomic-bert-moe", "jina-bert-v2"];
+
+/// What a GGUF file says about its model
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ModelInfo {
+    /// `general.name`, e.g. "Mistral 7B Instruct"
+    pub name: Option<String>,
+    /// `general.architecture`, e.g. "llama" or "qwen2"
+    pub architecture: Option<String>,
+    /// e.g. "Q4_K_M"; from `general.file_type`, or the type most weights are stored in
+    pub quantization: Option<String>,
+    /// Weights across all tensors
+    pub parameter_count: u64,
+    /// Tokens the model was trained to attend to
+    pub context_length: Option<u64>,
+    /// Repeating transformer blocks, which llama.cpp offloads to the GPU one by one
+    pub layer_count: Option<u64>,
+    /// Size of the model's hidden states, and of the vectors an embedding model writes
+    pub embedding_length: Option<u64>,
+    /// An embedding model, e.g. a BERT or one that pools its states, rather than a chat model
+    pub embedding_only: bool,
+    /// Jinja template the model formats chats with
+    pub chat_template: Option<String>,
+    pub gguf_version: u32,
+    pub file_size_bytes: u64,
+}
+
+/// Read the details of the model at `path` without loading its weights
+pub fn inspect(path: &Path) -> Result<ModelInfo> {
+    let header = gguf::read_header(path)?;
+    let file_size_bytes = std::fs::metadata(path).map(|metadata| metadata.len()).unwrap_or(0);
+    Ok(ModelInfo::from_header(&header, file_size_bytes))
+}
+
+impl ModelInfo {
+    pub fn from_header(header: &GgufHeader, file_size_bytes: u64) -> Self {
+        let string = |key: &str| header.metadata.get(key).and_then(GgufValue::as_str).map(str::to_string);
+        let architecture = string("general.architecture");
+        let architecture_value = |key: &str| {
+            architecture
+                .as_ref()
+                .and_then(|arch| header.metadata.get(&format!("{}.{}", arch, key)))
+                .and_then(GgufValue::as_u64)
+        };
+        let context_length = architecture_value("context_length");
+        let layer_count = architecture_value("block_count");
+        let embedding_length = architecture_value("embedding_length");
+        let embedding_only = architecture.as_deref().is_some_and(|arch| {
+            EMBEDDING_ARCHITECTURES.contains(&arch) || header.metadata.contains_key(&format!("{}.pooling_type", arch))
+        });
+        let quantization = header
+            .metadata
+            .get("general.file_type")
+            .and_then(GgufValue::as_u64)
+            .and_then(file_type_name)
+            .or_else(|| dominant_type(header).and_then(ggml_type_name))
+            .map(str::to_string);
+
+        Self {
+            name: string("general.name"),
+            architecture,
+            quantization,
+            parameter_count: header.tensors.iter().map(|tensor| tensor.element_count()).sum(),
+            context_length,
+            layer_count,
+            embedding_length,
+            embedding_only,
+            chat_template: string("tokenizer.chat_template"),
+            gguf_version: header.version,
+            file_size_bytes,
+        }
+    }
+}
+
+/// The ggml type holding the most weights
+fn dominant_type(header: &GgufHeader) -> Option<u32> {
+    let mut weights = std::collections::HashMap::new();
+    for tensor in &header.tensors {
+        *weights.entry(tensor.ggml_type).or_insert(0u64) += tensor.element_count();
+    }
+    weights.into_iter().max_by_key(|&(ggml_type, count)| (count, std::cmp::Reverse(ggml_type))).map(|(ggml_type, _)| ggml_type)
+}
+
+/// llama.cpp's `llama_ftype`, which names the quantization of the file as a whole
+fn file_type_name(file_type: u64) -> Option<&'static str> {
+    Some(match file_type {
+        0 => "F32",
+        1 => "F16",
+        2 => "Q4_0",
+        3 => "Q4_1",
+        7 => "Q8_0",
+        8 => "Q5_0",
+        9 => "Q5_1",
+        10 => "Q2_K",
+        11 => "Q3_K_S",
+        12 => "Q3_K_M",
+        13 => "Q3_K_L",
+        14 => "Q4_K_S",
+        15 => "Q4_K_M",
+        16 => "Q5_K_S",
+        17 => "Q5_K_M",
+        18 => "Q6_K",
+        19 => "IQ2_XXS",
+        20 => "IQ2_XS",
+        21 => "Q2_K_S",
+        22 => "IQ3_XS",
+        23 => "IQ3_XXS",
+        24 => "IQ1_S",
+        25 => "IQ4_NL",
+        26 => "IQ3_S",
+        27 => "IQ3_M",
+        28 => "IQ2_S",
+        29 => "IQ2_M",
+        30 => "IQ4_XS",
+        31 => "IQ1_M",
+        32 => "BF16",
+        _ => return None,
+    })
+}
+
+/// ggml's tensor types
+fn ggml_type_name(ggml_type: u32) -> Option<&'static str> {
+    Some(match ggml_type {
+        0 => "F32",
+        1 => "F16",
+        2 => "Q4_0",
+        3 => "Q4_1",
+        6 => "Q5_0",
+        7 => "Q5_1",
+        8 => "Q8_0",
+        9 => "Q8_1",
+        10 => "Q2_K",
+        11 => "Q3_K",
+        12 => "Q4_K",
+        13 => "Q5_K",
+        14 => "Q6_K",
+        15 => "Q8_K",
+        16 => "IQ2_XXS",
+        17 => "IQ2_XS",
+        18 => "IQ3_XXS",
+        19 => "IQ1_S",
+        20 => "IQ4_NL",
+        21 => "IQ3_S",
+        22 => "IQ2_S",
+        23 => "IQ4_XS",
+        24 => "I8",
+        25 => "I16",
+        26 => "I32",
+        27 => "I64",
+        28 => "F64",
+        29 => "IQ1_M",
+        30 => "BF16",
+        _ => return None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use common::gguf::GgufTensor;
+
+    #[test]
+    fn test_model_info() {
+        let tensor = |name: &str, shape: Vec<u64>, ggml_type| GgufTensor { name: name.to_string(), shape, ggml_type };
+        let mut header = GgufHeader {
+            version: 3,
+            metadata: [
+                ("general.name", GgufValue::String("Tiny Llama".to_string())),
+                ("general.architecture", GgufValue::String("llama".to_string())),
+                ("general.file_type", GgufValue::UInt(15)),
+                ("llama.context_length", GgufValue::UInt(2048)),
+                ("llama.block_count", GgufValue::UInt(22)),
+                ("llama.embedding_length", GgufValue::UInt(64)),
+                ("tokenizer.chat_template", GgufValue::String("{{ messages }}".to_string())),
+            ]
+            .into_iter()
+            .map(|(key, value)| (key.to_string(), value))
+            .collect(),
+            tensors: vec![
+                tensor("token_embd.weight", vec![64, 1000], 14),
+                tensor("blk.0.attn_q.weight", vec![64, 64], 12),
+                tensor("blk.0.ffn_up.weight", vec![64, 256], 12),
+                tensor("output_norm.weight", vec![64], 0),
+            ],
+        };
+
+        let info = ModelInfo::from_header(&header, 1234);
+        assert_eq!(info.name.as_deref(), Some("Tiny Llama"));
+        assert_eq!(info.architecture.as_deref(), Some("llama"));
+        assert_eq!(info.quantization.as_deref(), Some("Q4_K_M"));
+        assert_eq!(info.parameter_count, 64 * 1000 + 64 * 64 + 64 * 256 + 64);
+        assert_eq!(info.context_length, Some(2048));
+        assert_eq!(info.layer_count, Some(22));
+        assert_eq!(info.embedding_length, Some(64));
+        assert!(!info.embedding_only);
+        assert_eq!(info.chat_template.as_deref(), Some("{{ messages }}"));
+        assert_eq!(info.file_size_bytes, 1234);
+
+        // Without a file type, the type of the embeddings outweighs the rest
+        header.metadata.remove("general.file_type");
+        assert_eq!(ModelInfo::from_header(&header, 0).quantization.as_deref(), Some("Q6_K"));
+
+        // A model that pools its states is an embedding model
+        header.metadata.insert("llama.pooling_type".to_string(), GgufValue::UInt(1));
+        assert!(ModelInfo::from_header(&header, 0).embedding_only);
+    }
+}
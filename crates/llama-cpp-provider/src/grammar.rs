@@ -0,0 +1,335 @@
+//! GBNF grammars for constrained sampling, written from a `ResponseFormat`
+
+use common::errors::{HybridLLMError, Result};
+use common::ResponseFormat;
+use serde_json::{Map, Value};
+use std::collections::BTreeMap;
+
+/// Rule every grammar starts at
+pub const ROOT: &str = "root";
+
+/// Whitespace between tokens, bounded so the model can't pad forever
+const SPACE: &str = r#"| " " | "\n" [ \t]{0,20}"#;
+
+/// JSON's building blocks, by rule name, with the rules each one uses
+const PRIMITIVES: &[(&str, &str, &[&str])] = &[
+    ("boolean", r#"("true" | "false") space"#, &[]),
+    ("null", r#""null" space"#, &[]),
+    ("char", r#"[^"\\\x7F\x00-\x1F] | [\\] (["\\bfnrt] | "u" [0-9a-fA-F]{4})"#, &[]),
+    ("string", r#""\"" char* "\"" space"#, &["char"]),
+    ("integral-part", r#"[0] | [1-9] [0-9]{0,15}"#, &[]),
+    ("decimal-part", r#"[0-9]{1,16}"#, &[]),
+    ("integer", r#"("-"? integral-part) space"#, &["integral-part"]),
+    (
+        "number",
+        r#"("-"? integral-part) ("." decimal-part)? ([eE] [-+]? integral-part)? space"#,
+        &["integral-part", "decimal-part"],
+    ),
+    (
+        "value",
+        r#"object | array | string | number | boolean | null"#,
+        &["object", "array", "string", "number", "boolean", "null"],
+    ),
+    (
+        "object",
+        r#""{" space ( string ":" space value ("," space string ":" space value)* )? "}" space"#,
+        &["string", "value"],
+    ),
+    ("array", r#""[" space ( value ("," space value)* )? "]" space"#, &["value"]),
+];
+
+/// The grammar replies in `format` must follow
+pub fn to_gbnf(format: &ResponseFormat) -> Result<String> {
+    let any_object = serde_json::json!({ "type": "object" });
+    let schema = match format {
+        ResponseFormat::Grammar { grammar } => return Ok(grammar.clone()),
+        ResponseFormat::JsonObject => &any_object,
+        ResponseFormat::JsonSchema { schema } => schema,
+    };
+    let mut converter = Converter::new(schema);
+    let root = converter.visit(schema, ROOT)?;
+    converter.rules.insert(ROOT.to_string(), root);
+    Ok(converter.finish())
+}
+
+struct Converter<'a> {
+    /// The whole schema, which `$ref`s point into
+    root: &'a Value,
+    rules: BTreeMap<String, String>,
+}
+
+impl<'a> Converter<'a> {
+    fn new(root: &'a Value) -> Self {
+        let rules = BTreeMap::from([("space".to_string(), SPACE.to_string())]);
+        Self { root, rules }
+    }
+
+    /// The rules, root first
+    fn finish(mut self) -> String {
+        let root = self.rules.remove(ROOT).unwrap_or_default();
+        let mut grammar = format!("{} ::= {}\n", ROOT, root);
+        for (name, body) in &self.rules {
+            grammar.push_str(&format!("{} ::= {}\n", name, body));
+        }
+        grammar
+    }
+
+    /// Name a JSON building block, adding it and the blocks it is made of
+    fn primitive(&mut self, name: &str) -> String {
+        if !self.rules.contains_key(name) {
+            if let Some((_, body, uses)) = PRIMITIVES.iter().find(|(primitive, _, _)| *primitive == name) {
+                self.rules.insert(name.to_string(), body.to_string());
+                for used in *uses {
+                    self.primitive(used);
+                }
+            }
+        }
+        name.to_string()
+    }
+
+    /// Add a rule, returning its name
+    fn rule(&mut self, name: &str, body: String) -> String {
+        self.rules.insert(name.to_string(), body);
+        name.to_string()
+    }
+
+    /// An expression matching what `schema` allows; `name` prefixes the rules it adds
+    fn visit(&mut self, schema: &'a Value, name: &str) -> Result<String> {
+        let schema = match schema {
+            Value::Bool(true) => return Ok(self.primitive("value")),
+            Value::Object(schema) => schema,
+            _ => return Err(invalid(format!("{} is not a schema", schema))),
+        };
+
+        if let Some(reference) = schema.get("$ref").and_then(Value::as_str) {
+            return self.reference(reference);
+        }
+        if let Some(value) = schema.get("const") {
+            return Ok(literal(value));
+        }
+        if let Some(values) = schema.get("enum").and_then(Value::as_array) {
+            return Ok(format!("({})", values.iter().map(literal).collect::<Vec<_>>().join(" | ")));
+        }
+        if let Some(choices) = schema.get("anyOf").or_else(|| schema.get("oneOf")).and_then(Value::as_array) {
+            let alternatives = choices
+                .iter()
+                .enumerate()
+                .map(|(n, choice)| self.visit(choice, &format!("{}-{}", name, n)))
+                .collect::<Result<Vec<_>>>()?;
+            return Ok(format!("({})", alternatives.join(" | ")));
+        }
+
+        match schema.get("type") {
+            Some(Value::String(kind)) => self.typed(kind, schema, name),
+            Some(Value::Array(kinds)) => {
+                let alternatives = kinds
+                    .iter()
+                    .map(|kind| match kind.as_str() {
+                        Some(kind) => self.typed(kind, schema, &format!("{}-{}", name, kind)),
+                        None => Err(invalid(format!("{} is not a type", kind))),
+                    })
+                    .collect::<Result<Vec<_>>>()?;
+                Ok(format!("({})", alternatives.join(" | ")))
+            }
+            Some(kind) => Err(invalid(format!("{} is not a type", kind))),
+            None if schema.contains_key("properties") => self.typed("object", schema, name),
+            None if schema.contains_key("items") => self.typed("array", schema, name),
+            None => Ok(self.primitive("value")),
+        }
+    }
+
+    fn typed(&mut self, kind: &str, schema: &'a Map<String, Value>, name: &str) -> Result<String> {
+        match kind {
+            "object" => self.object(schema, name),
+            "array" => self.array(schema, name),
+            "string" => Ok(self.string(schema)),
+            "integer" | "number" | "boolean" | "null" => Ok(self.primitive(kind)),
+            _ => Err(invalid(format!("unsupported type \"{}\"", kind))),
+        }
+    }
+
+    /// Required properties, then any of the optional ones, each in key order
+    fn object(&mut self, schema: &'a Map<String, Value>, name: &str) -> Result<String> {
+        let Some(properties) = schema.get("properties").and_then(Value::as_object) else {
+            return Ok(self.primitive("object"));
+        };
+        let required: Vec<&str> = schema
+            .get("required")
+            .and_then(Value::as_array)
+            .map(|required| required.iter().filter_map(Value::as_str).collect())
+            .unwrap_or_default();
+
+        let (mut present, mut optional) = (Vec::new(), Vec::new());
+        for (property, property_schema) in properties {
+            let rule_name = format!("{}-{}", name, sanitize(property));
+            let value = self.visit(property_schema, &rule_name)?;
+            let pair = format!("{} \":\" space {}", literal(&Value::String(property.clone())), value);
+            let pair = self.rule(&format!("{}-kv", rule_name), pair);
+            if required.contains(&property.as_str()) {
+                present.push(pair);
+            } else {
+                optional.push(pair);
+            }
+        }
+
+        let mut parts = vec!["\"{\" space".to_string()];
+        if present.is_empty() {
+            if !optional.is_empty() {
+                parts.push(optional_pairs(&optional));
+            }
+        } else {
+            parts.push(present.join(" \",\" space "));
+            parts.extend(optional.iter().map(|pair| format!("(\",\" space {})?", pair)));
+        }
+        parts.push("\"}\" space".to_string());
+        Ok(parts.join(" "))
+    }
+
+    fn array(&mut self, schema: &'a Map<String, Value>, name: &str) -> Result<String> {
+        let item = match schema.get("items") {
+            Some(items) => {
+                let item = self.visit(items, &format!("{}-item", name))?;
+                self.rule(&format!("{}-item", name), item)
+            }
+            None => self.primitive("value"),
+        };
+        let min = schema.get("minItems").and_then(Value::as_u64).unwrap_or(0);
+        let max = schema.get("maxItems").and_then(Value::as_u64);
+        let more = format!("(\",\" space {})", item);
+        let items = match (min, max) {
+            (_, Some(0)) => String::new(),
+            (0, None) => format!("({} {}*)?", item, more),
+            (0, Some(max)) => format!("({} {}{{0,{}}})?", item, more, max - 1),
+            (min, None) => format!("{} {}{{{},}}", item, more, min - 1),
+            (min, Some(max)) => format!("{} {}{{{},{}}}", item, more, min - 1, max.max(min) - 1),
+        };
+        Ok(format!("\"[\" space {} \"]\" space", items))
+    }
+
+    fn string(&mut self, schema: &Map<String, Value>) -> String {
+        let min = schema.get("minLength").and_then(Value::as_u64);
+        let max = schema.get("maxLength").and_then(Value::as_u64);
+        if min.is_none() && max.is_none() {
+            return self.primitive("string");
+        }
+        let character = self.primitive("char");
+        let max = max.map(|max| max.to_string()).unwrap_or_default();
+        format!("\"\\\"\" {}{{{},{}}} \"\\\"\" space", character, min.unwrap_or(0), max)
+    }
+
+    /// A `$ref` into this schema's `$defs` or `definitions`, as a rule of its own so it may recurse
+    fn reference(&mut self, reference: &str) -> Result<String> {
+        let name = format!("ref-{}", sanitize(reference.rsplit('/').next().unwrap_or(reference)));
+        if self.rules.contains_key(&name) {
+            return Ok(name);
+        }
+        let pointer = reference.strip_prefix('#').ok_or_else(|| invalid(format!("{} is not a local $ref", reference)))?;
+        let target = self.root.pointer(pointer).ok_or_else(|| invalid(format!("{} points nowhere", reference)))?;
+        // Taken before visiting, so the definition can refer to itself
+        self.rules.insert(name.clone(), String::new());
+        let body = self.visit(target, &name)?;
+        Ok(self.rule(&name, body))
+    }
+}
+
+/// The pairs of an object without required properties: none, or any of them in order
+fn optional_pairs(pairs: &[String]) -> String {
+    let alternatives: Vec<String> = (0..pairs.len())
+        .map(|first| {
+            let rest: String = pairs[first + 1..].iter().map(|pair| format!(" (\",\" space {})?", pair)).collect();
+            format!("{}{}", pairs[first], rest)
+        })
+        .collect();
+    format!("({})?", alternatives.join(" | "))
+}
+
+/// Exactly `value` as JSON, quoted for GBNF
+fn literal(value: &Value) -> String {
+    let json = value.to_string();
+    format!("\"{}\" space", json.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// Rule names take letters, digits and dashes
+fn sanitize(name: &str) -> String {
+    name.chars().map(|c| if c.is_ascii_alphanumeric() { c } else { '-' }).collect()
+}
+
+fn invalid(message: String) -> HybridLLMError {
+    HybridLLMError::InvalidRequest(format!("Unusable response schema: {}", message))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn rules(grammar: &str) -> BTreeMap<&str, &str> {
+        grammar.lines().filter_map(|line| line.split_once(" ::= ")).collect()
+    }
+
+    #[test]
+    fn test_json_schema() {
+        let schema = json!({
+            "type": "object",
+            "properties": {
+                "files": { "type": "array", "items": { "type": "string" }, "minItems": 1 },
+                "kind": { "enum": ["code", "docs"] },
+                "note": { "type": ["string", "null"] },
+            },
+            "required": ["files", "kind"],
+        });
+        let grammar = to_gbnf(&ResponseFormat::JsonSchema { schema }).unwrap();
+        assert!(grammar.starts_with("root ::= "));
+        let rules = rules(&grammar);
+        assert_eq!(
+            rules["root"],
+            r#""{" space root-files-kv "," space root-kind-kv ("," space root-note-kv)? "}" space"#
+        );
+        assert_eq!(
+            rules["root-files-kv"],
+            r#""\"files\"" space ":" space "[" space root-files-item ("," space root-files-item){0,} "]" space"#
+        );
+        assert_eq!(rules["root-files-item"], "string");
+        assert_eq!(rules["root-kind-kv"], r#""\"kind\"" space ":" space ("\"code\"" space | "\"docs\"" space)"#);
+        assert_eq!(rules["root-note-kv"], r#""\"note\"" space ":" space (string | null)"#);
+        // Every rule used is defined
+        for name in ["string", "char", "null", "space"] {
+            assert!(rules.contains_key(name), "{} is missing", name);
+        }
+    }
+
+    #[test]
+    fn test_optional_and_recursive() {
+        let schema = json!({
+            "$defs": {
+                "node": {
+                    "type": "object",
+                    "properties": { "children": { "type": "array", "items": { "$ref": "#/$defs/node" } } },
+                },
+            },
+            "$ref": "#/$defs/node",
+        });
+        let grammar = to_gbnf(&ResponseFormat::JsonSchema { schema }).unwrap();
+        let rules = rules(&grammar);
+        assert_eq!(rules["root"], "ref-node");
+        assert_eq!(rules["ref-node"], r#""{" space (ref-node-children-kv)? "}" space"#);
+        assert_eq!(rules["ref-node-children-item"], "ref-node");
+
+        assert_eq!(
+            optional_pairs(&["a".to_string(), "b".to_string()]),
+            r#"(a ("," space b)? | b)?"#
+        );
+        assert!(to_gbnf(&ResponseFormat::JsonSchema { schema: json!({ "$ref": "#/$defs/missing" }) }).is_err());
+        assert!(to_gbnf(&ResponseFormat::JsonSchema { schema: json!({ "type": "date" }) }).is_err());
+    }
+
+    #[test]
+    fn test_other_formats() {
+        let object = to_gbnf(&ResponseFormat::JsonObject).unwrap();
+        assert!(object.starts_with("root ::= object\n"));
+        assert!(rules(&object).contains_key("value"));
+
+        let grammar = r#"root ::= "yes" | "no""#.to_string();
+        assert_eq!(to_gbnf(&ResponseFormat::Grammar { grammar: grammar.clone() }).unwrap(), grammar);
+    }
+}
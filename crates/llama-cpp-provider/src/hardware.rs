@@ -0,0 +1,159 @@
+//! Finding the GPUs llama.cpp can offload to, and how much of a model fits on them
+
+use serde::{Deserialize, Serialize};
+use std::process::Command;
+
+/// VRAM left free for the KV cache and llama.cpp's scratch buffers
+const VRAM_HEADROOM_MB: u64 = 1024;
+
+/// Share of unified memory macOS lets the GPU wire down
+const METAL_WORKING_SET_PERCENT: u64 = 75;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum GpuBackend {
+    Cuda,
+    Metal,
+    Vulkan,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct GpuInfo {
+    pub name: String,
+    pub backend: GpuBackend,
+    /// Unknown for Vulkan devices
+    pub total_vram_mb: Option<u64>,
+    pub free_vram_mb: Option<u64>,
+}
+
+/// GPUs on this machine, and whether llama.cpp was built to use them
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HardwareInfo {
+    /// Whether this build of llama.cpp can offload layers at all
+    pub gpu_offload: bool,
+    pub gpus: Vec<GpuInfo>,
+}
+
+impl HardwareInfo {
+    /// Probe for CUDA (`nvidia-smi`), Metal (Apple Silicon) and Vulkan (`vulkaninfo`) devices
+    /// Runs external tools, so call it off the async runtime
+    pub fn detect(gpu_offload: bool) -> Self {
+        let mut gpus = run("nvidia-smi", &["--query-gpu=name,memory.total,memory.free", "--format=csv,noheader,nounits"])
+            .map(|output| parse_nvidia_smi(&output))
+            .unwrap_or_default();
+
+        if cfg!(all(target_os = "macos", target_arch = "aarch64")) {
+            let memory_mb = run("sysctl", &["-n", "hw.memsize"])
+                .and_then(|output| output.trim().parse::<u64>().ok())
+                .map(|bytes| bytes / (1024 * 1024) * METAL_WORKING_SET_PERCENT / 100);
+            let name = run("sysctl", &["-n", "machdep.cpu.brand_string"]).map(|output| output.trim().to_string());
+            gpus.push(GpuInfo {
+                name: name.unwrap_or_else(|| "Apple GPU".to_string()),
+                backend: GpuBackend::Metal,
+                total_vram_mb: memory_mb,
+                free_vram_mb: memory_mb,
+            });
+        }
+
+        // NVIDIA cards show up under Vulkan as well; their CUDA entry knows the memory
+        for name in run("vulkaninfo", &["--summary"]).map(|output| parse_vulkaninfo(&output)).unwrap_or_default() {
+            if !gpus.iter().any(|gpu| gpu.name == name) {
+                gpus.push(GpuInfo { name, backend: GpuBackend::Vulkan, total_vram_mb: None, free_vram_mb: None });
+            }
+        }
+
+        Self { gpu_offload, gpus }
+    }
+
+    /// Free VRAM on the GPU with the most of it
+    pub fn free_vram_mb(&self) -> Option<u64> {
+        self.gpus.iter().filter_map(|gpu| gpu.free_vram_mb).max()
+    }
+
+    /// Layers of a `file_size_bytes` model with `layer_count` blocks that fit in free VRAM, for `n_gpu_layers`
+    /// All of them when everything fits, including the output layer; 0 without offloading or a known VRAM size
+    pub fn gpu_layers(&self, file_size_bytes: u64, layer_count: Option<u64>) -> u32 {
+        if !self.gpu_offload {
+            return 0;
+        }
+        let (Some(free_mb), Some(layers)) = (self.free_vram_mb(), layer_count.filter(|&layers| layers > 0)) else {
+            return 0;
+        };
+        // Embeddings and the output layer take about one more block's worth
+        let layer_mb = (file_size_bytes / (1024 * 1024) / (layers + 1)).max(1);
+        let fit = free_mb.saturating_sub(VRAM_HEADROOM_MB) / layer_mb;
+        fit.min(layers + 1) as u32
+    }
+}
+
+fn run(program: &str, args: &[&str]) -> Option<String> {
+    let output = Command::new(program).args(args).output().ok()?;
+    output.status.success().then(|| String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// Lines of `name, memory.total, memory.free` in MiB
+fn parse_nvidia_smi(output: &str) -> Vec<GpuInfo> {
+    output
+        .lines()
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.split(',').map(str::trim).collect();
+            Some(GpuInfo {
+                name: fields.first().filter(|name| !name.is_empty())?.to_string(),
+                backend: GpuBackend::Cuda,
+                total_vram_mb: fields.get(1).and_then(|field| field.parse().ok()),
+                free_vram_mb: fields.get(2).and_then(|field| field.parse().ok()),
+            })
+        })
+        .collect()
+}
+
+/// Device names from `vulkaninfo --summary`, leaving out software renderers such as llvmpipe
+fn parse_vulkaninfo(output: &str) -> Vec<String> {
+    let mut names = Vec::new();
+    let mut cpu = false;
+    for line in output.lines().map(str::trim) {
+        let Some((key, value)) = line.split_once('=') else { continue };
+        // Each device's block gives its type before its name
+        match key.trim() {
+            "deviceType" => cpu = value.contains("CPU"),
+            "deviceName" if !cpu => names.push(value.trim().to_string()),
+            _ => {}
+        }
+    }
+    names
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_probes() {
+        let gpus = parse_nvidia_smi("NVIDIA GeForce RTX 4090, 24564, 23012\nNVIDIA T4, 15360, [N/A]\n");
+        assert_eq!(gpus.len(), 2);
+        assert_eq!(gpus[0].name, "NVIDIA GeForce RTX 4090");
+        assert_eq!(gpus[0].free_vram_mb, Some(23012));
+        assert_eq!(gpus[1].free_vram_mb, None);
+
+        let summary = "Devices:\n========\nGPU0:\n\tdeviceType         = PHYSICAL_DEVICE_TYPE_DISCRETE_GPU\n\
+                       \tdeviceName         = AMD Radeon RX 7900 XTX\nGPU1:\n\
+                       \tdeviceType         = PHYSICAL_DEVICE_TYPE_CPU\n\tdeviceName         = llvmpipe (LLVM 15.0.7, 256 bits)\n";
+        assert_eq!(parse_vulkaninfo(summary), vec!["AMD Radeon RX 7900 XTX".to_string()]);
+    }
+
+    #[test]
+    fn test_gpu_layers() {
+        let gpu = |free_vram_mb| HardwareInfo {
+            gpu_offload: true,
+            gpus: vec![GpuInfo { name: "gpu".to_string(), backend: GpuBackend::Cuda, total_vram_mb: Some(free_vram_mb), free_vram_mb: Some(free_vram_mb) }],
+        };
+        // 33 layers' worth of 128 MB each
+        let file_size = 33 * 128 * 1024 * 1024;
+        assert_eq!(gpu(24_000).gpu_layers(file_size, Some(32)), 33);
+        assert_eq!(gpu(1024 + 10 * 128).gpu_layers(file_size, Some(32)), 10);
+        assert_eq!(gpu(512).gpu_layers(file_size, Some(32)), 0);
+        assert_eq!(gpu(24_000).gpu_layers(file_size, None), 0);
+        assert_eq!(HardwareInfo { gpu_offload: false, ..gpu(24_000) }.gpu_layers(file_size, Some(32)), 0);
+        assert_eq!(HardwareInfo { gpu_offload: true, gpus: Vec::new() }.gpu_layers(file_size, Some(32)), 0);
+    }
+}
@@ -1,22 +1,54 @@
+pub mod chat_template;
+pub mod gguf;
+pub mod grammar;
+pub mod hardware;
+pub mod queue;
+pub mod session;
+
 use common::{
     errors::{Result, HybridLLMError},
-    traits::LLMProvider,
+    traits::{LLMProvider, QueueDepth},
     types::{Capability, LLMInstance},
-    LLMProviderType,
+    tokens::Tokenizer,
+    CancellationToken, Completion, CompletionRequest, GenerationOptions, LLMProviderType, Priority, StreamChunk, Usage,
 };
 use async_trait::async_trait;
-use std::collections::HashMap;
+use chat_template::ChatTemplate;
+use hardware::HardwareInfo;
+use queue::InferenceQueue;
+use session::{Session, SessionStore};
+use llama_cpp_2::context::params::LlamaContextParams;
+use llama_cpp_2::llama_backend::LlamaBackend;
+use llama_cpp_2::llama_batch::LlamaBatch;
+use llama_cpp_2::model::params::LlamaModelParams;
+use llama_cpp_2::model::{AddBos, LlamaModel, Special};
+use llama_cpp_2::sampling::LlamaSampler;
+use std::future::Future;
+use std::num::NonZeroU32;
 use std::path::{Path, PathBuf};
-use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, OnceLock};
 use tokio::sync::RwLock;
-use tracing::{info, debug, error, warn};
+use tracing::{info, debug, warn};
 
 /// llama.cpp provider for local model inference
 pub struct LlamaCppProvider {
     instance: LLMInstance,
     model_path: PathBuf,
-    model: Arc<RwLock<Option<LlamaModel>>>,
+    model: Arc<RwLock<Option<Arc<LlamaModel>>>>,
+    /// Whether `model` holds the weights, readable without waiting for a load in progress
+    loaded: AtomicBool,
     config: ModelConfig,
+    /// Size of the weights file, which is mapped into memory when loaded
+    file_size: u64,
+    /// The model's own tokenizer, read from its vocabulary on first use
+    tokenizer: OnceLock<Tokenizer>,
+    /// How conversations are laid out for the model, settled on first use
+    chat_template: OnceLock<ChatTemplate>,
+    /// KV caches of recent conversations
+    sessions: Arc<SessionStore>,
+    /// Requests running and waiting for a turn
+    queue: Arc<InferenceQueue>,
 }
 
 /// Configuration for llama.cpp models
@@ -25,11 +57,15 @@ pub struct ModelConfig {
     pub n_ctx: u32,           // Context window size
     pub n_batch: u32,         // Batch size for prompt processing
     pub n_threads: u32,       // Number of threads to use
-    pub n_gpu_layers: u32,    // Number of layers to offload to GPU
+    pub n_gpu_layers: Option<u32>, // Layers to offload to GPU; as many as fit in free VRAM when unset
     pub temperature: f32,     // Sampling temperature
     pub top_p: f32,           // Nucleus sampling
     pub top_k: u32,           // Top-K sampling
     pub repeat_penalty: f32,  // Repetition penalty
+    pub chat_template: Option<ChatTemplate>, // Overrides the one detected from the model
+    pub session_dir: Option<PathBuf>, // Where conversations' KV caches are saved; kept in memory only when unset
+    pub session_persistence: Arc<AtomicBool>, // Whether `session_dir` may be used right now; may be shared and flipped at any time
+    pub max_concurrency: usize, // Requests generating at once, each in a context of its own; the rest wait by priority
 }
 
 impl Default for ModelConfig {
@@ -38,19 +74,69 @@ impl Default for ModelConfig {
             n_ctx: 4096,
             n_batch: 512,
             n_threads: 8,
-            n_gpu_layers: 0,  // CPU-only by default
+            n_gpu_layers: None,
             temperature: 0.7,
             top_p: 0.9,
             top_k: 40,
             repeat_penalty: 1.1,
+            chat_template: None,
+            session_dir: None,
+            session_persistence: Arc::new(AtomicBool::new(true)),
+            max_concurrency: 1,
         }
     }
 }
 
-// Placeholder for actual llama.cpp model
-// In production, this would wrap llama-cpp-2::LlamaModel
-struct LlamaModel {
-    _placeholder: (),
+/// Tokens the repeat penalty looks back over, as in llama.cpp
+const REPEAT_LAST_N: i32 = 64;
+
+/// Seed that has llama.cpp pick a random one
+const RANDOM_SEED: u32 = u32::MAX;
+
+/// Text chunks a stream runs ahead of its reader before generation waits
+const STREAM_BUFFER: usize = 32;
+
+/// The llama.cpp backend, initialized once per process
+fn backend() -> Result<&'static LlamaBackend> {
+    static BACKEND: OnceLock<std::result::Result<LlamaBackend, String>> = OnceLock::new();
+    BACKEND
+        .get_or_init(|| LlamaBackend::init().map_err(|e| e.to_string()))
+        .as_ref()
+        .map_err(|e| HybridLLMError::LLMError(format!("Could not initialize llama.cpp: {}", e)))
+}
+
+/// GPUs on this machine, and whether this build of llama.cpp can offload to them
+/// Runs external tools, so call it off the async runtime
+pub fn detect_hardware() -> HardwareInfo {
+    HardwareInfo::detect(backend().is_ok_and(LlamaBackend::supports_gpu_offload))
+}
+
+/// As many layers of the model at `path` as fit in free VRAM
+fn auto_gpu_layers(path: &Path) -> u32 {
+    let info = match gguf::inspect(path) {
+        Ok(info) => info,
+        Err(e) => {
+            warn!("⚠️  Running on the CPU, the layers of the model aren't known: {}", e);
+            return 0;
+        }
+    };
+    let layers = detect_hardware().gpu_layers(info.file_size_bytes, info.layer_count);
+    info!("🎮 Offloading {} of {} layers to the GPU", layers, info.layer_count.map_or(0, |count| count + 1));
+    layers
+}
+
+fn llama_error(e: impl std::fmt::Display) -> HybridLLMError {
+    HybridLLMError::LLMError(e.to_string())
+}
+
+/// Text generated for one prompt, with the tokens it took
+#[derive(Default)]
+struct Generated {
+    text: String,
+    prompt_tokens: usize,
+    output_tokens: usize,
+    /// The KV cache afterwards, when generating with a session
+    session: Option<Session>,
 }
 
 impl LlamaCppProvider {
@@ -77,6 +163,7 @@ impl LlamaCppProvider {
             .to_string();
 
         let config = config.unwrap_or_default();
+        let file_size = std::fs::metadata(&model_path).map_or(0, |m| m.len());
 
         let instance = LLMInstance {
             id: model_id,
@@ -84,93 +171,389 @@ impl LlamaCppProvider {
             capabilities,
             model_name,
             max_context: config.n_ctx as usize,
-            is_loaded: false,
+            is_loaded: false, // Kept current by `loaded` instead, see `is_loaded`
         };
 
+        let sessions = SessionStore::new(
+            config.session_dir.as_ref().map(|dir| dir.join(&instance.id)),
+            config.n_ctx,
+            Arc::clone(&config.session_persistence),
+        );
+        let queue = InferenceQueue::new(config.max_concurrency);
+
         Ok(Self {
             instance,
             model_path,
             model: Arc::new(RwLock::new(None)),
+            loaded: AtomicBool::new(false),
             config,
+            file_size,
+            tokenizer: OnceLock::new(),
+            chat_template: OnceLock::new(),
+            sessions: Arc::new(sessions),
+            queue: Arc::new(queue),
         })
     }
 
-    /// Load the model into memory
-    async fn load_model(&self) -> Result<()> {
+    /// Load the model into memory unless it already is, returning it either way
+    async fn load_model(&self) -> Result<Arc<LlamaModel>> {
+        // Held throughout, so requests arriving meanwhile wait for this load instead of starting their own
+        let mut model_lock = self.model.write().await;
+        if let Some(model) = model_lock.as_ref() {
+            return Ok(Arc::clone(model));
+        }
         info!("📥 Loading model from: {}", self.model_path.display());
 
-        // TODO: Implement actual llama.cpp loading
-        // This is a placeholder for the MVP
-        // In production, this would use llama-cpp-2 crate:
-        //
-        // let params = LlamaContextParams::default()
-        //     .with_n_ctx(Some(self.config.n_ctx))
-        //     .with_n_batch(self.config.n_batch)
-        //     .with_n_threads(self.config.n_threads)
-        //     .with_n_gpu_layers(self.config.n_gpu_layers);
-        //
-        // let model = LlamaModel::load_from_file(
-        //     &self.model_path,
-        //     params
-        // )?;
+        let path = self.model_path.clone();
+        let configured_layers = self.config.n_gpu_layers;
+        // Probing the GPUs and reading and mapping the weights block for seconds
+        let model = tokio::task::spawn_blocking(move || {
+            let gpu_layers = configured_layers.unwrap_or_else(|| auto_gpu_layers(&path));
+            let params = LlamaModelParams::default().with_n_gpu_layers(gpu_layers);
+            LlamaModel::load_from_file(backend()?, path, &params).map_err(llama_error)
+        })
+        .await
+        .map_err(llama_error)??;
 
-        let mut model_lock = self.model.write().await;
-        *model_lock = Some(LlamaModel { _placeholder: () });
+        let model = Arc::new(model);
+        *model_lock = Some(Arc::clone(&model));
+        self.loaded.store(true, Ordering::SeqCst);
 
-        info!("✅ Model loaded successfully");
-        Ok(())
+        info!("✅ Model loaded successfully, chatting in the {:?} format", self.chat_template());
+        Ok(model)
     }
 
-    /// Unload the model from memory
+    /// Unload the model from memory, saving the KV caches of its conversations
     async fn unload_model(&self) -> Result<()> {
         info!("📤 Unloading model");
         let mut model_lock = self.model.write().await;
         *model_lock = None;
+        self.loaded.store(false, Ordering::SeqCst);
+        let sessions = Arc::clone(&self.sessions);
+        tokio::task::spawn_blocking(move || sessions.persist_all()).await.map_err(llama_error)?;
         info!("✅ Model unloaded");
         Ok(())
     }
 
-    /// Run inference with the loaded model
-    async fn infer(&self, prompt: &str) -> Result<String> {
-        let model_lock = self.model.read().await;
+    /// Complete a request, stopping early once `cancel` fires
+    async fn generate(&self, request: &CompletionRequest, cancel: &CancellationToken) -> Result<Completion> {
+        debug!("💬 Completing prompt with llama.cpp");
+        let model = self.loaded_model().await?;
+        let generated = self.queue_inference(model, request, cancel, |_| true).await?;
+        let usage = generated.usage();
+        Ok(Completion { content: generated.text, usage })
+    }
+
+    /// The configured template, else the one the model's metadata or name points to
+    fn chat_template(&self) -> ChatTemplate {
+        *self.chat_template.get_or_init(|| {
+            self.config.chat_template.unwrap_or_else(|| {
+                let info = gguf::inspect(&self.model_path)
+                    .map_err(|e| warn!("⚠️  Could not read the chat template of {}: {}", self.instance.id, e))
+                    .ok();
+                ChatTemplate::for_model(info.as_ref(), &self.instance.model_name)
+            })
+        })
+    }
+
+    /// The model, loaded on first use if the pool hasn't loaded it yet
+    async fn loaded_model(&self) -> Result<Arc<LlamaModel>> {
+        if let Some(model) = self.model.read().await.clone() {
+            return Ok(model);
+        }
+        self.load_model().await
+    }
+
+    /// Wait for a turn by the request's priority, then run inference on a blocking thread, handing text to
+    /// `emit` as it is generated; cancelled while waiting, nothing is generated
+    /// Sampling follows the request's options where set and the model config otherwise
+    fn queue_inference(
+        &self,
+        model: Arc<LlamaModel>,
+        request: &CompletionRequest,
+        cancel: &CancellationToken,
+        emit: impl FnMut(&str) -> bool + Send + 'static,
+    ) -> impl Future<Output = Result<Generated>> + Send + 'static {
+        let config = self.config.clone();
+        // The model takes one text, so the system prompt and turns are laid out in its chat format
+        let template = self.chat_template();
+        let prompt = template.format(request);
+        let mut options = request.options.clone();
+        options.stop.extend(template.stop_sequences().iter().map(|stop| stop.to_string()));
+        let cancel = cancel.clone();
+        // Turns of one conversation pick up the KV cache the previous turn left
+        let conversation_id = request.context.as_ref().and_then(|context| context.conversation_id);
+        let sessions = Arc::clone(&self.sessions);
+        let queue = Arc::clone(&self.queue);
+        let priority = request.priority;
+
+        async move {
+            let depth = queue.depth();
+            if depth.in_flight >= config.max_concurrency.max(1) {
+                debug!("⏳ Waiting behind {} running and {} queued requests", depth.in_flight, depth.queued);
+            }
+            let permit = tokio::select! {
+                permit = queue.acquire(priority) => permit,
+                _ = cancel.cancelled() => return Ok(Generated::default()),
+            };
+            debug!("🤖 Running inference...");
+            // Decoding holds a thread for the whole generation
+            tokio::task::spawn_blocking(move || {
+                let _permit = permit;
+                let session = conversation_id.map(|id| sessions.take(id).unwrap_or_default());
+                let mut generated = run_inference(&model, &config, &prompt, &options, &cancel, session, emit)?;
+                if let (Some(id), Some(session)) = (conversation_id, generated.session.take()) {
+                    sessions.put(id, session);
+                }
+                Ok(generated)
+            })
+            .await
+            .map_err(llama_error)?
+        }
+    }
+
+    /// Embed `texts` on a blocking thread once the queue gives a turn
+    async fn embed_texts(&self, texts: &[String]) -> Result<Vec<Vec<f32>>> {
+        let model = self.loaded_model().await?;
+        let config = self.config.clone();
+        let texts = texts.to_vec();
+        // Indexing documents can wait for the chats on the same model
+        let permit = self.queue.acquire(Priority::Low).await;
+        debug!("🧮 Embedding {} texts", texts.len());
+        tokio::task::spawn_blocking(move || {
+            let _permit = permit;
+            run_embedding(&model, &config, &texts)
+        })
+        .await
+        .map_err(llama_error)?
+    }
+}
+
+impl Generated {
+    fn usage(&self) -> Usage {
+        Usage {
+            input_tokens: self.prompt_tokens as u64,
+            output_tokens: self.output_tokens as u64,
+            cost_usd: Some(0.0),
+            ..Usage::default()
+        }
+    }
+}
+
+/// Decode `prompt` in batches, then sample until an end-of-generation token, a stop sequence,
+/// the token limit, a full context, `cancel`, or `emit` returning false
+/// Text goes to `emit` as each token completes it, less any tail that may turn out to start a stop sequence
+/// With a `session`, the tokens its cache shares with the prompt aren't decoded again, and the cache is
+/// handed back with the result
+/// A response format in `options` holds sampling to its grammar
+fn run_inference(
+    model: &LlamaModel,
+    config: &ModelConfig,
+    prompt: &str,
+    options: &GenerationOptions,
+    cancel: &CancellationToken,
+    session: Option<Session>,
+    mut emit: impl FnMut(&str) -> bool,
+) -> Result<Generated> {
+    // A schema that can't be turned into a grammar fails before any decoding
+    let grammar = options.response_format.as_ref().map(grammar::to_gbnf).transpose()?;
+    let n_threads = config.n_threads as i32;
+    let params = LlamaContextParams::default()
+        .with_n_ctx(NonZeroU32::new(config.n_ctx))
+        .with_n_batch(config.n_batch)
+        .with_n_threads(n_threads)
+        .with_n_threads_batch(n_threads);
+    let mut ctx = model.new_context(backend()?, params).map_err(llama_error)?;
+
+    let tokens = model.str_to_token(prompt, AddBos::Always).map_err(llama_error)?;
+    let n_ctx = config.n_ctx as usize;
+    if tokens.len() >= n_ctx {
+        return Err(HybridLLMError::InvalidRequest(format!(
+            "The prompt takes {} tokens, more than the {} the context holds",
+            tokens.len(),
+            n_ctx
+        )));
+    }
+
+    let token_ids: Vec<i32> = tokens.iter().map(|token| token.0).collect();
+    let reused = match &session {
+        Some(cached) => match cached.reusable(&token_ids) {
+            0 => 0,
+            // SAFETY: the state was copied out of a context of this model and context size
+            reusable if unsafe { ctx.set_state_data(&cached.state) } == cached.state.len() => {
+                // What the cache holds past the shared prefix belongs to an earlier reply
+                ctx.clear_kv_cache_seq(Some(0), Some(reusable as u32), None).map_err(llama_error)?;
+                debug!("♻️  Reusing {} of {} prompt tokens from the session", reusable, tokens.len());
+                reusable
+            }
+            _ => {
+                warn!("⚠️  Could not restore the session, decoding the whole prompt");
+                ctx.clear_kv_cache();
+                0
+            }
+        },
+        None => 0,
+    };
+    // Tokens in the KV cache, in order
+    let mut cached = token_ids[..reused].to_vec();
+
+    let mut batch = LlamaBatch::new(config.n_batch.max(1) as usize, 1);
+    let mut position = reused as i32;
+    for chunk in tokens[reused..].chunks(config.n_batch.max(1) as usize) {
+        batch.clear();
+        for &token in chunk {
+            // Only the prompt's last token needs logits, to sample the first reply token from
+            let last = position as usize == tokens.len() - 1;
+            batch.add(token, position, &[0], last).map_err(llama_error)?;
+            position += 1;
+        }
+        ctx.decode(&mut batch).map_err(llama_error)?;
+        cached.extend(chunk.iter().map(|token| token.0));
+        if cancel.is_cancelled() {
+            return Ok(Generated { text: String::new(), prompt_tokens: tokens.len(), output_tokens: 0, session: None });
+        }
+    }
+
+    let temperature = options.temperature.unwrap_or(config.temperature);
+    let mut samplers = vec![LlamaSampler::penalties(REPEAT_LAST_N, config.repeat_penalty, 0.0, 0.0)];
+    // Ahead of the rest, so only tokens that keep the reply in shape are left to pick from
+    if let Some(grammar) = &grammar {
+        samplers.push(LlamaSampler::grammar(model, grammar, grammar::ROOT).map_err(llama_error)?);
+    }
+    if temperature <= 0.0 {
+        samplers.push(LlamaSampler::greedy());
+    } else {
+        samplers.extend([
+            LlamaSampler::top_k(config.top_k as i32),
+            LlamaSampler::top_p(options.top_p.unwrap_or(config.top_p), 1),
+            LlamaSampler::temp(temperature),
+            LlamaSampler::dist(options.seed.map_or(RANDOM_SEED, |seed| seed as u32)),
+        ]);
+    }
+    let mut sampler = LlamaSampler::chain_simple(samplers);
+
+    let room = n_ctx - tokens.len();
+    let max_tokens = options.max_tokens.map_or(room, |max| (max as usize).min(room));
+    let mut text = String::new();
+    let mut pending = Vec::new();
+    // Bytes of `text` already emitted; no stop sequence starts before them
+    let mut sent = 0;
+    let mut output_tokens = 0;
+    while output_tokens < max_tokens && !cancel.is_cancelled() {
+        let token = sampler.sample(&ctx, batch.n_tokens() - 1);
+        sampler.accept(token);
+        if model.is_eog_token(token) {
+            break;
+        }
+        output_tokens += 1;
+
+        pending.extend(model.token_to_bytes(token, Special::Tokenize).map_err(llama_error)?);
+        push_utf8(&mut text, &mut pending);
+        if let Some(at) = options.stop.iter().filter_map(|stop| text[sent..].find(stop.as_str())).min() {
+            text.truncate(sent + at);
+            pending.clear();
+            break;
+        }
+        let ready = text.len() - held_back(&text[sent..], &options.stop);
+        if ready > sent {
+            if !emit(&text[sent..ready]) {
+                break;
+            }
+            sent = ready;
+        }
+
+        batch.clear();
+        batch.add(token, position, &[0], true).map_err(llama_error)?;
+        position += 1;
+        ctx.decode(&mut batch).map_err(llama_error)?;
+        cached.push(token.0);
+    }
+    // A reply cut off mid-character keeps what it has
+    text.push_str(&String::from_utf8_lossy(&pending));
+    if text.len() > sent {
+        emit(&text[sent..]);
+    }
+
+    let session = session.map(|_| {
+        let mut state = vec![0; ctx.get_state_size()];
+        // SAFETY: the buffer is as large as llama.cpp says the state is
+        let written = unsafe { ctx.copy_state_data(state.as_mut_ptr()) };
+        state.truncate(written);
+        Session { tokens: cached, state }
+    });
+
+    Ok(Generated { text, prompt_tokens: tokens.len(), output_tokens, session })
+}
 
-        if model_lock.is_none() {
-            return Err(HybridLLMError::LLMError(
-                "Model not loaded".to_string()
-            ));
+/// The pooled, unit-length vector of each text, decoded one at a time in a context that keeps embeddings
+/// Texts longer than a batch are cut to it
+fn run_embedding(model: &LlamaModel, config: &ModelConfig, texts: &[String]) -> Result<Vec<Vec<f32>>> {
+    let n_batch = config.n_batch.max(1);
+    let n_threads = config.n_threads as i32;
+    // Encoder models see the whole text in one micro-batch
+    let params = LlamaContextParams::default()
+        .with_n_ctx(NonZeroU32::new(config.n_ctx.max(n_batch)))
+        .with_n_batch(n_batch)
+        .with_n_ubatch(n_batch)
+        .with_n_threads(n_threads)
+        .with_n_threads_batch(n_threads)
+        .with_embeddings(true);
+    let mut ctx = model.new_context(backend()?, params).map_err(llama_error)?;
+
+    let mut batch = LlamaBatch::new(n_batch as usize, 1);
+    let mut vectors = Vec::with_capacity(texts.len());
+    for text in texts {
+        let mut tokens = model.str_to_token(text, AddBos::Always).map_err(llama_error)?;
+        if tokens.len() > n_batch as usize {
+            debug!("✂️  Embedding the first {} of {} tokens", n_batch, tokens.len());
+            tokens.truncate(n_batch as usize);
         }
+        batch.clear();
+        batch.add_sequence(&tokens, 0, false).map_err(llama_error)?;
+        // Each text is its own sequence, with nothing of the last one in the cache
+        ctx.clear_kv_cache();
+        ctx.decode(&mut batch).map_err(llama_error)?;
+        let mut vector = ctx.embeddings_seq_ith(0).map_err(llama_error)?.to_vec();
+        normalize(&mut vector);
+        vectors.push(vector);
+    }
+    Ok(vectors)
+}
+
+/// Scale `vector` to unit length, so cosine similarity is a dot product; a zero vector stays as it is
+fn normalize(vector: &mut [f32]) {
+    let norm = vector.iter().map(|x| x * x).sum::<f32>().sqrt();
+    if norm > 0.0 {
+        vector.iter_mut().for_each(|x| *x /= norm);
+    }
+}
+
+/// Length of the longest end of `text` that begins one of the `stops`; it is held back until the next token tells
+fn held_back(text: &str, stops: &[String]) -> usize {
+    stops
+        .iter()
+        .flat_map(|stop| (1..stop.len()).filter(move |&n| stop.is_char_boundary(n) && text.ends_with(&stop[..n])))
+        .max()
+        .unwrap_or(0)
+}
 
-        debug!("🤖 Running inference...");
-
-        // TODO: Implement actual inference
-        // This is a placeholder for the MVP
-        // In production:
-        //
-        // let mut session = model.create_session(params)?;
-        // session.advance_context(prompt)?;
-        //
-        // let mut output = String::new();
-        // let mut decoder = session.start_completing_with(
-        //     sampler,
-        //     max_tokens
-        // )?;
-        //
-        // while let Some(token) = decoder.next_token()? {
-        //     output.push_str(&token);
-        // }
-
-        warn!("⚠️  Using placeholder inference (llama.cpp integration pending)");
-
-        Ok(format!(
-            "[llama.cpp placeholder response]\n\nModel: {}\nPrompt: {}\n\n\
-            This is a placeholder. Full llama.cpp integration requires:\n\
-            1. llama-cpp-2 crate properly configured\n\
-            2. Model files in GGUF format\n\
-            3. Actual inference implementation\n\n\
-            The architecture is ready - just needs the bindings wired up!",
-            self.instance.model_name,
-            prompt
-        ))
+/// Move the complete UTF-8 characters of `pending` into `text`; tokens can end mid-character
+fn push_utf8(text: &mut String, pending: &mut Vec<u8>) {
+    match std::str::from_utf8(pending) {
+        Ok(complete) => {
+            text.push_str(complete);
+            pending.clear();
+        }
+        // The rest may complete with the next token
+        Err(e) if e.error_len().is_none() => {
+            let valid = e.valid_up_to();
+            text.push_str(std::str::from_utf8(&pending[..valid]).unwrap_or_default());
+            pending.drain(..valid);
+        }
+        Err(_) => {
+            text.push_str(&String::from_utf8_lossy(pending));
+            pending.clear();
+        }
     }
 }
 
@@ -184,45 +567,34 @@ impl LLMProvider for LlamaCppProvider {
         &self.instance
     }
 
-    async fn complete(
-        &self,
-        prompt: &str,
-        context: HashMap<String, serde_json::Value>,
-    ) -> Result<String> {
-        debug!("💬 Completing prompt with llama.cpp");
-
-        // Check if model is loaded
-        {
-            let model_lock = self.model.read().await;
-            if model_lock.is_none() {
-                return Err(HybridLLMError::LLMError(
-                    "Model not loaded. Call load() first.".to_string()
-                ));
-            }
-        }
-
-        // Build full prompt with system message if provided
-        let full_prompt = if let Some(system) = context.get("system").and_then(|v| v.as_str()) {
-            format!("System: {}\n\nUser: {}", system, prompt)
-        } else {
-            prompt.to_string()
-        };
-
-        self.infer(&full_prompt).await
+    async fn complete(&self, request: CompletionRequest) -> Result<Completion> {
+        self.generate(&request, &CancellationToken::new()).await
     }
 
     async fn complete_stream(
         &self,
-        prompt: &str,
-        context: HashMap<String, serde_json::Value>,
-    ) -> Result<tokio::sync::mpsc::Receiver<Result<String>>> {
-        // TODO: Implement actual streaming
-        // For now, just return the complete response
-        let (tx, rx) = tokio::sync::mpsc::channel(1);
-        let result = self.complete(prompt, context).await;
-
+        request: CompletionRequest,
+        cancel: CancellationToken,
+    ) -> Result<tokio::sync::mpsc::Receiver<Result<StreamChunk>>> {
+        let model = self.loaded_model().await?;
+        let (tx, rx) = tokio::sync::mpsc::channel(STREAM_BUFFER);
+
+        // Waits while the reader is STREAM_BUFFER chunks behind, and fails once it dropped the receiver,
+        // which ends generation
+        let chunks = tx.clone();
+        let emit = move |text: &str| chunks.blocking_send(Ok(StreamChunk::Text(text.to_string()))).is_ok();
+        let inference = self.queue_inference(model, &request, &cancel, emit);
         tokio::spawn(async move {
-            let _ = tx.send(result).await;
+            match inference.await {
+                // A cancelled stream ends without its usage
+                Ok(_) if cancel.is_cancelled() => {}
+                Ok(generated) => {
+                    let _ = tx.send(Ok(StreamChunk::Usage(generated.usage()))).await;
+                }
+                Err(e) => {
+                    let _ = tx.send(Err(e)).await;
+                }
+            }
         });
 
         Ok(rx)
@@ -233,16 +605,42 @@ impl LLMProvider for LlamaCppProvider {
         Ok(self.model_path.exists())
     }
 
-    async fn load(&mut self) -> Result<()> {
-        self.load_model().await?;
+    fn tokenizer(&self) -> Tokenizer {
+        self.tokenizer
+            .get_or_init(|| {
+                Tokenizer::for_model_file(&self.model_path).unwrap_or_else(|e| {
+                    warn!("⚠️  Counting tokens for {} with cl100k: {}", self.instance.id, e);
+                    Tokenizer::for_instance(&self.instance)
+                })
+            })
+            .clone()
+    }
 
-        // Update instance state
-        // Note: We can't directly mutate self.instance.is_loaded through the trait
-        // This is a known limitation - in practice, we'd use Arc<RwLock<LLMInstance>>
-        Ok(())
+    async fn embed(&self, texts: &[String]) -> Result<Vec<Vec<f32>>> {
+        if !self.instance.capabilities.contains(&Capability::Embedding) {
+            return Err(HybridLLMError::LLMError(format!("{} isn't an embedding model", self.instance.id)));
+        }
+        self.embed_texts(texts).await
+    }
+
+    fn queue_depth(&self) -> Option<QueueDepth> {
+        Some(self.queue.depth())
+    }
+
+    fn memory_footprint_bytes(&self) -> Option<u64> {
+        // Weights dominate; the KV cache for `n_ctx` comes on top
+        Some(self.file_size)
     }
 
-    async fn unload(&mut self) -> Result<()> {
+    fn is_loaded(&self) -> bool {
+        self.loaded.load(Ordering::SeqCst)
+    }
+
+    async fn load(&self) -> Result<()> {
+        self.load_model().await.map(|_| ())
+    }
+
+    async fn unload(&self) -> Result<()> {
         self.unload_model().await
     }
 }
@@ -301,7 +699,7 @@ impl LlamaCppProviderBuilder {
     }
 
     pub fn gpu_layers(mut self, n: u32) -> Self {
-        self.config.n_gpu_layers = n;
+        self.config.n_gpu_layers = Some(n);
         self
     }
 
@@ -310,6 +708,26 @@ impl LlamaCppProviderBuilder {
         self
     }
 
+    pub fn chat_template(mut self, template: ChatTemplate) -> Self {
+        self.config.chat_template = Some(template);
+        self
+    }
+
+    pub fn session_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.config.session_dir = Some(dir.into());
+        self
+    }
+
+    pub fn session_persistence(mut self, switch: Arc<AtomicBool>) -> Self {
+        self.config.session_persistence = switch;
+        self
+    }
+
+    pub fn max_concurrency(mut self, n: usize) -> Self {
+        self.config.max_concurrency = n;
+        self
+    }
+
     pub fn build(self) -> Result<LlamaCppProvider> {
         let model_id = self.model_id.ok_or_else(|| {
             HybridLLMError::ConfigError("model_id is required".to_string())
@@ -337,6 +755,42 @@ impl Default for LlamaCppProviderBuilder {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use common::types::RequestContext;
+    use llm_pool::LLMPool;
+    use uuid::Uuid;
+
+    /// A GGUF model for the tests that generate, named by `LLAMA_TEST_MODEL`; they are skipped without one
+    fn test_model() -> Option<PathBuf> {
+        std::env::var_os("LLAMA_TEST_MODEL").map(PathBuf::from).filter(|path| path.is_file())
+    }
+
+    /// Register `model` with a pool as the app registers downloaded models
+    fn register(pool: &LLMPool, model: &Path, session_dir: &Path) -> String {
+        let provider = LlamaCppProviderBuilder::new()
+            .model_id("test-model")
+            .model_path(model)
+            .capability(Capability::General)
+            .session_dir(session_dir)
+            .build()
+            .unwrap();
+        pool.register(Box::new(provider)).unwrap();
+        "test-model".to_string()
+    }
+
+    /// One short turn of a conversation through the pool, returning the text
+    async fn chat(pool: &LLMPool, llm_id: &str, conversation_id: Uuid) -> String {
+        let request = CompletionRequest::prompt("Say hello.")
+            .with_options(GenerationOptions { max_tokens: Some(8), ..GenerationOptions::default() })
+            .with_context(RequestContext::user().with_conversation(conversation_id));
+        let mut chunks = pool.complete_stream(Uuid::new_v4(), llm_id, request).await.unwrap();
+        let mut text = String::new();
+        while let Some(chunk) = chunks.recv().await {
+            if let StreamChunk::Text(chunk) = chunk.unwrap() {
+                text.push_str(&chunk);
+            }
+        }
+        text
+    }
 
     #[test]
     fn test_builder() {
@@ -358,5 +812,82 @@ mod tests {
         let config = ModelConfig::default();
         assert_eq!(config.n_ctx, 4096);
         assert_eq!(config.temperature, 0.7);
+        assert_eq!(config.n_gpu_layers, None);
+        assert_eq!(config.max_concurrency, 1);
+    }
+
+    #[test]
+    fn test_push_utf8() {
+        let mut text = String::new();
+        // "é" split across two tokens
+        let mut pending = b"caf\xc3".to_vec();
+        push_utf8(&mut text, &mut pending);
+        assert_eq!((text.as_str(), pending.as_slice()), ("caf", &b"\xc3"[..]));
+
+        pending.push(0xa9);
+        push_utf8(&mut text, &mut pending);
+        assert_eq!(text, "café");
+        assert!(pending.is_empty());
+
+        pending.extend(b"\xff!");
+        push_utf8(&mut text, &mut pending);
+        assert_eq!(text, "café\u{fffd}!");
+    }
+
+    #[test]
+    fn test_held_back() {
+        let stops = vec!["</answer>".to_string(), "\n\nUser:".to_string()];
+        assert_eq!(held_back("The answer is 4", &stops), 0);
+        assert_eq!(held_back("The answer is 4</ans", &stops), 5);
+        assert_eq!(held_back("The answer is 4\n", &stops), 1);
+        assert_eq!(held_back("The answer is 4\n\nUser", &stops), 6);
+        assert_eq!(held_back("anything", &[]), 0);
+    }
+
+    #[tokio::test]
+    async fn test_register_and_complete() {
+        let Some(model) = test_model() else {
+            return;
+        };
+        let dir = std::env::temp_dir().join(format!("sessions-{}", Uuid::new_v4()));
+        let pool = LLMPool::new();
+        let llm_id = register(&pool, &model, &dir);
+        assert!(!pool.get(&llm_id).unwrap().is_loaded());
+
+        // Nothing loads it first; the request does
+        assert!(!chat(&pool, &llm_id, Uuid::new_v4()).await.is_empty());
+        assert!(pool.get(&llm_id).unwrap().is_loaded());
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[tokio::test]
+    async fn test_unload_through_pool_saves_sessions() {
+        let Some(model) = test_model() else {
+            return;
+        };
+        let dir = std::env::temp_dir().join(format!("sessions-{}", Uuid::new_v4()));
+        let pool = LLMPool::new();
+        let llm_id = register(&pool, &model, &dir);
+
+        let conversation_id = Uuid::new_v4();
+        chat(&pool, &llm_id, conversation_id).await;
+        let session = dir.join(&llm_id).join(format!("{}.kv", conversation_id));
+        assert!(!session.exists());
+
+        pool.unload(&llm_id).await.unwrap();
+        assert!(!pool.get(&llm_id).unwrap().is_loaded());
+        assert!(session.is_file());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_normalize() {
+        let mut vector = vec![3.0, 4.0];
+        normalize(&mut vector);
+        assert_eq!(vector, vec![0.6, 0.8]);
+
+        let mut zero = vec![0.0; 3];
+        normalize(&mut zero);
+        assert_eq!(zero, vec![0.0; 3]);
     }
 }
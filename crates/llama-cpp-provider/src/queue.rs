@@ -0,0 +1,170 @@
+//! Turns at inference, so a model works on a few requests at once and the rest wait by priority
+
+use common::{Priority, QueueDepth};
+use std::cmp::Reverse;
+use std::sync::{Arc, Mutex};
+use tokio::sync::oneshot;
+
+/// Requests running on one model and those waiting for a turn
+pub struct InferenceQueue {
+    max_concurrency: usize,
+    state: Mutex<State>,
+}
+
+#[derive(Default)]
+struct State {
+    running: usize,
+    /// Hands out tickets in arrival order, which breaks ties between equal priorities
+    next_ticket: u64,
+    waiting: Vec<Waiter>,
+}
+
+struct Waiter {
+    ticket: u64,
+    priority: Priority,
+    turn: oneshot::Sender<()>,
+}
+
+impl InferenceQueue {
+    /// Running at most `max_concurrency` requests at once, at least one
+    pub fn new(max_concurrency: usize) -> Self {
+        Self { max_concurrency: max_concurrency.max(1), state: Mutex::new(State::default()) }
+    }
+
+    /// Wait for a turn; higher priorities go first, and the earliest request among equals
+    /// Dropping the future gives up the place in the queue
+    pub async fn acquire(self: &Arc<Self>, priority: Priority) -> InferencePermit {
+        let (ticket, turn) = {
+            let mut state = self.state.lock().unwrap();
+            if state.running < self.max_concurrency {
+                state.running += 1;
+                return InferencePermit { queue: Arc::clone(self) };
+            }
+            let (tx, rx) = oneshot::channel();
+            let ticket = state.next_ticket;
+            state.next_ticket += 1;
+            state.waiting.push(Waiter { ticket, priority, turn: tx });
+            (ticket, rx)
+        };
+
+        let mut waiting = Waiting { queue: self, ticket, granted: false };
+        // The sender only goes away once the turn was handed over
+        let _ = turn.await;
+        waiting.granted = true;
+        InferencePermit { queue: Arc::clone(self) }
+    }
+
+    pub fn depth(&self) -> QueueDepth {
+        let state = self.state.lock().unwrap();
+        QueueDepth { in_flight: state.running, queued: state.waiting.len() }
+    }
+
+    /// Hand a finished request's turn to the most urgent waiter, or free it
+    fn release(&self) {
+        let mut state = self.state.lock().unwrap();
+        loop {
+            let next = state
+                .waiting
+                .iter()
+                .enumerate()
+                .max_by_key(|(_, waiter)| (waiter.priority, Reverse(waiter.ticket)))
+                .map(|(index, _)| index);
+            let Some(index) = next else {
+                state.running -= 1;
+                return;
+            };
+            if state.waiting.remove(index).turn.send(()).is_ok() {
+                return;
+            }
+        }
+    }
+}
+
+/// A place in the queue, given up if the waiting future is dropped
+struct Waiting<'a> {
+    queue: &'a Arc<InferenceQueue>,
+    ticket: u64,
+    granted: bool,
+}
+
+impl Drop for Waiting<'_> {
+    fn drop(&mut self) {
+        if self.granted {
+            return;
+        }
+        let mut state = self.queue.state.lock().unwrap();
+        if let Some(index) = state.waiting.iter().position(|waiter| waiter.ticket == self.ticket) {
+            state.waiting.remove(index);
+        } else {
+            // The turn came just as the request was given up; pass it on
+            drop(state);
+            self.queue.release();
+        }
+    }
+}
+
+/// A turn at inference; hold it until the request is done
+pub struct InferencePermit {
+    queue: Arc<InferenceQueue>,
+}
+
+impl Drop for InferencePermit {
+    fn drop(&mut self) {
+        self.queue.release();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    async fn until_queued(queue: &InferenceQueue, queued: usize) {
+        while queue.depth().queued < queued {
+            tokio::task::yield_now().await;
+        }
+    }
+
+    #[tokio::test]
+    async fn test_priority() {
+        let queue = Arc::new(InferenceQueue::new(1));
+        let first = queue.acquire(Priority::Normal).await;
+        assert_eq!(queue.depth(), QueueDepth { in_flight: 1, queued: 0 });
+
+        let order = Arc::new(Mutex::new(Vec::new()));
+        let mut waiters = Vec::new();
+        for (n, priority) in [Priority::Low, Priority::Normal, Priority::High, Priority::Normal].into_iter().enumerate() {
+            let (waiter_queue, order) = (Arc::clone(&queue), Arc::clone(&order));
+            waiters.push(tokio::spawn(async move {
+                let _permit = waiter_queue.acquire(priority).await;
+                order.lock().unwrap().push(n);
+            }));
+            until_queued(&queue, n + 1).await;
+        }
+        assert_eq!(queue.depth(), QueueDepth { in_flight: 1, queued: 4 });
+
+        drop(first);
+        for waiter in waiters {
+            waiter.await.unwrap();
+        }
+        // The urgent one first, then the normal ones in the order they came
+        assert_eq!(*order.lock().unwrap(), [2, 1, 3, 0]);
+        assert_eq!(queue.depth(), QueueDepth::default());
+    }
+
+    #[tokio::test]
+    async fn test_give_up() {
+        let queue = Arc::new(InferenceQueue::new(2));
+        let first = queue.acquire(Priority::Normal).await;
+        let second = queue.acquire(Priority::Normal).await;
+
+        let gave_up = tokio::time::timeout(Duration::from_millis(10), queue.acquire(Priority::High)).await;
+        assert!(gave_up.is_err());
+        assert_eq!(queue.depth(), QueueDepth { in_flight: 2, queued: 0 });
+
+        drop((first, second));
+        assert_eq!(queue.depth(), QueueDepth::default());
+        let _third = queue.acquire(Priority::Low).await;
+        assert_eq!(queue.depth().in_flight, 1);
+    }
+}
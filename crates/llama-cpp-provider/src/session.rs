@@ -0,0 +1,236 @@
+//! KV caches kept per conversation, so a new turn only decodes what the last one didn't see
+
+use common::errors::{HybridLLMError, Result};
+use std::collections::{HashSet, VecDeque};
+use std::io::{Read, Write};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use tracing::{debug, warn};
+use uuid::Uuid;
+
+/// Sessions held in memory; the least recently used beyond this go to disk
+const MAX_SESSIONS: usize = 4;
+
+/// Start of a session file
+const SESSION_MAGIC: &[u8; 4] = b"HLKV";
+const SESSION_VERSION: u32 = 1;
+
+/// The KV cache of one conversation: the tokens in it, in order, and llama.cpp's state holding them
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Session {
+    pub tokens: Vec<i32>,
+    pub state: Vec<u8>,
+}
+
+impl Session {
+    /// Tokens of `prompt` already in the cache, leaving at least one to decode for the next logits
+    pub fn reusable(&self, prompt: &[i32]) -> usize {
+        let common = self.tokens.iter().zip(prompt).take_while(|(a, b)| a == b).count();
+        common.min(prompt.len().saturating_sub(1))
+    }
+}
+
+/// Sessions of one model by conversation, the recent ones in memory and the rest in `dir` if there is one
+pub struct SessionStore {
+    dir: Option<PathBuf>,
+    /// Whether `dir` may be used right now; while off, evicted sessions are dropped instead
+    persist: Arc<AtomicBool>,
+    /// Conversations with a session kept while `persist` was off, which never go to disk
+    memory_only: Mutex<HashSet<Uuid>>,
+    /// Context size the states were made with; saved sessions of another size are ignored
+    n_ctx: u32,
+    /// Least recently used first
+    sessions: Mutex<VecDeque<(Uuid, Session)>>,
+}
+
+impl SessionStore {
+    pub fn new(dir: Option<PathBuf>, n_ctx: u32, persist: Arc<AtomicBool>) -> Self {
+        Self { dir, persist, memory_only: Mutex::new(HashSet::new()), n_ctx, sessions: Mutex::new(VecDeque::new()) }
+    }
+
+    /// The session of a conversation, from memory or disk; it is the caller's until `put` back
+    /// Reads from disk, so call it off the async runtime
+    pub fn take(&self, conversation_id: Uuid) -> Option<Session> {
+        let mut sessions = self.sessions.lock().unwrap();
+        if let Some(index) = sessions.iter().position(|(id, _)| *id == conversation_id) {
+            return sessions.remove(index).map(|(_, session)| session);
+        }
+        drop(sessions);
+
+        let path = self.path(conversation_id)?;
+        if !self.persist.load(Ordering::SeqCst) || !path.is_file() {
+            return None;
+        }
+        match self.load(&path) {
+            Ok(session) => {
+                debug!("💾 Restored the session of {} ({} tokens)", conversation_id, session.tokens.len());
+                Some(session)
+            }
+            Err(e) => {
+                warn!("⚠️  Dropping the session of {}: {}", conversation_id, e);
+                let _ = std::fs::remove_file(&path);
+                None
+            }
+        }
+    }
+
+    /// Keep the session of a conversation, saving the least recently used one to disk if memory is full
+    pub fn put(&self, conversation_id: Uuid, session: Session) {
+        if !self.persist.load(Ordering::SeqCst) {
+            self.memory_only.lock().unwrap().insert(conversation_id);
+        }
+        let evicted = {
+            let mut sessions = self.sessions.lock().unwrap();
+            sessions.retain(|(id, _)| *id != conversation_id);
+            sessions.push_back((conversation_id, session));
+            if sessions.len() > MAX_SESSIONS { sessions.pop_front() } else { None }
+        };
+        if let Some((id, session)) = evicted {
+            self.save(id, &session);
+        }
+    }
+
+    /// Save every session in memory to disk and free them, e.g. when the model is unloaded
+    pub fn persist_all(&self) {
+        let sessions = std::mem::take(&mut *self.sessions.lock().unwrap());
+        for (id, session) in sessions {
+            self.save(id, &session);
+        }
+    }
+
+    fn path(&self, conversation_id: Uuid) -> Option<PathBuf> {
+        self.dir.as_ref().map(|dir| dir.join(format!("{}.kv", conversation_id)))
+    }
+
+    fn save(&self, conversation_id: Uuid, session: &Session) {
+        let Some(path) = self.path(conversation_id) else {
+            return;
+        };
+        if !self.persist.load(Ordering::SeqCst) || self.memory_only.lock().unwrap().contains(&conversation_id) {
+            return;
+        }
+        let write = || -> std::io::Result<()> {
+            if let Some(parent) = path.parent() {
+                std::fs::create_dir_all(parent)?;
+            }
+            let mut file = std::io::BufWriter::new(std::fs::File::create(&path)?);
+            file.write_all(SESSION_MAGIC)?;
+            file.write_all(&SESSION_VERSION.to_le_bytes())?;
+            file.write_all(&self.n_ctx.to_le_bytes())?;
+            file.write_all(&(session.tokens.len() as u64).to_le_bytes())?;
+            for token in &session.tokens {
+                file.write_all(&token.to_le_bytes())?;
+            }
+            file.write_all(&session.state)?;
+            file.flush()
+        };
+        match write() {
+            Ok(()) => debug!("💾 Saved the session of {} ({} tokens)", conversation_id, session.tokens.len()),
+            Err(e) => warn!("⚠️  Could not save the session of {}: {}", conversation_id, e),
+        }
+    }
+
+    fn load(&self, path: &std::path::Path) -> Result<Session> {
+        let invalid = |message: &str| HybridLLMError::InvalidRequest(format!("{}: {}", path.display(), message));
+        let mut bytes = Vec::new();
+        std::fs::File::open(path)
+            .and_then(|mut file| file.read_to_end(&mut bytes))
+            .map_err(|e| HybridLLMError::FileSystemError(e.to_string()))?;
+
+        let mut reader = bytes.as_slice();
+        let mut take = |n: usize| -> Result<&[u8]> {
+            if reader.len() < n {
+                return Err(invalid("cut off"));
+            }
+            let (head, rest) = reader.split_at(n);
+            reader = rest;
+            Ok(head)
+        };
+        if take(4)? != SESSION_MAGIC || take(4)? != SESSION_VERSION.to_le_bytes() {
+            return Err(invalid("not a session file of this version"));
+        }
+        if take(4)? != self.n_ctx.to_le_bytes() {
+            return Err(invalid("made with another context size"));
+        }
+        let count = u64::from_le_bytes(take(8)?.try_into().unwrap()) as usize;
+        if count > self.n_ctx as usize {
+            return Err(invalid("more tokens than the context holds"));
+        }
+        let tokens = take(count * 4)?.chunks_exact(4).map(|token| i32::from_le_bytes(token.try_into().unwrap())).collect();
+        Ok(Session { tokens, state: reader.to_vec() })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn session(tokens: &[i32]) -> Session {
+        Session { tokens: tokens.to_vec(), state: tokens.iter().map(|&token| token as u8).collect() }
+    }
+
+    #[test]
+    fn test_reusable() {
+        let cached = session(&[1, 2, 3, 4]);
+        assert_eq!(cached.reusable(&[1, 2, 3, 4, 5, 6]), 4);
+        assert_eq!(cached.reusable(&[1, 2, 9]), 2);
+        // The whole prompt is cached, but its last token is decoded again for logits
+        assert_eq!(cached.reusable(&[1, 2, 3]), 2);
+        assert_eq!(cached.reusable(&[]), 0);
+    }
+
+    #[test]
+    fn test_session_store() {
+        let dir = std::env::temp_dir().join(format!("sessions-{}", Uuid::new_v4()));
+        let store = SessionStore::new(Some(dir.clone()), 4096, Arc::new(AtomicBool::new(true)));
+        let ids: Vec<Uuid> = (0..=MAX_SESSIONS).map(|_| Uuid::new_v4()).collect();
+        for (n, id) in ids.iter().enumerate() {
+            store.put(*id, session(&[n as i32, 7]));
+        }
+        // The first session went to disk to make room and comes back from there
+        assert!(dir.join(format!("{}.kv", ids[0])).is_file());
+        assert_eq!(store.take(ids[0]), Some(session(&[0, 7])));
+        assert_eq!(store.take(ids[1]), Some(session(&[1, 7])));
+        assert_eq!(store.take(ids[1]), None);
+
+        store.persist_all();
+        assert_eq!(store.take(ids[2]), Some(session(&[2, 7])));
+        // Another context size can't restore the state
+        assert_eq!(SessionStore::new(Some(dir.clone()), 2048, Arc::new(AtomicBool::new(true))).take(ids[3]), None);
+
+        let memory_only = SessionStore::new(None, 4096, Arc::new(AtomicBool::new(true)));
+        memory_only.put(ids[0], session(&[1]));
+        memory_only.persist_all();
+        assert_eq!(memory_only.take(ids[0]), None);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_session_store_without_persistence() {
+        let dir = std::env::temp_dir().join(format!("sessions-{}", Uuid::new_v4()));
+        let persist = Arc::new(AtomicBool::new(false));
+        let store = SessionStore::new(Some(dir.clone()), 4096, Arc::clone(&persist));
+        let ids: Vec<Uuid> = (0..=MAX_SESSIONS).map(|_| Uuid::new_v4()).collect();
+        for (n, id) in ids.iter().enumerate() {
+            store.put(*id, session(&[n as i32, 7]));
+        }
+        // Evicted sessions are dropped rather than saved
+        assert_eq!(store.take(ids[0]), None);
+        assert!(!dir.exists());
+
+        // Nor are sessions kept while off saved once it is back on
+        persist.store(true, Ordering::SeqCst);
+        store.put(ids[1], store.take(ids[1]).unwrap());
+        let kept_while_on = Uuid::new_v4();
+        store.put(kept_while_on, session(&[9]));
+        store.persist_all();
+        assert!(!dir.join(format!("{}.kv", ids[1])).exists());
+        assert!(dir.join(format!("{}.kv", kept_while_on)).is_file());
+
+        // Nor restored while it is off
+        persist.store(false, Ordering::SeqCst);
+        assert_eq!(store.take(kept_while_on), None);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,191 @@
+use chrono::{DateTime, Datelike, Utc};
+use common::{LLMProvider, QueueDepth, Usage};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+/// Requests, health and spending of one registered LLM
+pub(crate) struct Activity {
+    /// `None` for providers that take any number of requests at once
+    slots: Option<Arc<Semaphore>>,
+    in_flight: AtomicUsize,
+    queued: AtomicUsize,
+    health: Mutex<Option<HealthCheck>>,
+    spend: Mutex<MonthlySpend>,
+    /// Completed requests since registration
+    usage: Mutex<Usage>,
+}
+
+/// Outcome of the last health check of an LLM
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct HealthCheck {
+    pub healthy: bool,
+    pub checked_at: DateTime<Utc>,
+}
+
+/// Spending in the current calendar month, in US dollars
+#[derive(Default)]
+struct MonthlySpend {
+    month: Option<(i32, u32)>,
+    usd: f64,
+}
+
+impl Activity {
+    /// `concurrency` is how many requests the LLM works on at once, `None` for no limit
+    pub fn new(concurrency: Option<usize>) -> Self {
+        Self {
+            slots: concurrency.map(|n| Arc::new(Semaphore::new(n))),
+            in_flight: AtomicUsize::new(0),
+            queued: AtomicUsize::new(0),
+            health: Mutex::new(None),
+            spend: Mutex::new(MonthlySpend::default()),
+            usage: Mutex::new(Usage::default()),
+        }
+    }
+
+    /// Wait for a free slot; the request counts as queued until it gets one
+    pub async fn acquire(self: &Arc<Self>) -> RequestPermit {
+        let queued = Queued::new(Arc::clone(self));
+        let slot = match &self.slots {
+            // The semaphore is never closed
+            Some(slots) => Some(Arc::clone(slots).acquire_owned().await.expect("request slots closed")),
+            None => None,
+        };
+        drop(queued);
+
+        self.in_flight.fetch_add(1, Ordering::Relaxed);
+        RequestPermit { activity: Arc::clone(self), _slot: slot }
+    }
+
+    pub fn in_flight(&self) -> usize {
+        self.in_flight.load(Ordering::Relaxed)
+    }
+
+    pub fn queued(&self) -> usize {
+        self.queued.load(Ordering::Relaxed)
+    }
+
+    /// The queue of `provider` if it keeps its own, else the requests waiting here
+    pub fn queue_depth(&self, provider: &dyn LLMProvider) -> QueueDepth {
+        provider.queue_depth().unwrap_or(QueueDepth { in_flight: self.in_flight(), queued: self.queued() })
+    }
+
+    pub fn health(&self) -> Option<HealthCheck> {
+        *self.health.lock().unwrap()
+    }
+
+    pub fn set_health(&self, healthy: bool) {
+        *self.health.lock().unwrap() = Some(HealthCheck { healthy, checked_at: Utc::now() });
+    }
+
+    pub fn record_spend(&self, usd: f64, at: DateTime<Utc>) {
+        let mut spend = self.spend.lock().unwrap();
+        let month = Some((at.year(), at.month()));
+        if spend.month != month {
+            *spend = MonthlySpend { month, usd: 0.0 };
+        }
+        spend.usd += usd;
+    }
+
+    /// Add a completed request to the totals, and its cost to the month of `at`
+    pub fn record_usage(&self, usage: &Usage, at: DateTime<Utc>) {
+        *self.usage.lock().unwrap() += *usage;
+        if let Some(usd) = usage.cost_usd {
+            self.record_spend(usd, at);
+        }
+    }
+
+    pub fn usage(&self) -> Usage {
+        *self.usage.lock().unwrap()
+    }
+
+    /// Spent in the month of `now`; nothing once a new month has started
+    pub fn spent_usd(&self, now: DateTime<Utc>) -> f64 {
+        let spend = self.spend.lock().unwrap();
+        if spend.month == Some((now.year(), now.month())) {
+            spend.usd
+        } else {
+            0.0
+        }
+    }
+}
+
+/// Counts a request as queued until dropped, including when the waiting task is cancelled
+struct Queued(Arc<Activity>);
+
+impl Queued {
+    fn new(activity: Arc<Activity>) -> Self {
+        activity.queued.fetch_add(1, Ordering::Relaxed);
+        Self(activity)
+    }
+}
+
+impl Drop for Queued {
+    fn drop(&mut self) {
+        self.0.queued.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// A request an LLM is working on; hold it until the request is finished or cancelled
+pub struct RequestPermit {
+    activity: Arc<Activity>,
+    _slot: Option<OwnedSemaphorePermit>,
+}
+
+impl Drop for RequestPermit {
+    fn drop(&mut self) {
+        self.activity.in_flight.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[tokio::test]
+    async fn test_requests_queue_for_a_slot() {
+        let activity = Arc::new(Activity::new(Some(1)));
+
+        let first = activity.acquire().await;
+        let waiting = tokio::spawn({
+            let activity = Arc::clone(&activity);
+            async move { activity.acquire().await }
+        });
+        tokio::task::yield_now().await;
+        assert_eq!((activity.in_flight(), activity.queued()), (1, 1));
+
+        drop(first);
+        let second = waiting.await.unwrap();
+        assert_eq!((activity.in_flight(), activity.queued()), (1, 0));
+
+        // Cancelling a waiting request takes it off the queue
+        let waiting = tokio::spawn({
+            let activity = Arc::clone(&activity);
+            async move { activity.acquire().await }
+        });
+        tokio::task::yield_now().await;
+        assert_eq!(activity.queued(), 1);
+        waiting.abort();
+        let _ = waiting.await;
+        assert_eq!(activity.queued(), 0);
+
+        drop(second);
+        assert_eq!(activity.in_flight(), 0);
+    }
+
+    #[test]
+    fn test_spend_resets_monthly() {
+        let activity = Activity::new(None);
+        let march = Utc.with_ymd_and_hms(2024, 3, 10, 12, 0, 0).unwrap();
+        let april = Utc.with_ymd_and_hms(2024, 4, 1, 0, 0, 0).unwrap();
+
+        activity.record_spend(1.25, march);
+        activity.record_spend(0.5, march);
+        assert_eq!(activity.spent_usd(march), 1.75);
+        assert_eq!(activity.spent_usd(april), 0.0);
+
+        activity.record_spend(0.25, april);
+        assert_eq!(activity.spent_usd(april), 0.25);
+    }
+}
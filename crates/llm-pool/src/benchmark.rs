@@ -0,0 +1,359 @@
+use chrono::{DateTime, Utc};
+use common::{
+    errors::{HybridLLMError, Result},
+    types::{LLMProvider as LLMProviderType, RequestContext},
+    CompletionRequest, GenerationOptions, StreamChunk,
+};
+use serde::{Deserialize, Serialize};
+use std::time::{Duration, Instant};
+use tracing::{info, warn};
+use uuid::Uuid;
+
+use crate::store::RunStore;
+use crate::LLMPool;
+
+/// Longest one prompt may take before the run counts as failed
+const RUN_TIMEOUT: Duration = Duration::from_secs(180);
+
+/// Distinct error messages kept per LLM
+const MAX_ERRORS: usize = 5;
+
+/// One prompt of a suite
+#[derive(Debug, Clone, Copy)]
+pub struct BenchmarkPrompt {
+    pub name: &'static str,
+    pub prompt: &'static str,
+    pub max_tokens: u32,
+}
+
+/// Prompts every LLM is measured with: short and long answers, code and reasoning
+pub const STANDARD_SUITE: &[BenchmarkPrompt] = &[
+    BenchmarkPrompt { name: "short_answer", prompt: "What is the capital of Australia? Answer in one sentence.", max_tokens: 64 },
+    BenchmarkPrompt {
+        name: "summary",
+        prompt: "Summarize in three bullet points: The printing press, invented by Johannes Gutenberg around 1440, \
+                 made books cheaper and faster to produce. Literacy spread, scientific ideas travelled across Europe \
+                 within years instead of decades, and the Reformation used pamphlets to reach ordinary people.",
+        max_tokens: 160,
+    },
+    BenchmarkPrompt {
+        name: "code",
+        prompt: "Write a Python function that returns the n-th Fibonacci number iteratively, with a docstring.",
+        max_tokens: 256,
+    },
+    BenchmarkPrompt {
+        name: "reasoning",
+        prompt: "A train leaves at 14:10 and arrives at 17:45, stopping twice for 12 minutes each. How long was it \
+                 moving? Show your steps.",
+        max_tokens: 256,
+    },
+    BenchmarkPrompt {
+        name: "long_generation",
+        prompt: "Write a 400-word short story about a lighthouse keeper who finds a message in a bottle.",
+        max_tokens: 640,
+    },
+];
+
+/// What to benchmark and how often
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BenchmarkOptions {
+    pub llm_ids: Vec<String>,
+    /// Times each prompt is sent to each LLM
+    #[serde(default = "default_repetitions")]
+    pub repetitions: u32,
+}
+
+fn default_repetitions() -> u32 {
+    1
+}
+
+/// How one LLM did on the suite
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BenchmarkResult {
+    pub llm_id: String,
+    pub provider: LLMProviderType,
+    /// For local models the file name, which names the quantization
+    pub model_name: String,
+    pub runs: u32,
+    pub failures: u32,
+    pub failure_rate: f64,
+    /// Of successful runs, from sending the prompt to the end of the answer
+    pub mean_latency_ms: u64,
+    pub p95_latency_ms: u64,
+    /// Of successful runs, until the first text arrived
+    pub mean_first_token_ms: u64,
+    /// Output tokens per second after the first token, over all successful runs
+    pub tokens_per_second: f64,
+    pub input_tokens: u64,
+    pub output_tokens: u64,
+    /// `None` when the provider didn't say what it charged
+    pub cost_usd: Option<f64>,
+    pub errors: Vec<String>,
+}
+
+/// A benchmark of one or more LLMs
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BenchmarkRun {
+    pub id: Uuid,
+    pub started_at: DateTime<Utc>,
+    pub finished_at: DateTime<Utc>,
+    /// Prompts per LLM, repetitions included
+    pub prompts: u32,
+    pub results: Vec<BenchmarkResult>,
+}
+
+/// One sent prompt
+struct Sample {
+    latency: Duration,
+    first_token: Duration,
+    input_tokens: u64,
+    output_tokens: u64,
+    cost_usd: Option<f64>,
+}
+
+/// Send every prompt of `suite` to each LLM in turn, `repetitions` times
+/// LLMs run one after another so they don't slow each other down; unknown ids fail every run
+pub async fn run_benchmark(pool: &LLMPool, options: &BenchmarkOptions, suite: &[BenchmarkPrompt]) -> BenchmarkRun {
+    let started_at = Utc::now();
+    let mut results = Vec::new();
+    for llm_id in &options.llm_ids {
+        info!("⏱️  Benchmarking {} with {} prompts", llm_id, suite.len() as u32 * options.repetitions);
+        let mut samples = Vec::new();
+        let mut errors = Vec::new();
+        for _ in 0..options.repetitions {
+            for prompt in suite {
+                match sample(pool, llm_id, prompt).await {
+                    Ok(sample) => samples.push(Some(sample)),
+                    Err(e) => {
+                        warn!("⏱️  {} failed {}: {}", llm_id, prompt.name, e);
+                        samples.push(None);
+                        if errors.len() < MAX_ERRORS && !errors.contains(&e.to_string()) {
+                            errors.push(e.to_string());
+                        }
+                    }
+                }
+            }
+        }
+        results.push(summarize(pool, llm_id, samples, errors));
+    }
+
+    BenchmarkRun {
+        id: Uuid::new_v4(),
+        started_at,
+        finished_at: Utc::now(),
+        prompts: suite.len() as u32 * options.repetitions,
+        results,
+    }
+}
+
+async fn sample(pool: &LLMPool, llm_id: &str, prompt: &BenchmarkPrompt) -> Result<Sample> {
+    let request_id = Uuid::new_v4();
+    // Fixed sampling, so LLMs and runs are compared on the same footing
+    let options = GenerationOptions { max_tokens: Some(prompt.max_tokens), temperature: Some(0.0), seed: Some(42), ..Default::default() };
+    let request = CompletionRequest::prompt(prompt.prompt)
+        .with_options(options)
+        .with_context(RequestContext::user().with_trace_id(request_id).with_llm(llm_id));
+
+    let started = Instant::now();
+    let measure = async {
+        let mut chunks = pool.complete_stream(request_id, llm_id, request).await?;
+        let mut first_token = None;
+        let mut usage = None;
+        while let Some(chunk) = chunks.recv().await {
+            match chunk? {
+                StreamChunk::Text(_) => {
+                    first_token.get_or_insert_with(|| started.elapsed());
+                }
+                StreamChunk::Usage(reported) => usage = Some(reported),
+            }
+        }
+        let usage = usage.unwrap_or_default();
+        Ok(Sample {
+            latency: started.elapsed(),
+            first_token: first_token.unwrap_or_else(|| started.elapsed()),
+            input_tokens: usage.input_tokens,
+            output_tokens: usage.output_tokens,
+            cost_usd: usage.cost_usd,
+        })
+    };
+    // Dropping the stream on timeout cancels it in the pool
+    tokio::time::timeout(RUN_TIMEOUT, measure)
+        .await
+        .unwrap_or_else(|_| Err(HybridLLMError::Timeout(format!("No answer within {} seconds", RUN_TIMEOUT.as_secs()))))
+}
+
+fn summarize(pool: &LLMPool, llm_id: &str, samples: Vec<Option<Sample>>, errors: Vec<String>) -> BenchmarkResult {
+    let runs = samples.len() as u32;
+    let succeeded: Vec<Sample> = samples.into_iter().flatten().collect();
+    let failures = runs - succeeded.len() as u32;
+
+    let mut latencies: Vec<u64> = succeeded.iter().map(|sample| sample.latency.as_millis() as u64).collect();
+    latencies.sort_unstable();
+    let mean = |values: &mut dyn Iterator<Item = u64>| match succeeded.len() as u64 {
+        0 => 0,
+        n => values.sum::<u64>() / n,
+    };
+    let generating: f64 = succeeded.iter().map(|sample| (sample.latency - sample.first_token).as_secs_f64()).sum();
+    let output_tokens = succeeded.iter().map(|sample| sample.output_tokens).sum();
+    let (provider, model_name) = match pool.get(llm_id) {
+        Some(llm) => (llm.instance().provider.clone(), llm.instance().model_name.clone()),
+        None => (LLMProviderType::Local(llm_id.to_string()), llm_id.to_string()),
+    };
+
+    BenchmarkResult {
+        llm_id: llm_id.to_string(),
+        provider,
+        model_name,
+        runs,
+        failures,
+        failure_rate: if runs == 0 { 0.0 } else { failures as f64 / runs as f64 },
+        mean_latency_ms: mean(&mut latencies.iter().copied()),
+        p95_latency_ms: percentile(&latencies, 0.95),
+        mean_first_token_ms: mean(&mut succeeded.iter().map(|sample| sample.first_token.as_millis() as u64)),
+        tokens_per_second: if generating > 0.0 { output_tokens as f64 / generating } else { 0.0 },
+        input_tokens: succeeded.iter().map(|sample| sample.input_tokens).sum(),
+        output_tokens,
+        // Unknown as soon as one run's cost is
+        cost_usd: succeeded.iter().map(|sample| sample.cost_usd).sum(),
+        errors,
+    }
+}
+
+/// Nearest-rank percentile of sorted values
+fn percentile(sorted: &[u64], p: f64) -> u64 {
+    match sorted.len() {
+        0 => 0,
+        n => sorted[((p * n as f64).ceil() as usize).clamp(1, n) - 1],
+    }
+}
+
+/// Past benchmark runs, kept as JSON lines so results can be compared across sessions
+pub type BenchmarkStore = RunStore<BenchmarkRun>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use async_trait::async_trait;
+    use common::types::{Capability, LLMInstance};
+    use common::{CancellationToken, Completion, LLMProvider, Usage};
+    use tokio::sync::mpsc;
+
+    /// Answers with `tokens` tokens, failing every prompt mentioning "Fibonacci"
+    struct Counter {
+        instance: LLMInstance,
+        tokens: u64,
+    }
+
+    #[async_trait]
+    impl LLMProvider for Counter {
+        fn capabilities(&self) -> Vec<Capability> {
+            self.instance.capabilities.clone()
+        }
+
+        fn instance(&self) -> &LLMInstance {
+            &self.instance
+        }
+
+        async fn complete(&self, _request: CompletionRequest) -> Result<Completion> {
+            Ok(Completion { content: String::new(), usage: Usage::default() })
+        }
+
+        async fn complete_stream(
+            &self,
+            request: CompletionRequest,
+            _cancel: CancellationToken,
+        ) -> Result<mpsc::Receiver<Result<StreamChunk>>> {
+            if request.messages.iter().any(|message| message.content.contains("Fibonacci")) {
+                return Err(HybridLLMError::LLMError("model crashed".to_string()));
+            }
+            let (tx, rx) = mpsc::channel(4);
+            let tokens = self.tokens;
+            tokio::spawn(async move {
+                for _ in 0..tokens {
+                    tokio::time::sleep(Duration::from_millis(2)).await;
+                    let _ = tx.send(Ok(StreamChunk::Text("x".to_string()))).await;
+                }
+                let usage = Usage { input_tokens: 10, output_tokens: tokens, cost_usd: Some(0.0), latency_ms: 0 };
+                let _ = tx.send(Ok(StreamChunk::Usage(usage))).await;
+            });
+            Ok(rx)
+        }
+
+        async fn health_check(&self) -> Result<bool> {
+            Ok(true)
+        }
+
+        async fn load(&self) -> Result<()> {
+            Ok(())
+        }
+
+        async fn unload(&self) -> Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_percentile() {
+        assert_eq!(percentile(&[], 0.95), 0);
+        assert_eq!(percentile(&[7], 0.95), 7);
+        let sorted: Vec<u64> = (1..=20).collect();
+        assert_eq!(percentile(&sorted, 0.95), 19);
+        assert_eq!(percentile(&sorted, 0.5), 10);
+    }
+
+    #[tokio::test]
+    async fn test_run_and_store() {
+        let pool = LLMPool::new();
+        pool.register(Box::new(Counter {
+            instance: LLMInstance {
+                id: "local".to_string(),
+                provider: LLMProviderType::Local("local".to_string()),
+                capabilities: vec![Capability::General],
+                model_name: "llama-3-8b.Q4_K_M.gguf".to_string(),
+                max_context: 4096,
+                is_loaded: true,
+            },
+            tokens: 5,
+        }))
+        .unwrap();
+
+        let options = BenchmarkOptions { llm_ids: vec!["local".to_string(), "missing".to_string()], repetitions: 2 };
+        let run = run_benchmark(&pool, &options, STANDARD_SUITE).await;
+        assert_eq!(run.prompts, 10);
+
+        let local = &run.results[0];
+        assert_eq!(local.model_name, "llama-3-8b.Q4_K_M.gguf");
+        assert_eq!((local.runs, local.failures), (10, 2));
+        assert!((local.failure_rate - 0.2).abs() < f64::EPSILON);
+        assert_eq!(local.output_tokens, 40);
+        assert_eq!(local.cost_usd, Some(0.0));
+        assert!(local.tokens_per_second > 0.0);
+        assert!(local.p95_latency_ms >= local.mean_first_token_ms);
+        assert_eq!(local.errors, vec!["LLM error: model crashed".to_string()]);
+
+        let missing = &run.results[1];
+        assert_eq!((missing.runs, missing.failures), (10, 10));
+        assert_eq!(missing.tokens_per_second, 0.0);
+
+        let dir = std::env::temp_dir().join(format!("benchmarks-{}", Uuid::new_v4()));
+        let path = dir.join("benchmarks.jsonl");
+        BenchmarkStore::open(&path).unwrap().record(&run).unwrap();
+        // Floats don't always survive JSON to the last bit, so they are compared with a tolerance and the rest exactly
+        let close = |a: f64, b: f64| (a - b).abs() <= 1e-9 * a.abs().max(b.abs()).max(1.0);
+        let mut reopened = BenchmarkStore::open(&path).unwrap().list();
+        assert_eq!(reopened.len(), 1);
+        assert_eq!(reopened[0].results.len(), run.results.len());
+        for (read, written) in reopened[0].results.iter_mut().zip(&run.results) {
+            assert!(close(read.failure_rate, written.failure_rate));
+            assert!(close(read.tokens_per_second, written.tokens_per_second));
+            match (read.cost_usd, written.cost_usd) {
+                (Some(a), Some(b)) => assert!(close(a, b)),
+                (a, b) => assert_eq!(a, b),
+            }
+            (read.failure_rate, read.tokens_per_second, read.cost_usd) =
+                (written.failure_rate, written.tokens_per_second, written.cost_usd);
+        }
+        assert_eq!(reopened, vec![run]);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
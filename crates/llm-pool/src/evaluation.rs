@@ -0,0 +1,542 @@
+use chrono::{DateTime, Utc};
+use common::{
+    errors::{HybridLLMError, Result},
+    types::{MessageRole, RequestContext},
+    CompletionMessage, CompletionRequest, GenerationOptions, Priority, ResponseFormat, StreamChunk, Usage,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::time::{Duration, Instant};
+use tracing::{info, warn};
+use uuid::Uuid;
+
+use crate::store::RunStore;
+use crate::LLMPool;
+
+/// Longest one answer or verdict may take before it counts as failed
+const ANSWER_TIMEOUT: Duration = Duration::from_secs(180);
+
+/// Room the judge gets for its scores and reasoning
+const JUDGE_MAX_TOKENS: u32 = 768;
+
+/// Scores the judge gives range from 1 to this
+pub const MAX_SCORE: u8 = 10;
+
+const JUDGE_SYSTEM_PROMPT: &str = "You are an impartial judge comparing two AI assistants' answers to the same \
+    conversation. Judge only the final answer to the last user message. Ignore answer length and position, and \
+    do not favour either assistant by name. Reply with a single JSON object and nothing else.";
+
+/// A model and the settings it answers with
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EvalCandidate {
+    pub llm_id: String,
+    /// System prompt sent with every case
+    #[serde(default)]
+    pub system: Option<String>,
+    #[serde(default)]
+    pub options: GenerationOptions,
+}
+
+/// Something the judge scores each answer on
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EvalCriterion {
+    pub name: String,
+    /// What a high score means, shown to the judge
+    pub description: String,
+}
+
+/// Criteria used when none are given
+pub fn default_criteria() -> Vec<EvalCriterion> {
+    [
+        ("correctness", "Facts, code and reasoning are right, and match the reference answer when there is one"),
+        ("helpfulness", "Answers what was asked, completely, with what the user needs to act on it"),
+        ("clarity", "Easy to follow, well organised and no longer than it needs to be"),
+    ]
+    .into_iter()
+    .map(|(name, description)| EvalCriterion { name: name.to_string(), description: description.to_string() })
+    .collect()
+}
+
+/// One prompt to answer: a test-set question, or a stored conversation up to a user message
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EvalCase {
+    pub name: String,
+    /// Ends with the user message both candidates answer
+    pub messages: Vec<CompletionMessage>,
+    /// A known good answer the judge compares against
+    #[serde(default)]
+    pub reference: Option<String>,
+}
+
+impl EvalCase {
+    pub fn prompt(name: impl Into<String>, prompt: impl Into<String>) -> Self {
+        Self { name: name.into(), messages: vec![CompletionMessage::new(MessageRole::User, prompt)], reference: None }
+    }
+}
+
+/// The two candidates to compare, and who judges them on what
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EvaluationOptions {
+    pub a: EvalCandidate,
+    pub b: EvalCandidate,
+    pub judge_llm_id: String,
+    #[serde(default = "default_criteria")]
+    pub criteria: Vec<EvalCriterion>,
+}
+
+/// What one candidate said to a case
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EvalAnswer {
+    /// `None` when the candidate failed
+    pub content: Option<String>,
+    pub error: Option<String>,
+    pub usage: Usage,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EvalWinner {
+    A,
+    B,
+    Tie,
+}
+
+/// The judge's decision on one case
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EvalVerdict {
+    pub winner: EvalWinner,
+    /// By criterion name; empty when only one candidate answered and the judge wasn't asked
+    pub scores_a: BTreeMap<String, u8>,
+    pub scores_b: BTreeMap<String, u8>,
+    pub reasoning: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EvalCaseResult {
+    pub name: String,
+    pub a: EvalAnswer,
+    pub b: EvalAnswer,
+    /// `None` when neither candidate answered or the judge failed
+    pub verdict: Option<EvalVerdict>,
+    pub judge_error: Option<String>,
+}
+
+/// Totals over every case of a report
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct EvalSummary {
+    pub wins_a: u32,
+    pub wins_b: u32,
+    pub ties: u32,
+    /// Cases without a verdict
+    pub unjudged: u32,
+    pub failures_a: u32,
+    pub failures_b: u32,
+    /// Per criterion, over the cases the judge scored
+    pub mean_scores_a: BTreeMap<String, f64>,
+    pub mean_scores_b: BTreeMap<String, f64>,
+    pub usage_a: Usage,
+    pub usage_b: Usage,
+    pub judge_usage: Usage,
+}
+
+/// A comparison of two candidates over a set of cases
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EvaluationReport {
+    pub id: Uuid,
+    pub started_at: DateTime<Utc>,
+    pub finished_at: DateTime<Utc>,
+    pub a: EvalCandidate,
+    pub b: EvalCandidate,
+    pub judge_llm_id: String,
+    pub criteria: Vec<EvalCriterion>,
+    pub cases: Vec<EvalCaseResult>,
+    pub summary: EvalSummary,
+}
+
+/// Past evaluation reports, newest first
+pub type EvaluationStore = RunStore<EvaluationReport>;
+
+/// Have both candidates answer every case, then have the judge score each pair
+/// Cases run one after another; the judge sees every other pair in swapped order so position bias evens out
+pub async fn run_evaluation(pool: &LLMPool, options: &EvaluationOptions, cases: &[EvalCase]) -> EvaluationReport {
+    let started_at = Utc::now();
+    info!(
+        "⚖️  Evaluating {} against {} on {} cases, judged by {}",
+        options.a.llm_id,
+        options.b.llm_id,
+        cases.len(),
+        options.judge_llm_id
+    );
+
+    let mut results = Vec::with_capacity(cases.len());
+    let mut judge_usage = Usage::default();
+    for (index, case) in cases.iter().enumerate() {
+        let a = answer(pool, &options.a, case).await;
+        let b = answer(pool, &options.b, case).await;
+        let (verdict, judge_error) = match (&a.content, &b.content) {
+            (Some(answer_a), Some(answer_b)) => {
+                match judge(pool, options, case, answer_a, answer_b, index % 2 == 1).await {
+                    Ok((verdict, usage)) => {
+                        judge_usage += usage;
+                        (Some(verdict), None)
+                    }
+                    Err(e) => {
+                        warn!("⚖️  Judge failed on {}: {}", case.name, e);
+                        (None, Some(e.to_string()))
+                    }
+                }
+            }
+            // A candidate that failed loses without asking the judge
+            (Some(_), None) => (Some(forfeit(EvalWinner::A, &options.b.llm_id)), None),
+            (None, Some(_)) => (Some(forfeit(EvalWinner::B, &options.a.llm_id)), None),
+            (None, None) => (None, None),
+        };
+        results.push(EvalCaseResult { name: case.name.clone(), a, b, verdict, judge_error });
+    }
+
+    let mut summary = summarize(&results, &options.criteria);
+    summary.judge_usage = judge_usage;
+    EvaluationReport {
+        id: Uuid::new_v4(),
+        started_at,
+        finished_at: Utc::now(),
+        a: options.a.clone(),
+        b: options.b.clone(),
+        judge_llm_id: options.judge_llm_id.clone(),
+        criteria: options.criteria.clone(),
+        cases: results,
+        summary,
+    }
+}
+
+async fn answer(pool: &LLMPool, candidate: &EvalCandidate, case: &EvalCase) -> EvalAnswer {
+    // Runs behind chats waiting on the same local models
+    let mut request = CompletionRequest::messages(case.messages.clone())
+        .with_options(candidate.options.clone())
+        .with_priority(Priority::Low);
+    if let Some(system) = &candidate.system {
+        request = request.with_system(system.clone());
+    }
+    match complete(pool, &candidate.llm_id, request).await {
+        Ok((content, usage)) => EvalAnswer { content: Some(content), error: None, usage },
+        Err(e) => {
+            warn!("⚖️  {} failed {}: {}", candidate.llm_id, case.name, e);
+            EvalAnswer { content: None, error: Some(e.to_string()), usage: Usage::default() }
+        }
+    }
+}
+
+/// Ask the judge about one pair; with `swapped`, B's answer is shown first
+async fn judge(
+    pool: &LLMPool,
+    options: &EvaluationOptions,
+    case: &EvalCase,
+    answer_a: &str,
+    answer_b: &str,
+    swapped: bool,
+) -> Result<(EvalVerdict, Usage)> {
+    let (first, second) = if swapped { (answer_b, answer_a) } else { (answer_a, answer_b) };
+    let prompt = judge_prompt(case, &options.criteria, first, second);
+    // Fixed sampling, so the same pair gets the same verdict
+    // Local judges are held to JSON while sampling; the rest are asked for it in the system prompt
+    let generation = GenerationOptions {
+        max_tokens: Some(JUDGE_MAX_TOKENS),
+        temperature: Some(0.0),
+        seed: Some(42),
+        response_format: Some(ResponseFormat::JsonObject),
+        ..Default::default()
+    };
+    // Carries the candidates' answers, which the judge must not take orders from
+    let request = CompletionRequest::prompt(prompt)
+        .constructed()
+        .with_system(JUDGE_SYSTEM_PROMPT)
+        .with_options(generation)
+        .with_priority(Priority::Low);
+
+    let (reply, usage) = complete(pool, &options.judge_llm_id, request).await?;
+    Ok((parse_verdict(&reply, &options.criteria, swapped)?, usage))
+}
+
+fn judge_prompt(case: &EvalCase, criteria: &[EvalCriterion], first: &str, second: &str) -> String {
+    let conversation: Vec<String> = case
+        .messages
+        .iter()
+        .map(|message| {
+            let role = match message.role {
+                MessageRole::User => "User",
+                MessageRole::Assistant => "Assistant",
+                MessageRole::System => "System",
+            };
+            format!("{}: {}", role, message.content)
+        })
+        .collect();
+    let criteria_list: Vec<String> = criteria
+        .iter()
+        .map(|criterion| format!("- {}: {}", criterion.name, criterion.description))
+        .collect();
+    let example: Vec<String> = criteria.iter().map(|criterion| format!("\"{}\": 7", criterion.name)).collect();
+    let reference = case
+        .reference
+        .as_deref()
+        .map(|reference| format!("[Reference answer]\n{}\n\n", reference))
+        .unwrap_or_default();
+
+    format!(
+        "[Conversation]\n{}\n\n{}[Answer 1]\n{}\n\n[Answer 2]\n{}\n\n\
+         Score each answer from 1 to {} on these criteria:\n{}\n\n\
+         Reply with JSON only, in this form:\n\
+         {{\"scores\": {{\"1\": {{{}}}, \"2\": {{{}}}}}, \"winner\": \"1\" | \"2\" | \"tie\", \"reasoning\": \"one or two sentences\"}}",
+        conversation.join("\n"),
+        reference,
+        first,
+        second,
+        MAX_SCORE,
+        criteria_list.join("\n"),
+        example.join(", "),
+        example.join(", "),
+    )
+}
+
+/// Read the judge's JSON reply, mapping answer 1 and 2 back to A and B
+fn parse_verdict(reply: &str, criteria: &[EvalCriterion], swapped: bool) -> Result<EvalVerdict> {
+    let invalid = |reason: String| HybridLLMError::LLMError(format!("Judge gave no usable verdict: {}", reason));
+    // Models often wrap JSON in prose or a code fence
+    let json = match (reply.find('{'), reply.rfind('}')) {
+        (Some(start), Some(end)) if start < end => &reply[start..=end],
+        _ => return Err(invalid("no JSON object in the reply".to_string())),
+    };
+    let value: serde_json::Value = serde_json::from_str(json).map_err(|e| invalid(e.to_string()))?;
+
+    let scores = |position: &str| -> Result<BTreeMap<String, u8>> {
+        criteria
+            .iter()
+            .map(|criterion| {
+                let score = value["scores"][position][&criterion.name]
+                    .as_f64()
+                    .ok_or_else(|| invalid(format!("no {} score for answer {}", criterion.name, position)))?;
+                Ok((criterion.name.clone(), score.round().clamp(1.0, MAX_SCORE as f64) as u8))
+            })
+            .collect()
+    };
+    let (first, second) = (scores("1")?, scores("2")?);
+    let winner = match (value["winner"].as_str().map(str::trim), swapped) {
+        (Some("1"), false) | (Some("2"), true) => EvalWinner::A,
+        (Some("2"), false) | (Some("1"), true) => EvalWinner::B,
+        (Some(winner), _) if winner.eq_ignore_ascii_case("tie") => EvalWinner::Tie,
+        (winner, _) => return Err(invalid(format!("winner {:?}", winner))),
+    };
+    let (scores_a, scores_b) = if swapped { (second, first) } else { (first, second) };
+
+    Ok(EvalVerdict {
+        winner,
+        scores_a,
+        scores_b,
+        reasoning: value["reasoning"].as_str().unwrap_or_default().to_string(),
+    })
+}
+
+fn forfeit(winner: EvalWinner, failed_llm_id: &str) -> EvalVerdict {
+    EvalVerdict {
+        winner,
+        scores_a: BTreeMap::new(),
+        scores_b: BTreeMap::new(),
+        reasoning: format!("{} gave no answer", failed_llm_id),
+    }
+}
+
+/// Stream a completion to the end and return its text
+async fn complete(pool: &LLMPool, llm_id: &str, request: CompletionRequest) -> Result<(String, Usage)> {
+    let request_id = Uuid::new_v4();
+    let request = request.with_context(RequestContext::user().with_trace_id(request_id).with_llm(llm_id));
+    let started = Instant::now();
+    let generate = async {
+        let mut chunks = pool.complete_stream(request_id, llm_id, request).await?;
+        let mut content = String::new();
+        let mut usage = Usage::default();
+        while let Some(chunk) = chunks.recv().await {
+            match chunk? {
+                StreamChunk::Text(text) => content.push_str(&text),
+                StreamChunk::Usage(reported) => usage = reported,
+            }
+        }
+        usage.latency_ms = started.elapsed().as_millis() as u64;
+        Ok((content, usage))
+    };
+    // Dropping the stream on timeout cancels it in the pool
+    tokio::time::timeout(ANSWER_TIMEOUT, generate)
+        .await
+        .unwrap_or_else(|_| Err(HybridLLMError::Timeout(format!("No answer within {} seconds", ANSWER_TIMEOUT.as_secs()))))
+}
+
+fn summarize(results: &[EvalCaseResult], criteria: &[EvalCriterion]) -> EvalSummary {
+    let mut summary = EvalSummary::default();
+    for result in results {
+        match result.verdict.as_ref().map(|verdict| verdict.winner) {
+            Some(EvalWinner::A) => summary.wins_a += 1,
+            Some(EvalWinner::B) => summary.wins_b += 1,
+            Some(EvalWinner::Tie) => summary.ties += 1,
+            None => summary.unjudged += 1,
+        }
+        summary.failures_a += result.a.content.is_none() as u32;
+        summary.failures_b += result.b.content.is_none() as u32;
+        summary.usage_a += result.a.usage;
+        summary.usage_b += result.b.usage;
+    }
+
+    let scored: Vec<&EvalVerdict> = results
+        .iter()
+        .filter_map(|result| result.verdict.as_ref())
+        .filter(|verdict| !verdict.scores_a.is_empty())
+        .collect();
+    if !scored.is_empty() {
+        let mean = |scores: &dyn Fn(&EvalVerdict) -> &BTreeMap<String, u8>, name: &str| {
+            scored.iter().map(|verdict| scores(verdict)[name] as f64).sum::<f64>() / scored.len() as f64
+        };
+        for criterion in criteria {
+            summary.mean_scores_a.insert(criterion.name.clone(), mean(&|verdict| &verdict.scores_a, &criterion.name));
+            summary.mean_scores_b.insert(criterion.name.clone(), mean(&|verdict| &verdict.scores_b, &criterion.name));
+        }
+    }
+    summary
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use async_trait::async_trait;
+    use common::types::{Capability, LLMInstance, LLMProvider as LLMProviderType};
+    use common::{CancellationToken, Completion, LLMProvider};
+    use tokio::sync::mpsc;
+
+    /// Answers every prompt with `reply(prompt)`
+    struct Scripted {
+        instance: LLMInstance,
+        reply: fn(&str) -> Result<String>,
+    }
+
+    impl Scripted {
+        fn register(pool: &LLMPool, id: &str, reply: fn(&str) -> Result<String>) {
+            let instance = LLMInstance {
+                id: id.to_string(),
+                provider: LLMProviderType::Local(id.to_string()),
+                capabilities: vec![Capability::General],
+                model_name: id.to_string(),
+                max_context: 4096,
+                is_loaded: true,
+            };
+            pool.register(Box::new(Self { instance, reply })).unwrap();
+        }
+    }
+
+    #[async_trait]
+    impl LLMProvider for Scripted {
+        fn capabilities(&self) -> Vec<Capability> {
+            self.instance.capabilities.clone()
+        }
+
+        fn instance(&self) -> &LLMInstance {
+            &self.instance
+        }
+
+        async fn complete(&self, _request: CompletionRequest) -> Result<Completion> {
+            Ok(Completion { content: String::new(), usage: Usage::default() })
+        }
+
+        async fn complete_stream(
+            &self,
+            request: CompletionRequest,
+            _cancel: CancellationToken,
+        ) -> Result<mpsc::Receiver<Result<StreamChunk>>> {
+            let prompt = request.messages.last().map(|message| message.content.clone()).unwrap_or_default();
+            let reply = (self.reply)(&prompt)?;
+            let (tx, rx) = mpsc::channel(2);
+            tokio::spawn(async move {
+                let _ = tx.send(Ok(StreamChunk::Text(reply))).await;
+                let usage = Usage { input_tokens: 10, output_tokens: 5, cost_usd: Some(0.0), latency_ms: 0 };
+                let _ = tx.send(Ok(StreamChunk::Usage(usage))).await;
+            });
+            Ok(rx)
+        }
+
+        async fn health_check(&self) -> Result<bool> {
+            Ok(true)
+        }
+
+        async fn load(&self) -> Result<()> {
+            Ok(())
+        }
+
+        async fn unload(&self) -> Result<()> {
+            Ok(())
+        }
+    }
+
+    fn criteria() -> Vec<EvalCriterion> {
+        default_criteria().into_iter().take(2).collect()
+    }
+
+    #[test]
+    fn test_parse_verdict() {
+        let reply = "Here is my verdict:\n```json\n{\"scores\": {\"1\": {\"correctness\": 9, \"helpfulness\": 8.6}, \
+                     \"2\": {\"correctness\": 3, \"helpfulness\": 14}}, \"winner\": \"1\", \"reasoning\": \"1 is right\"}\n```";
+        let verdict = parse_verdict(reply, &criteria(), false).unwrap();
+        assert_eq!(verdict.winner, EvalWinner::A);
+        assert_eq!(verdict.scores_a["helpfulness"], 9);
+        assert_eq!(verdict.scores_b["helpfulness"], MAX_SCORE);
+
+        // Shown in swapped order, answer 1 was B's
+        let swapped = parse_verdict(reply, &criteria(), true).unwrap();
+        assert_eq!(swapped.winner, EvalWinner::B);
+        assert_eq!(swapped.scores_b["correctness"], 9);
+
+        assert!(parse_verdict("Answer 1 is better.", &criteria(), false).is_err());
+        let unscored = "{\"scores\": {\"1\": {\"correctness\": 9}, \"2\": {}}, \"winner\": \"tie\"}";
+        assert!(parse_verdict(unscored, &criteria(), false).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_run_evaluation() {
+        let pool = LLMPool::new();
+        Scripted::register(&pool, "small", |prompt| match prompt.contains("crash") {
+            true => Err(HybridLLMError::LLMError("model crashed".to_string())),
+            false => Ok("a rough answer".to_string()),
+        });
+        Scripted::register(&pool, "large", |_| Ok("a good answer".to_string()));
+        // Prefers whichever answer says "good", wherever it is shown
+        Scripted::register(&pool, "judge", |prompt| {
+            let second = prompt.find("[Answer 2]").unwrap();
+            let (good, bad) = if prompt[..second].contains("good answer") { ("1", "2") } else { ("2", "1") };
+            Ok(format!(
+                "{{\"scores\": {{\"{}\": {{\"correctness\": 9, \"helpfulness\": 8}}, \"{}\": {{\"correctness\": 4, \"helpfulness\": 5}}}}, \
+                 \"winner\": \"{}\", \"reasoning\": \"more accurate\"}}",
+                good, bad, good
+            ))
+        });
+
+        let candidate = |llm_id: &str| EvalCandidate { llm_id: llm_id.to_string(), system: None, options: GenerationOptions::default() };
+        let options = EvaluationOptions { a: candidate("small"), b: candidate("large"), judge_llm_id: "judge".to_string(), criteria: criteria() };
+        let cases = vec![
+            EvalCase::prompt("first", "Explain borrowing"),
+            EvalCase::prompt("second", "Explain lifetimes"),
+            EvalCase::prompt("third", "Make it crash"),
+        ];
+        let report = run_evaluation(&pool, &options, &cases).await;
+
+        assert_eq!(report.cases.len(), 3);
+        assert!(report.cases.iter().all(|case| case.verdict.as_ref().unwrap().winner == EvalWinner::B));
+        let summary = &report.summary;
+        assert_eq!((summary.wins_a, summary.wins_b, summary.ties, summary.unjudged), (0, 3, 0, 0));
+        assert_eq!((summary.failures_a, summary.failures_b), (1, 0));
+        // The forfeited case isn't scored
+        assert_eq!(summary.mean_scores_a["correctness"], 4.0);
+        assert_eq!(summary.mean_scores_b["helpfulness"], 8.0);
+        assert_eq!(summary.judge_usage.output_tokens, 10);
+        assert_eq!(report.cases[2].a.error.as_deref(), Some("LLM error: model crashed"));
+
+        let dir = std::env::temp_dir().join(format!("evaluations-{}", Uuid::new_v4()));
+        let path = dir.join("evaluations.jsonl");
+        EvaluationStore::open(&path).unwrap().record(&report).unwrap();
+        assert_eq!(EvaluationStore::open(&path).unwrap().list(), vec![report]);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
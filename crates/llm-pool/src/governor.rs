@@ -0,0 +1,167 @@
+use common::{
+    errors::{Result, HybridLLMError},
+    traits::GpuAllocator,
+    types::GpuRequest,
+};
+use serde::{Deserialize, Serialize};
+use std::sync::Mutex;
+use tracing::{info, debug};
+
+/// A GPU whose memory the governor hands out
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GpuDevice {
+    pub index: u32,
+    pub name: String,
+    pub total_vram_mb: u64,
+}
+
+/// VRAM held by one model or sandbox
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct VramReservation {
+    pub owner: String,
+    pub device: u32,
+    pub vram_mb: u64,
+}
+
+/// Single VRAM ledger for local models and GPU sandboxes
+/// Models reserve under their LLM ID, sandboxes under `sandbox:<id>`
+pub struct MemoryGovernor {
+    devices: Vec<GpuDevice>,
+    reservations: Mutex<Vec<VramReservation>>,
+}
+
+impl MemoryGovernor {
+    pub fn new(devices: Vec<GpuDevice>) -> Self {
+        Self {
+            devices,
+            reservations: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Discover NVIDIA GPUs through `nvidia-smi`; no devices when it is unavailable
+    pub fn detect() -> Self {
+        let output = std::process::Command::new("nvidia-smi")
+            .args(["--query-gpu=index,name,memory.total", "--format=csv,noheader,nounits"])
+            .output();
+
+        let devices: Vec<GpuDevice> = match output {
+            Ok(output) if output.status.success() => String::from_utf8_lossy(&output.stdout)
+                .lines()
+                .filter_map(|line| {
+                    let fields: Vec<&str> = line.split(',').map(str::trim).collect();
+                    Some(GpuDevice {
+                        index: fields.first()?.parse().ok()?,
+                        name: fields.get(1)?.to_string(),
+                        total_vram_mb: fields.get(2)?.parse().ok()?,
+                    })
+                })
+                .collect(),
+            _ => Vec::new(),
+        };
+
+        if devices.is_empty() {
+            debug!("No GPUs detected, GPU reservations will be refused");
+        } else {
+            info!("🎮 Detected GPUs: {:?}", devices);
+        }
+
+        Self::new(devices)
+    }
+
+    pub fn devices(&self) -> &[GpuDevice] {
+        &self.devices
+    }
+
+    /// Current reservations across all devices
+    pub fn reservations(&self) -> Vec<VramReservation> {
+        self.reservations.lock().unwrap().clone()
+    }
+
+    /// VRAM not yet reserved on a device
+    pub fn free_vram_mb(&self, device: u32) -> u64 {
+        let total = self
+            .devices
+            .iter()
+            .find(|d| d.index == device)
+            .map_or(0, |d| d.total_vram_mb);
+        total.saturating_sub(Self::reserved_on(&self.reservations.lock().unwrap(), device))
+    }
+
+    fn reserved_on(reservations: &[VramReservation], device: u32) -> u64 {
+        reservations
+            .iter()
+            .filter(|r| r.device == device)
+            .map(|r| r.vram_mb)
+            .sum()
+    }
+}
+
+impl GpuAllocator for MemoryGovernor {
+    fn reserve(&self, owner: &str, request: &GpuRequest) -> Result<u32> {
+        let mut reservations = self.reservations.lock().unwrap();
+
+        // The device with the most free VRAM that fits, so models and sandboxes spread out
+        let free = |device: &GpuDevice| device.total_vram_mb.saturating_sub(Self::reserved_on(&reservations, device.index));
+        let best = self
+            .devices
+            .iter()
+            .filter(|device| request.device.is_none_or(|index| index == device.index))
+            .max_by_key(|device| free(device));
+
+        let Some(device) = best.filter(|device| free(device) >= request.vram_mb) else {
+            return Err(HybridLLMError::ResourceLimitExceeded {
+                resource: "vram_mb".to_string(),
+                limit: best.map_or(0, free) as f32,
+                actual: request.vram_mb as f32,
+            });
+        };
+
+        info!("🎮 Reserved {} MB VRAM on GPU {} for {}", request.vram_mb, device.index, owner);
+        let index = device.index;
+        reservations.push(VramReservation {
+            owner: owner.to_string(),
+            device: index,
+            vram_mb: request.vram_mb,
+        });
+        Ok(index)
+    }
+
+    fn release(&self, owner: &str) {
+        let mut reservations = self.reservations.lock().unwrap();
+        let before = reservations.len();
+        reservations.retain(|r| r.owner != owner);
+        if reservations.len() != before {
+            debug!("🎮 Released VRAM held by {}", owner);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_models_and_sandboxes_share_budget() {
+        let governor = MemoryGovernor::new(vec![
+            GpuDevice { index: 0, name: "gpu0".to_string(), total_vram_mb: 8192 },
+            GpuDevice { index: 1, name: "gpu1".to_string(), total_vram_mb: 4096 },
+        ]);
+
+        let model = GpuRequest { vram_mb: 6144, device: None };
+        assert_eq!(governor.reserve("llama-3-8b", &model).unwrap(), 0);
+
+        // GPU 1 now has the most room
+        let sandbox = GpuRequest { vram_mb: 3072, device: None };
+        assert_eq!(governor.reserve("sandbox:a", &sandbox).unwrap(), 1);
+
+        let pinned = GpuRequest { vram_mb: 4096, device: Some(0) };
+        assert!(matches!(
+            governor.reserve("sandbox:b", &pinned),
+            Err(HybridLLMError::ResourceLimitExceeded { .. })
+        ));
+
+        governor.release("llama-3-8b");
+        assert_eq!(governor.reserve("sandbox:b", &pinned).unwrap(), 0);
+        assert_eq!(governor.free_vram_mb(0), 4096);
+    }
+}
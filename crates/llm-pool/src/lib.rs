@@ -1,5 +1,22 @@
+mod activity;
+mod benchmark;
+mod evaluation;
+mod governor;
 mod pool;
 mod load_balancer;
+mod store;
+mod usage;
 
-pub use pool::LLMPool;
+pub use activity::RequestPermit;
+pub use benchmark::{
+    BenchmarkOptions, BenchmarkPrompt, BenchmarkResult, BenchmarkRun, BenchmarkStore, run_benchmark, STANDARD_SUITE,
+};
+pub use evaluation::{
+    default_criteria, run_evaluation, EvalAnswer, EvalCandidate, EvalCase, EvalCaseResult, EvalCriterion, EvalSummary,
+    EvalVerdict, EvalWinner, EvaluationOptions, EvaluationReport, EvaluationStore, MAX_SCORE,
+};
+pub use governor::{GpuDevice, MemoryGovernor, VramReservation};
+pub use pool::{LLMMemory, LLMPool, LLMStatus};
 pub use load_balancer::LoadBalancer;
+pub use store::RunStore;
+pub use usage::{summarize_usage, UsageGroup, UsageGrouping, UsageLedger, UsageQuery, UsageRecord, UsageSummary};
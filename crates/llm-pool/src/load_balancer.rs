@@ -28,12 +28,14 @@ impl LoadBalancer {
         Some(&llm_ids[index])
     }
 
-    /// Select the best LLM based on current load (placeholder)
-    /// In a real implementation, this would consider actual resource usage
-    pub fn select_least_loaded<'a>(&self, llm_ids: &'a [String]) -> Option<&'a String> {
-        // For now, just use round-robin
-        // TODO: Implement actual load tracking
-        self.select_round_robin(llm_ids)
+    /// Select the LLM with the fewest requests running or queued, e.g. by `LLMPool::pending`
+    /// Ties take turns, as in round-robin
+    pub fn select_least_loaded<'a>(&self, llm_ids: &'a [String], pending: impl Fn(&str) -> usize) -> Option<&'a String> {
+        let loads: Vec<usize> = llm_ids.iter().map(|id| pending(id)).collect();
+        let least = *loads.iter().min()?;
+        let idle: Vec<&String> = llm_ids.iter().zip(&loads).filter(|(_, &load)| load == least).map(|(id, _)| id).collect();
+        let count = self.counter.fetch_add(1, Ordering::Relaxed);
+        Some(idle[count % idle.len()])
     }
 
     /// Select LLM with preference for local models
@@ -82,6 +84,17 @@ mod tests {
         assert_eq!(balancer.select_round_robin(&llms), Some(&"llm1".to_string()));
     }
 
+    #[test]
+    fn test_least_loaded() {
+        let balancer = LoadBalancer::new();
+        let llms = vec!["busy".to_string(), "idle1".to_string(), "idle2".to_string()];
+        let pending = |id: &str| if id == "busy" { 3 } else { 0 };
+
+        assert_eq!(balancer.select_least_loaded(&llms, pending), Some(&"idle1".to_string()));
+        assert_eq!(balancer.select_least_loaded(&llms, pending), Some(&"idle2".to_string()));
+        assert_eq!(balancer.select_least_loaded(&[], pending), None);
+    }
+
     #[test]
     fn test_prefer_local() {
         let balancer = LoadBalancer::new();
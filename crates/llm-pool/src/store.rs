@@ -0,0 +1,62 @@
+use common::errors::{HybridLLMError, Result};
+use serde::{de::DeserializeOwned, Serialize};
+use std::io::{BufRead, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use tracing::warn;
+
+/// Finished runs appended to a JSON-lines file, so results can be compared across sessions
+pub struct RunStore<T> {
+    path: PathBuf,
+    runs: Mutex<Vec<T>>,
+}
+
+impl<T: Serialize + DeserializeOwned + Clone> RunStore<T> {
+    pub fn open(path: &Path) -> Result<Self> {
+        let fs_err = |e: std::io::Error| HybridLLMError::FileSystemError(format!("{}: {}", path.display(), e));
+
+        let mut runs = Vec::new();
+        match std::fs::File::open(path) {
+            Ok(file) => {
+                for line in std::io::BufReader::new(file).lines() {
+                    let line = line.map_err(fs_err)?;
+                    if line.trim().is_empty() {
+                        continue;
+                    }
+                    match serde_json::from_str(&line) {
+                        Ok(run) => runs.push(run),
+                        Err(e) => warn!("⚠️  Skipping unreadable run in {:?}: {}", path, e),
+                    }
+                }
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                if let Some(parent) = path.parent() {
+                    std::fs::create_dir_all(parent).map_err(fs_err)?;
+                }
+            }
+            Err(e) => return Err(fs_err(e)),
+        }
+
+        Ok(Self { path: path.to_path_buf(), runs: Mutex::new(runs) })
+    }
+
+    /// Keep a finished run
+    pub fn record(&self, run: &T) -> Result<()> {
+        let fs_err = |e: std::io::Error| HybridLLMError::FileSystemError(format!("{}: {}", self.path.display(), e));
+        let mut line = serde_json::to_string(run).map_err(|e| HybridLLMError::Other(e.into()))?;
+        line.push('\n');
+        std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .and_then(|mut file| file.write_all(line.as_bytes()))
+            .map_err(fs_err)?;
+        self.runs.lock().unwrap().push(run.clone());
+        Ok(())
+    }
+
+    /// Runs, newest first
+    pub fn list(&self) -> Vec<T> {
+        self.runs.lock().unwrap().iter().rev().cloned().collect()
+    }
+}
@@ -0,0 +1,185 @@
+use chrono::{DateTime, Datelike, TimeZone, Utc};
+use common::Usage;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use uuid::Uuid;
+
+use crate::store::RunStore;
+
+/// One finished completion, as kept for cost and usage analytics
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct UsageRecord {
+    pub at: DateTime<Utc>,
+    pub llm_id: String,
+    /// Whether the LLM is a paid cloud provider
+    pub cloud: bool,
+    #[serde(default)]
+    pub conversation_id: Option<Uuid>,
+    pub usage: Usage,
+}
+
+/// Every completion the pool finished, so spending survives restarts and can be broken down
+pub type UsageLedger = RunStore<UsageRecord>;
+
+impl RunStore<UsageRecord> {
+    /// What an LLM spent in the calendar month of `now`
+    pub fn spent_usd(&self, llm_id: &str, now: DateTime<Utc>) -> f64 {
+        let month = start_of_month(now);
+        self.list()
+            .iter()
+            .filter(|record| record.at >= month && record.llm_id == llm_id)
+            .filter_map(|record| record.usage.cost_usd)
+            .sum()
+    }
+}
+
+/// What a usage summary is broken down by
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum UsageGrouping {
+    /// UTC calendar days, oldest first
+    Day,
+    Llm,
+    Conversation,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct UsageQuery {
+    pub group_by: UsageGrouping,
+    /// Defaults to the start of the current month
+    #[serde(default)]
+    pub from: Option<DateTime<Utc>>,
+    /// Defaults to now
+    #[serde(default)]
+    pub to: Option<DateTime<Utc>>,
+    /// Only this LLM's completions
+    #[serde(default)]
+    pub llm_id: Option<String>,
+    /// Only completions of paid cloud providers
+    #[serde(default)]
+    pub cloud_only: bool,
+}
+
+/// Completions sharing a day, LLM or conversation
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct UsageGroup {
+    /// `2026-03-14`, an LLM id or a conversation id; `None` for completions outside any conversation
+    pub key: Option<String>,
+    pub requests: u64,
+    pub usage: Usage,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct UsageSummary {
+    pub from: DateTime<Utc>,
+    pub to: DateTime<Utc>,
+    pub requests: u64,
+    pub total: Usage,
+    /// Days oldest first; LLMs and conversations by cost, then by tokens, highest first
+    pub groups: Vec<UsageGroup>,
+}
+
+/// Add up the records `query` selects, grouped as it asks
+pub fn summarize_usage(records: &[UsageRecord], query: &UsageQuery, now: DateTime<Utc>) -> UsageSummary {
+    let from = query.from.unwrap_or_else(|| start_of_month(now));
+    let to = query.to.unwrap_or(now);
+    let selected = records.iter().filter(|record| {
+        record.at >= from
+            && record.at <= to
+            && query.llm_id.as_ref().is_none_or(|llm_id| &record.llm_id == llm_id)
+            && (record.cloud || !query.cloud_only)
+    });
+
+    let mut groups: HashMap<Option<String>, UsageGroup> = HashMap::new();
+    let (mut requests, mut total) = (0, Usage::default());
+    for record in selected {
+        let key = match query.group_by {
+            UsageGrouping::Day => Some(record.at.format("%Y-%m-%d").to_string()),
+            UsageGrouping::Llm => Some(record.llm_id.clone()),
+            UsageGrouping::Conversation => record.conversation_id.map(|id| id.to_string()),
+        };
+        let group = groups
+            .entry(key.clone())
+            .or_insert_with(|| UsageGroup { key, requests: 0, usage: Usage::default() });
+        group.requests += 1;
+        group.usage += record.usage;
+        requests += 1;
+        total += record.usage;
+    }
+
+    let mut groups: Vec<UsageGroup> = groups.into_values().collect();
+    match query.group_by {
+        UsageGrouping::Day => groups.sort_by(|a, b| a.key.cmp(&b.key)),
+        UsageGrouping::Llm | UsageGrouping::Conversation => groups.sort_by(|a, b| {
+            let cost = |group: &UsageGroup| group.usage.cost_usd.unwrap_or_default();
+            cost(b)
+                .total_cmp(&cost(a))
+                .then(b.usage.total_tokens().cmp(&a.usage.total_tokens()))
+                .then(a.key.cmp(&b.key))
+        }),
+    }
+
+    UsageSummary { from, to, requests, total, groups }
+}
+
+fn start_of_month(now: DateTime<Utc>) -> DateTime<Utc> {
+    Utc.with_ymd_and_hms(now.year(), now.month(), 1, 0, 0, 0).unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(at: &str, llm_id: &str, conversation_id: Option<Uuid>, tokens: u64, cost_usd: Option<f64>) -> UsageRecord {
+        UsageRecord {
+            at: at.parse().unwrap(),
+            llm_id: llm_id.to_string(),
+            cloud: llm_id == "claude",
+            conversation_id,
+            usage: Usage { input_tokens: tokens, output_tokens: tokens, cost_usd, latency_ms: 100 },
+        }
+    }
+
+    #[test]
+    fn test_summarize_usage() {
+        let conversation = Uuid::new_v4();
+        let records = vec![
+            record("2026-02-27T10:00:00Z", "claude", Some(conversation), 1000, Some(5.0)),
+            record("2026-03-01T09:00:00Z", "claude", Some(conversation), 100, Some(0.5)),
+            record("2026-03-01T23:00:00Z", "local", Some(conversation), 400, Some(0.0)),
+            record("2026-03-03T12:00:00Z", "claude", None, 200, Some(1.25)),
+        ];
+        let now = "2026-03-14T12:00:00Z".parse().unwrap();
+        let query = |group_by| UsageQuery { group_by, from: None, to: None, llm_id: None, cloud_only: false };
+
+        // This month only, by default
+        let by_day = summarize_usage(&records, &query(UsageGrouping::Day), now);
+        assert_eq!(by_day.requests, 3);
+        assert_eq!(by_day.total.cost_usd, Some(1.75));
+        let days: Vec<_> = by_day.groups.iter().map(|group| (group.key.clone().unwrap(), group.requests)).collect();
+        assert_eq!(days, vec![("2026-03-01".to_string(), 2), ("2026-03-03".to_string(), 1)]);
+
+        let by_llm = summarize_usage(&records, &query(UsageGrouping::Llm), now);
+        assert_eq!(by_llm.groups[0].key.as_deref(), Some("claude"));
+        assert_eq!(by_llm.groups[0].usage.cost_usd, Some(1.75));
+        assert_eq!(by_llm.groups[1].usage.output_tokens, 400);
+
+        let by_conversation = summarize_usage(
+            &records,
+            &UsageQuery { from: Some("2026-02-01T00:00:00Z".parse().unwrap()), cloud_only: true, ..query(UsageGrouping::Conversation) },
+            now,
+        );
+        assert_eq!(by_conversation.groups[0].key, Some(conversation.to_string()));
+        assert_eq!(by_conversation.groups[0].usage.cost_usd, Some(5.5));
+        assert_eq!(by_conversation.groups[1].key, None);
+
+        let dir = std::env::temp_dir().join(format!("usage-{}", Uuid::new_v4()));
+        let ledger = UsageLedger::open(&dir.join("usage.jsonl")).unwrap();
+        for record in &records {
+            ledger.record(record).unwrap();
+        }
+        assert_eq!(ledger.spent_usd("claude", now), 1.75);
+        assert_eq!(ledger.spent_usd("local", now), 0.0);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,163 @@
+use common::errors::{Result, HybridLLMError};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fs::File;
+use std::io::Read;
+use std::path::{Component, Path, PathBuf};
+
+/// A file inside a sandbox that has been located and checksummed for transfer
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ArtifactInfo {
+    pub file_path: String,
+    pub size_bytes: u64,
+    pub sha256: String,
+    /// Resolved location on the host, for scanning
+    #[serde(skip)]
+    pub host_path: PathBuf,
+}
+
+/// Resolve an artifact path inside a sandbox root
+/// Rejects traversal, symlinks pointing outside the sandbox and non-regular files
+pub(crate) fn resolve_artifact(root: &Path, file_path: &str) -> Result<PathBuf> {
+    let (_, path) = resolve_in_sandbox(root, file_path)?;
+    if !path.is_file() {
+        return Err(HybridLLMError::InvalidRequest(format!(
+            "Artifact is not a regular file: {}",
+            file_path
+        )));
+    }
+
+    Ok(path)
+}
+
+/// Canonicalize a sandbox root and a path inside it
+/// Rejects traversal and symlinks that resolve outside the sandbox
+pub(crate) fn resolve_in_sandbox(root: &Path, file_path: &str) -> Result<(PathBuf, PathBuf)> {
+    let relative = Path::new(file_path);
+    if relative
+        .components()
+        .any(|c| !matches!(c, Component::Normal(_) | Component::CurDir))
+    {
+        return Err(HybridLLMError::SecurityViolation(format!(
+            "Artifact path escapes sandbox: {}",
+            file_path
+        )));
+    }
+
+    let root = root
+        .canonicalize()
+        .map_err(|e| HybridLLMError::FileSystemError(e.to_string()))?;
+    let path = root
+        .join(relative)
+        .canonicalize()
+        .map_err(|e| HybridLLMError::FileSystemError(format!("{}: {}", file_path, e)))?;
+
+    if !path.starts_with(&root) {
+        return Err(HybridLLMError::SecurityViolation(format!(
+            "Artifact resolves outside sandbox: {}",
+            file_path
+        )));
+    }
+
+    Ok((root, path))
+}
+
+/// Size and SHA-256 of a file
+pub(crate) fn checksum(path: &Path) -> Result<(u64, String)> {
+    let mut file = File::open(path).map_err(|e| HybridLLMError::FileSystemError(e.to_string()))?;
+    let mut hasher = Sha256::new();
+    let mut buf = [0u8; 64 * 1024];
+    let mut size = 0u64;
+
+    loop {
+        let n = file
+            .read(&mut buf)
+            .map_err(|e| HybridLLMError::FileSystemError(e.to_string()))?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buf[..n]);
+        size += n as u64;
+    }
+
+    Ok((size, format!("{:x}", hasher.finalize())))
+}
+
+/// Copy `source` to `destination`, verifying both ends against `expected_sha256`
+/// The file is staged next to the destination and only renamed into place once verified
+pub(crate) fn copy_verified(source: &Path, destination: &Path, expected_sha256: &str) -> Result<()> {
+    let (_, source_sha) = checksum(source)?;
+    if source_sha != expected_sha256 {
+        return Err(HybridLLMError::SecurityViolation(
+            "Artifact changed after it was approved".to_string(),
+        ));
+    }
+
+    let staging = destination.with_extension("partial");
+    let copied = std::fs::copy(source, &staging)
+        .and_then(|_| File::open(&staging)?.sync_all())
+        .map_err(|e| HybridLLMError::FileSystemError(e.to_string()));
+
+    let verified = copied.and_then(|_| checksum(&staging)).and_then(|(_, sha)| {
+        if sha == expected_sha256 {
+            Ok(())
+        } else {
+            Err(HybridLLMError::FileSystemError(
+                "Checksum mismatch after copy".to_string(),
+            ))
+        }
+    });
+
+    if let Err(e) = verified {
+        let _ = std::fs::remove_file(&staging);
+        return Err(e);
+    }
+
+    std::fs::rename(&staging, destination).map_err(|e| {
+        let _ = std::fs::remove_file(&staging);
+        HybridLLMError::FileSystemError(e.to_string())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resolve_rejects_escape() {
+        let root = std::env::temp_dir().join(format!("artifacts-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&root).unwrap();
+        std::fs::write(root.join("out.txt"), "data").unwrap();
+
+        assert!(resolve_artifact(&root, "out.txt").is_ok());
+        assert!(resolve_artifact(&root, "../out.txt").is_err());
+        assert!(resolve_artifact(&root, "/etc/passwd").is_err());
+
+        #[cfg(unix)]
+        {
+            std::os::unix::fs::symlink("/etc/hostname", root.join("link")).unwrap();
+            assert!(resolve_artifact(&root, "link").is_err());
+        }
+
+        let _ = std::fs::remove_dir_all(root);
+    }
+
+    #[test]
+    fn test_copy_verified() {
+        let dir = std::env::temp_dir().join(format!("artifacts-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let source = dir.join("report.csv");
+        std::fs::write(&source, "a,b\n1,2\n").unwrap();
+        let (size, sha) = checksum(&source).unwrap();
+        assert_eq!(size, 8);
+
+        let destination = dir.join("copy.csv");
+        assert!(copy_verified(&source, &destination, "0000").is_err());
+        assert!(!destination.exists());
+
+        copy_verified(&source, &destination, &sha).unwrap();
+        assert_eq!(std::fs::read_to_string(&destination).unwrap(), "a,b\n1,2\n");
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+}
@@ -0,0 +1,522 @@
+use base64::Engine as _;
+use chrono::{DateTime, Utc};
+use common::errors::{Result, HybridLLMError};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::process::{Child, ChildStdin, Command};
+use tokio::sync::oneshot;
+use tracing::{info, debug};
+use uuid::Uuid;
+
+use crate::execution::DEFAULT_PATH;
+use crate::limits::Confinement;
+use crate::{SandboxEvent, SandboxManager};
+
+/// Longest one DevTools command, or a page load, may take
+const COMMAND_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Text `extract` returns past this is cut off
+const MAX_EXTRACT_CHARS: usize = 64 * 1024;
+
+/// Starts the first Chromium on PATH speaking DevTools over fds 3 and 4 (`--remote-debugging-pipe`),
+/// so no debugging port is opened; `$0` is the profile directory, `$1` the proxy flag if any and
+/// `$2` the sandbox flag if any
+const LAUNCHER: &str = r#"
+for browser in chromium chromium-browser google-chrome-stable google-chrome headless_shell; do
+  if command -v "$browser" >/dev/null 2>&1; then
+    exec "$browser" --headless=new --remote-debugging-pipe --no-first-run --no-default-browser-check \
+      --disable-gpu --disable-extensions --disable-sync --disable-background-networking \
+      --user-data-dir="$0" $1 $2 about:blank 3<&0 4>&1 0</dev/null 1>&2
+  fi
+done
+echo "No Chromium found on PATH" >&2
+exit 127
+"#;
+
+/// Something to do in a sandboxed browser
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum BrowserAction {
+    /// Load a page and wait for it to finish loading
+    Navigate { url: String },
+    /// Click the first element matching a CSS selector
+    Click { selector: String },
+    /// Set a form field's value as if it were typed
+    Fill { selector: String, value: String },
+    /// Visible text of the page, or of the first element matching `selector`
+    Extract {
+        #[serde(default)]
+        selector: Option<String>,
+    },
+    /// Save a PNG of the viewport in the sandbox
+    Screenshot,
+}
+
+impl BrowserAction {
+    /// For the audit log; filled values are left out as they may be passwords
+    pub fn summary(&self) -> String {
+        match self {
+            BrowserAction::Navigate { url } => format!("navigate {}", url),
+            BrowserAction::Click { selector } => format!("click {}", selector),
+            BrowserAction::Fill { selector, value } => format!("fill {} ({} chars)", selector, value.chars().count()),
+            BrowserAction::Extract { selector: Some(selector) } => format!("extract {}", selector),
+            BrowserAction::Extract { selector: None } => "extract".to_string(),
+            BrowserAction::Screenshot => "screenshot".to_string(),
+        }
+    }
+}
+
+/// Where the browser is after an action, and what the action produced
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct BrowserOutcome {
+    pub url: String,
+    pub title: String,
+    /// Text from `extract`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub text: Option<String>,
+    /// The text was longer than the limit and was cut off
+    #[serde(default)]
+    pub truncated: bool,
+    /// PNG from `screenshot`, relative to the sandbox root
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub screenshot: Option<String>,
+}
+
+/// A headless browser running in a sandbox
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BrowserInfo {
+    pub id: Uuid,
+    pub sandbox_id: Uuid,
+    pub started_at: DateTime<Utc>,
+}
+
+/// Replies awaited by command ID; `None` once the browser has exited
+type Pending = Arc<Mutex<Option<HashMap<u64, oneshot::Sender<std::result::Result<serde_json::Value, String>>>>>>;
+
+/// A Chromium process and its DevTools pipe
+struct DevTools {
+    child: tokio::sync::Mutex<Child>,
+    pid: Option<u32>,
+    stdin: tokio::sync::Mutex<ChildStdin>,
+    pending: Pending,
+    next_id: AtomicU64,
+}
+
+impl DevTools {
+    fn launch(working_dir: &Path, environment: &[(String, String)], confinement: &Confinement) -> Result<Self> {
+        let proxy = confinement.proxy_url().map(|proxy| format!("--proxy-server={}", proxy)).unwrap_or_default();
+        // Chromium sandboxes its renderers in namespaces nested inside the sandbox's own, but
+        // refuses to as root; the sandbox's namespaces are then the only boundary
+        // SAFETY: geteuid cannot fail
+        let sandbox = if unsafe { libc::geteuid() } == 0 { "--no-sandbox" } else { "" };
+        let mut command = Command::new("sh");
+        command
+            .arg("-c")
+            .arg(LAUNCHER)
+            .arg(working_dir.join(".browser-profile"))
+            .arg(proxy)
+            .arg(sandbox)
+            .current_dir(working_dir)
+            .env_clear()
+            .env("PATH", DEFAULT_PATH)
+            .env("HOME", working_dir)
+            .envs(environment.iter().map(|(key, value)| (key, value)))
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true);
+        confinement.apply_without_address_limit(&mut command)?;
+
+        let mut child = command
+            .spawn()
+            .map_err(|e| HybridLLMError::SandboxError(format!("Failed to start browser: {}", e)))?;
+        let pid = child.id();
+        if let Some(pid) = pid {
+            confinement.track(pid);
+        }
+
+        let stderr = child.stderr.take().expect("stderr is piped");
+        tokio::spawn(async move {
+            let mut lines = BufReader::new(stderr).lines();
+            while let Ok(Some(line)) = lines.next_line().await {
+                debug!("browser stderr: {}", line);
+            }
+        });
+
+        // Replies are NUL-terminated JSON; events carry no ID and are dropped
+        let pending: Pending = Arc::new(Mutex::new(Some(HashMap::new())));
+        let stdout = child.stdout.take().expect("stdout is piped");
+        let replies = Arc::clone(&pending);
+        tokio::spawn(async move {
+            let mut reader = BufReader::new(stdout);
+            let mut message = Vec::new();
+            loop {
+                message.clear();
+                match reader.read_until(0, &mut message).await {
+                    Ok(0) | Err(_) => break,
+                    Ok(_) => {}
+                }
+                if message.last() == Some(&0) {
+                    message.pop();
+                }
+                let Ok(message) = serde_json::from_slice::<serde_json::Value>(&message) else {
+                    continue;
+                };
+                let Some(id) = message["id"].as_u64() else {
+                    continue;
+                };
+                let waiting = replies.lock().unwrap().as_mut().and_then(|pending| pending.remove(&id));
+                if let Some(waiting) = waiting {
+                    let reply = match message["error"]["message"].as_str() {
+                        Some(error) => Err(error.to_string()),
+                        None => Ok(message["result"].clone()),
+                    };
+                    let _ = waiting.send(reply);
+                }
+            }
+            // Dropping the senders fails every call still waiting
+            replies.lock().unwrap().take();
+        });
+
+        Ok(Self {
+            stdin: tokio::sync::Mutex::new(child.stdin.take().expect("stdin is piped")),
+            child: tokio::sync::Mutex::new(child),
+            pid,
+            pending,
+            next_id: AtomicU64::new(1),
+        })
+    }
+
+    /// Send a command, to the page when `session` is given, and wait for its result
+    async fn call(&self, session: Option<&str>, method: &str, params: serde_json::Value) -> Result<serde_json::Value> {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let mut message = serde_json::json!({ "id": id, "method": method, "params": params });
+        if let Some(session) = session {
+            message["sessionId"] = serde_json::json!(session);
+        }
+
+        let (tx, rx) = oneshot::channel();
+        match self.pending.lock().unwrap().as_mut() {
+            Some(pending) => pending.insert(id, tx),
+            None => return Err(HybridLLMError::SandboxError("Browser has exited".to_string())),
+        };
+        let mut frame = message.to_string().into_bytes();
+        frame.push(0);
+        {
+            let mut stdin = self.stdin.lock().await;
+            if stdin.write_all(&frame).await.is_err() || stdin.flush().await.is_err() {
+                return Err(HybridLLMError::SandboxError("Browser has exited".to_string()));
+            }
+        }
+
+        match tokio::time::timeout(COMMAND_TIMEOUT, rx).await {
+            Ok(Ok(Ok(result))) => Ok(result),
+            Ok(Ok(Err(error))) => Err(HybridLLMError::SandboxError(format!("{} failed: {}", method, error))),
+            Ok(Err(_)) => Err(HybridLLMError::SandboxError("Browser has exited".to_string())),
+            Err(_) => {
+                if let Some(pending) = self.pending.lock().unwrap().as_mut() {
+                    pending.remove(&id);
+                }
+                Err(HybridLLMError::Timeout(format!("{} took longer than {}s", method, COMMAND_TIMEOUT.as_secs())))
+            }
+        }
+    }
+
+    async fn kill(&self, confinement: &Confinement) {
+        #[cfg(unix)]
+        if let Some(pid) = self.pid {
+            // SAFETY: plain syscall on the browser's own process group
+            unsafe {
+                libc::kill(-(pid as i32), libc::SIGKILL);
+            }
+        }
+        let _ = self.child.lock().await.kill().await;
+        if let Some(pid) = self.pid {
+            confinement.untrack(pid);
+        }
+    }
+}
+
+/// A browser and the page its actions go to; actions run one at a time
+pub(crate) struct Browser {
+    info: BrowserInfo,
+    root: PathBuf,
+    confinement: Arc<Confinement>,
+    devtools: DevTools,
+    /// DevTools session of the page
+    session: String,
+    busy: tokio::sync::Mutex<()>,
+    screenshots: AtomicU64,
+}
+
+impl Browser {
+    async fn page(&self, method: &str, params: serde_json::Value) -> Result<serde_json::Value> {
+        self.devtools.call(Some(&self.session), method, params).await
+    }
+
+    /// Run a script in the page and return its value
+    async fn evaluate(&self, expression: &str) -> Result<serde_json::Value> {
+        let result = self
+            .page(
+                "Runtime.evaluate",
+                serde_json::json!({ "expression": expression, "returnByValue": true, "awaitPromise": true }),
+            )
+            .await?;
+        if let Some(exception) = result.get("exceptionDetails") {
+            let message = exception["exception"]["description"].as_str().or(exception["text"].as_str());
+            return Err(HybridLLMError::SandboxError(message.unwrap_or("Script failed").to_string()));
+        }
+        Ok(result["result"]["value"].clone())
+    }
+
+    /// Wait until the page has loaded, or give up quietly after the command timeout
+    async fn wait_for_load(&self) {
+        let started = Instant::now();
+        while started.elapsed() < COMMAND_TIMEOUT {
+            // Fails while the old document is swapped for the new one
+            if let Ok(state) = self.evaluate("document.readyState").await {
+                if state == "complete" {
+                    return;
+                }
+            }
+            tokio::time::sleep(Duration::from_millis(200)).await;
+        }
+    }
+
+    async fn run(&self, action: &BrowserAction) -> Result<BrowserOutcome> {
+        let _busy = self.busy.lock().await;
+        let mut outcome = BrowserOutcome::default();
+        match action {
+            BrowserAction::Navigate { url } => {
+                let result = self.page("Page.navigate", serde_json::json!({ "url": url })).await?;
+                if let Some(error) = result["errorText"].as_str() {
+                    return Err(HybridLLMError::NetworkError(format!("{} failed to load: {}", url, error)));
+                }
+                self.wait_for_load().await;
+            }
+            BrowserAction::Click { selector } => {
+                let script = format!(
+                    "(() => {{ const el = document.querySelector({}); if (!el) return false; \
+                     el.scrollIntoView({{ block: 'center' }}); el.click(); return true; }})()",
+                    js_string(selector)
+                );
+                if self.evaluate(&script).await? != true {
+                    return Err(no_match(selector));
+                }
+                // The click may have started a navigation
+                tokio::time::sleep(Duration::from_millis(300)).await;
+                self.wait_for_load().await;
+            }
+            BrowserAction::Fill { selector, value } => {
+                // The prototype's setter keeps frameworks that track the value in sync
+                let script = format!(
+                    "(() => {{ const el = document.querySelector({}); if (!el) return false; el.focus(); \
+                     const proto = Object.getPrototypeOf(el); \
+                     const setter = Object.getOwnPropertyDescriptor(proto, 'value')?.set; \
+                     if (setter) setter.call(el, {value}); else el.value = {value}; \
+                     el.dispatchEvent(new Event('input', {{ bubbles: true }})); \
+                     el.dispatchEvent(new Event('change', {{ bubbles: true }})); return true; }})()",
+                    js_string(selector),
+                    value = js_string(value)
+                );
+                if self.evaluate(&script).await? != true {
+                    return Err(no_match(selector));
+                }
+            }
+            BrowserAction::Extract { selector } => {
+                let target = match selector {
+                    Some(selector) => format!("document.querySelector({})", js_string(selector)),
+                    None => "document.body".to_string(),
+                };
+                let text = self.evaluate(&format!("(() => {{ const el = {}; return el ? el.innerText : null; }})()", target)).await?;
+                let Some(text) = text.as_str() else {
+                    return Err(no_match(selector.as_deref().unwrap_or("body")));
+                };
+                let end = text.char_indices().nth(MAX_EXTRACT_CHARS).map_or(text.len(), |(end, _)| end);
+                outcome.truncated = end < text.len();
+                outcome.text = Some(text[..end].to_string());
+            }
+            BrowserAction::Screenshot => {
+                let result = self.page("Page.captureScreenshot", serde_json::json!({ "format": "png" })).await?;
+                let png = base64::engine::general_purpose::STANDARD
+                    .decode(result["data"].as_str().unwrap_or_default())
+                    .map_err(|e| HybridLLMError::SandboxError(format!("Invalid screenshot: {}", e)))?;
+                let relative = format!(
+                    "screenshots/{}-{}.png",
+                    self.info.id.simple(),
+                    self.screenshots.fetch_add(1, Ordering::Relaxed) + 1
+                );
+                let path = self.root.join(&relative);
+                let fs_err = |e: std::io::Error| HybridLLMError::FileSystemError(e.to_string());
+                tokio::fs::create_dir_all(path.parent().unwrap_or(&self.root)).await.map_err(fs_err)?;
+                tokio::fs::write(&path, png).await.map_err(fs_err)?;
+                outcome.screenshot = Some(relative);
+            }
+        }
+
+        let page = self.evaluate("({ url: location.href, title: document.title })").await?;
+        outcome.url = page["url"].as_str().unwrap_or_default().to_string();
+        outcome.title = page["title"].as_str().unwrap_or_default().to_string();
+        Ok(outcome)
+    }
+
+    async fn shutdown(&self) {
+        self.devtools.kill(&self.confinement).await;
+    }
+}
+
+/// `text` as a JavaScript string literal
+fn js_string(text: &str) -> String {
+    serde_json::Value::from(text).to_string()
+}
+
+fn no_match(selector: &str) -> HybridLLMError {
+    HybridLLMError::InvalidRequest(format!("No element matches {}", selector))
+}
+
+impl SandboxManager {
+    /// Start a headless Chromium in a sandbox, driven through `browser_action`
+    /// Needs Chromium on the sandbox's PATH and cgroup v2 memory limits; pages load through the sandbox's network mode
+    pub async fn open_browser(&self, sandbox_id: Uuid) -> Result<Uuid> {
+        let context = self.prepare(sandbox_id, None).await?;
+        let devtools = DevTools::launch(&context.cwd, &context.environment, &context.confinement)?;
+
+        let attach = async {
+            let target = devtools.call(None, "Target.createTarget", serde_json::json!({ "url": "about:blank" })).await?;
+            let session = devtools
+                .call(None, "Target.attachToTarget", serde_json::json!({ "targetId": target["targetId"], "flatten": true }))
+                .await?;
+            session["sessionId"]
+                .as_str()
+                .map(str::to_string)
+                .ok_or_else(|| HybridLLMError::SandboxError("Browser returned no page session".to_string()))
+        };
+        let session = match attach.await {
+            Ok(session) => session,
+            Err(e) => {
+                devtools.kill(&context.confinement).await;
+                return Err(e);
+            }
+        };
+
+        let id = Uuid::new_v4();
+        let browser = Browser {
+            info: BrowserInfo { id, sandbox_id, started_at: Utc::now() },
+            root: context.cwd,
+            confinement: context.confinement,
+            devtools,
+            session,
+            busy: tokio::sync::Mutex::new(()),
+            screenshots: AtomicU64::new(0),
+        };
+        self.browsers.write().await.insert(id, Arc::new(browser));
+
+        info!("🧭 Started browser {} in sandbox {}", id, sandbox_id);
+        let _ = self.events.send(SandboxEvent::BrowserAction {
+            sandbox_id,
+            browser_id: id,
+            llm_id: context.llm_id,
+            action: "open".to_string(),
+            url: None,
+            error: None,
+        });
+        Ok(id)
+    }
+
+    /// Run one action in a sandboxed browser; every action is reported for the audit log
+    pub async fn browser_action(&self, browser_id: Uuid, action: &BrowserAction) -> Result<BrowserOutcome> {
+        let browser = self.browser(browser_id).await?;
+        let sandbox_id = browser.info.sandbox_id;
+        let context = self.prepare(sandbox_id, None).await?;
+
+        context.confinement.mark_active();
+        let outcome = browser.run(action).await;
+        context.confinement.mark_active();
+
+        let _ = self.events.send(SandboxEvent::BrowserAction {
+            sandbox_id,
+            browser_id,
+            llm_id: context.llm_id,
+            action: action.summary(),
+            url: outcome.as_ref().ok().map(|outcome| outcome.url.clone()),
+            error: outcome.as_ref().err().map(ToString::to_string),
+        });
+        outcome
+    }
+
+    /// Stop a browser and forget it
+    pub async fn close_browser(&self, browser_id: Uuid) -> Result<()> {
+        let browser = self.browsers.write().await.remove(&browser_id).ok_or_else(|| {
+            HybridLLMError::SandboxError(format!("Browser not found: {}", browser_id))
+        })?;
+        browser.shutdown().await;
+
+        info!("🧭 Closed browser {}", browser_id);
+        Ok(())
+    }
+
+    /// Browsers running in a sandbox
+    pub async fn list_browsers(&self, sandbox_id: Uuid) -> Vec<BrowserInfo> {
+        self.browsers
+            .read()
+            .await
+            .values()
+            .map(|browser| browser.info.clone())
+            .filter(|info| info.sandbox_id == sandbox_id)
+            .collect()
+    }
+
+    /// Stop every browser in a sandbox that is being destroyed
+    pub(crate) async fn close_sandbox_browsers(&self, sandbox_id: Uuid) {
+        let browsers: Vec<Arc<Browser>> = {
+            let mut all = self.browsers.write().await;
+            let ids: Vec<Uuid> = all
+                .iter()
+                .filter(|(_, browser)| browser.info.sandbox_id == sandbox_id)
+                .map(|(id, _)| *id)
+                .collect();
+            ids.iter().filter_map(|id| all.remove(id)).collect()
+        };
+
+        for browser in browsers {
+            browser.shutdown().await;
+        }
+    }
+
+    async fn browser(&self, browser_id: Uuid) -> Result<Arc<Browser>> {
+        self.browsers
+            .read()
+            .await
+            .get(&browser_id)
+            .cloned()
+            .ok_or_else(|| HybridLLMError::SandboxError(format!("Browser not found: {}", browser_id)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_browser_action() {
+        let fill: BrowserAction = serde_json::from_value(serde_json::json!({
+            "action": "fill",
+            "selector": "#password",
+            "value": "hunter2",
+        }))
+        .unwrap();
+        assert_eq!(fill.summary(), "fill #password (7 chars)");
+
+        let extract: BrowserAction = serde_json::from_value(serde_json::json!({ "action": "extract" })).unwrap();
+        assert_eq!(extract, BrowserAction::Extract { selector: None });
+        assert!(serde_json::from_value::<BrowserAction>(serde_json::json!({ "action": "download" })).is_err());
+
+        assert_eq!(js_string(r#"a[name="q"]</script>"#), r#""a[name=\"q\"]</script>""#);
+    }
+}
@@ -0,0 +1,328 @@
+use common::errors::{Result, HybridLLMError};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use std::process::Stdio;
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncBufReadExt, AsyncRead, BufReader};
+use tokio::process::Command;
+use tokio::sync::mpsc;
+use tracing::{debug, warn};
+
+use crate::limits::Confinement;
+
+/// Maximum bytes of each output stream kept in an `ExecutionResult`
+const MAX_CAPTURED_OUTPUT: usize = 4 * 1024 * 1024;
+
+/// How long a timed-out command gets to exit after SIGTERM before it is killed
+const KILL_GRACE_PERIOD: Duration = Duration::from_secs(3);
+
+/// PATH given to sandboxed commands
+pub(crate) const DEFAULT_PATH: &str = "/usr/local/bin:/usr/bin:/bin";
+
+/// Outcome of a command executed in a sandbox
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExecutionResult {
+    pub stdout: String,
+    pub stderr: String,
+    pub exit_code: i32,
+    pub duration_ms: u64,
+    /// The command was killed for exceeding its execution timeout
+    #[serde(default)]
+    pub timed_out: bool,
+}
+
+/// Incremental output from a running command
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ExecutionEvent {
+    Stdout { line: String },
+    Stderr { line: String },
+    Finished { result: ExecutionResult },
+}
+
+/// Run a shell command in `working_dir` under `confinement`, forwarding output lines to `events` as they arrive
+/// `env` is applied on top of the minimal default environment
+/// After `timeout` the command is sent SIGTERM, then SIGKILL if it is still running
+pub(crate) async fn run_command(
+    command: &str,
+    working_dir: &Path,
+    env: &[(String, String)],
+    confinement: &Confinement,
+    timeout: Option<Duration>,
+    events: Option<mpsc::Sender<ExecutionEvent>>,
+) -> Result<ExecutionResult> {
+    let started = Instant::now();
+
+    let mut process = Command::new("sh");
+    process
+        .arg("-c")
+        .arg(command)
+        .current_dir(working_dir)
+        .env_clear()
+        .env("PATH", DEFAULT_PATH)
+        .env("HOME", working_dir)
+        .envs(env.iter().map(|(key, value)| (key, value)))
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true);
+
+    confinement.apply(&mut process);
+
+    let mut child = process
+        .spawn()
+        .map_err(|e| HybridLLMError::SandboxError(format!("Failed to spawn command: {}", e)))?;
+
+    let pid = child.id();
+    if let Some(pid) = pid {
+        confinement.track(pid);
+    }
+
+    let stdout = child.stdout.take().expect("stdout is piped");
+    let stderr = child.stderr.take().expect("stderr is piped");
+
+    // Confinement makes the command lead its own process group, so the whole tree is signalled
+    let target = pid.map(|pid| -(pid as i32));
+
+    let wait = async {
+        let Some(limit) = timeout else {
+            return (child.wait().await, false);
+        };
+        match tokio::time::timeout(limit, child.wait()).await {
+            Ok(status) => (status, false),
+            Err(_) => {
+                warn!("⏱️  Command exceeded {}s timeout, terminating: {}", limit.as_secs(), command);
+                signal(target, SIGTERM);
+                if tokio::time::timeout(KILL_GRACE_PERIOD, child.wait()).await.is_err() {
+                    signal(target, SIGKILL);
+                }
+                (child.wait().await, true)
+            }
+        }
+    };
+
+    let (stdout, stderr, (status, timed_out)) = tokio::join!(
+        capture(stdout, events.clone(), |line| ExecutionEvent::Stdout { line }),
+        capture(stderr, events.clone(), |line| ExecutionEvent::Stderr { line }),
+        wait,
+    );
+
+    if let Some(pid) = pid {
+        confinement.untrack(pid);
+    }
+
+    let status = status.map_err(|e| HybridLLMError::SandboxError(e.to_string()))?;
+
+    let result = ExecutionResult {
+        stdout,
+        stderr,
+        // Killed by a signal: report the conventional 128 + signal code
+        exit_code: status.code().unwrap_or_else(|| signal_exit_code(&status)),
+        duration_ms: started.elapsed().as_millis() as u64,
+        timed_out,
+    };
+
+    debug!("🏁 Command finished with exit code {} in {}ms", result.exit_code, result.duration_ms);
+
+    if let Some(events) = events {
+        let _ = events.send(ExecutionEvent::Finished { result: result.clone() }).await;
+    }
+
+    Ok(result)
+}
+
+/// Read a stream line by line, capturing it and optionally forwarding each line
+async fn capture<R, F>(
+    stream: R,
+    events: Option<mpsc::Sender<ExecutionEvent>>,
+    to_event: F,
+) -> String
+where
+    R: AsyncRead + Unpin,
+    F: Fn(String) -> ExecutionEvent,
+{
+    let mut lines = BufReader::new(stream).lines();
+    let mut captured = String::new();
+
+    while let Ok(Some(line)) = lines.next_line().await {
+        if captured.len() + line.len() < MAX_CAPTURED_OUTPUT {
+            captured.push_str(&line);
+            captured.push('\n');
+        }
+
+        if let Some(events) = &events {
+            let _ = events.send(to_event(line)).await;
+        }
+    }
+
+    captured
+}
+
+#[cfg(unix)]
+const SIGTERM: i32 = libc::SIGTERM;
+#[cfg(unix)]
+const SIGKILL: i32 = libc::SIGKILL;
+#[cfg(not(unix))]
+const SIGTERM: i32 = 15;
+#[cfg(not(unix))]
+const SIGKILL: i32 = 9;
+
+/// Send a signal to a process, or to a process group when `target` is negative
+#[cfg(unix)]
+fn signal(target: Option<i32>, signal: i32) {
+    if let Some(target) = target {
+        // SAFETY: plain syscall on a process (group) we spawned
+        unsafe {
+            libc::kill(target, signal);
+        }
+    }
+}
+
+#[cfg(not(unix))]
+fn signal(_target: Option<i32>, _signal: i32) {}
+
+#[cfg(unix)]
+fn signal_exit_code(status: &std::process::ExitStatus) -> i32 {
+    use std::os::unix::process::ExitStatusExt;
+    status.signal().map(|s| 128 + s).unwrap_or(-1)
+}
+
+#[cfg(not(unix))]
+fn signal_exit_code(_status: &std::process::ExitStatus) -> i32 {
+    -1
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use common::types::{NetworkMode, SandboxConfig};
+    use std::path::PathBuf;
+    use uuid::Uuid;
+
+    /// A sandbox root with its confinement, removed by the caller
+    async fn sandbox() -> (PathBuf, Confinement) {
+        let base = std::env::temp_dir().join(format!("execution-{}", Uuid::new_v4()));
+        let root = base.join("root");
+        std::fs::create_dir_all(&root).unwrap();
+
+        let config = SandboxConfig {
+            id: Uuid::new_v4(),
+            network_mode: NetworkMode::None,
+            cpu_limit: 100.0,
+            memory_limit_gb: 1.0,
+            disk_limit_gb: 1.0,
+            allowed_commands: vec![],
+            template: None,
+            max_lifetime_secs: None,
+            idle_timeout_secs: None,
+            execution_timeout_secs: None,
+            llm_id: None,
+            gpu: None,
+            volumes: vec![],
+        };
+        let confinement = Confinement::new(&config, &root, &base.join("staging")).await.unwrap();
+        (base, confinement)
+    }
+
+    #[tokio::test]
+    async fn test_captures_output_and_exit_code() {
+        let (base, confinement) = sandbox().await;
+        let dir = base.join("root");
+        let result = run_command("echo out; echo err >&2; exit 3", &dir, &[], &confinement, None, None)
+            .await
+            .unwrap();
+
+        assert_eq!(result.stdout, "out\n");
+        assert_eq!(result.stderr, "err\n");
+        assert_eq!(result.exit_code, 3);
+        confinement.release();
+        std::fs::remove_dir_all(&base).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_streams_events() {
+        let (base, confinement) = sandbox().await;
+        let dir = base.join("root");
+        let (tx, mut rx) = mpsc::channel(16);
+        run_command("echo one; echo two", &dir, &[], &confinement, None, Some(tx)).await.unwrap();
+
+        let mut lines = Vec::new();
+        while let Some(event) = rx.recv().await {
+            match event {
+                ExecutionEvent::Stdout { line } => lines.push(line),
+                ExecutionEvent::Finished { result } => assert_eq!(result.exit_code, 0),
+                ExecutionEvent::Stderr { .. } => {}
+            }
+        }
+
+        assert_eq!(lines, vec!["one", "two"]);
+        confinement.release();
+        std::fs::remove_dir_all(&base).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_timeout_terminates_command() {
+        let (base, confinement) = sandbox().await;
+        let dir = base.join("root");
+        let result = run_command(
+            "echo started; sleep 30",
+            &dir,
+            &[],
+            &confinement,
+            Some(Duration::from_millis(200)),
+            None,
+        )
+        .await
+        .unwrap();
+
+        assert!(result.timed_out);
+        assert_eq!(result.stdout, "started\n");
+        assert!(result.duration_ms < 5_000);
+        confinement.release();
+        std::fs::remove_dir_all(&base).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_only_sandbox_root_is_visible() {
+        let (base, confinement) = sandbox().await;
+        let dir = base.join("root");
+        std::fs::write(base.join("secret"), "host").unwrap();
+
+        let command = format!(
+            "cat {}; echo written > file; touch /usr/escape; cat file",
+            base.join("secret").display()
+        );
+        let result = run_command(&command, &dir, &[], &confinement, None, None).await.unwrap();
+
+        assert_eq!(result.stdout, "written\n");
+        assert!(result.stderr.contains("secret"));
+        assert!(dir.join("file").exists());
+        assert!(!Path::new("/usr/escape").exists());
+        confinement.release();
+        std::fs::remove_dir_all(&base).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_background_processes_outlive_their_command() {
+        let (base, confinement) = sandbox().await;
+        let dir = base.join("root");
+        let started = run_command("sleep 30 >/dev/null 2>&1 &", &dir, &[], &confinement, None, None).await.unwrap();
+        assert_eq!(started.exit_code, 0);
+
+        // Only the sandbox's own processes are listed, the sleep among them
+        let listed = run_command(
+            "for p in /proc/[0-9]*; do tr '\\0' ' ' < $p/cmdline; echo; done",
+            &dir,
+            &[],
+            &confinement,
+            None,
+            None,
+        )
+        .await
+        .unwrap();
+        assert!(listed.stdout.contains("sleep 30"), "{:?}", listed);
+        assert!(!listed.stdout.contains("cargo"), "{:?}", listed);
+        confinement.release();
+        std::fs::remove_dir_all(&base).unwrap();
+    }
+}
@@ -0,0 +1,171 @@
+use chrono::{DateTime, Utc};
+use common::errors::{Result, HybridLLMError};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::Path;
+use std::time::SystemTime;
+
+use crate::artifacts;
+use crate::volumes;
+
+/// A file or directory inside a sandbox
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SandboxFile {
+    pub name: String,
+    /// Path relative to the sandbox root
+    pub path: String,
+    pub size_bytes: u64,
+    pub modified: DateTime<Utc>,
+    pub is_directory: bool,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum FileChangeKind {
+    Created,
+    Modified,
+    Deleted,
+}
+
+/// A difference between a sandbox and its baseline
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FileChange {
+    pub path: String,
+    pub kind: FileChangeKind,
+    /// Current size; `None` for deleted files
+    pub size_bytes: Option<u64>,
+}
+
+/// Size and modification time of every file when the sandbox was created
+pub(crate) type Baseline = HashMap<String, (u64, SystemTime)>;
+
+/// Record the current state of a sandbox as its baseline
+pub(crate) fn capture_baseline(root: &Path) -> Baseline {
+    walk_files(root)
+        .map(|(path, metadata)| (path, (metadata.len(), modified(&metadata))))
+        .collect()
+}
+
+/// Files created, modified or deleted since the baseline, sorted by path
+pub(crate) fn diff(root: &Path, baseline: &Baseline) -> Vec<FileChange> {
+    let mut seen = std::collections::HashSet::new();
+    let mut changes = Vec::new();
+
+    for (path, metadata) in walk_files(root) {
+        let current = (metadata.len(), modified(&metadata));
+        let kind = match baseline.get(&path) {
+            None => Some(FileChangeKind::Created),
+            Some(original) if *original != current => Some(FileChangeKind::Modified),
+            Some(_) => None,
+        };
+
+        if let Some(kind) = kind {
+            changes.push(FileChange { path: path.clone(), kind, size_bytes: Some(current.0) });
+        }
+        seen.insert(path);
+    }
+
+    changes.extend(
+        baseline
+            .keys()
+            .filter(|path| !seen.contains(*path))
+            .map(|path| FileChange { path: path.clone(), kind: FileChangeKind::Deleted, size_bytes: None }),
+    );
+
+    changes.sort_by(|a, b| a.path.cmp(&b.path));
+    changes
+}
+
+/// List the entries of one directory in a sandbox, directories first
+/// `dir` is relative to the sandbox root; `None` lists the root itself
+pub(crate) fn list_dir(root: &Path, dir: Option<&str>) -> Result<Vec<SandboxFile>> {
+    let (root, dir) = artifacts::resolve_in_sandbox(root, dir.unwrap_or("."))?;
+    if !dir.is_dir() {
+        return Err(HybridLLMError::InvalidRequest(format!(
+            "Not a directory: {}",
+            dir.strip_prefix(&root).unwrap_or(&dir).display()
+        )));
+    }
+
+    let entries = std::fs::read_dir(&dir).map_err(|e| HybridLLMError::FileSystemError(e.to_string()))?;
+
+    let mut files: Vec<SandboxFile> = entries
+        .filter_map(|e| e.ok())
+        .filter_map(|entry| {
+            // symlink_metadata so links are reported, not followed out of the sandbox
+            let metadata = entry.path().symlink_metadata().ok()?;
+            let path = entry.path().strip_prefix(&root).ok()?.to_string_lossy().into_owned();
+            Some(SandboxFile {
+                name: entry.file_name().to_string_lossy().into_owned(),
+                path,
+                size_bytes: if metadata.is_dir() { 0 } else { metadata.len() },
+                modified: modified(&metadata).into(),
+                is_directory: metadata.is_dir(),
+            })
+        })
+        .collect();
+
+    files.sort_by(|a, b| b.is_directory.cmp(&a.is_directory).then_with(|| a.name.cmp(&b.name)));
+    Ok(files)
+}
+
+/// Regular files and symlinks under `root` with paths relative to it
+/// Mounted volumes are skipped; their contents do not belong to the sandbox
+fn walk_files(root: &Path) -> impl Iterator<Item = (String, std::fs::Metadata)> + '_ {
+    walkdir::WalkDir::new(root)
+        .follow_links(false)
+        .into_iter()
+        .filter_entry(|e| !volumes::is_volumes_dir(e))
+        .filter_map(|e| e.ok())
+        .filter(|e| !e.file_type().is_dir())
+        .filter_map(move |e| {
+            let metadata = e.metadata().ok()?;
+            let path = e.path().strip_prefix(root).ok()?.to_string_lossy().into_owned();
+            Some((path, metadata))
+        })
+}
+
+fn modified(metadata: &std::fs::Metadata) -> SystemTime {
+    metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_diff_against_baseline() {
+        let root = std::env::temp_dir().join(format!("files-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(root.join("src")).unwrap();
+        std::fs::write(root.join("src/keep.py"), "keep").unwrap();
+        std::fs::write(root.join("src/edit.py"), "old").unwrap();
+        std::fs::write(root.join("gone.txt"), "bye").unwrap();
+
+        let baseline = capture_baseline(&root);
+
+        std::fs::write(root.join("src/edit.py"), "new contents").unwrap();
+        std::fs::remove_file(root.join("gone.txt")).unwrap();
+        std::fs::write(root.join("out.csv"), "a,b").unwrap();
+
+        let changes: Vec<_> = diff(&root, &baseline)
+            .into_iter()
+            .map(|change| (change.path, change.kind))
+            .collect();
+        assert_eq!(
+            changes,
+            vec![
+                ("gone.txt".to_string(), FileChangeKind::Deleted),
+                ("out.csv".to_string(), FileChangeKind::Created),
+                ("src/edit.py".to_string(), FileChangeKind::Modified),
+            ]
+        );
+
+        let listing = list_dir(&root, None).unwrap();
+        assert_eq!(listing[0].name, "src");
+        assert!(listing[0].is_directory);
+        assert_eq!(list_dir(&root, Some("src")).unwrap()[0].path, "src/edit.py");
+        assert!(list_dir(&root, Some("../")).is_err());
+
+        let _ = std::fs::remove_dir_all(root);
+    }
+}
@@ -0,0 +1,250 @@
+use chrono::{DateTime, Utc};
+use common::errors::{Result, HybridLLMError};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::net::TcpListener;
+use tokio::task::JoinHandle;
+use tracing::{info, debug};
+use uuid::Uuid;
+
+use crate::limits::Confinement;
+use crate::{SandboxEvent, SandboxManager};
+
+/// Longest a port may stay exposed, whatever was requested
+const MAX_FORWARD_DURATION: Duration = Duration::from_secs(3600);
+
+/// A sandbox port exposed on the host's loopback interface
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PortForward {
+    pub id: Uuid,
+    pub sandbox_id: Uuid,
+    pub sandbox_port: u16,
+    /// Port on 127.0.0.1 that reaches the sandbox port
+    pub host_port: u16,
+    pub created_at: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
+}
+
+/// Why a port forward stopped
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ForwardCloseReason {
+    Expired,
+    Closed,
+    SandboxDestroyed,
+}
+
+struct ActiveForward {
+    forward: PortForward,
+    llm_id: Option<String>,
+    task: JoinHandle<()>,
+}
+
+/// Port forwards currently open, by forward ID
+#[derive(Default)]
+pub(crate) struct PortForwards {
+    active: Arc<Mutex<HashMap<Uuid, ActiveForward>>>,
+}
+
+impl SandboxManager {
+    /// Expose `sandbox_port` on a free localhost port for `duration`, capped at one hour
+    /// Callers are responsible for getting the user's approval first
+    pub async fn forward_port(&self, sandbox_id: Uuid, sandbox_port: u16, duration: Duration) -> Result<PortForward> {
+        let (llm_id, confinement) = {
+            let sandboxes = self.sandboxes.read().await;
+            let sandbox = sandboxes
+                .get(&sandbox_id)
+                .ok_or_else(|| HybridLLMError::SandboxError(format!("Sandbox not found: {}", sandbox_id)))?;
+            (sandbox.config.llm_id.clone(), Arc::clone(&sandbox.confinement))
+        };
+
+        let listener = TcpListener::bind("127.0.0.1:0")
+            .await
+            .map_err(|e| HybridLLMError::NetworkError(e.to_string()))?;
+        let host_port = listener
+            .local_addr()
+            .map_err(|e| HybridLLMError::NetworkError(e.to_string()))?
+            .port();
+
+        let duration = duration.min(MAX_FORWARD_DURATION);
+        let created_at = Utc::now();
+        let forward = PortForward {
+            id: Uuid::new_v4(),
+            sandbox_id,
+            sandbox_port,
+            host_port,
+            created_at,
+            expires_at: created_at + chrono::Duration::from_std(duration).unwrap_or_default(),
+        };
+
+        info!("🔌 Forwarding localhost:{} to sandbox {} port {} for {:?}",
+              host_port, sandbox_id, sandbox_port, duration);
+
+        // Hold the lock across the spawn so the expiry cannot run before the insert
+        let mut active = self.forwards.active.lock().unwrap();
+        let task = {
+            let forwards = Arc::clone(&self.forwards.active);
+            let events = self.events.clone();
+            let forward_id = forward.id;
+
+            tokio::spawn(async move {
+                tokio::select! {
+                    _ = accept_loop(listener, confinement, sandbox_port) => {}
+                    _ = tokio::time::sleep(duration) => {}
+                }
+
+                let expired = forwards.lock().unwrap().remove(&forward_id);
+                if let Some(expired) = expired {
+                    info!("🔌 Port forward {} expired", forward_id);
+                    let _ = events.send(SandboxEvent::PortForwardClosed {
+                        forward: expired.forward,
+                        llm_id: expired.llm_id,
+                        reason: ForwardCloseReason::Expired,
+                    });
+                }
+            })
+        };
+        active.insert(forward.id, ActiveForward { forward: forward.clone(), llm_id: llm_id.clone(), task });
+        drop(active);
+
+        let _ = self.events.send(SandboxEvent::PortForwardOpened { forward: forward.clone(), llm_id });
+        Ok(forward)
+    }
+
+    /// Tear down a port forward before it expires
+    pub fn close_port_forward(&self, forward_id: Uuid) -> Result<()> {
+        let closed = self.forwards.active.lock().unwrap().remove(&forward_id);
+        let closed = closed.ok_or_else(|| {
+            HybridLLMError::InvalidRequest(format!("No open port forward: {}", forward_id))
+        })?;
+
+        self.report_closed(closed, ForwardCloseReason::Closed);
+        Ok(())
+    }
+
+    /// Port forwards currently open, oldest first
+    pub fn port_forwards(&self) -> Vec<PortForward> {
+        let mut forwards: Vec<PortForward> = self
+            .forwards
+            .active
+            .lock()
+            .unwrap()
+            .values()
+            .map(|active| active.forward.clone())
+            .collect();
+        forwards.sort_by_key(|forward| forward.created_at);
+        forwards
+    }
+
+    /// Tear down every forward into a sandbox that is being destroyed
+    pub(crate) fn close_sandbox_forwards(&self, sandbox_id: Uuid) {
+        let closed: Vec<ActiveForward> = {
+            let mut active = self.forwards.active.lock().unwrap();
+            let ids: Vec<Uuid> = active
+                .values()
+                .filter(|active| active.forward.sandbox_id == sandbox_id)
+                .map(|active| active.forward.id)
+                .collect();
+            ids.iter().filter_map(|id| active.remove(id)).collect()
+        };
+
+        for forward in closed {
+            self.report_closed(forward, ForwardCloseReason::SandboxDestroyed);
+        }
+    }
+
+    fn report_closed(&self, closed: ActiveForward, reason: ForwardCloseReason) {
+        closed.task.abort();
+        debug!("🔌 Closed port forward {} ({:?})", closed.forward.id, reason);
+        let _ = self.events.send(SandboxEvent::PortForwardClosed {
+            forward: closed.forward,
+            llm_id: closed.llm_id,
+            reason,
+        });
+    }
+}
+
+async fn accept_loop(listener: TcpListener, confinement: Arc<Confinement>, sandbox_port: u16) {
+    while let Ok((mut client, _)) = listener.accept().await {
+        let confinement = Arc::clone(&confinement);
+        tokio::spawn(async move {
+            // TODO: Dial the VM's address once sandboxes run in Firecracker
+            match confinement.connect(sandbox_port).await {
+                Ok(mut upstream) => {
+                    let _ = tokio::io::copy_bidirectional(&mut client, &mut upstream).await;
+                }
+                Err(e) => debug!("Port forward to {} failed: {}", sandbox_port, e),
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::AsyncReadExt;
+    use tokio::net::TcpStream;
+
+    /// Serves "dev server" on port 8000 of the sandbox's own loopback
+    const SERVER: &str = "python3 -c '
+import socket
+server = socket.create_server((\"127.0.0.1\", 8000))
+while True:
+    conn, _ = server.accept()
+    conn.sendall(b\"dev server\")
+    conn.close()
+' >/dev/null 2>&1 &";
+
+    async fn read_forward(host_port: u16) -> String {
+        let mut reply = String::new();
+        if let Ok(mut client) = TcpStream::connect(("127.0.0.1", host_port)).await {
+            let _ = client.read_to_string(&mut reply).await;
+        }
+        reply
+    }
+
+    #[tokio::test]
+    async fn test_forward_expires_and_closes_with_sandbox() {
+        let base = std::env::temp_dir().join(format!("sandboxes-{}", Uuid::new_v4()));
+        let manager = SandboxManager::new(base.clone()).unwrap();
+        let sandbox_id = manager.checkout(None, Some("coder".to_string())).await.unwrap();
+
+        // The server keeps running after its command, in the sandbox's own network namespace
+        manager.execute(sandbox_id, SERVER, None).await.unwrap();
+        let sandbox_port = 8000;
+
+        let forward = manager.forward_port(sandbox_id, sandbox_port, Duration::from_secs(60)).await.unwrap();
+        let mut reply = String::new();
+        for _ in 0..50 {
+            reply = read_forward(forward.host_port).await;
+            if !reply.is_empty() {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+        assert_eq!(reply, "dev server");
+        assert_eq!(manager.port_forwards().len(), 1);
+
+        let mut events = manager.subscribe();
+        let short = manager.forward_port(sandbox_id, sandbox_port, Duration::from_millis(50)).await.unwrap();
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        assert!(manager.port_forwards().iter().all(|f| f.id != short.id));
+        assert!(TcpStream::connect(("127.0.0.1", short.host_port)).await.is_err());
+
+        manager.destroy_sandbox(sandbox_id).await.unwrap();
+        assert!(manager.port_forwards().is_empty());
+
+        let mut reasons = Vec::new();
+        while let Ok(event) = events.try_recv() {
+            if let SandboxEvent::PortForwardClosed { reason, llm_id, .. } = event {
+                assert_eq!(llm_id.as_deref(), Some("coder"));
+                reasons.push(reason);
+            }
+        }
+        assert_eq!(reasons, vec![ForwardCloseReason::Expired, ForwardCloseReason::SandboxDestroyed]);
+
+        let _ = std::fs::remove_dir_all(base);
+    }
+}
@@ -0,0 +1,328 @@
+use common::errors::{HybridLLMError, Result};
+use std::collections::HashSet;
+use std::ffi::{CStr, CString};
+use std::io;
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Component, Path, PathBuf};
+
+use crate::volumes::VOLUMES_DIR;
+
+/// Directory under the sandboxes path that each process mounts its private root on
+/// Every process gets its own mount namespace, so they can all share it
+pub(crate) const STAGING_DIR: &str = "isolation";
+
+/// Host directories exposed read-only, recreated as symlinks where the host links them
+const SYSTEM_DIRS: &[&str] = &["/usr", "/bin", "/sbin", "/lib", "/lib32", "/lib64", "/libx32"];
+
+/// Entries under /etc that programs need for users, name resolution, certificates and libraries
+const ETC_ENTRIES: &[&str] = &[
+    "alternatives",
+    "ca-certificates",
+    "fonts",
+    "gai.conf",
+    "group",
+    "host.conf",
+    "hosts",
+    "ld.so.cache",
+    "ld.so.conf",
+    "ld.so.conf.d",
+    "localtime",
+    "mime.types",
+    "nsswitch.conf",
+    "os-release",
+    "passwd",
+    "pki",
+    "protocols",
+    "resolv.conf",
+    "services",
+    "ssl",
+];
+
+/// Device nodes bound in from the host
+const DEVICES: &[&str] = &["/dev/null", "/dev/zero", "/dev/full", "/dev/random", "/dev/urandom", "/dev/tty"];
+
+/// Scratch directories backed by a private tmpfs
+const SCRATCH_DIRS: &[&str] = &["/tmp", "/dev/shm"];
+
+/// Links into the sandbox's own /proc that programs expect under /dev
+const PROC_LINKS: &[(&str, &str)] = &[
+    ("/dev/fd", "/proc/self/fd"),
+    ("/dev/stdin", "/proc/self/fd/0"),
+    ("/dev/stdout", "/proc/self/fd/1"),
+    ("/dev/stderr", "/proc/self/fd/2"),
+];
+
+/// One filesystem operation performed while building the private root
+enum Step {
+    Dir(CString),
+    File(CString),
+    Symlink { target: CString, path: CString },
+    Tmpfs(CString),
+    Proc(CString),
+    /// `remount` holds the flags for a read-only remount, keeping those the source is locked with
+    Bind { source: CString, path: CString, remount: Option<libc::c_ulong> },
+}
+
+enum Mount {
+    Bind { source: PathBuf, read_only: bool },
+    Symlink(PathBuf),
+    Tmpfs,
+    Proc,
+}
+
+/// Private filesystem view for sandbox processes
+/// After fork the child builds a tmpfs root holding read-only system directories, a /proc
+/// showing only the sandbox's PID namespace and the sandbox root, mounted at its host path
+/// so working directories and template paths stay valid, then pivots into it; nothing else
+/// on the host is reachable
+pub(crate) struct Isolation {
+    staging: CString,
+    steps: Vec<Step>,
+}
+
+impl Isolation {
+    /// Plan the view for processes in the sandbox rooted at `root`
+    /// `read_only` lists further host trees to expose, such as a toolchain
+    pub(crate) fn new(root: &Path, staging: &Path, read_only: &[PathBuf]) -> Result<Self> {
+        let fs_err = |e: io::Error| HybridLLMError::SandboxError(format!("Failed to prepare sandbox isolation: {}", e));
+        std::fs::create_dir_all(staging).map_err(fs_err)?;
+        let staging = staging.canonicalize().map_err(fs_err)?;
+
+        let mut mounts = Vec::new();
+        for dir in SYSTEM_DIRS.iter().map(Path::new) {
+            match std::fs::read_link(dir) {
+                Ok(target) => mounts.push((dir.to_path_buf(), Mount::Symlink(target))),
+                Err(_) if dir.is_dir() => {
+                    mounts.push((dir.to_path_buf(), Mount::Bind { source: dir.to_path_buf(), read_only: true }))
+                }
+                Err(_) => {}
+            }
+        }
+        // Followed rather than recreated: links such as resolv.conf point outside the view
+        for entry in ETC_ENTRIES.iter().map(|name| Path::new("/etc").join(name)) {
+            if entry.exists() {
+                mounts.push((entry.clone(), Mount::Bind { source: entry, read_only: true }));
+            }
+        }
+        for device in DEVICES.iter().map(Path::new).filter(|device| device.exists()) {
+            mounts.push((device.to_path_buf(), Mount::Bind { source: device.to_path_buf(), read_only: false }));
+        }
+        for dir in SCRATCH_DIRS {
+            mounts.push((PathBuf::from(dir), Mount::Tmpfs));
+        }
+        mounts.push((PathBuf::from("/proc"), Mount::Proc));
+        for (link, target) in PROC_LINKS {
+            mounts.push((PathBuf::from(link), Mount::Symlink(PathBuf::from(target))));
+        }
+        for tree in read_only {
+            let tree = tree.canonicalize().map_err(fs_err)?;
+            mounts.push((tree.clone(), Mount::Bind { source: tree, read_only: true }));
+        }
+
+        // Bound at the path commands are given, and at the resolved one getcwd reports
+        let absolute = std::path::absolute(root).map_err(fs_err)?;
+        let canonical = root.canonicalize().map_err(fs_err)?;
+        if absolute != canonical {
+            mounts.push((absolute, Mount::Bind { source: canonical.clone(), read_only: false }));
+        }
+        mounts.push((canonical.clone(), Mount::Bind { source: canonical.clone(), read_only: false }));
+
+        // Read-write volumes attached without mount privileges are symlinks into the volume store
+        if let Ok(entries) = std::fs::read_dir(canonical.join(VOLUMES_DIR)) {
+            for entry in entries.filter_map(|e| e.ok()) {
+                if entry.file_type().is_ok_and(|t| t.is_symlink()) {
+                    if let Ok(target) = entry.path().canonicalize() {
+                        mounts.push((target.clone(), Mount::Bind { source: target, read_only: false }));
+                    }
+                }
+            }
+        }
+
+        // Outer mounts first, so /tmp's tmpfs never hides a sandbox root beneath it
+        mounts.sort_by_key(|(path, _)| path.components().count());
+
+        let mut steps = Vec::new();
+        let mut created = HashSet::new();
+        for (path, mount) in mounts {
+            let target = staged(&staging, &path)?;
+            if let Some(parent) = path.parent() {
+                for dir in parent.ancestors().collect::<Vec<_>>().into_iter().rev() {
+                    if dir.parent().is_some() && created.insert(dir.to_path_buf()) {
+                        steps.push(Step::Dir(staged(&staging, dir)?));
+                    }
+                }
+            }
+
+            match mount {
+                Mount::Symlink(link) => steps.push(Step::Symlink { target: c_path(&link)?, path: target }),
+                Mount::Tmpfs => {
+                    created.insert(path);
+                    steps.push(Step::Dir(target.clone()));
+                    steps.push(Step::Tmpfs(target));
+                }
+                Mount::Proc => {
+                    created.insert(path);
+                    steps.push(Step::Dir(target.clone()));
+                    steps.push(Step::Proc(target));
+                }
+                Mount::Bind { source, read_only } => {
+                    if source.is_dir() {
+                        created.insert(path);
+                        steps.push(Step::Dir(target.clone()));
+                    } else {
+                        steps.push(Step::File(target.clone()));
+                    }
+                    let remount = read_only.then(|| libc::MS_RDONLY | locked_flags(&source));
+                    steps.push(Step::Bind { source: c_path(&source)?, path: target, remount });
+                }
+            }
+        }
+
+        Ok(Self { staging: c_path(&staging)?, steps })
+    }
+
+    /// Move the calling process into the view
+    /// Runs between fork and exec, after the process joined the sandbox's namespaces, so
+    /// only async-signal-safe calls are made
+    ///
+    /// # Safety
+    /// Must only be called in a single-threaded child about to exec
+    pub(crate) unsafe fn enter(&self) -> io::Result<()> {
+        let mut cwd = [0 as libc::c_char; libc::PATH_MAX as usize];
+        if libc::getcwd(cwd.as_mut_ptr(), cwd.len()).is_null() {
+            return Err(io::Error::last_os_error());
+        }
+
+        check(libc::unshare(libc::CLONE_NEWNS))?;
+
+        // Nothing mounted from here on may propagate back to the host
+        check(libc::mount(std::ptr::null(), c"/".as_ptr(), std::ptr::null(), libc::MS_REC | libc::MS_PRIVATE, std::ptr::null()))?;
+        mount_tmpfs(&self.staging, c"mode=0755")?;
+
+        for step in &self.steps {
+            match step {
+                Step::Dir(path) => {
+                    if libc::mkdir(path.as_ptr(), 0o755) != 0 && io::Error::last_os_error().raw_os_error() != Some(libc::EEXIST) {
+                        return Err(io::Error::last_os_error());
+                    }
+                }
+                Step::File(path) => {
+                    let fd = libc::open(path.as_ptr(), libc::O_WRONLY | libc::O_CREAT | libc::O_CLOEXEC, 0o644);
+                    if fd < 0 {
+                        return Err(io::Error::last_os_error());
+                    }
+                    libc::close(fd);
+                }
+                Step::Symlink { target, path } => check(libc::symlink(target.as_ptr(), path.as_ptr()))?,
+                Step::Tmpfs(path) => mount_tmpfs(path, c"mode=1777")?,
+                // Mounted while the host's /proc is still visible, which a user namespace requires
+                Step::Proc(path) => check(libc::mount(
+                    c"proc".as_ptr(),
+                    path.as_ptr(),
+                    c"proc".as_ptr(),
+                    libc::MS_NOSUID | libc::MS_NODEV | libc::MS_NOEXEC,
+                    std::ptr::null(),
+                ))?,
+                Step::Bind { source, path, remount } => {
+                    check(libc::mount(source.as_ptr(), path.as_ptr(), std::ptr::null(), libc::MS_BIND | libc::MS_REC, std::ptr::null()))?;
+                    if let Some(flags) = remount {
+                        check(libc::mount(std::ptr::null(), path.as_ptr(), std::ptr::null(), libc::MS_BIND | libc::MS_REMOUNT | flags, std::ptr::null()))?;
+                    }
+                }
+            }
+        }
+
+        // Stack the old root on the new one, then detach it so only the view is left
+        check(libc::chdir(self.staging.as_ptr()))?;
+        check(libc::syscall(libc::SYS_pivot_root, c".".as_ptr(), c".".as_ptr()) as libc::c_int)?;
+        check(libc::umount2(c".".as_ptr(), libc::MNT_DETACH))?;
+        check(libc::chdir(cwd.as_ptr()))?;
+
+        drop_capabilities()
+    }
+}
+
+/// Path of `path` inside the staged root
+fn staged(staging: &Path, path: &Path) -> Result<CString> {
+    let relative: PathBuf = path.components().filter(|c| matches!(c, Component::Normal(_))).collect();
+    c_path(&staging.join(relative))
+}
+
+fn c_path(path: &Path) -> Result<CString> {
+    CString::new(path.as_os_str().as_bytes())
+        .map_err(|_| HybridLLMError::SandboxError(format!("Path contains a NUL byte: {}", path.display())))
+}
+
+/// Flags a bind of `source` must keep when remounted; a user namespace can't clear them
+fn locked_flags(source: &Path) -> libc::c_ulong {
+    let Ok(path) = c_path(source) else {
+        return 0;
+    };
+    // SAFETY: statvfs only writes into the struct it is given
+    let mut stat: libc::statvfs = unsafe { std::mem::zeroed() };
+    if unsafe { libc::statvfs(path.as_ptr(), &mut stat) } != 0 {
+        return 0;
+    }
+
+    [
+        (libc::ST_NOSUID, libc::MS_NOSUID),
+        (libc::ST_NODEV, libc::MS_NODEV),
+        (libc::ST_NOEXEC, libc::MS_NOEXEC),
+        (libc::ST_NOATIME, libc::MS_NOATIME),
+        (libc::ST_NODIRATIME, libc::MS_NODIRATIME),
+        (libc::ST_RELATIME, libc::MS_RELATIME),
+    ]
+    .into_iter()
+    .filter(|(st, _)| stat.f_flag & st != 0)
+    .fold(0, |flags, (_, ms)| flags | ms)
+}
+
+pub(crate) fn check(ret: libc::c_int) -> io::Result<()> {
+    if ret != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+unsafe fn mount_tmpfs(path: &CString, options: &CStr) -> io::Result<()> {
+    check(libc::mount(
+        c"tmpfs".as_ptr(),
+        path.as_ptr(),
+        c"tmpfs".as_ptr(),
+        libc::MS_NOSUID | libc::MS_NODEV,
+        options.as_ptr() as *const libc::c_void,
+    ))
+}
+
+/// Leave the command no capabilities, even as root, so it can't undo the mounts
+unsafe fn drop_capabilities() -> io::Result<()> {
+    #[repr(C)]
+    struct Header {
+        version: u32,
+        pid: libc::c_int,
+    }
+    #[repr(C)]
+    #[derive(Clone, Copy)]
+    struct Data {
+        effective: u32,
+        permitted: u32,
+        inheritable: u32,
+    }
+    const CAPABILITY_VERSION_3: u32 = 0x2008_0522;
+
+    check(libc::prctl(libc::PR_SET_NO_NEW_PRIVS, 1, 0, 0, 0))?;
+    // The bounding set ends at the first capability this kernel doesn't know
+    for capability in 0..64 {
+        if libc::prctl(libc::PR_CAPBSET_DROP, capability, 0, 0, 0) != 0 {
+            if io::Error::last_os_error().raw_os_error() == Some(libc::EINVAL) {
+                break;
+            }
+            return Err(io::Error::last_os_error());
+        }
+    }
+
+    let header = Header { version: CAPABILITY_VERSION_3, pid: 0 };
+    let data = [Data { effective: 0, permitted: 0, inheritable: 0 }; 2];
+    check(libc::syscall(libc::SYS_capset, &header, data.as_ptr()) as libc::c_int)
+}
+
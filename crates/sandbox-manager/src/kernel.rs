@@ -0,0 +1,572 @@
+use chrono::{DateTime, Utc};
+use common::{
+    errors::{Result, HybridLLMError},
+    types::CodeLanguage,
+};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use std::process::Stdio;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader, Lines};
+use tokio::process::{Child, ChildStdin, ChildStdout, Command};
+use tracing::{info, debug, warn};
+use uuid::Uuid;
+
+use crate::execution::DEFAULT_PATH;
+use crate::limits::Confinement;
+use crate::{SandboxEvent, SandboxManager};
+
+/// How long an interrupted cell gets to return before the kernel is killed
+const INTERRUPT_GRACE_PERIOD: Duration = Duration::from_secs(3);
+
+/// Python kernel: runs each cell in one shared namespace
+/// Cell output is captured at the file descriptor level so subprocess output is
+/// included; the value of a trailing expression is returned like a notebook would
+const PYTHON_DRIVER: &str = r#"
+import ast, json, os, signal, sys, tempfile, traceback
+LIMIT = 1 << 20
+running = False
+def interrupt(signum, frame):
+    # Only a running cell is interrupted; a late signal must not kill the kernel
+    if running:
+        raise KeyboardInterrupt
+signal.signal(signal.SIGINT, interrupt)
+proto = os.fdopen(os.dup(1), "w")
+cells = os.fdopen(os.dup(0), "r")
+os.dup2(os.open(os.devnull, os.O_RDONLY), 0)
+os.dup2(2, 1)
+namespace = {"__name__": "__main__"}
+def read(f):
+    f.seek(0)
+    return f.read(LIMIT).decode(errors="replace")
+for line in cells:
+    code = json.loads(line)["code"]
+    out, err = tempfile.TemporaryFile(), tempfile.TemporaryFile()
+    saved = (os.dup(1), os.dup(2))
+    os.dup2(out.fileno(), 1)
+    os.dup2(err.fileno(), 2)
+    result = error = None
+    running = True
+    try:
+        tree = ast.parse(code, "<cell>", "exec")
+        last = tree.body.pop() if tree.body and isinstance(tree.body[-1], ast.Expr) else None
+        exec(compile(tree, "<cell>", "exec"), namespace)
+        if last is not None:
+            value = eval(compile(ast.Expression(last.value), "<cell>", "eval"), namespace)
+            if value is not None:
+                result = repr(value)
+    except BaseException:
+        error = traceback.format_exc()
+    running = False
+    sys.stdout.flush()
+    sys.stderr.flush()
+    os.dup2(saved[0], 1)
+    os.dup2(saved[1], 2)
+    os.close(saved[0])
+    os.close(saved[1])
+    proto.write(json.dumps({"stdout": read(out), "stderr": read(err), "result": result, "error": error}) + "\n")
+    proto.flush()
+"#;
+
+/// JavaScript kernel: runs each cell in one shared `vm` context
+const JAVASCRIPT_DRIVER: &str = r#"
+const vm = require('vm');
+const util = require('util');
+const readline = require('readline');
+let out = [], err = [];
+const write = (stream) => (...args) => { stream.push(util.format(...args) + '\n'); };
+const context = vm.createContext({
+  require, Buffer, URL, setTimeout, clearTimeout, setInterval, clearInterval,
+  console: { log: write(out), info: write(out), debug: write(out), warn: write(err), error: write(err) },
+});
+process.on('SIGINT', () => {});
+readline.createInterface({ input: process.stdin }).on('line', (line) => {
+  const { code } = JSON.parse(line);
+  out.length = 0;
+  err.length = 0;
+  let result = null, error = null;
+  try {
+    const value = vm.runInContext(code, context, { filename: '<cell>', breakOnSigint: true });
+    if (value !== undefined) result = util.inspect(value);
+  } catch (e) {
+    error = e && e.stack ? e.stack : String(e);
+  }
+  process.stdout.write(JSON.stringify({ stdout: out.join(''), stderr: err.join(''), result, error }) + '\n');
+});
+"#;
+
+/// Output of one cell run in a kernel
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CellOutput {
+    /// 1 for the first cell after the kernel started or was reset
+    pub execution_count: u32,
+    pub stdout: String,
+    pub stderr: String,
+    /// Representation of the cell's trailing expression, if it had a value
+    pub result: Option<String>,
+    /// Traceback when the cell raised
+    pub error: Option<String>,
+    pub duration_ms: u64,
+    /// The cell was interrupted for exceeding the sandbox's execution timeout
+    #[serde(default)]
+    pub timed_out: bool,
+}
+
+/// A stateful interpreter session in a sandbox
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct KernelInfo {
+    pub id: Uuid,
+    pub sandbox_id: Uuid,
+    pub language: CodeLanguage,
+    /// Cells run since the kernel started or was last reset
+    pub execution_count: u32,
+    pub started_at: DateTime<Utc>,
+    /// False after the interpreter exited or was killed; reset to continue
+    pub alive: bool,
+}
+
+/// What a kernel process sends back for each cell
+#[derive(Deserialize)]
+struct CellReply {
+    stdout: String,
+    stderr: String,
+    result: Option<String>,
+    error: Option<String>,
+}
+
+/// A running interpreter and the pipes used to drive it
+struct KernelProcess {
+    child: Child,
+    pid: Option<u32>,
+    stdin: ChildStdin,
+    replies: Lines<BufReader<ChildStdout>>,
+}
+
+impl KernelProcess {
+    fn spawn(
+        language: CodeLanguage,
+        working_dir: &Path,
+        environment: &[(String, String)],
+        confinement: &Confinement,
+    ) -> Result<Self> {
+        let mut command = match language {
+            CodeLanguage::Python => {
+                // Packages installed by `install_packages` live in the project venv
+                let venv = working_dir.join(".venv/bin/python");
+                let mut command = Command::new(if venv.exists() { venv.as_path() } else { Path::new("python3") });
+                command.arg("-c").arg(PYTHON_DRIVER);
+                command
+            }
+            CodeLanguage::JavaScript => {
+                let mut command = Command::new("node");
+                command.arg("-e").arg(JAVASCRIPT_DRIVER);
+                command
+            }
+            CodeLanguage::Rust => {
+                return Err(HybridLLMError::InvalidRequest(
+                    "Kernels are not available for Rust; use run_code".to_string(),
+                ))
+            }
+        };
+
+        command
+            .current_dir(working_dir)
+            .env_clear()
+            .env("PATH", DEFAULT_PATH)
+            .env("HOME", working_dir)
+            .envs(environment.iter().map(|(key, value)| (key, value)))
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true);
+        confinement.apply(&mut command);
+
+        let mut child = command
+            .spawn()
+            .map_err(|e| HybridLLMError::SandboxError(format!("Failed to start kernel: {}", e)))?;
+
+        let pid = child.id();
+        if let Some(pid) = pid {
+            confinement.track(pid);
+        }
+
+        // Only interpreter noise reaches stderr between cells
+        let stderr = child.stderr.take().expect("stderr is piped");
+        tokio::spawn(async move {
+            let mut lines = BufReader::new(stderr).lines();
+            while let Ok(Some(line)) = lines.next_line().await {
+                debug!("kernel stderr: {}", line);
+            }
+        });
+
+        Ok(Self {
+            stdin: child.stdin.take().expect("stdin is piped"),
+            replies: BufReader::new(child.stdout.take().expect("stdout is piped")).lines(),
+            child,
+            pid,
+        })
+    }
+
+    /// Send a cell and wait for its reply; `None` if the interpreter went away
+    async fn send(&mut self, code: &str) -> Option<CellReply> {
+        let mut request = serde_json::json!({ "code": code }).to_string();
+        request.push('\n');
+        self.stdin.write_all(request.as_bytes()).await.ok()?;
+        self.stdin.flush().await.ok()?;
+        self.reply().await
+    }
+
+    async fn reply(&mut self) -> Option<CellReply> {
+        let line = self.replies.next_line().await.ok()??;
+        serde_json::from_str(&line).ok()
+    }
+
+    /// Interrupt the running cell, as Ctrl-C in a notebook would
+    fn interrupt(&self) {
+        #[cfg(unix)]
+        if let Some(pid) = self.pid {
+            // SAFETY: plain syscall on the kernel's own process group
+            unsafe {
+                libc::kill(-(pid as i32), libc::SIGINT);
+            }
+        }
+    }
+
+    async fn kill(mut self, confinement: &Confinement) {
+        #[cfg(unix)]
+        if let Some(pid) = self.pid {
+            // SAFETY: plain syscall on the kernel's own process group
+            unsafe {
+                libc::kill(-(pid as i32), libc::SIGKILL);
+            }
+        }
+        let _ = self.child.kill().await;
+        if let Some(pid) = self.pid {
+            confinement.untrack(pid);
+        }
+    }
+}
+
+/// A kernel and its interpreter; cells run one at a time
+pub(crate) struct Kernel {
+    info: std::sync::Mutex<KernelInfo>,
+    confinement: Arc<Confinement>,
+    process: tokio::sync::Mutex<Option<KernelProcess>>,
+}
+
+impl Kernel {
+    fn info(&self) -> KernelInfo {
+        self.info.lock().unwrap().clone()
+    }
+
+    /// Run one cell, interrupting it after `timeout`
+    /// A cell that ignores the interrupt takes the kernel down with it
+    async fn execute(&self, code: &str, timeout: Duration) -> Result<CellOutput> {
+        let mut process = self.process.lock().await;
+        let Some(running) = process.as_mut() else {
+            return Err(HybridLLMError::SandboxError(
+                "Kernel is not running; reset it to continue".to_string(),
+            ));
+        };
+
+        let started = Instant::now();
+        self.confinement.mark_active();
+        let (reply, timed_out) = match tokio::time::timeout(timeout, running.send(code)).await {
+            Ok(reply) => (reply, false),
+            Err(_) => {
+                warn!("⏱️  Kernel cell exceeded {}s timeout, interrupting", timeout.as_secs());
+                running.interrupt();
+                let reply = tokio::time::timeout(INTERRUPT_GRACE_PERIOD, running.reply())
+                    .await
+                    .ok()
+                    .flatten();
+                (reply, true)
+            }
+        };
+        self.confinement.mark_active();
+
+        let Some(reply) = reply else {
+            if let Some(dead) = process.take() {
+                dead.kill(&self.confinement).await;
+            }
+            self.info.lock().unwrap().alive = false;
+            return Err(HybridLLMError::SandboxError(if timed_out {
+                format!("Kernel killed after ignoring interrupt at {}s timeout; its state is lost", timeout.as_secs())
+            } else {
+                "Kernel exited; its state is lost, reset it to continue".to_string()
+            }));
+        };
+
+        let execution_count = {
+            let mut info = self.info.lock().unwrap();
+            info.execution_count += 1;
+            info.execution_count
+        };
+
+        Ok(CellOutput {
+            execution_count,
+            stdout: reply.stdout,
+            stderr: reply.stderr,
+            result: reply.result,
+            error: reply.error,
+            duration_ms: started.elapsed().as_millis() as u64,
+            timed_out,
+        })
+    }
+
+    async fn shutdown(&self) {
+        if let Some(process) = self.process.lock().await.take() {
+            process.kill(&self.confinement).await;
+        }
+        self.info.lock().unwrap().alive = false;
+    }
+}
+
+impl SandboxManager {
+    /// Start a stateful interpreter in a sandbox
+    /// Cells run with `execute_cell` share variables, imports and definitions
+    pub async fn start_kernel(&self, sandbox_id: Uuid, language: CodeLanguage) -> Result<Uuid> {
+        self.ensure_not_locked_down().await?;
+        let context = self.prepare(sandbox_id, None).await?;
+        let process = KernelProcess::spawn(language, &context.cwd, &context.environment, &context.confinement)?;
+
+        let id = Uuid::new_v4();
+        let kernel = Kernel {
+            info: std::sync::Mutex::new(KernelInfo {
+                id,
+                sandbox_id,
+                language,
+                execution_count: 0,
+                started_at: Utc::now(),
+                alive: true,
+            }),
+            confinement: context.confinement,
+            process: tokio::sync::Mutex::new(Some(process)),
+        };
+        self.kernels.write().await.insert(id, Arc::new(kernel));
+
+        info!("🧮 Started {:?} kernel {} in sandbox {}", language, id, sandbox_id);
+        Ok(id)
+    }
+
+    /// Run a code cell in a kernel, keeping state from earlier cells
+    /// Cells longer than the sandbox's execution timeout are interrupted
+    pub async fn execute_cell(&self, kernel_id: Uuid, code: &str) -> Result<CellOutput> {
+        self.ensure_not_locked_down().await?;
+        let kernel = self.kernel(kernel_id).await?;
+        let sandbox_id = kernel.info().sandbox_id;
+        let context = self.prepare(sandbox_id, None).await?;
+
+        let output = kernel.execute(code, context.timeout).await?;
+        let _ = self.events.send(SandboxEvent::CellExecuted {
+            sandbox_id,
+            kernel_id,
+            llm_id: context.llm_id,
+            code: code.to_string(),
+            execution_count: output.execution_count,
+            succeeded: output.error.is_none(),
+            duration_ms: output.duration_ms,
+        });
+
+        Ok(output)
+    }
+
+    /// Restart a kernel's interpreter, discarding all of its state
+    pub async fn reset_kernel(&self, kernel_id: Uuid) -> Result<()> {
+        self.ensure_not_locked_down().await?;
+        let kernel = self.kernel(kernel_id).await?;
+        let info = kernel.info();
+        let context = self.prepare(info.sandbox_id, None).await?;
+
+        let mut process = kernel.process.lock().await;
+        if let Some(old) = process.take() {
+            old.kill(&kernel.confinement).await;
+        }
+        *process = Some(KernelProcess::spawn(
+            info.language,
+            &context.cwd,
+            &context.environment,
+            &kernel.confinement,
+        )?);
+
+        let mut info = kernel.info.lock().unwrap();
+        info.execution_count = 0;
+        info.alive = true;
+
+        info!("🧮 Reset kernel {}", kernel_id);
+        Ok(())
+    }
+
+    /// Stop a kernel and forget it
+    pub async fn shutdown_kernel(&self, kernel_id: Uuid) -> Result<()> {
+        let kernel = self.kernels.write().await.remove(&kernel_id).ok_or_else(|| {
+            HybridLLMError::SandboxError(format!("Kernel not found: {}", kernel_id))
+        })?;
+        kernel.shutdown().await;
+
+        info!("🧮 Shut down kernel {}", kernel_id);
+        Ok(())
+    }
+
+    /// Kernels running in a sandbox
+    pub async fn list_kernels(&self, sandbox_id: Uuid) -> Vec<KernelInfo> {
+        self.kernels
+            .read()
+            .await
+            .values()
+            .map(|kernel| kernel.info())
+            .filter(|info| info.sandbox_id == sandbox_id)
+            .collect()
+    }
+
+    /// Stop every kernel in a sandbox that is being destroyed
+    pub(crate) async fn shutdown_sandbox_kernels(&self, sandbox_id: Uuid) {
+        let kernels: Vec<Arc<Kernel>> = {
+            let mut all = self.kernels.write().await;
+            let ids: Vec<Uuid> = all
+                .iter()
+                .filter(|(_, kernel)| kernel.info().sandbox_id == sandbox_id)
+                .map(|(id, _)| *id)
+                .collect();
+            ids.iter().filter_map(|id| all.remove(id)).collect()
+        };
+
+        for kernel in kernels {
+            kernel.shutdown().await;
+        }
+    }
+
+    /// Kernels run arbitrary code, which a lockdown or read-only mode stops
+    async fn ensure_not_locked_down(&self) -> Result<()> {
+        let Some(security) = &self.security else {
+            return Ok(());
+        };
+        let lockdown = security.lockdown_state().await?;
+        if lockdown.allows_writes() {
+            return Ok(());
+        }
+        Err(HybridLLMError::LockdownActive(format!("Kernels are unavailable during lockdown ({:?})", lockdown)))
+    }
+
+    async fn kernel(&self, kernel_id: Uuid) -> Result<Arc<Kernel>> {
+        self.kernels
+            .read()
+            .await
+            .get(&kernel_id)
+            .cloned()
+            .ok_or_else(|| HybridLLMError::SandboxError(format!("Kernel not found: {}", kernel_id)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use async_trait::async_trait;
+    use common::messages::PermissionType;
+    use common::traits::{PlannedAction, SecurityAnalysis, SecurityEngine, SimulatedDecision};
+    use common::types::{LockdownReason, LockdownState, NetworkMode, RequestContext, SandboxConfig};
+
+    /// Reports whatever lockdown state it is set to
+    struct Lockdown(std::sync::Mutex<LockdownState>);
+
+    #[async_trait]
+    impl SecurityEngine for Lockdown {
+        async fn check_permission(&self, _: &RequestContext, _: &PermissionType, _: &str) -> Result<bool> {
+            unimplemented!()
+        }
+        async fn analyze_command(&self, _: &str) -> Result<SecurityAnalysis> {
+            unimplemented!()
+        }
+        async fn trigger_lockdown(&self, _: LockdownReason) -> Result<()> {
+            unimplemented!()
+        }
+        async fn release_lockdown(&self, _: &str) -> Result<()> {
+            unimplemented!()
+        }
+        async fn lockdown_state(&self) -> Result<LockdownState> {
+            Ok(*self.0.lock().unwrap())
+        }
+        async fn simulate(&self, _: &RequestContext, _: &PlannedAction) -> Result<SimulatedDecision> {
+            unimplemented!()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_kernels_refused_during_lockdown() {
+        let base = std::env::temp_dir().join(format!("sandboxes-{}", Uuid::new_v4()));
+        let security = Arc::new(Lockdown(std::sync::Mutex::new(LockdownState::ReadOnly)));
+        let manager = SandboxManager::new(base.clone()).unwrap().with_security_engine(security.clone());
+        let locked = |result: Result<()>| matches!(result, Err(HybridLLMError::LockdownActive(_)));
+
+        let (sandbox_id, kernel_id) = (Uuid::new_v4(), Uuid::new_v4());
+        assert!(locked(manager.start_kernel(sandbox_id, CodeLanguage::Python).await.map(|_| ())));
+        assert!(locked(manager.execute_cell(kernel_id, "1").await.map(|_| ())));
+        assert!(locked(manager.reset_kernel(kernel_id).await));
+
+        // Once released, the unknown sandbox is what stops it
+        *security.0.lock().unwrap() = LockdownState::Normal;
+        assert!(matches!(
+            manager.start_kernel(sandbox_id, CodeLanguage::Python).await,
+            Err(HybridLLMError::SandboxError(_))
+        ));
+        let _ = std::fs::remove_dir_all(base);
+    }
+
+    #[tokio::test]
+    async fn test_python_kernel_keeps_state() {
+        if !Path::new("/usr/bin/python3").exists() {
+            return;
+        }
+
+        let base = std::env::temp_dir().join(format!("sandboxes-{}", Uuid::new_v4()));
+        let manager = SandboxManager::new(base.clone()).unwrap();
+        let sandbox_id = manager
+            .create_sandbox(SandboxConfig {
+                id: Uuid::new_v4(),
+                network_mode: NetworkMode::None,
+                cpu_limit: 50.0,
+                memory_limit_gb: 1.0,
+                disk_limit_gb: 1.0,
+                allowed_commands: vec![],
+                template: None,
+                max_lifetime_secs: None,
+                idle_timeout_secs: None,
+                execution_timeout_secs: Some(1),
+                llm_id: None,
+                gpu: None,
+                volumes: vec![],
+            })
+            .await
+            .unwrap();
+        let kernel = manager.start_kernel(sandbox_id, CodeLanguage::Python).await.unwrap();
+
+        let cell = manager.execute_cell(kernel, "data = [1, 2, 3]\nprint('loaded')").await.unwrap();
+        assert_eq!((cell.execution_count, cell.stdout.as_str()), (1, "loaded\n"));
+        assert_eq!(cell.result, None);
+
+        let cell = manager.execute_cell(kernel, "import os\nos.system('echo from-shell')\nsum(data)").await.unwrap();
+        assert_eq!(cell.stdout, "from-shell\n");
+        assert_eq!(cell.result.as_deref(), Some("6"));
+
+        let cell = manager.execute_cell(kernel, "1 / 0").await.unwrap();
+        assert!(cell.error.unwrap().contains("ZeroDivisionError"));
+
+        // An interrupted cell keeps the kernel and its state
+        let cell = manager.execute_cell(kernel, "import time\ntime.sleep(30)").await.unwrap();
+        assert!(cell.timed_out);
+        assert!(cell.error.unwrap().contains("KeyboardInterrupt"));
+        let cell = manager.execute_cell(kernel, "len(data)").await.unwrap();
+        assert_eq!((cell.execution_count, cell.result.as_deref()), (5, Some("3")));
+
+        manager.reset_kernel(kernel).await.unwrap();
+        let cell = manager.execute_cell(kernel, "data").await.unwrap();
+        assert_eq!(cell.execution_count, 1);
+        assert!(cell.error.unwrap().contains("NameError"));
+
+        manager.destroy_sandbox(sandbox_id).await.unwrap();
+        assert!(manager.execute_cell(kernel, "1").await.is_err());
+
+        let _ = std::fs::remove_dir_all(base);
+    }
+}
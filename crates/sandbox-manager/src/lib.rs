@@ -1,15 +1,387 @@
+mod artifacts;
+mod browser;
+mod execution;
+mod files;
+mod forwarding;
+mod isolation;
+mod kernel;
+mod lifecycle;
+mod limits;
+mod namespaces;
+mod network;
+mod plugins;
+mod pool;
+mod pty;
+mod queue;
+mod rootfs;
+mod runners;
+mod snapshots;
+mod templates;
+mod volumes;
+mod wasm;
+
+pub use artifacts::ArtifactInfo;
+pub use browser::{BrowserAction, BrowserInfo, BrowserOutcome};
+pub use execution::{ExecutionResult, ExecutionEvent};
+pub use files::{FileChange, FileChangeKind, SandboxFile};
+pub use forwarding::{ForwardCloseReason, PortForward};
+pub use kernel::{CellOutput, KernelInfo};
+pub use lifecycle::ReclaimReason;
+pub use plugins::{
+    PluginCapabilities, PluginConfig, PluginFailure, PluginHost, PluginInfo, PluginKind, PluginManifest,
+    PluginToolInfo, PLUGIN_MANIFEST,
+};
+pub use pool::PoolConfig;
+pub use pty::{PtyEvent, PtyRecording, PtySession};
+pub use runners::PackageSpec;
+pub use snapshots::SnapshotInfo;
+pub use templates::TemplateManifest;
+pub use volumes::VolumeInfo;
+pub use wasm::{WasmExecutor, WasmConfig, WasmOutput};
+
 use common::{
+    config::{DEFAULT_MAX_QUEUED_SANDBOXES, DEFAULT_MAX_SANDBOXES},
     errors::{Result, HybridLLMError},
-    types::{SandboxConfig, ArtifactTransfer},
+    traits::{GpuAllocator, SecurityEngine},
+    types::{
+        SandboxConfig, SandboxTemplate, SandboxUsage, ArtifactTransfer, GpuRequest, NetworkMode,
+        PackagePermissions,
+    },
 };
-use std::path::PathBuf;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Component, Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::{broadcast, mpsc, OwnedSemaphorePermit, RwLock, Semaphore};
+use tokio::task::JoinHandle;
 use tracing::{info, debug, warn};
 use uuid::Uuid;
 
+use crate::browser::Browser;
+use crate::limits::Confinement;
+use crate::forwarding::PortForwards;
+use crate::kernel::Kernel;
+use crate::lifecycle::OwnerLock;
+use crate::network::{EgressProxy, NetworkCounters};
+use crate::pool::WarmPool;
+use crate::queue::AdmissionQueue;
+use crate::rootfs::Rootfs;
+use crate::snapshots::SnapshotRegistry;
+use crate::templates::TemplateStore;
+use crate::volumes::VolumeStore;
+
+/// Execution timeout for sandboxes that do not configure their own
+const DEFAULT_EXECUTION_TIMEOUT: Duration = Duration::from_secs(300);
+
+/// Lifecycle and enforcement events emitted by the sandbox manager
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum SandboxEvent {
+    /// A sandbox was created, or handed out from the warm pool
+    Created {
+        sandbox_id: Uuid,
+        llm_id: Option<String>,
+        template: Option<SandboxTemplate>,
+    },
+    /// A sandbox creation is waiting for a slot; sent again each time it moves up
+    Queued {
+        sandbox_id: Uuid,
+        llm_id: Option<String>,
+        /// 1 is next in line
+        position: usize,
+    },
+    /// A command finished running in a sandbox
+    CommandExecuted {
+        sandbox_id: Uuid,
+        llm_id: Option<String>,
+        command: String,
+        exit_code: i32,
+        duration_ms: u64,
+    },
+    /// A code cell finished running in a sandbox kernel
+    CellExecuted {
+        sandbox_id: Uuid,
+        kernel_id: Uuid,
+        llm_id: Option<String>,
+        code: String,
+        execution_count: u32,
+        succeeded: bool,
+        duration_ms: u64,
+    },
+    /// A sandboxed browser was opened or ran an action
+    BrowserAction {
+        sandbox_id: Uuid,
+        browser_id: Uuid,
+        llm_id: Option<String>,
+        /// e.g. "navigate https://example.com"; filled values are left out
+        action: String,
+        /// Page the browser was on afterwards
+        url: Option<String>,
+        /// Why the action failed
+        error: Option<String>,
+    },
+    /// A sandbox was destroyed
+    Destroyed {
+        sandbox_id: Uuid,
+        llm_id: Option<String>,
+    },
+    /// A sandbox port was exposed on localhost
+    PortForwardOpened {
+        forward: PortForward,
+        llm_id: Option<String>,
+    },
+    /// A port forward expired, was closed, or its sandbox was destroyed
+    PortForwardClosed {
+        forward: PortForward,
+        llm_id: Option<String>,
+        reason: ForwardCloseReason,
+    },
+    /// A sandbox was killed after sustained resource limit violation
+    ResourceExceeded {
+        sandbox_id: Uuid,
+        resource: String,
+        limit: f32,
+        actual: f32,
+    },
+    /// An outbound connection was attempted through the egress proxy
+    NetworkConnection {
+        sandbox_id: Uuid,
+        host: String,
+        port: u16,
+        allowed: bool,
+    },
+    /// A command was killed for running past its execution timeout
+    ExecutionTimedOut {
+        sandbox_id: Uuid,
+        llm_id: Option<String>,
+        command: String,
+        timeout_secs: u64,
+    },
+    /// Periodic resource usage sample
+    Usage {
+        usage: SandboxUsage,
+    },
+    /// A sandbox was destroyed by the reaper
+    Reclaimed {
+        sandbox_id: Uuid,
+        reason: ReclaimReason,
+    },
+}
+
+/// Name a sandbox's VRAM reservation is held under
+fn gpu_owner(sandbox_id: Uuid) -> String {
+    format!("sandbox:{}", sandbox_id)
+}
+
+/// Fields of an audit log entry describing a sandbox event
+#[derive(Debug, Clone)]
+pub struct SandboxAuditRecord {
+    pub llm_id: Option<String>,
+    pub action: String,
+    pub details: serde_json::Value,
+    pub approved: bool,
+    pub reason: Option<String>,
+}
+
+impl SandboxEvent {
+    /// The audit log entry for this event, if it belongs in the audit log
+    pub fn audit_record(&self) -> Option<SandboxAuditRecord> {
+        let record = |llm_id: &Option<String>, action: &str, details, approved, reason| SandboxAuditRecord {
+            llm_id: llm_id.clone(),
+            action: action.to_string(),
+            details,
+            approved,
+            reason,
+        };
+
+        Some(match self {
+            SandboxEvent::Created { sandbox_id, llm_id, template } => record(
+                llm_id,
+                "Sandbox created",
+                serde_json::json!({ "sandbox_id": sandbox_id, "template": template }),
+                true,
+                None,
+            ),
+            SandboxEvent::CommandExecuted { sandbox_id, llm_id, command, exit_code, duration_ms } => record(
+                llm_id,
+                "Sandbox command executed",
+                serde_json::json!({
+                    "sandbox_id": sandbox_id,
+                    "command": command,
+                    "exit_code": exit_code,
+                    "duration_ms": duration_ms,
+                }),
+                true,
+                None,
+            ),
+            SandboxEvent::CellExecuted {
+                sandbox_id,
+                kernel_id,
+                llm_id,
+                code,
+                execution_count,
+                succeeded,
+                duration_ms,
+            } => record(
+                llm_id,
+                "Sandbox kernel cell executed",
+                serde_json::json!({
+                    "sandbox_id": sandbox_id,
+                    "kernel_id": kernel_id,
+                    "code": code,
+                    "execution_count": execution_count,
+                    "succeeded": succeeded,
+                    "duration_ms": duration_ms,
+                }),
+                true,
+                None,
+            ),
+            SandboxEvent::BrowserAction { sandbox_id, browser_id, llm_id, action, url, error } => record(
+                llm_id,
+                "Sandbox browser action",
+                serde_json::json!({
+                    "sandbox_id": sandbox_id,
+                    "browser_id": browser_id,
+                    "action": action,
+                    "url": url,
+                }),
+                error.is_none(),
+                error.clone(),
+            ),
+            SandboxEvent::ExecutionTimedOut { sandbox_id, llm_id, command, timeout_secs } => record(
+                llm_id,
+                "Sandbox execution timed out",
+                serde_json::json!({
+                    "sandbox_id": sandbox_id,
+                    "command": command,
+                    "timeout_secs": timeout_secs,
+                }),
+                false,
+                Some(format!("Killed after {}s", timeout_secs)),
+            ),
+            SandboxEvent::PortForwardOpened { forward, llm_id } => record(
+                llm_id,
+                "Sandbox port forwarded",
+                serde_json::json!({
+                    "sandbox_id": forward.sandbox_id,
+                    "forward": forward,
+                }),
+                true,
+                None,
+            ),
+            SandboxEvent::PortForwardClosed { forward, llm_id, reason } => record(
+                llm_id,
+                "Sandbox port forward closed",
+                serde_json::json!({
+                    "sandbox_id": forward.sandbox_id,
+                    "forward": forward,
+                    "reason": reason,
+                }),
+                true,
+                None,
+            ),
+            SandboxEvent::Destroyed { sandbox_id, llm_id } => record(
+                llm_id,
+                "Sandbox destroyed",
+                serde_json::json!({ "sandbox_id": sandbox_id }),
+                true,
+                None,
+            ),
+            _ => return None,
+        })
+    }
+}
+
+/// A live sandbox tracked by the manager
+struct Sandbox {
+    config: SandboxConfig,
+    root: PathBuf,
+    created_at: Instant,
+    /// Extra environment from the sandbox's template
+    environment: Vec<(String, String)>,
+    /// Filesystem state right after creation, used to diff what the LLM changed
+    baseline: Arc<files::Baseline>,
+    confinement: Arc<Confinement>,
+    /// Traffic through the sandbox's egress proxies
+    network: Arc<NetworkCounters>,
+    /// Most recent watchdog sample
+    usage: Arc<std::sync::Mutex<Option<SandboxUsage>>>,
+    watchdog: JoinHandle<()>,
+    /// Held for its lifetime; dropping it stops the proxy
+    _proxy: Option<EgressProxy>,
+    /// Marks the sandbox as this manager's until it is dropped
+    _owner: OwnerLock,
+    /// Counts against the sandbox cap until the sandbox is dropped
+    _slot: OwnedSemaphorePermit,
+}
+
+/// Everything needed to run a process in a sandbox, resolved under the lock
+struct ExecutionContext {
+    cwd: PathBuf,
+    environment: Vec<(String, String)>,
+    confinement: Arc<Confinement>,
+    network: Arc<NetworkCounters>,
+    timeout: Duration,
+    llm_id: Option<String>,
+}
+
+impl ExecutionContext {
+    /// Emit the audit events for a finished command
+    fn report(
+        &self,
+        events: &broadcast::Sender<SandboxEvent>,
+        sandbox_id: Uuid,
+        command: &str,
+        result: &ExecutionResult,
+    ) {
+        if result.timed_out {
+            let _ = events.send(SandboxEvent::ExecutionTimedOut {
+                sandbox_id,
+                llm_id: self.llm_id.clone(),
+                command: command.to_string(),
+                timeout_secs: self.timeout.as_secs(),
+            });
+        }
+        let _ = events.send(SandboxEvent::CommandExecuted {
+            sandbox_id,
+            llm_id: self.llm_id.clone(),
+            command: command.to_string(),
+            exit_code: result.exit_code,
+            duration_ms: result.duration_ms,
+        });
+    }
+}
+
 /// Sandbox manager for isolated code execution
 /// Uses Firecracker microVMs for strong isolation
 pub struct SandboxManager {
     sandboxes_path: PathBuf,
+    sandboxes: Arc<RwLock<HashMap<Uuid, Sandbox>>>,
+    events: broadcast::Sender<SandboxEvent>,
+    /// Domains reachable in proxy-only mode, kept in sync with the security policy
+    allowed_domains: Arc<RwLock<Vec<String>>>,
+    /// Packages language runners may install, kept in sync with the security policy
+    package_permissions: RwLock<PackagePermissions>,
+    snapshots: SnapshotRegistry,
+    templates: TemplateStore,
+    volumes: VolumeStore,
+    pool: WarmPool,
+    forwards: PortForwards,
+    /// Stateful interpreter sessions, by kernel ID
+    kernels: RwLock<HashMap<Uuid, Arc<Kernel>>>,
+    /// Headless browsers, by browser ID
+    browsers: RwLock<HashMap<Uuid, Arc<Browser>>>,
+    /// One permit per sandbox that may exist at once
+    slots: Arc<Semaphore>,
+    max_sandboxes: usize,
+    /// Creations waiting for a slot, bounded by `max_queued`
+    queue: AdmissionQueue,
+    max_queued: usize,
+    /// VRAM budget shared with local models; GPU sandboxes are refused without one
+    gpu: Option<Arc<dyn GpuAllocator>>,
+    /// Lockdown state kernels respect; they run code only while it allows writes
+    security: Option<Arc<dyn SecurityEngine>>,
 }
 
 impl SandboxManager {
@@ -19,21 +391,207 @@ impl SandboxManager {
 
         info!("🔒 Sandbox manager initialized at {:?}", sandboxes_path);
 
-        Ok(Self { sandboxes_path })
+        let snapshots = SnapshotRegistry::open(sandboxes_path.join("snapshots"))?;
+        let templates = TemplateStore::new(sandboxes_path.join("templates"));
+        let volumes = VolumeStore::open(sandboxes_path.join("volumes"))?;
+        let (events, _) = broadcast::channel(256);
+
+        Ok(Self {
+            sandboxes_path,
+            sandboxes: Arc::new(RwLock::new(HashMap::new())),
+            events,
+            allowed_domains: Arc::new(RwLock::new(Vec::new())),
+            package_permissions: RwLock::new(PackagePermissions::default()),
+            snapshots,
+            templates,
+            volumes,
+            pool: WarmPool::default(),
+            forwards: PortForwards::default(),
+            kernels: RwLock::new(HashMap::new()),
+            browsers: RwLock::new(HashMap::new()),
+            slots: Arc::new(Semaphore::new(DEFAULT_MAX_SANDBOXES)),
+            max_sandboxes: DEFAULT_MAX_SANDBOXES,
+            queue: AdmissionQueue::default(),
+            max_queued: DEFAULT_MAX_QUEUED_SANDBOXES,
+            gpu: None,
+            security: None,
+        })
+    }
+
+    /// Cap how many sandboxes, warm ones included, may exist at once
+    pub fn with_max_sandboxes(mut self, max_sandboxes: usize) -> Self {
+        self.slots = Arc::new(Semaphore::new(max_sandboxes));
+        self.max_sandboxes = max_sandboxes;
+        self
+    }
+
+    /// Cap how many creations may wait for a slot; zero refuses instead of queueing
+    pub fn with_max_queued(mut self, max_queued: usize) -> Self {
+        self.max_queued = max_queued;
+        self
+    }
+
+    /// Reserve GPU sandboxes' VRAM from `allocator`, normally the LLM pool's governor
+    pub fn with_gpu_allocator(mut self, allocator: Arc<dyn GpuAllocator>) -> Self {
+        self.gpu = Some(allocator);
+        self
+    }
+
+    /// Refuse to start, run or reset kernels while `security` is locked down or read-only
+    pub fn with_security_engine(mut self, security: Arc<dyn SecurityEngine>) -> Self {
+        self.security = Some(security);
+        self
+    }
+
+    /// Replace the domain allowlist used by proxy-only sandboxes
+    pub async fn set_allowed_domains(&self, domains: Vec<String>) {
+        *self.allowed_domains.write().await = domains;
+    }
+
+    /// Subscribe to sandbox lifecycle and enforcement events
+    pub fn subscribe(&self) -> broadcast::Receiver<SandboxEvent> {
+        self.events.subscribe()
     }
 
     /// Create a new sandbox
+    /// Waits in the admission queue when the sandbox cap is reached, emitting
+    /// `Queued` events, and fails with `ResourceLimitExceeded` when the queue is full
     pub async fn create_sandbox(&self, config: SandboxConfig) -> Result<Uuid> {
+        let slot = self.admit(config.id, config.llm_id.clone()).await?;
+        let (llm_id, template) = (config.llm_id.clone(), config.template);
+        let sandbox_id = self.create_with_slot(config, slot).await?;
+
+        let _ = self.events.send(SandboxEvent::Created { sandbox_id, llm_id, template });
+        Ok(sandbox_id)
+    }
+
+    async fn create_with_slot(&self, config: SandboxConfig, slot: OwnedSemaphorePermit) -> Result<Uuid> {
         info!("📦 Creating sandbox with config: {:?}", config);
 
         // TODO: Implement actual Firecracker VM creation
-        // For MVP, this is a placeholder
+        // Until then, commands run as host processes in private namespaces under resource limits
 
         let sandbox_id = config.id;
         let sandbox_path = self.sandboxes_path.join(sandbox_id.to_string());
+        // Locked before the root exists, so other managers never mistake it for an orphan
+        let owner = OwnerLock::try_acquire(&self.sandboxes_path, sandbox_id)?.ok_or_else(|| {
+            HybridLLMError::SandboxError(format!("Sandbox {} is owned by another manager", sandbox_id))
+        })?;
+        let gpu_device = match &config.gpu {
+            Some(request) => Some(self.reserve_gpu(sandbox_id, request)?),
+            None => None,
+        };
 
-        std::fs::create_dir_all(&sandbox_path)
-            .map_err(|e| HybridLLMError::SandboxError(e.to_string()))?;
+        if let Err(e) = std::fs::create_dir_all(&sandbox_path) {
+            self.release_gpu(sandbox_id);
+            return Err(HybridLLMError::SandboxError(e.to_string()));
+        }
+
+        let layer = rootfs::layer_dir(&self.sandboxes_path, sandbox_id);
+        let (mut environment, rootfs) = match config.template {
+            Some(template) => match self.templates.install(template, &sandbox_path, &layer).await {
+                Ok((manifest, rootfs)) => (manifest.environment(&sandbox_path), rootfs),
+                Err(e) => {
+                    rootfs::teardown(&sandbox_path, &layer);
+                    let _ = std::fs::remove_dir_all(&sandbox_path);
+                    self.release_gpu(sandbox_id);
+                    return Err(e);
+                }
+            },
+            None => (Vec::new(), Rootfs::Directory),
+        };
+
+        if let Err(e) = self.volumes.attach(sandbox_id, &sandbox_path, &config.volumes) {
+            rootfs::teardown(&sandbox_path, &layer);
+            let _ = std::fs::remove_dir_all(&sandbox_path);
+            self.release_gpu(sandbox_id);
+            return Err(e);
+        }
+
+        // Sandboxes without a reservation must not touch VRAM the governor handed out
+        // TODO: Pass the device through with VFIO once sandboxes run in Firecracker
+        let visible_devices = gpu_device.map(|device| device.to_string()).unwrap_or_default();
+        environment.push(("CUDA_VISIBLE_DEVICES".to_string(), visible_devices.clone()));
+        environment.push(("NVIDIA_VISIBLE_DEVICES".to_string(), visible_devices));
+
+        let baseline = Self::capture_baseline(&sandbox_path).await?;
+        let network = Arc::new(NetworkCounters::default());
+
+        let staging = self.sandboxes_path.join(isolation::STAGING_DIR);
+        let mut confinement = match Confinement::new(&config, &sandbox_path, &staging).await {
+            Ok(confinement) => confinement,
+            Err(e) => {
+                let _ = self.volumes.detach(sandbox_id, &sandbox_path);
+                self.release_gpu(sandbox_id);
+                return Err(e);
+            }
+        };
+
+        let proxy = match config.network_mode {
+            NetworkMode::None => Ok(None),
+            NetworkMode::ProxyOnly => match confinement.proxy_listener().await {
+                Ok(listener) => EgressProxy::start_in_namespace(
+                    listener,
+                    sandbox_id,
+                    NetworkMode::ProxyOnly,
+                    Arc::clone(&self.allowed_domains),
+                    Arc::clone(&network),
+                    self.events.clone(),
+                )
+                .map(Some),
+                Err(e) => Err(e),
+            },
+            NetworkMode::Full => EgressProxy::start(
+                sandbox_id,
+                NetworkMode::Full,
+                Arc::clone(&self.allowed_domains),
+                Arc::clone(&network),
+                self.events.clone(),
+            )
+            .await
+            .map(Some),
+        };
+        let proxy = match proxy {
+            Ok(proxy) => proxy,
+            Err(e) => {
+                confinement.release();
+                let _ = self.volumes.detach(sandbox_id, &sandbox_path);
+                self.release_gpu(sandbox_id);
+                return Err(e);
+            }
+        };
+        if let Some(proxy) = &proxy {
+            confinement.set_proxy(proxy);
+        }
+        let confinement = Arc::new(confinement);
+
+        let usage = Arc::new(std::sync::Mutex::new(None));
+        let watchdog = tokio::spawn(limits::watchdog(
+            sandbox_id,
+            rootfs.writable(&sandbox_path),
+            Arc::clone(&confinement),
+            Arc::clone(&network),
+            Arc::clone(&usage),
+            self.events.clone(),
+        ));
+
+        self.sandboxes.write().await.insert(
+            sandbox_id,
+            Sandbox {
+                config,
+                root: sandbox_path,
+                created_at: Instant::now(),
+                environment,
+                baseline: Arc::new(baseline),
+                confinement,
+                network,
+                usage,
+                watchdog,
+                _proxy: proxy,
+                _owner: owner,
+                _slot: slot,
+            },
+        );
 
         info!("✅ Sandbox created: {}", sandbox_id);
 
@@ -44,7 +602,21 @@ impl SandboxManager {
     pub async fn destroy_sandbox(&self, sandbox_id: Uuid) -> Result<()> {
         info!("🗑️  Destroying sandbox: {}", sandbox_id);
 
+        self.pool.remove(sandbox_id);
+        self.close_sandbox_forwards(sandbox_id);
+        self.shutdown_sandbox_kernels(sandbox_id).await;
+        self.close_sandbox_browsers(sandbox_id).await;
+        self.release_gpu(sandbox_id);
+        let removed = self.sandboxes.write().await.remove(&sandbox_id);
+        if let Some(sandbox) = &removed {
+            sandbox.watchdog.abort();
+            sandbox.confinement.release();
+        }
+
         let sandbox_path = self.sandboxes_path.join(sandbox_id.to_string());
+        // Never delete the root while a volume is still mounted inside it
+        self.volumes.detach(sandbox_id, &sandbox_path)?;
+        rootfs::teardown(&sandbox_path, &rootfs::layer_dir(&self.sandboxes_path, sandbox_id));
 
         if sandbox_path.exists() {
             std::fs::remove_dir_all(&sandbox_path)
@@ -52,52 +624,703 @@ impl SandboxManager {
         }
 
         info!("✅ Sandbox destroyed: {}", sandbox_id);
+        if let Some(sandbox) = removed {
+            let _ = self.events.send(SandboxEvent::Destroyed { sandbox_id, llm_id: sandbox.config.llm_id });
+        }
 
         Ok(())
     }
 
+    /// Reserve VRAM for a sandbox, returning the GPU it was placed on
+    fn reserve_gpu(&self, sandbox_id: Uuid, request: &GpuRequest) -> Result<u32> {
+        let allocator = self.gpu.as_ref().ok_or_else(|| {
+            HybridLLMError::SandboxError("GPU sandboxes need a GPU allocator".to_string())
+        })?;
+        allocator.reserve(&gpu_owner(sandbox_id), request)
+    }
+
+    fn release_gpu(&self, sandbox_id: Uuid) {
+        if let Some(allocator) = &self.gpu {
+            allocator.release(&gpu_owner(sandbox_id));
+        }
+    }
+
+    /// Where a sandbox creation stands in the admission queue, `None` when not queued
+    pub fn queue_position(&self, sandbox_id: Uuid) -> Option<usize> {
+        self.queue.position(sandbox_id)
+    }
+
+    /// Sandbox creations currently waiting for a slot
+    pub fn queued(&self) -> usize {
+        self.queue.len()
+    }
+
+    /// Create an empty persistent volume
+    pub fn create_volume(&self, name: &str) -> Result<VolumeInfo> {
+        info!("💾 Creating volume: {}", name);
+        self.volumes.create(name)
+    }
+
+    /// All persistent volumes, by name
+    pub fn list_volumes(&self) -> Vec<VolumeInfo> {
+        self.volumes.list()
+    }
+
+    /// Delete a persistent volume and its files; refused while it is mounted
+    pub fn delete_volume(&self, name: &str) -> Result<()> {
+        info!("🗑️  Deleting volume: {}", name);
+        self.volumes.delete(name)
+    }
+
+    /// Templates whose prebuilt images are available on this machine
+    pub fn available_templates(&self) -> Vec<SandboxTemplate> {
+        self.templates.available()
+    }
+
+    /// Get the configuration a sandbox was created with
+    pub async fn get_config(&self, sandbox_id: Uuid) -> Option<SandboxConfig> {
+        self.sandboxes
+            .read()
+            .await
+            .get(&sandbox_id)
+            .map(|sandbox| sandbox.config.clone())
+    }
+
+    /// Latest resource usage sample of a sandbox
+    pub async fn usage(&self, sandbox_id: Uuid) -> Option<SandboxUsage> {
+        self.sandboxes
+            .read()
+            .await
+            .get(&sandbox_id)
+            .and_then(|sandbox| sandbox.usage.lock().unwrap().clone())
+    }
+
+    /// Latest resource usage sample of every live sandbox
+    pub async fn all_usage(&self) -> Vec<SandboxUsage> {
+        self.sandboxes
+            .read()
+            .await
+            .values()
+            .filter_map(|sandbox| sandbox.usage.lock().unwrap().clone())
+            .collect()
+    }
+
     /// Execute a command in a sandbox
-    pub async fn execute(&self, sandbox_id: Uuid, command: &str) -> Result<String> {
+    /// `working_dir` is relative to the sandbox root
+    pub async fn execute(
+        &self,
+        sandbox_id: Uuid,
+        command: &str,
+        working_dir: Option<&str>,
+    ) -> Result<ExecutionResult> {
         debug!("🚀 Executing in sandbox {}: {}", sandbox_id, command);
 
-        // TODO: Implement actual command execution in Firecracker VM
-        // For MVP, this is a placeholder
+        // TODO: Run inside the Firecracker VM instead of a confined host process
+        let context = self.prepare(sandbox_id, working_dir).await?;
+        let result = execution::run_command(
+            command,
+            &context.cwd,
+            &context.environment,
+            &context.confinement,
+            Some(context.timeout),
+            None,
+        )
+        .await?;
+
+        context.report(&self.events, sandbox_id, command, &result);
+        Ok(result)
+    }
+
+    /// Execute a command in a sandbox, streaming output as it is produced
+    /// The final event is always `ExecutionEvent::Finished` unless spawning fails
+    pub async fn execute_stream(
+        &self,
+        sandbox_id: Uuid,
+        command: &str,
+        working_dir: Option<&str>,
+    ) -> Result<mpsc::Receiver<ExecutionEvent>> {
+        debug!("🚀 Streaming execution in sandbox {}: {}", sandbox_id, command);
+
+        let context = self.prepare(sandbox_id, working_dir).await?;
+        let command = command.to_string();
+        let events = self.events.clone();
+        let (tx, rx) = mpsc::channel(256);
+
+        tokio::spawn(async move {
+            match execution::run_command(
+                &command,
+                &context.cwd,
+                &context.environment,
+                &context.confinement,
+                Some(context.timeout),
+                Some(tx.clone()),
+            )
+            .await
+            {
+                Ok(result) => context.report(&events, sandbox_id, &command, &result),
+                Err(e) => {
+                    let _ = tx
+                        .send(ExecutionEvent::Stderr { line: e.to_string() })
+                        .await;
+                }
+            }
+        });
+
+        Ok(rx)
+    }
+
+    /// Open an interactive shell in a sandbox on a pseudo-terminal
+    pub async fn open_pty(
+        &self,
+        sandbox_id: Uuid,
+        cols: u16,
+        rows: u16,
+    ) -> Result<(PtySession, mpsc::Receiver<PtyEvent>)> {
+        info!("🖥️  Opening PTY session in sandbox {}", sandbox_id);
+
+        let context = self.prepare(sandbox_id, None).await?;
+        PtySession::open(
+            sandbox_id,
+            &context.cwd,
+            &context.environment,
+            context.confinement,
+            cols,
+            rows,
+        )
+    }
+
+    /// Look up a sandbox and resolve the working directory for an execution
+    async fn prepare(&self, sandbox_id: Uuid, working_dir: Option<&str>) -> Result<ExecutionContext> {
+        let sandboxes = self.sandboxes.read().await;
+        let sandbox = sandboxes.get(&sandbox_id).ok_or_else(|| {
+            HybridLLMError::SandboxError(format!("Sandbox not found: {}", sandbox_id))
+        })?;
+
+        if sandbox.confinement.is_terminated() {
+            return Err(HybridLLMError::SandboxError(format!(
+                "Sandbox {} was terminated for exceeding its resource limits",
+                sandbox_id
+            )));
+        }
+
+        Ok(ExecutionContext {
+            cwd: Self::resolve_working_dir(&sandbox.root, working_dir)?,
+            environment: sandbox.environment.clone(),
+            confinement: Arc::clone(&sandbox.confinement),
+            network: Arc::clone(&sandbox.network),
+            timeout: sandbox
+                .config
+                .execution_timeout_secs
+                .map(Duration::from_secs)
+                .unwrap_or(DEFAULT_EXECUTION_TIMEOUT),
+            llm_id: sandbox.config.llm_id.clone(),
+        })
+    }
+
+    /// Resolve a working directory inside a sandbox, rejecting paths that escape it
+    fn resolve_working_dir(root: &Path, working_dir: Option<&str>) -> Result<PathBuf> {
+        let Some(relative) = working_dir else {
+            return Ok(root.to_path_buf());
+        };
+
+        let relative = Path::new(relative);
+        if relative
+            .components()
+            .any(|c| !matches!(c, Component::Normal(_) | Component::CurDir))
+        {
+            return Err(HybridLLMError::SecurityViolation(format!(
+                "Working directory escapes sandbox: {}",
+                relative.display()
+            )));
+        }
+
+        let cwd = root.join(relative);
+        std::fs::create_dir_all(&cwd)
+            .map_err(|e| HybridLLMError::SandboxError(e.to_string()))?;
+
+        Ok(cwd)
+    }
 
-        warn!("⚠️  Sandbox execution not yet implemented (MVP placeholder)");
+    /// Locate and checksum a file inside a sandbox ahead of a transfer request
+    pub async fn inspect_artifact(&self, sandbox_id: Uuid, file_path: &str) -> Result<ArtifactInfo> {
+        let root = self.sandbox_root(sandbox_id).await?;
+        let file_path = file_path.to_string();
 
-        Ok("Sandbox execution placeholder".to_string())
+        tokio::task::spawn_blocking(move || {
+            let path = artifacts::resolve_artifact(&root, &file_path)?;
+            let (size_bytes, sha256) = artifacts::checksum(&path)?;
+            Ok(ArtifactInfo { file_path, size_bytes, sha256, host_path: path })
+        })
+        .await
+        .map_err(|e| HybridLLMError::SandboxError(e.to_string()))?
     }
 
-    /// Transfer artifact from sandbox to main system
-    pub async fn transfer_artifact(&self, transfer: ArtifactTransfer) -> Result<PathBuf> {
+    /// Transfer an approved artifact from a sandbox into `downloads_dir`
+    /// The copy is verified against the checksum the user approved
+    pub async fn transfer_artifact(
+        &self,
+        transfer: ArtifactTransfer,
+        expected_sha256: &str,
+        downloads_dir: &Path,
+    ) -> Result<PathBuf> {
+        if transfer.approved != Some(true) {
+            return Err(HybridLLMError::PermissionDenied(format!(
+                "Artifact transfer not approved: {}",
+                transfer.file_path
+            )));
+        }
+
         info!("📤 Transferring artifact from sandbox {}: {} -> {}",
               transfer.sandbox_id, transfer.file_path, transfer.destination);
 
-        // TODO: Implement actual file transfer with approval
-        // For MVP, this is a placeholder
+        // Only the file name of the requested destination is honoured
+        let file_name = Path::new(&transfer.destination)
+            .file_name()
+            .ok_or_else(|| {
+                HybridLLMError::InvalidRequest(format!(
+                    "Invalid artifact destination: {}",
+                    transfer.destination
+                ))
+            })?
+            .to_owned();
+
+        let root = self.sandbox_root(transfer.sandbox_id).await?;
+        let destination = downloads_dir.join(file_name);
+        let expected_sha256 = expected_sha256.to_string();
+        let file_path = transfer.file_path;
+        let downloads_dir = downloads_dir.to_path_buf();
+
+        tokio::task::spawn_blocking(move || {
+            std::fs::create_dir_all(&downloads_dir)
+                .map_err(|e| HybridLLMError::FileSystemError(e.to_string()))?;
+
+            if destination.exists() {
+                return Err(HybridLLMError::FileSystemError(format!(
+                    "Destination already exists: {}",
+                    destination.display()
+                )));
+            }
 
-        Ok(PathBuf::from(&transfer.destination))
+            let source = artifacts::resolve_artifact(&root, &file_path)?;
+            artifacts::copy_verified(&source, &destination, &expected_sha256)?;
+
+            info!("✅ Artifact transferred to {:?}", destination);
+            Ok(destination)
+        })
+        .await
+        .map_err(|e| HybridLLMError::SandboxError(e.to_string()))?
+    }
+
+    /// List one directory of a sandbox; `path` is relative to the sandbox root
+    pub async fn list_files(&self, sandbox_id: Uuid, path: Option<&str>) -> Result<Vec<SandboxFile>> {
+        let root = self.sandbox_root(sandbox_id).await?;
+        let path = path.filter(|p| !p.is_empty()).map(str::to_string);
+
+        tokio::task::spawn_blocking(move || files::list_dir(&root, path.as_deref()))
+            .await
+            .map_err(|e| HybridLLMError::SandboxError(e.to_string()))?
+    }
+
+    /// Files created, modified or deleted in a sandbox since it was created
+    /// This is what a transfer could take out, or what destroying it would lose
+    pub async fn diff(&self, sandbox_id: Uuid) -> Result<Vec<FileChange>> {
+        let (root, baseline) = {
+            let sandboxes = self.sandboxes.read().await;
+            let sandbox = sandboxes.get(&sandbox_id).ok_or_else(|| {
+                HybridLLMError::SandboxError(format!("Sandbox not found: {}", sandbox_id))
+            })?;
+            (sandbox.root.clone(), Arc::clone(&sandbox.baseline))
+        };
+
+        tokio::task::spawn_blocking(move || files::diff(&root, &baseline))
+            .await
+            .map_err(|e| HybridLLMError::SandboxError(e.to_string()))
+    }
+
+    async fn capture_baseline(root: &Path) -> Result<files::Baseline> {
+        let root = root.to_path_buf();
+        tokio::task::spawn_blocking(move || files::capture_baseline(&root))
+            .await
+            .map_err(|e| HybridLLMError::SandboxError(e.to_string()))
+    }
+
+    /// Root directory of a live sandbox
+    async fn sandbox_root(&self, sandbox_id: Uuid) -> Result<PathBuf> {
+        self.sandboxes
+            .read()
+            .await
+            .get(&sandbox_id)
+            .map(|sandbox| sandbox.root.clone())
+            .ok_or_else(|| HybridLLMError::SandboxError(format!("Sandbox not found: {}", sandbox_id)))
+    }
+
+    /// Kill every running command and kernel in every sandbox, e.g. on a panic lockdown
+    /// The sandboxes themselves stay; returns how many processes were killed
+    pub async fn kill_running(&self) -> usize {
+        let sandboxes = self.sandboxes.read().await;
+        let mut killed = 0;
+        for (sandbox_id, sandbox) in sandboxes.iter() {
+            let running = sandbox.confinement.running();
+            if running == 0 {
+                continue;
+            }
+            warn!("🛑 Killing {} running process(es) in sandbox {}", running, sandbox_id);
+            #[cfg(unix)]
+            sandbox.confinement.signal_all(libc::SIGKILL);
+            killed += running;
+        }
+        killed
     }
 
     /// Snapshot a sandbox for later restoration
+    /// Running processes are paused while the filesystem is archived
     pub async fn snapshot(&self, sandbox_id: Uuid) -> Result<Uuid> {
         info!("📸 Snapshotting sandbox: {}", sandbox_id);
 
-        // TODO: Implement Firecracker snapshot
-        // For MVP, this is a placeholder
+        // TODO: Use Firecracker memory snapshots once the VM backend lands
+        let (root, config, confinement) = {
+            let sandboxes = self.sandboxes.read().await;
+            let sandbox = sandboxes.get(&sandbox_id).ok_or_else(|| {
+                HybridLLMError::SandboxError(format!("Sandbox not found: {}", sandbox_id))
+            })?;
+            (sandbox.root.clone(), sandbox.config.clone(), Arc::clone(&sandbox.confinement))
+        };
+
+        #[cfg(unix)]
+        confinement.signal_all(libc::SIGSTOP);
+        let snapshot = self.snapshots.create(&root, config).await;
+        #[cfg(unix)]
+        confinement.signal_all(libc::SIGCONT);
 
-        let snapshot_id = Uuid::new_v4();
-        Ok(snapshot_id)
+        let snapshot = snapshot?;
+        info!("✅ Snapshot {} created ({} bytes)", snapshot.id, snapshot.size_bytes);
+
+        Ok(snapshot.id)
     }
 
-    /// Restore a sandbox from snapshot
+    /// Restore a snapshot into a new sandbox
     pub async fn restore(&self, snapshot_id: Uuid) -> Result<Uuid> {
         info!("♻️  Restoring sandbox from snapshot: {}", snapshot_id);
 
-        // TODO: Implement snapshot restoration
-        // For MVP, this is a placeholder
+        let snapshot = self.snapshots.get(snapshot_id).await.ok_or_else(|| {
+            HybridLLMError::SandboxError(format!("Snapshot not found: {}", snapshot_id))
+        })?;
+
+        let config = SandboxConfig {
+            id: Uuid::new_v4(),
+            ..snapshot.config
+        };
+        let sandbox_id = self.create_sandbox(config).await?;
+        let root = self.sandbox_root(sandbox_id).await?;
+
+        if let Err(e) = self.snapshots.extract(snapshot_id, &root).await {
+            let _ = self.destroy_sandbox(sandbox_id).await;
+            return Err(e);
+        }
+
+        // The restored contents are the starting point for this sandbox's diff
+        let baseline = Self::capture_baseline(&root).await?;
+        if let Some(sandbox) = self.sandboxes.write().await.get_mut(&sandbox_id) {
+            sandbox.baseline = Arc::new(baseline);
+        }
+
+        info!("✅ Sandbox {} restored from snapshot {}", sandbox_id, snapshot_id);
 
-        let sandbox_id = Uuid::new_v4();
         Ok(sandbox_id)
     }
+
+    /// All stored snapshots, newest first
+    pub async fn list_snapshots(&self) -> Vec<SnapshotInfo> {
+        self.snapshots.list().await
+    }
+
+    /// Delete a stored snapshot
+    pub async fn delete_snapshot(&self, snapshot_id: Uuid) -> Result<()> {
+        info!("🗑️  Deleting snapshot: {}", snapshot_id);
+        self.snapshots.delete(snapshot_id).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use common::types::VolumeMount;
+
+    fn test_config() -> SandboxConfig {
+        SandboxConfig {
+            id: Uuid::new_v4(),
+            network_mode: NetworkMode::None,
+            cpu_limit: 50.0,
+            memory_limit_gb: 1.0,
+            disk_limit_gb: 1.0,
+            allowed_commands: vec![],
+            template: None,
+            max_lifetime_secs: None,
+            idle_timeout_secs: None,
+            execution_timeout_secs: None,
+            llm_id: None,
+            gpu: None,
+            volumes: vec![],
+        }
+    }
+
+    #[tokio::test]
+    async fn test_execute_in_working_dir() {
+        let base = std::env::temp_dir().join(format!("sandboxes-{}", Uuid::new_v4()));
+        let manager = SandboxManager::new(base.clone()).unwrap();
+        let id = manager.create_sandbox(test_config()).await.unwrap();
+
+        let result = manager.execute(id, "pwd", Some("project/src")).await.unwrap();
+        assert_eq!(result.exit_code, 0);
+        assert!(result.stdout.trim_end().ends_with("project/src"));
+
+        assert!(manager.execute(id, "ls", Some("../..")).await.is_err());
+
+        let _ = std::fs::remove_dir_all(base);
+    }
+
+    #[tokio::test]
+    async fn test_volume_survives_sandbox() {
+        let base = std::env::temp_dir().join(format!("sandboxes-{}", Uuid::new_v4()));
+        let manager = SandboxManager::new(base.clone()).unwrap();
+        manager.create_volume("project").unwrap();
+        let with_volume = |read_only| SandboxConfig {
+            volumes: vec![VolumeMount { name: "project".to_string(), read_only }],
+            ..test_config()
+        };
+
+        let id = manager.create_sandbox(with_volume(false)).await.unwrap();
+        manager.execute(id, "echo step-1 > volumes/project/progress", None).await.unwrap();
+        // Volume files are not the sandbox's own
+        assert!(manager.diff(id).await.unwrap().is_empty());
+        manager.destroy_sandbox(id).await.unwrap();
+
+        let id = manager.create_sandbox(with_volume(true)).await.unwrap();
+        let result = manager.execute(id, "cat volumes/project/progress", None).await.unwrap();
+        assert_eq!(result.stdout, "step-1\n");
+        manager.destroy_sandbox(id).await.unwrap();
+
+        assert_eq!(manager.list_volumes()[0].size_bytes, "step-1\n".len() as u64);
+        let missing = SandboxConfig {
+            volumes: vec![VolumeMount { name: "missing".to_string(), read_only: true }],
+            ..test_config()
+        };
+        assert!(manager.create_sandbox(missing).await.is_err());
+
+        let _ = std::fs::remove_dir_all(base);
+    }
+
+    #[tokio::test]
+    async fn test_audit_records() {
+        let base = std::env::temp_dir().join(format!("sandboxes-{}", Uuid::new_v4()));
+        let manager = SandboxManager::new(base.clone()).unwrap();
+        let mut events = manager.subscribe();
+
+        let id = manager
+            .create_sandbox(SandboxConfig {
+                llm_id: Some("coder".to_string()),
+                ..test_config()
+            })
+            .await
+            .unwrap();
+        manager.execute(id, "echo audited", None).await.unwrap();
+        manager.destroy_sandbox(id).await.unwrap();
+
+        let mut actions = Vec::new();
+        while let Ok(event) = events.try_recv() {
+            if let Some(record) = event.audit_record() {
+                assert_eq!(record.llm_id.as_deref(), Some("coder"));
+                assert_eq!(record.details["sandbox_id"], serde_json::json!(id));
+                actions.push(record.action);
+            }
+        }
+        assert_eq!(
+            actions,
+            vec!["Sandbox created", "Sandbox command executed", "Sandbox destroyed"]
+        );
+
+        let _ = std::fs::remove_dir_all(base);
+    }
+
+    /// Single 1 GB device, enough to test placement without hardware
+    struct FakeGpu(std::sync::Mutex<Vec<String>>);
+
+    impl GpuAllocator for FakeGpu {
+        fn reserve(&self, owner: &str, request: &GpuRequest) -> Result<u32> {
+            let mut owners = self.0.lock().unwrap();
+            if !owners.is_empty() || request.vram_mb > 1024 {
+                return Err(HybridLLMError::ResourceLimitExceeded {
+                    resource: "vram_mb".to_string(),
+                    limit: 1024.0,
+                    actual: request.vram_mb as f32,
+                });
+            }
+            owners.push(owner.to_string());
+            Ok(0)
+        }
+
+        fn release(&self, owner: &str) {
+            self.0.lock().unwrap().retain(|o| o != owner);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_gpu_reservation() {
+        let base = std::env::temp_dir().join(format!("sandboxes-{}", Uuid::new_v4()));
+        let gpu_config = || SandboxConfig {
+            gpu: Some(GpuRequest { vram_mb: 512, device: None }),
+            ..test_config()
+        };
+
+        let manager = SandboxManager::new(base.clone()).unwrap();
+        assert!(manager.create_sandbox(gpu_config()).await.is_err());
+
+        let gpu = Arc::new(FakeGpu(Default::default()));
+        let manager = SandboxManager::new(base.clone()).unwrap().with_gpu_allocator(gpu.clone());
+        let id = manager.create_sandbox(gpu_config()).await.unwrap();
+        let result = manager.execute(id, "echo $CUDA_VISIBLE_DEVICES", None).await.unwrap();
+        assert_eq!(result.stdout, "0\n");
+
+        // The device is taken until the sandbox is destroyed
+        assert!(manager.create_sandbox(gpu_config()).await.is_err());
+        let plain = manager.create_sandbox(test_config()).await.unwrap();
+        let result = manager.execute(plain, "echo \"[$CUDA_VISIBLE_DEVICES]\"", None).await.unwrap();
+        assert_eq!(result.stdout, "[]\n");
+
+        manager.destroy_sandbox(id).await.unwrap();
+        assert!(gpu.0.lock().unwrap().is_empty());
+        assert!(manager.create_sandbox(gpu_config()).await.is_ok());
+
+        let _ = std::fs::remove_dir_all(base);
+    }
+
+    #[tokio::test]
+    async fn test_proxy_only_reaches_nothing_but_the_proxy() {
+        if !Path::new("/usr/bin/python3").exists() {
+            return;
+        }
+        let base = std::env::temp_dir().join(format!("sandboxes-{}", Uuid::new_v4()));
+        let manager = SandboxManager::new(base.clone()).unwrap();
+        let id = manager
+            .create_sandbox(SandboxConfig { network_mode: NetworkMode::ProxyOnly, ..test_config() })
+            .await
+            .unwrap();
+
+        let host_server = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let script = format!(
+            "import os, socket\n\
+             proxy = os.environ['HTTP_PROXY'].rsplit(':', 1)\n\
+             socket.create_connection(('127.0.0.1', int(proxy[1])), 2).close()\n\
+             print('proxy reachable')\n\
+             try:\n    socket.create_connection(('127.0.0.1', {}), 2)\n    print('host reachable')\n\
+             except OSError:\n    print('host unreachable')\n",
+            host_server.local_addr().unwrap().port()
+        );
+        std::fs::write(manager.sandbox_root(id).await.unwrap().join("probe.py"), script).unwrap();
+
+        let result = manager.execute(id, "python3 probe.py", None).await.unwrap();
+        assert_eq!(result.stdout, "proxy reachable\nhost unreachable\n", "{}", result.stderr);
+
+        manager.destroy_sandbox(id).await.unwrap();
+        let _ = std::fs::remove_dir_all(base);
+    }
+
+    #[tokio::test]
+    async fn test_kill_running() {
+        let base = std::env::temp_dir().join(format!("sandboxes-{}", Uuid::new_v4()));
+        let manager = Arc::new(SandboxManager::new(base.clone()).unwrap());
+        let id = manager.create_sandbox(test_config()).await.unwrap();
+        assert_eq!(manager.kill_running().await, 0);
+
+        let running = {
+            let manager = Arc::clone(&manager);
+            tokio::spawn(async move { manager.execute(id, "sleep 30", None).await })
+        };
+        while manager.kill_running().await == 0 {
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+
+        let result = tokio::time::timeout(Duration::from_secs(5), running).await.unwrap().unwrap().unwrap();
+        assert_ne!(result.exit_code, 0);
+        assert_eq!(manager.execute(id, "echo ok", None).await.unwrap().stdout, "ok\n");
+
+        let _ = std::fs::remove_dir_all(base);
+    }
+
+    #[tokio::test]
+    async fn test_execution_timeout() {
+        let base = std::env::temp_dir().join(format!("sandboxes-{}", Uuid::new_v4()));
+        let manager = SandboxManager::new(base.clone()).unwrap();
+        let id = manager
+            .create_sandbox(SandboxConfig {
+                execution_timeout_secs: Some(1),
+                ..test_config()
+            })
+            .await
+            .unwrap();
+        let mut events = manager.subscribe();
+
+        let result = manager.execute(id, "sleep 30", None).await.unwrap();
+        assert!(result.timed_out);
+        assert!(matches!(
+            events.recv().await.unwrap(),
+            SandboxEvent::ExecutionTimedOut { sandbox_id, timeout_secs: 1, .. } if sandbox_id == id
+        ));
+
+        // The sandbox stays usable afterwards
+        let result = manager.execute(id, "echo ok", None).await.unwrap();
+        assert!(!result.timed_out);
+        assert_eq!(result.stdout, "ok\n");
+
+        let _ = std::fs::remove_dir_all(base);
+    }
+
+    #[tokio::test]
+    async fn test_snapshot_and_restore() {
+        let base = std::env::temp_dir().join(format!("sandboxes-{}", Uuid::new_v4()));
+        let manager = SandboxManager::new(base.clone()).unwrap();
+        let id = manager.create_sandbox(test_config()).await.unwrap();
+        manager.execute(id, "mkdir data && echo state > data/file", None).await.unwrap();
+
+        let snapshot_id = manager.snapshot(id).await.unwrap();
+        let listed = manager.list_snapshots().await;
+        assert_eq!(listed.len(), 1);
+        assert_eq!(listed[0].parent_sandbox, id);
+
+        let restored = manager.restore(snapshot_id).await.unwrap();
+        assert_ne!(restored, id);
+        let result = manager.execute(restored, "cat data/file", None).await.unwrap();
+        assert_eq!(result.stdout, "state\n");
+
+        manager.delete_snapshot(snapshot_id).await.unwrap();
+        assert!(manager.list_snapshots().await.is_empty());
+        assert!(manager.restore(snapshot_id).await.is_err());
+
+        let _ = std::fs::remove_dir_all(base);
+    }
+
+    #[tokio::test]
+    async fn test_pty_session() {
+        let base = std::env::temp_dir().join(format!("sandboxes-{}", Uuid::new_v4()));
+        let manager = SandboxManager::new(base.clone()).unwrap();
+        let id = manager.create_sandbox(test_config()).await.unwrap();
+
+        let (mut session, mut events) = manager.open_pty(id, 80, 24).await.unwrap();
+        session.resize(120, 40).unwrap();
+        session.write("echo pty-$((20 + 22))\n").await.unwrap();
+
+        let mut output = String::new();
+        while !output.contains("pty-42") {
+            match tokio::time::timeout(std::time::Duration::from_secs(5), events.recv()).await {
+                Ok(Some(PtyEvent::Output { data })) => output.push_str(&data),
+                other => panic!("unexpected PTY event: {:?}", other),
+            }
+        }
+
+        let recording = session.close();
+        assert!(recording.input.contains("echo pty-"));
+        assert!(recording.output.contains("pty-42"));
+
+        let _ = std::fs::remove_dir_all(base);
+    }
 }
@@ -0,0 +1,245 @@
+use common::errors::{HybridLLMError, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fs::{File, TryLockError};
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::task::JoinHandle;
+use tracing::{info, debug, warn};
+use uuid::Uuid;
+
+use crate::{limits, SandboxEvent, SandboxManager};
+
+/// Why a sandbox was reclaimed by the reaper
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ReclaimReason {
+    /// Exceeded its maximum lifetime
+    Expired,
+    /// No command ran within its idle timeout
+    Idle,
+    /// Left on disk by a previous run that did not shut down cleanly
+    Orphaned,
+}
+
+/// Extension of the lock file kept beside each sandbox root
+const OWNER_LOCK_EXTENSION: &str = "lock";
+
+/// Lock held on `<id>.lock` for as long as a manager owns the sandbox
+/// The app, `hybrid-llm run` and `hybrid-llm mcp` share a sandboxes path, so a sandbox
+/// is only an orphan once the lock can be taken, meaning its owner has exited; the file
+/// sits outside the root, where the sandbox can't reach it
+pub(crate) struct OwnerLock {
+    path: PathBuf,
+    _file: File,
+}
+
+impl OwnerLock {
+    /// Take the lock for a sandbox, or `None` while a live manager holds it
+    pub(crate) fn try_acquire(sandboxes_path: &Path, sandbox_id: Uuid) -> Result<Option<Self>> {
+        let path = sandboxes_path.join(format!("{}.{}", sandbox_id, OWNER_LOCK_EXTENSION));
+        let fs_err = |e: std::io::Error| HybridLLMError::SandboxError(format!("Sandbox lock {:?}: {}", path, e));
+
+        loop {
+            let file = File::options()
+                .read(true)
+                .write(true)
+                .create(true)
+                .truncate(false)
+                .open(&path)
+                .map_err(fs_err)?;
+            match file.try_lock() {
+                Ok(()) => {}
+                Err(TryLockError::WouldBlock) => return Ok(None),
+                Err(TryLockError::Error(e)) => return Err(fs_err(e)),
+            }
+
+            // A previous holder may have removed the file between our open and lock
+            let locked = file.metadata().map_err(fs_err)?;
+            if std::fs::metadata(&path).is_ok_and(|current| current.ino() == locked.ino() && current.dev() == locked.dev()) {
+                return Ok(Some(Self { path, _file: file }));
+            }
+        }
+    }
+}
+
+impl Drop for OwnerLock {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+impl SandboxManager {
+    /// Start the background reaper
+    /// Orphans from earlier runs are cleaned once, then expired and idle sandboxes every `interval`
+    pub fn spawn_reaper(self: &Arc<Self>, interval: Duration) -> JoinHandle<()> {
+        let manager = Arc::downgrade(self);
+
+        tokio::spawn(async move {
+            if let Some(manager) = manager.upgrade() {
+                manager.cleanup_orphans().await;
+            }
+
+            let mut ticker = tokio::time::interval(interval);
+            loop {
+                ticker.tick().await;
+                let Some(manager) = manager.upgrade() else {
+                    return;
+                };
+                manager.reap_expired().await;
+            }
+        })
+    }
+
+    /// Destroy sandboxes past their lifetime or idle timeout
+    pub async fn reap_expired(&self) -> Vec<(Uuid, ReclaimReason)> {
+        let waiting = self.pool.waiting();
+        let expired: Vec<(Uuid, ReclaimReason)> = {
+            let sandboxes = self.sandboxes.read().await;
+            sandboxes
+                .iter()
+                // Warm sandboxes start their timeouts when checked out
+                .filter(|(id, _)| !waiting.contains(id))
+                .filter_map(|(id, sandbox)| {
+                    let lifetime = sandbox.config.max_lifetime_secs.map(Duration::from_secs);
+                    let idle_timeout = sandbox.config.idle_timeout_secs.map(Duration::from_secs);
+
+                    if lifetime.is_some_and(|max| sandbox.created_at.elapsed() >= max) {
+                        return Some((*id, ReclaimReason::Expired));
+                    }
+
+                    match (idle_timeout, sandbox.confinement.idle_for()) {
+                        (Some(timeout), Some(idle)) if idle >= timeout => {
+                            Some((*id, ReclaimReason::Idle))
+                        }
+                        _ => None,
+                    }
+                })
+                .collect()
+        };
+
+        for (sandbox_id, reason) in &expired {
+            info!("♻️  Reclaiming sandbox {} ({:?})", sandbox_id, reason);
+            self.reclaim(*sandbox_id, *reason).await;
+        }
+
+        expired
+    }
+
+    /// Remove sandboxes, and their cgroups, left behind by managers that are no longer running
+    /// Sandboxes of other live managers sharing the sandboxes path are left alone
+    pub async fn cleanup_orphans(&self) -> Vec<Uuid> {
+        let live: HashSet<Uuid> = self.sandboxes.read().await.keys().copied().collect();
+
+        // Sandbox roots and their locks are named by ID; snapshots and templates are not
+        let mut candidates = HashSet::new();
+        if let Ok(entries) = std::fs::read_dir(&self.sandboxes_path) {
+            for entry in entries.filter_map(|e| e.ok()) {
+                let name = entry.file_name();
+                let Some(name) = name.to_str() else {
+                    continue;
+                };
+                let stem = name.strip_suffix(&format!(".{}", OWNER_LOCK_EXTENSION)).unwrap_or(name);
+                if let Ok(id) = Uuid::parse_str(stem) {
+                    candidates.insert(id);
+                }
+            }
+        }
+
+        let mut orphans = Vec::new();
+        for sandbox_id in candidates.into_iter().filter(|id| !live.contains(id)) {
+            let lock = match OwnerLock::try_acquire(&self.sandboxes_path, sandbox_id) {
+                Ok(Some(lock)) => lock,
+                Ok(None) => {
+                    debug!("Sandbox {} belongs to another running manager", sandbox_id);
+                    continue;
+                }
+                Err(e) => {
+                    warn!("⚠️  Failed to check the owner of sandbox {}: {}", sandbox_id, e);
+                    continue;
+                }
+            };
+
+            limits::remove_orphaned_cgroup(sandbox_id);
+            if self.sandboxes_path.join(sandbox_id.to_string()).is_dir() {
+                info!("🧹 Removing orphaned sandbox {}", sandbox_id);
+                self.reclaim(sandbox_id, ReclaimReason::Orphaned).await;
+                orphans.push(sandbox_id);
+            }
+            drop(lock);
+        }
+
+        orphans
+    }
+
+    async fn reclaim(&self, sandbox_id: Uuid, reason: ReclaimReason) {
+        match self.destroy_sandbox(sandbox_id).await {
+            Ok(()) => {
+                let _ = self.events.send(SandboxEvent::Reclaimed { sandbox_id, reason });
+            }
+            Err(e) => warn!("⚠️  Failed to reclaim sandbox {}: {}", sandbox_id, e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use common::types::{NetworkMode, SandboxConfig};
+
+    #[tokio::test]
+    async fn test_reaps_idle_and_orphaned() {
+        let base = std::env::temp_dir().join(format!("sandboxes-{}", Uuid::new_v4()));
+        let orphan = Uuid::new_v4();
+        std::fs::create_dir_all(base.join(orphan.to_string())).unwrap();
+
+        let config = |idle_timeout_secs| SandboxConfig {
+            id: Uuid::new_v4(),
+            network_mode: NetworkMode::None,
+            cpu_limit: 50.0,
+            memory_limit_gb: 1.0,
+            disk_limit_gb: 1.0,
+            allowed_commands: vec![],
+            template: None,
+            max_lifetime_secs: None,
+            idle_timeout_secs,
+            execution_timeout_secs: None,
+            llm_id: None,
+            gpu: None,
+            volumes: vec![],
+        };
+
+        // Another manager on the same path, as when the CLI runs next to the app
+        let other = SandboxManager::new(base.clone()).unwrap();
+        let others = other.create_sandbox(config(None)).await.unwrap();
+
+        let manager = SandboxManager::new(base.clone()).unwrap();
+        let mut events = manager.subscribe();
+        assert_eq!(manager.cleanup_orphans().await, vec![orphan]);
+        assert!(!base.join(orphan.to_string()).exists());
+        assert!(base.join(others.to_string()).exists());
+
+        // Once its owner lets go, the other manager's sandbox is an orphan too
+        drop(other.sandboxes.write().await.remove(&others));
+        assert_eq!(manager.cleanup_orphans().await, vec![others]);
+        assert!(!base.join(others.to_string()).exists());
+
+        let id = manager.create_sandbox(config(Some(0))).await.unwrap();
+
+        assert_eq!(manager.reap_expired().await, vec![(id, ReclaimReason::Idle)]);
+        assert!(manager.get_config(id).await.is_none());
+        assert!(!base.join(format!("{}.{}", id, OWNER_LOCK_EXTENSION)).exists());
+
+        let mut reasons = Vec::new();
+        while let Ok(event) = events.try_recv() {
+            if let SandboxEvent::Reclaimed { reason, .. } = event {
+                reasons.push(reason);
+            }
+        }
+        assert_eq!(reasons, vec![ReclaimReason::Orphaned, ReclaimReason::Orphaned, ReclaimReason::Idle]);
+
+        let _ = std::fs::remove_dir_all(base);
+    }
+}
@@ -0,0 +1,553 @@
+use chrono::Utc;
+use common::{
+    errors::{HybridLLMError, Result},
+    types::{NetworkMode, SandboxConfig, SandboxUsage},
+};
+use std::collections::HashSet;
+use std::ffi::CString;
+use std::os::fd::OwnedFd;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::broadcast;
+use tracing::{info, debug, warn};
+use uuid::Uuid;
+
+use crate::isolation::Isolation;
+use crate::namespaces::Namespaces;
+use crate::network::{EgressProxy, NetworkCounters};
+use crate::volumes;
+use crate::SandboxEvent;
+
+/// Delegated cgroup v2 subtree that sandbox cgroups are created under
+const CGROUP_ROOT: &str = "/sys/fs/cgroup/hybrid-llm";
+
+/// cpu.max period in microseconds
+const CPU_PERIOD_USEC: u64 = 100_000;
+
+/// How often the watchdog samples usage
+const SAMPLE_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Consecutive over-limit samples before a sandbox is killed
+const SUSTAINED_SAMPLES: u32 = 3;
+
+/// Publish a usage event every this many samples; the latest sample is always queryable
+const USAGE_EVENT_SAMPLES: u32 = 5;
+
+const GB: f64 = 1024.0 * 1024.0 * 1024.0;
+
+/// Resource confinement shared by every process started in one sandbox
+/// Uses a cgroup v2 group when one can be created and falls back to rlimits otherwise;
+/// processes only ever see the sandbox's namespaces and private filesystem view
+pub(crate) struct Confinement {
+    cgroup: Option<PathBuf>,
+    /// Pre-formatted `cgroup.procs` path so the child can join without allocating after fork
+    cgroup_procs: Option<CString>,
+    memory_limit_bytes: u64,
+    disk_limit_bytes: u64,
+    cpu_limit_percent: f32,
+    namespaces: Arc<Namespaces>,
+    /// Network namespace used instead of the sandbox's own, for package installs
+    network: Option<Arc<OwnedFd>>,
+    isolation: Arc<Isolation>,
+    /// Egress proxy URL exported to commands
+    proxy_url: Option<String>,
+    /// Process group IDs of running commands
+    processes: Mutex<HashSet<i32>>,
+    /// When a command last started or finished
+    last_active: Mutex<Instant>,
+    terminated: AtomicBool,
+}
+
+impl Confinement {
+    /// `staging` is the empty directory processes build their private root on
+    /// Unless the sandbox has full network access it gets a network namespace of its own,
+    /// holding only loopback and, for proxy-only access, the egress proxy's listener
+    pub(crate) async fn new(config: &SandboxConfig, root: &Path, staging: &Path) -> Result<Self> {
+        Self::with_isolation(config, Isolation::new(root, staging, &[])?).await
+    }
+
+    /// Confinement for a one-off build in `root` that may also read `toolchain`
+    pub(crate) async fn for_build(config: &SandboxConfig, root: &Path, staging: &Path, toolchain: &Path) -> Result<Self> {
+        Self::with_isolation(config, Isolation::new(root, staging, &[toolchain.to_path_buf()])?).await
+    }
+
+    async fn with_isolation(config: &SandboxConfig, isolation: Isolation) -> Result<Self> {
+        let memory_limit_bytes = (config.memory_limit_gb as f64 * GB) as u64;
+        let disk_limit_bytes = (config.disk_limit_gb as f64 * GB) as u64;
+        let cgroup = Self::create_cgroup(config.id, config.cpu_limit, memory_limit_bytes);
+
+        let cgroup_procs = cgroup.as_ref().and_then(|path| {
+            CString::new(path.join("cgroup.procs").to_string_lossy().into_owned()).ok()
+        });
+
+        if cgroup.is_none() {
+            debug!("cgroup v2 unavailable for sandbox {}, using rlimits only", config.id);
+        }
+
+        let isolate_network = config.network_mode != NetworkMode::Full;
+        let namespaces = match Namespaces::create(isolate_network, cgroup_procs.clone()).await {
+            Ok(namespaces) => Arc::new(namespaces),
+            Err(e) => {
+                if let Some(cgroup) = &cgroup {
+                    let _ = std::fs::remove_dir(cgroup);
+                }
+                return Err(e);
+            }
+        };
+
+        Ok(Self {
+            cgroup,
+            cgroup_procs,
+            memory_limit_bytes,
+            disk_limit_bytes,
+            cpu_limit_percent: config.cpu_limit,
+            namespaces,
+            network: None,
+            isolation: Arc::new(isolation),
+            proxy_url: None,
+            processes: Mutex::new(HashSet::new()),
+            last_active: Mutex::new(Instant::now()),
+            terminated: AtomicBool::new(false),
+        })
+    }
+
+    /// Create a cgroup with cpu.max and memory.max set
+    /// `cpu_limit` is a percentage of one core
+    fn create_cgroup(sandbox_id: Uuid, cpu_limit: f32, memory_limit_bytes: u64) -> Option<PathBuf> {
+        let root = Path::new(CGROUP_ROOT);
+        // Only a cgroup v2 hierarchy exposes cgroup.controllers
+        if !root.parent()?.join("cgroup.controllers").exists() {
+            return None;
+        }
+
+        let path = root.join(sandbox_id.to_string());
+        std::fs::create_dir_all(&path).ok()?;
+
+        let quota = ((cpu_limit as f64 / 100.0) * CPU_PERIOD_USEC as f64).max(1000.0) as u64;
+        let configured = std::fs::write(path.join("cpu.max"), format!("{} {}", quota, CPU_PERIOD_USEC))
+            .and_then(|_| std::fs::write(path.join("memory.max"), memory_limit_bytes.to_string()))
+            .and_then(|_| std::fs::write(path.join("memory.swap.max"), "0"));
+
+        if let Err(e) = configured {
+            warn!("⚠️  Failed to configure cgroup for sandbox {}: {}", sandbox_id, e);
+            let _ = std::fs::remove_dir(&path);
+            return None;
+        }
+
+        Some(path)
+    }
+
+    /// Listener for a proxy-only sandbox's egress proxy, inside the sandbox's network namespace
+    pub(crate) async fn proxy_listener(&self) -> Result<std::net::TcpListener> {
+        self.namespaces.proxy_listener().await
+    }
+
+    /// Same limits, but in a fresh network namespace whose only way out is an egress proxy
+    /// serving the returned listener; set it with `set_proxy` before running anything
+    /// Used for package installs, which only download and never run package code
+    pub(crate) async fn with_egress(&self) -> Result<(Self, std::net::TcpListener)> {
+        let (network, listener) = self.namespaces.egress_network().await?;
+        let confinement = Self {
+            cgroup: self.cgroup.clone(),
+            cgroup_procs: self.cgroup_procs.clone(),
+            memory_limit_bytes: self.memory_limit_bytes,
+            disk_limit_bytes: self.disk_limit_bytes,
+            cpu_limit_percent: self.cpu_limit_percent,
+            namespaces: Arc::clone(&self.namespaces),
+            network: Some(Arc::new(network)),
+            isolation: Arc::clone(&self.isolation),
+            proxy_url: None,
+            processes: Mutex::new(HashSet::new()),
+            last_active: Mutex::new(Instant::now()),
+            terminated: AtomicBool::new(false),
+        };
+        Ok((confinement, listener))
+    }
+
+    /// Connect to `port` on the sandbox's loopback, from inside its network namespace if it has one
+    pub(crate) async fn connect(&self, port: u16) -> Result<tokio::net::TcpStream> {
+        let addr = std::net::SocketAddr::from((std::net::Ipv4Addr::LOCALHOST, port));
+        let connected = match self.namespaces.socket().await? {
+            Some(socket) => {
+                let socket = std::net::TcpStream::from(socket);
+                match socket.set_nonblocking(true) {
+                    Ok(()) => tokio::net::TcpSocket::from_std_stream(socket).connect(addr).await,
+                    Err(e) => Err(e),
+                }
+            }
+            None => tokio::net::TcpStream::connect(addr).await,
+        };
+        connected.map_err(|e| HybridLLMError::NetworkError(e.to_string()))
+    }
+
+    /// Route the sandbox's traffic through `proxy`
+    pub(crate) fn set_proxy(&mut self, proxy: &EgressProxy) {
+        self.proxy_url = Some(proxy.url());
+    }
+
+    /// Egress proxy the sandbox's traffic goes through, if it has network access
+    pub(crate) fn proxy_url(&self) -> Option<&str> {
+        self.proxy_url.as_deref()
+    }
+
+    /// Whether the sandbox was killed for exceeding its limits
+    pub(crate) fn is_terminated(&self) -> bool {
+        self.terminated.load(Ordering::SeqCst)
+    }
+
+    /// Configure a command so its process joins this sandbox's limits, filesystem view and network policy
+    /// Spawning fails rather than running the command unconfined
+    pub(crate) fn apply(&self, command: &mut tokio::process::Command) {
+        self.configure(command, true);
+    }
+
+    /// Like `apply`, for programs such as Chromium that reserve far more address space than
+    /// they use; their memory is held to the cgroup limit, so they can't run without one
+    pub(crate) fn apply_without_address_limit(&self, command: &mut tokio::process::Command) -> Result<()> {
+        if self.cgroup.is_none() {
+            return Err(HybridLLMError::SandboxError(
+                "This program needs a cgroup v2 memory limit, which is unavailable here".to_string(),
+            ));
+        }
+        self.configure(command, false);
+        Ok(())
+    }
+
+    fn configure(&self, command: &mut tokio::process::Command, address_limit: bool) {
+        // Proxy-only commands share a network namespace with the proxy's listener, the one way
+        // out; with full access the proxy only meters traffic
+        if let Some(proxy) = &self.proxy_url {
+            for var in ["HTTP_PROXY", "HTTPS_PROXY", "ALL_PROXY", "http_proxy", "https_proxy", "all_proxy"] {
+                command.env(var, proxy);
+            }
+        }
+
+        #[cfg(target_os = "linux")]
+        {
+            let cgroup_procs = self.cgroup_procs.clone();
+            let namespaces = Arc::clone(&self.namespaces);
+            let network = self.network.clone();
+            let isolation = Arc::clone(&self.isolation);
+            let memory = if address_limit { self.memory_limit_bytes as libc::rlim_t } else { libc::RLIM_INFINITY };
+            let file_size = self.disk_limit_bytes as libc::rlim_t;
+
+            // SAFETY: only async-signal-safe libc calls run between fork and exec
+            unsafe {
+                command.pre_exec(move || {
+                    // Own session and process group so the whole tree can be signalled;
+                    // a terminal may already have started one
+                    if libc::getsid(0) != libc::getpid() && libc::setsid() < 0 {
+                        return Err(std::io::Error::last_os_error());
+                    }
+
+                    if let Some(procs) = &cgroup_procs {
+                        let fd = libc::open(procs.as_ptr(), libc::O_WRONLY);
+                        if fd < 0 {
+                            return Err(std::io::Error::last_os_error());
+                        }
+                        let written = libc::write(fd, b"0".as_ptr() as *const libc::c_void, 1);
+                        libc::close(fd);
+                        if written != 1 {
+                            return Err(std::io::Error::last_os_error());
+                        }
+                    }
+
+                    let memory_limit = libc::rlimit { rlim_cur: memory, rlim_max: memory };
+                    let file_limit = libc::rlimit { rlim_cur: file_size, rlim_max: file_size };
+                    if libc::setrlimit(libc::RLIMIT_AS, &memory_limit) != 0
+                        || libc::setrlimit(libc::RLIMIT_FSIZE, &file_limit) != 0
+                    {
+                        return Err(std::io::Error::last_os_error());
+                    }
+
+                    // Fail closed: without its own namespaces the command doesn't run
+                    namespaces.enter(network.as_deref())?;
+                    isolation.enter()
+                });
+            }
+        }
+    }
+
+    pub(crate) fn track(&self, pid: u32) {
+        self.processes.lock().unwrap().insert(pid as i32);
+        *self.last_active.lock().unwrap() = Instant::now();
+    }
+
+    pub(crate) fn untrack(&self, pid: u32) {
+        self.processes.lock().unwrap().remove(&(pid as i32));
+        *self.last_active.lock().unwrap() = Instant::now();
+    }
+
+    /// Restart the idle clock without running anything
+    pub(crate) fn mark_active(&self) {
+        *self.last_active.lock().unwrap() = Instant::now();
+    }
+
+    /// How long the sandbox has had no running commands; `None` while busy
+    pub(crate) fn idle_for(&self) -> Option<Duration> {
+        if !self.processes.lock().unwrap().is_empty() {
+            return None;
+        }
+        Some(self.last_active.lock().unwrap().elapsed())
+    }
+
+    /// Process groups currently running in the sandbox
+    pub(crate) fn running(&self) -> usize {
+        self.processes.lock().unwrap().len()
+    }
+
+    /// Send a signal to every running process group in the sandbox
+    pub(crate) fn signal_all(&self, signal: i32) {
+        #[cfg(unix)]
+        for pgid in self.processes.lock().unwrap().iter() {
+            // SAFETY: plain syscall on a process group we created
+            unsafe {
+                libc::kill(-pgid, signal);
+            }
+        }
+    }
+
+    /// Kill everything in the sandbox and refuse further executions
+    pub(crate) fn kill(&self) {
+        self.terminated.store(true, Ordering::SeqCst);
+        self.namespaces.kill();
+
+        if let Some(cgroup) = &self.cgroup {
+            let _ = std::fs::write(cgroup.join("cgroup.kill"), "1");
+        }
+
+        #[cfg(unix)]
+        self.signal_all(libc::SIGKILL);
+    }
+
+    fn memory_current(&self) -> Option<u64> {
+        let cgroup = self.cgroup.as_ref()?;
+        std::fs::read_to_string(cgroup.join("memory.current"))
+            .ok()?
+            .trim()
+            .parse()
+            .ok()
+    }
+
+    fn cpu_usage_usec(&self) -> Option<u64> {
+        let cgroup = self.cgroup.as_ref()?;
+        let stat = std::fs::read_to_string(cgroup.join("cpu.stat")).ok()?;
+        stat.lines()
+            .find_map(|line| line.strip_prefix("usage_usec "))
+            .and_then(|v| v.trim().parse().ok())
+    }
+
+    /// Cumulative CPU time, resident memory and process count for telemetry
+    /// Read from the cgroup when there is one, otherwise summed over the sandbox's process groups
+    fn sample(&self) -> (u64, u64, usize) {
+        if let Some(cgroup) = &self.cgroup {
+            let processes = std::fs::read_to_string(cgroup.join("cgroup.procs"))
+                .map(|procs| procs.lines().count())
+                .unwrap_or(0);
+            return (
+                self.cpu_usage_usec().unwrap_or(0),
+                self.memory_current().unwrap_or(0),
+                processes,
+            );
+        }
+
+        let groups = self.processes.lock().unwrap().clone();
+        if groups.is_empty() {
+            return (0, 0, 0);
+        }
+        proc_usage(&groups)
+    }
+
+    /// Kill whatever still runs in the sandbox and remove its cgroup
+    /// Without a cgroup, killing the PID namespace's init takes everything else with it
+    pub(crate) fn release(&self) {
+        self.kill();
+        if let Some(cgroup) = &self.cgroup {
+            let _ = std::fs::remove_dir(cgroup);
+        }
+    }
+}
+
+/// Kill and remove the cgroup of a sandbox whose owner has exited
+pub(crate) fn remove_orphaned_cgroup(sandbox_id: Uuid) {
+    let path = Path::new(CGROUP_ROOT).join(sandbox_id.to_string());
+    if !path.is_dir() {
+        return;
+    }
+
+    let _ = std::fs::write(path.join("cgroup.kill"), "1");
+    if std::fs::remove_dir(&path).is_ok() {
+        debug!("Removed orphaned cgroup {:?}", path);
+    }
+}
+
+/// Sum CPU time (usec), resident memory and count of processes in `groups` from /proc
+#[cfg(target_os = "linux")]
+fn proc_usage(groups: &HashSet<i32>) -> (u64, u64, usize) {
+    // SAFETY: sysconf has no preconditions
+    let (ticks_per_sec, page_size) = unsafe {
+        (
+            libc::sysconf(libc::_SC_CLK_TCK).max(1) as u64,
+            libc::sysconf(libc::_SC_PAGESIZE).max(1) as u64,
+        )
+    };
+
+    let Ok(entries) = std::fs::read_dir("/proc") else {
+        return (0, 0, 0);
+    };
+
+    let (mut ticks, mut pages, mut count) = (0u64, 0u64, 0usize);
+    for entry in entries.filter_map(|e| e.ok()) {
+        if !entry.file_name().to_str().is_some_and(|name| name.bytes().all(|b| b.is_ascii_digit())) {
+            continue;
+        }
+        let Ok(stat) = std::fs::read_to_string(entry.path().join("stat")) else {
+            continue;
+        };
+        // The command name may contain spaces; fields are counted after its closing paren
+        let Some((_, rest)) = stat.rsplit_once(')') else {
+            continue;
+        };
+        let fields: Vec<&str> = rest.split_whitespace().collect();
+        let field = |n: usize| fields.get(n - 3).and_then(|v| v.parse::<u64>().ok()).unwrap_or(0);
+
+        let in_sandbox = fields
+            .get(2)
+            .and_then(|pgrp| pgrp.parse::<i32>().ok())
+            .is_some_and(|pgrp| groups.contains(&pgrp));
+        if in_sandbox {
+            // utime + stime + cutime + cstime, then rss
+            ticks += field(14) + field(15) + field(16) + field(17);
+            pages += field(24);
+            count += 1;
+        }
+    }
+
+    (ticks * 1_000_000 / ticks_per_sec, pages * page_size, count)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn proc_usage(_groups: &HashSet<i32>) -> (u64, u64, usize) {
+    (0, 0, 0)
+}
+
+/// Total size of all files under `path`, leaving out mounted volumes
+pub(crate) fn disk_usage(path: &Path) -> u64 {
+    walkdir::WalkDir::new(path)
+        .into_iter()
+        .filter_entry(|e| !volumes::is_volumes_dir(e))
+        .filter_map(|e| e.ok())
+        .filter(|e| e.file_type().is_file())
+        .filter_map(|e| e.metadata().ok())
+        .map(|m| m.len())
+        .sum()
+}
+
+/// Sample a sandbox's usage, publish it and kill the sandbox on sustained violation
+pub(crate) async fn watchdog(
+    sandbox_id: Uuid,
+    root: PathBuf,
+    confinement: Arc<Confinement>,
+    network: Arc<NetworkCounters>,
+    latest: Arc<Mutex<Option<SandboxUsage>>>,
+    events: broadcast::Sender<SandboxEvent>,
+) {
+    let mut violations = 0u32;
+    let mut last_cpu = confinement.cpu_usage_usec();
+    let mut last_sample_cpu = None;
+    let mut samples = 0u32;
+    let mut interval = tokio::time::interval(SAMPLE_INTERVAL);
+
+    loop {
+        interval.tick().await;
+
+        if confinement.is_terminated() {
+            return;
+        }
+
+        let disk = {
+            let root = root.clone();
+            tokio::task::spawn_blocking(move || disk_usage(&root))
+                .await
+                .unwrap_or(0)
+        };
+
+        let (cpu_usec, memory_bytes, processes) = confinement.sample();
+        let cpu_percent = last_sample_cpu.map_or(0.0, |previous: u64| {
+            cpu_usec.saturating_sub(previous) as f64 / SAMPLE_INTERVAL.as_micros() as f64 * 100.0
+        });
+        last_sample_cpu = Some(cpu_usec);
+
+        let usage = SandboxUsage {
+            sandbox_id,
+            timestamp: Utc::now(),
+            cpu_percent: cpu_percent as f32,
+            memory_bytes,
+            disk_bytes: disk,
+            network_sent_bytes: network.sent.load(Ordering::Relaxed),
+            network_received_bytes: network.received.load(Ordering::Relaxed),
+            processes,
+        };
+        *latest.lock().unwrap() = Some(usage.clone());
+
+        samples += 1;
+        if samples.is_multiple_of(USAGE_EVENT_SAMPLES) {
+            let _ = events.send(SandboxEvent::Usage { usage });
+        }
+
+        let mut exceeded = None;
+
+        if disk > confinement.disk_limit_bytes {
+            exceeded = Some(("disk", confinement.disk_limit_bytes as f64 / GB, disk as f64 / GB));
+        }
+
+        // The cgroup enforces memory.max itself; catch groups pinned right at it
+        if let Some(memory) = confinement.memory_current() {
+            if memory >= confinement.memory_limit_bytes {
+                exceeded = Some(("memory", confinement.memory_limit_bytes as f64 / GB, memory as f64 / GB));
+            }
+        }
+
+        if let (Some(previous), Some(current)) = (last_cpu, confinement.cpu_usage_usec()) {
+            let percent = (current.saturating_sub(previous)) as f64
+                / SAMPLE_INTERVAL.as_micros() as f64
+                * 100.0;
+            // cpu.max throttles, so only flag usage well past the quota
+            if percent > confinement.cpu_limit_percent as f64 * 1.5 {
+                exceeded = Some(("cpu", confinement.cpu_limit_percent as f64, percent));
+            }
+            last_cpu = Some(current);
+        }
+
+        match exceeded {
+            Some((resource, limit, actual)) => {
+                violations += 1;
+                debug!(
+                    "Sandbox {} over {} limit ({}/{})",
+                    sandbox_id, resource, violations, SUSTAINED_SAMPLES
+                );
+
+                if violations >= SUSTAINED_SAMPLES {
+                    warn!(
+                        "🚨 Sandbox {} exceeded {} limit ({:.2} > {:.2}), killing",
+                        sandbox_id, resource, actual, limit
+                    );
+                    confinement.kill();
+
+                    let _ = events.send(SandboxEvent::ResourceExceeded {
+                        sandbox_id,
+                        resource: resource.to_string(),
+                        limit: limit as f32,
+                        actual: actual as f32,
+                    });
+
+                    info!("💀 Sandbox {} terminated for resource violation", sandbox_id);
+                    return;
+                }
+            }
+            None => violations = 0,
+        }
+    }
+}
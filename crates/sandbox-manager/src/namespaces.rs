@@ -0,0 +1,431 @@
+use common::errors::{HybridLLMError, Result};
+use std::ffi::{CStr, CString};
+use std::io;
+use std::net::Ipv4Addr;
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd, RawFd};
+use std::os::unix::net::UnixStream;
+use std::process::Stdio;
+
+use crate::isolation::check;
+
+/// Set in the byte sent with the descriptors when a network namespace follows
+const SENT_NET: u8 = 1;
+
+/// Set in the byte sent with the descriptors when a user namespace follows
+const SENT_USER: u8 = 2;
+
+/// Namespaces shared by every process in one sandbox
+/// A PID namespace whose init outlives single commands, so background servers keep running
+/// and orphans are reaped; a network namespace unless the sandbox has full network access;
+/// and, when the host grants no privileges, the user namespace that owns both
+pub(crate) struct Namespaces {
+    user: Option<OwnedFd>,
+    pid: OwnedFd,
+    net: Option<OwnedFd>,
+    /// pidfd of the namespace's init; killing it ends every process in the sandbox
+    init: OwnedFd,
+}
+
+impl Namespaces {
+    /// Create the namespaces in a short-lived helper, which forks their init and passes back
+    /// handles to them; the init joins the sandbox's cgroup when `cgroup_procs` is given
+    pub(crate) async fn create(isolate_network: bool, cgroup_procs: Option<CString>) -> Result<Self> {
+        let (uid_map, gid_map) = id_maps();
+        // SAFETY: the helper only makes async-signal-safe calls
+        let (flags, fds) = unsafe {
+            run_helper("namespaces", move |channel| {
+                create_helper(channel, isolate_network, cgroup_procs.as_deref(), &uid_map, &gid_map)
+            })
+        }
+        .await?;
+
+        let mut fds = fds.into_iter();
+        let (Some(pid), Some(init)) = (fds.next(), fds.next()) else {
+            return Err(helper_error("namespaces", io::Error::other("helper sent no namespace")));
+        };
+        let net = if flags & SENT_NET != 0 { fds.next() } else { None };
+        let user = if flags & SENT_USER != 0 { fds.next() } else { None };
+        Ok(Self { user, pid, net, init })
+    }
+
+    /// Bind a listener for the egress proxy inside the sandbox's network namespace
+    pub(crate) async fn proxy_listener(&self) -> Result<std::net::TcpListener> {
+        let net = self.net.as_ref().map(|net| net.as_raw_fd()).ok_or_else(|| {
+            HybridLLMError::NetworkError("Sandbox has no network namespace of its own".to_string())
+        })?;
+        let (_, fds) = self.listener_helper(Some(net)).await?;
+        let Some(listener) = fds.into_iter().next() else {
+            return Err(helper_error("proxy listener", io::Error::other("helper sent no listener")));
+        };
+        Ok(std::net::TcpListener::from(listener))
+    }
+
+    /// A fresh network namespace owned by the sandbox's user namespace, together with a
+    /// listener inside it for an egress proxy that will be its only way out
+    pub(crate) async fn egress_network(&self) -> Result<(OwnedFd, std::net::TcpListener)> {
+        let (_, fds) = self.listener_helper(None).await?;
+        let mut fds = fds.into_iter();
+        let (Some(listener), Some(net)) = (fds.next(), fds.next()) else {
+            return Err(helper_error("egress network", io::Error::other("helper sent no namespace")));
+        };
+        Ok((net, std::net::TcpListener::from(listener)))
+    }
+
+    /// An unconnected TCP socket in the sandbox's network namespace, for reaching its loopback
+    /// from the host; `None` when the sandbox shares the host's network
+    pub(crate) async fn socket(&self) -> Result<Option<OwnedFd>> {
+        let Some(net) = self.net.as_ref().map(|net| net.as_raw_fd()) else {
+            return Ok(None);
+        };
+        let user = self.user.as_ref().map(|user| user.as_raw_fd());
+        // SAFETY: the helper only makes async-signal-safe calls
+        let (_, fds) = unsafe { run_helper("socket", move |channel| socket_helper(channel, user, net)) }.await?;
+        match fds.into_iter().next() {
+            Some(socket) => Ok(Some(socket)),
+            None => Err(helper_error("socket", io::Error::other("helper sent no socket"))),
+        }
+    }
+
+    async fn listener_helper(&self, net: Option<RawFd>) -> Result<(u8, Vec<OwnedFd>)> {
+        let user = self.user.as_ref().map(|user| user.as_raw_fd());
+        // SAFETY: the helper only makes async-signal-safe calls
+        unsafe { run_helper("proxy listener", move |channel| listener_helper(channel, user, net)) }.await
+    }
+
+    /// Move the calling process into the namespaces, then fork; `network` replaces the
+    /// sandbox's own network namespace when given
+    /// Only the child returns: the caller stays outside the PID namespace, supervising the
+    /// child and exiting the way it does, so callers still signal and wait on one process group
+    ///
+    /// # Safety
+    /// Must only be called in a single-threaded child about to exec
+    pub(crate) unsafe fn enter(&self, network: Option<&OwnedFd>) -> io::Result<()> {
+        if let Some(user) = &self.user {
+            check(libc::setns(user.as_raw_fd(), libc::CLONE_NEWUSER))?;
+        }
+        if let Some(net) = network.or(self.net.as_ref()) {
+            check(libc::setns(net.as_raw_fd(), libc::CLONE_NEWNET))?;
+        }
+        check(libc::setns(self.pid.as_raw_fd(), libc::CLONE_NEWPID))?;
+
+        // Born non-dumpable, so other sandbox processes can't follow its /proc entries to the
+        // host's root before it pivots; exec makes it dumpable again
+        check(libc::prctl(libc::PR_SET_DUMPABLE, 0, 0, 0, 0))?;
+        match libc::fork() {
+            -1 => Err(io::Error::last_os_error()),
+            0 => check(libc::prctl(libc::PR_SET_PDEATHSIG, libc::SIGKILL, 0, 0, 0)),
+            child => supervise(child),
+        }
+    }
+
+    /// Kill the namespace's init, and with it every process in the sandbox
+    pub(crate) fn kill(&self) {
+        // SAFETY: plain syscall on a pidfd we own
+        unsafe {
+            libc::syscall(libc::SYS_pidfd_send_signal, self.init.as_raw_fd(), libc::SIGKILL, std::ptr::null::<libc::siginfo_t>(), 0);
+        }
+    }
+}
+
+impl Drop for Namespaces {
+    fn drop(&mut self) {
+        self.kill();
+    }
+}
+
+/// Run `setup` in a helper process between fork and exec and collect the descriptors it sends
+///
+/// # Safety
+/// `setup` must only make async-signal-safe calls
+async unsafe fn run_helper<F>(what: &'static str, setup: F) -> Result<(u8, Vec<OwnedFd>)>
+where
+    F: Fn(RawFd) -> io::Result<()> + Send + Sync + 'static,
+{
+    let (ours, theirs) = UnixStream::pair().map_err(|e| helper_error(what, e))?;
+    let channel = theirs.as_raw_fd();
+
+    let mut helper = tokio::process::Command::new("true");
+    helper.stdin(Stdio::null()).stdout(Stdio::null()).stderr(Stdio::null());
+    helper.pre_exec(move || setup(channel));
+    let status = helper.status().await.map_err(|e| helper_error(what, e))?;
+    drop(theirs);
+    if !status.success() {
+        return Err(helper_error(what, io::Error::other(format!("helper exited with {}", status))));
+    }
+
+    receive_fds(&ours).map_err(|e| helper_error(what, e))
+}
+
+fn helper_error(what: &str, e: io::Error) -> HybridLLMError {
+    HybridLLMError::SandboxError(format!("Failed to create the sandbox {}: {}", what, e))
+}
+
+/// Runs in the helper between fork and exec: creates the namespaces, forks their init and
+/// sends back the PID namespace, the init's pidfd and any network and user namespaces
+unsafe fn create_helper(
+    channel: RawFd,
+    isolate_network: bool,
+    cgroup_procs: Option<&CStr>,
+    uid_map: &CStr,
+    gid_map: &CStr,
+) -> io::Result<()> {
+    // Out of the application's session, so signals meant for its terminal never reach the init
+    if libc::setsid() < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    if let Some(procs) = cgroup_procs {
+        write_file(procs, b"0")?;
+    }
+
+    let mut fds = [-1; 4];
+    let mut count = 2;
+    let mut flags = 0;
+    let namespaces = libc::CLONE_NEWPID | if isolate_network { libc::CLONE_NEWNET } else { 0 };
+    let mut user = -1;
+    // Without privileges a user namespace grants the right to create the others; IDs stay the same inside
+    if libc::unshare(namespaces) != 0 {
+        check(libc::unshare(libc::CLONE_NEWUSER | namespaces))?;
+        write_id_maps(uid_map, gid_map)?;
+        user = open_namespace(c"/proc/self/ns/user")?;
+    }
+    if isolate_network {
+        loopback_up()?;
+        fds[count] = open_namespace(c"/proc/self/ns/net")?;
+        count += 1;
+        flags |= SENT_NET;
+    }
+    if user >= 0 {
+        fds[count] = user;
+        count += 1;
+        flags |= SENT_USER;
+    }
+
+    // The init's /proc entries would lead to the host's root, so it is never dumpable
+    check(libc::prctl(libc::PR_SET_DUMPABLE, 0, 0, 0, 0))?;
+    let init = match libc::fork() {
+        -1 => return Err(io::Error::last_os_error()),
+        0 => init(),
+        init => init,
+    };
+    let sent = open_namespace(c"/proc/self/ns/pid_for_children").and_then(|pid| {
+        fds[0] = pid;
+        fds[1] = libc::syscall(libc::SYS_pidfd_open, init, 0) as RawFd;
+        if fds[1] < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        send_fds(channel, &fds[..count], flags)
+    });
+    // Nobody else could ever kill an init whose handle never arrived
+    if sent.is_err() {
+        libc::kill(init, libc::SIGKILL);
+    }
+    sent
+}
+
+/// Init of a sandbox's PID namespace: adopts and reaps orphans until it is killed
+unsafe fn init() -> ! {
+    // Hold nothing of the application's; the namespace only needs this process alive
+    libc::syscall(libc::SYS_close_range, 3, u32::MAX, 0);
+    loop {
+        if libc::waitpid(-1, std::ptr::null_mut(), 0) < 0 {
+            libc::sleep(1);
+        }
+    }
+}
+
+/// Wait for the command forked into the PID namespace, then exit with its status
+/// Signals the caller's process group receives reach the command directly, so here they stay blocked
+unsafe fn supervise(child: libc::pid_t) -> ! {
+    // Spawning completes once the command execs, not when this process closes its copy
+    libc::syscall(libc::SYS_close_range, 3, u32::MAX, 0);
+    let mut signals: libc::sigset_t = std::mem::zeroed();
+    libc::sigfillset(&mut signals);
+    libc::sigprocmask(libc::SIG_BLOCK, &signals, std::ptr::null_mut());
+
+    let mut status = 0;
+    if libc::waitpid(child, &mut status, 0) < 0 {
+        libc::_exit(127);
+    }
+    if libc::WIFSIGNALED(status) {
+        let signal = libc::WTERMSIG(status);
+        libc::signal(signal, libc::SIG_DFL);
+        libc::sigemptyset(&mut signals);
+        libc::sigaddset(&mut signals, signal);
+        libc::sigprocmask(libc::SIG_UNBLOCK, &signals, std::ptr::null_mut());
+        libc::kill(libc::getpid(), signal);
+        libc::_exit(128 + signal);
+    }
+    libc::_exit(libc::WEXITSTATUS(status))
+}
+
+/// Runs in the helper between fork and exec: joins the sandbox's user namespace and the
+/// network namespace `net`, or a fresh one, and sends back a listener bound to its loopback
+/// followed by the fresh namespace
+unsafe fn listener_helper(channel: RawFd, user: Option<RawFd>, net: Option<RawFd>) -> io::Result<()> {
+    if let Some(user) = user {
+        check(libc::setns(user, libc::CLONE_NEWUSER))?;
+    }
+    let mut fds = [-1; 2];
+    let count = match net {
+        Some(net) => {
+            check(libc::setns(net, libc::CLONE_NEWNET))?;
+            1
+        }
+        None => {
+            check(libc::unshare(libc::CLONE_NEWNET))?;
+            loopback_up()?;
+            fds[1] = open_namespace(c"/proc/self/ns/net")?;
+            2
+        }
+    };
+
+    let listener = libc::socket(libc::AF_INET, libc::SOCK_STREAM | libc::SOCK_CLOEXEC, 0);
+    if listener < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    let addr = libc::sockaddr_in {
+        sin_family: libc::AF_INET as libc::sa_family_t,
+        sin_port: 0,
+        sin_addr: libc::in_addr { s_addr: u32::from(Ipv4Addr::LOCALHOST).to_be() },
+        sin_zero: [0; 8],
+    };
+    check(libc::bind(
+        listener,
+        &addr as *const libc::sockaddr_in as *const libc::sockaddr,
+        std::mem::size_of::<libc::sockaddr_in>() as libc::socklen_t,
+    ))?;
+    check(libc::listen(listener, 128))?;
+    fds[0] = listener;
+
+    send_fds(channel, &fds[..count], 0)
+}
+
+/// Runs in the helper between fork and exec: sends back a TCP socket created in the network namespace `net`
+unsafe fn socket_helper(channel: RawFd, user: Option<RawFd>, net: RawFd) -> io::Result<()> {
+    if let Some(user) = user {
+        check(libc::setns(user, libc::CLONE_NEWUSER))?;
+    }
+    check(libc::setns(net, libc::CLONE_NEWNET))?;
+    let socket = libc::socket(libc::AF_INET, libc::SOCK_STREAM | libc::SOCK_CLOEXEC, 0);
+    if socket < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    send_fds(channel, &[socket], 0)
+}
+
+/// Loopback starts out down in a new network namespace
+unsafe fn loopback_up() -> io::Result<()> {
+    #[repr(C)]
+    struct InterfaceFlags {
+        name: [u8; libc::IFNAMSIZ],
+        flags: libc::c_short,
+        _union: [u8; 22],
+    }
+    let mut request = InterfaceFlags {
+        name: [0; libc::IFNAMSIZ],
+        flags: (libc::IFF_UP | libc::IFF_RUNNING) as libc::c_short,
+        _union: [0; 22],
+    };
+    request.name[..2].copy_from_slice(b"lo");
+    let control = libc::socket(libc::AF_INET, libc::SOCK_DGRAM | libc::SOCK_CLOEXEC, 0);
+    if control < 0 || libc::ioctl(control, libc::SIOCSIFFLAGS, &request) != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    libc::close(control);
+    Ok(())
+}
+
+/// `uid_map` and `gid_map` contents that keep the caller's IDs inside a new user namespace
+fn id_maps() -> (CString, CString) {
+    // SAFETY: getuid and getgid cannot fail
+    let (uid, gid) = unsafe { (libc::getuid(), libc::getgid()) };
+    (
+        CString::new(format!("{} {} 1", uid, uid)).expect("no interior NUL"),
+        CString::new(format!("{} {} 1", gid, gid)).expect("no interior NUL"),
+    )
+}
+
+/// Map IDs in a user namespace the caller just created
+unsafe fn write_id_maps(uid_map: &CStr, gid_map: &CStr) -> io::Result<()> {
+    write_file(c"/proc/self/setgroups", b"deny")?;
+    write_file(c"/proc/self/uid_map", uid_map.to_bytes())?;
+    write_file(c"/proc/self/gid_map", gid_map.to_bytes())
+}
+
+unsafe fn write_file(path: &CStr, contents: &[u8]) -> io::Result<()> {
+    let fd = libc::open(path.as_ptr(), libc::O_WRONLY | libc::O_CLOEXEC);
+    if fd < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    let written = libc::write(fd, contents.as_ptr() as *const libc::c_void, contents.len());
+    libc::close(fd);
+    if written != contents.len() as isize {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+unsafe fn open_namespace(path: &CStr) -> io::Result<RawFd> {
+    let fd = libc::open(path.as_ptr(), libc::O_RDONLY | libc::O_CLOEXEC);
+    if fd < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(fd)
+}
+
+/// Room for the SCM_RIGHTS control message carrying up to four descriptors
+#[repr(C, align(8))]
+struct ControlBuffer([u8; 64]);
+
+unsafe fn send_fds(channel: RawFd, fds: &[RawFd], flags: u8) -> io::Result<()> {
+    let mut control = ControlBuffer([0; 64]);
+    let mut byte = [flags];
+    let mut iov = libc::iovec { iov_base: byte.as_mut_ptr() as *mut libc::c_void, iov_len: 1 };
+    let payload = std::mem::size_of_val(fds) as libc::c_uint;
+
+    let mut message: libc::msghdr = std::mem::zeroed();
+    message.msg_iov = &mut iov;
+    message.msg_iovlen = 1;
+    message.msg_control = control.0.as_mut_ptr() as *mut libc::c_void;
+    message.msg_controllen = libc::CMSG_SPACE(payload) as _;
+
+    let header = libc::CMSG_FIRSTHDR(&message);
+    (*header).cmsg_level = libc::SOL_SOCKET;
+    (*header).cmsg_type = libc::SCM_RIGHTS;
+    (*header).cmsg_len = libc::CMSG_LEN(payload) as _;
+    std::ptr::copy_nonoverlapping(fds.as_ptr(), libc::CMSG_DATA(header) as *mut RawFd, fds.len());
+
+    if libc::sendmsg(channel, &message, 0) != 1 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+fn receive_fds(channel: &UnixStream) -> io::Result<(u8, Vec<OwnedFd>)> {
+    let mut control = ControlBuffer([0; 64]);
+    let mut byte = [0u8; 1];
+    let mut iov = libc::iovec { iov_base: byte.as_mut_ptr() as *mut libc::c_void, iov_len: 1 };
+
+    // SAFETY: the message points at live buffers, and each descriptor the kernel
+    // installed is wrapped exactly once
+    unsafe {
+        let mut message: libc::msghdr = std::mem::zeroed();
+        message.msg_iov = &mut iov;
+        message.msg_iovlen = 1;
+        message.msg_control = control.0.as_mut_ptr() as *mut libc::c_void;
+        message.msg_controllen = control.0.len() as _;
+
+        let received = libc::recvmsg(channel.as_raw_fd(), &mut message, libc::MSG_CMSG_CLOEXEC | libc::MSG_DONTWAIT);
+        if received < 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        let header = libc::CMSG_FIRSTHDR(&message);
+        if header.is_null() || (*header).cmsg_level != libc::SOL_SOCKET || (*header).cmsg_type != libc::SCM_RIGHTS {
+            return Ok((byte[0], Vec::new()));
+        }
+        let count = ((*header).cmsg_len as usize - libc::CMSG_LEN(0) as usize) / std::mem::size_of::<RawFd>();
+        let data = libc::CMSG_DATA(header) as *const RawFd;
+        Ok((byte[0], (0..count).map(|i| OwnedFd::from_raw_fd(std::ptr::read_unaligned(data.add(i)))).collect()))
+    }
+}
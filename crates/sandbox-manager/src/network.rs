@@ -0,0 +1,271 @@
+use common::{
+    errors::{Result, HybridLLMError},
+    types::{domain_matches, NetworkMode},
+};
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::{broadcast, RwLock};
+use tokio::task::JoinHandle;
+use tracing::{info, debug, warn};
+use uuid::Uuid;
+
+use crate::SandboxEvent;
+
+/// Largest request head the proxy will buffer
+const MAX_HEAD_BYTES: usize = 16 * 1024;
+
+/// Bytes moved through a sandbox's egress proxy since it was created
+#[derive(Debug, Default)]
+pub(crate) struct NetworkCounters {
+    pub(crate) sent: AtomicU64,
+    pub(crate) received: AtomicU64,
+}
+
+/// Per-sandbox HTTP/HTTPS egress proxy
+/// Enforces the domain allowlist in proxy-only mode and reports every connection
+pub(crate) struct EgressProxy {
+    addr: SocketAddr,
+    task: JoinHandle<()>,
+}
+
+impl EgressProxy {
+    /// Start a proxy listening on the host's loopback, for sandboxes sharing the host network
+    pub(crate) async fn start(
+        sandbox_id: Uuid,
+        mode: NetworkMode,
+        allowed_domains: Arc<RwLock<Vec<String>>>,
+        counters: Arc<NetworkCounters>,
+        events: broadcast::Sender<SandboxEvent>,
+    ) -> Result<Self> {
+        let listener = TcpListener::bind("127.0.0.1:0")
+            .await
+            .map_err(|e| HybridLLMError::NetworkError(e.to_string()))?;
+        Self::serve(listener, sandbox_id, mode, allowed_domains, counters, events)
+    }
+
+    /// Start a proxy on a listener bound inside a sandbox's network namespace, where it is
+    /// the only way out
+    pub(crate) fn start_in_namespace(
+        listener: std::net::TcpListener,
+        sandbox_id: Uuid,
+        mode: NetworkMode,
+        allowed_domains: Arc<RwLock<Vec<String>>>,
+        counters: Arc<NetworkCounters>,
+        events: broadcast::Sender<SandboxEvent>,
+    ) -> Result<Self> {
+        let listener = listener
+            .set_nonblocking(true)
+            .and_then(|_| TcpListener::from_std(listener))
+            .map_err(|e| HybridLLMError::NetworkError(e.to_string()))?;
+        Self::serve(listener, sandbox_id, mode, allowed_domains, counters, events)
+    }
+
+    fn serve(
+        listener: TcpListener,
+        sandbox_id: Uuid,
+        mode: NetworkMode,
+        allowed_domains: Arc<RwLock<Vec<String>>>,
+        counters: Arc<NetworkCounters>,
+        events: broadcast::Sender<SandboxEvent>,
+    ) -> Result<Self> {
+        let addr = listener
+            .local_addr()
+            .map_err(|e| HybridLLMError::NetworkError(e.to_string()))?;
+
+        info!("🌐 Egress proxy for sandbox {} ({:?}) on {}", sandbox_id, mode, addr);
+
+        let task = tokio::spawn(async move {
+            while let Ok((client, _)) = listener.accept().await {
+                let allowed_domains = Arc::clone(&allowed_domains);
+                let counters = Arc::clone(&counters);
+                let events = events.clone();
+                tokio::spawn(async move {
+                    if let Err(e) =
+                        handle_client(sandbox_id, mode, client, allowed_domains, &counters, events).await
+                    {
+                        debug!("Proxy connection for sandbox {} ended: {}", sandbox_id, e);
+                    }
+                });
+            }
+        });
+
+        Ok(Self { addr, task })
+    }
+
+    /// Proxy URL to hand to processes in the sandbox
+    pub(crate) fn url(&self) -> String {
+        format!("http://{}", self.addr)
+    }
+}
+
+impl Drop for EgressProxy {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+async fn handle_client(
+    sandbox_id: Uuid,
+    mode: NetworkMode,
+    mut client: TcpStream,
+    allowed_domains: Arc<RwLock<Vec<String>>>,
+    counters: &NetworkCounters,
+    events: broadcast::Sender<SandboxEvent>,
+) -> std::io::Result<()> {
+    let mut head = Vec::with_capacity(1024);
+    let mut buf = [0u8; 1024];
+    let head_end = loop {
+        let n = client.read(&mut buf).await?;
+        if n == 0 {
+            return Ok(());
+        }
+        head.extend_from_slice(&buf[..n]);
+
+        if let Some(pos) = find_head_end(&head) {
+            break pos;
+        }
+        if head.len() > MAX_HEAD_BYTES {
+            client.write_all(b"HTTP/1.1 431 Request Header Fields Too Large\r\n\r\n").await?;
+            return Ok(());
+        }
+    };
+
+    let head_text = String::from_utf8_lossy(&head[..head_end]).into_owned();
+    let Some(request) = parse_request_line(&head_text) else {
+        client.write_all(b"HTTP/1.1 400 Bad Request\r\n\r\n").await?;
+        return Ok(());
+    };
+
+    let allowed = match mode {
+        NetworkMode::Full => true,
+        NetworkMode::ProxyOnly => {
+            let domains = allowed_domains.read().await;
+            domains.iter().any(|pattern| domain_matches(pattern, &request.host))
+        }
+        NetworkMode::None => false,
+    };
+
+    let _ = events.send(SandboxEvent::NetworkConnection {
+        sandbox_id,
+        host: request.host.clone(),
+        port: request.port,
+        allowed,
+    });
+
+    if !allowed {
+        warn!("🚫 Sandbox {} denied egress to {}:{}", sandbox_id, request.host, request.port);
+        client.write_all(b"HTTP/1.1 403 Forbidden\r\n\r\n").await?;
+        return Ok(());
+    }
+
+    let mut upstream = match TcpStream::connect((request.host.as_str(), request.port)).await {
+        Ok(upstream) => upstream,
+        Err(e) => {
+            client.write_all(b"HTTP/1.1 502 Bad Gateway\r\n\r\n").await?;
+            return Err(e);
+        }
+    };
+
+    if request.is_connect {
+        client.write_all(b"HTTP/1.1 200 Connection Established\r\n\r\n").await?;
+    } else {
+        // Rewrite the absolute-form target to origin-form for the upstream server
+        let rewritten = head_text.replacen(&request.target, &request.path, 1);
+        upstream.write_all(rewritten.as_bytes()).await?;
+        counters.sent.fetch_add(rewritten.len() as u64, Ordering::Relaxed);
+    }
+    upstream.write_all(&head[head_end..]).await?;
+    counters.sent.fetch_add((head.len() - head_end) as u64, Ordering::Relaxed);
+
+    // Counted as it flows so long-lived connections show up in live usage
+    let (mut client_read, mut client_write) = client.split();
+    let (mut upstream_read, mut upstream_write) = upstream.split();
+    tokio::try_join!(
+        relay(&mut client_read, &mut upstream_write, &counters.sent),
+        relay(&mut upstream_read, &mut client_write, &counters.received),
+    )?;
+    Ok(())
+}
+
+/// Copy one direction of a proxied connection, counting bytes as they pass
+async fn relay<R, W>(reader: &mut R, writer: &mut W, counter: &AtomicU64) -> std::io::Result<()>
+where
+    R: AsyncRead + Unpin,
+    W: AsyncWrite + Unpin,
+{
+    let mut buf = [0u8; 16 * 1024];
+    loop {
+        let n = reader.read(&mut buf).await?;
+        if n == 0 {
+            return writer.shutdown().await;
+        }
+        writer.write_all(&buf[..n]).await?;
+        counter.fetch_add(n as u64, Ordering::Relaxed);
+    }
+}
+
+struct ProxyRequest {
+    is_connect: bool,
+    target: String,
+    host: String,
+    port: u16,
+    path: String,
+}
+
+fn find_head_end(buf: &[u8]) -> Option<usize> {
+    buf.windows(4).position(|w| w == b"\r\n\r\n").map(|p| p + 4)
+}
+
+fn parse_request_line(head: &str) -> Option<ProxyRequest> {
+    let mut parts = head.lines().next()?.split_whitespace();
+    let method = parts.next()?;
+    let target = parts.next()?.to_string();
+
+    if method.eq_ignore_ascii_case("CONNECT") {
+        let (host, port) = split_host_port(&target, 443)?;
+        return Some(ProxyRequest { is_connect: true, target, host, port, path: String::new() });
+    }
+
+    let rest = target.strip_prefix("http://")?;
+    let (authority, path) = match rest.find('/') {
+        Some(idx) => (&rest[..idx], rest[idx..].to_string()),
+        None => (rest, "/".to_string()),
+    };
+    let (host, port) = split_host_port(authority, 80)?;
+
+    Some(ProxyRequest { is_connect: false, target, host, port, path })
+}
+
+fn split_host_port(authority: &str, default_port: u16) -> Option<(String, u16)> {
+    match authority.rsplit_once(':') {
+        Some((host, port)) => Some((host.to_lowercase(), port.parse().ok()?)),
+        None => Some((authority.to_lowercase(), default_port)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_domain_matches() {
+        assert!(domain_matches("pypi.org", "PyPI.org"));
+        assert!(!domain_matches("pypi.org", "evil-pypi.org"));
+        assert!(domain_matches("*.github.com", "api.github.com"));
+        assert!(!domain_matches("*.github.com", "github.com"));
+    }
+
+    #[test]
+    fn test_parse_request_line() {
+        let connect = parse_request_line("CONNECT pypi.org:443 HTTP/1.1\r\n\r\n").unwrap();
+        assert!(connect.is_connect);
+        assert_eq!((connect.host.as_str(), connect.port), ("pypi.org", 443));
+
+        let get = parse_request_line("GET http://example.com/a/b HTTP/1.1\r\n\r\n").unwrap();
+        assert!(!get.is_connect);
+        assert_eq!((get.host.as_str(), get.port, get.path.as_str()), ("example.com", 80, "/a/b"));
+    }
+}
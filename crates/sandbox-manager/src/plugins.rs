@@ -0,0 +1,590 @@
+use async_trait::async_trait;
+use common::{
+    errors::{HybridLLMError, Result},
+    traits::{CommandAnalyzer, RiskLevel, SecurityAnalysis, Tool},
+    types::RequestContext,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Component as PathComponent, Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::{debug, error, info, warn};
+use wasmtime::component::{Component, Linker};
+use wasmtime::{Engine, Store, StoreLimits, StoreLimitsBuilder};
+
+use crate::wasm::{epoch_deadline, metered_engine};
+
+mod bindings {
+    pub mod tool {
+        wasmtime::component::bindgen!({ path: "wit/plugin.wit", world: "tool-plugin" });
+    }
+    pub mod parser {
+        wasmtime::component::bindgen!({
+            path: "wit/plugin.wit",
+            world: "parser-plugin",
+            with: { "hybrid-llm:plugin/host": super::tool::hybrid_llm::plugin::host },
+        });
+    }
+    pub mod analyzer {
+        wasmtime::component::bindgen!({
+            path: "wit/plugin.wit",
+            world: "analyzer-plugin",
+            with: { "hybrid-llm:plugin/host": super::tool::hybrid_llm::plugin::host },
+        });
+    }
+}
+
+use bindings::analyzer::exports::hybrid_llm::plugin::analyzer::RiskLevel as PluginRiskLevel;
+use bindings::analyzer::AnalyzerPlugin;
+use bindings::parser::ParserPlugin;
+use bindings::tool::hybrid_llm::plugin::host::{self, LogLevel};
+use bindings::tool::ToolPlugin;
+
+/// Each plugin is a directory in the plugins directory holding this manifest and its component
+pub const PLUGIN_MANIFEST: &str = "plugin.toml";
+
+/// Largest file `read-file` hands a plugin
+const MAX_PLUGIN_READ_BYTES: u64 = 32 * 1024 * 1024;
+
+/// Configuration for the plugin host
+#[derive(Debug, Clone)]
+pub struct PluginConfig {
+    /// Searched for plugin directories once, at startup
+    pub plugins_dir: PathBuf,
+    /// Folders plugins may ask to read, by the name they use for them
+    pub folders: HashMap<String, PathBuf>,
+    /// Instruction budget per call
+    pub fuel: u64,
+    /// Maximum linear memory per instance
+    pub max_memory_bytes: usize,
+    /// Wall-clock limit per call
+    pub timeout: Duration,
+}
+
+impl Default for PluginConfig {
+    fn default() -> Self {
+        Self {
+            plugins_dir: PathBuf::from("./plugins"),
+            folders: HashMap::new(),
+            fuel: 2_000_000_000,
+            max_memory_bytes: 128 * 1024 * 1024,
+            timeout: Duration::from_secs(10),
+        }
+    }
+}
+
+/// What a plugin provides, and so which world its component implements
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PluginKind {
+    Tool,
+    Parser,
+    Analyzer,
+}
+
+/// What a plugin may do beyond computing and logging; nothing unless asked for
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PluginCapabilities {
+    /// Managed folders the plugin may read files from, e.g. `["uploads"]`
+    pub read_folders: Vec<String>,
+}
+
+/// `plugin.toml`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PluginManifest {
+    /// Lowercase letters, digits and dashes
+    pub name: String,
+    pub version: String,
+    #[serde(default)]
+    pub description: String,
+    pub kind: PluginKind,
+    /// The WASM component, relative to the plugin's directory
+    pub component: PathBuf,
+    #[serde(default)]
+    pub capabilities: PluginCapabilities,
+}
+
+/// A tool a plugin provides
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PluginToolInfo {
+    pub name: String,
+    pub description: String,
+    pub parameters: serde_json::Value,
+    pub read_only: bool,
+}
+
+/// A loaded plugin as the platform shows it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PluginInfo {
+    pub manifest: PluginManifest,
+    pub directory: PathBuf,
+    /// Tools of a tool plugin
+    pub tools: Vec<PluginToolInfo>,
+    /// File extensions a parser plugin handles
+    pub extensions: Vec<String>,
+}
+
+/// A plugin directory that could not be loaded
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PluginFailure {
+    pub directory: PathBuf,
+    pub error: String,
+}
+
+/// What a plugin's store holds: its name for the log and the folders it was granted
+struct PluginState {
+    plugin: String,
+    read_folders: HashMap<String, PathBuf>,
+    limits: StoreLimits,
+}
+
+impl host::Host for PluginState {
+    fn log(&mut self, level: LogLevel, message: String) {
+        match level {
+            LogLevel::Debug => debug!("🧩 [{}] {}", self.plugin, message),
+            LogLevel::Info => info!("🧩 [{}] {}", self.plugin, message),
+            LogLevel::Warn => warn!("🧩 [{}] {}", self.plugin, message),
+            LogLevel::Error => error!("🧩 [{}] {}", self.plugin, message),
+        }
+    }
+
+    fn read_file(&mut self, folder: String, path: String) -> std::result::Result<Vec<u8>, String> {
+        let root = self
+            .read_folders
+            .get(&folder)
+            .ok_or_else(|| format!("Plugin {} was not granted reading {}", self.plugin, folder))?;
+        let path = resolve_in(root, &path).map_err(|e| e.to_string())?;
+
+        let size = std::fs::metadata(&path).map_err(|e| e.to_string())?.len();
+        if size > MAX_PLUGIN_READ_BYTES {
+            return Err(format!("{} is larger than {} bytes", path.display(), MAX_PLUGIN_READ_BYTES));
+        }
+        debug!("🧩 Plugin {} reading {:?}", self.plugin, path);
+        std::fs::read(&path).map_err(|e| e.to_string())
+    }
+}
+
+/// A compiled plugin and the store settings every call to it gets
+struct Plugin {
+    info: PluginInfo,
+    component: Component,
+    read_folders: HashMap<String, PathBuf>,
+}
+
+/// The engine and the limits each call runs under
+#[derive(Clone)]
+struct Runtime {
+    engine: Engine,
+    fuel: u64,
+    max_memory_bytes: usize,
+    timeout: Duration,
+}
+
+impl Runtime {
+    /// A fresh store per call, so nothing a plugin keeps in memory outlives the call
+    fn store(&self, plugin: &Plugin) -> Result<Store<PluginState>> {
+        let state = PluginState {
+            plugin: plugin.info.manifest.name.clone(),
+            read_folders: plugin.read_folders.clone(),
+            limits: StoreLimitsBuilder::new().memory_size(self.max_memory_bytes).instances(1).build(),
+        };
+        let mut store = Store::new(&self.engine, state);
+        store.limiter(|state| &mut state.limits);
+        store.set_fuel(self.fuel).map_err(plugin_err)?;
+        store.set_epoch_deadline(epoch_deadline(self.timeout));
+        Ok(store)
+    }
+
+    fn linker(&self) -> Result<Linker<PluginState>> {
+        let mut linker = Linker::new(&self.engine);
+        host::add_to_linker(&mut linker, |state: &mut PluginState| state).map_err(plugin_err)?;
+        Ok(linker)
+    }
+
+    fn describe_tools(&self, plugin: &Plugin) -> Result<Vec<PluginToolInfo>> {
+        let mut store = self.store(plugin)?;
+        let instance = ToolPlugin::instantiate(&mut store, &plugin.component, &self.linker()?).map_err(plugin_err)?;
+        let tools = instance.hybrid_llm_plugin_tool().call_describe(&mut store).map_err(plugin_err)?;
+
+        tools
+            .into_iter()
+            .map(|tool| {
+                let parameters: serde_json::Value = serde_json::from_str(&tool.parameters).map_err(|e| {
+                    HybridLLMError::InvalidRequest(format!("Parameters of tool {} are not JSON: {}", tool.name, e))
+                })?;
+                if !parameters.is_object() {
+                    return Err(HybridLLMError::InvalidRequest(format!("Parameters of tool {} must be a JSON schema object", tool.name)));
+                }
+                Ok(PluginToolInfo { name: tool.name, description: tool.description, parameters, read_only: tool.read_only })
+            })
+            .collect()
+    }
+
+    fn call_tool(&self, plugin: &Plugin, name: &str, arguments: &serde_json::Value) -> Result<serde_json::Value> {
+        let mut store = self.store(plugin)?;
+        let instance = ToolPlugin::instantiate(&mut store, &plugin.component, &self.linker()?).map_err(plugin_err)?;
+        let output = instance
+            .hybrid_llm_plugin_tool()
+            .call_call(&mut store, name, &arguments.to_string())
+            .map_err(plugin_err)?
+            .map_err(|e| HybridLLMError::InvalidRequest(format!("{}: {}", name, e)))?;
+
+        // Output that isn't JSON is passed on as a string
+        Ok(serde_json::from_str(&output).unwrap_or(serde_json::Value::String(output)))
+    }
+
+    fn extensions(&self, plugin: &Plugin) -> Result<Vec<String>> {
+        let mut store = self.store(plugin)?;
+        let instance = ParserPlugin::instantiate(&mut store, &plugin.component, &self.linker()?).map_err(plugin_err)?;
+        let extensions = instance.hybrid_llm_plugin_parser().call_extensions(&mut store).map_err(plugin_err)?;
+        Ok(extensions.into_iter().map(|extension| extension.trim_start_matches('.').to_lowercase()).collect())
+    }
+
+    fn parse(&self, plugin: &Plugin, filename: &str, content: &[u8]) -> Result<String> {
+        let mut store = self.store(plugin)?;
+        let instance = ParserPlugin::instantiate(&mut store, &plugin.component, &self.linker()?).map_err(plugin_err)?;
+        instance
+            .hybrid_llm_plugin_parser()
+            .call_parse(&mut store, filename, content)
+            .map_err(plugin_err)?
+            .map_err(|e| HybridLLMError::InvalidRequest(format!("{} could not parse {}: {}", plugin.info.manifest.name, filename, e)))
+    }
+
+    fn analyze(&self, plugin: &Plugin, command: &str) -> Result<SecurityAnalysis> {
+        let mut store = self.store(plugin)?;
+        let instance = AnalyzerPlugin::instantiate(&mut store, &plugin.component, &self.linker()?).map_err(plugin_err)?;
+        let analysis = instance.hybrid_llm_plugin_analyzer().call_analyze(&mut store, command).map_err(plugin_err)?;
+
+        let risk_level = match analysis.risk {
+            PluginRiskLevel::Low => RiskLevel::Low,
+            PluginRiskLevel::Medium => RiskLevel::Medium,
+            PluginRiskLevel::High => RiskLevel::High,
+            PluginRiskLevel::Critical => RiskLevel::Critical,
+        };
+        Ok(SecurityAnalysis {
+            safe: risk_level as u8 <= RiskLevel::Medium as u8,
+            risk_level,
+            issues: analysis.issues,
+            suggestions: analysis.suggestions,
+        })
+    }
+}
+
+/// Third-party tools, document parsers and command analyzers, run as WASM components
+/// Plugins are found in the plugins directory at startup; each call gets a fresh instance with fuel, memory
+/// and time limits, and reaches the host only through the `host` interface of `wit/plugin.wit`
+pub struct PluginHost {
+    runtime: Runtime,
+    plugins: Vec<Arc<Plugin>>,
+    failures: Vec<PluginFailure>,
+}
+
+impl PluginHost {
+    /// Load every plugin in `config.plugins_dir`; plugins that fail to load are reported and left out
+    /// Compiles the components, so this blocks for a while
+    pub fn discover(config: PluginConfig) -> Result<Self> {
+        let runtime = Runtime {
+            engine: metered_engine()?,
+            fuel: config.fuel,
+            max_memory_bytes: config.max_memory_bytes,
+            timeout: config.timeout,
+        };
+        let mut host = Self { runtime, plugins: Vec::new(), failures: Vec::new() };
+
+        let entries = match std::fs::read_dir(&config.plugins_dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                debug!("🧩 No plugins directory at {:?}", config.plugins_dir);
+                return Ok(host);
+            }
+            Err(e) => return Err(HybridLLMError::FileSystemError(format!("{}: {}", config.plugins_dir.display(), e))),
+        };
+        let mut directories: Vec<PathBuf> = entries
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.path())
+            .filter(|path| path.join(PLUGIN_MANIFEST).is_file())
+            .collect();
+        directories.sort();
+
+        for directory in directories {
+            match host.load(&config, &directory) {
+                Ok(plugin) => {
+                    info!("🧩 Loaded {:?} plugin {} {}", plugin.info.manifest.kind, plugin.info.manifest.name, plugin.info.manifest.version);
+                    host.plugins.push(Arc::new(plugin));
+                }
+                Err(e) => {
+                    warn!("⚠️  Skipping plugin {:?}: {}", directory, e);
+                    host.failures.push(PluginFailure { directory, error: e.to_string() });
+                }
+            }
+        }
+        Ok(host)
+    }
+
+    fn load(&self, config: &PluginConfig, directory: &Path) -> Result<Plugin> {
+        let manifest = read_manifest(&directory.join(PLUGIN_MANIFEST))?;
+        if self.plugins.iter().any(|plugin| plugin.info.manifest.name == manifest.name) {
+            return Err(HybridLLMError::InvalidRequest(format!("Another plugin is already named {}", manifest.name)));
+        }
+        let read_folders = manifest
+            .capabilities
+            .read_folders
+            .iter()
+            .map(|folder| match config.folders.get(folder) {
+                Some(root) => Ok((folder.clone(), root.clone())),
+                None => Err(HybridLLMError::InvalidRequest(format!("Unknown folder {:?} in capabilities.read_folders", folder))),
+            })
+            .collect::<Result<HashMap<_, _>>>()?;
+
+        let component_path = resolve_in(directory, &manifest.component.to_string_lossy())?;
+        let component = Component::from_file(&self.runtime.engine, &component_path)
+            .map_err(|e| HybridLLMError::SandboxError(format!("{}: {}", component_path.display(), e)))?;
+
+        let info = PluginInfo { manifest, directory: directory.to_path_buf(), tools: Vec::new(), extensions: Vec::new() };
+        let mut plugin = Plugin { info, component, read_folders };
+        match plugin.info.manifest.kind {
+            PluginKind::Tool => {
+                let tools = self.runtime.describe_tools(&plugin)?;
+                if let Some(tool) = tools.iter().find(|tool| !is_tool_name(&tool.name)) {
+                    return Err(HybridLLMError::InvalidRequest(format!(
+                        "Tool name {:?} must be lowercase letters, digits and underscores",
+                        tool.name
+                    )));
+                }
+                let taken = self.plugins.iter().flat_map(|other| &other.info.tools).find(|other| tools.iter().any(|tool| tool.name == other.name));
+                if let Some(tool) = taken {
+                    return Err(HybridLLMError::InvalidRequest(format!("Another plugin already provides tool {}", tool.name)));
+                }
+                plugin.info.tools = tools;
+            }
+            PluginKind::Parser => plugin.info.extensions = self.runtime.extensions(&plugin)?,
+            PluginKind::Analyzer => {}
+        }
+        Ok(plugin)
+    }
+
+    /// Every loaded plugin, by directory name
+    pub fn plugins(&self) -> Vec<PluginInfo> {
+        self.plugins.iter().map(|plugin| plugin.info.clone()).collect()
+    }
+
+    /// Plugin directories that could not be loaded, and why
+    pub fn failures(&self) -> &[PluginFailure] {
+        &self.failures
+    }
+
+    /// The tools of every tool plugin, to register alongside the built-in ones
+    pub fn tools(&self) -> Vec<Arc<dyn Tool>> {
+        self.plugins
+            .iter()
+            .flat_map(|plugin| {
+                plugin.info.tools.iter().map(|tool| {
+                    Arc::new(PluginTool { runtime: self.runtime.clone(), plugin: Arc::clone(plugin), tool: tool.clone() }) as Arc<dyn Tool>
+                })
+            })
+            .collect()
+    }
+
+    /// The analyzer plugins, to check commands alongside the guardrails
+    pub fn analyzers(&self) -> Vec<Arc<dyn CommandAnalyzer>> {
+        self.plugins
+            .iter()
+            .filter(|plugin| plugin.info.manifest.kind == PluginKind::Analyzer)
+            .map(|plugin| Arc::new(PluginAnalyzer { runtime: self.runtime.clone(), plugin: Arc::clone(plugin) }) as Arc<dyn CommandAnalyzer>)
+            .collect()
+    }
+
+    /// Whether a parser plugin handles files named like `filename`
+    pub fn can_parse(&self, filename: &str) -> bool {
+        self.parser_for(filename).is_some()
+    }
+
+    /// Text of a document through the first parser plugin handling its extension
+    pub async fn parse(&self, filename: &str, content: Vec<u8>) -> Result<String> {
+        let plugin = self
+            .parser_for(filename)
+            .ok_or_else(|| HybridLLMError::InvalidRequest(format!("No parser plugin handles {}", filename)))?;
+        let (runtime, filename) = (self.runtime.clone(), filename.to_string());
+        tokio::task::spawn_blocking(move || runtime.parse(&plugin, &filename, &content))
+            .await
+            .map_err(|e| HybridLLMError::SandboxError(e.to_string()))?
+    }
+
+    fn parser_for(&self, filename: &str) -> Option<Arc<Plugin>> {
+        let extension = Path::new(filename).extension()?.to_string_lossy().to_lowercase();
+        self.plugins
+            .iter()
+            .find(|plugin| plugin.info.extensions.contains(&extension))
+            .cloned()
+    }
+}
+
+/// A tool provided by a plugin
+struct PluginTool {
+    runtime: Runtime,
+    plugin: Arc<Plugin>,
+    tool: PluginToolInfo,
+}
+
+#[async_trait]
+impl Tool for PluginTool {
+    fn name(&self) -> &str {
+        &self.tool.name
+    }
+
+    fn description(&self) -> &str {
+        &self.tool.description
+    }
+
+    fn parameters(&self) -> serde_json::Value {
+        self.tool.parameters.clone()
+    }
+
+    fn read_only(&self) -> bool {
+        self.tool.read_only
+    }
+
+    async fn execute(&self, _context: &RequestContext, arguments: serde_json::Value) -> Result<serde_json::Value> {
+        let (runtime, plugin, name) = (self.runtime.clone(), Arc::clone(&self.plugin), self.tool.name.clone());
+        tokio::task::spawn_blocking(move || runtime.call_tool(&plugin, &name, &arguments))
+            .await
+            .map_err(|e| HybridLLMError::SandboxError(e.to_string()))?
+    }
+}
+
+/// An analyzer plugin checking commands
+struct PluginAnalyzer {
+    runtime: Runtime,
+    plugin: Arc<Plugin>,
+}
+
+#[async_trait]
+impl CommandAnalyzer for PluginAnalyzer {
+    fn name(&self) -> &str {
+        &self.plugin.info.manifest.name
+    }
+
+    async fn analyze(&self, command: &str) -> Result<SecurityAnalysis> {
+        let (runtime, plugin, command) = (self.runtime.clone(), Arc::clone(&self.plugin), command.to_string());
+        tokio::task::spawn_blocking(move || runtime.analyze(&plugin, &command))
+            .await
+            .map_err(|e| HybridLLMError::SandboxError(e.to_string()))?
+    }
+}
+
+fn read_manifest(path: &Path) -> Result<PluginManifest> {
+    let text = std::fs::read_to_string(path).map_err(|e| HybridLLMError::FileSystemError(format!("{}: {}", path.display(), e)))?;
+    let manifest: PluginManifest = toml::from_str(&text)
+        .map_err(|e| HybridLLMError::ConfigError(format!("{}: {}", path.display(), e)))?;
+
+    let valid_name = !manifest.name.is_empty()
+        && manifest.name.len() <= 64
+        && manifest.name.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-');
+    if !valid_name {
+        return Err(HybridLLMError::ConfigError(format!(
+            "{}: name {:?} must be 1 to 64 lowercase letters, digits and dashes",
+            path.display(),
+            manifest.name
+        )));
+    }
+    Ok(manifest)
+}
+
+fn is_tool_name(name: &str) -> bool {
+    !name.is_empty() && name.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_')
+}
+
+/// `relative` inside `root`, refusing absolute paths, `..` and symlinks leading out
+fn resolve_in(root: &Path, relative: &str) -> Result<PathBuf> {
+    let escapes = || HybridLLMError::SecurityViolation(format!("{:?} is outside {}", relative, root.display()));
+    let relative = Path::new(relative);
+    if relative.as_os_str().is_empty() || !relative.components().all(|part| matches!(part, PathComponent::Normal(_))) {
+        return Err(escapes());
+    }
+
+    let io_err = |e: std::io::Error| HybridLLMError::FileSystemError(format!("{}: {}", relative.display(), e));
+    let root = root.canonicalize().map_err(io_err)?;
+    let path = root.join(relative).canonicalize().map_err(io_err)?;
+    if !path.starts_with(&root) {
+        return Err(escapes());
+    }
+    Ok(path)
+}
+
+fn plugin_err(e: wasmtime::Error) -> HybridLLMError {
+    HybridLLMError::SandboxError(format!("Plugin failed: {:#}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write_plugin(plugins_dir: &Path, directory: &str, manifest: &str, component: &[u8]) {
+        let directory = plugins_dir.join(directory);
+        std::fs::create_dir_all(&directory).unwrap();
+        std::fs::write(directory.join(PLUGIN_MANIFEST), manifest).unwrap();
+        std::fs::write(directory.join("plugin.wasm"), component).unwrap();
+    }
+
+    #[test]
+    fn test_discover_reports_broken_plugins() {
+        let base = std::env::temp_dir().join(format!("plugins-{}", uuid::Uuid::new_v4()));
+        let uploads = base.join("uploads");
+        std::fs::create_dir_all(&uploads).unwrap();
+
+        // A core module rather than a component
+        let core_module = b"\0asm\x01\0\0\0";
+        write_plugin(&base, "core", "name = \"core\"\nversion = \"1.0.0\"\nkind = \"tool\"\ncomponent = \"plugin.wasm\"\n", core_module);
+        write_plugin(
+            &base,
+            "greedy",
+            "name = \"greedy\"\nversion = \"1.0.0\"\nkind = \"parser\"\ncomponent = \"plugin.wasm\"\n\n[capabilities]\nread_folders = [\"home\"]\n",
+            core_module,
+        );
+        write_plugin(&base, "escape", "name = \"escape\"\nversion = \"1.0.0\"\nkind = \"analyzer\"\ncomponent = \"../outside.wasm\"\n", core_module);
+        write_plugin(&base, "caps", "name = \"Caps\"\nversion = \"1.0.0\"\nkind = \"analyzer\"\ncomponent = \"plugin.wasm\"\n", core_module);
+
+        let config = PluginConfig {
+            plugins_dir: base.clone(),
+            folders: HashMap::from([("uploads".to_string(), uploads)]),
+            ..Default::default()
+        };
+        let host = PluginHost::discover(config).unwrap();
+        assert!(host.plugins().is_empty());
+        let errors: HashMap<String, String> = host
+            .failures()
+            .iter()
+            .map(|failure| (failure.directory.file_name().unwrap().to_string_lossy().into_owned(), failure.error.clone()))
+            .collect();
+        assert_eq!(errors.len(), 4);
+        assert!(errors["greedy"].contains("Unknown folder \"home\""));
+        assert!(errors["escape"].contains("outside"));
+        assert!(errors["caps"].contains("lowercase"));
+        assert!(host.tools().is_empty() && !host.can_parse("report.docx"));
+
+        // No plugins directory is no plugins
+        let empty = PluginHost::discover(PluginConfig { plugins_dir: base.join("missing"), ..Default::default() }).unwrap();
+        assert!(empty.plugins().is_empty() && empty.failures().is_empty());
+        std::fs::remove_dir_all(base).unwrap();
+    }
+
+    #[test]
+    fn test_resolve_in() {
+        let root = std::env::temp_dir().join(format!("plugin-folder-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(root.join("papers")).unwrap();
+        std::fs::write(root.join("papers/notes.txt"), "notes").unwrap();
+
+        assert_eq!(resolve_in(&root, "papers/notes.txt").unwrap(), root.canonicalize().unwrap().join("papers/notes.txt"));
+        assert!(resolve_in(&root, "../etc/passwd").is_err());
+        assert!(resolve_in(&root, "/etc/passwd").is_err());
+        assert!(resolve_in(&root, "").is_err());
+        #[cfg(unix)]
+        {
+            std::os::unix::fs::symlink("/etc", root.join("papers/etc")).unwrap();
+            assert!(resolve_in(&root, "papers/etc/hostname").is_err());
+        }
+        std::fs::remove_dir_all(root).unwrap();
+    }
+}
@@ -0,0 +1,248 @@
+use common::{
+    errors::{Result, HybridLLMError},
+    types::{NetworkMode, SandboxConfig, SandboxTemplate},
+};
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::{Notify, OwnedSemaphorePermit};
+use tokio::task::JoinHandle;
+use tracing::{debug, info, warn};
+use uuid::Uuid;
+
+use crate::{SandboxEvent, SandboxManager};
+
+/// Checked-out pool sandboxes are reclaimed after this long without a command
+const CHECKED_OUT_IDLE_TIMEOUT_SECS: u64 = 600;
+
+/// How many sandboxes to keep booted ahead of demand
+#[derive(Debug, Clone)]
+pub struct PoolConfig {
+    /// Warm sandboxes kept ready per template
+    pub warm_per_template: usize,
+    /// Templates to keep warm; `None` is an empty sandbox
+    pub templates: Vec<Option<SandboxTemplate>>,
+    /// Top up the pool at least this often, even without checkouts
+    pub refill_interval: Duration,
+}
+
+impl Default for PoolConfig {
+    fn default() -> Self {
+        Self {
+            warm_per_template: 2,
+            templates: vec![None],
+            refill_interval: Duration::from_secs(10),
+        }
+    }
+}
+
+/// Booted sandboxes waiting to be handed out
+#[derive(Default)]
+pub(crate) struct WarmPool {
+    config: Mutex<Option<PoolConfig>>,
+    ready: Mutex<HashMap<Option<SandboxTemplate>, Vec<Uuid>>>,
+    refill: Arc<Notify>,
+}
+
+impl WarmPool {
+    fn take(&self, template: Option<SandboxTemplate>) -> Option<Uuid> {
+        self.ready.lock().unwrap().get_mut(&template)?.pop()
+    }
+
+    /// Give up any warm sandbox, e.g. to free its slot for a real request
+    fn evict(&self) -> Option<Uuid> {
+        self.ready
+            .lock()
+            .unwrap()
+            .values_mut()
+            .max_by_key(|ids| ids.len())?
+            .pop()
+    }
+
+    /// Forget a sandbox that was destroyed while waiting in the pool
+    pub(crate) fn remove(&self, sandbox_id: Uuid) {
+        for ids in self.ready.lock().unwrap().values_mut() {
+            ids.retain(|id| *id != sandbox_id);
+        }
+    }
+
+    /// Sandboxes still waiting in the pool; these are never idle-reaped
+    pub(crate) fn waiting(&self) -> HashSet<Uuid> {
+        self.ready.lock().unwrap().values().flatten().copied().collect()
+    }
+
+    fn len(&self, template: Option<SandboxTemplate>) -> usize {
+        self.ready.lock().unwrap().get(&template).map_or(0, Vec::len)
+    }
+}
+
+/// Configuration pool sandboxes are booted with
+fn pooled_config(template: Option<SandboxTemplate>) -> SandboxConfig {
+    SandboxConfig {
+        id: Uuid::new_v4(),
+        network_mode: NetworkMode::None,
+        cpu_limit: 50.0,
+        memory_limit_gb: 1.0,
+        disk_limit_gb: 1.0,
+        allowed_commands: vec![],
+        template,
+        max_lifetime_secs: None,
+        idle_timeout_secs: Some(CHECKED_OUT_IDLE_TIMEOUT_SECS),
+        execution_timeout_secs: None,
+        llm_id: None,
+        gpu: None,
+        volumes: vec![],
+    }
+}
+
+impl SandboxManager {
+    /// Start keeping prewarmed sandboxes ready for `checkout`
+    /// The pool refills in the background and never exceeds the sandbox cap
+    pub fn spawn_pool(self: &Arc<Self>, config: PoolConfig) -> JoinHandle<()> {
+        info!(
+            "🔥 Prewarming {} sandboxes for each of {:?}",
+            config.warm_per_template, config.templates
+        );
+
+        let interval = config.refill_interval;
+        let refill = Arc::clone(&self.pool.refill);
+        *self.pool.config.lock().unwrap() = Some(config);
+        let manager = Arc::downgrade(self);
+
+        tokio::spawn(async move {
+            loop {
+                let Some(manager) = manager.upgrade() else {
+                    return;
+                };
+                manager.refill_pool().await;
+                drop(manager);
+
+                tokio::select! {
+                    _ = refill.notified() => {}
+                    _ = tokio::time::sleep(interval) => {}
+                }
+            }
+        })
+    }
+
+    /// Get a ready-to-use sandbox, from the warm pool when one is available
+    /// Falls back to booting a new sandbox when the pool is empty
+    pub async fn checkout(&self, template: Option<SandboxTemplate>, llm_id: Option<String>) -> Result<Uuid> {
+        let warm = self.pool.take(template);
+        self.pool.refill.notify_one();
+
+        if let Some(sandbox_id) = warm {
+            if let Some(sandbox) = self.sandboxes.write().await.get_mut(&sandbox_id) {
+                // Lifetime and idle timeouts run from checkout, not from boot
+                sandbox.created_at = Instant::now();
+                sandbox.confinement.mark_active();
+                sandbox.config.llm_id = llm_id.clone();
+                debug!("🔥 Checked out warm sandbox {}", sandbox_id);
+
+                let _ = self.events.send(SandboxEvent::Created { sandbox_id, llm_id, template });
+                return Ok(sandbox_id);
+            }
+        }
+
+        debug!("🥶 Sandbox pool empty for {:?}, booting on demand", template);
+        self.create_sandbox(SandboxConfig { llm_id, ..pooled_config(template) }).await
+    }
+
+    /// Boot sandboxes until every pooled template has its warm quota
+    async fn refill_pool(&self) {
+        let Some(config) = self.pool.config.lock().unwrap().clone() else {
+            return;
+        };
+        let available = self.templates.available();
+
+        for template in config.templates {
+            if template.is_some_and(|t| !available.contains(&t)) {
+                continue;
+            }
+
+            while self.pool.len(template) < config.warm_per_template {
+                // Only use free slots; warm sandboxes never displace real ones
+                let Ok(slot) = Arc::clone(&self.slots).try_acquire_owned() else {
+                    return;
+                };
+
+                match self.create_with_slot(pooled_config(template), slot).await {
+                    Ok(sandbox_id) => {
+                        self.pool.ready.lock().unwrap().entry(template).or_default().push(sandbox_id);
+                    }
+                    Err(e) => {
+                        warn!("⚠️  Failed to prewarm {:?} sandbox: {}", template, e);
+                        break;
+                    }
+                }
+            }
+        }
+    }
+
+    /// Reserve a sandbox slot, evicting a warm sandbox when at capacity
+    /// Queues behind earlier requests when no warm sandbox can make room
+    pub(crate) async fn admit(&self, sandbox_id: Uuid, llm_id: Option<String>) -> Result<OwnedSemaphorePermit> {
+        if let Ok(slot) = Arc::clone(&self.slots).try_acquire_owned() {
+            return Ok(slot);
+        }
+
+        if let Some(warm) = self.pool.evict() {
+            debug!("🔥 Evicting warm sandbox {} to make room", warm);
+            self.destroy_sandbox(warm).await?;
+            if let Ok(slot) = Arc::clone(&self.slots).try_acquire_owned() {
+                return Ok(slot);
+            }
+        }
+
+        let _ticket = self.queue.join(sandbox_id, llm_id, self.max_queued, &self.events)?;
+        Arc::clone(&self.slots)
+            .acquire_owned()
+            .await
+            .map_err(|e| HybridLLMError::SandboxError(e.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_checkout_from_warm_pool() {
+        let base = std::env::temp_dir().join(format!("sandboxes-{}", Uuid::new_v4()));
+        let manager = Arc::new(
+            SandboxManager::new(base.clone())
+                .unwrap()
+                .with_max_sandboxes(2)
+                .with_max_queued(0),
+        );
+        manager.spawn_pool(PoolConfig {
+            warm_per_template: 1,
+            ..Default::default()
+        });
+
+        while manager.pool.len(None) == 0 {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        let warm = manager.pool.waiting();
+
+        let started = Instant::now();
+        let id = manager.checkout(None, None).await.unwrap();
+        assert!(warm.contains(&id));
+        assert!(started.elapsed() < Duration::from_millis(100));
+        assert_eq!(manager.execute(id, "echo warm", None).await.unwrap().stdout, "warm\n");
+
+        // The pool refills into the remaining slot, then yields it to a real request
+        while manager.pool.len(None) == 0 {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        manager.create_sandbox(pooled_config(None)).await.unwrap();
+        assert!(manager.pool.waiting().is_empty());
+
+        assert!(matches!(
+            manager.create_sandbox(pooled_config(None)).await,
+            Err(HybridLLMError::ResourceLimitExceeded { .. })
+        ));
+
+        let _ = std::fs::remove_dir_all(base);
+    }
+}
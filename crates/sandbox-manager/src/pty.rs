@@ -0,0 +1,272 @@
+use common::errors::{Result, HybridLLMError};
+use serde::{Deserialize, Serialize};
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+use std::path::Path;
+use std::process::Stdio;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::process::Command;
+use tokio::sync::mpsc;
+use tracing::debug;
+use uuid::Uuid;
+
+use crate::execution::DEFAULT_PATH;
+use crate::limits::Confinement;
+
+/// Bytes of input and of output kept in a session recording
+const MAX_RECORDING: usize = 256 * 1024;
+
+/// Output from an interactive terminal session
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum PtyEvent {
+    Output { data: String },
+    Exited { exit_code: i32 },
+}
+
+/// Transcript of a terminal session for the audit trail
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct PtyRecording {
+    pub session_id: Uuid,
+    pub sandbox_id: Uuid,
+    pub duration_ms: u64,
+    pub input: String,
+    pub output: String,
+    /// Set when input or output exceeded the recording cap
+    pub truncated: bool,
+}
+
+impl PtyRecording {
+    fn record(buffer: &mut String, truncated: &mut bool, data: &str) {
+        if buffer.len() + data.len() <= MAX_RECORDING {
+            buffer.push_str(data);
+        } else {
+            *truncated = true;
+        }
+    }
+}
+
+/// Interactive shell attached to a pseudo-terminal inside a sandbox
+pub struct PtySession {
+    id: Uuid,
+    sandbox_id: Uuid,
+    pid: Option<u32>,
+    master: OwnedFd,
+    writer: tokio::fs::File,
+    recording: Arc<Mutex<PtyRecording>>,
+    started: Instant,
+}
+
+impl PtySession {
+    /// Start a shell in `working_dir` on a new pseudo-terminal
+    pub(crate) fn open(
+        sandbox_id: Uuid,
+        working_dir: &Path,
+        environment: &[(String, String)],
+        confinement: Arc<Confinement>,
+        cols: u16,
+        rows: u16,
+    ) -> Result<(Self, mpsc::Receiver<PtyEvent>)> {
+        let (master, slave) = open_pty(cols, rows)?;
+        let io_err = |e: std::io::Error| HybridLLMError::SandboxError(format!("PTY error: {}", e));
+
+        let shell = if Path::new("/bin/bash").exists() { "/bin/bash" } else { "/bin/sh" };
+        let mut command = Command::new(shell);
+        command
+            .current_dir(working_dir)
+            .env_clear()
+            .env("PATH", DEFAULT_PATH)
+            .env("HOME", working_dir)
+            .env("TERM", "xterm-256color")
+            .envs(environment.iter().map(|(key, value)| (key, value)))
+            .stdin(Stdio::from(slave.try_clone().map_err(io_err)?))
+            .stdout(Stdio::from(slave.try_clone().map_err(io_err)?))
+            .stderr(Stdio::from(slave))
+            .kill_on_drop(true);
+
+        // SAFETY: only async-signal-safe libc calls run between fork and exec
+        unsafe {
+            command.pre_exec(|| {
+                // Attached before confinement forks the shell into the sandbox, so the shell
+                // inherits the session and its controlling terminal
+                if libc::getsid(0) != libc::getpid() && libc::setsid() < 0 {
+                    return Err(std::io::Error::last_os_error());
+                }
+                if libc::ioctl(0, libc::TIOCSCTTY, 0) != 0 {
+                    return Err(std::io::Error::last_os_error());
+                }
+                Ok(())
+            });
+        }
+        confinement.apply(&mut command);
+
+        let mut child = command
+            .spawn()
+            .map_err(|e| HybridLLMError::SandboxError(format!("Failed to start shell: {}", e)))?;
+        // Release our copies of the slave so reads see EOF once the shell exits
+        drop(command);
+
+        let pid = child.id();
+        if let Some(pid) = pid {
+            confinement.track(pid);
+        }
+
+        let id = Uuid::new_v4();
+        let recording = Arc::new(Mutex::new(PtyRecording {
+            session_id: id,
+            sandbox_id,
+            ..Default::default()
+        }));
+
+        let reader = tokio::fs::File::from_std(std::fs::File::from(master.try_clone().map_err(io_err)?));
+        let writer = tokio::fs::File::from_std(std::fs::File::from(master.try_clone().map_err(io_err)?));
+
+        let (tx, rx) = mpsc::channel(256);
+        {
+            let recording = Arc::clone(&recording);
+            tokio::spawn(async move {
+                forward_output(reader, &tx, &recording).await;
+
+                let exit_code = match child.wait().await {
+                    Ok(status) => status.code().unwrap_or(-1),
+                    Err(_) => -1,
+                };
+                if let Some(pid) = pid {
+                    confinement.untrack(pid);
+                }
+
+                debug!("🖥️  PTY session {} exited with {}", id, exit_code);
+                let _ = tx.send(PtyEvent::Exited { exit_code }).await;
+            });
+        }
+
+        Ok((
+            Self {
+                id,
+                sandbox_id,
+                pid,
+                master,
+                writer,
+                recording,
+                started: Instant::now(),
+            },
+            rx,
+        ))
+    }
+
+    pub fn id(&self) -> Uuid {
+        self.id
+    }
+
+    pub fn sandbox_id(&self) -> Uuid {
+        self.sandbox_id
+    }
+
+    /// Send keystrokes to the shell
+    pub async fn write(&mut self, data: &str) -> Result<()> {
+        {
+            let mut recording = self.recording.lock().unwrap();
+            let PtyRecording { input, truncated, .. } = &mut *recording;
+            PtyRecording::record(input, truncated, data);
+        }
+
+        self.writer
+            .write_all(data.as_bytes())
+            .await
+            .map_err(|e| HybridLLMError::SandboxError(format!("PTY write failed: {}", e)))?;
+        self.writer
+            .flush()
+            .await
+            .map_err(|e| HybridLLMError::SandboxError(format!("PTY write failed: {}", e)))
+    }
+
+    /// Change the terminal size
+    pub fn resize(&self, cols: u16, rows: u16) -> Result<()> {
+        let size = libc::winsize { ws_row: rows, ws_col: cols, ws_xpixel: 0, ws_ypixel: 0 };
+        // SAFETY: TIOCSWINSZ reads a winsize from a valid pointer on our own fd
+        if unsafe { libc::ioctl(self.master.as_raw_fd(), libc::TIOCSWINSZ, &size) } != 0 {
+            return Err(HybridLLMError::SandboxError(format!(
+                "PTY resize failed: {}",
+                std::io::Error::last_os_error()
+            )));
+        }
+        Ok(())
+    }
+
+    /// Hang up the shell and return the session transcript
+    pub fn close(self) -> PtyRecording {
+        if let Some(pid) = self.pid {
+            // SAFETY: plain syscall on the session's own process group
+            unsafe {
+                libc::kill(-(pid as i32), libc::SIGHUP);
+            }
+        }
+
+        let mut recording = self.recording.lock().unwrap().clone();
+        recording.duration_ms = self.started.elapsed().as_millis() as u64;
+        recording
+    }
+}
+
+/// Read terminal output until the slave side closes, keeping UTF-8 sequences intact
+async fn forward_output(
+    mut reader: tokio::fs::File,
+    tx: &mpsc::Sender<PtyEvent>,
+    recording: &Mutex<PtyRecording>,
+) {
+    let mut buf = [0u8; 4096];
+    let mut pending = Vec::new();
+
+    // EIO signals that every slave descriptor has been closed
+    while let Ok(n) = reader.read(&mut buf).await {
+        if n == 0 {
+            break;
+        }
+        pending.extend_from_slice(&buf[..n]);
+
+        let valid = match std::str::from_utf8(&pending) {
+            Ok(_) => pending.len(),
+            Err(e) if e.error_len().is_none() => e.valid_up_to(),
+            // Invalid bytes rather than a split sequence: pass through lossily
+            Err(_) => pending.len(),
+        };
+        let data = String::from_utf8_lossy(&pending[..valid]).into_owned();
+        pending.drain(..valid);
+
+        if data.is_empty() {
+            continue;
+        }
+
+        {
+            let mut recording = recording.lock().unwrap();
+            let PtyRecording { output, truncated, .. } = &mut *recording;
+            PtyRecording::record(output, truncated, &data);
+        }
+
+        if tx.send(PtyEvent::Output { data }).await.is_err() {
+            break;
+        }
+    }
+}
+
+/// Allocate a pseudo-terminal pair, both ends close-on-exec
+fn open_pty(cols: u16, rows: u16) -> Result<(OwnedFd, OwnedFd)> {
+    let mut master: libc::c_int = -1;
+    let mut slave: libc::c_int = -1;
+    let size = libc::winsize { ws_row: rows, ws_col: cols, ws_xpixel: 0, ws_ypixel: 0 };
+
+    // SAFETY: out-pointers are valid and the returned fds are immediately owned
+    unsafe {
+        if libc::openpty(&mut master, &mut slave, std::ptr::null_mut(), std::ptr::null(), &size) != 0 {
+            return Err(HybridLLMError::SandboxError(format!(
+                "Failed to allocate PTY: {}",
+                std::io::Error::last_os_error()
+            )));
+        }
+        let (master, slave) = (OwnedFd::from_raw_fd(master), OwnedFd::from_raw_fd(slave));
+        libc::fcntl(master.as_raw_fd(), libc::F_SETFD, libc::FD_CLOEXEC);
+        libc::fcntl(slave.as_raw_fd(), libc::F_SETFD, libc::FD_CLOEXEC);
+        Ok((master, slave))
+    }
+}
@@ -0,0 +1,177 @@
+use common::errors::{Result, HybridLLMError};
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use tokio::sync::broadcast;
+use tracing::debug;
+use uuid::Uuid;
+
+use crate::SandboxEvent;
+
+/// A sandbox creation waiting for a free slot
+struct QueuedRequest {
+    sandbox_id: Uuid,
+    llm_id: Option<String>,
+}
+
+/// Sandbox creations waiting for the cap, in the order they will be admitted
+/// Slots are handed out first-come first-served by the semaphore; this tracks
+/// the same order so requesters can be told where they stand
+#[derive(Default)]
+pub(crate) struct AdmissionQueue {
+    waiting: Mutex<VecDeque<QueuedRequest>>,
+}
+
+impl AdmissionQueue {
+    /// Join the back of the queue, announcing the position
+    /// Fails with `ResourceLimitExceeded` when `max_queued` requests are already waiting
+    pub(crate) fn join<'a>(
+        &'a self,
+        sandbox_id: Uuid,
+        llm_id: Option<String>,
+        max_queued: usize,
+        events: &'a broadcast::Sender<SandboxEvent>,
+    ) -> Result<QueueTicket<'a>> {
+        let mut waiting = self.waiting.lock().unwrap();
+        if waiting.len() >= max_queued {
+            return Err(HybridLLMError::ResourceLimitExceeded {
+                resource: "sandbox_queue".to_string(),
+                limit: max_queued as f32,
+                actual: waiting.len() as f32,
+            });
+        }
+
+        waiting.push_back(QueuedRequest { sandbox_id, llm_id: llm_id.clone() });
+        let position = waiting.len();
+        debug!("⏳ Sandbox {} queued at position {}", sandbox_id, position);
+        let _ = events.send(SandboxEvent::Queued { sandbox_id, llm_id, position });
+
+        Ok(QueueTicket { queue: self, sandbox_id, events })
+    }
+
+    /// 1-based position of a queued sandbox, `None` once it has been admitted
+    pub(crate) fn position(&self, sandbox_id: Uuid) -> Option<usize> {
+        self.waiting
+            .lock()
+            .unwrap()
+            .iter()
+            .position(|request| request.sandbox_id == sandbox_id)
+            .map(|index| index + 1)
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.waiting.lock().unwrap().len()
+    }
+
+    /// Leave the queue and tell everyone behind that they moved up
+    fn leave(&self, sandbox_id: Uuid, events: &broadcast::Sender<SandboxEvent>) {
+        let mut waiting = self.waiting.lock().unwrap();
+        let Some(index) = waiting.iter().position(|request| request.sandbox_id == sandbox_id) else {
+            return;
+        };
+        waiting.remove(index);
+
+        for (offset, request) in waiting.iter().enumerate().skip(index) {
+            let _ = events.send(SandboxEvent::Queued {
+                sandbox_id: request.sandbox_id,
+                llm_id: request.llm_id.clone(),
+                position: offset + 1,
+            });
+        }
+    }
+}
+
+/// A place in the admission queue, given up on drop
+/// Dropping it covers both admission and a requester that stopped waiting
+pub(crate) struct QueueTicket<'a> {
+    queue: &'a AdmissionQueue,
+    sandbox_id: Uuid,
+    events: &'a broadcast::Sender<SandboxEvent>,
+}
+
+impl Drop for QueueTicket<'_> {
+    fn drop(&mut self) {
+        self.queue.leave(self.sandbox_id, self.events);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::SandboxManager;
+    use common::types::{NetworkMode, SandboxConfig};
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    fn test_config() -> SandboxConfig {
+        SandboxConfig {
+            id: Uuid::new_v4(),
+            network_mode: NetworkMode::None,
+            cpu_limit: 50.0,
+            memory_limit_gb: 1.0,
+            disk_limit_gb: 1.0,
+            allowed_commands: vec![],
+            template: None,
+            max_lifetime_secs: None,
+            idle_timeout_secs: None,
+            execution_timeout_secs: None,
+            llm_id: None,
+            gpu: None,
+            volumes: vec![],
+        }
+    }
+
+    #[tokio::test]
+    async fn test_requests_queue_for_free_slot() {
+        let base = std::env::temp_dir().join(format!("sandboxes-{}", Uuid::new_v4()));
+        let manager = Arc::new(
+            SandboxManager::new(base.clone())
+                .unwrap()
+                .with_max_sandboxes(1)
+                .with_max_queued(2),
+        );
+        let mut events = manager.subscribe();
+        let running = manager.create_sandbox(test_config()).await.unwrap();
+
+        let (first, second) = (test_config(), test_config());
+        let (first_id, second_id) = (first.id, second.id);
+        let first = tokio::spawn({
+            let manager = Arc::clone(&manager);
+            async move { manager.create_sandbox(first).await }
+        });
+        while manager.queue_position(first_id).is_none() {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        let second = tokio::spawn({
+            let manager = Arc::clone(&manager);
+            async move { manager.create_sandbox(second).await }
+        });
+        while manager.queue_position(second_id).is_none() {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        assert_eq!(manager.queue_position(second_id), Some(2));
+
+        // The queue is full, so further requests are refused outright
+        assert!(matches!(
+            manager.create_sandbox(test_config()).await,
+            Err(HybridLLMError::ResourceLimitExceeded { .. })
+        ));
+
+        manager.destroy_sandbox(running).await.unwrap();
+        assert_eq!(first.await.unwrap().unwrap(), first_id);
+        assert_eq!(manager.queue_position(second_id), Some(1));
+
+        manager.destroy_sandbox(first_id).await.unwrap();
+        assert_eq!(second.await.unwrap().unwrap(), second_id);
+        assert_eq!(manager.queued(), 0);
+
+        let mut positions = Vec::new();
+        while let Ok(event) = events.try_recv() {
+            if let SandboxEvent::Queued { sandbox_id, position, .. } = event {
+                positions.push((sandbox_id, position));
+            }
+        }
+        assert_eq!(positions, vec![(first_id, 1), (second_id, 2), (second_id, 1)]);
+
+        let _ = std::fs::remove_dir_all(base);
+    }
+}
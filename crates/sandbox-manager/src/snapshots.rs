@@ -0,0 +1,192 @@
+use chrono::{DateTime, Utc};
+use common::{
+    errors::{Result, HybridLLMError},
+    types::SandboxConfig,
+};
+use flate2::{read::GzDecoder, write::GzEncoder, Compression};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use tokio::sync::RwLock;
+use tracing::{info, warn};
+use uuid::Uuid;
+
+use crate::volumes::VOLUMES_DIR;
+
+/// Archive holding a snapshot's filesystem
+const ROOTFS_ARCHIVE: &str = "rootfs.tar.gz";
+
+/// Metadata file stored alongside each snapshot
+const METADATA_FILE: &str = "snapshot.json";
+
+/// A point-in-time copy of a sandbox
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SnapshotInfo {
+    pub id: Uuid,
+    /// Sandbox the snapshot was taken from
+    pub parent_sandbox: Uuid,
+    /// Size of the stored snapshot on disk
+    pub size_bytes: u64,
+    pub created_at: DateTime<Utc>,
+    /// Configuration restored sandboxes are created with
+    pub config: SandboxConfig,
+}
+
+/// On-disk registry of snapshots, one directory per snapshot
+pub(crate) struct SnapshotRegistry {
+    path: PathBuf,
+    snapshots: RwLock<HashMap<Uuid, SnapshotInfo>>,
+}
+
+impl SnapshotRegistry {
+    /// Open the registry, loading metadata for snapshots already on disk
+    pub(crate) fn open(path: PathBuf) -> Result<Self> {
+        std::fs::create_dir_all(&path)
+            .map_err(|e| HybridLLMError::FileSystemError(e.to_string()))?;
+
+        let mut snapshots = HashMap::new();
+        let entries = std::fs::read_dir(&path)
+            .map_err(|e| HybridLLMError::FileSystemError(e.to_string()))?;
+
+        for entry in entries.filter_map(|e| e.ok()) {
+            let metadata = entry.path().join(METADATA_FILE);
+            match std::fs::read(&metadata)
+                .ok()
+                .and_then(|bytes| serde_json::from_slice::<SnapshotInfo>(&bytes).ok())
+            {
+                Some(info) => {
+                    snapshots.insert(info.id, info);
+                }
+                None => warn!("⚠️  Skipping unreadable snapshot at {:?}", entry.path()),
+            }
+        }
+
+        info!("📸 Loaded {} sandbox snapshots", snapshots.len());
+
+        Ok(Self {
+            path,
+            snapshots: RwLock::new(snapshots),
+        })
+    }
+
+    /// Archive a sandbox root and record it
+    pub(crate) async fn create(&self, root: &Path, config: SandboxConfig) -> Result<SnapshotInfo> {
+        let id = Uuid::new_v4();
+        let dir = self.path.join(id.to_string());
+        let root = root.to_path_buf();
+        let parent_sandbox = config.id;
+
+        let info = tokio::task::spawn_blocking(move || {
+            std::fs::create_dir_all(&dir)
+                .map_err(|e| HybridLLMError::FileSystemError(e.to_string()))?;
+
+            let result = archive(&root, &dir.join(ROOTFS_ARCHIVE)).and_then(|size_bytes| {
+                let info = SnapshotInfo {
+                    id,
+                    parent_sandbox,
+                    size_bytes,
+                    created_at: Utc::now(),
+                    config,
+                };
+                let metadata = serde_json::to_vec_pretty(&info)
+                    .map_err(|e| HybridLLMError::Other(e.into()))?;
+                std::fs::write(dir.join(METADATA_FILE), metadata)
+                    .map_err(|e| HybridLLMError::FileSystemError(e.to_string()))?;
+                Ok(info)
+            });
+
+            if result.is_err() {
+                let _ = std::fs::remove_dir_all(&dir);
+            }
+            result
+        })
+        .await
+        .map_err(|e| HybridLLMError::SandboxError(e.to_string()))??;
+
+        self.snapshots.write().await.insert(id, info.clone());
+
+        Ok(info)
+    }
+
+    /// Unpack a snapshot's filesystem into `root`
+    pub(crate) async fn extract(&self, snapshot_id: Uuid, root: &Path) -> Result<()> {
+        let archive = self.path.join(snapshot_id.to_string()).join(ROOTFS_ARCHIVE);
+        let root = root.to_path_buf();
+
+        tokio::task::spawn_blocking(move || {
+            let file = File::open(&archive)
+                .map_err(|e| HybridLLMError::FileSystemError(e.to_string()))?;
+            // `unpack` refuses entries that would land outside `root`
+            tar::Archive::new(GzDecoder::new(file))
+                .unpack(&root)
+                .map_err(|e| HybridLLMError::SandboxError(format!("Failed to restore snapshot: {}", e)))
+        })
+        .await
+        .map_err(|e| HybridLLMError::SandboxError(e.to_string()))?
+    }
+
+    pub(crate) async fn get(&self, snapshot_id: Uuid) -> Option<SnapshotInfo> {
+        self.snapshots.read().await.get(&snapshot_id).cloned()
+    }
+
+    /// All snapshots, newest first
+    pub(crate) async fn list(&self) -> Vec<SnapshotInfo> {
+        let mut snapshots: Vec<_> = self.snapshots.read().await.values().cloned().collect();
+        snapshots.sort_by_key(|snapshot| std::cmp::Reverse(snapshot.created_at));
+        snapshots
+    }
+
+    pub(crate) async fn delete(&self, snapshot_id: Uuid) -> Result<()> {
+        if self.snapshots.write().await.remove(&snapshot_id).is_none() {
+            return Err(HybridLLMError::SandboxError(format!(
+                "Snapshot not found: {}",
+                snapshot_id
+            )));
+        }
+
+        let dir = self.path.join(snapshot_id.to_string());
+        if dir.exists() {
+            std::fs::remove_dir_all(&dir)
+                .map_err(|e| HybridLLMError::FileSystemError(e.to_string()))?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Write a gzipped tarball of `root` to `destination`, returning its size
+fn archive(root: &Path, destination: &Path) -> Result<u64> {
+    let file = File::create(destination)
+        .map_err(|e| HybridLLMError::FileSystemError(e.to_string()))?;
+
+    let mut builder = tar::Builder::new(GzEncoder::new(file, Compression::fast()));
+    // Store symlinks as links so a snapshot never captures host files
+    builder.follow_symlinks(false);
+    append_root(&mut builder, root)
+        .and_then(|_| builder.into_inner()?.finish())
+        .map_err(|e| HybridLLMError::SandboxError(format!("Failed to snapshot sandbox: {}", e)))?;
+
+    std::fs::metadata(destination)
+        .map(|m| m.len())
+        .map_err(|e| HybridLLMError::FileSystemError(e.to_string()))
+}
+
+/// Add everything in a sandbox root except mounted volumes, which persist on their own
+fn append_root<W: std::io::Write>(builder: &mut tar::Builder<W>, root: &Path) -> std::io::Result<()> {
+    for entry in std::fs::read_dir(root)? {
+        let entry = entry?;
+        let name = entry.file_name();
+        if name == VOLUMES_DIR {
+            continue;
+        }
+
+        if entry.file_type()?.is_dir() {
+            builder.append_dir_all(&name, entry.path())?;
+        } else {
+            builder.append_path_with_name(entry.path(), &name)?;
+        }
+    }
+
+    Ok(())
+}
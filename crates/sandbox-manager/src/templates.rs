@@ -0,0 +1,143 @@
+use common::{
+    errors::{Result, HybridLLMError},
+    types::SandboxTemplate,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use tracing::debug;
+
+use crate::rootfs::{self, Rootfs};
+
+/// Placeholder in manifest values replaced with the sandbox root
+const ROOT_PLACEHOLDER: &str = "$SANDBOX_ROOT";
+
+/// Describes how to use a template's prebuilt rootfs
+/// Written by `scripts/build_sandbox_templates.sh` next to each rootfs
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct TemplateManifest {
+    pub description: String,
+    /// Directories prepended to PATH, relative to the sandbox root
+    #[serde(default)]
+    pub path: Vec<String>,
+    /// Extra environment variables; `$SANDBOX_ROOT` expands to the sandbox root
+    #[serde(default)]
+    pub env: HashMap<String, String>,
+}
+
+impl TemplateManifest {
+    /// Environment for commands run in a sandbox rooted at `root`
+    pub(crate) fn environment(&self, root: &Path) -> Vec<(String, String)> {
+        let root_str = root.to_string_lossy();
+        let mut environment: Vec<(String, String)> = self
+            .env
+            .iter()
+            .map(|(key, value)| (key.clone(), value.replace(ROOT_PLACEHOLDER, &root_str)))
+            .collect();
+
+        if !self.path.is_empty() {
+            let mut path: Vec<String> = self
+                .path
+                .iter()
+                .map(|dir| root.join(dir).to_string_lossy().into_owned())
+                .collect();
+            path.push(crate::execution::DEFAULT_PATH.to_string());
+            environment.push(("PATH".to_string(), path.join(":")));
+        }
+
+        environment
+    }
+}
+
+/// Prebuilt template images on disk, laid out as `<name>/template.json` and `<name>/rootfs/`
+pub(crate) struct TemplateStore {
+    path: PathBuf,
+}
+
+impl TemplateStore {
+    pub(crate) fn new(path: PathBuf) -> Self {
+        Self { path }
+    }
+
+    /// Templates whose images have been built
+    pub(crate) fn available(&self) -> Vec<SandboxTemplate> {
+        SandboxTemplate::ALL
+            .into_iter()
+            .filter(|template| self.manifest(*template).is_ok())
+            .collect()
+    }
+
+    pub(crate) fn manifest(&self, template: SandboxTemplate) -> Result<TemplateManifest> {
+        let dir = self.path.join(template.name());
+        if !dir.join("rootfs").is_dir() {
+            return Err(HybridLLMError::SandboxError(format!(
+                "Sandbox template '{}' is not built; run scripts/build_sandbox_templates.sh",
+                template.name()
+            )));
+        }
+
+        let manifest = std::fs::read(dir.join("template.json"))
+            .map_err(|e| HybridLLMError::FileSystemError(e.to_string()))?;
+        serde_json::from_slice(&manifest).map_err(|e| {
+            HybridLLMError::ConfigError(format!("Invalid manifest for template '{}': {}", template.name(), e))
+        })
+    }
+
+    /// Lay a template's rootfs under a sandbox root
+    /// The image is shared read-only; `layer` holds the sandbox's writes when overlays are available
+    pub(crate) async fn install(
+        &self,
+        template: SandboxTemplate,
+        root: &Path,
+        layer: &Path,
+    ) -> Result<(TemplateManifest, Rootfs)> {
+        let manifest = self.manifest(template)?;
+        let image = self.path.join(template.name()).join("rootfs");
+        let root = root.to_path_buf();
+        let layer = layer.to_path_buf();
+
+        debug!("📦 Installing template {} into {:?}", template.name(), root);
+
+        let rootfs = tokio::task::spawn_blocking(move || rootfs::layer(&image, &layer, &root))
+            .await
+            .map_err(|e| HybridLLMError::SandboxError(e.to_string()))?
+            .map_err(|e| HybridLLMError::SandboxError(format!("Failed to install template: {}", e)))?;
+
+        Ok((manifest, rootfs))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_install_template() {
+        let base = std::env::temp_dir().join(format!("templates-{}", uuid::Uuid::new_v4()));
+        let store = TemplateStore::new(base.join("templates"));
+        assert!(store.available().is_empty());
+
+        let image = base.join("templates/shell-minimal");
+        std::fs::create_dir_all(image.join("rootfs/.toolchain/bin")).unwrap();
+        std::fs::write(image.join("rootfs/.toolchain/bin/tool"), "#!/bin/sh\n").unwrap();
+        std::fs::write(
+            image.join("template.json"),
+            r#"{"description": "test", "path": [".toolchain/bin"], "env": {"TOOL_HOME": "$SANDBOX_ROOT/.toolchain"}}"#,
+        )
+        .unwrap();
+        assert_eq!(store.available(), vec![SandboxTemplate::ShellMinimal]);
+
+        let root = base.join("sandbox");
+        let layer = base.join("layer");
+        std::fs::create_dir_all(&root).unwrap();
+        let (manifest, _) = store.install(SandboxTemplate::ShellMinimal, &root, &layer).await.unwrap();
+        assert!(root.join(".toolchain/bin/tool").is_file());
+
+        let env: HashMap<_, _> = manifest.environment(&root).into_iter().collect();
+        assert_eq!(env["TOOL_HOME"], format!("{}/.toolchain", root.display()));
+        assert!(env["PATH"].starts_with(&format!("{}/.toolchain/bin:", root.display())));
+
+        rootfs::teardown(&root, &layer);
+        let _ = std::fs::remove_dir_all(base);
+    }
+}
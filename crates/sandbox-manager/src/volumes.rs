@@ -0,0 +1,453 @@
+use chrono::{DateTime, Utc};
+use common::{
+    errors::{Result, HybridLLMError},
+    types::VolumeMount,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use tracing::{info, debug, warn};
+use uuid::Uuid;
+
+use crate::rootfs;
+
+/// Directory inside a sandbox root that volumes are mounted under
+pub(crate) const VOLUMES_DIR: &str = "volumes";
+
+/// Metadata file stored alongside each volume's data
+const METADATA_FILE: &str = "volume.json";
+
+/// Directory holding a volume's files
+const DATA_DIR: &str = "data";
+
+/// Longest accepted volume name
+const MAX_NAME_LEN: usize = 64;
+
+/// A named directory that persists across sandboxes
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct VolumeInfo {
+    pub name: String,
+    pub created_at: DateTime<Utc>,
+    /// Size of the volume's files, refreshed when listed
+    #[serde(default)]
+    pub size_bytes: u64,
+    /// Sandboxes the volume is currently mounted into
+    #[serde(default)]
+    pub attached_to: Vec<Uuid>,
+}
+
+/// How a volume ended up inside a sandbox
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Attachment {
+    /// Bind mount of the volume, remounted read-only when requested
+    Bind,
+    /// Symlink to the volume, used for read-write mounts without mount privileges
+    Link,
+    /// Private copy, used for read-only mounts without mount privileges
+    Copy,
+}
+
+/// One volume mounted into one sandbox
+#[derive(Debug, Clone)]
+struct Mounted {
+    sandbox_id: Uuid,
+    read_only: bool,
+    attachment: Attachment,
+}
+
+/// On-disk store of persistent volumes, one directory per volume
+/// A volume may be mounted read-only into any number of sandboxes, or
+/// read-write into exactly one
+pub(crate) struct VolumeStore {
+    path: PathBuf,
+    volumes: Mutex<HashMap<String, VolumeInfo>>,
+    mounts: Mutex<HashMap<String, Vec<Mounted>>>,
+}
+
+impl VolumeStore {
+    /// Open the store, loading metadata for volumes already on disk
+    pub(crate) fn open(path: PathBuf) -> Result<Self> {
+        std::fs::create_dir_all(&path)
+            .map_err(|e| HybridLLMError::FileSystemError(e.to_string()))?;
+
+        let mut volumes = HashMap::new();
+        let entries = std::fs::read_dir(&path)
+            .map_err(|e| HybridLLMError::FileSystemError(e.to_string()))?;
+
+        for entry in entries.filter_map(|e| e.ok()) {
+            let metadata = entry.path().join(METADATA_FILE);
+            match std::fs::read(&metadata)
+                .ok()
+                .and_then(|bytes| serde_json::from_slice::<VolumeInfo>(&bytes).ok())
+            {
+                Some(info) => {
+                    volumes.insert(info.name.clone(), info);
+                }
+                None => warn!("⚠️  Skipping unreadable volume at {:?}", entry.path()),
+            }
+        }
+
+        info!("💾 Loaded {} sandbox volumes", volumes.len());
+
+        Ok(Self {
+            path,
+            volumes: Mutex::new(volumes),
+            mounts: Mutex::new(HashMap::new()),
+        })
+    }
+
+    pub(crate) fn create(&self, name: &str) -> Result<VolumeInfo> {
+        validate_name(name)?;
+
+        let mut volumes = self.volumes.lock().unwrap();
+        if volumes.contains_key(name) {
+            return Err(HybridLLMError::InvalidRequest(format!("Volume already exists: {}", name)));
+        }
+
+        let dir = self.path.join(name);
+        let info = VolumeInfo {
+            name: name.to_string(),
+            created_at: Utc::now(),
+            size_bytes: 0,
+            attached_to: Vec::new(),
+        };
+
+        let result = std::fs::create_dir_all(dir.join(DATA_DIR))
+            .map_err(|e| HybridLLMError::FileSystemError(e.to_string()))
+            .and_then(|_| serde_json::to_vec_pretty(&info).map_err(|e| HybridLLMError::Other(e.into())))
+            .and_then(|metadata| {
+                std::fs::write(dir.join(METADATA_FILE), metadata)
+                    .map_err(|e| HybridLLMError::FileSystemError(e.to_string()))
+            });
+        if let Err(e) = result {
+            let _ = std::fs::remove_dir_all(&dir);
+            return Err(e);
+        }
+
+        volumes.insert(name.to_string(), info.clone());
+        Ok(info)
+    }
+
+    /// All volumes by name, with current size and attachments
+    pub(crate) fn list(&self) -> Vec<VolumeInfo> {
+        let volumes = self.volumes.lock().unwrap();
+        let mounts = self.mounts.lock().unwrap();
+        let mut volumes: Vec<_> = volumes
+            .values()
+            .cloned()
+            .map(|mut info| {
+                info.size_bytes = data_size(&self.path.join(&info.name).join(DATA_DIR));
+                info.attached_to = mounts
+                    .get(&info.name)
+                    .map(|mounted| mounted.iter().map(|m| m.sandbox_id).collect())
+                    .unwrap_or_default();
+                info
+            })
+            .collect();
+        volumes.sort_by(|a, b| a.name.cmp(&b.name));
+        volumes
+    }
+
+    /// Delete a volume and its files; refused while it is mounted anywhere
+    pub(crate) fn delete(&self, name: &str) -> Result<()> {
+        let mut volumes = self.volumes.lock().unwrap();
+        if !volumes.contains_key(name) {
+            return Err(HybridLLMError::SandboxError(format!("Volume not found: {}", name)));
+        }
+        if self.mounts.lock().unwrap().get(name).is_some_and(|m| !m.is_empty()) {
+            return Err(HybridLLMError::SandboxError(format!(
+                "Volume {} is mounted into a sandbox",
+                name
+            )));
+        }
+
+        volumes.remove(name);
+        let dir = self.path.join(name);
+        if dir.exists() {
+            std::fs::remove_dir_all(&dir)
+                .map_err(|e| HybridLLMError::FileSystemError(e.to_string()))?;
+        }
+
+        Ok(())
+    }
+
+    /// Mount volumes into a sandbox root under `volumes/<name>`
+    /// Either every volume is mounted or none is
+    pub(crate) fn attach(&self, sandbox_id: Uuid, root: &Path, requested: &[VolumeMount]) -> Result<()> {
+        for (index, mount) in requested.iter().enumerate() {
+            if let Err(e) = self.attach_one(sandbox_id, root, mount) {
+                for attached in &requested[..index] {
+                    let _ = self.detach_one(sandbox_id, root, &attached.name);
+                }
+                return Err(e);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn attach_one(&self, sandbox_id: Uuid, root: &Path, mount: &VolumeMount) -> Result<()> {
+        if !self.volumes.lock().unwrap().contains_key(&mount.name) {
+            return Err(HybridLLMError::SandboxError(format!("Volume not found: {}", mount.name)));
+        }
+
+        let mut mounts = self.mounts.lock().unwrap();
+        let existing = mounts.entry(mount.name.clone()).or_default();
+        // One writer, or any number of readers
+        if existing.iter().any(|m| !m.read_only) || (!mount.read_only && !existing.is_empty()) {
+            return Err(HybridLLMError::SandboxError(format!(
+                "Volume {} is already mounted read-write, or cannot be mounted read-write while in use",
+                mount.name
+            )));
+        }
+
+        let source = self.path.join(&mount.name).join(DATA_DIR);
+        let target = root.join(VOLUMES_DIR).join(&mount.name);
+        let attachment = place(&source, &target, mount.read_only)
+            .map_err(|e| HybridLLMError::SandboxError(format!("Failed to mount volume {}: {}", mount.name, e)))?;
+
+        debug!(
+            "💾 Mounted volume {} into sandbox {} ({:?}, read-only: {})",
+            mount.name, sandbox_id, attachment, mount.read_only
+        );
+        existing.push(Mounted { sandbox_id, read_only: mount.read_only, attachment });
+        Ok(())
+    }
+
+    /// Unmount every volume from a sandbox
+    /// Fails if a volume is still mounted, in which case the root must not be deleted
+    pub(crate) fn detach(&self, sandbox_id: Uuid, root: &Path) -> Result<()> {
+        let names: Vec<String> = self
+            .mounts
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(_, mounted)| mounted.iter().any(|m| m.sandbox_id == sandbox_id))
+            .map(|(name, _)| name.clone())
+            .collect();
+
+        let mut result = Ok(());
+        for name in names {
+            if let Err(e) = self.detach_one(sandbox_id, root, &name) {
+                warn!("⚠️  Failed to unmount volume {} from sandbox {}: {}", name, sandbox_id, e);
+                result = Err(e);
+            }
+        }
+
+        // Mounts left behind by a run that did not shut down cleanly are not tracked
+        if let Ok(entries) = std::fs::read_dir(root.join(VOLUMES_DIR)) {
+            for entry in entries.filter_map(|e| e.ok()) {
+                let _ = unmount(&entry.path());
+            }
+        }
+
+        result
+    }
+
+    fn detach_one(&self, sandbox_id: Uuid, root: &Path, name: &str) -> Result<()> {
+        let mut mounts = self.mounts.lock().unwrap();
+        let Some(mounted) = mounts.get_mut(name) else {
+            return Ok(());
+        };
+        let Some(index) = mounted.iter().position(|m| m.sandbox_id == sandbox_id) else {
+            return Ok(());
+        };
+
+        let target = root.join(VOLUMES_DIR).join(name);
+        let removed = match mounted[index].attachment {
+            Attachment::Bind => unmount(&target).and_then(|_| std::fs::remove_dir(&target)),
+            Attachment::Link => std::fs::remove_file(&target),
+            Attachment::Copy => std::fs::remove_dir_all(&target),
+        };
+        if let Err(e) = removed {
+            // A mount point that is already gone is as good as unmounted
+            if e.kind() != std::io::ErrorKind::NotFound {
+                return Err(HybridLLMError::SandboxError(format!(
+                    "Failed to unmount volume {}: {}",
+                    name, e
+                )));
+            }
+        }
+
+        mounted.remove(index);
+        Ok(())
+    }
+}
+
+/// Volume names become directory names, so keep them to a safe alphabet
+fn validate_name(name: &str) -> Result<()> {
+    let valid = !name.is_empty()
+        && name.len() <= MAX_NAME_LEN
+        && !name.starts_with('.')
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'));
+
+    if valid {
+        Ok(())
+    } else {
+        Err(HybridLLMError::InvalidRequest(format!(
+            "Invalid volume name {:?}: use letters, digits, '-', '_' and '.'",
+            name
+        )))
+    }
+}
+
+/// Whether a walk entry is the volumes directory at the top of a sandbox root
+/// Volumes are shared storage, so they are left out of the sandbox's own files
+pub(crate) fn is_volumes_dir(entry: &walkdir::DirEntry) -> bool {
+    entry.depth() == 1 && entry.file_type().is_dir() && entry.file_name() == VOLUMES_DIR
+}
+
+/// Make `source` appear at `target`
+/// Bind mounts when permitted; otherwise links read-write volumes and copies read-only ones
+fn place(source: &Path, target: &Path, read_only: bool) -> std::io::Result<Attachment> {
+    std::fs::create_dir_all(target)?;
+
+    match bind_mount(source, target, read_only) {
+        Ok(()) => return Ok(Attachment::Bind),
+        Err(e) => debug!("bind mount unavailable ({}), falling back for {:?}", e, target),
+    }
+
+    if read_only {
+        rootfs::clone_tree(source, target)?;
+        Ok(Attachment::Copy)
+    } else {
+        std::fs::remove_dir(target)?;
+        link(source, target)?;
+        Ok(Attachment::Link)
+    }
+}
+
+#[cfg(unix)]
+fn link(source: &Path, target: &Path) -> std::io::Result<()> {
+    std::os::unix::fs::symlink(source, target)
+}
+
+#[cfg(not(unix))]
+fn link(_source: &Path, _target: &Path) -> std::io::Result<()> {
+    Err(std::io::Error::new(std::io::ErrorKind::Unsupported, "volume links require Unix"))
+}
+
+#[cfg(target_os = "linux")]
+fn bind_mount(source: &Path, target: &Path, read_only: bool) -> std::io::Result<()> {
+    use std::ffi::CString;
+
+    let invalid = |e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e);
+    let source = CString::new(source.to_string_lossy().into_owned()).map_err(invalid)?;
+    let target_c = CString::new(target.to_string_lossy().into_owned()).map_err(invalid)?;
+
+    // SAFETY: every pointer is a NUL-terminated string that outlives the call
+    let result = unsafe {
+        libc::mount(
+            source.as_ptr(),
+            target_c.as_ptr(),
+            std::ptr::null(),
+            libc::MS_BIND,
+            std::ptr::null(),
+        )
+    };
+    if result != 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+
+    if read_only {
+        // Bind mounts only take MS_RDONLY on a remount
+        // SAFETY: as above
+        let result = unsafe {
+            libc::mount(
+                std::ptr::null(),
+                target_c.as_ptr(),
+                std::ptr::null(),
+                libc::MS_REMOUNT | libc::MS_BIND | libc::MS_RDONLY | libc::MS_NOSUID | libc::MS_NODEV,
+                std::ptr::null(),
+            )
+        };
+        if result != 0 {
+            let error = std::io::Error::last_os_error();
+            let _ = unmount(target);
+            return Err(error);
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn bind_mount(_source: &Path, _target: &Path, _read_only: bool) -> std::io::Result<()> {
+    Err(std::io::Error::new(std::io::ErrorKind::Unsupported, "bind mounts require Linux"))
+}
+
+#[cfg(target_os = "linux")]
+fn unmount(target: &Path) -> std::io::Result<()> {
+    let target = std::ffi::CString::new(target.to_string_lossy().into_owned())
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e))?;
+
+    // SAFETY: plain syscall on a NUL-terminated path
+    // Detach so processes still running in the sandbox cannot keep the volume busy
+    if unsafe { libc::umount2(target.as_ptr(), libc::MNT_DETACH) } != 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn unmount(_target: &Path) -> std::io::Result<()> {
+    Err(std::io::Error::new(std::io::ErrorKind::Unsupported, "bind mounts require Linux"))
+}
+
+/// Total size of the files in a volume
+fn data_size(path: &Path) -> u64 {
+    walkdir::WalkDir::new(path)
+        .into_iter()
+        .filter_map(|e| e.ok())
+        .filter(|e| e.file_type().is_file())
+        .filter_map(|e| e.metadata().ok())
+        .map(|m| m.len())
+        .sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_volume_mount_modes() {
+        let base = std::env::temp_dir().join(format!("volumes-{}", Uuid::new_v4()));
+        let store = VolumeStore::open(base.join("store")).unwrap();
+        assert!(store.create("../escape").is_err());
+        store.create("workspace").unwrap();
+        assert!(store.create("workspace").is_err());
+
+        let (writer, reader) = (Uuid::new_v4(), Uuid::new_v4());
+        let (writer_root, reader_root) = (base.join("writer"), base.join("reader"));
+        let read_write = VolumeMount { name: "workspace".to_string(), read_only: false };
+        let read_only = VolumeMount { name: "workspace".to_string(), read_only: true };
+
+        store.attach(writer, &writer_root, std::slice::from_ref(&read_write)).unwrap();
+        std::fs::write(writer_root.join("volumes/workspace/notes.md"), "progress").unwrap();
+
+        // A read-write mount is exclusive
+        assert!(store.attach(reader, &reader_root, std::slice::from_ref(&read_only)).is_err());
+        assert!(store.delete("workspace").is_err());
+        store.detach(writer, &writer_root).unwrap();
+        std::fs::remove_dir_all(&writer_root).unwrap();
+
+        // Files written through the mount outlive the sandbox
+        store.attach(reader, &reader_root, std::slice::from_ref(&read_only)).unwrap();
+        let mounted = reader_root.join("volumes/workspace/notes.md");
+        assert_eq!(std::fs::read_to_string(&mounted).unwrap(), "progress");
+        let _ = std::fs::write(&mounted, "overwritten");
+        assert_eq!(store.list()[0].attached_to, vec![reader]);
+        store.detach(reader, &reader_root).unwrap();
+
+        let volume = &store.list()[0];
+        assert_eq!(volume.size_bytes, "progress".len() as u64);
+        assert!(volume.attached_to.is_empty());
+
+        let reopened = VolumeStore::open(base.join("store")).unwrap();
+        reopened.delete("workspace").unwrap();
+        assert!(reopened.list().is_empty());
+
+        let _ = std::fs::remove_dir_all(base);
+    }
+}
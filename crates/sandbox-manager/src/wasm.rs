@@ -0,0 +1,439 @@
+use common::{
+    errors::{Result, HybridLLMError},
+    types::{CodeLanguage, NetworkMode, SandboxConfig},
+};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+use tokio::sync::RwLock;
+use tracing::{info, debug, warn};
+use uuid::Uuid;
+use wasmtime::{Config, Engine, Linker, Module, Store, StoreLimits, StoreLimitsBuilder};
+use wasmtime_wasi::{
+    pipe::{MemoryInputPipe, MemoryOutputPipe},
+    preview1::{self, WasiP1Ctx},
+    DirPerms, FilePerms, I32Exit, WasiCtxBuilder,
+};
+
+use crate::execution;
+use crate::limits::Confinement;
+
+/// Interval at which the engine epoch advances (wall-clock limit granularity)
+const EPOCH_TICK: Duration = Duration::from_millis(10);
+
+/// Longest rustc may take to compile a snippet
+const COMPILE_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Address space and output limits for rustc, in GB
+const COMPILE_MEMORY_GB: f32 = 4.0;
+const COMPILE_DISK_GB: f32 = 1.0;
+
+/// An engine that meters fuel and interrupts stores past their epoch deadline, one epoch per `EPOCH_TICK`
+pub(crate) fn metered_engine() -> Result<Engine> {
+    let mut engine_config = Config::new();
+    engine_config.consume_fuel(true);
+    engine_config.epoch_interruption(true);
+
+    let engine = Engine::new(&engine_config)
+        .map_err(|e| HybridLLMError::SandboxError(e.to_string()))?;
+
+    // Advance the epoch so each store can trap after its own deadline;
+    // the ticker exits once the engine is dropped
+    let weak = engine.weak();
+    std::thread::spawn(move || {
+        while let Some(engine) = weak.upgrade() {
+            engine.increment_epoch();
+            drop(engine);
+            std::thread::sleep(EPOCH_TICK);
+        }
+    });
+
+    Ok(engine)
+}
+
+/// Epoch deadline of a store that may run for `timeout`
+pub(crate) fn epoch_deadline(timeout: Duration) -> u64 {
+    (timeout.as_millis() / EPOCH_TICK.as_millis()).max(1) as u64
+}
+
+/// Configuration for the WASM micro-sandbox
+#[derive(Debug, Clone)]
+pub struct WasmConfig {
+    /// Directory holding interpreter modules (python.wasm, quickjs.wasm)
+    pub runtimes_dir: PathBuf,
+    /// Instruction budget per execution
+    pub fuel: u64,
+    /// Maximum linear memory per instance
+    pub max_memory_bytes: usize,
+    /// Wall-clock limit per execution
+    pub timeout: Duration,
+    /// Maximum captured bytes per output stream
+    pub max_output_bytes: usize,
+    /// Snippets larger than this are not eligible for the micro-sandbox
+    pub max_snippet_bytes: usize,
+}
+
+impl Default for WasmConfig {
+    fn default() -> Self {
+        Self {
+            runtimes_dir: PathBuf::from("./data/wasm-runtimes"),
+            fuel: 2_000_000_000,
+            max_memory_bytes: 256 * 1024 * 1024,
+            timeout: Duration::from_secs(5),
+            max_output_bytes: 1024 * 1024,
+            max_snippet_bytes: 16 * 1024,
+        }
+    }
+}
+
+/// Output of a snippet run in the WASM micro-sandbox
+#[derive(Debug, Clone)]
+pub struct WasmOutput {
+    pub stdout: String,
+    pub stderr: String,
+    pub exit_code: i32,
+    pub fuel_consumed: u64,
+    pub duration_ms: u64,
+}
+
+struct WasmState {
+    wasi: WasiP1Ctx,
+    limits: StoreLimits,
+}
+
+/// WASI-based executor for short untrusted snippets
+/// Guests get no network, no ambient filesystem and a bounded fuel/memory budget
+pub struct WasmExecutor {
+    engine: Engine,
+    config: WasmConfig,
+    /// Compiled interpreter modules, keyed by language
+    modules: RwLock<HashMap<CodeLanguage, Module>>,
+    /// Sysroot of the host Rust toolchain, if it can target wasm32-wasip1
+    rust_sysroot: Option<PathBuf>,
+}
+
+impl WasmExecutor {
+    pub fn new(config: WasmConfig) -> Result<Self> {
+        let engine = metered_engine()?;
+
+        info!("🧩 WASM micro-sandbox initialized (runtimes: {:?})", config.runtimes_dir);
+
+        Ok(Self {
+            engine,
+            config,
+            modules: RwLock::new(HashMap::new()),
+            rust_sysroot: wasi_sysroot(),
+        })
+    }
+
+    /// Whether a snippet should be run here rather than in a full sandbox
+    pub fn is_eligible(&self, language: CodeLanguage, code: &str) -> bool {
+        if code.len() > self.config.max_snippet_bytes {
+            return false;
+        }
+
+        match language {
+            CodeLanguage::Rust => self.rust_sysroot.is_some(),
+            _ => self.runtime_path(language).is_some_and(|p| p.exists()),
+        }
+    }
+
+    /// Run a snippet and capture its output
+    pub async fn run(&self, language: CodeLanguage, code: &str) -> Result<WasmOutput> {
+        debug!("🧩 Running {:?} snippet in WASM sandbox ({} bytes)", language, code.len());
+
+        let (module, args) = match language {
+            CodeLanguage::Python => (
+                self.interpreter(language).await?,
+                vec!["python".to_string(), "-c".to_string(), code.to_string()],
+            ),
+            CodeLanguage::JavaScript => (
+                self.interpreter(language).await?,
+                vec!["qjs".to_string(), "--std".to_string(), "-e".to_string(), code.to_string()],
+            ),
+            CodeLanguage::Rust => match self.compile_rust(code).await? {
+                Ok(module) => (module, vec!["main".to_string()]),
+                Err(output) => return Ok(output),
+            },
+        };
+
+        let python_lib = self.config.runtimes_dir.join("python-lib");
+        let preopen = (language == CodeLanguage::Python && python_lib.exists())
+            .then_some(python_lib);
+
+        let engine = self.engine.clone();
+        let config = self.config.clone();
+        let run = tokio::task::spawn_blocking(move || {
+            Self::run_module(&engine, &config, &module, &args, preopen)
+        });
+
+        let output = run
+            .await
+            .map_err(|e| HybridLLMError::SandboxError(e.to_string()))??;
+
+        if output.duration_ms >= self.config.timeout.as_millis() as u64 {
+            warn!("⏱️  WASM snippet exceeded {:?}", self.config.timeout);
+            return Err(HybridLLMError::Timeout(format!(
+                "WASM snippet exceeded {:?}",
+                self.config.timeout
+            )));
+        }
+
+        Ok(output)
+    }
+
+    fn run_module(
+        engine: &Engine,
+        config: &WasmConfig,
+        module: &Module,
+        args: &[String],
+        preopen: Option<PathBuf>,
+    ) -> Result<WasmOutput> {
+        let stdout = MemoryOutputPipe::new(config.max_output_bytes);
+        let stderr = MemoryOutputPipe::new(config.max_output_bytes);
+
+        let mut builder = WasiCtxBuilder::new();
+        builder
+            .args(args)
+            .stdin(MemoryInputPipe::new(Vec::<u8>::new()))
+            .stdout(stdout.clone())
+            .stderr(stderr.clone());
+
+        if let Some(lib) = preopen {
+            builder
+                .preopened_dir(lib, "/usr/local/lib", DirPerms::READ, FilePerms::READ)
+                .map_err(|e| HybridLLMError::SandboxError(e.to_string()))?;
+        }
+
+        let state = WasmState {
+            wasi: builder.build_p1(),
+            limits: StoreLimitsBuilder::new()
+                .memory_size(config.max_memory_bytes)
+                .instances(1)
+                .build(),
+        };
+
+        let mut store = Store::new(engine, state);
+        store.limiter(|state| &mut state.limits);
+        store
+            .set_fuel(config.fuel)
+            .map_err(|e| HybridLLMError::SandboxError(e.to_string()))?;
+        store.set_epoch_deadline(epoch_deadline(config.timeout));
+
+        let mut linker: Linker<WasmState> = Linker::new(engine);
+        preview1::add_to_linker_sync(&mut linker, |state| &mut state.wasi)
+            .map_err(|e| HybridLLMError::SandboxError(e.to_string()))?;
+
+        let started = Instant::now();
+        let instance = linker
+            .instantiate(&mut store, module)
+            .map_err(|e| HybridLLMError::SandboxError(e.to_string()))?;
+        let start = instance
+            .get_typed_func::<(), ()>(&mut store, "_start")
+            .map_err(|e| HybridLLMError::SandboxError(e.to_string()))?;
+
+        let (exit_code, trap) = match start.call(&mut store, ()) {
+            Ok(()) => (0, None),
+            Err(e) => match e.downcast_ref::<I32Exit>() {
+                Some(exit) => (exit.0, None),
+                None => (-1, Some(e.to_string())),
+            },
+        };
+
+        let mut stderr = String::from_utf8_lossy(&stderr.contents()).into_owned();
+        if let Some(trap) = trap {
+            debug!("WASM guest trapped: {}", trap);
+            stderr.push_str(&trap);
+        }
+
+        Ok(WasmOutput {
+            stdout: String::from_utf8_lossy(&stdout.contents()).into_owned(),
+            stderr,
+            exit_code,
+            fuel_consumed: config.fuel - store.get_fuel().unwrap_or(0),
+            duration_ms: started.elapsed().as_millis() as u64,
+        })
+    }
+
+    /// Get (and cache) the interpreter module for a language
+    async fn interpreter(&self, language: CodeLanguage) -> Result<Module> {
+        if let Some(module) = self.modules.read().await.get(&language) {
+            return Ok(module.clone());
+        }
+
+        let path = self.runtime_path(language).ok_or_else(|| {
+            HybridLLMError::SandboxError(format!("No WASM runtime for {:?}", language))
+        })?;
+
+        let engine = self.engine.clone();
+        let module = tokio::task::spawn_blocking(move || Module::from_file(&engine, &path))
+            .await
+            .map_err(|e| HybridLLMError::SandboxError(e.to_string()))?
+            .map_err(|e| HybridLLMError::SandboxError(format!("Failed to load runtime: {}", e)))?;
+
+        info!("📥 Loaded WASM runtime for {:?}", language);
+        self.modules.write().await.insert(language, module.clone());
+
+        Ok(module)
+    }
+
+    fn runtime_path(&self, language: CodeLanguage) -> Option<PathBuf> {
+        match language {
+            CodeLanguage::Python => Some(self.config.runtimes_dir.join("python.wasm")),
+            CodeLanguage::JavaScript => Some(self.config.runtimes_dir.join("quickjs.wasm")),
+            CodeLanguage::Rust => None,
+        }
+    }
+
+    /// Compile a Rust snippet to wasm32-wasip1
+    /// rustc runs confined like a sandbox command, seeing only the build directory and its
+    /// own toolchain, so `include_str!` and friends can't read host files
+    /// Compiler diagnostics are returned as a failed run rather than an error
+    async fn compile_rust(&self, code: &str) -> Result<std::result::Result<Module, WasmOutput>> {
+        let sysroot = self.rust_sysroot.as_ref().ok_or_else(|| {
+            HybridLLMError::SandboxError("rustc with the wasm32-wasip1 target is not installed".to_string())
+        })?;
+
+        let build_dir = std::env::temp_dir().join(format!("hybrid-llm-wasm-{}", Uuid::new_v4()));
+        let source_dir = build_dir.join("src");
+        tokio::fs::create_dir_all(&source_dir)
+            .await
+            .map_err(|e| HybridLLMError::SandboxError(e.to_string()))?;
+
+        let result = async {
+            tokio::fs::write(source_dir.join("main.rs"), code)
+                .await
+                .map_err(|e| HybridLLMError::SandboxError(e.to_string()))?;
+
+            let staging = build_dir.join("isolation");
+            let confinement = Confinement::for_build(&compile_config(), &source_dir, &staging, sysroot).await?;
+
+            // The toolchain's own rustc, as the rustup proxy needs the host home directory
+            let command = format!(
+                "{} --edition 2021 --target wasm32-wasip1 -O -o main.wasm main.rs",
+                shell_quote(&sysroot.join("bin").join("rustc"))
+            );
+            let compiled =
+                execution::run_command(&command, &source_dir, &[], &confinement, Some(COMPILE_TIMEOUT), None)
+                    .await;
+            confinement.release();
+            let compiled = compiled?;
+
+            if compiled.timed_out {
+                warn!("⏱️  Compiling a Rust snippet exceeded {:?}", COMPILE_TIMEOUT);
+                return Err(HybridLLMError::Timeout(format!(
+                    "Compiling the snippet exceeded {:?}",
+                    COMPILE_TIMEOUT
+                )));
+            }
+            if compiled.exit_code != 0 {
+                return Ok(Err(WasmOutput {
+                    stdout: String::new(),
+                    stderr: compiled.stderr,
+                    exit_code: compiled.exit_code,
+                    fuel_consumed: 0,
+                    duration_ms: compiled.duration_ms,
+                }));
+            }
+
+            let module = Module::from_file(&self.engine, source_dir.join("main.wasm"))
+                .map_err(|e| HybridLLMError::SandboxError(e.to_string()))?;
+
+            Ok(Ok(module))
+        }
+        .await;
+
+        let _ = tokio::fs::remove_dir_all(&build_dir).await;
+
+        result
+    }
+}
+
+/// Limits rustc runs under
+fn compile_config() -> SandboxConfig {
+    SandboxConfig {
+        id: Uuid::new_v4(),
+        network_mode: NetworkMode::None,
+        cpu_limit: 100.0,
+        memory_limit_gb: COMPILE_MEMORY_GB,
+        disk_limit_gb: COMPILE_DISK_GB,
+        allowed_commands: vec![],
+        template: None,
+        max_lifetime_secs: None,
+        idle_timeout_secs: None,
+        execution_timeout_secs: None,
+        llm_id: None,
+        gpu: None,
+        volumes: vec![],
+    }
+}
+
+/// Sysroot of the host Rust toolchain
+fn host_sysroot() -> Option<PathBuf> {
+    let output = std::process::Command::new("rustc").args(["--print", "sysroot"]).output().ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    Some(PathBuf::from(String::from_utf8(output.stdout).ok()?.trim()))
+}
+
+/// Sysroot of the host Rust toolchain, when it has the wasm32-wasip1 target installed
+fn wasi_sysroot() -> Option<PathBuf> {
+    host_sysroot().filter(|sysroot| sysroot.join("lib/rustlib/wasm32-wasip1").is_dir())
+}
+
+/// Quote a path for `sh -c`
+fn shell_quote(path: &Path) -> String {
+    format!("'{}'", path.to_string_lossy().replace('\'', "'\\''"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_eligibility() {
+        let executor = WasmExecutor::new(WasmConfig::default()).unwrap();
+
+        // Rust snippets need rustc and the wasm32-wasip1 target
+        let has_target = wasi_sysroot().is_some();
+        assert_eq!(executor.is_eligible(CodeLanguage::Rust, "fn main() {}"), has_target);
+        assert!(!executor.is_eligible(CodeLanguage::Rust, &"x".repeat(32 * 1024)));
+        // No interpreter modules in the default runtimes dir
+        assert!(!executor.is_eligible(CodeLanguage::Python, "print(1)"));
+    }
+
+    #[tokio::test]
+    async fn test_rustc_sees_only_build_dir() {
+        let Some(sysroot) = host_sysroot() else {
+            return;
+        };
+        let base = std::env::temp_dir().join(format!("wasm-build-{}", Uuid::new_v4()));
+        let source_dir = base.join("src");
+        std::fs::create_dir_all(&source_dir).unwrap();
+        let secret = base.join("secret.txt");
+        std::fs::write(&secret, "host file").unwrap();
+        std::fs::write(source_dir.join("plain.rs"), "pub const X: u32 = 1;").unwrap();
+        std::fs::write(source_dir.join("leak.rs"), format!("pub const X: &str = include_str!({:?});", secret)).unwrap();
+
+        let confinement = Confinement::for_build(&compile_config(), &source_dir, &base.join("isolation"), &sysroot)
+            .await
+            .unwrap();
+        let rustc = shell_quote(&sysroot.join("bin").join("rustc"));
+        let compile = |file: &str| format!("{} --crate-type lib --emit metadata {}", rustc, file);
+
+        let plain = execution::run_command(&compile("plain.rs"), &source_dir, &[], &confinement, Some(COMPILE_TIMEOUT), None)
+            .await
+            .unwrap();
+        assert_eq!(plain.exit_code, 0, "{}", plain.stderr);
+
+        let leak = execution::run_command(&compile("leak.rs"), &source_dir, &[], &confinement, Some(COMPILE_TIMEOUT), None)
+            .await
+            .unwrap();
+        assert_ne!(leak.exit_code, 0);
+        assert!(leak.stderr.contains("couldn't read"), "{}", leak.stderr);
+
+        confinement.release();
+        std::fs::remove_dir_all(&base).unwrap();
+    }
+}
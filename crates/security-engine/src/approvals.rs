@@ -0,0 +1,255 @@
+use chrono::{DateTime, Utc};
+use common::{
+    messages::PermissionType,
+    types::{ArtifactTransfer, PortForwardRequest},
+};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{broadcast, oneshot, RwLock};
+use tracing::{info, warn};
+use uuid::Uuid;
+
+/// A permission an LLM asked the user for because the policy did not grant it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PermissionApproval {
+    pub permission: PermissionType,
+    pub explanation: String,
+}
+
+/// What a pending approval asks for
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum ApprovalRequest {
+    Permission(PermissionApproval),
+    ArtifactTransfer(ArtifactTransfer),
+    PortForward(PortForwardRequest),
+}
+
+impl From<PermissionApproval> for ApprovalRequest {
+    fn from(request: PermissionApproval) -> Self {
+        Self::Permission(request)
+    }
+}
+
+impl From<ArtifactTransfer> for ApprovalRequest {
+    fn from(transfer: ArtifactTransfer) -> Self {
+        Self::ArtifactTransfer(transfer)
+    }
+}
+
+impl From<PortForwardRequest> for ApprovalRequest {
+    fn from(request: PortForwardRequest) -> Self {
+        Self::PortForward(request)
+    }
+}
+
+/// A request awaiting a decision, as shown to the user
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PendingApproval {
+    pub id: Uuid,
+    pub llm_id: String,
+    pub requested_at: DateTime<Utc>,
+    /// Denied automatically if still undecided at this time
+    pub expires_at: DateTime<Utc>,
+    #[serde(flatten)]
+    pub request: ApprovalRequest,
+}
+
+/// A change to the set of pending approvals
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ApprovalEvent {
+    Requested { approval: PendingApproval },
+    /// Sent once the waiting subsystem has its answer, including denials on timeout
+    Resolved { id: Uuid, approved: bool },
+}
+
+/// A request waiting for a human decision
+struct PendingRequest<T> {
+    llm_id: String,
+    request: T,
+    requested_at: DateTime<Utc>,
+    expires_at: DateTime<Utc>,
+    responder: oneshot::Sender<bool>,
+}
+
+/// Tracks requests awaiting user approval
+/// Anything not explicitly approved before its timeout is denied
+pub struct Approvals<T> {
+    pending: Arc<RwLock<HashMap<Uuid, PendingRequest<T>>>>,
+    events: broadcast::Sender<ApprovalEvent>,
+}
+
+/// Permissions awaiting approval
+pub type PermissionApprovals = Approvals<PermissionApproval>;
+
+/// Artifact transfers awaiting approval
+pub type ArtifactApprovals = Approvals<ArtifactTransfer>;
+
+/// Sandbox port forwards awaiting approval
+pub type PortForwardApprovals = Approvals<PortForwardRequest>;
+
+impl<T: Clone + Into<ApprovalRequest>> Approvals<T> {
+    pub fn new() -> Self {
+        Self::with_events(broadcast::channel(64).0)
+    }
+
+    /// Publish requests and their outcomes on `events`, which several stores may share
+    pub fn with_events(events: broadcast::Sender<ApprovalEvent>) -> Self {
+        Self {
+            pending: Arc::new(RwLock::new(HashMap::new())),
+            events,
+        }
+    }
+
+    /// Register a request and wait for a decision, denying on timeout
+    pub async fn request(&self, llm_id: &str, request_id: Uuid, request: T, timeout: Duration) -> bool {
+        let (responder, decision) = oneshot::channel();
+        let requested_at = Utc::now();
+        let expires_at = chrono::Duration::from_std(timeout)
+            .ok()
+            .and_then(|timeout| requested_at.checked_add_signed(timeout))
+            .unwrap_or(DateTime::<Utc>::MAX_UTC);
+
+        let approval = PendingApproval {
+            id: request_id,
+            llm_id: llm_id.to_string(),
+            requested_at,
+            expires_at,
+            request: request.clone().into(),
+        };
+        self.pending.write().await.insert(
+            request_id,
+            PendingRequest {
+                llm_id: llm_id.to_string(),
+                request,
+                requested_at,
+                expires_at,
+                responder,
+            },
+        );
+        let _ = self.events.send(ApprovalEvent::Requested { approval });
+
+        info!("⏳ Awaiting approval for request {}", request_id);
+
+        let approved = match tokio::time::timeout(timeout, decision).await {
+            Ok(Ok(approved)) => approved,
+            Ok(Err(_)) => false,
+            Err(_) => {
+                warn!("⏱️  Approval request {} timed out, denying", request_id);
+                false
+            }
+        };
+
+        self.pending.write().await.remove(&request_id);
+        let _ = self.events.send(ApprovalEvent::Resolved { id: request_id, approved });
+
+        approved
+    }
+
+    /// Record a decision for a pending request
+    /// Returns false if the request is unknown or already decided
+    pub async fn resolve(&self, request_id: Uuid, approved: bool) -> bool {
+        match self.pending.write().await.remove(&request_id) {
+            Some(pending) => pending.responder.send(approved).is_ok(),
+            None => false,
+        }
+    }
+
+    /// Requests currently awaiting a decision
+    pub async fn pending(&self) -> Vec<(Uuid, T)> {
+        self.pending
+            .read()
+            .await
+            .iter()
+            .map(|(id, pending)| (*id, pending.request.clone()))
+            .collect()
+    }
+
+    /// Requests currently awaiting a decision, with who asked and until when
+    pub async fn pending_approvals(&self) -> Vec<PendingApproval> {
+        self.pending
+            .read()
+            .await
+            .iter()
+            .map(|(id, pending)| PendingApproval {
+                id: *id,
+                llm_id: pending.llm_id.clone(),
+                requested_at: pending.requested_at,
+                expires_at: pending.expires_at,
+                request: pending.request.clone().into(),
+            })
+            .collect()
+    }
+
+    /// Subscribe to requests and their outcomes
+    pub fn subscribe(&self) -> broadcast::Receiver<ApprovalEvent> {
+        self.events.subscribe()
+    }
+}
+
+impl<T: Clone + Into<ApprovalRequest>> Default for Approvals<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn transfer() -> ArtifactTransfer {
+        ArtifactTransfer {
+            sandbox_id: Uuid::new_v4(),
+            file_path: "out/report.csv".to_string(),
+            destination: "report.csv".to_string(),
+            explanation: "Analysis output".to_string(),
+            approved: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_approve() {
+        let approvals = Arc::new(ArtifactApprovals::new());
+        let mut events = approvals.subscribe();
+        let id = Uuid::new_v4();
+
+        let waiter = {
+            let approvals = Arc::clone(&approvals);
+            tokio::spawn(async move { approvals.request("llm", id, transfer(), Duration::from_secs(5)).await })
+        };
+
+        match events.recv().await.unwrap() {
+            ApprovalEvent::Requested { approval } => {
+                assert_eq!(approval.id, id);
+                assert_eq!(approval.llm_id, "llm");
+                let json = serde_json::to_value(&approval).unwrap();
+                assert_eq!(json["kind"], "artifact_transfer");
+                assert_eq!(json["file_path"], "out/report.csv");
+            }
+            other => panic!("unexpected event: {:?}", other),
+        }
+        assert_eq!(approvals.pending_approvals().await.len(), 1);
+
+        assert!(approvals.resolve(id, true).await);
+        assert!(waiter.await.unwrap());
+        assert!(matches!(
+            events.recv().await.unwrap(),
+            ApprovalEvent::Resolved { id: resolved, approved: true } if resolved == id
+        ));
+        assert!(!approvals.resolve(id, false).await);
+    }
+
+    #[tokio::test]
+    async fn test_timeout_denies() {
+        let approvals = ArtifactApprovals::new();
+        let approved = approvals
+            .request("llm", Uuid::new_v4(), transfer(), Duration::from_millis(10))
+            .await;
+
+        assert!(!approved);
+        assert!(approvals.pending().await.is_empty());
+    }
+}
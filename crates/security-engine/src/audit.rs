@@ -1,23 +1,109 @@
-use common::types::AuditLogEntry;
+use common::{
+    errors::{Result, HybridLLMError},
+    types::{AuditLogEntry, RequestContext},
+};
+use serde::{Deserialize, Serialize};
+use std::io::{BufRead, Write};
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
-use tokio::sync::RwLock;
-use tracing::{info, debug};
+use tokio::sync::{broadcast, RwLock};
+use tracing::{info, debug, error, warn};
 use uuid::Uuid;
-use chrono::Utc;
+use chrono::{DateTime, Utc};
+
+/// Entries per page unless asked otherwise
+pub const DEFAULT_AUDIT_PAGE_SIZE: usize = 100;
+
+/// Most entries one page may hold
+pub const MAX_AUDIT_PAGE_SIZE: usize = 1000;
+
+/// Filters and paging for audit log queries; unset filters match everything
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AuditQuery {
+    pub llm_id: Option<String>,
+    pub since: Option<DateTime<Utc>>,
+    pub until: Option<DateTime<Utc>>,
+    pub approved: Option<bool>,
+    /// Only entries logged for this user action
+    pub trace_id: Option<Uuid>,
+    /// Matching entries to skip, newest first
+    pub offset: usize,
+    pub limit: Option<usize>,
+}
+
+impl AuditQuery {
+    pub fn matches(&self, entry: &AuditLogEntry) -> bool {
+        self.llm_id.as_ref().is_none_or(|llm_id| entry.llm_id.as_ref() == Some(llm_id))
+            && self.since.is_none_or(|since| entry.timestamp >= since)
+            && self.until.is_none_or(|until| entry.timestamp < until)
+            && self.approved.is_none_or(|approved| entry.approved == approved)
+            && self.trace_id.is_none_or(|trace_id| entry.context.as_ref().is_some_and(|context| context.trace_id == trace_id))
+    }
+}
+
+/// One page of matching entries, newest first
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuditPage {
+    pub entries: Vec<AuditLogEntry>,
+    /// Matching entries across all pages
+    pub total: usize,
+}
 
 /// Audit logger for tracking all system actions
 pub struct AuditLogger {
     /// In-memory log (in production, this would be a database)
     logs: Arc<RwLock<Vec<AuditLogEntry>>>,
+    /// Append-only JSON lines file the log survives restarts in
+    file: Option<PathBuf>,
+    /// New entries as they are logged, for live views
+    events: broadcast::Sender<AuditLogEntry>,
 }
 
 impl AuditLogger {
     pub fn new() -> Self {
         Self {
             logs: Arc::new(RwLock::new(Vec::new())),
+            file: None,
+            events: broadcast::channel(256).0,
         }
     }
 
+    /// Keep the log in a JSON lines file, loading what earlier runs wrote
+    /// Unreadable lines are skipped rather than losing the rest of the log
+    pub fn open(path: &Path) -> Result<Self> {
+        let fs_err = |e: std::io::Error| HybridLLMError::FileSystemError(format!("{}: {}", path.display(), e));
+
+        let mut logs = Vec::new();
+        match std::fs::File::open(path) {
+            Ok(file) => {
+                for line in std::io::BufReader::new(file).lines() {
+                    let line = line.map_err(fs_err)?;
+                    if line.trim().is_empty() {
+                        continue;
+                    }
+                    match serde_json::from_str(&line) {
+                        Ok(entry) => logs.push(entry),
+                        Err(e) => warn!("⚠️  Skipping unreadable audit entry in {:?}: {}", path, e),
+                    }
+                }
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                if let Some(parent) = path.parent() {
+                    std::fs::create_dir_all(parent).map_err(fs_err)?;
+                }
+            }
+            Err(e) => return Err(fs_err(e)),
+        }
+
+        info!("📋 Audit log at {:?} ({} entries)", path, logs.len());
+        Ok(Self {
+            logs: Arc::new(RwLock::new(logs)),
+            file: Some(path.to_path_buf()),
+            events: broadcast::channel(256).0,
+        })
+    }
+
     /// Log an action
     pub async fn log(
         &self,
@@ -26,6 +112,30 @@ impl AuditLogger {
         details: serde_json::Value,
         approved: bool,
         reason: Option<String>,
+    ) {
+        self.record(llm_id, None, action, details, approved, reason).await;
+    }
+
+    /// Log an action taken as part of `context`, attributed to its LLM
+    pub async fn log_in(
+        &self,
+        context: &RequestContext,
+        action: String,
+        details: serde_json::Value,
+        approved: bool,
+        reason: Option<String>,
+    ) {
+        self.record(context.llm_id.clone(), Some(context.clone()), action, details, approved, reason).await;
+    }
+
+    async fn record(
+        &self,
+        llm_id: Option<String>,
+        context: Option<RequestContext>,
+        action: String,
+        details: serde_json::Value,
+        approved: bool,
+        reason: Option<String>,
     ) {
         let entry = AuditLogEntry {
             id: Uuid::new_v4(),
@@ -35,14 +145,50 @@ impl AuditLogger {
             details,
             approved,
             reason,
+            context,
         };
 
         debug!("📋 Audit log: {} - {}", action, if approved { "✅" } else { "❌" });
 
+        // Appended under the lock so the file keeps the same order as memory
         let mut logs = self.logs.write().await;
+        if let Some(path) = &self.file {
+            if let Err(e) = Self::append(path, &entry) {
+                error!("❌ Failed to persist audit entry to {:?}: {}", path, e);
+            }
+        }
+        // No subscribers is fine
+        let _ = self.events.send(entry.clone());
         logs.push(entry);
     }
 
+    /// Receive each entry as it is logged
+    pub fn subscribe(&self) -> broadcast::Receiver<AuditLogEntry> {
+        self.events.subscribe()
+    }
+
+    fn append(path: &Path, entry: &AuditLogEntry) -> std::io::Result<()> {
+        let mut line = serde_json::to_vec(entry)?;
+        line.push(b'\n');
+        std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)?
+            .write_all(&line)
+    }
+
+    /// Entries matching `query`, newest first, one page at a time
+    pub async fn query(&self, query: &AuditQuery) -> AuditPage {
+        let limit = query.limit.unwrap_or(DEFAULT_AUDIT_PAGE_SIZE).clamp(1, MAX_AUDIT_PAGE_SIZE);
+        let logs = self.logs.read().await;
+
+        let matching = logs.iter().rev().filter(|entry| query.matches(entry));
+        let total = matching.clone().count();
+        let entries = matching.skip(query.offset).take(limit).cloned().collect();
+
+        AuditPage { entries, total }
+    }
+
     /// Get all logs
     pub async fn get_all(&self) -> Vec<AuditLogEntry> {
         let logs = self.logs.read().await;
@@ -88,3 +234,63 @@ impl Default for AuditLogger {
         Self::new()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_query_and_reopen() {
+        let path = std::env::temp_dir().join(format!("audit-{}", Uuid::new_v4())).join("audit.log");
+        let audit = AuditLogger::open(&path).unwrap();
+        for i in 0..5 {
+            let llm_id = if i % 2 == 0 { "local" } else { "claude" };
+            audit.log(Some(llm_id.to_string()), format!("action {}", i), serde_json::json!({}), i != 3, None).await;
+        }
+
+        let page = audit.query(&AuditQuery { llm_id: Some("local".to_string()), limit: Some(2), ..Default::default() }).await;
+        assert_eq!(page.total, 3);
+        assert_eq!(page.entries.iter().map(|e| e.action.as_str()).collect::<Vec<_>>(), ["action 4", "action 2"]);
+
+        let page = audit.query(&AuditQuery { llm_id: Some("local".to_string()), offset: 2, ..Default::default() }).await;
+        assert_eq!(page.entries.len(), 1);
+        assert_eq!(page.entries[0].action, "action 0");
+
+        let denied = audit.query(&AuditQuery { approved: Some(false), ..Default::default() }).await;
+        assert_eq!(denied.total, 1);
+        let future = audit.query(&AuditQuery { since: Some(Utc::now() + chrono::Duration::hours(1)), ..Default::default() }).await;
+        assert_eq!(future.total, 0);
+
+        // A restart picks up what was written
+        let reopened = AuditLogger::open(&path).unwrap();
+        assert_eq!(reopened.count().await, 5);
+        assert_eq!(reopened.query(&AuditQuery::default()).await.entries[0].action, "action 4");
+
+        let _ = std::fs::remove_dir_all(path.parent().unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_subscribe() {
+        let audit = AuditLogger::new();
+        let mut events = audit.subscribe();
+        audit.log(None, "Lockdown".to_string(), serde_json::json!({}), true, None).await;
+
+        let entry = events.recv().await.unwrap();
+        assert_eq!(entry.action, "Lockdown");
+        assert_eq!(audit.get_all().await[0].id, entry.id);
+    }
+
+    #[tokio::test]
+    async fn test_query_by_trace() {
+        let audit = AuditLogger::new();
+        let context = RequestContext::user().with_llm("local");
+        audit.log_in(&context, "Tool call".to_string(), serde_json::json!({}), true, None).await;
+        audit.log_in(&context, "Permission request".to_string(), serde_json::json!({}), false, None).await;
+        audit.log_in(&RequestContext::background(), "Health check".to_string(), serde_json::json!({}), true, None).await;
+        audit.log(Some("local".to_string()), "Untraced".to_string(), serde_json::json!({}), true, None).await;
+
+        let page = audit.query(&AuditQuery { trace_id: Some(context.trace_id), ..Default::default() }).await;
+        assert_eq!(page.total, 2);
+        assert!(page.entries.iter().all(|entry| entry.llm_id.as_deref() == Some("local")));
+    }
+}
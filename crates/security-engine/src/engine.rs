@@ -1,31 +1,143 @@
 use common::{
+    config::{JailbreakAction, DEFAULT_MAX_FAILED_REQUESTS},
     errors::{Result, HybridLLMError},
     messages::PermissionType,
-    traits::{SecurityEngine, SecurityAnalysis},
-    types::{LockdownState, LockdownReason},
+    traits::{
+        CommandAnalyzer, PlannedAction, PromptGuard, RiskLevel, ScreenedPrompt, SecurityEngine, SecurityAnalysis,
+        SimulatedDecision,
+    },
+    types::{
+        ArtifactScanReport, ArtifactTransfer, LockdownState, LockdownReason, PortForwardRequest,
+        RequestContext, SandboxUsage, ScanVerdict,
+    },
 };
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 use std::sync::Arc;
-use tokio::sync::RwLock;
+use std::time::Duration;
+use tokio::sync::{broadcast, RwLock};
 use tracing::{info, warn, error};
 
-use crate::{Guardrails, PermissionManager, AuditLogger};
+use uuid::Uuid;
+
+use crate::{
+    Guardrails, PermissionManager, AuditLogger, ApprovalEvent, ArtifactApprovals, ArtifactScanner,
+    PendingApproval, PermissionApproval, PermissionApprovals, PortForwardApprovals, UsageMonitor,
+};
 
 /// Implementation of the SecurityEngine trait
 pub struct SecurityEngineImpl {
     guardrails: Arc<Guardrails>,
     permissions: Arc<PermissionManager>,
     audit: Arc<AuditLogger>,
+    approval_events: broadcast::Sender<ApprovalEvent>,
+    permission_approvals: Arc<PermissionApprovals>,
+    artifact_approvals: Arc<ArtifactApprovals>,
+    port_forward_approvals: Arc<PortForwardApprovals>,
+    artifact_scanner: Arc<ArtifactScanner>,
+    usage_monitor: Arc<UsageMonitor>,
     lockdown_state: Arc<RwLock<LockdownState>>,
+    max_failed_requests: AtomicUsize,
+    /// Consulted after the guardrails, e.g. analyzer plugins
+    analyzers: std::sync::RwLock<Vec<Arc<dyn CommandAnalyzer>>>,
+    jailbreak_action: std::sync::RwLock<JailbreakAction>,
+    /// Findings the user should hear about as they happen
+    alerts: broadcast::Sender<SecurityAnalysis>,
+    /// While set, network access is refused without asking the user
+    offline: AtomicBool,
 }
 
 impl SecurityEngineImpl {
     pub fn new() -> Self {
+        let (approval_events, _) = broadcast::channel(64);
         Self {
             guardrails: Arc::new(Guardrails::new()),
             permissions: Arc::new(PermissionManager::new()),
             audit: Arc::new(AuditLogger::new()),
+            permission_approvals: Arc::new(PermissionApprovals::with_events(approval_events.clone())),
+            artifact_approvals: Arc::new(ArtifactApprovals::with_events(approval_events.clone())),
+            port_forward_approvals: Arc::new(PortForwardApprovals::with_events(approval_events.clone())),
+            approval_events,
+            artifact_scanner: Arc::new(ArtifactScanner::default()),
+            usage_monitor: Arc::new(UsageMonitor::new()),
             lockdown_state: Arc::new(RwLock::new(LockdownState::Normal)),
+            max_failed_requests: AtomicUsize::new(DEFAULT_MAX_FAILED_REQUESTS),
+            analyzers: std::sync::RwLock::new(Vec::new()),
+            jailbreak_action: std::sync::RwLock::new(JailbreakAction::default()),
+            alerts: broadcast::channel(64).0,
+            offline: AtomicBool::new(false),
+        }
+    }
+
+    /// Use `audit` instead of the in-memory audit log, e.g. one backed by a file
+    pub fn with_audit_logger(mut self, audit: AuditLogger) -> Self {
+        self.audit = Arc::new(audit);
+        self
+    }
+
+    /// Change how many denied permission requests from one LLM trigger a lockdown
+    pub fn set_max_failed_requests(&self, max: usize) {
+        self.max_failed_requests.store(max.max(1), Ordering::Relaxed);
+    }
+
+    /// Change whether jailbreak scaffolding in constructed prompts is stripped or only flagged
+    pub fn set_jailbreak_action(&self, action: JailbreakAction) {
+        *self.jailbreak_action.write().unwrap() = action;
+    }
+
+    /// Refuse every network access permission while `offline`, whatever the policy or the user would say
+    pub fn set_offline(&self, offline: bool) {
+        self.offline.store(offline, Ordering::Relaxed);
+    }
+
+    fn blocked_offline(&self, permission: &PermissionType) -> bool {
+        self.offline.load(Ordering::Relaxed) && matches!(permission, PermissionType::NetworkAccess { .. })
+    }
+
+    /// Check commands with `analyzer` as well as the guardrails; the highest risk either finds decides
+    pub fn add_analyzer(&self, analyzer: Arc<dyn CommandAnalyzer>) {
+        info!("🔍 Adding command analyzer {}", analyzer.name());
+        self.analyzers.write().unwrap().push(analyzer);
+    }
+
+    /// Stop writes, code execution and network access while reads and answers go on
+    /// Does nothing during a full lockdown, which stays in place; returns whether the state changed
+    pub async fn enter_read_only(&self, reason: LockdownReason) -> bool {
+        let mut state = self.lockdown_state.write().await;
+        if *state != LockdownState::Normal {
+            return false;
+        }
+        *state = LockdownState::ReadOnly;
+        drop(state);
+
+        warn!("🟡 Read-only mode entered: {:?}", reason);
+        self.audit
+            .log(
+                None,
+                "Read-only mode entered".to_string(),
+                serde_json::json!({ "reason": format!("{:?}", reason) }),
+                false,
+                Some(format!("Read-only: {:?}", reason)),
+            )
+            .await;
+        true
+    }
+
+    /// Leave read-only mode; a full lockdown still needs `release_lockdown`
+    /// Returns whether the state changed
+    pub async fn exit_read_only(&self) -> bool {
+        let mut state = self.lockdown_state.write().await;
+        if *state != LockdownState::ReadOnly {
+            return false;
         }
+        *state = LockdownState::Normal;
+        drop(state);
+
+        info!("✅ Read-only mode exited");
+        self.audit
+            .log(None, "Read-only mode exited".to_string(), serde_json::json!({}), true, None)
+            .await;
+        true
     }
 
     /// Get the permission manager
@@ -37,23 +149,354 @@ impl SecurityEngineImpl {
     pub fn audit(&self) -> Arc<AuditLogger> {
         Arc::clone(&self.audit)
     }
+
+    /// Scan an artifact before it is offered for transfer
+    pub async fn scan_artifact(&self, llm_id: &str, path: &Path) -> Result<ArtifactScanReport> {
+        let report = self.artifact_scanner.scan(path).await?;
+
+        if report.verdict != ScanVerdict::Clean {
+            warn!("⚠️  Artifact scan of {:?}: {:?} ({} findings)", path, report.verdict, report.findings.len());
+        }
+
+        self.audit
+            .log(
+                Some(llm_id.to_string()),
+                "Artifact scan".to_string(),
+                serde_json::json!({
+                    "file": path.file_name().map(|n| n.to_string_lossy().into_owned()),
+                    "report": report,
+                }),
+                report.verdict != ScanVerdict::Blocked,
+                if report.verdict == ScanVerdict::Blocked {
+                    Some("Blocked by artifact scan".to_string())
+                } else {
+                    None
+                },
+            )
+            .await;
+
+        Ok(report)
+    }
+
+    /// Grant a permission by policy, or ask the user when the policy doesn't
+    /// Denied when locked down, for network access while offline, on explicit denial, or when `timeout` elapses
+    pub async fn request_permission_approval(
+        &self,
+        context: &RequestContext,
+        request_id: Uuid,
+        permission: PermissionType,
+        explanation: &str,
+        timeout: Duration,
+    ) -> Result<bool> {
+        if self.check_permission(context, &permission, explanation).await? {
+            return Ok(true);
+        }
+        // The policy check may have just triggered a lockdown; offline, the user isn't asked either
+        if !permitted_during(*self.lockdown_state.read().await, &permission) || self.blocked_offline(&permission) {
+            return Ok(false);
+        }
+
+        let details = serde_json::json!({
+            "request_id": request_id,
+            "permission": permission,
+            "explanation": explanation,
+        });
+        let request = PermissionApproval {
+            permission,
+            explanation: explanation.to_string(),
+        };
+        let approved = self.permission_approvals.request(context.actor(), request_id, request, timeout).await;
+
+        self.audit
+            .log_in(
+                context,
+                "Permission approval".to_string(),
+                details,
+                approved,
+                if approved { None } else { Some("Denied by user or timed out".to_string()) },
+            )
+            .await;
+
+        Ok(approved)
+    }
+
+    /// Ask the user to approve moving an artifact out of a sandbox
+    /// Denied when locked down, on explicit denial, or when `timeout` elapses
+    pub async fn request_artifact_approval(
+        &self,
+        llm_id: &str,
+        request_id: Uuid,
+        transfer: ArtifactTransfer,
+        timeout: Duration,
+    ) -> bool {
+        let details = serde_json::json!({
+            "request_id": request_id,
+            "sandbox_id": transfer.sandbox_id,
+            "file_path": transfer.file_path,
+            "destination": transfer.destination,
+            "explanation": transfer.explanation,
+        });
+
+        // Both write to the host, so read-only mode refuses them too
+        let locked = !self.lockdown_state.read().await.allows_writes();
+        let approved = if locked {
+            error!("🔒 System locked down, denying artifact transfer");
+            false
+        } else {
+            self.artifact_approvals.request(llm_id, request_id, transfer, timeout).await
+        };
+
+        self.audit
+            .log(
+                Some(llm_id.to_string()),
+                "Artifact transfer approval".to_string(),
+                details,
+                approved,
+                if locked {
+                    Some("System is locked down".to_string())
+                } else if !approved {
+                    Some("Denied by user or timed out".to_string())
+                } else {
+                    None
+                },
+            )
+            .await;
+
+        approved
+    }
+
+    /// Record the user's decision on a pending artifact transfer
+    pub async fn resolve_artifact_approval(&self, request_id: Uuid, approved: bool) -> Result<()> {
+        if self.artifact_approvals.resolve(request_id, approved).await {
+            Ok(())
+        } else {
+            Err(HybridLLMError::InvalidRequest(format!(
+                "No pending artifact transfer: {}",
+                request_id
+            )))
+        }
+    }
+
+    /// Artifact transfers awaiting a decision
+    pub async fn pending_artifact_approvals(&self) -> Vec<(Uuid, ArtifactTransfer)> {
+        self.artifact_approvals.pending().await
+    }
+
+    /// Ask the user to approve exposing a sandbox port on localhost
+    /// Denied when locked down, on explicit denial, or when `timeout` elapses
+    pub async fn request_port_forward_approval(
+        &self,
+        llm_id: &str,
+        request_id: Uuid,
+        request: PortForwardRequest,
+        timeout: Duration,
+    ) -> bool {
+        let details = serde_json::json!({
+            "request_id": request_id,
+            "sandbox_id": request.sandbox_id,
+            "sandbox_port": request.sandbox_port,
+            "duration_secs": request.duration_secs,
+            "explanation": request.explanation,
+        });
+
+        // Both write to the host, so read-only mode refuses them too
+        let locked = !self.lockdown_state.read().await.allows_writes();
+        let approved = if locked {
+            error!("🔒 System locked down, denying port forward");
+            false
+        } else {
+            self.port_forward_approvals.request(llm_id, request_id, request, timeout).await
+        };
+
+        self.audit
+            .log(
+                Some(llm_id.to_string()),
+                "Port forward approval".to_string(),
+                details,
+                approved,
+                if locked {
+                    Some("System is locked down".to_string())
+                } else if !approved {
+                    Some("Denied by user or timed out".to_string())
+                } else {
+                    None
+                },
+            )
+            .await;
+
+        approved
+    }
+
+    /// Record the user's decision on a pending port forward
+    pub async fn resolve_port_forward_approval(&self, request_id: Uuid, approved: bool) -> Result<()> {
+        if self.port_forward_approvals.resolve(request_id, approved).await {
+            Ok(())
+        } else {
+            Err(HybridLLMError::InvalidRequest(format!(
+                "No pending port forward: {}",
+                request_id
+            )))
+        }
+    }
+
+    /// Port forwards awaiting a decision
+    pub async fn pending_port_forward_approvals(&self) -> Vec<(Uuid, PortForwardRequest)> {
+        self.port_forward_approvals.pending().await
+    }
+
+    /// Everything awaiting a decision, oldest first
+    pub async fn pending_approvals(&self) -> Vec<PendingApproval> {
+        let mut pending = self.permission_approvals.pending_approvals().await;
+        pending.extend(self.artifact_approvals.pending_approvals().await);
+        pending.extend(self.port_forward_approvals.pending_approvals().await);
+        pending.sort_by_key(|approval| approval.requested_at);
+        pending
+    }
+
+    /// Record the user's decision on any pending request
+    pub async fn resolve_approval(&self, request_id: Uuid, approved: bool) -> Result<()> {
+        let resolved = self.permission_approvals.resolve(request_id, approved).await
+            || self.artifact_approvals.resolve(request_id, approved).await
+            || self.port_forward_approvals.resolve(request_id, approved).await;
+
+        if resolved {
+            Ok(())
+        } else {
+            Err(HybridLLMError::InvalidRequest(format!(
+                "No pending approval: {}",
+                request_id
+            )))
+        }
+    }
+
+    /// Subscribe to approval requests and their outcomes
+    pub fn subscribe_approvals(&self) -> broadcast::Receiver<ApprovalEvent> {
+        self.approval_events.subscribe()
+    }
+
+    /// Subscribe to security alerts, such as jailbreak scaffolding found in a constructed prompt
+    pub fn subscribe_alerts(&self) -> broadcast::Receiver<SecurityAnalysis> {
+        self.alerts.subscribe()
+    }
+
+    /// Check a sandbox usage sample for cryptomining-style behavior
+    pub async fn analyze_sandbox_usage(&self, usage: &SandboxUsage) -> SecurityAnalysis {
+        let analysis = self.usage_monitor.analyze(usage);
+        if !analysis.issues.is_empty() {
+            self.audit
+                .log(
+                    None,
+                    "Suspicious sandbox usage".to_string(),
+                    serde_json::json!({
+                        "usage": usage,
+                        "issues": analysis.issues,
+                    }),
+                    false,
+                    Some(format!("Risk level: {:?}", analysis.risk_level)),
+                )
+                .await;
+        }
+        analysis
+    }
+
+    /// Check an outbound sandbox connection for mining pool traffic
+    pub async fn analyze_sandbox_connection(&self, sandbox_id: Uuid, host: &str, port: u16) -> SecurityAnalysis {
+        let analysis = self.usage_monitor.analyze_connection(host, port);
+        if !analysis.issues.is_empty() {
+            self.audit
+                .log(
+                    None,
+                    "Suspicious sandbox connection".to_string(),
+                    serde_json::json!({
+                        "sandbox_id": sandbox_id,
+                        "host": host,
+                        "port": port,
+                        "issues": analysis.issues,
+                    }),
+                    false,
+                    Some(format!("Risk level: {:?}", analysis.risk_level)),
+                )
+                .await;
+        }
+        analysis
+    }
+
+    /// The guardrails' and analyzers' verdict on `command`, unaudited
+    async fn inspect_command(&self, command: &str) -> Result<SecurityAnalysis> {
+        let mut analysis = self.guardrails.analyze_command(command)?;
+        // An analyzer that fails is passed over, so a broken plugin can't block every command
+        let analyzers = self.analyzers.read().unwrap().clone();
+        for analyzer in analyzers {
+            match analyzer.analyze(command).await {
+                Ok(found) => {
+                    if (found.risk_level as u8) > (analysis.risk_level as u8) {
+                        analysis.risk_level = found.risk_level;
+                    }
+                    analysis.issues.extend(found.issues.into_iter().map(|issue| format!("{}: {}", analyzer.name(), issue)));
+                    analysis.suggestions.extend(found.suggestions);
+                    analysis.safe &= found.safe;
+                }
+                Err(e) => warn!("⚠️  Command analyzer {} failed: {}", analyzer.name(), e),
+            }
+        }
+        analysis.safe &= analysis.risk_level as u8 <= RiskLevel::Medium as u8;
+        Ok(analysis)
+    }
+}
+
+#[async_trait::async_trait]
+impl PromptGuard for SecurityEngineImpl {
+    async fn screen(&self, context: Option<&RequestContext>, llm_id: &str, text: &str) -> Result<ScreenedPrompt> {
+        let analysis = self.guardrails.analyze_prompt(text);
+        if analysis.issues.is_empty() {
+            return Ok(ScreenedPrompt { text: text.to_string(), analysis });
+        }
+
+        let action = *self.jailbreak_action.read().unwrap();
+        warn!("🛡️  Jailbreak scaffolding in a prompt for {} ({:?})", llm_id, action);
+        let details = serde_json::json!({
+            "llm_id": llm_id,
+            "issues": analysis.issues,
+            "action": action,
+        });
+        let reason = Some(format!("Risk level: {:?}", analysis.risk_level));
+        let entry = "Jailbreak pattern in constructed prompt".to_string();
+        // Recorded as let through only when the flagged lines went out
+        let sent = action == JailbreakAction::Flag;
+        match context {
+            Some(context) => self.audit.log_in(context, entry, details, sent, reason).await,
+            None => self.audit.log(Some(llm_id.to_string()), entry, details, sent, reason).await,
+        }
+        let _ = self.alerts.send(analysis.clone());
+
+        let text = match action {
+            JailbreakAction::Flag => text.to_string(),
+            JailbreakAction::Strip => self.guardrails.strip_jailbreaks(text),
+        };
+        Ok(ScreenedPrompt { text, analysis })
+    }
 }
 
 #[async_trait::async_trait]
 impl SecurityEngine for SecurityEngineImpl {
     async fn check_permission(
         &self,
-        llm_id: &str,
+        context: &RequestContext,
         permission: &PermissionType,
         explanation: &str,
     ) -> Result<bool> {
+        let llm_id = context.actor();
+
         // Check current lockdown state
-        let state = self.lockdown_state.read().await;
-        if *state == LockdownState::Locked {
-            error!("🔒 System locked, denying permission request");
+        let state = *self.lockdown_state.read().await;
+        if !permitted_during(state, permission) {
+            error!("🔒 System is {:?}, denying permission request", state);
+            return Ok(false);
+        }
+        if self.blocked_offline(permission) {
+            warn!("📴 Offline, denying {:?}", permission);
             return Ok(false);
         }
-        drop(state);
 
         // Check permission
         let granted = self.permissions
@@ -62,8 +505,8 @@ impl SecurityEngine for SecurityEngineImpl {
 
         // Log the decision
         self.audit
-            .log(
-                Some(llm_id.to_string()),
+            .log_in(
+                context,
                 format!("Permission request: {:?}", permission),
                 serde_json::json!({
                     "permission": permission,
@@ -81,7 +524,7 @@ impl SecurityEngine for SecurityEngineImpl {
         // Check if too many failed requests
         if !granted {
             let failed_count = self.permissions.get_failed_count(llm_id).await;
-            if failed_count >= 5 {
+            if failed_count >= self.max_failed_requests.load(Ordering::Relaxed) {
                 warn!("⚠️  LLM {} has {} failed requests, triggering lockdown", llm_id, failed_count);
                 self.trigger_lockdown(LockdownReason::MultipleFailedRequests {
                     count: failed_count,
@@ -94,7 +537,7 @@ impl SecurityEngine for SecurityEngineImpl {
     }
 
     async fn analyze_command(&self, command: &str) -> Result<SecurityAnalysis> {
-        let analysis = self.guardrails.analyze_command(command)?;
+        let analysis = self.inspect_command(command).await?;
 
         // Log the analysis
         self.audit
@@ -179,6 +622,43 @@ impl SecurityEngine for SecurityEngineImpl {
         let state = self.lockdown_state.read().await;
         Ok(*state)
     }
+
+    async fn simulate(&self, context: &RequestContext, action: &PlannedAction) -> Result<SimulatedDecision> {
+        let permission = match action {
+            PlannedAction::Permission { permission } => permission.clone(),
+            PlannedAction::Command { command } => {
+                let analysis = self.inspect_command(command).await?;
+                if !analysis.safe {
+                    return Ok(SimulatedDecision::Denied {
+                        reason: format!("Risk level {:?}: {}", analysis.risk_level, analysis.issues.join("; ")),
+                    });
+                }
+                PermissionType::Command { command: command.clone() }
+            }
+        };
+
+        let state = *self.lockdown_state.read().await;
+        if !permitted_during(state, &permission) {
+            return Ok(SimulatedDecision::Denied { reason: format!("System is {:?}", state) });
+        }
+        if self.blocked_offline(&permission) {
+            return Ok(SimulatedDecision::Denied { reason: "The platform is offline".to_string() });
+        }
+        Ok(if self.permissions.evaluate(context.actor(), &permission).await {
+            SimulatedDecision::Allowed
+        } else {
+            SimulatedDecision::NeedsApproval { reason: "Not granted by policy".to_string() }
+        })
+    }
+}
+
+/// Whether `permission` may be granted at all in lockdown `state`; read-only mode only grants file reads
+fn permitted_during(state: LockdownState, permission: &PermissionType) -> bool {
+    match state {
+        LockdownState::Normal => true,
+        LockdownState::ReadOnly => matches!(permission, PermissionType::FileRead { .. }),
+        LockdownState::Locked => false,
+    }
 }
 
 impl Default for SecurityEngineImpl {
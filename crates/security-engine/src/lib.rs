@@ -2,8 +2,20 @@ mod engine;
 mod guardrails;
 mod permissions;
 mod audit;
+mod approvals;
+mod scanner;
+mod malware;
+mod usage;
 
 pub use engine::SecurityEngineImpl;
-pub use guardrails::{Guardrails, GuardrailRule};
+pub use common::config::DEFAULT_MAX_FAILED_REQUESTS;
+pub use guardrails::{Guardrails, GuardrailCategory, GuardrailRule, STRIPPED_LINE};
 pub use permissions::PermissionManager;
-pub use audit::AuditLogger;
+pub use audit::{AuditLogger, AuditPage, AuditQuery, DEFAULT_AUDIT_PAGE_SIZE, MAX_AUDIT_PAGE_SIZE};
+pub use approvals::{
+    ApprovalEvent, ApprovalRequest, Approvals, ArtifactApprovals, PendingApproval, PermissionApproval,
+    PermissionApprovals, PortForwardApprovals,
+};
+pub use scanner::{ArtifactScanner, ArtifactScanConfig};
+pub use malware::{ClamdScanner, CommandScanner, detect_malware_scanner};
+pub use usage::UsageMonitor;
@@ -0,0 +1,163 @@
+use async_trait::async_trait;
+use common::{
+    errors::{Result, HybridLLMError},
+    traits::MalwareScanner,
+    types::MalwareScan,
+};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::process::Command;
+use tracing::{debug, info};
+
+/// Where distributions put the clamd control socket
+const CLAMD_SOCKETS: [&str; 3] = ["/run/clamav/clamd.ctl", "/var/run/clamav/clamd.ctl", "/run/clamd.scan/clamd.sock"];
+
+/// Bytes sent to clamd per INSTREAM chunk
+const CLAMD_CHUNK_BYTES: usize = 64 * 1024;
+
+/// Scans through a running clamd over its unix socket
+/// The file is streamed, so clamd needs no access to the path
+pub struct ClamdScanner {
+    socket: PathBuf,
+}
+
+impl ClamdScanner {
+    pub fn new(socket: impl Into<PathBuf>) -> Self {
+        Self { socket: socket.into() }
+    }
+}
+
+#[async_trait]
+impl MalwareScanner for ClamdScanner {
+    async fn scan_file(&self, path: &Path) -> Result<MalwareScan> {
+        let fs_err = |e: std::io::Error| HybridLLMError::FileSystemError(e.to_string());
+        let clamd_err = |e: std::io::Error| HybridLLMError::Other(anyhow::anyhow!("clamd: {}", e));
+
+        let mut file = tokio::fs::File::open(path).await.map_err(fs_err)?;
+        let mut stream = tokio::net::UnixStream::connect(&self.socket).await.map_err(clamd_err)?;
+        stream.write_all(b"zINSTREAM\0").await.map_err(clamd_err)?;
+
+        let mut chunk = vec![0; CLAMD_CHUNK_BYTES];
+        loop {
+            let read = file.read(&mut chunk).await.map_err(fs_err)?;
+            stream.write_all(&(read as u32).to_be_bytes()).await.map_err(clamd_err)?;
+            if read == 0 {
+                break;
+            }
+            stream.write_all(&chunk[..read]).await.map_err(clamd_err)?;
+        }
+
+        let mut reply = String::new();
+        stream.read_to_string(&mut reply).await.map_err(clamd_err)?;
+        let reply = reply.trim_end_matches(['\0', '\n']);
+        debug!("🦠 clamd replied for {:?}: {}", path, reply);
+
+        // "stream: OK", "stream: <signature> FOUND" or "<message> ERROR"
+        match reply.strip_prefix("stream: ") {
+            Some("OK") => Ok(MalwareScan::Clean),
+            Some(result) if result.ends_with(" FOUND") => Ok(MalwareScan::Infected {
+                signature: result.trim_end_matches(" FOUND").to_string(),
+            }),
+            _ => Err(HybridLLMError::Other(anyhow::anyhow!("clamd: {}", reply))),
+        }
+    }
+}
+
+/// Scans by running an external command with the file path appended
+/// Exit status 0 is clean and 1 is infected, as with `clamscan`; anything else is a scanner error
+pub struct CommandScanner {
+    program: String,
+    args: Vec<String>,
+}
+
+impl CommandScanner {
+    pub fn new(program: impl Into<String>, args: Vec<String>) -> Self {
+        Self { program: program.into(), args }
+    }
+
+    pub fn clamscan() -> Self {
+        Self::new("clamscan", vec!["--no-summary".to_string()])
+    }
+
+    pub fn clamdscan() -> Self {
+        Self::new("clamdscan", vec!["--no-summary".to_string(), "--fdpass".to_string()])
+    }
+}
+
+#[async_trait]
+impl MalwareScanner for CommandScanner {
+    async fn scan_file(&self, path: &Path) -> Result<MalwareScan> {
+        let output = Command::new(&self.program)
+            .args(&self.args)
+            .arg(path)
+            .output()
+            .await
+            .map_err(|e| HybridLLMError::Other(anyhow::anyhow!("{}: {}", self.program, e)))?;
+
+        match output.status.code() {
+            Some(0) => Ok(MalwareScan::Clean),
+            Some(1) => {
+                // "<path>: <signature> FOUND"
+                let report = String::from_utf8_lossy(&output.stdout);
+                let signature = report
+                    .lines()
+                    .find_map(|line| line.rsplit_once(": ")?.1.strip_suffix(" FOUND"))
+                    .unwrap_or("unknown signature")
+                    .to_string();
+                Ok(MalwareScan::Infected { signature })
+            }
+            _ => Err(HybridLLMError::Other(anyhow::anyhow!(
+                "{} failed: {}",
+                self.program,
+                String::from_utf8_lossy(&output.stderr).trim()
+            ))),
+        }
+    }
+}
+
+/// Pick a scanner for this machine: clamd's socket when it is running, otherwise `clamscan` if installed
+pub async fn detect_malware_scanner() -> Option<Arc<dyn MalwareScanner>> {
+    if let Some(socket) = CLAMD_SOCKETS.iter().map(Path::new).find(|socket| socket.exists()) {
+        info!("🦠 Scanning files through clamd at {:?}", socket);
+        return Some(Arc::new(ClamdScanner::new(socket)));
+    }
+
+    let installed = Command::new("clamscan").arg("--version").output().await.is_ok_and(|o| o.status.success());
+    if installed {
+        info!("🦠 Scanning files with clamscan");
+        return Some(Arc::new(CommandScanner::clamscan()));
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_command_scanner_reports_signature() {
+        let dir = std::env::temp_dir().join(format!("malware-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let (clean, infected) = (dir.join("clean.txt"), dir.join("infected.txt"));
+        std::fs::write(&clean, "hello\n").unwrap();
+        std::fs::write(&infected, "EICAR\n").unwrap();
+
+        // Stands in for clamscan: the path arrives as $0
+        let scanner = CommandScanner::new(
+            "sh",
+            vec!["-c".to_string(), r#"grep -q EICAR "$0" || exit 0; echo "$0: Eicar-Test FOUND"; exit 1"#.to_string()],
+        );
+        assert_eq!(scanner.scan_file(&clean).await.unwrap(), MalwareScan::Clean);
+        assert_eq!(
+            scanner.scan_file(&infected).await.unwrap(),
+            MalwareScan::Infected { signature: "Eicar-Test".to_string() }
+        );
+
+        let missing = CommandScanner::new("definitely-not-a-scanner", vec![]);
+        assert!(missing.scan_file(&clean).await.is_err());
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+}
@@ -37,10 +37,24 @@ impl PermissionManager {
         debug!("🔐 Checking permission for {}: {:?}", llm_id, permission);
         debug!("📝 Explanation: {}", explanation);
 
+        let granted = self.evaluate(llm_id, permission).await;
+
+        if granted {
+            info!("✅ Permission granted for {}: {:?}", llm_id, permission);
+        } else {
+            warn!("❌ Permission denied for {}: {:?}", llm_id, permission);
+            self.track_failed_request(llm_id).await;
+        }
+
+        Ok(granted)
+    }
+
+    /// Whether the policy grants `permission`, without counting a denial
+    pub async fn evaluate(&self, llm_id: &str, permission: &PermissionType) -> bool {
         // Get the applicable scope (LLM-specific or global)
         let scope = self.get_scope(llm_id).await;
 
-        let granted = match permission {
+        match permission {
             PermissionType::FileRead { path } => {
                 self.check_file_access(&scope.file_system.read_paths, path)
             }
@@ -54,22 +68,13 @@ impl PermissionManager {
                 self.check_command(&scope.commands, command)
             }
             PermissionType::NetworkAccess { url } => {
-                // Network requires approval by default
-                scope.network.outbound || scope.network.inbound
+                // Anything off the allowlist needs the user's approval
+                scope.network.allows_url(url)
             }
             PermissionType::ResourceIncrease { resource, amount } => {
                 self.check_resource_increase(&scope.resources, resource, *amount)
             }
-        };
-
-        if granted {
-            info!("✅ Permission granted for {}: {:?}", llm_id, permission);
-        } else {
-            warn!("❌ Permission denied for {}: {:?}", llm_id, permission);
-            self.track_failed_request(llm_id).await;
         }
-
-        Ok(granted)
     }
 
     /// Get the applicable permission scope for an LLM
@@ -206,6 +211,20 @@ mod tests {
         assert!(granted);
     }
 
+    #[tokio::test]
+    async fn test_network_allowlist() {
+        let manager = PermissionManager::new();
+        let mut scope = PermissionScope::default();
+        scope.network.allowed_domains = vec!["api.search.brave.com".to_string()];
+        manager.set_global_scope(scope).await;
+
+        let allowed = PermissionType::NetworkAccess { url: "https://api.search.brave.com/res/v1/web/search".to_string() };
+        assert!(manager.check_permission("test-llm", &allowed, "Search the web").await.unwrap());
+
+        let other = PermissionType::NetworkAccess { url: "https://evil.example/exfiltrate".to_string() };
+        assert!(!manager.check_permission("test-llm", &other, "Upload notes").await.unwrap());
+    }
+
     #[tokio::test]
     async fn test_command_whitelist() {
         let manager = PermissionManager::new();
@@ -237,4 +256,16 @@ mod tests {
 
         assert!(!granted);
     }
+
+    #[tokio::test]
+    async fn test_evaluate_does_not_count() {
+        let manager = PermissionManager::new();
+        let perm = PermissionType::Command { command: "rm -rf /".to_string() };
+
+        assert!(!manager.evaluate("test-llm", &perm).await);
+        assert_eq!(manager.get_failed_count("test-llm").await, 0);
+
+        manager.check_permission("test-llm", &perm, "Delete files").await.unwrap();
+        assert_eq!(manager.get_failed_count("test-llm").await, 1);
+    }
 }
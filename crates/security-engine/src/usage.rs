@@ -0,0 +1,197 @@
+use chrono::{DateTime, Duration, Utc};
+use common::{
+    traits::{SecurityAnalysis, RiskLevel},
+    types::SandboxUsage,
+};
+use std::collections::HashMap;
+use std::sync::Mutex;
+use tracing::warn;
+use uuid::Uuid;
+
+/// Ports commonly used by stratum mining pools
+const MINING_PORTS: [u16; 8] = [3333, 4444, 5555, 7777, 8333, 9999, 14444, 45700];
+
+/// Host fragments of well-known mining pools and protocols
+const MINING_HOSTS: [&str; 12] = [
+    "stratum",
+    "xmr",
+    "monero",
+    "nicehash",
+    "nanopool",
+    "2miners",
+    "f2pool",
+    "ethermine",
+    "hashvault",
+    "minergate",
+    "miningpool",
+    "pool.",
+];
+
+/// A run of consecutive samples above the CPU threshold
+struct Streak {
+    since: DateTime<Utc>,
+    last_seen: DateTime<Utc>,
+    network_bytes_at_start: u64,
+    reported: bool,
+}
+
+/// Detects cryptomining-style behavior from sandbox usage samples
+pub struct UsageMonitor {
+    cpu_threshold_percent: f32,
+    sustained_for: Duration,
+    streaks: Mutex<HashMap<Uuid, Streak>>,
+}
+
+impl UsageMonitor {
+    pub fn new() -> Self {
+        Self::with_thresholds(80.0, Duration::minutes(10))
+    }
+
+    /// Flag sandboxes above `cpu_threshold_percent` for longer than `sustained_for`
+    pub fn with_thresholds(cpu_threshold_percent: f32, sustained_for: Duration) -> Self {
+        Self {
+            cpu_threshold_percent,
+            sustained_for,
+            streaks: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Analyze one usage sample; each high-CPU streak is reported at most once
+    pub fn analyze(&self, usage: &SandboxUsage) -> SecurityAnalysis {
+        let network_bytes = usage.network_sent_bytes + usage.network_received_bytes;
+        let mut streaks = self.streaks.lock().unwrap();
+
+        // Destroyed sandboxes stop reporting; drop their streaks eventually
+        let stale = usage.timestamp - self.sustained_for;
+        streaks.retain(|_, streak| streak.last_seen > stale);
+
+        if usage.cpu_percent < self.cpu_threshold_percent {
+            streaks.remove(&usage.sandbox_id);
+            return clean();
+        }
+
+        let streak = streaks.entry(usage.sandbox_id).or_insert(Streak {
+            since: usage.timestamp,
+            last_seen: usage.timestamp,
+            network_bytes_at_start: network_bytes,
+            reported: false,
+        });
+        streak.last_seen = usage.timestamp;
+
+        let sustained = usage.timestamp - streak.since >= self.sustained_for;
+        let talking = network_bytes > streak.network_bytes_at_start;
+        if !sustained || !talking || streak.reported {
+            return clean();
+        }
+        streak.reported = true;
+
+        warn!(
+            "⛏️  Sandbox {} at {:.0}% CPU for {} minutes with network traffic",
+            usage.sandbox_id,
+            usage.cpu_percent,
+            (usage.timestamp - streak.since).num_minutes()
+        );
+
+        SecurityAnalysis {
+            safe: false,
+            risk_level: RiskLevel::High,
+            issues: vec![format!(
+                "possible_cryptomining: sustained {:.0}% CPU since {} while using the network",
+                usage.cpu_percent, streak.since
+            )],
+            suggestions: vec!["Inspect the sandbox's processes or destroy it".to_string()],
+        }
+    }
+
+    /// Analyze an outbound sandbox connection for mining pool traffic
+    pub fn analyze_connection(&self, host: &str, port: u16) -> SecurityAnalysis {
+        let host = host.to_ascii_lowercase();
+        let known_host = MINING_HOSTS.iter().any(|fragment| host.contains(fragment));
+        let known_port = MINING_PORTS.contains(&port);
+
+        let risk_level = match (known_host, known_port) {
+            (true, true) => RiskLevel::Critical,
+            (true, false) => RiskLevel::High,
+            (false, true) => RiskLevel::Medium,
+            (false, false) => return clean(),
+        };
+
+        warn!("⛏️  Possible mining pool connection to {}:{}", host, port);
+
+        SecurityAnalysis {
+            safe: risk_level as u8 <= RiskLevel::Medium as u8,
+            risk_level,
+            issues: vec![format!("mining_pool_connection: {}:{} looks like a mining pool", host, port)],
+            suggestions: vec!["Block the host and inspect the sandbox".to_string()],
+        }
+    }
+}
+
+impl Default for UsageMonitor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn clean() -> SecurityAnalysis {
+    SecurityAnalysis {
+        safe: true,
+        risk_level: RiskLevel::Low,
+        issues: vec![],
+        suggestions: vec![],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample(sandbox_id: Uuid, minute: i64, cpu_percent: f32, network_bytes: u64) -> SandboxUsage {
+        SandboxUsage {
+            sandbox_id,
+            timestamp: DateTime::UNIX_EPOCH + Duration::minutes(minute),
+            cpu_percent,
+            memory_bytes: 0,
+            disk_bytes: 0,
+            network_sent_bytes: network_bytes,
+            network_received_bytes: 0,
+            processes: 1,
+        }
+    }
+
+    #[test]
+    fn test_sustained_cpu_with_network() {
+        let monitor = UsageMonitor::new();
+        let id = Uuid::new_v4();
+
+        assert!(monitor.analyze(&sample(id, 0, 95.0, 100)).safe);
+        assert!(monitor.analyze(&sample(id, 5, 95.0, 200)).safe);
+        let flagged = monitor.analyze(&sample(id, 10, 95.0, 300));
+        assert!(!flagged.safe);
+        assert_eq!(flagged.risk_level, RiskLevel::High);
+        // Reported once per streak
+        assert!(monitor.analyze(&sample(id, 11, 95.0, 400)).safe);
+
+        // Busy but offline, e.g. a long build
+        let offline = Uuid::new_v4();
+        assert!(monitor.analyze(&sample(offline, 0, 99.0, 0)).safe);
+        assert!(monitor.analyze(&sample(offline, 15, 99.0, 0)).safe);
+
+        // A dip below the threshold restarts the streak
+        let bursty = Uuid::new_v4();
+        monitor.analyze(&sample(bursty, 0, 95.0, 0));
+        monitor.analyze(&sample(bursty, 6, 10.0, 100));
+        assert!(monitor.analyze(&sample(bursty, 12, 95.0, 200)).safe);
+    }
+
+    #[test]
+    fn test_mining_pool_connection() {
+        let monitor = UsageMonitor::new();
+        assert_eq!(
+            monitor.analyze_connection("xmr.pool.minergate.com", 45700).risk_level,
+            RiskLevel::Critical
+        );
+        assert!(!monitor.analyze_connection("stratum.example.net", 443).safe);
+        assert!(monitor.analyze_connection("pypi.org", 443).issues.is_empty());
+    }
+}
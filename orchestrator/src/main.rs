@@ -1,30 +1,43 @@
+mod mcp;
 mod message_bus;
 mod router;
 mod orchestrator;
+mod tools;
 
 use anyhow::Result;
+use common::config::{PlatformConfig, DEFAULT_CONFIG_FILE};
+use common::telemetry;
+use std::path::Path;
 use tracing::{info, error};
-use tracing_subscriber;
 
 use crate::orchestrator::Orchestrator;
 
 #[tokio::main]
 async fn main() -> Result<()> {
-    // Initialize logging
-    tracing_subscriber::fmt()
-        .with_env_filter("hybrid_llm=debug,info")
-        .init();
+    // `hybrid-llm mcp` serves tools to an MCP client over stdio, so stdout carries only the protocol
+    let mcp = std::env::args().nth(1).as_deref() == Some("mcp");
+
+    // A broken config stops startup rather than running with settings nobody chose
+    // Read before logging starts, since it says where telemetry goes
+    let config = PlatformConfig::load(Path::new(DEFAULT_CONFIG_FILE))?;
+
+    // Initialize logging; held to the end so buffered telemetry is sent on exit
+    let _telemetry = telemetry::init("hybrid-llm", &config.telemetry, std::io::stderr);
 
     info!("🚀 Hybrid LLM Platform starting...");
 
     // Create and run orchestrator
-    let orchestrator = Orchestrator::new().await?;
+    let orchestrator = Orchestrator::new(config).await?;
 
     info!("✅ Orchestrator initialized");
     info!("🎯 System ready for LLM operations");
 
     // Run the orchestrator
-    orchestrator.run().await?;
+    if mcp {
+        orchestrator.serve_mcp().await?;
+    } else {
+        orchestrator.run().await?;
+    }
 
     Ok(())
 }
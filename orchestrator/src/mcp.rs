@@ -0,0 +1,278 @@
+use common::{
+    errors::{HybridLLMError, Result},
+    types::RequestContext,
+    ToolCall,
+};
+use security_engine::SecurityEngineImpl;
+use serde_json::{json, Value};
+use std::sync::{Arc, Mutex};
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader};
+use tokio::sync::mpsc;
+use tracing::{debug, info, Instrument};
+
+use crate::tools::ToolRegistry;
+
+/// Tools external agents get: RAG search, the document list and reading, and sandboxed code execution
+pub const MCP_TOOLS: &[&str] = &["rag_search", "list_documents", "read_file", "sandbox_exec"];
+
+/// Protocol revisions this server speaks, newest first
+const PROTOCOL_VERSIONS: &[&str] = &["2025-06-18", "2025-03-26", "2024-11-05"];
+
+// JSON-RPC error codes
+const PARSE_ERROR: i64 = -32700;
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+
+/// Serves platform tools to MCP clients over newline-delimited JSON-RPC, such as stdio
+/// Calls go through the same lockdown, permission and audit checks as calls from the platform's own LLMs
+pub struct McpServer {
+    tools: Arc<ToolRegistry>,
+    security_engine: Arc<SecurityEngineImpl>,
+    exported: Vec<String>,
+    /// Name the client gave when it connected, the actor of its calls in the audit log
+    client: Mutex<String>,
+}
+
+impl McpServer {
+    pub fn new(tools: Arc<ToolRegistry>, security_engine: Arc<SecurityEngineImpl>, exported: &[&str]) -> Self {
+        Self {
+            tools,
+            security_engine,
+            exported: exported.iter().map(|name| name.to_string()).collect(),
+            client: Mutex::new("mcp".to_string()),
+        }
+    }
+
+    /// Answer requests from `input` on `output` until the client closes `input`
+    /// Requests are handled concurrently, so a ping is answered while code is still running
+    pub async fn serve<R, W>(self: Arc<Self>, input: R, mut output: W) -> Result<()>
+    where
+        R: AsyncRead + Unpin,
+        W: AsyncWrite + Unpin,
+    {
+        let io_error = |e: std::io::Error| HybridLLMError::Other(anyhow::anyhow!("MCP transport failed: {}", e));
+        let (tx, mut responses) = mpsc::unbounded_channel::<Value>();
+        // Dropped at the end of the input, so the loop ends once every request is answered
+        let mut tx = Some(tx);
+        let mut lines = BufReader::new(input).lines();
+
+        loop {
+            tokio::select! {
+                line = lines.next_line(), if tx.is_some() => match line.map_err(io_error)? {
+                    Some(line) if line.trim().is_empty() => {}
+                    Some(line) => {
+                        let server = Arc::clone(&self);
+                        let tx = tx.clone();
+                        tokio::spawn(async move {
+                            if let (Some(response), Some(tx)) = (server.handle(&line).await, tx) {
+                                let _ = tx.send(response);
+                            }
+                        });
+                    }
+                    None => tx = None,
+                },
+                Some(response) = responses.recv() => {
+                    let mut line = response.to_string();
+                    line.push('\n');
+                    output.write_all(line.as_bytes()).await.map_err(io_error)?;
+                    output.flush().await.map_err(io_error)?;
+                }
+                else => break,
+            }
+        }
+
+        info!("🔌 MCP client {} disconnected", self.client.lock().unwrap());
+        Ok(())
+    }
+
+    /// The response to one JSON-RPC message; notifications get none
+    async fn handle(&self, line: &str) -> Option<Value> {
+        let message: Value = match serde_json::from_str(line) {
+            Ok(message) => message,
+            Err(e) => return Some(error_response(Value::Null, PARSE_ERROR, e.to_string())),
+        };
+        let id = message.get("id").cloned();
+        let Some(method) = message["method"].as_str() else {
+            return Some(error_response(id.unwrap_or_default(), INVALID_REQUEST, "Not a JSON-RPC request".to_string()));
+        };
+
+        debug!("🔌 MCP request: {}", method);
+        let result = self.dispatch(method, &message["params"]).await;
+        let id = id?;
+        Some(match result {
+            Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+            Err((code, message)) => error_response(id, code, message),
+        })
+    }
+
+    async fn dispatch(&self, method: &str, params: &Value) -> std::result::Result<Value, (i64, String)> {
+        match method {
+            "initialize" => Ok(self.initialize(params)),
+            "ping" => Ok(json!({})),
+            "tools/list" => Ok(json!({
+                "tools": self
+                    .tools
+                    .definitions()
+                    .into_iter()
+                    .filter(|tool| self.exported.contains(&tool.name))
+                    .map(|tool| json!({ "name": tool.name, "description": tool.description, "inputSchema": tool.parameters }))
+                    .collect::<Vec<_>>(),
+            })),
+            "tools/call" => self.call_tool(params).await,
+            method if method.starts_with("notifications/") => Ok(Value::Null),
+            method => Err((METHOD_NOT_FOUND, format!("Unknown method {}", method))),
+        }
+    }
+
+    fn initialize(&self, params: &Value) -> Value {
+        let client = params["clientInfo"]["name"].as_str().unwrap_or("unknown");
+        *self.client.lock().unwrap() = format!("mcp:{}", client);
+        info!("🔌 MCP client connected: {}", client);
+
+        // The client's revision when we speak it, else our newest for the client to decide on
+        let version = params["protocolVersion"]
+            .as_str()
+            .filter(|version| PROTOCOL_VERSIONS.contains(version))
+            .unwrap_or(PROTOCOL_VERSIONS[0]);
+        json!({
+            "protocolVersion": version,
+            "capabilities": { "tools": { "listChanged": false } },
+            "serverInfo": { "name": "hybrid-llm", "version": env!("CARGO_PKG_VERSION") },
+        })
+    }
+
+    async fn call_tool(&self, params: &Value) -> std::result::Result<Value, (i64, String)> {
+        let name = params["name"]
+            .as_str()
+            .filter(|name| self.exported.iter().any(|exported| exported == name))
+            .ok_or_else(|| (INVALID_PARAMS, format!("Unknown tool {}", params["name"])))?;
+        let arguments = match &params["arguments"] {
+            Value::Null => json!({}),
+            arguments => arguments.clone(),
+        };
+
+        // Each call is its own action, attributed to the client
+        let actor = self.client.lock().unwrap().clone();
+        let context = RequestContext::background().with_llm(actor);
+        let call = ToolCall { id: context.trace_id.to_string(), name: name.to_string(), arguments };
+        let result = self.tools.call(&*self.security_engine, &context, &call).instrument(context.span()).await;
+
+        self.security_engine
+            .audit()
+            .log_in(
+                &context,
+                "MCP tool call".to_string(),
+                json!({ "tool": call.name, "arguments": call.arguments }),
+                !result.is_error(),
+                result.error.clone(),
+            )
+            .await;
+
+        // Tool failures are results the agent should see, not protocol errors
+        let text = match (&result.output, &result.error) {
+            (Some(output), _) => output.to_string(),
+            (None, error) => error.clone().unwrap_or_default(),
+        };
+        Ok(json!({
+            "content": [{ "type": "text", "text": text }],
+            "isError": result.is_error(),
+        }))
+    }
+}
+
+fn error_response(id: Value, code: i64, message: String) -> Value {
+    json!({ "jsonrpc": "2.0", "id": id, "error": { "code": code, "message": message } })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use async_trait::async_trait;
+    use common::traits::Tool;
+
+    struct Echo;
+
+    #[async_trait]
+    impl Tool for Echo {
+        fn name(&self) -> &str {
+            "echo"
+        }
+        fn description(&self) -> &str {
+            "Echo the arguments"
+        }
+        fn parameters(&self) -> Value {
+            json!({ "type": "object", "required": ["text"] })
+        }
+        async fn execute(&self, _: &RequestContext, arguments: Value) -> Result<Value> {
+            Ok(arguments)
+        }
+    }
+
+    fn server() -> Arc<McpServer> {
+        let mut tools = ToolRegistry::new();
+        tools.register(Arc::new(Echo));
+        Arc::new(McpServer::new(Arc::new(tools), Arc::new(SecurityEngineImpl::new()), &["echo"]))
+    }
+
+    #[tokio::test]
+    async fn test_handle() {
+        let server = server();
+        let response = |line: &str| {
+            let server = Arc::clone(&server);
+            let line = line.to_string();
+            async move { server.handle(&line).await }
+        };
+
+        let initialized = response(
+            r#"{"jsonrpc":"2.0","id":1,"method":"initialize","params":{"protocolVersion":"2024-11-05","clientInfo":{"name":"agent"}}}"#,
+        )
+        .await
+        .unwrap();
+        assert_eq!(initialized["result"]["protocolVersion"], "2024-11-05");
+        assert!(response(r#"{"jsonrpc":"2.0","method":"notifications/initialized"}"#).await.is_none());
+
+        let listed = response(r#"{"jsonrpc":"2.0","id":2,"method":"tools/list"}"#).await.unwrap();
+        assert_eq!(listed["result"]["tools"][0]["name"], "echo");
+        assert_eq!(listed["result"]["tools"][0]["inputSchema"]["required"][0], "text");
+
+        let called = response(r#"{"jsonrpc":"2.0","id":3,"method":"tools/call","params":{"name":"echo","arguments":{"text":"hi"}}}"#)
+            .await
+            .unwrap();
+        assert_eq!(called["result"]["isError"], false);
+        assert_eq!(called["result"]["content"][0]["text"], r#"{"text":"hi"}"#);
+
+        let failed = response(r#"{"jsonrpc":"2.0","id":4,"method":"tools/call","params":{"name":"echo"}}"#).await.unwrap();
+        assert_eq!(failed["result"]["isError"], true);
+
+        let entries = server.security_engine.audit().get_by_llm("mcp:agent").await;
+        assert_eq!(entries.iter().filter(|entry| entry.action == "MCP tool call").count(), 2);
+
+        let unknown = response(r#"{"jsonrpc":"2.0","id":5,"method":"tools/call","params":{"name":"rm"}}"#).await.unwrap();
+        assert_eq!(unknown["error"]["code"], INVALID_PARAMS);
+        let missing = response(r#"{"jsonrpc":"2.0","id":6,"method":"resources/list"}"#).await.unwrap();
+        assert_eq!(missing["error"]["code"], METHOD_NOT_FOUND);
+        let garbled = response("{not json").await.unwrap();
+        assert_eq!(garbled["error"]["code"], PARSE_ERROR);
+    }
+
+    #[tokio::test]
+    async fn test_serve() {
+        let (client, server_end) = tokio::io::duplex(4096);
+        let (server_read, server_write) = tokio::io::split(server_end);
+        let serving = tokio::spawn(server().serve(server_read, server_write));
+
+        let (client_read, mut client_write) = tokio::io::split(client);
+        client_write
+            .write_all(b"{\"jsonrpc\":\"2.0\",\"id\":1,\"method\":\"ping\"}\n\n{\"jsonrpc\":\"2.0\",\"method\":\"notifications/initialized\"}\n")
+            .await
+            .unwrap();
+        client_write.shutdown().await.unwrap();
+
+        let mut lines = BufReader::new(client_read).lines();
+        let pong: Value = serde_json::from_str(&lines.next_line().await.unwrap().unwrap()).unwrap();
+        assert_eq!(pong, json!({ "jsonrpc": "2.0", "id": 1, "result": {} }));
+        serving.await.unwrap().unwrap();
+        assert!(lines.next_line().await.unwrap().is_none());
+    }
+}
@@ -45,8 +45,7 @@ impl MessageBus {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use common::messages::OrchestratorMessage;
-    use std::collections::HashMap;
+    use common::{messages::OrchestratorMessage, CompletionRequest};
 
     #[tokio::test]
     async fn test_message_bus() {
@@ -55,16 +54,15 @@ mod tests {
 
         let msg = OrchestratorMessage::UserRequest {
             id: Uuid::new_v4(),
-            content: "Test".to_string(),
-            context: HashMap::new(),
+            request: CompletionRequest::prompt("Test"),
         };
 
         bus.publish(msg.clone()).unwrap();
 
         let received = rx.recv().await.unwrap();
         match received {
-            OrchestratorMessage::UserRequest { content, .. } => {
-                assert_eq!(content, "Test");
+            OrchestratorMessage::UserRequest { request, .. } => {
+                assert_eq!(request.to_prompt(), "Test");
             }
             _ => panic!("Wrong message type"),
         }
@@ -1,13 +1,61 @@
 use common::{
-    messages::OrchestratorMessage,
-    errors::Result,
-    types::LockdownState,
+    config::{PlatformConfig, SandboxBackend, SearchBackend, SearchSettings},
+    messages::{AlertSeverity, OrchestratorMessage, StateChangeType, SuggestedAction},
+    errors::{Result, HybridLLMError},
+    traits::{ContextManager, PromptGuard},
+    CompletionRequest, ToolCall, ToolResult,
+    types::{
+        url_host, ArtifactTransfer, CodeLanguage, LockdownLevel, LockdownState, PermissionScope, PortForwardRequest,
+        RequestContext, SandboxTemplate, ScanVerdict,
+    },
 };
+use api_gateway::{PageFetcher, SearchEngine, WebSearch};
+use sandbox_manager::{
+    ExecutionResult, PluginConfig, PluginHost, PoolConfig, SandboxEvent, SandboxManager, WasmConfig,
+    WasmExecutor,
+};
+use context_manager::{DatabaseContextManager, InMemoryContextManager, Recrawler};
+use filesystem_interface::{FileSystemInterface, ManagedFolder, ObjectStorage, S3Config};
+use llm_pool::LLMPool;
+use security_engine::{detect_malware_scanner, SecurityEngineImpl};
 use std::sync::Arc;
+use std::time::Duration;
 use tokio::sync::RwLock;
-use tracing::{info, debug, error};
+use tracing::{info, debug, error, warn, Instrument};
+
+use crate::{
+    mcp::{McpServer, MCP_TOOLS},
+    message_bus::MessageBus,
+    router::Router,
+    tools::ToolRegistry,
+};
+
+/// How long a permission the policy doesn't grant waits for the user before being denied
+const PERMISSION_APPROVAL_TIMEOUT: Duration = Duration::from_secs(300);
+
+/// How long a transfer waits for the user before being denied
+const ARTIFACT_APPROVAL_TIMEOUT: Duration = Duration::from_secs(300);
 
-use crate::{message_bus::MessageBus, router::Router};
+/// How long a port forward request waits for the user before being denied
+const PORT_FORWARD_APPROVAL_TIMEOUT: Duration = Duration::from_secs(300);
+
+/// How often expired and idle sandboxes are reclaimed
+const SANDBOX_REAP_INTERVAL: Duration = Duration::from_secs(30);
+
+/// The web search client `settings` ask for, if any and if its key is available
+fn web_search(settings: &SearchSettings) -> Option<WebSearch> {
+    let engine = match settings.backend? {
+        SearchBackend::Searxng => SearchEngine::Searxng { url: settings.searxng_url.clone()? },
+        SearchBackend::Brave => match std::env::var(&settings.brave_api_key_env) {
+            Ok(api_key) if !api_key.is_empty() => SearchEngine::Brave { api_key },
+            _ => {
+                warn!("⚠️  {} not set, LLMs get no web search", settings.brave_api_key_env);
+                return None;
+            }
+        },
+    };
+    Some(WebSearch::new(engine, Duration::from_secs(settings.cache_secs)))
+}
 
 /// Main orchestrator that coordinates all system components
 pub struct Orchestrator {
@@ -17,21 +65,134 @@ pub struct Orchestrator {
     router: Arc<RwLock<Router>>,
     /// Current system lockdown state
     lockdown_state: Arc<RwLock<LockdownState>>,
+    /// WASM micro-sandbox for short code evaluations
+    wasm_executor: Arc<WasmExecutor>,
+    /// Full sandboxes for longer-running code execution
+    sandbox_manager: Arc<SandboxManager>,
+    /// Approvals and audit trail for sensitive actions
+    security_engine: Arc<SecurityEngineImpl>,
+    /// Managed downloads/uploads/rag folders and their quotas
+    filesystem: Arc<FileSystemInterface>,
+    /// Registered LLMs and the completions they are generating
+    llm_pool: Arc<LLMPool>,
+    /// Tools LLMs may call
+    tools: Arc<ToolRegistry>,
+    /// Conversations, RAG documents and contexts
+    context: Arc<dyn ContextManager>,
+    /// Keeps the documents of `recrawl.folders` and `recrawl.urls` current
+    recrawler: Arc<Recrawler>,
+    /// Settings the orchestrator was started with
+    config: PlatformConfig,
 }
 
 impl Orchestrator {
     /// Create a new orchestrator instance
-    pub async fn new() -> Result<Self> {
+    pub async fn new(config: PlatformConfig) -> Result<Self> {
         info!("🏗️  Initializing orchestrator...");
+        if config.sandbox.backend == SandboxBackend::Firecracker {
+            warn!("⚠️  Firecracker sandboxes are not available yet, running sandboxes as processes");
+        }
 
         let message_bus = Arc::new(MessageBus::new(1000));
-        let router = Arc::new(RwLock::new(Router::new()));
+        let mut router = Router::new();
+        router.set_offline(config.offline);
+        let router = Arc::new(RwLock::new(router));
         let lockdown_state = Arc::new(RwLock::new(LockdownState::Normal));
+        let wasm = &config.sandbox.wasm;
+        let wasm_executor = Arc::new(WasmExecutor::new(WasmConfig {
+            runtimes_dir: wasm.runtimes_dir.clone(),
+            fuel: wasm.fuel,
+            max_memory_bytes: (wasm.max_memory_mb * 1024 * 1024) as usize,
+            timeout: Duration::from_secs(wasm.timeout_secs),
+            max_snippet_bytes: (wasm.max_snippet_kb * 1024) as usize,
+            ..WasmConfig::default()
+        })?);
+        let security_engine = Arc::new(SecurityEngineImpl::new());
+        security_engine.set_max_failed_requests(config.security.max_failed_requests);
+        security_engine.set_jailbreak_action(config.security.jailbreak_action);
+        security_engine.set_offline(config.offline);
+        // Local models and GPU sandboxes reserve VRAM from the same governor
+        let llm_pool = Arc::new(LLMPool::new().with_prompt_guard(Arc::clone(&security_engine) as Arc<dyn PromptGuard>));
+        llm_pool.set_offline(config.offline);
+        let sandbox_manager = Arc::new(
+            SandboxManager::new(config.paths.data_dir.join("sandboxes"))?
+                .with_max_sandboxes(config.sandbox.max_sandboxes)
+                .with_max_queued(config.sandbox.max_queued)
+                .with_gpu_allocator(llm_pool.governor()),
+        );
+        let mut scope = PermissionScope::default();
+        sandbox_manager.set_allowed_domains(scope.network.allowed_domains.clone()).await;
+        sandbox_manager.set_package_permissions(scope.packages.clone()).await;
+        let web_search = web_search(&config.search).map(Arc::new);
+        // The search engine is the one host the web_search tool calls without asking
+        if let Some(host) = web_search.as_ref().and_then(|search| url_host(&search.endpoint())) {
+            scope.network.allowed_domains.push(host);
+        }
+        security_engine.permissions().set_global_scope(scope).await;
+        let mut filesystem = FileSystemInterface::new(&config.paths.data_dir)?;
+        if let Some(scanner) = detect_malware_scanner().await {
+            filesystem = filesystem.with_malware_scanner(scanner);
+        }
+        // Headless deployments keep managed files in a bucket, with the data dir as the working copy
+        if let Some(config) = S3Config::from_env() {
+            filesystem = filesystem.with_storage_backend(Arc::new(ObjectStorage::s3(&config)?));
+            filesystem.sync_from_storage().await?;
+        }
+        let filesystem = Arc::new(filesystem);
+
+        let plugin_config = PluginConfig {
+            plugins_dir: config.paths.plugins_dir.clone(),
+            folders: ManagedFolder::ALL
+                .into_iter()
+                .map(|folder| (folder.name().to_string(), filesystem.folder_path(folder).to_path_buf()))
+                .collect(),
+            ..Default::default()
+        };
+        let plugins = Arc::new(
+            tokio::task::spawn_blocking(move || PluginHost::discover(plugin_config))
+                .await
+                .map_err(|e| HybridLLMError::SandboxError(e.to_string()))??,
+        );
+        for analyzer in plugins.analyzers() {
+            security_engine.add_analyzer(analyzer);
+        }
+
+        let context: Arc<dyn ContextManager> = match std::env::var("DATABASE_URL") {
+            Ok(url) if !url.is_empty() => Arc::new(DatabaseContextManager::connect_lazy(&url)?),
+            _ => {
+                warn!("⚠️  DATABASE_URL not set, RAG search only covers this session");
+                Arc::new(InMemoryContextManager::new())
+            }
+        };
+        let fetcher = Arc::new(PageFetcher::new(
+            (config.fetch.max_kb * 1024) as usize,
+            Duration::from_secs(config.fetch.timeout_secs),
+            config.fetch.respect_robots,
+        ));
+        let recrawler = Arc::new(Recrawler::new(Arc::clone(&fetcher)));
+        let tools = Arc::new(ToolRegistry::with_builtins(
+            Arc::clone(&context),
+            Arc::clone(&filesystem),
+            Arc::clone(&sandbox_manager),
+            fetcher,
+            web_search,
+            plugins,
+            config.recrawl.stale_after(),
+        ));
 
         Ok(Self {
             message_bus,
             router,
             lockdown_state,
+            wasm_executor,
+            sandbox_manager,
+            security_engine,
+            filesystem,
+            llm_pool,
+            tools,
+            context,
+            recrawler,
+            config,
         })
     }
 
@@ -42,6 +203,20 @@ impl Orchestrator {
         // Subscribe to message bus
         let mut receiver = self.message_bus.subscribe();
 
+        self.forward_sandbox_events();
+        self.sandbox_manager.spawn_reaper(SANDBOX_REAP_INTERVAL);
+        // Keep sandboxes of each template booted so code execution starts instantly
+        if self.config.sandbox.warm_per_template > 0 {
+            self.sandbox_manager.spawn_pool(PoolConfig {
+                warm_per_template: self.config.sandbox.warm_per_template,
+                templates: std::iter::once(None)
+                    .chain(SandboxTemplate::ALL.into_iter().map(Some))
+                    .collect(),
+                ..Default::default()
+            });
+        }
+        self.spawn_recrawl();
+
         // Main event loop
         loop {
             tokio::select! {
@@ -52,24 +227,185 @@ impl Orchestrator {
         }
     }
 
+    /// Serve the tools in `MCP_TOOLS` to an MCP client on stdin and stdout until it disconnects
+    pub async fn serve_mcp(self) -> Result<()> {
+        info!("🔌 Serving MCP tools on stdio: {}", MCP_TOOLS.join(", "));
+
+        self.forward_sandbox_events();
+        self.sandbox_manager.spawn_reaper(SANDBOX_REAP_INTERVAL);
+
+        let server = Arc::new(McpServer::new(Arc::clone(&self.tools), Arc::clone(&self.security_engine), MCP_TOOLS));
+        server.serve(tokio::io::stdin(), tokio::io::stdout()).await
+    }
+
+    /// Re-crawl the watched folders and pages every `recrawl.interval_minutes`, first right away
+    fn spawn_recrawl(&self) {
+        let Some(minutes) = self.config.recrawl.interval_minutes else {
+            return;
+        };
+        let (context, recrawler, settings) = (Arc::clone(&self.context), Arc::clone(&self.recrawler), self.config.recrawl.clone());
+        let lockdown_state = Arc::clone(&self.lockdown_state);
+
+        info!("📚 Re-crawling watched sources every {} minutes", minutes);
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(Duration::from_secs(minutes * 60));
+            loop {
+                interval.tick().await;
+                if !lockdown_state.read().await.allows_writes() {
+                    debug!("📚 Skipping the re-crawl during lockdown");
+                    continue;
+                }
+                let report = recrawler.recrawl(context.as_ref(), &settings).await;
+                for failure in &report.failed {
+                    warn!("⚠️  Re-crawl failed for {}", failure);
+                }
+            }
+        });
+    }
+
+    /// Republish sandbox enforcement events as security alerts and state changes
+    fn forward_sandbox_events(&self) {
+        let mut events = self.sandbox_manager.subscribe();
+        let message_bus = Arc::clone(&self.message_bus);
+        let audit = self.security_engine.audit();
+        let security_engine = Arc::clone(&self.security_engine);
+
+        tokio::spawn(async move {
+            while let Ok(event) = events.recv().await {
+                if let Some(record) = event.audit_record() {
+                    audit
+                        .log(record.llm_id, record.action, record.details, record.approved, record.reason)
+                        .await;
+                }
+
+                let message = match event {
+                    SandboxEvent::ResourceExceeded { sandbox_id, resource, limit, actual } => {
+                        OrchestratorMessage::SecurityAlert {
+                            id: uuid::Uuid::new_v4(),
+                            severity: AlertSeverity::Critical,
+                            reason: format!(
+                                "Sandbox {} exceeded {} limit (limit: {}, actual: {})",
+                                sandbox_id, resource, limit, actual
+                            ),
+                            llm_id: None,
+                            suggested_action: SuggestedAction::Lockdown { level: LockdownLevel::Locked },
+                        }
+                    }
+                    SandboxEvent::NetworkConnection { sandbox_id, host, port, allowed: true } => {
+                        info!("🌐 Sandbox {} connected to {}:{}", sandbox_id, host, port);
+                        let analysis = security_engine.analyze_sandbox_connection(sandbox_id, &host, port).await;
+                        if analysis.issues.is_empty() {
+                            continue;
+                        }
+                        OrchestratorMessage::SecurityAlert {
+                            id: uuid::Uuid::new_v4(),
+                            severity: if analysis.safe { AlertSeverity::Warning } else { AlertSeverity::Critical },
+                            reason: format!("Sandbox {}: {}", sandbox_id, analysis.issues.join("; ")),
+                            llm_id: None,
+                            suggested_action: SuggestedAction::RequestHumanReview,
+                        }
+                    }
+                    SandboxEvent::NetworkConnection { sandbox_id, host, port, allowed: false } => {
+                        OrchestratorMessage::SecurityAlert {
+                            id: uuid::Uuid::new_v4(),
+                            severity: AlertSeverity::Warning,
+                            reason: format!(
+                                "Sandbox {} attempted blocked egress to {}:{}",
+                                sandbox_id, host, port
+                            ),
+                            llm_id: None,
+                            suggested_action: SuggestedAction::Deny,
+                        }
+                    }
+                    SandboxEvent::Created { .. }
+                    | SandboxEvent::CommandExecuted { .. }
+                    | SandboxEvent::ExecutionTimedOut { .. }
+                    | SandboxEvent::CellExecuted { .. }
+                    | SandboxEvent::BrowserAction { .. }
+                    | SandboxEvent::Destroyed { .. } => continue,
+                    SandboxEvent::Usage { usage } => {
+                        let analysis = security_engine.analyze_sandbox_usage(&usage).await;
+                        if analysis.issues.is_empty() {
+                            continue;
+                        }
+                        OrchestratorMessage::SecurityAlert {
+                            id: uuid::Uuid::new_v4(),
+                            severity: AlertSeverity::Critical,
+                            reason: format!("Sandbox {}: {}", usage.sandbox_id, analysis.issues.join("; ")),
+                            llm_id: None,
+                            suggested_action: SuggestedAction::RequestHumanReview,
+                        }
+                    }
+                    SandboxEvent::PortForwardOpened { forward, .. } => OrchestratorMessage::StateChange {
+                        id: uuid::Uuid::new_v4(),
+                        change_type: StateChangeType::PortForwardOpened,
+                        data: serde_json::json!(forward),
+                    },
+                    SandboxEvent::PortForwardClosed { forward, reason, .. } => OrchestratorMessage::StateChange {
+                        id: uuid::Uuid::new_v4(),
+                        change_type: StateChangeType::PortForwardClosed,
+                        data: serde_json::json!({
+                            "forward": forward,
+                            "reason": reason,
+                        }),
+                    },
+                    SandboxEvent::Queued { sandbox_id, llm_id, position } => {
+                        OrchestratorMessage::StateChange {
+                            id: uuid::Uuid::new_v4(),
+                            change_type: StateChangeType::SandboxQueued,
+                            data: serde_json::json!({
+                                "sandbox_id": sandbox_id,
+                                "llm_id": llm_id,
+                                "position": position,
+                            }),
+                        }
+                    }
+                    SandboxEvent::Reclaimed { sandbox_id, reason } => {
+                        OrchestratorMessage::StateChange {
+                            id: uuid::Uuid::new_v4(),
+                            change_type: StateChangeType::SandboxReclaimed,
+                            data: serde_json::json!({
+                                "sandbox_id": sandbox_id,
+                                "reason": reason,
+                            }),
+                        }
+                    }
+                };
+
+                let _ = message_bus.publish(message);
+            }
+        });
+    }
+
     /// Handle incoming messages
     async fn handle_message(&self, message: OrchestratorMessage) -> Result<()> {
         debug!("📨 Handling message: {:?}", message);
 
+        // Cancelling only stops work, so it goes through even while locked down
+        if let OrchestratorMessage::CancelGeneration { request_id, .. } = message {
+            if !self.llm_pool.cancel_generation(&request_id) {
+                debug!("Generation {} already finished", request_id);
+            }
+            return Ok(());
+        }
+
         // Check lockdown state before processing
-        let lockdown = self.lockdown_state.read().await;
-        if *lockdown == LockdownState::Locked {
+        let lockdown = *self.lockdown_state.read().await;
+        if !lockdown.allows_reads() {
             error!("🔒 System is locked down, rejecting message");
             return Ok(());
         }
-        drop(lockdown); // Release the lock
+        if !lockdown.allows_writes() && writes(&message) {
+            warn!("🟡 System is read-only, rejecting message");
+            return Ok(());
+        }
 
         match message {
-            OrchestratorMessage::UserRequest { id, content, context } => {
-                self.handle_user_request(id, content, context).await?;
+            OrchestratorMessage::UserRequest { id, request } => {
+                self.handle_user_request(id, request).await?;
             }
-            OrchestratorMessage::LLMDelegation { id, from, to, task, callback } => {
-                self.handle_llm_delegation(id, from, to, task, callback).await?;
+            OrchestratorMessage::LLMDelegation { id, from, to, task, callback, context } => {
+                self.handle_llm_delegation(id, from, to, task, callback).instrument(context.span()).await?;
             }
             OrchestratorMessage::SecurityAlert { id, severity, reason, llm_id, suggested_action } => {
                 self.handle_security_alert(id, severity, reason, llm_id, suggested_action).await?;
@@ -77,6 +413,58 @@ impl Orchestrator {
             OrchestratorMessage::StateChange { id, change_type, data } => {
                 self.handle_state_change(id, change_type, data).await?;
             }
+            OrchestratorMessage::CodeEvaluation { id, llm_id, language, code, context } => {
+                self.handle_code_evaluation(id, llm_id, language, code).instrument(context.span()).await?;
+            }
+            OrchestratorMessage::ArtifactTransferRequest {
+                id,
+                llm_id,
+                sandbox_id,
+                file_path,
+                destination,
+                explanation,
+            } => {
+                let transfer = ArtifactTransfer {
+                    sandbox_id,
+                    file_path,
+                    destination,
+                    explanation,
+                    approved: None,
+                };
+                self.handle_artifact_transfer(id, llm_id, transfer).await?;
+            }
+            OrchestratorMessage::PortForwardRequest {
+                id,
+                llm_id,
+                sandbox_id,
+                sandbox_port,
+                duration_secs,
+                explanation,
+            } => {
+                let request = PortForwardRequest {
+                    sandbox_id,
+                    sandbox_port,
+                    duration_secs,
+                    explanation,
+                };
+                self.handle_port_forward(id, llm_id, request).await?;
+            }
+            OrchestratorMessage::ToolCall { request_id, llm_id, call, context, .. } => {
+                self.handle_tool_call(request_id, context.with_llm(llm_id), call).await?;
+            }
+            OrchestratorMessage::ToolResult { request_id, llm_id, result, context, .. } => {
+                self.handle_tool_result(request_id, context.with_llm(llm_id), result).await?;
+            }
+            OrchestratorMessage::PlanCheck { request_id, llm_id, calls, context, .. } => {
+                self.handle_plan_check(request_id, context.with_llm(llm_id), calls).await?;
+            }
+            OrchestratorMessage::PermissionRequest { id, llm_id, permission_type, explanation, context } => {
+                self.handle_permission_request(id, context.with_llm(llm_id), permission_type, explanation).await?;
+            }
+            OrchestratorMessage::PermissionResponse { request_id, granted, .. } => {
+                // Responses to anything other than a pending approval are ignored here
+                let _ = self.security_engine.resolve_approval(request_id, granted).await;
+            }
             _ => {
                 debug!("Unhandled message type, passing through");
             }
@@ -88,14 +476,91 @@ impl Orchestrator {
     async fn handle_user_request(
         &self,
         id: uuid::Uuid,
-        content: String,
-        context: std::collections::HashMap<String, serde_json::Value>,
+        request: CompletionRequest,
     ) -> Result<()> {
         info!("👤 Handling user request: {}", id);
         // TODO: Classify task and route to appropriate LLM
         Ok(())
     }
 
+    /// Record a tool call in the audit log, run it and publish its result
+    async fn handle_tool_call(&self, request_id: uuid::Uuid, context: RequestContext, call: ToolCall) -> Result<()> {
+        info!("🔧 {} calling tool {} for request {} (trace {})", context.actor(), call.name, request_id, context.trace_id);
+        self.security_engine
+            .audit()
+            .log_in(
+                &context,
+                "Tool call requested".to_string(),
+                serde_json::json!({
+                    "request_id": request_id,
+                    "call_id": call.id,
+                    "tool": call.name,
+                    "arguments": call.arguments,
+                }),
+                true,
+                None,
+            )
+            .await;
+
+        let tools = Arc::clone(&self.tools);
+        let security_engine = Arc::clone(&self.security_engine);
+        let message_bus = Arc::clone(&self.message_bus);
+        let span = context.span();
+        tokio::spawn(
+            async move {
+                let result = tools.call(&*security_engine, &context, &call).await;
+                let _ = message_bus.publish(OrchestratorMessage::ToolResult {
+                    id: uuid::Uuid::new_v4(),
+                    request_id,
+                    llm_id: context.actor().to_string(),
+                    result,
+                    context,
+                });
+            }
+            .instrument(span),
+        );
+
+        Ok(())
+    }
+
+    /// Dry-run the tool calls of a plan and publish what would need approval, without auditing or counting denials
+    async fn handle_plan_check(&self, request_id: uuid::Uuid, context: RequestContext, calls: Vec<ToolCall>) -> Result<()> {
+        debug!("🧪 {} checking a plan of {} tool calls (trace {})", context.actor(), calls.len(), context.trace_id);
+        let decisions = self.tools.simulate(&*self.security_engine, &context, &calls).await?;
+        self.message_bus.publish(OrchestratorMessage::PlanChecked {
+            id: uuid::Uuid::new_v4(),
+            request_id,
+            llm_id: context.actor().to_string(),
+            decisions,
+            context,
+        })?;
+        Ok(())
+    }
+
+    /// Record the outcome of a tool call in the audit log
+    async fn handle_tool_result(&self, request_id: uuid::Uuid, context: RequestContext, result: ToolResult) -> Result<()> {
+        if let Some(error) = &result.error {
+            warn!("⚠️  Tool {} failed for {} (trace {}): {}", result.name, context.actor(), context.trace_id, error);
+        }
+        let action = if result.is_error() { "Tool call failed" } else { "Tool call completed" };
+        self.security_engine
+            .audit()
+            .log_in(
+                &context,
+                action.to_string(),
+                serde_json::json!({
+                    "request_id": request_id,
+                    "call_id": result.call_id,
+                    "tool": result.name,
+                    "output": result.output,
+                }),
+                !result.is_error(),
+                result.error.clone(),
+            )
+            .await;
+        Ok(())
+    }
+
     async fn handle_llm_delegation(
         &self,
         id: uuid::Uuid,
@@ -105,6 +570,8 @@ impl Orchestrator {
         callback: bool,
     ) -> Result<()> {
         info!("🔄 LLM {} delegating task to {:?}", from, to);
+        // A malformed response format fails the delegation here rather than at the delegate's sampler
+        task.response_format()?;
 
         let target_llm = if let Some(to_id) = to {
             to_id
@@ -119,6 +586,341 @@ impl Orchestrator {
         Ok(())
     }
 
+    async fn handle_code_evaluation(
+        &self,
+        id: uuid::Uuid,
+        llm_id: String,
+        language: CodeLanguage,
+        code: String,
+    ) -> Result<()> {
+        info!("🧪 Code evaluation from {}: {:?}", llm_id, language);
+
+        let eligible = self.wasm_executor.is_eligible(language, &code);
+        if !eligible {
+            debug!("Snippet not eligible for WASM micro-sandbox, using a full sandbox");
+        }
+
+        let executor = Arc::clone(&self.wasm_executor);
+        let sandbox_manager = Arc::clone(&self.sandbox_manager);
+        let message_bus = Arc::clone(&self.message_bus);
+        // Keeps the caller's span, and with it the request context, on the task
+        tokio::spawn(
+            async move {
+                let output = if eligible {
+                    executor
+                        .run(language, &code)
+                        .await
+                        .map(|output| (output.stdout, output.stderr, output.exit_code))
+                } else {
+                    run_in_sandbox(&sandbox_manager, &llm_id, language, &code)
+                        .await
+                        .map(|result| (result.stdout, result.stderr, result.exit_code))
+                };
+
+                let (stdout, stderr, exit_code) = output.unwrap_or_else(|e| {
+                    error!("❌ Code evaluation failed: {}", e);
+                    (String::new(), e.to_string(), -1)
+                });
+
+                let _ = message_bus.publish(OrchestratorMessage::CodeEvaluationResult {
+                    id: uuid::Uuid::new_v4(),
+                    request_id: id,
+                    stdout,
+                    stderr,
+                    exit_code,
+                });
+            }
+            .in_current_span(),
+        );
+
+        Ok(())
+    }
+
+    /// Scan an artifact, wait for the user's approval and copy it into downloads
+    /// Artifacts the scan blocks are refused without asking
+    async fn handle_artifact_transfer(
+        &self,
+        id: uuid::Uuid,
+        llm_id: String,
+        mut transfer: ArtifactTransfer,
+    ) -> Result<()> {
+        info!("📤 Artifact transfer requested by {}: {}", llm_id, transfer.file_path);
+
+        self.security_engine
+            .audit()
+            .log(
+                Some(llm_id.clone()),
+                "Artifact transfer requested".to_string(),
+                serde_json::json!({
+                    "sandbox_id": transfer.sandbox_id,
+                    "file_path": transfer.file_path,
+                    "destination": transfer.destination,
+                    "explanation": transfer.explanation,
+                }),
+                true,
+                None,
+            )
+            .await;
+
+        let sandbox_manager = Arc::clone(&self.sandbox_manager);
+        let security_engine = Arc::clone(&self.security_engine);
+        let message_bus = Arc::clone(&self.message_bus);
+        let filesystem = Arc::clone(&self.filesystem);
+
+        tokio::spawn(async move {
+            let publish_result = |approved: bool, path: Option<String>, error: Option<String>| {
+                let _ = message_bus.publish(OrchestratorMessage::ArtifactTransferResult {
+                    id: uuid::Uuid::new_v4(),
+                    request_id: id,
+                    approved,
+                    path,
+                    error,
+                });
+            };
+
+            let artifact = match sandbox_manager
+                .inspect_artifact(transfer.sandbox_id, &transfer.file_path)
+                .await
+            {
+                Ok(artifact) => artifact,
+                Err(e) => {
+                    error!("❌ Artifact inspection failed: {}", e);
+                    publish_result(false, None, Some(e.to_string()));
+                    return;
+                }
+            };
+
+            let scan = match security_engine.scan_artifact(&llm_id, &artifact.host_path).await {
+                Ok(scan) => scan,
+                Err(e) => {
+                    error!("❌ Artifact scan failed: {}", e);
+                    publish_result(false, None, Some(e.to_string()));
+                    return;
+                }
+            };
+
+            if scan.verdict == ScanVerdict::Blocked {
+                let reasons: Vec<_> = scan.findings.iter().map(|f| f.description.as_str()).collect();
+                publish_result(false, None, Some(format!("Blocked by scan: {}", reasons.join("; "))));
+                return;
+            }
+
+            // Don't ask the user about a file that could not be stored anyway
+            if let Err(e) = filesystem.ensure_capacity(ManagedFolder::Downloads, artifact.size_bytes) {
+                publish_result(false, None, Some(e.to_string()));
+                return;
+            }
+
+            let approval_id = uuid::Uuid::new_v4();
+            let approval_request = OrchestratorMessage::ArtifactApproval {
+                id: approval_id,
+                sandbox_id: transfer.sandbox_id,
+                file_path: transfer.file_path.clone(),
+                destination: transfer.destination.clone(),
+                explanation: transfer.explanation.clone(),
+                size_bytes: artifact.size_bytes,
+                sha256: artifact.sha256.clone(),
+                scan,
+            };
+
+            // Register the pending approval before the UI can see the request
+            let (approved, _) = tokio::join!(
+                security_engine.request_artifact_approval(
+                    &llm_id,
+                    approval_id,
+                    transfer.clone(),
+                    ARTIFACT_APPROVAL_TIMEOUT,
+                ),
+                async { message_bus.publish(approval_request) },
+            );
+
+            if !approved {
+                publish_result(false, None, None);
+                return;
+            }
+
+            transfer.approved = Some(true);
+            let details = serde_json::json!({
+                "sandbox_id": transfer.sandbox_id,
+                "file_path": transfer.file_path,
+                "sha256": artifact.sha256,
+                "size_bytes": artifact.size_bytes,
+            });
+
+            let sandbox_id = transfer.sandbox_id;
+            let transferred = async {
+                // Downloads may have filled up while the approval was pending
+                filesystem.ensure_capacity(ManagedFolder::Downloads, artifact.size_bytes)?;
+                let path = sandbox_manager
+                    .transfer_artifact(transfer, &artifact.sha256, filesystem.downloads_path())
+                    .await?;
+                let quarantined = filesystem.scan(&path).await?;
+                if quarantined.is_none() {
+                    if let Some(filename) = path.file_name().and_then(|name| name.to_str()) {
+                        filesystem.persist_file(ManagedFolder::Downloads, filename).await?;
+                    }
+                }
+                Ok::<_, HybridLLMError>((path, quarantined))
+            }
+            .await;
+
+            match transferred {
+                Ok((_, Some(quarantined))) => {
+                    let reason = format!(
+                        "Artifact {} from sandbox {} contains malware ({}) and was quarantined",
+                        quarantined.original_path.display(),
+                        sandbox_id,
+                        quarantined.signature
+                    );
+                    let _ = message_bus.publish(OrchestratorMessage::SecurityAlert {
+                        id: uuid::Uuid::new_v4(),
+                        severity: AlertSeverity::Critical,
+                        reason: reason.clone(),
+                        llm_id: Some(llm_id.clone()),
+                        suggested_action: SuggestedAction::RequestHumanReview,
+                    });
+                    security_engine
+                        .audit()
+                        .log(Some(llm_id), "Artifact quarantined".to_string(), details, false, Some(reason.clone()))
+                        .await;
+                    publish_result(true, None, Some(reason));
+                }
+                Ok((path, None)) => {
+                    security_engine
+                        .audit()
+                        .log(Some(llm_id), "Artifact transferred".to_string(), details, true, None)
+                        .await;
+                    publish_result(true, Some(path.display().to_string()), None);
+                }
+                Err(e) => {
+                    error!("❌ Artifact transfer failed: {}", e);
+                    security_engine
+                        .audit()
+                        .log(
+                            Some(llm_id),
+                            "Artifact transfer failed".to_string(),
+                            details,
+                            false,
+                            Some(e.to_string()),
+                        )
+                        .await;
+                    publish_result(true, None, Some(e.to_string()));
+                }
+            }
+        });
+
+        Ok(())
+    }
+
+    /// Wait for the user's approval, then expose a sandbox port on localhost
+    async fn handle_permission_request(
+        &self,
+        id: uuid::Uuid,
+        context: RequestContext,
+        permission: common::messages::PermissionType,
+        explanation: String,
+    ) -> Result<()> {
+        info!("🔐 Permission requested by {}: {:?}", context.actor(), permission);
+
+        let security_engine = Arc::clone(&self.security_engine);
+        let message_bus = Arc::clone(&self.message_bus);
+
+        // The request itself is on the bus, so the UI answers it by its id
+        tokio::spawn(async move {
+            let (granted, reason) = match security_engine
+                .request_permission_approval(&context, id, permission, &explanation, PERMISSION_APPROVAL_TIMEOUT)
+                .await
+            {
+                Ok(true) => (true, None),
+                Ok(false) => (false, Some("Denied by policy or user".to_string())),
+                Err(e) => {
+                    error!("❌ Permission check failed: {}", e);
+                    (false, Some(e.to_string()))
+                }
+            };
+
+            let _ = message_bus.publish(OrchestratorMessage::PermissionResponse {
+                id: uuid::Uuid::new_v4(),
+                request_id: id,
+                granted,
+                reason,
+            });
+        });
+
+        Ok(())
+    }
+
+    async fn handle_port_forward(
+        &self,
+        id: uuid::Uuid,
+        llm_id: String,
+        request: PortForwardRequest,
+    ) -> Result<()> {
+        info!("🔌 Port forward requested by {}: sandbox {} port {}",
+              llm_id, request.sandbox_id, request.sandbox_port);
+
+        let sandbox_manager = Arc::clone(&self.sandbox_manager);
+        let security_engine = Arc::clone(&self.security_engine);
+        let message_bus = Arc::clone(&self.message_bus);
+
+        tokio::spawn(async move {
+            let publish_result = |host_port: Option<u16>, expires_at, error: Option<String>| {
+                let _ = message_bus.publish(OrchestratorMessage::PortForwardResult {
+                    id: uuid::Uuid::new_v4(),
+                    request_id: id,
+                    approved: host_port.is_some(),
+                    host_port,
+                    expires_at,
+                    error,
+                });
+            };
+
+            if sandbox_manager.get_config(request.sandbox_id).await.is_none() {
+                publish_result(None, None, Some(format!("Sandbox not found: {}", request.sandbox_id)));
+                return;
+            }
+
+            let approval_id = uuid::Uuid::new_v4();
+            let approval_request = OrchestratorMessage::PortForwardApproval {
+                id: approval_id,
+                llm_id: llm_id.clone(),
+                sandbox_id: request.sandbox_id,
+                sandbox_port: request.sandbox_port,
+                duration_secs: request.duration_secs,
+                explanation: request.explanation.clone(),
+            };
+
+            // Register the pending approval before the UI can see the request
+            let (approved, _) = tokio::join!(
+                security_engine.request_port_forward_approval(
+                    &llm_id,
+                    approval_id,
+                    request.clone(),
+                    PORT_FORWARD_APPROVAL_TIMEOUT,
+                ),
+                async { message_bus.publish(approval_request) },
+            );
+
+            if !approved {
+                publish_result(None, None, None);
+                return;
+            }
+
+            match sandbox_manager
+                .forward_port(request.sandbox_id, request.sandbox_port, Duration::from_secs(request.duration_secs))
+                .await
+            {
+                Ok(forward) => publish_result(Some(forward.host_port), Some(forward.expires_at), None),
+                Err(e) => {
+                    error!("❌ Port forward failed: {}", e);
+                    publish_result(None, None, Some(e.to_string()));
+                }
+            }
+        });
+
+        Ok(())
+    }
+
     async fn handle_security_alert(
         &self,
         id: uuid::Uuid,
@@ -130,10 +932,23 @@ impl Orchestrator {
         error!("🚨 Security alert [{:?}]: {} (LLM: {:?})", severity, reason, llm_id);
 
         match suggested_action {
-            common::messages::SuggestedAction::Lockdown => {
-                info!("🔒 Triggering lockdown");
+            common::messages::SuggestedAction::Lockdown { level } => {
                 let mut lockdown = self.lockdown_state.write().await;
-                *lockdown = LockdownState::Locked;
+                let previous = *lockdown;
+                *lockdown = previous.escalate(level);
+                if *lockdown != previous {
+                    info!("🔒 Entering {:?}", *lockdown);
+                    // Lets components that only follow state changes react to the new tier
+                    let change_type = match level {
+                        LockdownLevel::ReadOnly => StateChangeType::ReadOnlyEntered,
+                        LockdownLevel::Locked => StateChangeType::LockdownTriggered,
+                    };
+                    let _ = self.message_bus.publish(OrchestratorMessage::StateChange {
+                        id: uuid::Uuid::new_v4(),
+                        change_type,
+                        data: serde_json::json!({ "alert_id": id, "reason": reason }),
+                    });
+                }
             }
             common::messages::SuggestedAction::RequestHumanReview => {
                 info!("👨‍💼 Requesting human review");
@@ -162,6 +977,17 @@ impl Orchestrator {
                 let mut lockdown = self.lockdown_state.write().await;
                 *lockdown = LockdownState::Normal;
             }
+            common::messages::StateChangeType::ReadOnlyEntered => {
+                let mut lockdown = self.lockdown_state.write().await;
+                *lockdown = lockdown.escalate(LockdownLevel::ReadOnly);
+            }
+            common::messages::StateChangeType::ReadOnlyExited => {
+                // Leaving read-only mode doesn't release a full lockdown
+                let mut lockdown = self.lockdown_state.write().await;
+                if *lockdown == LockdownState::ReadOnly {
+                    *lockdown = LockdownState::Normal;
+                }
+            }
             common::messages::StateChangeType::LLMLoaded => {
                 // TODO: Update router
             }
@@ -179,3 +1005,35 @@ impl Orchestrator {
         Arc::clone(&self.message_bus)
     }
 }
+
+/// Whether handling `message` writes files, runs code or exposes the sandbox, which read-only mode refuses
+/// Tool calls go through, since the registry only runs read-only tools in that mode
+fn writes(message: &OrchestratorMessage) -> bool {
+    matches!(
+        message,
+        OrchestratorMessage::SandboxRequest { .. }
+            | OrchestratorMessage::CodeEvaluation { .. }
+            | OrchestratorMessage::ArtifactTransferRequest { .. }
+            | OrchestratorMessage::PortForwardRequest { .. }
+    )
+}
+
+/// Run code in a throwaway sandbox from the warm pool
+/// Uses the language's template when it has been built, the host toolchain otherwise
+pub(crate) async fn run_in_sandbox(
+    sandbox_manager: &SandboxManager,
+    llm_id: &str,
+    language: CodeLanguage,
+    code: &str,
+) -> Result<ExecutionResult> {
+    let template = Some(SandboxTemplate::for_language(language))
+        .filter(|template| sandbox_manager.available_templates().contains(template));
+
+    let sandbox_id = sandbox_manager.checkout(template, Some(llm_id.to_string())).await?;
+    let result = sandbox_manager.run_code(sandbox_id, language, code).await;
+    if let Err(e) = sandbox_manager.destroy_sandbox(sandbox_id).await {
+        error!("❌ Failed to destroy evaluation sandbox {}: {}", sandbox_id, e);
+    }
+
+    result
+}
@@ -1,4 +1,6 @@
 use common::{
+    language::detect_language,
+    tokens::Tokenizer,
     messages::{OrchestratorMessage, TaskDescription},
     types::{Capability, TaskType, LLMInstance},
     errors::{Result, HybridLLMError},
@@ -10,15 +12,23 @@ use tracing::{debug, info};
 pub struct Router {
     /// Registry of available LLMs and their capabilities
     llm_registry: HashMap<String, LLMInstance>,
+    /// While set, only local models are routed to
+    offline: bool,
 }
 
 impl Router {
     pub fn new() -> Self {
         Self {
             llm_registry: HashMap::new(),
+            offline: false,
         }
     }
 
+    /// Route to local models only while `offline`
+    pub fn set_offline(&mut self, offline: bool) {
+        self.offline = offline;
+    }
+
     /// Register an LLM instance
     pub fn register_llm(&mut self, instance: LLMInstance) {
         info!("📝 Registering LLM: {} with capabilities: {:?}",
@@ -41,6 +51,7 @@ impl Router {
             .values()
             .filter(|instance| {
                 instance.is_loaded &&
+                !(self.offline && instance.provider.is_cloud()) &&
                 task.required_capabilities
                     .iter()
                     .all(|cap| instance.capabilities.contains(cap))
@@ -53,16 +64,29 @@ impl Router {
             ));
         }
 
+        // Counted per model, as a long task may fit one tokenizer's context and not another's
+        candidates.retain(|instance| Tokenizer::for_instance(instance).count(&task.description) < instance.max_context);
+        if candidates.is_empty() {
+            return Err(HybridLLMError::InvalidRequest(
+                "The task is longer than the context of any LLM that could take it".to_string()
+            ));
+        }
+
+        // A Japanese task goes to a multilingual model rather than an English code model
+        let language = detect_language(&task.description);
+        if let Some(language) = language {
+            debug!("🌐 Task is in {}", language);
+        }
+        let suits = |instance: &LLMInstance| language.is_none_or(|language| instance.supports_language(language));
+
         // Sort by preference (for now, prefer local models)
         candidates.sort_by(|a, b| {
             // Prefer loaded models
             match (a.is_loaded, b.is_loaded) {
                 (true, false) => std::cmp::Ordering::Less,
                 (false, true) => std::cmp::Ordering::Greater,
-                _ => {
-                    // Then prefer models with more specific capabilities
-                    b.capabilities.len().cmp(&a.capabilities.len())
-                }
+                // Then models suited to the task's language, then models with more specific capabilities
+                _ => suits(b).cmp(&suits(a)).then_with(|| b.capabilities.len().cmp(&a.capabilities.len())),
             }
         });
 
@@ -118,5 +142,77 @@ mod tests {
 
         let result = router.route_task(&task).unwrap();
         assert_eq!(result, "test-llm");
+
+        router.register_llm(LLMInstance {
+            id: "embedder".to_string(),
+            provider: LLMProvider::Local("embedder".to_string()),
+            capabilities: vec![Capability::Embedding, Capability::Multilingual],
+            model_name: "embedder".to_string(),
+            max_context: 512,
+            is_loaded: true,
+        });
+        let task = TaskDescription {
+            required_capabilities: vec![Capability::from("Multilingual".to_string()), Capability::Embedding],
+            ..task
+        };
+        assert_eq!(router.route_task(&task).unwrap(), "embedder");
+        assert_eq!(router.find_by_capability(&Capability::Vision).len(), 0);
+    }
+
+    #[test]
+    fn test_route_by_language() {
+        let mut router = Router::new();
+        let llm = |id: &str, capabilities: Vec<Capability>| LLMInstance {
+            id: id.to_string(),
+            provider: LLMProvider::Local(id.to_string()),
+            capabilities,
+            model_name: id.to_string(),
+            max_context: 4096,
+            is_loaded: true,
+        };
+        router.register_llm(llm("coder", vec![Capability::General, Capability::Code, Capability::Analysis]));
+        router.register_llm(llm("qwen", vec![Capability::General, Capability::Multilingual]));
+
+        let task = |description: &str| TaskDescription {
+            description: description.to_string(),
+            task_type: TaskType::General,
+            required_capabilities: vec![Capability::General],
+            context: HashMap::new(),
+            constraints: vec![],
+        };
+        assert_eq!(router.route_task(&task("東京で一番おいしいラーメン屋はどこですか？")).unwrap(), "qwen");
+        // English, or no telling, keeps the most specific model
+        assert_eq!(router.route_task(&task("Summarize the quick brown fox jumping over the lazy dog.")).unwrap(), "coder");
+        assert_eq!(router.route_task(&task("ok")).unwrap(), "coder");
+
+        // Too long for the coder's context, so the multilingual model takes it
+        router.register_llm(LLMInstance { max_context: 64, ..llm("coder", vec![Capability::General, Capability::Code]) });
+        assert_eq!(router.route_task(&task(&"Summarize the release notes. ".repeat(20))).unwrap(), "qwen");
+    }
+
+    #[test]
+    fn test_route_offline() {
+        let mut router = Router::new();
+        let llm = |id: &str, provider: LLMProvider, capabilities: Vec<Capability>| LLMInstance {
+            id: id.to_string(),
+            provider,
+            capabilities,
+            model_name: id.to_string(),
+            max_context: 4096,
+            is_loaded: true,
+        };
+        router.register_llm(llm("claude", LLMProvider::Claude, vec![Capability::General, Capability::Code, Capability::Analysis]));
+        router.register_llm(llm("local", LLMProvider::Local("local".to_string()), vec![Capability::General]));
+
+        let task = TaskDescription {
+            description: "Review this function".to_string(),
+            task_type: TaskType::General,
+            required_capabilities: vec![Capability::General],
+            context: HashMap::new(),
+            constraints: vec![],
+        };
+        assert_eq!(router.route_task(&task).unwrap(), "claude");
+        router.set_offline(true);
+        assert_eq!(router.route_task(&task).unwrap(), "local");
     }
 }
@@ -0,0 +1,704 @@
+use async_trait::async_trait;
+use common::{
+    errors::{Result, HybridLLMError},
+    messages::PermissionType,
+    traits::{ContextManager, PlannedAction, SecurityEngine, SimulatedDecision, Tool},
+    types::{url_host, CodeLanguage, LockdownState, NetworkMode, RequestContext, SandboxConfig},
+    ToolCall, ToolDefinition, ToolResult,
+};
+use api_gateway::{PageFetcher, WebSearch};
+use context_manager::extract_readable;
+use filesystem_interface::{FileQuery, FileSystemInterface, ManagedFolder};
+use sandbox_manager::{BrowserAction, PluginHost, SandboxManager};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::{debug, info, warn};
+use uuid::Uuid;
+
+use crate::orchestrator::run_in_sandbox;
+
+/// Most RAG chunks one search returns
+const MAX_SEARCH_RESULTS: usize = 20;
+
+/// Most web search results one call returns
+const MAX_WEB_RESULTS: usize = 10;
+
+/// A conversation's browser sandbox is reclaimed after this long without an action
+const BROWSER_IDLE_TIMEOUT_SECS: u64 = 600;
+
+/// File contents past this are cut off, so one read can't fill the model's context
+const MAX_READ_BYTES: usize = 64 * 1024;
+
+/// Most files one listing returns
+const MAX_LISTED_FILES: usize = 500;
+
+/// Tools LLMs may call, by name
+#[derive(Default)]
+pub struct ToolRegistry {
+    tools: HashMap<String, Arc<dyn Tool>>,
+}
+
+impl ToolRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The built-in tools: RAG search, listing and reading managed files, running code in a sandbox,
+    /// fetching pages, a sandboxed browser and, when a search engine is configured, web search;
+    /// then the tools of plugins, which can't replace built-in ones
+    pub fn with_builtins(
+        context: Arc<dyn ContextManager>,
+        filesystem: Arc<FileSystemInterface>,
+        sandbox_manager: Arc<SandboxManager>,
+        fetcher: Arc<PageFetcher>,
+        web_search: Option<Arc<WebSearch>>,
+        plugins: Arc<PluginHost>,
+        stale_after: Duration,
+    ) -> Self {
+        let mut registry = Self::new();
+        registry.register(Arc::new(RagSearchTool { context, stale_after }));
+        registry.register(Arc::new(ListDocumentsTool { filesystem: Arc::clone(&filesystem) }));
+        registry.register(Arc::new(FileReadTool { filesystem, plugins: Arc::clone(&plugins) }));
+        registry.register(Arc::new(BrowserTool {
+            sandbox_manager: Arc::clone(&sandbox_manager),
+            sessions: tokio::sync::Mutex::new(HashMap::new()),
+        }));
+        registry.register(Arc::new(SandboxExecTool { sandbox_manager }));
+        registry.register(Arc::new(FetchUrlTool { fetcher }));
+        if let Some(search) = web_search {
+            registry.register(Arc::new(WebSearchTool { search }));
+        }
+        for tool in plugins.tools() {
+            if registry.tools.contains_key(tool.name()) {
+                warn!("⚠️  Ignoring plugin tool {}, a built-in tool has that name", tool.name());
+                continue;
+            }
+            registry.register(tool);
+        }
+        registry
+    }
+
+    /// Add a tool, replacing any with the same name
+    pub fn register(&mut self, tool: Arc<dyn Tool>) {
+        info!("🔧 Registering tool: {}", tool.name());
+        self.tools.insert(tool.name().to_string(), tool);
+    }
+
+    /// Definitions of every tool, by name
+    pub fn definitions(&self) -> Vec<ToolDefinition> {
+        let mut definitions: Vec<_> = self.tools.values().map(|tool| tool.definition()).collect();
+        definitions.sort_by(|a, b| a.name.cmp(&b.name));
+        definitions
+    }
+
+    /// Run a tool call; failures, refusals included, become failed results for the model to see
+    pub async fn call(&self, security: &dyn SecurityEngine, context: &RequestContext, call: &ToolCall) -> ToolResult {
+        match self.run(security, context, call).await {
+            Ok(output) => ToolResult::success(call, output),
+            Err(e) => ToolResult::failure(call, e.to_string()),
+        }
+    }
+
+    /// What would happen to each call of a plan, without running, auditing or counting any of them
+    pub async fn simulate(
+        &self,
+        security: &dyn SecurityEngine,
+        context: &RequestContext,
+        calls: &[ToolCall],
+    ) -> Result<Vec<SimulatedDecision>> {
+        let mut decisions = Vec::with_capacity(calls.len());
+        for call in calls {
+            decisions.push(match self.admit(security, call).await {
+                Ok((_, Some(permission))) => security.simulate(context, &PlannedAction::Permission { permission }).await?,
+                Ok((_, None)) => SimulatedDecision::Allowed,
+                Err(e) => SimulatedDecision::Denied { reason: e.to_string() },
+            });
+        }
+        Ok(decisions)
+    }
+
+    async fn run(&self, security: &dyn SecurityEngine, context: &RequestContext, call: &ToolCall) -> Result<serde_json::Value> {
+        let (tool, permission) = self.admit(security, call).await?;
+
+        // The security engine audits the decision and counts denials towards a lockdown
+        if let Some(permission) = permission {
+            let explanation = format!("Tool call {} ({})", call.name, call.id);
+            if !security.check_permission(context, &permission, &explanation).await? {
+                return Err(HybridLLMError::PermissionDenied(format!("{:?}", permission)));
+            }
+        }
+
+        debug!("🔧 Running tool {} for {}", call.name, context.actor());
+        tool.execute(context, call.arguments.clone()).await
+    }
+
+    /// The tool `call` names and the permission it needs, unless it can't run at all
+    async fn admit(&self, security: &dyn SecurityEngine, call: &ToolCall) -> Result<(&Arc<dyn Tool>, Option<PermissionType>)> {
+        let tool = self
+            .tools
+            .get(&call.name)
+            .ok_or_else(|| HybridLLMError::InvalidRequest(format!("No tool named {}", call.name)))?;
+
+        match security.lockdown_state().await? {
+            LockdownState::Locked => {
+                return Err(HybridLLMError::LockdownActive("Tools can't run while the system is locked".to_string()));
+            }
+            LockdownState::ReadOnly if !tool.read_only() => {
+                return Err(HybridLLMError::LockdownActive(format!("{} can't run in read-only mode", call.name)));
+            }
+            _ => {}
+        }
+        check_required(&tool.parameters(), &call.arguments)?;
+        Ok((tool, tool.permission(&call.arguments)?))
+    }
+}
+
+/// Refuse arguments that aren't an object or miss a field the schema requires
+fn check_required(schema: &serde_json::Value, arguments: &serde_json::Value) -> Result<()> {
+    let arguments = arguments
+        .as_object()
+        .ok_or_else(|| HybridLLMError::InvalidRequest("Tool arguments must be a JSON object".to_string()))?;
+    let required = schema["required"].as_array().into_iter().flatten().filter_map(|name| name.as_str());
+    for name in required {
+        if !arguments.contains_key(name) {
+            return Err(HybridLLMError::InvalidRequest(format!("Missing argument: {}", name)));
+        }
+    }
+    Ok(())
+}
+
+fn parse_arguments<T: for<'de> Deserialize<'de>>(arguments: serde_json::Value) -> Result<T> {
+    serde_json::from_value(arguments).map_err(|e| HybridLLMError::InvalidRequest(format!("Invalid arguments: {}", e)))
+}
+
+/// Searches indexed documents, the conversation's attached ones first
+struct RagSearchTool {
+    context: Arc<dyn ContextManager>,
+    /// Results from watched sources not re-crawled for this long are flagged stale
+    stale_after: Duration,
+}
+
+#[derive(Deserialize)]
+struct RagSearchArguments {
+    query: String,
+    limit: Option<usize>,
+}
+
+#[async_trait]
+impl Tool for RagSearchTool {
+    fn name(&self) -> &str {
+        "rag_search"
+    }
+
+    fn read_only(&self) -> bool {
+        true
+    }
+
+    fn description(&self) -> &str {
+        "Search the user's indexed documents for passages relevant to a query; stale passages come from a watched \
+         page or file that couldn't be checked lately and may be out of date"
+    }
+
+    fn parameters(&self) -> serde_json::Value {
+        serde_json::json!({
+            "type": "object",
+            "properties": {
+                "query": { "type": "string", "description": "What to look for" },
+                "limit": { "type": "integer", "minimum": 1, "maximum": MAX_SEARCH_RESULTS },
+            },
+            "required": ["query"],
+        })
+    }
+
+    async fn execute(&self, context: &RequestContext, arguments: serde_json::Value) -> Result<serde_json::Value> {
+        let arguments: RagSearchArguments = parse_arguments(arguments)?;
+        let limit = arguments.limit.unwrap_or(5).clamp(1, MAX_SEARCH_RESULTS);
+        let results = self
+            .context
+            .search_rag(&arguments.query, context.llm_id.as_deref(), context.conversation_id.as_ref(), limit)
+            .await?;
+
+        Ok(results
+            .into_iter()
+            .map(|result| {
+                serde_json::json!({
+                    "document_id": result.document_id,
+                    "content": result.content,
+                    "similarity": result.similarity,
+                    "source": result.metadata.get("source"),
+                    "checked_at": result.checked_at(),
+                    "stale": result.is_stale(self.stale_after),
+                })
+            })
+            .collect())
+    }
+}
+
+/// Reads a text file from the managed folders, once the security engine grants it
+/// Documents a parser plugin handles are read as the text it extracts
+struct FileReadTool {
+    filesystem: Arc<FileSystemInterface>,
+    plugins: Arc<PluginHost>,
+}
+
+#[derive(Deserialize)]
+struct FileReadArguments {
+    folder: ManagedFolder,
+    filename: String,
+}
+
+#[async_trait]
+impl Tool for FileReadTool {
+    fn name(&self) -> &str {
+        "read_file"
+    }
+
+    fn read_only(&self) -> bool {
+        true
+    }
+
+    fn description(&self) -> &str {
+        "Read a text file the user uploaded or downloaded"
+    }
+
+    fn parameters(&self) -> serde_json::Value {
+        serde_json::json!({
+            "type": "object",
+            "properties": {
+                "folder": { "type": "string", "enum": ["uploads", "downloads", "rag"] },
+                "filename": { "type": "string" },
+            },
+            "required": ["folder", "filename"],
+        })
+    }
+
+    fn permission(&self, arguments: &serde_json::Value) -> Result<Option<PermissionType>> {
+        let arguments: FileReadArguments = parse_arguments(arguments.clone())?;
+        let path = self.filesystem.managed_path(arguments.folder, &arguments.filename)?;
+        Ok(Some(PermissionType::FileRead { path: path.display().to_string() }))
+    }
+
+    async fn execute(&self, _context: &RequestContext, arguments: serde_json::Value) -> Result<serde_json::Value> {
+        let arguments: FileReadArguments = parse_arguments(arguments)?;
+        let content = match arguments.folder {
+            // Uploads may only be in object storage
+            ManagedFolder::Uploads => self.filesystem.read_upload(&arguments.filename).await?,
+            folder => {
+                let path = self.filesystem.managed_path(folder, &arguments.filename)?;
+                tokio::fs::read(&path).await.map_err(|e| HybridLLMError::FileSystemError(e.to_string()))?
+            }
+        };
+        let content = if self.plugins.can_parse(&arguments.filename) {
+            self.plugins.parse(&arguments.filename, content).await?.into_bytes()
+        } else if content.contains(&0) {
+            return Err(HybridLLMError::InvalidRequest(format!("{} is not a text file", arguments.filename)));
+        } else {
+            content
+        };
+
+        let truncated = content.len() > MAX_READ_BYTES;
+        let text = String::from_utf8_lossy(&content[..content.len().min(MAX_READ_BYTES)]).into_owned();
+        Ok(serde_json::json!({ "content": text, "truncated": truncated }))
+    }
+}
+
+/// Lists the files in the managed folders, e.g. to find one for `read_file`
+struct ListDocumentsTool {
+    filesystem: Arc<FileSystemInterface>,
+}
+
+#[derive(Deserialize)]
+struct ListDocumentsArguments {
+    folder: Option<ManagedFolder>,
+    extension: Option<String>,
+    indexed: Option<bool>,
+}
+
+#[async_trait]
+impl Tool for ListDocumentsTool {
+    fn name(&self) -> &str {
+        "list_documents"
+    }
+
+    fn read_only(&self) -> bool {
+        true
+    }
+
+    fn description(&self) -> &str {
+        "List the user's uploaded, downloaded and RAG files with their size and whether they are indexed"
+    }
+
+    fn parameters(&self) -> serde_json::Value {
+        serde_json::json!({
+            "type": "object",
+            "properties": {
+                "folder": { "type": "string", "enum": ["uploads", "downloads", "rag"] },
+                "extension": { "type": "string", "description": "Only files with this extension, e.g. pdf" },
+                "indexed": { "type": "boolean", "description": "Only files that are, or are not, indexed for rag_search" },
+            },
+        })
+    }
+
+    async fn execute(&self, _context: &RequestContext, arguments: serde_json::Value) -> Result<serde_json::Value> {
+        let arguments: ListDocumentsArguments = parse_arguments(arguments)?;
+        let query = FileQuery {
+            folder: arguments.folder,
+            extension: arguments.extension,
+            indexed: arguments.indexed,
+            ..FileQuery::default()
+        };
+        let mut files = self.filesystem.list_files(&query)?;
+        let truncated = files.len() > MAX_LISTED_FILES;
+        files.truncate(MAX_LISTED_FILES);
+        Ok(serde_json::json!({ "files": files, "truncated": truncated }))
+    }
+}
+
+/// Searches the web through the configured engine; off-allowlist engines need the user's approval
+struct WebSearchTool {
+    search: Arc<WebSearch>,
+}
+
+#[derive(Deserialize)]
+struct WebSearchArguments {
+    query: String,
+    count: Option<usize>,
+}
+
+#[async_trait]
+impl Tool for WebSearchTool {
+    fn name(&self) -> &str {
+        "web_search"
+    }
+
+    fn description(&self) -> &str {
+        "Search the web and return the titles, URLs and snippets of the top results"
+    }
+
+    fn parameters(&self) -> serde_json::Value {
+        serde_json::json!({
+            "type": "object",
+            "properties": {
+                "query": { "type": "string", "description": "What to search for" },
+                "count": { "type": "integer", "minimum": 1, "maximum": MAX_WEB_RESULTS },
+            },
+            "required": ["query"],
+        })
+    }
+
+    fn permission(&self, _arguments: &serde_json::Value) -> Result<Option<PermissionType>> {
+        Ok(Some(PermissionType::NetworkAccess { url: self.search.endpoint() }))
+    }
+
+    async fn execute(&self, _context: &RequestContext, arguments: serde_json::Value) -> Result<serde_json::Value> {
+        let arguments: WebSearchArguments = parse_arguments(arguments)?;
+        let count = arguments.count.unwrap_or(5).clamp(1, MAX_WEB_RESULTS);
+        let results = self.search.search(&arguments.query, count).await?;
+        Ok(serde_json::json!({ "query": arguments.query, "results": results }))
+    }
+}
+
+/// Downloads a page and returns its readable text; each fetch needs network access to its URL
+struct FetchUrlTool {
+    fetcher: Arc<PageFetcher>,
+}
+
+#[derive(Deserialize)]
+struct FetchUrlArguments {
+    url: String,
+}
+
+#[async_trait]
+impl Tool for FetchUrlTool {
+    fn name(&self) -> &str {
+        "fetch_url"
+    }
+
+    fn description(&self) -> &str {
+        "Download a web page and return its main text, e.g. to read or summarize a search result"
+    }
+
+    fn parameters(&self) -> serde_json::Value {
+        serde_json::json!({
+            "type": "object",
+            "properties": {
+                "url": { "type": "string", "description": "An http or https URL" },
+            },
+            "required": ["url"],
+        })
+    }
+
+    fn permission(&self, arguments: &serde_json::Value) -> Result<Option<PermissionType>> {
+        let arguments: FetchUrlArguments = parse_arguments(arguments.clone())?;
+        if url_host(&arguments.url).is_none() {
+            return Err(HybridLLMError::InvalidRequest(format!("Not an http(s) URL: {}", arguments.url)));
+        }
+        Ok(Some(PermissionType::NetworkAccess { url: arguments.url }))
+    }
+
+    async fn execute(&self, _context: &RequestContext, arguments: serde_json::Value) -> Result<serde_json::Value> {
+        let arguments: FetchUrlArguments = parse_arguments(arguments)?;
+        let page = self.fetcher.fetch(&arguments.url).await?;
+        let (title, text) = if page.is_html() {
+            let readable = extract_readable(&page.body);
+            (readable.title, readable.text)
+        } else {
+            (None, page.body)
+        };
+
+        let mut end = text.len().min(MAX_READ_BYTES);
+        while !text.is_char_boundary(end) {
+            end -= 1;
+        }
+        Ok(serde_json::json!({
+            "url": page.url,
+            "title": title,
+            "content": &text[..end],
+            "truncated": page.truncated || end < text.len(),
+        }))
+    }
+}
+
+/// Drives a headless Chromium in a sandbox of its own, one per conversation, apart from the user's browser
+/// Loading a page needs network access to its URL; the sandbox's proxy audits every connection
+struct BrowserTool {
+    sandbox_manager: Arc<SandboxManager>,
+    /// (sandbox, browser) by conversation, or by LLM outside one
+    sessions: tokio::sync::Mutex<HashMap<String, (Uuid, Uuid)>>,
+}
+
+impl BrowserTool {
+    /// The session's browser, started in a new sandbox when it has none or its sandbox was reclaimed
+    async fn browser(&self, key: &str, context: &RequestContext) -> Result<Uuid> {
+        let mut sessions = self.sessions.lock().await;
+        if let Some(&(sandbox_id, browser_id)) = sessions.get(key) {
+            let running = self.sandbox_manager.list_browsers(sandbox_id).await;
+            if running.iter().any(|browser| browser.id == browser_id) {
+                return Ok(browser_id);
+            }
+        }
+
+        let sandbox_id = self
+            .sandbox_manager
+            .create_sandbox(SandboxConfig {
+                id: Uuid::new_v4(),
+                network_mode: NetworkMode::Full,
+                cpu_limit: 100.0,
+                memory_limit_gb: 2.0,
+                disk_limit_gb: 1.0,
+                allowed_commands: vec![],
+                template: None,
+                max_lifetime_secs: None,
+                idle_timeout_secs: Some(BROWSER_IDLE_TIMEOUT_SECS),
+                execution_timeout_secs: None,
+                llm_id: context.llm_id.clone(),
+                gpu: None,
+                volumes: vec![],
+            })
+            .await?;
+        let browser_id = match self.sandbox_manager.open_browser(sandbox_id).await {
+            Ok(browser_id) => browser_id,
+            Err(e) => {
+                let _ = self.sandbox_manager.destroy_sandbox(sandbox_id).await;
+                return Err(e);
+            }
+        };
+        sessions.insert(key.to_string(), (sandbox_id, browser_id));
+        Ok(browser_id)
+    }
+}
+
+#[async_trait]
+impl Tool for BrowserTool {
+    fn name(&self) -> &str {
+        "browser"
+    }
+
+    fn description(&self) -> &str {
+        "Control a sandboxed headless browser: navigate to a URL, click or fill elements by CSS selector, \
+         extract visible text, take a screenshot, or close it. The page stays open between calls."
+    }
+
+    fn parameters(&self) -> serde_json::Value {
+        serde_json::json!({
+            "type": "object",
+            "properties": {
+                "action": { "type": "string", "enum": ["navigate", "click", "fill", "extract", "screenshot", "close"] },
+                "url": { "type": "string", "description": "For navigate" },
+                "selector": { "type": "string", "description": "CSS selector, for click, fill and extract" },
+                "value": { "type": "string", "description": "For fill" },
+            },
+            "required": ["action"],
+        })
+    }
+
+    fn permission(&self, arguments: &serde_json::Value) -> Result<Option<PermissionType>> {
+        if arguments["action"] != "navigate" {
+            return Ok(None);
+        }
+        let url = arguments["url"].as_str().unwrap_or_default();
+        if url_host(url).is_none() {
+            return Err(HybridLLMError::InvalidRequest(format!("Not an http(s) URL: {}", url)));
+        }
+        Ok(Some(PermissionType::NetworkAccess { url: url.to_string() }))
+    }
+
+    async fn execute(&self, context: &RequestContext, arguments: serde_json::Value) -> Result<serde_json::Value> {
+        let key = context.conversation_id.map_or_else(|| context.actor().to_string(), |id| id.to_string());
+        if arguments["action"] == "close" {
+            let session = self.sessions.lock().await.remove(&key);
+            if let Some((sandbox_id, _)) = session {
+                self.sandbox_manager.destroy_sandbox(sandbox_id).await?;
+            }
+            return Ok(serde_json::json!({ "closed": true }));
+        }
+
+        let action: BrowserAction = parse_arguments(arguments)?;
+        let browser_id = self.browser(&key, context).await?;
+        let outcome = self.sandbox_manager.browser_action(browser_id, &action).await?;
+        Ok(serde_json::json!(outcome))
+    }
+}
+
+/// Runs code in a throwaway sandbox from the warm pool
+struct SandboxExecTool {
+    sandbox_manager: Arc<SandboxManager>,
+}
+
+#[derive(Deserialize)]
+struct SandboxExecArguments {
+    language: CodeLanguage,
+    code: String,
+}
+
+#[async_trait]
+impl Tool for SandboxExecTool {
+    fn name(&self) -> &str {
+        "sandbox_exec"
+    }
+
+    fn description(&self) -> &str {
+        "Run a program in an isolated sandbox and return its output"
+    }
+
+    fn parameters(&self) -> serde_json::Value {
+        serde_json::json!({
+            "type": "object",
+            "properties": {
+                "language": { "type": "string", "enum": ["python", "java_script", "rust"] },
+                "code": { "type": "string" },
+            },
+            "required": ["language", "code"],
+        })
+    }
+
+    async fn execute(&self, context: &RequestContext, arguments: serde_json::Value) -> Result<serde_json::Value> {
+        let arguments: SandboxExecArguments = parse_arguments(arguments)?;
+        let result = run_in_sandbox(&self.sandbox_manager, context.actor(), arguments.language, &arguments.code).await?;
+        Ok(serde_json::json!({
+            "stdout": result.stdout,
+            "stderr": result.stderr,
+            "exit_code": result.exit_code,
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use common::traits::SecurityAnalysis;
+    use common::types::LockdownReason;
+
+    /// Denies every permission
+    struct DenyAll;
+
+    #[async_trait]
+    impl SecurityEngine for DenyAll {
+        async fn check_permission(&self, _: &RequestContext, _: &PermissionType, _: &str) -> Result<bool> {
+            Ok(false)
+        }
+        async fn analyze_command(&self, _: &str) -> Result<SecurityAnalysis> {
+            unimplemented!()
+        }
+        async fn trigger_lockdown(&self, _: LockdownReason) -> Result<()> {
+            unimplemented!()
+        }
+        async fn release_lockdown(&self, _: &str) -> Result<()> {
+            unimplemented!()
+        }
+        async fn lockdown_state(&self) -> Result<LockdownState> {
+            Ok(LockdownState::Normal)
+        }
+        async fn simulate(&self, _: &RequestContext, _: &PlannedAction) -> Result<SimulatedDecision> {
+            Ok(SimulatedDecision::NeedsApproval { reason: "Denies everything".to_string() })
+        }
+    }
+
+    /// Echoes its arguments; needs a command permission when asked to
+    struct Echo;
+
+    #[async_trait]
+    impl Tool for Echo {
+        fn name(&self) -> &str {
+            "echo"
+        }
+        fn description(&self) -> &str {
+            "Echo the arguments"
+        }
+        fn parameters(&self) -> serde_json::Value {
+            serde_json::json!({ "type": "object", "required": ["text"] })
+        }
+        fn permission(&self, arguments: &serde_json::Value) -> Result<Option<PermissionType>> {
+            Ok(arguments["privileged"].as_bool().unwrap_or(false).then(|| PermissionType::Command {
+                command: "echo".to_string(),
+            }))
+        }
+        async fn execute(&self, _: &RequestContext, arguments: serde_json::Value) -> Result<serde_json::Value> {
+            Ok(arguments)
+        }
+    }
+
+    fn call(name: &str, arguments: serde_json::Value) -> ToolCall {
+        ToolCall { id: "call_1".to_string(), name: name.to_string(), arguments }
+    }
+
+    #[tokio::test]
+    async fn test_registry_call() {
+        let mut registry = ToolRegistry::new();
+        registry.register(Arc::new(Echo));
+        let context = RequestContext::user().with_llm("local");
+
+        let ok = registry.call(&DenyAll, &context, &call("echo", serde_json::json!({ "text": "hi" }))).await;
+        assert_eq!(ok.output, Some(serde_json::json!({ "text": "hi" })));
+
+        let unknown = registry.call(&DenyAll, &context, &call("rm", serde_json::json!({}))).await;
+        assert!(unknown.error.unwrap().contains("No tool named rm"));
+
+        let missing = registry.call(&DenyAll, &context, &call("echo", serde_json::json!({}))).await;
+        assert!(missing.error.unwrap().contains("Missing argument: text"));
+
+        let denied = registry
+            .call(&DenyAll, &context, &call("echo", serde_json::json!({ "text": "hi", "privileged": true })))
+            .await;
+        assert!(denied.error.unwrap().starts_with("Permission denied"));
+    }
+
+    #[tokio::test]
+    async fn test_registry_simulate() {
+        let mut registry = ToolRegistry::new();
+        registry.register(Arc::new(Echo));
+        let context = RequestContext::user().with_llm("local");
+
+        let plan = [
+            call("echo", serde_json::json!({ "text": "hi" })),
+            call("echo", serde_json::json!({ "text": "hi", "privileged": true })),
+            call("rm", serde_json::json!({})),
+        ];
+        let decisions = registry.simulate(&DenyAll, &context, &plan).await.unwrap();
+        assert_eq!(decisions[0], SimulatedDecision::Allowed);
+        assert!(matches!(decisions[1], SimulatedDecision::NeedsApproval { .. }));
+        assert!(matches!(&decisions[2], SimulatedDecision::Denied { reason } if reason.contains("No tool named rm")));
+    }
+}
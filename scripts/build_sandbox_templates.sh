@@ -0,0 +1,97 @@
+#!/bin/bash
+# Build prebuilt sandbox templates so sandboxes start without network installs
+#
+# Usage: scripts/build_sandbox_templates.sh [template...]
+# Templates: python-data node rust shell-minimal (default: all)
+
+set -e
+
+# Configuration
+TEMPLATES_DIR="${SANDBOX_TEMPLATES_DIR:-./data/sandboxes/templates}"
+NODE_VERSION="${NODE_VERSION:-20.11.1}"
+PYTHON_PACKAGES="numpy pandas matplotlib scipy requests"
+
+TEMPLATES=("$@")
+if [ ${#TEMPLATES[@]} -eq 0 ]; then
+    TEMPLATES=(python-data node rust shell-minimal)
+fi
+
+echo "🚀 Building sandbox templates in $TEMPLATES_DIR..."
+
+# Start a template from scratch and write its manifest
+# Manifest paths are relative to the sandbox root; $SANDBOX_ROOT expands at runtime
+begin_template() {
+    local name="$1" manifest="$2"
+    rm -rf "$TEMPLATES_DIR/$name"
+    mkdir -p "$TEMPLATES_DIR/$name/rootfs/.toolchain"
+    echo "$manifest" > "$TEMPLATES_DIR/$name/template.json"
+    echo "📦 Building $name..."
+}
+
+build_python_data() {
+    begin_template python-data '{
+  "description": "Python 3 with numpy, pandas, matplotlib, scipy and requests",
+  "path": [".toolchain/python/bin"],
+  "env": {
+    "PYTHONPATH": "$SANDBOX_ROOT/.toolchain/python",
+    "MPLBACKEND": "Agg"
+  }
+}'
+    local target="$TEMPLATES_DIR/python-data/rootfs/.toolchain/python"
+    # --target keeps the install relocatable, unlike a virtualenv
+    python3 -m pip install --quiet --target "$target" $PYTHON_PACKAGES
+}
+
+build_node() {
+    begin_template node '{
+  "description": "Node.js with npm",
+  "path": [".toolchain/node/bin"],
+  "env": {
+    "NPM_CONFIG_CACHE": "$SANDBOX_ROOT/.cache/npm"
+  }
+}'
+    local arch
+    case "$(uname -m)" in
+        x86_64) arch="x64" ;;
+        aarch64) arch="arm64" ;;
+        *) echo "❌ Unsupported architecture: $(uname -m)"; exit 1 ;;
+    esac
+    local target="$TEMPLATES_DIR/node/rootfs/.toolchain/node"
+    mkdir -p "$target"
+    curl -fsSL "https://nodejs.org/dist/v$NODE_VERSION/node-v$NODE_VERSION-linux-$arch.tar.xz" \
+        | tar -xJ --strip-components=1 -C "$target"
+}
+
+build_rust() {
+    begin_template rust '{
+  "description": "Rust stable toolchain with cargo",
+  "path": [".toolchain/cargo/bin"],
+  "env": {
+    "RUSTUP_HOME": "$SANDBOX_ROOT/.toolchain/rustup",
+    "CARGO_HOME": "$SANDBOX_ROOT/.toolchain/cargo"
+  }
+}'
+    local toolchain="$TEMPLATES_DIR/rust/rootfs/.toolchain"
+    curl -fsSL https://sh.rustup.rs \
+        | RUSTUP_HOME="$toolchain/rustup" CARGO_HOME="$toolchain/cargo" \
+          sh -s -- -y --quiet --profile minimal --no-modify-path
+}
+
+build_shell_minimal() {
+    begin_template shell-minimal '{
+  "description": "POSIX shell and coreutils only"
+}'
+}
+
+for template in "${TEMPLATES[@]}"; do
+    case "$template" in
+        python-data) build_python_data ;;
+        node) build_node ;;
+        rust) build_rust ;;
+        shell-minimal) build_shell_minimal ;;
+        *) echo "❌ Unknown template: $template"; exit 1 ;;
+    esac
+    echo "✅ $template ready"
+done
+
+echo "🎉 Sandbox templates built"
@@ -0,0 +1,15 @@
+-- Versioned RAG documents
+-- Chunks are kept across document versions so unchanged sections are not re-embedded,
+-- and older versions stay queryable
+
+ALTER TABLE document_chunks
+    ADD COLUMN IF NOT EXISTS chunk_hash VARCHAR(64),              -- SHA256 of chunk_text
+    ADD COLUMN IF NOT EXISTS valid_from_version INTEGER NOT NULL DEFAULT 1,
+    ADD COLUMN IF NOT EXISTS valid_to_version INTEGER;            -- NULL while still current
+
+-- Index for the chunks of each document's current version
+CREATE INDEX IF NOT EXISTS idx_chunks_current
+    ON document_chunks(document_id) WHERE valid_to_version IS NULL;
+
+COMMENT ON COLUMN document_chunks.valid_from_version IS 'First document version containing this chunk';
+COMMENT ON COLUMN document_chunks.valid_to_version IS 'First document version no longer containing this chunk';
@@ -0,0 +1,12 @@
+-- Conversation management
+-- Conversations can be renamed, and deleting one moves it to the trash until it is purged
+
+ALTER TABLE conversations
+    ADD COLUMN IF NOT EXISTS title TEXT,                          -- NULL until set; listed as the first user message
+    ADD COLUMN IF NOT EXISTS trashed_at TIMESTAMP WITH TIME ZONE; -- NULL unless in the trash
+
+-- Index for listing the trash
+CREATE INDEX IF NOT EXISTS idx_conversations_trashed
+    ON conversations(trashed_at) WHERE trashed_at IS NOT NULL;
+
+COMMENT ON COLUMN conversations.trashed_at IS 'When the conversation was moved to the trash';
@@ -0,0 +1,11 @@
+-- Documents attached to conversations
+-- Chunks of a conversation's attached documents rank first when retrieving for it
+
+CREATE TABLE IF NOT EXISTS conversation_documents (
+    conversation_id UUID NOT NULL REFERENCES conversations(id) ON DELETE CASCADE,
+    document_id UUID NOT NULL,                                    -- Uploaded document, tracked by the app
+    attached_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (conversation_id, document_id)
+);
+
+COMMENT ON TABLE conversation_documents IS 'Documents whose chunks are preferred when retrieving for a conversation';
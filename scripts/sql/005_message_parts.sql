@@ -0,0 +1,8 @@
+-- Multi-part messages
+-- Messages with images, tool calls or file references keep their parts here; `content` keeps their
+-- text so titles and search still work. Plain text messages, including every earlier row, leave it NULL
+
+ALTER TABLE messages
+    ADD COLUMN IF NOT EXISTS parts JSONB; -- NULL for plain text messages
+
+COMMENT ON COLUMN messages.parts IS 'Parts of a multi-part message, as serialized by MessageContent';
@@ -0,0 +1,13 @@
+-- RAG collections
+-- Documents are grouped by where they came from: uploaded files, or pages sent over from the browser
+
+ALTER TABLE documents
+    ADD COLUMN IF NOT EXISTS collection VARCHAR(64) NOT NULL DEFAULT 'uploads';
+
+CREATE INDEX IF NOT EXISTS idx_documents_collection ON documents(collection);
+
+-- Full-text match over current chunks, which ranks search results until embeddings are real
+CREATE INDEX IF NOT EXISTS idx_chunks_text
+    ON document_chunks USING GIN (to_tsvector('simple', chunk_text)) WHERE valid_to_version IS NULL;
+
+COMMENT ON COLUMN documents.collection IS 'uploads or browsing; browsing documents keep their page URL in metadata';
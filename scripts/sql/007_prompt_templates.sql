@@ -0,0 +1,20 @@
+-- Prompt library
+-- Reusable prompts with {{variable}} placeholders; every edit is kept as a new version
+
+CREATE TABLE IF NOT EXISTS prompt_templates (
+    id UUID PRIMARY KEY,
+    name TEXT NOT NULL UNIQUE,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW()
+);
+
+CREATE TABLE IF NOT EXISTS prompt_template_versions (
+    template_id UUID NOT NULL REFERENCES prompt_templates(id) ON DELETE CASCADE,
+    version INTEGER NOT NULL,                                     -- 1 for the first save, then one per edit
+    description TEXT NOT NULL DEFAULT '',
+    body TEXT NOT NULL,
+    model_overrides JSONB NOT NULL DEFAULT '{}',                  -- LLM id -> body used instead for that LLM
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (template_id, version)
+);
+
+COMMENT ON TABLE prompt_template_versions IS 'Every saved version of a prompt template; the highest version is current';
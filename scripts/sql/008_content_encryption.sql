@@ -0,0 +1,12 @@
+-- Encryption at rest
+-- Message content, titles, context values and document text are stored as `enc:v1:` ciphertext once a
+-- passphrase is set; rows without the prefix were written before and are rewritten when it is set
+
+CREATE TABLE IF NOT EXISTS content_encryption (
+    singleton BOOLEAN PRIMARY KEY DEFAULT TRUE CHECK (singleton), -- one passphrase per database
+    salt BYTEA NOT NULL,                                          -- Argon2id salt the key is derived with
+    verifier TEXT NOT NULL,                                       -- known text encrypted with the key
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW()
+);
+
+COMMENT ON TABLE content_encryption IS 'How the content key is derived from the passphrase; the key itself is kept in the OS keyring';
@@ -0,0 +1,11 @@
+-- RAG freshness
+-- Documents indexed from a watched folder or URL are re-crawled on a schedule and found again by their source;
+-- checked_at tells how current they are
+
+ALTER TABLE documents
+    ADD COLUMN IF NOT EXISTS source TEXT,                         -- File path or URL; NULL unless watched
+    ADD COLUMN IF NOT EXISTS checked_at TIMESTAMP WITH TIME ZONE; -- Last re-crawl that read the source
+
+CREATE UNIQUE INDEX IF NOT EXISTS idx_documents_source ON documents(source) WHERE source IS NOT NULL;
+
+COMMENT ON COLUMN documents.checked_at IS 'Results from a source not checked for a while are flagged stale';
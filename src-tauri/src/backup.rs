@@ -0,0 +1,457 @@
+use chrono::{DateTime, Utc};
+use flate2::{read::GzDecoder, write::GzEncoder, Compression};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+use tauri::{AppHandle, Manager};
+use tracing::{info, warn};
+use walkdir::WalkDir;
+
+use common::errors::{HybridLLMError, Result};
+use common::traits::SecurityEngine;
+use common::types::LockdownState;
+use context_manager::TableDump;
+
+use crate::profiles::{self, Profiles};
+use crate::settings::{Settings, SETTINGS_FILE};
+use crate::state::AppState;
+
+/// Version of the archive layout this build writes; archives from newer builds are refused
+pub const BACKUP_FORMAT_VERSION: u32 = 1;
+
+/// First entry of every archive, so listing backups only reads their start
+const MANIFEST_ENTRY: &str = "manifest.json";
+const SETTINGS_ENTRY: &str = "settings.toml";
+/// Files of the data directory, under their path relative to it
+const DATA_DIR_ENTRY: &str = "data";
+/// One `<profile>.json` per profile stored in PostgreSQL
+const DATABASE_DIR_ENTRY: &str = "database";
+
+const ARCHIVE_PREFIX: &str = "hybrid-llm-backup-";
+const ARCHIVE_EXTENSION: &str = ".tar.gz";
+/// Scheduled archives end with this; only they are pruned
+const SCHEDULED_SUFFIX: &str = "-scheduled";
+
+/// Where an archive is unpacked during a restore, inside the data directory so files move in by renaming
+const RESTORE_STAGING: &str = ".restoring";
+
+/// Left out of the data directory: sandboxes are recreated, files still arriving are incomplete,
+/// and local models' KV caches are only a speed-up
+const EXCLUDED_DATA: [&str; 4] = ["sandboxes", "incoming", RESTORE_STAGING, crate::models::SESSIONS_DIR];
+
+/// How often the scheduler checks whether a backup is due
+const SCHEDULE_CHECK_INTERVAL: Duration = Duration::from_secs(10 * 60);
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BackupManifest {
+    pub format_version: u32,
+    pub created_at: DateTime<Utc>,
+    /// Version of the app that wrote the archive
+    pub app_version: String,
+    /// Profiles whose PostgreSQL tables are in the archive; conversations kept in memory are not
+    pub databases: Vec<String>,
+    /// Files from the data directory: audit log, ledgers, profiles, session and managed folders
+    pub files: usize,
+    pub scheduled: bool,
+}
+
+/// An archive in the backup directory
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BackupInfo {
+    pub path: PathBuf,
+    pub size: u64,
+    pub manifest: BackupManifest,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RestoreReport {
+    pub manifest: BackupManifest,
+    /// Profiles whose tables were replaced with the archive's
+    pub databases: Vec<String>,
+    /// Profiles whose tables were left alone, with the reason
+    pub skipped: Vec<String>,
+    pub files: usize,
+    /// Restored settings and files are read at startup
+    pub restart_required: bool,
+}
+
+/// Archive the settings, every profile's database and the data directory into the backup directory
+/// Scheduled backups beyond `backup.keep` are removed, oldest first
+pub async fn create_backup(state: &AppState, scheduled: bool) -> Result<BackupInfo> {
+    if !state.security_engine.lockdown_state().await?.allows_reads() {
+        return Err(HybridLLMError::LockdownActive("Backups cannot be made during a lockdown".to_string()));
+    }
+    let settings = state.settings.read().await.clone();
+    let databases = export_databases(&state.profiles).await?;
+
+    let manifest = BackupManifest {
+        format_version: BACKUP_FORMAT_VERSION,
+        created_at: Utc::now(),
+        app_version: env!("CARGO_PKG_VERSION").to_string(),
+        databases: databases.iter().map(|(profile, _)| profile.clone()).collect(),
+        files: 0,
+        scheduled,
+    };
+    let backup_dir = settings.backup_dir();
+    let data_dir = settings.paths.data_dir.clone();
+    let written = tokio::task::spawn_blocking(move || write_archive(manifest, &databases, &data_dir, &backup_dir))
+        .await
+        .map_err(|e| HybridLLMError::Other(e.into()))?;
+
+    state.security_engine
+        .audit()
+        .log(
+            None,
+            "Backup created".to_string(),
+            json!({
+                "path": written.as_ref().ok().map(|backup| &backup.path),
+                "scheduled": scheduled,
+                "databases": written.as_ref().ok().map(|backup| &backup.manifest.databases),
+                "files": written.as_ref().ok().map(|backup| backup.manifest.files),
+            }),
+            written.is_ok(),
+            written.as_ref().err().map(|e| e.to_string()),
+        )
+        .await;
+    let backup = written?;
+    info!("💾 Backed up {} files and {} databases to {:?}", backup.manifest.files, backup.manifest.databases.len(), backup.path);
+
+    if scheduled {
+        let backup_dir = settings.backup_dir();
+        let keep = settings.backup.keep;
+        tokio::task::spawn_blocking(move || prune(&backup_dir, keep))
+            .await
+            .map_err(|e| HybridLLMError::Other(e.into()))?;
+    }
+    Ok(backup)
+}
+
+/// Archives in the backup directory, newest first; unreadable ones are reported and left out
+pub fn list_backups(backup_dir: &Path) -> Result<Vec<BackupInfo>> {
+    let entries = match std::fs::read_dir(backup_dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(fs_err(backup_dir, e)),
+    };
+
+    let mut backups = Vec::new();
+    for entry in entries {
+        let path = entry.map_err(|e| fs_err(backup_dir, e))?.path();
+        let name = path.file_name().and_then(|name| name.to_str()).unwrap_or_default();
+        if !(name.starts_with(ARCHIVE_PREFIX) && name.ends_with(ARCHIVE_EXTENSION)) {
+            continue;
+        }
+        match read_manifest(&path) {
+            Ok(manifest) => {
+                let size = std::fs::metadata(&path).map(|m| m.len()).unwrap_or_default();
+                backups.push(BackupInfo { path, size, manifest });
+            }
+            Err(e) => warn!("⚠️  Skipping unreadable backup {:?}: {}", path, e),
+        }
+    }
+    backups.sort_by(|a, b| b.manifest.created_at.cmp(&a.manifest.created_at));
+    Ok(backups)
+}
+
+/// Replace the databases, data files and settings with an archive's
+/// Profiles keep their own database URLs and this install keeps its `paths`; the app must restart afterwards
+pub async fn restore_backup(state: &AppState, archive: &Path) -> Result<RestoreReport> {
+    if state.security_engine.lockdown_state().await? != LockdownState::Normal {
+        return Err(HybridLLMError::LockdownActive("Backups cannot be restored during a lockdown".to_string()));
+    }
+    let settings = state.settings.read().await.clone();
+    let staging = settings.paths.data_dir.join(RESTORE_STAGING);
+
+    let restored = restore_from(state, &settings, archive, &staging).await;
+    if let Err(e) = std::fs::remove_dir_all(&staging) {
+        if e.kind() != std::io::ErrorKind::NotFound {
+            warn!("⚠️  Could not clean up {:?}: {}", staging, e);
+        }
+    }
+
+    // The restored audit log is appended to, so this entry follows the archive's
+    state.security_engine
+        .audit()
+        .log(
+            None,
+            "Backup restored".to_string(),
+            json!({
+                "path": archive,
+                "created_at": restored.as_ref().ok().map(|report| report.manifest.created_at),
+                "databases": restored.as_ref().ok().map(|report| &report.databases),
+                "skipped": restored.as_ref().ok().map(|report| &report.skipped),
+                "files": restored.as_ref().ok().map(|report| report.files),
+            }),
+            restored.is_ok(),
+            restored.as_ref().err().map(|e| e.to_string()),
+        )
+        .await;
+    let report = restored?;
+    info!("♻️  Restored the backup of {} from {:?}", report.manifest.created_at, archive);
+    Ok(report)
+}
+
+async fn restore_from(state: &AppState, settings: &Settings, archive: &Path, staging: &Path) -> Result<RestoreReport> {
+    let (archive_path, staging_dir) = (archive.to_path_buf(), staging.to_path_buf());
+    let manifest = tokio::task::spawn_blocking(move || unpack(&archive_path, &staging_dir))
+        .await
+        .map_err(|e| HybridLLMError::Other(e.into()))??;
+
+    let (mut databases, mut skipped) = (Vec::new(), Vec::new());
+    for profile in &manifest.databases {
+        match import_database(&state.profiles, profile, &staging.join(DATABASE_DIR_ENTRY)).await {
+            Ok(rows) => {
+                info!("♻️  Restored {} rows into the database of profile {}", rows, profile);
+                databases.push(profile.clone());
+            }
+            Err(e) => skipped.push(format!("{}: {}", profile, e)),
+        }
+    }
+
+    let data_dir = settings.paths.data_dir.clone();
+    let paths = settings.paths.clone();
+    let staging = staging.to_path_buf();
+    let files = tokio::task::spawn_blocking(move || -> Result<usize> {
+        let files = move_into(&staging.join(DATA_DIR_ENTRY), &data_dir)?;
+        // Settings from another machine would point this install at directories it doesn't have
+        let archived_settings = staging.join(SETTINGS_ENTRY);
+        if archived_settings.exists() {
+            let mut restored = Settings::load(&archived_settings)?;
+            restored.paths = paths;
+            restored.save(Path::new(SETTINGS_FILE))?;
+        }
+        Ok(files)
+    })
+    .await
+    .map_err(|e| HybridLLMError::Other(e.into()))??;
+
+    Ok(RestoreReport { manifest, databases, skipped, files, restart_required: true })
+}
+
+/// Make a backup whenever the newest scheduled one is older than `backup.interval_hours`
+pub fn spawn_scheduler(app: AppHandle) {
+    tokio::spawn(async move {
+        loop {
+            tokio::time::sleep(SCHEDULE_CHECK_INTERVAL).await;
+            let state = app.state::<AppState>();
+            let settings = state.settings.read().await.clone();
+            let Some(interval_hours) = settings.backup.interval_hours else {
+                continue;
+            };
+
+            let backup_dir = settings.backup_dir();
+            let last = match tokio::task::spawn_blocking(move || list_backups(&backup_dir)).await {
+                Ok(Ok(backups)) => backups.into_iter().find(|backup| backup.manifest.scheduled).map(|backup| backup.manifest.created_at),
+                Ok(Err(e)) => {
+                    warn!("⚠️  Could not list backups: {}", e);
+                    continue;
+                }
+                Err(_) => continue,
+            };
+            let due = last.is_none_or(|at| Utc::now() - at >= chrono::Duration::hours(interval_hours as i64));
+            if due {
+                if let Err(e) = create_backup(&state, true).await {
+                    warn!("⚠️  Scheduled backup failed: {}", e);
+                }
+            }
+        }
+    });
+}
+
+/// Tables of every profile stored in PostgreSQL; opening a store may read its key from the keyring
+async fn export_databases(profiles: &Arc<Profiles>) -> Result<Vec<(String, Vec<TableDump>)>> {
+    let mut databases = Vec::new();
+    for profile in profiles.list().profiles {
+        let Some(database) = open_database(profiles, &profile.name).await? else {
+            continue;
+        };
+        databases.push((profile.name.clone(), database.export_tables().await?));
+    }
+    Ok(databases)
+}
+
+async fn import_database(profiles: &Arc<Profiles>, profile: &str, database_dir: &Path) -> Result<usize> {
+    // The name becomes part of a file and environment variable name
+    profiles::validate_name(profile)?;
+    let database = open_database(profiles, profile).await?.ok_or_else(|| {
+        HybridLLMError::InvalidRequest("conversations are kept in memory here, so there is no database to restore into".to_string())
+    })?;
+
+    let path = database_dir.join(format!("{}.json", profile));
+    let text = std::fs::read_to_string(&path).map_err(|e| fs_err(&path, e))?;
+    let dumps: Vec<TableDump> = serde_json::from_str(&text)
+        .map_err(|e| HybridLLMError::InvalidRequest(format!("{}: {}", path.display(), e)))?;
+    database.import_tables(&dumps).await
+}
+
+async fn open_database(profiles: &Arc<Profiles>, name: &str) -> Result<Option<Arc<context_manager::DatabaseContextManager>>> {
+    let opened = Arc::clone(profiles);
+    let name = name.to_string();
+    tokio::task::spawn_blocking(move || opened.context(&name).map(|_| opened.database(&name)))
+        .await
+        .map_err(|e| HybridLLMError::Other(e.into()))?
+}
+
+/// Write the archive next to its final name and rename it once complete, so a listing never sees half of it
+fn write_archive(
+    mut manifest: BackupManifest,
+    databases: &[(String, Vec<TableDump>)],
+    data_dir: &Path,
+    backup_dir: &Path,
+) -> Result<BackupInfo> {
+    let files = data_files(data_dir, backup_dir);
+    manifest.files = files.len();
+
+    std::fs::create_dir_all(backup_dir).map_err(|e| fs_err(backup_dir, e))?;
+    let suffix = if manifest.scheduled { SCHEDULED_SUFFIX } else { "" };
+    let name = format!("{}{}{}{}", ARCHIVE_PREFIX, manifest.created_at.format("%Y%m%d-%H%M%S"), suffix, ARCHIVE_EXTENSION);
+    let path = backup_dir.join(name);
+    let mut partial = path.as_os_str().to_os_string();
+    partial.push(".tmp");
+    let partial = PathBuf::from(partial);
+
+    let file = File::create(&partial).map_err(|e| fs_err(&partial, e))?;
+    let builder = tar::Builder::new(GzEncoder::new(file, Compression::default()));
+    if let Err(e) = append_all(builder, &manifest, databases, data_dir, &files) {
+        let _ = std::fs::remove_file(&partial);
+        return Err(HybridLLMError::FileSystemError(format!("Failed to write backup {}: {}", path.display(), e)));
+    }
+    std::fs::rename(&partial, &path).map_err(|e| fs_err(&path, e))?;
+
+    let size = std::fs::metadata(&path).map(|m| m.len()).map_err(|e| fs_err(&path, e))?;
+    Ok(BackupInfo { path, size, manifest })
+}
+
+/// The manifest first, then the settings, the databases and the data files
+fn append_all<W: std::io::Write>(
+    mut builder: tar::Builder<GzEncoder<W>>,
+    manifest: &BackupManifest,
+    databases: &[(String, Vec<TableDump>)],
+    data_dir: &Path,
+    files: &[PathBuf],
+) -> std::io::Result<()> {
+    append_bytes(&mut builder, MANIFEST_ENTRY, &serde_json::to_vec_pretty(manifest)?)?;
+    match std::fs::read(SETTINGS_FILE) {
+        Ok(settings) => append_bytes(&mut builder, SETTINGS_ENTRY, &settings)?,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+        Err(e) => return Err(e),
+    }
+    for (profile, tables) in databases {
+        let entry = format!("{}/{}.json", DATABASE_DIR_ENTRY, profile);
+        append_bytes(&mut builder, &entry, &serde_json::to_vec(tables)?)?;
+    }
+    for relative in files {
+        builder.append_path_with_name(data_dir.join(relative), Path::new(DATA_DIR_ENTRY).join(relative))?;
+    }
+    builder.into_inner()?.finish()?;
+    Ok(())
+}
+
+fn append_bytes<W: std::io::Write>(builder: &mut tar::Builder<W>, name: &str, bytes: &[u8]) -> std::io::Result<()> {
+    let mut header = tar::Header::new_gnu();
+    header.set_size(bytes.len() as u64);
+    header.set_mode(0o600);
+    header.set_mtime(Utc::now().timestamp().max(0) as u64);
+    header.set_cksum();
+    builder.append_data(&mut header, name, bytes)
+}
+
+/// Regular files of the data directory relative to it, without excluded folders, backups or partial writes
+fn data_files(data_dir: &Path, backup_dir: &Path) -> Vec<PathBuf> {
+    WalkDir::new(data_dir)
+        .min_depth(1)
+        .into_iter()
+        .filter_entry(|entry| {
+            let top_level_excluded =
+                entry.depth() == 1 && EXCLUDED_DATA.iter().any(|name| entry.file_name() == *name);
+            !top_level_excluded && entry.path() != backup_dir
+        })
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.file_type().is_file())
+        .filter(|entry| !entry.file_name().to_string_lossy().ends_with(".tmp"))
+        .filter_map(|entry| entry.path().strip_prefix(data_dir).ok().map(Path::to_path_buf))
+        .collect()
+}
+
+fn read_manifest(path: &Path) -> Result<BackupManifest> {
+    let file = File::open(path).map_err(|e| fs_err(path, e))?;
+    let mut archive = tar::Archive::new(GzDecoder::new(file));
+    let not_a_backup = || HybridLLMError::InvalidRequest(format!("{} is not a platform backup", path.display()));
+
+    let mut entries = archive.entries().map_err(|_| not_a_backup())?;
+    let mut first = entries.next().ok_or_else(not_a_backup)?.map_err(|_| not_a_backup())?;
+    if first.path().map_err(|_| not_a_backup())? != Path::new(MANIFEST_ENTRY) {
+        return Err(not_a_backup());
+    }
+    serde_json::from_reader(&mut first).map_err(|_| not_a_backup())
+}
+
+/// Unpack an archive this build can read into `staging`, which is emptied first
+fn unpack(archive: &Path, staging: &Path) -> Result<BackupManifest> {
+    let manifest = read_manifest(archive)?;
+    if manifest.format_version == 0 || manifest.format_version > BACKUP_FORMAT_VERSION {
+        return Err(HybridLLMError::InvalidRequest(format!(
+            "Backup format {} is not supported; this build restores formats 1 to {}",
+            manifest.format_version, BACKUP_FORMAT_VERSION
+        )));
+    }
+
+    if let Err(e) = std::fs::remove_dir_all(staging) {
+        if e.kind() != std::io::ErrorKind::NotFound {
+            return Err(fs_err(staging, e));
+        }
+    }
+    std::fs::create_dir_all(staging).map_err(|e| fs_err(staging, e))?;
+    let file = File::open(archive).map_err(|e| fs_err(archive, e))?;
+    // Entries reaching outside `staging` are skipped by `unpack`
+    tar::Archive::new(GzDecoder::new(file))
+        .unpack(staging)
+        .map_err(|e| HybridLLMError::FileSystemError(format!("Failed to unpack {}: {}", archive.display(), e)))?;
+    Ok(manifest)
+}
+
+/// Move every file under `from` to the same place under `to`, replacing what is there; returns how many moved
+/// Files only in `to` are kept
+fn move_into(from: &Path, to: &Path) -> Result<usize> {
+    let mut moved = 0;
+    for entry in WalkDir::new(from).min_depth(1) {
+        let entry = entry.map_err(|e| HybridLLMError::FileSystemError(e.to_string()))?;
+        if !entry.file_type().is_file() {
+            continue;
+        }
+        let Ok(relative) = entry.path().strip_prefix(from) else {
+            continue;
+        };
+        let destination = to.join(relative);
+        if let Some(parent) = destination.parent() {
+            std::fs::create_dir_all(parent).map_err(|e| fs_err(parent, e))?;
+        }
+        std::fs::rename(entry.path(), &destination).map_err(|e| fs_err(&destination, e))?;
+        moved += 1;
+    }
+    Ok(moved)
+}
+
+/// Remove scheduled backups beyond the newest `keep`
+fn prune(backup_dir: &Path, keep: usize) {
+    let backups = match list_backups(backup_dir) {
+        Ok(backups) => backups,
+        Err(e) => {
+            warn!("⚠️  Could not list backups to prune: {}", e);
+            return;
+        }
+    };
+    for backup in backups.iter().filter(|backup| backup.manifest.scheduled).skip(keep) {
+        match std::fs::remove_file(&backup.path) {
+            Ok(()) => info!("🗑️  Removed old backup {:?}", backup.path),
+            Err(e) => warn!("⚠️  Could not remove old backup {:?}: {}", backup.path, e),
+        }
+    }
+}
+
+fn fs_err(path: &Path, e: std::io::Error) -> HybridLLMError {
+    HybridLLMError::FileSystemError(format!("{}: {}", path.display(), e))
+}
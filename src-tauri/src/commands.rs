@@ -1,13 +1,57 @@
-use tauri::State;
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::Arc;
+use tauri::{AppHandle, Manager, State};
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
-use tracing::{info, error, debug};
+use tracing::{info, error, debug, warn};
 
 use common::{
-    types::{LLMInstance, PermissionScope, LockdownState, LockdownReason},
-    errors::Result,
+    types::{
+        Capability, CodeLanguage, ContentPart, Conversation, LLMInstance, LockdownState, Message, MessageRole,
+        NewPromptTemplate, PermissionScope, LockdownReason, PromptTemplate, PromptTemplateRef, RequestContext,
+        SandboxTemplate, SandboxUsage,
+    },
+    errors::{ErrorCode, HybridLLMError, Result},
+    language::{detect_language, LANGUAGE_KEY},
+    CompletionMessage, CompletionRequest, ImageInput, PlannedAction, Priority, SecurityEngine, SimulatedDecision,
+    StreamChunk, Usage,
 };
-use crate::state::{AppState, SystemState, Document, AuditLogEntry};
+use context_manager::RecrawlReport;
+use filesystem_interface::{
+    FileHash, FileMetadata, FileQuery, FileVersion, FolderUsage, ManagedFolder, ShareLink, SkippedEntry, TrashEntry,
+    DEFAULT_SHARE_TTL, DEFAULT_TRASH_RETENTION,
+};
+use llama_cpp_provider::gguf::{self, ModelInfo};
+use llama_cpp_provider::hardware::HardwareInfo;
+use llm_pool::{
+    BenchmarkOptions, BenchmarkRun, EvalCase, EvaluationOptions, EvaluationReport, UsageQuery, UsageSummary, STANDARD_SUITE,
+};
+use security_engine::{AuditPage, AuditQuery, PendingApproval};
+use sandbox_manager::{
+    CellOutput, ExecutionEvent, ExecutionResult, FileChange, KernelInfo, PluginFailure, PluginInfo, PortForward, SandboxFile,
+    SnapshotInfo, VolumeInfo,
+};
+use crate::backup::{self, BackupInfo, RestoreReport};
+use crate::browser_bridge::BrowserBridgeInfo;
+use crate::openai_api::OpenAiApiInfo;
+use crate::deeplink::DeepLink;
+use crate::keys::{self, CloudProvider};
+use crate::models::{self, LocalModel, ModelDownloadEvent, ModelSearchResult, MODEL_DOWNLOAD_EVENT};
+use crate::panic;
+use crate::profiles::{NewProfile, Profile, ProfileList};
+use crate::recrawl;
+use crate::resources::{self, ResourceUsage};
+use crate::screenshot::{self, CaptureTarget, CaptureWindow, ScreenshotAttachment};
+use crate::security_window;
+use crate::session::RestoredSession;
+use crate::transcript::{ExportFormat, Transcript};
+use crate::uploads::{self, UploadProgress, UPLOAD_PROGRESS_EVENT};
+use crate::voice::VoiceTranscript;
+use crate::settings::{Settings, SETTINGS_FILE};
+use crate::state::{AppState, SystemState, Document, MessageStream, ModelDownload};
+use crate::tray::{self, SYSTEM_STATE_EVENT};
+use crate::websocket::{WebSocketMessage, WebSocketSession, SERVER_ADDR};
 
 // ============================================================================
 // System Commands
@@ -49,6 +93,179 @@ pub async fn release_lockdown(
     Ok(())
 }
 
+/// Stop writes, code execution and network access while chat and search keep working
+#[tauri::command]
+pub async fn enter_read_only(app: AppHandle, state: State<'_, AppState>, reason: String) -> Result<(), String> {
+    info!("🟡 Entering read-only mode: {}", reason);
+
+    if !state.security_engine.enter_read_only(LockdownReason::UserRequested { details: reason }).await {
+        let lockdown = state.security_engine.lockdown_state().await.map_err(|e| e.to_string())?;
+        return Err(format!("Read-only mode can only be entered from normal operation, not {:?}", lockdown));
+    }
+    tray::changed(&app).await;
+    Ok(())
+}
+
+/// Leave read-only mode; a full lockdown still needs `release_lockdown`
+#[tauri::command]
+pub async fn exit_read_only(app: AppHandle, state: State<'_, AppState>) -> Result<(), String> {
+    info!("✅ Exiting read-only mode");
+
+    if !state.security_engine.exit_read_only().await {
+        return Err("The system is not in read-only mode".to_string());
+    }
+    tray::changed(&app).await;
+    Ok(())
+}
+
+/// Where the UI connects for real-time updates, with the token the server requires
+#[tauri::command]
+pub async fn get_websocket_session(state: State<'_, AppState>) -> Result<WebSocketSession, String> {
+    Ok(WebSocketSession {
+        url: format!("ws://{}", SERVER_ADDR),
+        token: state.websocket_token.clone(),
+    })
+}
+
+/// CPU, RAM, VRAM and disk use now, and the memory each LLM takes
+/// The same snapshot arrives every few seconds as a `resource-usage` event
+#[tauri::command]
+pub async fn get_resource_usage(state: State<'_, AppState>) -> Result<ResourceUsage, String> {
+    debug!("📊 Getting resource usage");
+    Ok(resources::sample(&state).await)
+}
+
+/// GPUs llama.cpp could offload local models to, with their VRAM, and whether this build can offload at all
+#[tauri::command]
+pub async fn get_hardware_info() -> Result<HardwareInfo, String> {
+    debug!("🎮 Detecting GPUs");
+    tokio::task::spawn_blocking(llama_cpp_provider::detect_hardware)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// `hybridllm://` links received since the last call, oldest first
+/// Called on startup and on every `deep-link` event; the UI confirms each link before acting on it
+#[tauri::command]
+pub async fn take_pending_deep_links(state: State<'_, AppState>) -> Result<Vec<DeepLink>, String> {
+    Ok(state.deep_links.take())
+}
+
+/// What the last session had open, for the UI to reopen on startup
+#[tauri::command]
+pub async fn get_session(state: State<'_, AppState>) -> Result<RestoredSession, String> {
+    Ok(state.session.restored(&*state.context()).await)
+}
+
+/// Remember the open conversations so the next launch reopens them
+#[tauri::command]
+pub async fn set_open_conversations(
+    state: State<'_, AppState>,
+    conversation_ids: Vec<Uuid>,
+    active: Option<Uuid>,
+) -> Result<(), String> {
+    if active.is_some_and(|id| !conversation_ids.contains(&id)) {
+        return Err("The active conversation must be one of the open conversations".to_string());
+    }
+    state.session
+        .update(|session| {
+            session.open_conversations = conversation_ids;
+            session.active_conversation = active;
+        })
+        .map_err(|e| e.to_string())
+}
+
+/// Where the companion browser extension connects and the token to pair it with
+#[tauri::command]
+pub async fn get_browser_bridge(state: State<'_, AppState>) -> Result<BrowserBridgeInfo, String> {
+    Ok(state.browser_bridge.info())
+}
+
+/// Mint a new pairing token, unpairing every browser extension using the old one
+#[tauri::command]
+pub async fn reset_browser_bridge_token(state: State<'_, AppState>) -> Result<BrowserBridgeInfo, String> {
+    let result = state.browser_bridge.reset_token().await;
+    state.security_engine
+        .audit()
+        .log(
+            None,
+            "Browser extension token reset".to_string(),
+            serde_json::json!({}),
+            result.is_ok(),
+            result.as_ref().err().map(|e| e.to_string()),
+        )
+        .await;
+    result.map_err(|e| e.to_string())
+}
+
+/// Base URL and API key for OpenAI-compatible clients, and whether the server is turned on
+#[tauri::command]
+pub async fn get_openai_api(state: State<'_, AppState>) -> Result<OpenAiApiInfo, String> {
+    Ok(state.openai_api.info(&*state.settings.read().await))
+}
+
+/// Mint a new API key, cutting off every client using the old one
+#[tauri::command]
+pub async fn reset_openai_api_key(state: State<'_, AppState>) -> Result<OpenAiApiInfo, String> {
+    let settings = state.settings.read().await.clone();
+    let result = state.openai_api.reset_token(&settings).await;
+    state.security_engine
+        .audit()
+        .log(
+            None,
+            "OpenAI API key reset".to_string(),
+            serde_json::json!({}),
+            result.is_ok(),
+            result.as_ref().err().map(|e| e.to_string()),
+        )
+        .await;
+    result.map_err(|e| e.to_string())
+}
+
+#[derive(Debug, Serialize)]
+pub struct UpdateSettingsResponse {
+    pub settings: Settings,
+    /// Some changes, such as the data directory, only take effect after restarting the app
+    pub restart_required: bool,
+}
+
+#[tauri::command]
+pub async fn get_settings(state: State<'_, AppState>) -> Result<Settings, String> {
+    debug!("⚙️  Getting settings");
+    Ok(state.settings.read().await.clone())
+}
+
+/// Validate and save settings, applying what can change while running
+#[tauri::command]
+pub async fn update_settings(
+    app: AppHandle,
+    state: State<'_, AppState>,
+    settings: Settings,
+) -> Result<UpdateSettingsResponse, String> {
+    info!("💾 Updating settings");
+
+    let mut current = state.settings.write().await;
+    settings.validate().map_err(|e| e.to_string())?;
+    // A hotkey taken by another app is refused before anything is saved
+    panic::register_hotkey(&app, current.security.panic_hotkey(), settings.security.panic_hotkey())?;
+    if let Err(e) = settings.save(Path::new(SETTINGS_FILE)) {
+        let _ = panic::register_hotkey(&app, settings.security.panic_hotkey(), current.security.panic_hotkey());
+        return Err(e.to_string());
+    }
+
+    state.security_engine.set_max_failed_requests(settings.security.max_failed_requests);
+    state.security_engine.set_jailbreak_action(settings.security.jailbreak_action);
+    state.security_engine.set_offline(settings.offline);
+    state.llm_pool.read().await.set_offline(settings.offline);
+    if current.providers != settings.providers {
+        keys::register_all(&*state.llm_pool.read().await, &state.profiles.active().name, &settings.providers).await;
+    }
+    let restart_required = current.requires_restart(&settings);
+    *current = settings.clone();
+
+    Ok(UpdateSettingsResponse { settings, restart_required })
+}
+
 // ============================================================================
 // LLM Commands
 // ============================================================================
@@ -61,7 +278,7 @@ pub async fn get_llms(state: State<'_, AppState>) -> Result<Vec<LLMInstance>, St
     let llms: Vec<LLMInstance> = pool.get_all_ids()
         .iter()
         .filter_map(|id| pool.get(id))
-        .map(|provider| provider.instance().clone())
+        .map(|provider| LLMInstance { is_loaded: provider.is_loaded(), ..provider.instance().clone() })
         .collect();
 
     Ok(llms)
@@ -78,7 +295,15 @@ pub async fn load_llm(
     pool.load(&llm_id)
         .await
         .map_err(|e| e.to_string())?;
+    if let Err(e) = state.session.llm_loaded(&llm_id) {
+        warn!("⚠️  Could not save the session: {}", e);
+    }
 
+    state.websocket_events.publish(WebSocketMessage::LlmStatus {
+        llm_id,
+        status: "loaded".to_string(),
+        current_task: None,
+    });
     Ok(())
 }
 
@@ -93,224 +318,2215 @@ pub async fn unload_llm(
     pool.unload(&llm_id)
         .await
         .map_err(|e| e.to_string())?;
+    if let Err(e) = state.session.llm_unloaded(&llm_id) {
+        warn!("⚠️  Could not save the session: {}", e);
+    }
 
+    state.websocket_events.publish(WebSocketMessage::LlmStatus {
+        llm_id,
+        status: "unloaded".to_string(),
+        current_task: None,
+    });
     Ok(())
 }
 
 #[derive(Debug, Deserialize)]
 pub struct SendMessageRequest {
-    pub llm_id: String,
+    /// Falls back to the configured default LLM
+    pub llm_id: Option<String>,
     pub content: String,
+    /// The message and the reply are appended to this conversation
     pub conversation_id: Option<Uuid>,
+    /// Chosen by the caller so it can subscribe before the first chunk arrives
+    pub request_id: Option<Uuid>,
+    /// Shown to the model with the message, e.g. from `capture_screenshot`; needs a vision-capable LLM
+    #[serde(default)]
+    pub images: Vec<ImageInput>,
+    /// Prompt template to send instead of `content`, which fills its `{{input}}` placeholder
+    pub template: Option<PromptTemplateRef>,
+    /// Values for the template's other placeholders
+    #[serde(default)]
+    pub variables: HashMap<String, String>,
 }
 
-#[derive(Debug, Serialize)]
-pub struct SendMessageResponse {
-    pub content: String,
-    pub llm_id: String,
+/// Tauri event carrying streamed completion output
+pub const MESSAGE_STREAM_EVENT: &str = "message-stream";
+
+/// Progress of one streaming completion, emitted as `message-stream`
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum MessageStreamEvent {
+    Chunk { request_id: Uuid, llm_id: String, content: String },
+    Done {
+        request_id: Uuid,
+        llm_id: String,
+        /// From the request to the first chunk; `None` when the reply was empty
+        first_chunk_ms: Option<u64>,
+        usage: Usage,
+    },
+    Error {
+        request_id: Uuid,
+        llm_id: String,
+        message: String,
+        code: ErrorCode,
+        /// Sending the prompt again later may work, e.g. after a rate limit
+        retryable: bool,
+    },
+    Cancelled { request_id: Uuid, llm_id: String },
+}
+
+impl MessageStreamEvent {
+    fn error(request_id: Uuid, llm_id: &str, error: &HybridLLMError) -> Self {
+        Self::Error {
+            request_id,
+            llm_id: llm_id.to_string(),
+            message: error.to_string(),
+            code: error.code(),
+            retryable: error.is_retryable(),
+        }
+    }
 }
 
+/// Start a streaming completion and return its request id
+/// Output arrives as `message-stream` events until a `done`, `error` or `cancelled` event
 #[tauri::command]
-pub async fn send_message(
+pub async fn send_message_stream(
+    app: AppHandle,
     state: State<'_, AppState>,
     request: SendMessageRequest,
-) -> Result<SendMessageResponse, String> {
-    info!("💬 Sending message to LLM: {}", request.llm_id);
+) -> Result<Uuid, String> {
+    let request_id = request.request_id.unwrap_or_else(Uuid::new_v4);
+    let language = detect_language(&request.content);
+    let llm_id = match request.llm_id {
+        Some(llm_id) => llm_id,
+        None => {
+            let default_llm = state.settings.read().await.models.default_llm.clone()
+                .ok_or_else(|| "No LLM selected and no default LLM configured".to_string())?;
+            let default_llm = local_when_offline(&state, default_llm).await;
+            llm_for_language(&state, default_llm, language).await
+        }
+    };
+    info!("💬 Streaming message {} to LLM: {}", request_id, llm_id);
+
+    check_available(&state, &llm_id).await?;
+    let (content, template) = match &request.template {
+        Some(template) => {
+            let (content, template) =
+                render_prompt_template(&state, template, &llm_id, &request.content, request.variables).await?;
+            (content, Some(template))
+        }
+        None => (request.content, None),
+    };
+    if !request.images.is_empty() {
+        if let Some(image) = request.images.iter().find(|image| !image.media_type.starts_with("image/")) {
+            return Err(format!("{} is not an image type", image.media_type));
+        }
+        let pool = state.llm_pool.read().await;
+        if !pool.get(&llm_id).is_some_and(|llm| llm.capabilities().contains(&Capability::Vision)) {
+            return Err(format!("{} can't take images; pick a vision-capable model", llm_id));
+        }
+    }
+
+    let mut streams = state.message_streams.write().await;
+    if streams.contains_key(&request_id) {
+        return Err(format!("Request {} is already streaming", request_id));
+    }
+
+    if let Some(conversation_id) = request.conversation_id {
+        let mut message = chat_message(MessageRole::User, content.clone(), None);
+        if let Some(template) = &template {
+            message.metadata.insert(PROMPT_TEMPLATE_KEY.to_string(), serde_json::json!(template));
+        }
+        if let Some(language) = language {
+            message.metadata.insert(LANGUAGE_KEY.to_string(), language.into());
+        }
+        for image in &request.images {
+            message.content.push(ContentPart::Image { media_type: image.media_type.clone(), data: image.data.clone() });
+        }
+        state.context()
+            .add_message(&conversation_id, message)
+            .await
+            .map_err(|e| e.to_string())?;
+    }
+
+    let stream = spawn_stream(
+        app,
+        &state,
+        request_id,
+        llm_id,
+        content,
+        request.images,
+        request.conversation_id,
+    );
+    // Registered under the same lock the task removes itself with, so a fast stream can't finish first
+    streams.insert(request_id, stream);
+    Ok(request_id)
+}
+
+/// Message metadata key for the prompt template a message was written from
+pub const PROMPT_TEMPLATE_KEY: &str = "prompt_template";
+
+/// The default LLM, or a local model in its place when it is a cloud provider and the platform is offline
+async fn local_when_offline(state: &AppState, default_llm: String) -> String {
+    let pool = state.llm_pool.read().await;
+    if !pool.offline() || pool.is_available(&default_llm) {
+        return default_llm;
+    }
+    match pool.local_fallback() {
+        Some(llm_id) => {
+            info!("📴 Offline, routing to {} instead of {}", llm_id, default_llm);
+            llm_id
+        }
+        None => default_llm,
+    }
+}
 
+/// The default LLM, unless it isn't suited to `language` and another model is, loaded ones first; a local one
+/// that isn't loads on its first request
+/// An explicitly picked LLM is never swapped
+async fn llm_for_language(state: &AppState, default_llm: String, language: Option<&str>) -> String {
+    let Some(language) = language else {
+        return default_llm;
+    };
     let pool = state.llm_pool.read().await;
-    let provider = pool.get(&request.llm_id)
-        .ok_or_else(|| format!("LLM not found: {}", request.llm_id))?;
+    if pool.get(&default_llm).is_none_or(|llm| llm.instance().supports_language(language)) {
+        return default_llm;
+    }
+    match pool.find_by_language(language).into_iter().next() {
+        Some(llm) => {
+            info!("🌐 {} isn't suited to {}, routing to {}", default_llm, language, llm.instance().id);
+            llm.instance().id.clone()
+        }
+        None => default_llm,
+    }
+}
 
-    let response = provider.complete(&request.content, std::collections::HashMap::new())
+/// The prompt a template gives for `llm_id`, with `content` as its `input` unless a variable sets it
+async fn render_prompt_template(
+    state: &AppState,
+    template: &PromptTemplateRef,
+    llm_id: &str,
+    content: &str,
+    mut variables: HashMap<String, String>,
+) -> Result<(String, PromptTemplateRef), String> {
+    let template = state.context()
+        .get_prompt_template(&template.id, template.version)
         .await
         .map_err(|e| e.to_string())?;
+    variables
+        .entry(PromptTemplate::INPUT_VARIABLE.to_string())
+        .or_insert_with(|| content.to_string());
+    debug!("📝 Rendering prompt template {} v{} for {}", template.template.name, template.version, llm_id);
 
-    Ok(SendMessageResponse {
-        content: response,
-        llm_id: request.llm_id,
-    })
+    let rendered = template.render(llm_id, &variables).map_err(|e| e.to_string())?;
+    // Pinned to the version used, so the message can be traced back after the template changes
+    Ok((rendered, PromptTemplateRef { id: template.id, version: Some(template.version) }))
 }
 
-// ============================================================================
-// Document Commands
-// ============================================================================
+/// Most LLMs one comparison may ask at once
+const MAX_COMPARED_LLMS: usize = 4;
 
 #[derive(Debug, Deserialize)]
-pub struct UploadDocumentRequest {
-    pub filename: String,
-    pub content: Vec<u8>,
+pub struct CompareMessageRequest {
+    pub llm_ids: Vec<String>,
+    pub content: String,
+    /// One per LLM in the same order, chosen by the caller so it can subscribe before the first chunk arrives
+    pub request_ids: Option<Vec<Uuid>>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ComparedRequest {
+    pub llm_id: String,
+    pub request_id: Uuid,
 }
 
+/// Send the same prompt to several LLMs at once, each streamed as its own `message-stream` request
+/// Requests to a busy local model wait their turn as usual, and each one can be stopped with
+/// `cancel_generation`; replies are not added to any conversation
 #[tauri::command]
-pub async fn upload_document(
+pub async fn compare_message(
+    app: AppHandle,
     state: State<'_, AppState>,
-    request: UploadDocumentRequest,
-) -> Result<Document, String> {
-    info!("📤 Uploading document: {}", request.filename);
-
-    let doc = Document {
-        id: Uuid::new_v4(),
-        filename: request.filename.clone(),
-        size: request.content.len(),
-        uploaded_at: chrono::Utc::now(),
-        indexed: false,
-        chunk_count: None,
+    request: CompareMessageRequest,
+) -> Result<Vec<ComparedRequest>, String> {
+    let CompareMessageRequest { llm_ids, content, request_ids } = request;
+    if llm_ids.len() < 2 || llm_ids.len() > MAX_COMPARED_LLMS {
+        return Err(format!("Compare between 2 and {} LLMs, not {}", MAX_COMPARED_LLMS, llm_ids.len()));
+    }
+    if llm_ids.iter().enumerate().any(|(i, llm_id)| llm_ids[..i].contains(llm_id)) {
+        return Err("Each LLM can only be compared once".to_string());
+    }
+    let request_ids = match request_ids {
+        Some(ids) if ids.len() != llm_ids.len() => {
+            return Err(format!("Got {} request ids for {} LLMs", ids.len(), llm_ids.len()));
+        }
+        Some(ids) => ids,
+        None => llm_ids.iter().map(|_| Uuid::new_v4()).collect(),
     };
+    info!("⚖️  Comparing {} LLMs: {}", llm_ids.len(), llm_ids.join(", "));
 
-    // TODO: Actually save and index the document
-    // For now, just add to in-memory list
-    let mut documents = state.documents.write().await;
-    documents.push(doc.clone());
+    // Nothing starts unless every LLM can take the prompt
+    for llm_id in &llm_ids {
+        check_available(&state, llm_id).await?;
+    }
 
-    info!("✅ Document uploaded: {}", doc.id);
+    let mut streams = state.message_streams.write().await;
+    if let Some(request_id) = request_ids.iter().find(|id| streams.contains_key(id)) {
+        return Err(format!("Request {} is already streaming", request_id));
+    }
 
-    Ok(doc)
+    let mut compared = Vec::with_capacity(llm_ids.len());
+    for (llm_id, request_id) in llm_ids.into_iter().zip(request_ids) {
+        let stream = spawn_stream(app.clone(), &state, request_id, llm_id.clone(), content.clone(), Vec::new(), None);
+        streams.insert(request_id, stream);
+        compared.push(ComparedRequest { llm_id, request_id });
+    }
+    Ok(compared)
 }
 
-#[tauri::command]
-pub async fn get_documents(state: State<'_, AppState>) -> Result<Vec<Document>, String> {
-    debug!("📋 Getting document list");
+/// Fail unless the LLM is registered and may take requests right now
+async fn check_available(state: &AppState, llm_id: &str) -> Result<(), String> {
+    let pool = state.llm_pool.read().await;
+    if pool.get(llm_id).is_none() {
+        return Err(format!("LLM not found: {}", llm_id));
+    }
+    if !pool.is_available(llm_id) {
+        return Err(format!("{} is a cloud provider and {}", llm_id, pool.cloud_unavailable_reason()));
+    }
+    Ok(())
+}
 
-    let documents = state.documents.read().await;
-    Ok(documents.clone())
+/// Stream a completion as `message-stream` events, appending a complete reply to the conversation
+/// The caller registers the returned stream in `message_streams` while still holding its lock
+fn spawn_stream(
+    app: AppHandle,
+    state: &AppState,
+    request_id: Uuid,
+    llm_id: String,
+    content: String,
+    images: Vec<ImageInput>,
+    conversation_id: Option<Uuid>,
+) -> MessageStream {
+    let llm_pool = Arc::clone(&state.llm_pool);
+    let message_streams = Arc::clone(&state.message_streams);
+    let context = state.context();
+    let settings = Arc::clone(&state.settings);
+    let stream_llm_id = llm_id.clone();
+    // The request id doubles as the trace, so the UI can look up what a reply did in the audit log
+    let mut request_context = RequestContext::user().with_trace_id(request_id).with_llm(llm_id.clone());
+    if let Some(conversation_id) = conversation_id {
+        request_context = request_context.with_conversation(conversation_id);
+    }
+    let task = tokio::spawn(async move {
+        let emit = |event: MessageStreamEvent| {
+            let _ = app.emit_all(MESSAGE_STREAM_EVENT, event);
+        };
+        let started = std::time::Instant::now();
+
+        let request = CompletionRequest::prompt(content)
+            .with_images(images)
+            .with_options(settings.read().await.budgets.generation_options())
+            .with_context(request_context)
+            // Someone is watching it stream in, ahead of background work
+            .with_priority(Priority::High);
+
+        // Queued behind earlier requests to the same local model; `cancel_generation` stops it either way
+        let result = llm_pool
+            .read()
+            .await
+            .complete_stream(request_id, &llm_id, request)
+            .await;
+
+        match result {
+            Ok(mut chunks) => {
+                let mut first_chunk_ms = None;
+                let mut reply = String::new();
+                let mut usage = None;
+                while let Some(chunk) = chunks.recv().await {
+                    match chunk {
+                        Ok(StreamChunk::Text(content)) => {
+                            first_chunk_ms.get_or_insert(started.elapsed().as_millis() as u64);
+                            reply.push_str(&content);
+                            emit(MessageStreamEvent::Chunk { request_id, llm_id: llm_id.clone(), content });
+                        }
+                        Ok(StreamChunk::Usage(reported)) => usage = Some(reported),
+                        Err(e) => {
+                            error!("❌ Stream {} failed: {}", request_id, e);
+                            emit(MessageStreamEvent::error(request_id, &llm_id, &e));
+                            break;
+                        }
+                    }
+                }
+                // The pool ends only complete replies with their usage; failed and cancelled ones are not kept
+                if let Some(usage) = usage {
+                    if let Some(conversation_id) = conversation_id {
+                        let mut message = chat_message(MessageRole::Assistant, reply, Some(&llm_id));
+                        message.set_usage(&usage);
+                        if let Err(e) = context.add_message(&conversation_id, message).await {
+                            error!("❌ Failed to save reply to conversation {}: {}", conversation_id, e);
+                        }
+                    }
+                    emit(MessageStreamEvent::Done { request_id, llm_id: llm_id.clone(), first_chunk_ms, usage });
+                }
+            }
+            Err(e) => {
+                error!("❌ Failed to start stream {}: {}", request_id, e);
+                emit(MessageStreamEvent::error(request_id, &llm_id, &e));
+            }
+        }
+
+        message_streams.write().await.remove(&request_id);
+    });
+
+    MessageStream { llm_id: stream_llm_id, task: task.abort_handle() }
+}
+
+pub fn chat_message(role: MessageRole, content: String, llm_id: Option<&str>) -> Message {
+    Message {
+        id: Uuid::new_v4(),
+        role,
+        content: content.into(),
+        timestamp: chrono::Utc::now(),
+        metadata: llm_id
+            .map(|id| ("llm_id".to_string(), serde_json::Value::from(id)))
+            .into_iter()
+            .collect(),
+    }
 }
 
+/// Hold back or resume requests to cloud providers, as the tray does
 #[tauri::command]
-pub async fn delete_document(
+pub async fn pause_cloud_providers(
+    app: AppHandle,
     state: State<'_, AppState>,
-    document_id: Uuid,
+    paused: bool,
 ) -> Result<(), String> {
-    info!("🗑️  Deleting document: {}", document_id);
-
-    let mut documents = state.documents.write().await;
-    documents.retain(|doc| doc.id != document_id);
+    state.llm_pool.read().await.pause_cloud(paused);
+    let _ = app.emit_all(SYSTEM_STATE_EVENT, state.get_system_state().await);
+    Ok(())
+}
 
+/// Go offline, refusing cloud providers and network access, or back online; saved in the settings
+#[tauri::command]
+pub async fn set_offline_mode(app: AppHandle, state: State<'_, AppState>, offline: bool) -> Result<(), String> {
+    state.set_offline(offline).await.map_err(|e| e.to_string())?;
+    tray::changed(&app).await;
     Ok(())
 }
 
-// ============================================================================
-// Permission Commands
-// ============================================================================
+/// Stop a streaming completion and free the LLM generating it; returns whether it was still running
+#[tauri::command]
+pub async fn cancel_generation(
+    app: AppHandle,
+    state: State<'_, AppState>,
+    request_id: Uuid,
+) -> Result<bool, String> {
+    let Some(stream) = state.message_streams.write().await.remove(&request_id) else {
+        return Ok(false);
+    };
 
+    info!("🛑 Cancelling generation {}", request_id);
+    // The pool stops the provider; aborting the task keeps the partial reply out of the conversation
+    state.llm_pool.read().await.cancel_generation(&request_id);
+    stream.task.abort();
+    let _ = app.emit_all(
+        MESSAGE_STREAM_EVENT,
+        MessageStreamEvent::Cancelled { request_id, llm_id: stream.llm_id },
+    );
+    Ok(true)
+}
+
+/// Most times one benchmark may send each prompt
+const MAX_BENCHMARK_REPETITIONS: u32 = 10;
+
+/// Send the standard prompt suite to each LLM, one after another, and keep the results
+/// Latency, tokens per second, cost and failures are measured per LLM; local models can take minutes
 #[tauri::command]
-pub async fn get_permissions(state: State<'_, AppState>) -> Result<PermissionScope, String> {
-    debug!("📋 Getting permissions");
+pub async fn run_benchmark(state: State<'_, AppState>, options: BenchmarkOptions) -> Result<BenchmarkRun, String> {
+    if options.llm_ids.is_empty() {
+        return Err("Pick at least one LLM to benchmark".to_string());
+    }
+    if options.repetitions == 0 || options.repetitions > MAX_BENCHMARK_REPETITIONS {
+        return Err(format!("Repeat the suite between 1 and {} times, not {}", MAX_BENCHMARK_REPETITIONS, options.repetitions));
+    }
+    let lockdown = state.security_engine.lockdown_state().await.map_err(|e| e.to_string())?;
+    if lockdown != LockdownState::Normal {
+        return Err(format!("Benchmarks only run during normal operation, not {:?}", lockdown));
+    }
+    // Nothing starts unless every LLM can take the prompts
+    for llm_id in &options.llm_ids {
+        check_available(&state, llm_id).await?;
+    }
 
-    let permissions = state.permissions.read().await;
-    Ok(permissions.clone())
+    let run = llm_pool::run_benchmark(&*state.llm_pool.read().await, &options, STANDARD_SUITE).await;
+    let recorded = state.benchmarks.record(&run);
+    state.security_engine
+        .audit()
+        .log(
+            None,
+            "Benchmark run".to_string(),
+            serde_json::json!({ "run_id": run.id, "llm_ids": options.llm_ids, "prompts": run.prompts }),
+            true,
+            None,
+        )
+        .await;
+    recorded.map_err(|e| e.to_string())?;
+    Ok(run)
 }
 
+/// Past benchmark runs, newest first
 #[tauri::command]
-pub async fn update_permissions(
-    state: State<'_, AppState>,
-    permissions: PermissionScope,
-) -> Result<(), String> {
-    info!("💾 Updating permissions");
+pub async fn list_benchmarks(state: State<'_, AppState>) -> Result<Vec<BenchmarkRun>, String> {
+    Ok(state.benchmarks.list())
+}
 
-    let mut perms = state.permissions.write().await;
-    *perms = permissions;
+/// Most cases one evaluation may replay
+const MAX_EVALUATION_CASES: usize = 100;
 
-    Ok(())
+/// What an evaluation replays
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum EvaluationSource {
+    /// Every user message of these conversations, each with the conversation before it
+    Conversations { conversation_ids: Vec<Uuid> },
+    /// Prompts written for the purpose, optionally with reference answers
+    TestSet { cases: Vec<EvalCase> },
 }
 
-// ============================================================================
-// Audit Log Commands
-// ============================================================================
+#[derive(Debug, Deserialize)]
+pub struct EvaluationRequest {
+    #[serde(flatten)]
+    pub options: EvaluationOptions,
+    pub source: EvaluationSource,
+}
 
+/// Compare two models or configurations on the same prompts, with a third LLM judging each pair of answers
+/// Run before swapping the default model; every answer and verdict is kept in the report
 #[tauri::command]
-pub async fn get_audit_log(state: State<'_, AppState>) -> Result<Vec<AuditLogEntry>, String> {
-    debug!("📋 Getting audit log");
+pub async fn run_evaluation(state: State<'_, AppState>, request: EvaluationRequest) -> Result<EvaluationReport, String> {
+    let EvaluationRequest { options, source } = request;
+    if options.criteria.is_empty() {
+        return Err("Give the judge at least one criterion".to_string());
+    }
+    let lockdown = state.security_engine.lockdown_state().await.map_err(|e| e.to_string())?;
+    if lockdown != LockdownState::Normal {
+        return Err(format!("Evaluations only run during normal operation, not {:?}", lockdown));
+    }
+    // Nothing starts unless the candidates and the judge can all take prompts
+    for llm_id in [&options.a.llm_id, &options.b.llm_id, &options.judge_llm_id] {
+        check_available(&state, llm_id).await?;
+    }
+
+    let cases = match source {
+        EvaluationSource::TestSet { cases } => cases,
+        EvaluationSource::Conversations { conversation_ids } => {
+            let mut cases = Vec::new();
+            for conversation_id in conversation_ids {
+                let messages = state.context().get_conversation(&conversation_id).await.map_err(|e| e.to_string())?;
+                cases.extend(replay_cases(&conversation_id, &messages));
+            }
+            cases
+        }
+    };
+    if cases.is_empty() {
+        return Err("Nothing to evaluate: no prompts in the test set or conversations".to_string());
+    }
+    if cases.len() > MAX_EVALUATION_CASES {
+        return Err(format!("Evaluate at most {} prompts at once, not {}", MAX_EVALUATION_CASES, cases.len()));
+    }
+
+    let report = llm_pool::run_evaluation(&*state.llm_pool.read().await, &options, &cases).await;
+    let recorded = state.evaluations.record(&report);
+    state.security_engine
+        .audit()
+        .log(
+            None,
+            "Evaluation run".to_string(),
+            serde_json::json!({
+                "report_id": report.id,
+                "llm_ids": [options.a.llm_id, options.b.llm_id],
+                "judge": options.judge_llm_id,
+                "cases": cases.len(),
+            }),
+            true,
+            None,
+        )
+        .await;
+    recorded.map_err(|e| e.to_string())?;
+    Ok(report)
+}
+
+/// One case per user message, each with the conversation's text up to it
+fn replay_cases(conversation_id: &Uuid, messages: &[Message]) -> Vec<EvalCase> {
+    let mut history = Vec::new();
+    let mut cases = Vec::new();
+    for message in messages {
+        let text = message.content.text();
+        // Tool calls and results don't replay on their own, so only the text of the exchange is kept
+        if text.trim().is_empty() || message.tool_result().is_some() {
+            continue;
+        }
+        match message.role {
+            MessageRole::User => {
+                history.push(CompletionMessage::new(MessageRole::User, text.into_owned()));
+                cases.push(EvalCase {
+                    name: format!("{} #{}", conversation_id, cases.len() + 1),
+                    messages: history.clone(),
+                    reference: None,
+                });
+            }
+            MessageRole::Assistant => history.push(CompletionMessage::new(MessageRole::Assistant, text.into_owned())),
+            MessageRole::System => {}
+        }
+    }
+    cases
+}
+
+/// Past evaluation reports, newest first
+#[tauri::command]
+pub async fn list_evaluations(state: State<'_, AppState>) -> Result<Vec<EvaluationReport>, String> {
+    Ok(state.evaluations.list())
+}
 
-    let log = state.audit_log.read().await;
-    Ok(log.clone())
+/// Tokens and dollars of finished completions, totalled and broken down by day, LLM or conversation
+/// Without dates it covers the current month, e.g. to see where the cloud budget went
+#[tauri::command]
+pub async fn get_usage_summary(state: State<'_, AppState>, query: UsageQuery) -> Result<UsageSummary, String> {
+    if let (Some(from), Some(to)) = (query.from, query.to) {
+        if from > to {
+            return Err(format!("The range starts after it ends: {} > {}", from, to));
+        }
+    }
+    Ok(llm_pool::summarize_usage(&state.usage.list(), &query, chrono::Utc::now()))
 }
 
 // ============================================================================
-// Sandbox Commands
+// Provider Key Commands
 // ============================================================================
 
-#[derive(Debug, Deserialize)]
-pub struct CreateSandboxRequest {
-    pub llm_id: String,
-    pub purpose: String,
+#[derive(Debug, Serialize)]
+pub struct ProviderKeyResponse {
+    /// LLM now serving the provider; `None` when it is disabled or has no key left
+    pub llm_id: Option<String>,
 }
 
-#[derive(Debug, Serialize)]
-pub struct CreateSandboxResponse {
-    pub sandbox_id: Uuid,
+/// Store a cloud provider's API key in the OS keyring once the provider accepts it, then register the provider
+#[tauri::command]
+pub async fn set_provider_key(
+    state: State<'_, AppState>,
+    provider: CloudProvider,
+    api_key: String,
+) -> Result<ProviderKeyResponse, String> {
+    info!("🔑 Setting {} API key", provider.name());
+
+    let api_key = api_key.trim().to_string();
+    if api_key.is_empty() {
+        return Err("API key must not be empty".to_string());
+    }
+    let providers = state.settings.read().await.providers.clone();
+    if !keys::probe(provider, &providers, api_key.clone()).await.map_err(|e| e.to_string())? {
+        return Err(format!("{} rejected the API key", provider.name()));
+    }
+    let profile = state.profiles.active().name;
+    keys::store_key(provider, &profile, api_key).await.map_err(|e| e.to_string())?;
+
+    let llm_id = keys::register(&*state.llm_pool.read().await, provider, &profile, &providers)
+        .await
+        .map_err(|e| e.to_string())?;
+    state.security_engine
+        .audit()
+        .log(
+            None,
+            "Provider key set".to_string(),
+            serde_json::json!({ "provider": provider, "profile": profile, "llm_id": llm_id }),
+            true,
+            None,
+        )
+        .await;
+
+    Ok(ProviderKeyResponse { llm_id })
 }
 
+/// Check a key with the provider without storing it; with no key, check the one in use
 #[tauri::command]
-pub async fn create_sandbox(
-    _state: State<'_, AppState>,
-    request: CreateSandboxRequest,
-) -> Result<CreateSandboxResponse, String> {
-    info!("📦 Creating sandbox for LLM: {}", request.llm_id);
+pub async fn test_provider_key(
+    state: State<'_, AppState>,
+    provider: CloudProvider,
+    api_key: Option<String>,
+) -> Result<bool, String> {
+    debug!("🔑 Testing {} API key", provider.name());
 
-    // TODO: Actually create sandbox
-    let sandbox_id = Uuid::new_v4();
+    let providers = state.settings.read().await.providers.clone();
+    let api_key = match api_key.map(|key| key.trim().to_string()).filter(|key| !key.is_empty()) {
+        Some(key) => key,
+        None => keys::resolve_key(provider, &state.profiles.active().name, &providers)
+            .await
+            .map_err(|e| e.to_string())?
+            .map(|(key, _)| key)
+            .ok_or_else(|| format!("No API key for {}", provider.name()))?,
+    };
 
-    Ok(CreateSandboxResponse { sandbox_id })
+    keys::probe(provider, &providers, api_key).await.map_err(|e| e.to_string())
 }
 
-#[derive(Debug, Deserialize)]
-pub struct ExecuteInSandboxRequest {
-    pub sandbox_id: Uuid,
-    pub code: String,
-    pub language: String,
+/// Delete a provider's key from the keyring; the provider stays registered only if its environment variable has a key
+#[tauri::command]
+pub async fn remove_provider_key(
+    state: State<'_, AppState>,
+    provider: CloudProvider,
+) -> Result<ProviderKeyResponse, String> {
+    info!("🔑 Removing {} API key", provider.name());
+
+    let profile = state.profiles.active().name;
+    let removed = keys::delete_key(provider, &profile).await.map_err(|e| e.to_string())?;
+    let providers = state.settings.read().await.providers.clone();
+    let llm_id = keys::register(&*state.llm_pool.read().await, provider, &profile, &providers)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    if removed {
+        state.security_engine
+            .audit()
+            .log(
+                None,
+                "Provider key removed".to_string(),
+                serde_json::json!({ "provider": provider, "profile": profile, "llm_id": llm_id }),
+                true,
+                None,
+            )
+            .await;
+    }
+
+    Ok(ProviderKeyResponse { llm_id })
 }
 
-#[derive(Debug, Serialize)]
-pub struct ExecuteInSandboxResponse {
-    pub output: String,
-    pub exit_code: i32,
+// ============================================================================
+// Model Download Commands
+// ============================================================================
+
+/// Search Hugging Face for GGUF models
+#[tauri::command]
+pub async fn search_models(query: String, limit: Option<usize>) -> Result<Vec<ModelSearchResult>, String> {
+    info!("🔍 Searching models: {}", query);
+
+    models::search(&query, limit.unwrap_or(20)).await.map_err(|e| e.to_string())
 }
 
+/// Start downloading a GGUF file from a Hugging Face repository and return its download id
+/// Progress arrives as `model-download` events and on the `models` WebSocket topic until a `done`, `error`
+/// or `cancelled` event; a download that failed resumes where it stopped when started again
 #[tauri::command]
-pub async fn execute_in_sandbox(
-    _state: State<'_, AppState>,
-    request: ExecuteInSandboxRequest,
-) -> Result<ExecuteInSandboxResponse, String> {
-    info!("🚀 Executing code in sandbox: {}", request.sandbox_id);
+pub async fn download_model(
+    app: AppHandle,
+    state: State<'_, AppState>,
+    repo_id: String,
+    filename: String,
+) -> Result<Uuid, String> {
+    let (models_dir, sessions_dir) = {
+        let settings = state.settings.read().await;
+        (settings.paths.models_dir.clone(), models::sessions_dir(&settings.paths.data_dir))
+    };
+    let destination = models::destination(&models_dir, &repo_id, &filename).map_err(|e| e.to_string())?;
+    if destination.exists() {
+        return Err(format!("{:?} is already downloaded", destination));
+    }
 
-    // TODO: Actually execute in sandbox
-    Ok(ExecuteInSandboxResponse {
-        output: format!("Executed {} code (placeholder)", request.language),
-        exit_code: 0,
-    })
+    let partial = models::partial_path(&destination);
+    let mut downloads = state.model_downloads.write().await;
+    if downloads.values().any(|download| download.partial == partial) {
+        return Err(format!("{} is already downloading", filename));
+    }
+
+    let download_id = Uuid::new_v4();
+    info!("⬇️  Starting model download {}: {}/{}", download_id, repo_id, filename);
+
+    let model_downloads = Arc::clone(&state.model_downloads);
+    let websocket_events = Arc::clone(&state.websocket_events);
+    let llm_pool = Arc::clone(&state.llm_pool);
+    let profiles = Arc::clone(&state.profiles);
+    let kv_persistence = Arc::clone(&state.kv_persistence);
+    let (repo, file) = (repo_id.clone(), filename.clone());
+    let task = tokio::spawn(async move {
+        let emit = |event: ModelDownloadEvent| {
+            let _ = app.emit_all(MODEL_DOWNLOAD_EVENT, event.clone());
+            websocket_events.publish(WebSocketMessage::ModelDownload { event });
+        };
+
+        let progress = |downloaded_bytes, total_bytes| {
+            emit(ModelDownloadEvent::Progress {
+                download_id,
+                repo_id: repo.clone(),
+                filename: file.clone(),
+                downloaded_bytes,
+                total_bytes,
+            })
+        };
+        let result = models::download(&repo, &file, &destination, progress).await;
+
+        // Listed and registered before the final event, so a `done` always finds the model in `list_local_models`
+        // and the pool
+        let model = match result {
+            Ok(_) => models::list_local(&models_dir)
+                .await
+                .map(|local| local.into_iter().find(|model| model.path == destination)),
+            Err(e) => Err(e),
+        };
+        let registered = match model {
+            Ok(Some(model)) => models::register(&*llm_pool.read().await, &model, &sessions_dir, &kv_persistence)
+                .map(|llm_id| Some((model, llm_id))),
+            Ok(None) => Ok(None),
+            Err(e) => Err(e),
+        };
+        match registered {
+            Ok(Some((model, llm_id))) => {
+                info!("📝 Downloaded model registered as {}", llm_id);
+                models::pick_embedder(&*llm_pool.read().await, &profiles);
+                emit(ModelDownloadEvent::Done { download_id, repo_id: repo, filename: file, model, llm_id })
+            }
+            Ok(None) => emit(ModelDownloadEvent::Error {
+                download_id,
+                repo_id: repo,
+                filename: file,
+                message: format!("{:?} disappeared after downloading", destination),
+            }),
+            // What arrived is kept for the next attempt to resume from
+            Err(e) => {
+                error!("❌ Model download {} failed: {}", download_id, e);
+                emit(ModelDownloadEvent::Error { download_id, repo_id: repo, filename: file, message: e.to_string() });
+            }
+        }
+
+        model_downloads.write().await.remove(&download_id);
+    });
+
+    // Registered under the same lock the task removes itself with, so a fast download can't finish first
+    downloads.insert(download_id, ModelDownload { repo_id, filename, partial, task: task.abort_handle() });
+    Ok(download_id)
 }
 
-#[derive(Debug, Serialize)]
-pub struct SandboxFile {
-    pub path: String,
-    pub size: usize,
-    pub modified: chrono::DateTime<chrono::Utc>,
+/// Stop a model download and delete what it fetched so far; returns whether it was still running
+#[tauri::command]
+pub async fn cancel_download(
+    app: AppHandle,
+    state: State<'_, AppState>,
+    download_id: Uuid,
+) -> Result<bool, String> {
+    let Some(download) = state.model_downloads.write().await.remove(&download_id) else {
+        return Ok(false);
+    };
+
+    info!("🛑 Cancelling model download {}", download_id);
+    download.task.abort();
+    let _ = tokio::fs::remove_file(&download.partial).await;
+    let _ = app.emit_all(
+        MODEL_DOWNLOAD_EVENT,
+        ModelDownloadEvent::Cancelled { download_id, repo_id: download.repo_id, filename: download.filename },
+    );
+    Ok(true)
 }
 
+/// GGUF models in the models directory, newest first
 #[tauri::command]
-pub async fn get_sandbox_files(
-    _state: State<'_, AppState>,
-    sandbox_id: Uuid,
-) -> Result<Vec<SandboxFile>, String> {
-    debug!("📋 Getting files for sandbox: {}", sandbox_id);
+pub async fn list_local_models(state: State<'_, AppState>) -> Result<Vec<LocalModel>, String> {
+    debug!("📋 Listing local models");
 
-    // TODO: Actually list sandbox files
-    Ok(Vec::new())
+    let models_dir = state.settings.read().await.paths.models_dir.clone();
+    models::list_local(&models_dir).await.map_err(|e| e.to_string())
 }
 
-#[derive(Debug, Deserialize)]
-pub struct ApproveTransferRequest {
-    pub transfer_id: Uuid,
-    pub approved: bool,
+/// Architecture, quantization, size, context length and chat template of a local model, read without loading it
+#[tauri::command]
+pub async fn inspect_model(state: State<'_, AppState>, filename: String) -> Result<ModelInfo, String> {
+    debug!("🔎 Inspecting model {}", filename);
+
+    let models_dir = state.settings.read().await.paths.models_dir.clone();
+    let path = models::local_path(&models_dir, &filename).map_err(|e| e.to_string())?;
+    tokio::task::spawn_blocking(move || gguf::inspect(&path))
+        .await
+        .map_err(|e| e.to_string())?
+        .map_err(|e| e.to_string())
 }
 
+// ============================================================================
+// Voice Input Commands
+// ============================================================================
+
+/// Start recording from the microphone for `stop_voice_input` to transcribe
 #[tauri::command]
-pub async fn approve_transfer(
-    _state: State<'_, AppState>,
-    request: ApproveTransferRequest,
-) -> Result<(), String> {
-    info!("✅ Transfer approval: {} - {}", request.transfer_id, request.approved);
+pub async fn start_voice_input(state: State<'_, AppState>) -> Result<(), String> {
+    // Checked first so nobody talks into a recording that can't be transcribed
+    if state.settings.read().await.speech_model_path().is_none() {
+        return Err("No speech model configured; set models.speech_model to a Whisper GGML file".to_string());
+    }
+
+    let voice = Arc::clone(&state.voice);
+    tokio::task::spawn_blocking(move || voice.start())
+        .await
+        .map_err(|e| e.to_string())?
+        .map_err(|e| e.to_string())?;
 
-    // TODO: Actually handle transfer
+    state.security_engine
+        .audit()
+        .log(None, "Voice input started".to_string(), serde_json::json!({}), true, None)
+        .await;
     Ok(())
 }
+
+/// Stop recording and transcribe it with the local speech model; the audio is discarded afterwards
+#[tauri::command]
+pub async fn stop_voice_input(state: State<'_, AppState>) -> Result<VoiceTranscript, String> {
+    let settings = state.settings.read().await.clone();
+    let Some(model_path) = settings.speech_model_path() else {
+        state.voice.cancel();
+        return Err("No speech model configured; set models.speech_model to a Whisper GGML file".to_string());
+    };
+
+    let voice = Arc::clone(&state.voice);
+    tokio::task::spawn_blocking(move || voice.stop_and_transcribe(&model_path, settings.models.speech_language.as_deref()))
+        .await
+        .map_err(|e| e.to_string())?
+        .map_err(|e| e.to_string())
+}
+
+/// Stop recording without transcribing; returns whether a recording was running
+#[tauri::command]
+pub async fn cancel_voice_input(state: State<'_, AppState>) -> Result<bool, String> {
+    Ok(state.voice.cancel())
+}
+
+// ============================================================================
+// Screenshot Commands
+// ============================================================================
+
+/// Windows `capture_screenshot` can capture
+#[tauri::command]
+pub async fn list_capture_windows() -> Result<Vec<CaptureWindow>, String> {
+    tokio::task::spawn_blocking(screenshot::list_windows)
+        .await
+        .map_err(|e| e.to_string())?
+        .map_err(|e| e.to_string())
+}
+
+/// Capture the screen, a region of it or a window, and keep it in the uploads folder
+/// The result can go along with `send_message_stream` to a vision-capable LLM
+#[tauri::command]
+pub async fn capture_screenshot(
+    state: State<'_, AppState>,
+    target: CaptureTarget,
+) -> Result<ScreenshotAttachment, String> {
+    let shot = tokio::task::spawn_blocking(move || screenshot::capture(&target))
+        .await
+        .map_err(|e| e.to_string())?
+        .map_err(|e| e.to_string())?;
+
+    let filename = format!("screenshot-{}.png", chrono::Utc::now().format("%Y%m%d-%H%M%S-%3f"));
+    state.filesystem
+        .write_file(ManagedFolder::Uploads, &filename, &shot.png)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    state.security_engine
+        .audit()
+        .log(
+            None,
+            "Screenshot captured".to_string(),
+            serde_json::json!({ "filename": filename, "width": shot.width, "height": shot.height }),
+            true,
+            None,
+        )
+        .await;
+    Ok(shot.attachment(filename))
+}
+
+// ============================================================================
+// Conversation Commands
+// ============================================================================
+
+#[tauri::command]
+pub async fn create_conversation(
+    state: State<'_, AppState>,
+    title: Option<String>,
+) -> Result<Conversation, String> {
+    info!("💬 Creating conversation");
+
+    state.context()
+        .create_conversation(title.as_deref())
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// Conversations, most recently active first; `trashed` lists the trash instead
+#[tauri::command]
+pub async fn list_conversations(
+    state: State<'_, AppState>,
+    trashed: Option<bool>,
+) -> Result<Vec<Conversation>, String> {
+    debug!("📋 Listing conversations");
+
+    // Trashed conversations are kept as long as trashed files
+    let expired = chrono::Utc::now()
+        - chrono::Duration::from_std(DEFAULT_TRASH_RETENTION).map_err(|e| e.to_string())?;
+    state.context().purge_conversations(expired).await.map_err(|e| e.to_string())?;
+
+    state.context()
+        .list_conversations(trashed.unwrap_or(false))
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn get_conversation(
+    state: State<'_, AppState>,
+    conversation_id: Uuid,
+) -> Result<Vec<Message>, String> {
+    debug!("📖 Reading conversation: {}", conversation_id);
+
+    state.context()
+        .get_conversation(&conversation_id)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// Tokens, cost and generation time of a conversation's replies
+#[tauri::command]
+pub async fn get_conversation_usage(
+    state: State<'_, AppState>,
+    conversation_id: Uuid,
+) -> Result<Usage, String> {
+    debug!("📊 Reading usage of conversation: {}", conversation_id);
+
+    state.context()
+        .conversation_usage(&conversation_id)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn rename_conversation(
+    state: State<'_, AppState>,
+    conversation_id: Uuid,
+    title: String,
+) -> Result<Conversation, String> {
+    info!("✏️  Renaming conversation {} to {}", conversation_id, title);
+
+    let title = title.trim();
+    if title.is_empty() {
+        return Err("Conversation title cannot be empty".to_string());
+    }
+
+    state.context()
+        .rename_conversation(&conversation_id, title)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// Move a conversation to the trash; it can be restored until the trash is emptied
+#[tauri::command]
+pub async fn delete_conversation(
+    state: State<'_, AppState>,
+    conversation_id: Uuid,
+) -> Result<Conversation, String> {
+    info!("🗑️  Deleting conversation: {}", conversation_id);
+
+    state.context()
+        .trash_conversation(&conversation_id)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn restore_conversation(
+    state: State<'_, AppState>,
+    conversation_id: Uuid,
+) -> Result<Conversation, String> {
+    info!("♻️  Restoring conversation: {}", conversation_id);
+
+    state.context()
+        .restore_conversation(&conversation_id)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[derive(Debug, Serialize)]
+pub struct ExportConversationResponse {
+    pub filename: String,
+    pub path: std::path::PathBuf,
+}
+
+/// Write a conversation's transcript to the downloads folder as Markdown, JSON or HTML
+#[tauri::command]
+pub async fn export_conversation(
+    state: State<'_, AppState>,
+    conversation_id: Uuid,
+    format: ExportFormat,
+) -> Result<ExportConversationResponse, String> {
+    info!("📤 Exporting conversation {} as {:?}", conversation_id, format);
+
+    let mut conversation = None;
+    for trashed in [false, true] {
+        let conversations = state.context().list_conversations(trashed).await.map_err(|e| e.to_string())?;
+        conversation = conversations.into_iter().find(|c| c.id == conversation_id);
+        if conversation.is_some() {
+            break;
+        }
+    }
+    let conversation = conversation.ok_or_else(|| format!("No conversation {}", conversation_id))?;
+    let messages = state.context().get_conversation(&conversation_id).await.map_err(|e| e.to_string())?;
+
+    // Model names for LLMs still registered; others are listed by id
+    let models: std::collections::HashMap<String, String> = {
+        let pool = state.llm_pool.read().await;
+        pool.get_all_ids()
+            .into_iter()
+            .filter_map(|id| pool.get(&id).map(|llm| (id, llm.instance().model_name.clone())))
+            .collect()
+    };
+
+    let transcript = Transcript::new(conversation, messages, &models);
+    let filename = transcript.filename(format);
+    let path = state.filesystem
+        .write_download(&filename, transcript.render(format).as_bytes())
+        .await
+        .map_err(|e| e.to_string())?;
+
+    Ok(ExportConversationResponse { filename, path })
+}
+
+// ============================================================================
+// Prompt Template Commands
+// ============================================================================
+
+/// The latest version of every prompt template, by name
+#[tauri::command]
+pub async fn list_prompt_templates(state: State<'_, AppState>) -> Result<Vec<PromptTemplate>, String> {
+    state.context()
+        .list_prompt_templates()
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// A prompt template at `version`, or its latest
+#[tauri::command]
+pub async fn get_prompt_template(
+    state: State<'_, AppState>,
+    template_id: Uuid,
+    version: Option<i32>,
+) -> Result<PromptTemplate, String> {
+    state.context()
+        .get_prompt_template(&template_id, version)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// Every saved version of a prompt template, newest first
+#[tauri::command]
+pub async fn get_prompt_template_history(
+    state: State<'_, AppState>,
+    template_id: Uuid,
+) -> Result<Vec<PromptTemplate>, String> {
+    state.context()
+        .prompt_template_history(&template_id)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn create_prompt_template(
+    state: State<'_, AppState>,
+    template: NewPromptTemplate,
+) -> Result<PromptTemplate, String> {
+    info!("📝 Creating prompt template: {}", template.name);
+
+    state.context()
+        .create_prompt_template(template)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// Save an edit as the template's next version; messages and workflows pinned to older versions keep them
+#[tauri::command]
+pub async fn update_prompt_template(
+    state: State<'_, AppState>,
+    template_id: Uuid,
+    template: NewPromptTemplate,
+) -> Result<PromptTemplate, String> {
+    info!("📝 Saving prompt template {}: {}", template_id, template.name);
+
+    state.context()
+        .update_prompt_template(&template_id, template)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn delete_prompt_template(state: State<'_, AppState>, template_id: Uuid) -> Result<(), String> {
+    info!("🗑️  Deleting prompt template: {}", template_id);
+
+    state.context()
+        .delete_prompt_template(&template_id)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+// ============================================================================
+// Document Commands
+// ============================================================================
+
+#[derive(Debug, Deserialize)]
+pub struct UploadDocumentRequest {
+    pub filename: String,
+    pub content: Vec<u8>,
+}
+
+#[tauri::command]
+pub async fn upload_document(
+    state: State<'_, AppState>,
+    request: UploadDocumentRequest,
+) -> Result<Document, String> {
+    info!("📤 Uploading document: {}", request.filename);
+
+    let filename = std::path::Path::new(&request.filename)
+        .file_name()
+        .and_then(|name| name.to_str())
+        .ok_or_else(|| format!("Invalid filename: {}", request.filename))?;
+    let hash = state.filesystem
+        .write_upload(filename, &request.content)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    let doc = Document {
+        id: Uuid::new_v4(),
+        filename: hash.filename,
+        size: request.content.len(),
+        sha256: hash.sha256,
+        uploaded_at: chrono::Utc::now(),
+        indexed: false,
+        chunk_count: None,
+        url: None,
+    };
+
+    // TODO: Actually index the document
+    // For now, just add to in-memory list and wait in the index queue
+    let mut documents = state.documents.write().await;
+    documents.push(doc.clone());
+    queue_for_indexing(&state, std::slice::from_ref(&doc)).await;
+
+    info!("✅ Document uploaded: {}", doc.id);
+
+    Ok(doc)
+}
+
+#[derive(Debug, Serialize)]
+pub struct UploadArchiveResponse {
+    pub documents: Vec<Document>,
+    /// Entries that were not extracted, with the reason
+    pub skipped: Vec<SkippedEntry>,
+}
+
+/// Unpack an uploaded zip or tar archive into its own uploads directory,
+/// adding each extracted file as a separate document
+#[tauri::command]
+pub async fn upload_archive(
+    state: State<'_, AppState>,
+    request: UploadDocumentRequest,
+) -> Result<UploadArchiveResponse, String> {
+    info!("📦 Uploading archive: {}", request.filename);
+
+    let filename = std::path::Path::new(&request.filename)
+        .file_name()
+        .and_then(|name| name.to_str())
+        .ok_or_else(|| format!("Invalid filename: {}", request.filename))?;
+    let report = state.filesystem
+        .extract_archive(ManagedFolder::Uploads, filename, &request.content)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    let uploaded_at = chrono::Utc::now();
+    let documents: Vec<Document> = report.files
+        .into_iter()
+        .map(|hash| Document {
+            id: Uuid::new_v4(),
+            filename: hash.filename,
+            size: hash.size_bytes as usize,
+            sha256: hash.sha256,
+            uploaded_at,
+            indexed: false,
+            chunk_count: None,
+            url: None,
+        })
+        .collect();
+
+    state.documents.write().await.extend(documents.iter().cloned());
+    queue_for_indexing(&state, &documents).await;
+
+    info!("✅ Archive unpacked into {}: {} documents, {} skipped", report.directory, documents.len(), report.skipped.len());
+
+    Ok(UploadArchiveResponse { documents, skipped: report.skipped })
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UploadPathsRequest {
+    /// Files and folders dropped on the window
+    pub paths: Vec<String>,
+    /// Chosen by the caller so it can subscribe before the first progress event
+    pub upload_id: Uuid,
+}
+
+#[derive(Debug, Serialize)]
+pub struct UploadPathsResponse {
+    pub documents: Vec<Document>,
+    /// Files that were not uploaded, with the reason
+    pub skipped: Vec<SkippedEntry>,
+    /// Documents waiting to be indexed, including earlier uploads
+    pub queued_for_indexing: usize,
+}
+
+/// Upload dropped files and whole folders straight from disk, copying each in chunks
+/// `upload-progress` events report progress across all files; a file that fails is skipped
+#[tauri::command]
+pub async fn upload_paths(
+    app: AppHandle,
+    state: State<'_, AppState>,
+    request: UploadPathsRequest,
+) -> Result<UploadPathsResponse, String> {
+    let upload_id = request.upload_id;
+    let paths: Vec<std::path::PathBuf> = request.paths.iter().map(std::path::PathBuf::from).collect();
+    let (files, mut skipped) = tokio::task::spawn_blocking(move || uploads::enumerate(&paths))
+        .await
+        .map_err(|e| e.to_string())?
+        .map_err(|e| e.to_string())?;
+
+    let mut progress = UploadProgress {
+        upload_id,
+        files_total: files.len(),
+        files_done: 0,
+        bytes_total: files.iter().map(|file| file.size_bytes).sum(),
+        bytes_done: 0,
+        current: None,
+    };
+    info!("📂 Uploading {} files ({} bytes) as {}", progress.files_total, progress.bytes_total, upload_id);
+    let _ = app.emit_all(UPLOAD_PROGRESS_EVENT, &progress);
+
+    let mut documents = Vec::new();
+    for file in files {
+        progress.current = Some(file.name.clone());
+        let done_before = progress.bytes_done;
+        let copied = state.filesystem
+            .copy_upload(&file.source, &file.name, |copied| {
+                let _ = app.emit_all(
+                    UPLOAD_PROGRESS_EVENT,
+                    UploadProgress { bytes_done: done_before + copied, ..progress.clone() },
+                );
+            })
+            .await;
+
+        match copied {
+            Ok(hash) => documents.push(Document {
+                id: Uuid::new_v4(),
+                filename: hash.filename,
+                size: hash.size_bytes as usize,
+                sha256: hash.sha256,
+                uploaded_at: chrono::Utc::now(),
+                indexed: false,
+                chunk_count: None,
+                url: None,
+            }),
+            Err(e) => skipped.push(SkippedEntry { path: file.name, reason: e.to_string() }),
+        }
+        progress.files_done += 1;
+        progress.bytes_done = done_before + file.size_bytes;
+    }
+    progress.current = None;
+    let _ = app.emit_all(UPLOAD_PROGRESS_EVENT, &progress);
+
+    state.documents.write().await.extend(documents.iter().cloned());
+    let queued_for_indexing = queue_for_indexing(&state, &documents).await;
+
+    info!("✅ Upload {} finished: {} documents, {} skipped", upload_id, documents.len(), skipped.len());
+
+    Ok(UploadPathsResponse { documents, skipped, queued_for_indexing })
+}
+
+/// Queue uploaded documents for indexing and tell WebSocket subscribers; returns how many are waiting now
+async fn queue_for_indexing(state: &AppState, documents: &[Document]) -> usize {
+    let document_ids: Vec<Uuid> = documents.iter().map(|doc| doc.id).collect();
+    let waiting = state.index_queue.push(document_ids.iter().copied()).await;
+    state.websocket_events.publish(WebSocketMessage::DocumentsQueued { document_ids, waiting });
+    waiting
+}
+
+#[tauri::command]
+pub async fn get_documents(state: State<'_, AppState>) -> Result<Vec<Document>, String> {
+    debug!("📋 Getting document list");
+
+    let documents = state.documents.read().await;
+    Ok(documents.clone())
+}
+
+#[tauri::command]
+pub async fn delete_document(
+    state: State<'_, AppState>,
+    document_id: Uuid,
+) -> Result<(), String> {
+    info!("🗑️  Deleting document: {}", document_id);
+
+    let mut documents = state.documents.write().await;
+    let Some(index) = documents.iter().position(|doc| doc.id == document_id) else {
+        return Ok(());
+    };
+
+    // Kept in the trash so an accidental deletion can be undone
+    let entry = state.filesystem
+        .delete_file(ManagedFolder::Uploads, &documents[index].filename)
+        .await
+        .map_err(|e| e.to_string())?;
+    let document = documents.remove(index);
+    state.index_queue.remove(&document.id).await;
+    state.trashed_documents.write().await.insert(entry.id, document);
+
+    Ok(())
+}
+
+/// Link an uploaded document to a conversation so its chunks are retrieved first there
+#[tauri::command]
+pub async fn attach_document(
+    state: State<'_, AppState>,
+    conversation_id: Uuid,
+    document_id: Uuid,
+) -> Result<Conversation, String> {
+    info!("📎 Attaching document {} to conversation {}", document_id, conversation_id);
+
+    if !state.documents.read().await.iter().any(|doc| doc.id == document_id) {
+        return Err(format!("No document {}", document_id));
+    }
+
+    state.context()
+        .attach_document(&conversation_id, &document_id)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// Unlink a document from a conversation; deleted documents can be detached too
+#[tauri::command]
+pub async fn detach_document(
+    state: State<'_, AppState>,
+    conversation_id: Uuid,
+    document_id: Uuid,
+) -> Result<Conversation, String> {
+    info!("📎 Detaching document {} from conversation {}", document_id, conversation_id);
+
+    state.context()
+        .detach_document(&conversation_id, &document_id)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// Re-index the watched folders and pages now; unchanged documents only have their check recorded
+#[tauri::command]
+pub async fn recrawl_sources(state: State<'_, AppState>) -> Result<RecrawlReport, String> {
+    info!("📚 Re-crawling watched sources");
+    recrawl::recrawl(&state).await.map_err(|e| e.to_string())
+}
+
+// ============================================================================
+// Storage Commands
+// ============================================================================
+
+#[tauri::command]
+pub async fn get_storage_usage(state: State<'_, AppState>) -> Result<Vec<FolderUsage>, String> {
+    debug!("💽 Getting storage usage");
+
+    let filesystem = Arc::clone(&state.filesystem);
+    tokio::task::spawn_blocking(move || filesystem.usage())
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn list_trash(state: State<'_, AppState>) -> Result<Vec<TrashEntry>, String> {
+    debug!("📋 Listing trash");
+
+    Ok(state.filesystem.list_trash())
+}
+
+#[tauri::command]
+pub async fn restore_from_trash(
+    state: State<'_, AppState>,
+    entry_id: Uuid,
+) -> Result<TrashEntry, String> {
+    info!("♻️  Restoring from trash: {}", entry_id);
+
+    let (entry, _) = state.filesystem
+        .restore_file(entry_id)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    if let Some(document) = state.trashed_documents.write().await.remove(&entry_id) {
+        state.documents.write().await.push(document);
+    }
+
+    Ok(entry)
+}
+
+#[tauri::command]
+pub async fn empty_trash(state: State<'_, AppState>) -> Result<usize, String> {
+    info!("🧹 Emptying trash");
+
+    let purged = state.filesystem.empty_trash();
+    state.trashed_documents.write().await.clear();
+    let conversations = state.context()
+        .purge_conversations(chrono::Utc::now())
+        .await
+        .map_err(|e| e.to_string())?;
+    Ok(purged + conversations)
+}
+
+#[tauri::command]
+pub async fn list_rag_versions(
+    state: State<'_, AppState>,
+    filename: String,
+) -> Result<Vec<FileVersion>, String> {
+    debug!("🕓 Listing versions of {}", filename);
+
+    state.filesystem.rag_versions(&filename).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn diff_rag_versions(
+    state: State<'_, AppState>,
+    filename: String,
+    from: u32,
+    to: u32,
+) -> Result<String, String> {
+    debug!("🕓 Diffing {} versions {} and {}", filename, from, to);
+
+    state.filesystem
+        .diff_rag_versions(&filename, from, to)
+        .map_err(|e| e.to_string())
+}
+
+/// Managed files matching a query, served from the cached metadata index
+#[tauri::command]
+pub async fn list_files(
+    state: State<'_, AppState>,
+    query: Option<FileQuery>,
+) -> Result<Vec<FileMetadata>, String> {
+    debug!("📋 Listing managed files");
+
+    let filesystem = Arc::clone(&state.filesystem);
+    tokio::task::spawn_blocking(move || filesystem.list_files(&query.unwrap_or_default()))
+        .await
+        .map_err(|e| e.to_string())?
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn find_files_by_hash(
+    state: State<'_, AppState>,
+    sha256: String,
+) -> Result<Vec<FileHash>, String> {
+    debug!("#️⃣  Looking up files with hash {}", sha256);
+
+    let filesystem = Arc::clone(&state.filesystem);
+    tokio::task::spawn_blocking(move || filesystem.find_by_hash(&sha256))
+        .await
+        .map_err(|e| e.to_string())?
+        .map_err(|e| e.to_string())
+}
+
+#[derive(Debug, Serialize)]
+pub struct ShareLinkResponse {
+    #[serde(flatten)]
+    pub link: ShareLink,
+    /// Opens the file in any browser on this machine until the link expires
+    pub url: String,
+}
+
+/// Create an expiring link to a file in downloads
+#[tauri::command]
+pub async fn create_share_link(
+    state: State<'_, AppState>,
+    filename: String,
+    ttl_secs: Option<u64>,
+) -> Result<ShareLinkResponse, String> {
+    info!("🔗 Creating share link for {}", filename);
+
+    let ttl = ttl_secs.map_or(DEFAULT_SHARE_TTL, std::time::Duration::from_secs);
+    let link = state.filesystem
+        .share_download(&filename, ttl)
+        .map_err(|e| e.to_string())?;
+    let url = format!("http://{}/share/{}", SERVER_ADDR, link.token);
+
+    Ok(ShareLinkResponse { link, url })
+}
+
+// ============================================================================
+// Permission Commands
+// ============================================================================
+
+#[tauri::command]
+pub async fn get_permissions(state: State<'_, AppState>) -> Result<PermissionScope, String> {
+    debug!("📋 Getting permissions");
+
+    let permissions = state.permissions.read().await;
+    Ok(permissions.clone())
+}
+
+#[tauri::command]
+pub async fn update_permissions(
+    state: State<'_, AppState>,
+    permissions: PermissionScope,
+) -> Result<(), String> {
+    info!("💾 Updating permissions");
+
+    // Kept with the active profile, so they come back when switching to it
+    let mut perms = state.permissions.write().await;
+    state.profiles.set_permissions(permissions.clone()).map_err(|e| e.to_string())?;
+    *perms = permissions;
+
+    Ok(())
+}
+
+// ============================================================================
+// Profile Commands
+// ============================================================================
+
+#[tauri::command]
+pub async fn list_profiles(state: State<'_, AppState>) -> Result<ProfileList, String> {
+    debug!("📋 Listing profiles");
+    Ok(state.profiles.list())
+}
+
+#[tauri::command]
+pub async fn create_profile(state: State<'_, AppState>, profile: NewProfile) -> Result<Profile, String> {
+    info!("👤 Creating profile {}", profile.name);
+
+    let profile = state.profiles.create(profile).await.map_err(|e| e.to_string())?;
+    state.security_engine
+        .audit()
+        .log(
+            None,
+            "Profile created".to_string(),
+            serde_json::json!({ "profile": profile.name, "sensitive": profile.sensitive }),
+            true,
+            None,
+        )
+        .await;
+    Ok(profile)
+}
+
+/// Switch to another profile; sensitive profiles need their passphrase
+#[tauri::command]
+pub async fn switch_profile(
+    app: AppHandle,
+    state: State<'_, AppState>,
+    name: String,
+    passphrase: Option<String>,
+) -> Result<Profile, String> {
+    info!("👤 Switching to profile {}", name);
+
+    let profile = state.switch_profile(&name, passphrase.as_deref()).await.map_err(|e| e.to_string())?;
+    tray::changed(&app).await;
+    Ok(profile)
+}
+
+/// Whether the active profile's conversations and RAG content are encrypted at rest
+#[derive(Debug, Serialize)]
+pub struct EncryptionStatus {
+    /// Only conversations kept in PostgreSQL are stored, and so can be encrypted
+    pub available: bool,
+    /// A passphrase has been set; new content is written encrypted once unlocked
+    pub enabled: bool,
+    /// The key is at hand, from the keyring or the passphrase
+    pub unlocked: bool,
+}
+
+#[tauri::command]
+pub async fn get_encryption_status(state: State<'_, AppState>) -> Result<EncryptionStatus, String> {
+    encryption_status(&state).await
+}
+
+async fn encryption_status(state: &AppState) -> Result<EncryptionStatus, String> {
+    let Some(database) = state.profiles.database(&state.profiles.active().name) else {
+        return Ok(EncryptionStatus { available: false, enabled: false, unlocked: false });
+    };
+    Ok(EncryptionStatus {
+        available: true,
+        enabled: database.encryption_enabled().await.map_err(|e| e.to_string())?,
+        unlocked: database.is_unlocked(),
+    })
+}
+
+/// Encrypt the active profile's messages, titles, context values and indexed documents with a key derived
+/// from `passphrase`, including what is already stored; once on, the same passphrase unlocks them
+/// where the keyring lost the key
+#[tauri::command]
+pub async fn enable_encryption(state: State<'_, AppState>, passphrase: String) -> Result<EncryptionStatus, String> {
+    let profile = state.profiles.active().name;
+    info!("🔐 Enabling encryption at rest for profile {}", profile);
+
+    let enabled = state.profiles.enable_encryption(&profile, &passphrase).await;
+    state.security_engine
+        .audit()
+        .log(
+            None,
+            "Encryption at rest".to_string(),
+            serde_json::json!({ "profile": profile }),
+            enabled.is_ok(),
+            enabled.as_ref().err().map(|e| e.to_string()),
+        )
+        .await;
+    enabled.map_err(|e| e.to_string())?;
+
+    // Local models' KV caches would hold the profile's conversations in plaintext; those saved so far go too
+    state.revoke_kv_persistence();
+    let sessions_dir = models::sessions_dir(&state.settings.read().await.paths.data_dir);
+    if let Err(e) = tokio::fs::remove_dir_all(&sessions_dir).await {
+        if e.kind() != std::io::ErrorKind::NotFound {
+            warn!("⚠️  Could not delete the saved KV caches in {:?}: {}", sessions_dir, e);
+        }
+    }
+
+    encryption_status(&state).await
+}
+
+/// Delete a profile other than the active one; a sensitive profile needs its passphrase
+#[tauri::command]
+pub async fn delete_profile(
+    state: State<'_, AppState>,
+    name: String,
+    passphrase: Option<String>,
+) -> Result<(), String> {
+    info!("🗑️  Deleting profile {}", name);
+
+    let profile = state.profiles.get(&name).map_err(|e| e.to_string())?;
+    if !state.profiles.check_passphrase(&profile, passphrase.as_deref()).await.map_err(|e| e.to_string())? {
+        return Err(format!("Wrong passphrase for profile {}", name));
+    }
+    state.profiles.delete(&name).await.map_err(|e| e.to_string())?;
+    state.security_engine
+        .audit()
+        .log(
+            None,
+            "Profile deleted".to_string(),
+            serde_json::json!({ "profile": name }),
+            true,
+            None,
+        )
+        .await;
+    Ok(())
+}
+
+// ============================================================================
+// Backup Commands
+// ============================================================================
+
+/// Archive the settings, every profile's database and the data directory into the backup directory
+#[tauri::command]
+pub async fn create_backup(state: State<'_, AppState>) -> Result<BackupInfo, String> {
+    info!("💾 Creating a backup");
+    backup::create_backup(&state, false).await.map_err(|e| e.to_string())
+}
+
+/// Backups in the backup directory, newest first
+#[tauri::command]
+pub async fn list_backups(state: State<'_, AppState>) -> Result<Vec<BackupInfo>, String> {
+    debug!("📋 Listing backups");
+
+    let backup_dir = state.settings.read().await.backup_dir();
+    tokio::task::spawn_blocking(move || backup::list_backups(&backup_dir))
+        .await
+        .map_err(|e| e.to_string())?
+        .map_err(|e| e.to_string())
+}
+
+/// Replace the databases, data files and settings with a backup's; the app must restart afterwards
+#[tauri::command]
+pub async fn restore_backup(state: State<'_, AppState>, path: String) -> Result<RestoreReport, String> {
+    info!("♻️  Restoring the backup {}", path);
+    backup::restore_backup(&state, Path::new(&path)).await.map_err(|e| e.to_string())
+}
+
+// ============================================================================
+// Plugin Commands
+// ============================================================================
+
+#[derive(Debug, Serialize)]
+pub struct PluginList {
+    pub plugins: Vec<PluginInfo>,
+    /// Plugin directories that failed to load, so a broken plugin doesn't just go missing
+    pub failures: Vec<PluginFailure>,
+}
+
+/// Plugins loaded from `paths.plugins_dir` at startup, with the capabilities they were granted
+#[tauri::command]
+pub async fn list_plugins(state: State<'_, AppState>) -> Result<PluginList, String> {
+    debug!("📋 Listing plugins");
+    Ok(PluginList { plugins: state.plugins.plugins(), failures: state.plugins.failures().to_vec() })
+}
+
+// ============================================================================
+// Approval Commands
+// ============================================================================
+
+/// Event carrying `ApprovalEvent`s as requests arrive and are decided
+pub const APPROVAL_EVENT: &str = "approval";
+
+/// Permissions, artifact transfers and port forwards awaiting the user, oldest first
+#[tauri::command]
+pub async fn list_pending_approvals(state: State<'_, AppState>) -> Result<Vec<PendingApproval>, String> {
+    debug!("📋 Listing pending approvals");
+    Ok(state.security_engine.pending_approvals().await)
+}
+
+/// What the security engine would decide on each step of a plan, so the user sees the approvals a task needs
+/// Nothing runs, is audited or counts against the LLM
+#[tauri::command]
+pub async fn simulate_plan(
+    state: State<'_, AppState>,
+    llm_id: Option<String>,
+    plan: Vec<PlannedAction>,
+) -> Result<Vec<SimulatedDecision>, String> {
+    debug!("🧪 Simulating a plan of {} steps", plan.len());
+    let mut context = RequestContext::user();
+    if let Some(llm_id) = llm_id {
+        context = context.with_llm(llm_id);
+    }
+    state.security_engine.simulate_plan(&context, &plan).await.map_err(|e| e.to_string())
+}
+
+/// Approve a pending request; the waiting subsystem proceeds and an `approval` event follows
+#[tauri::command]
+pub async fn approve_request(state: State<'_, AppState>, request_id: Uuid) -> Result<(), String> {
+    info!("✅ Approving request {}", request_id);
+
+    state.security_engine
+        .resolve_approval(request_id, true)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// Deny a pending request; the waiting subsystem is told and an `approval` event follows
+#[tauri::command]
+pub async fn deny_request(state: State<'_, AppState>, request_id: Uuid) -> Result<(), String> {
+    info!("❌ Denying request {}", request_id);
+
+    state.security_engine
+        .resolve_approval(request_id, false)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+// ============================================================================
+// Audit Log Commands
+// ============================================================================
+
+/// One page of the security engine's audit log, newest first; no query returns the latest page
+#[tauri::command]
+pub async fn get_audit_log(state: State<'_, AppState>, query: Option<AuditQuery>) -> Result<AuditPage, String> {
+    debug!("📋 Getting audit log");
+
+    let query = query.unwrap_or_default();
+    if let (Some(since), Some(until)) = (query.since, query.until) {
+        if since > until {
+            return Err("since must not be after until".to_string());
+        }
+    }
+
+    Ok(state.security_engine.audit().query(&query).await)
+}
+
+// ============================================================================
+// Security Window Commands
+// ============================================================================
+
+/// Open the always-on-top security window, or bring it to the front if already open
+#[tauri::command]
+pub async fn open_security_window(app: AppHandle) -> Result<(), String> {
+    security_window::open(&app).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn close_security_window(app: AppHandle) -> Result<(), String> {
+    security_window::close(&app).map_err(|e| e.to_string())
+}
+
+// ============================================================================
+// Sandbox Commands
+// ============================================================================
+
+/// Refuse sandbox work unless the system is running normally; read-only mode refuses it too
+async fn ensure_not_locked_down(state: &AppState) -> Result<(), String> {
+    match state.security_engine.lockdown_state().await.map_err(|e| e.to_string())? {
+        lockdown if lockdown.allows_writes() => Ok(()),
+        lockdown => Err(format!("Sandboxes are unavailable during lockdown ({:?})", lockdown)),
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateSandboxRequest {
+    /// LLM the sandbox is for; a sandbox for the user when omitted
+    pub llm_id: Option<String>,
+    pub purpose: String,
+    /// Prebuilt environment to start from; empty sandbox when omitted
+    pub template: Option<SandboxTemplate>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CreateSandboxResponse {
+    pub sandbox_id: Uuid,
+}
+
+#[tauri::command]
+pub async fn create_sandbox(
+    state: State<'_, AppState>,
+    request: CreateSandboxRequest,
+) -> Result<CreateSandboxResponse, String> {
+    info!("📦 Creating sandbox for {}: {}", request.llm_id.as_deref().unwrap_or("user"), request.purpose);
+
+    ensure_not_locked_down(&state).await?;
+    if let Some(template) = request.template {
+        if !state.sandbox_manager.available_templates().contains(&template) {
+            return Err(format!("Sandbox template {:?} is not built", template));
+        }
+    }
+
+    // Handed out from the warm pool when one is ready
+    let sandbox_id = state.sandbox_manager
+        .checkout(request.template, request.llm_id.clone())
+        .await
+        .map_err(|e| e.to_string())?;
+
+    state.security_engine
+        .audit()
+        .log(
+            request.llm_id,
+            "Sandbox created".to_string(),
+            serde_json::json!({
+                "sandbox_id": sandbox_id,
+                "purpose": request.purpose,
+                "template": request.template,
+            }),
+            true,
+            None,
+        )
+        .await;
+
+    Ok(CreateSandboxResponse { sandbox_id })
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ExecuteInSandboxRequest {
+    pub sandbox_id: Uuid,
+    /// Source code, or a shell command when `language` is omitted
+    pub code: String,
+    pub language: Option<CodeLanguage>,
+    /// Chosen by the caller so it can subscribe before the first output line
+    pub execution_id: Option<Uuid>,
+}
+
+/// Tauri event carrying sandbox execution output
+pub const SANDBOX_OUTPUT_EVENT: &str = "sandbox-output";
+
+/// One output line or the final result of an execution, emitted as `sandbox-output`
+#[derive(Debug, Clone, Serialize)]
+pub struct SandboxOutputEvent {
+    pub execution_id: Uuid,
+    pub sandbox_id: Uuid,
+    #[serde(flatten)]
+    pub event: ExecutionEvent,
+}
+
+/// Run code or a shell command in a sandbox and return the result once it finishes
+/// Output is emitted as `sandbox-output` events while it runs; shell commands must pass the guardrails
+#[tauri::command]
+pub async fn execute_in_sandbox(
+    app: AppHandle,
+    state: State<'_, AppState>,
+    request: ExecuteInSandboxRequest,
+) -> Result<ExecutionResult, String> {
+    let execution_id = request.execution_id.unwrap_or_else(Uuid::new_v4);
+    let sandbox_id = request.sandbox_id;
+    info!("🚀 Executing {} in sandbox: {}", execution_id, sandbox_id);
+
+    ensure_not_locked_down(&state).await?;
+    let events = match request.language {
+        Some(language) => state.sandbox_manager.run_code_stream(sandbox_id, language, &request.code).await,
+        None => {
+            let analysis = state.security_engine
+                .analyze_command(&request.code)
+                .await
+                .map_err(|e| e.to_string())?;
+            if !analysis.safe {
+                return Err(format!(
+                    "Command blocked ({:?} risk): {}",
+                    analysis.risk_level,
+                    analysis.issues.join("; ")
+                ));
+            }
+            state.sandbox_manager.execute_stream(sandbox_id, &request.code, None).await
+        }
+    };
+    let mut events = events.map_err(|e| e.to_string())?;
+
+    let mut result = None;
+    let mut last_error = None;
+    while let Some(event) = events.recv().await {
+        match &event {
+            ExecutionEvent::Finished { result: finished } => result = Some(finished.clone()),
+            ExecutionEvent::Stderr { line } => last_error = Some(line.clone()),
+            ExecutionEvent::Stdout { .. } => {}
+        }
+        let _ = app.emit_all(SANDBOX_OUTPUT_EVENT, SandboxOutputEvent { execution_id, sandbox_id, event: event.clone() });
+        state.websocket_events.publish(WebSocketMessage::SandboxOutput { execution_id, sandbox_id, event });
+    }
+
+    // Without a result the command never started, and its last stderr line says why
+    result.ok_or_else(|| last_error.unwrap_or_else(|| format!("Execution {} produced no result", execution_id)))
+}
+
+#[tauri::command]
+pub async fn start_kernel(
+    state: State<'_, AppState>,
+    sandbox_id: Uuid,
+    language: CodeLanguage,
+) -> Result<KernelInfo, String> {
+    info!("🧮 Starting {:?} kernel in sandbox: {}", language, sandbox_id);
+
+    ensure_not_locked_down(&state).await?;
+
+    let kernel_id = state.sandbox_manager
+        .start_kernel(sandbox_id, language)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    state.sandbox_manager
+        .list_kernels(sandbox_id)
+        .await
+        .into_iter()
+        .find(|kernel| kernel.id == kernel_id)
+        .ok_or_else(|| format!("Kernel not found: {}", kernel_id))
+}
+
+#[tauri::command]
+pub async fn execute_cell(
+    state: State<'_, AppState>,
+    kernel_id: Uuid,
+    code: String,
+) -> Result<CellOutput, String> {
+    info!("🧮 Executing cell in kernel: {}", kernel_id);
+
+    ensure_not_locked_down(&state).await?;
+
+    state.sandbox_manager
+        .execute_cell(kernel_id, &code)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn reset_kernel(
+    state: State<'_, AppState>,
+    kernel_id: Uuid,
+) -> Result<(), String> {
+    info!("🧮 Resetting kernel: {}", kernel_id);
+
+    ensure_not_locked_down(&state).await?;
+
+    state.sandbox_manager
+        .reset_kernel(kernel_id)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn shutdown_kernel(
+    state: State<'_, AppState>,
+    kernel_id: Uuid,
+) -> Result<(), String> {
+    info!("🧮 Shutting down kernel: {}", kernel_id);
+
+    state.sandbox_manager
+        .shutdown_kernel(kernel_id)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn list_kernels(
+    state: State<'_, AppState>,
+    sandbox_id: Uuid,
+) -> Result<Vec<KernelInfo>, String> {
+    debug!("📋 Listing kernels in sandbox: {}", sandbox_id);
+
+    Ok(state.sandbox_manager.list_kernels(sandbox_id).await)
+}
+
+#[derive(Debug, Deserialize)]
+pub struct GetSandboxFilesRequest {
+    pub sandbox_id: Uuid,
+    /// Directory relative to the sandbox root; the root when omitted
+    pub path: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct GetSandboxFilesResponse {
+    pub sandbox_id: Uuid,
+    pub files: Vec<SandboxFile>,
+}
+
+#[tauri::command]
+pub async fn get_sandbox_files(
+    state: State<'_, AppState>,
+    request: GetSandboxFilesRequest,
+) -> Result<GetSandboxFilesResponse, String> {
+    debug!("📋 Getting files for sandbox: {}", request.sandbox_id);
+
+    let files = state.sandbox_manager
+        .list_files(request.sandbox_id, request.path.as_deref())
+        .await
+        .map_err(|e| e.to_string())?;
+
+    Ok(GetSandboxFilesResponse { sandbox_id: request.sandbox_id, files })
+}
+
+/// Files the LLM created, modified or deleted since the sandbox was created
+#[tauri::command]
+pub async fn get_sandbox_diff(
+    state: State<'_, AppState>,
+    sandbox_id: Uuid,
+) -> Result<Vec<FileChange>, String> {
+    debug!("📋 Diffing sandbox: {}", sandbox_id);
+
+    state.sandbox_manager
+        .diff(sandbox_id)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// Latest resource usage of one sandbox, or of every sandbox when omitted
+#[tauri::command]
+pub async fn get_sandbox_usage(
+    state: State<'_, AppState>,
+    sandbox_id: Option<Uuid>,
+) -> Result<Vec<SandboxUsage>, String> {
+    debug!("📈 Getting sandbox usage");
+
+    Ok(match sandbox_id {
+        Some(sandbox_id) => state.sandbox_manager.usage(sandbox_id).await.into_iter().collect(),
+        None => state.sandbox_manager.all_usage().await,
+    })
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ApproveTransferRequest {
+    pub transfer_id: Uuid,
+    pub approved: bool,
+}
+
+#[tauri::command]
+pub async fn approve_transfer(
+    state: State<'_, AppState>,
+    request: ApproveTransferRequest,
+) -> Result<(), String> {
+    info!("✅ Transfer approval: {} - {}", request.transfer_id, request.approved);
+
+    state.security_engine
+        .resolve_artifact_approval(request.transfer_id, request.approved)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// Approve or deny a pending request to expose a sandbox port
+#[tauri::command]
+pub async fn approve_port_forward(
+    state: State<'_, AppState>,
+    request_id: Uuid,
+    approved: bool,
+) -> Result<(), String> {
+    info!("🔌 Port forward approval: {} - {}", request_id, approved);
+
+    state.security_engine
+        .resolve_port_forward_approval(request_id, approved)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// Sandbox ports currently exposed on localhost
+#[tauri::command]
+pub async fn list_port_forwards(state: State<'_, AppState>) -> Result<Vec<PortForward>, String> {
+    debug!("📋 Listing port forwards");
+    Ok(state.sandbox_manager.port_forwards())
+}
+
+#[tauri::command]
+pub async fn close_port_forward(
+    state: State<'_, AppState>,
+    forward_id: Uuid,
+) -> Result<(), String> {
+    info!("🔌 Closing port forward: {}", forward_id);
+
+    state.sandbox_manager
+        .close_port_forward(forward_id)
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn list_sandbox_templates(
+    state: State<'_, AppState>,
+) -> Result<Vec<SandboxTemplate>, String> {
+    debug!("📋 Listing sandbox templates");
+
+    Ok(state.sandbox_manager.available_templates())
+}
+
+#[tauri::command]
+pub async fn list_snapshots(state: State<'_, AppState>) -> Result<Vec<SnapshotInfo>, String> {
+    debug!("📋 Listing sandbox snapshots");
+
+    Ok(state.sandbox_manager.list_snapshots().await)
+}
+
+#[tauri::command]
+pub async fn delete_snapshot(
+    state: State<'_, AppState>,
+    snapshot_id: Uuid,
+) -> Result<(), String> {
+    info!("🗑️  Deleting snapshot: {}", snapshot_id);
+
+    state.sandbox_manager
+        .delete_snapshot(snapshot_id)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn list_volumes(state: State<'_, AppState>) -> Result<Vec<VolumeInfo>, String> {
+    debug!("📋 Listing sandbox volumes");
+
+    Ok(state.sandbox_manager.list_volumes())
+}
+
+#[tauri::command]
+pub async fn create_volume(
+    state: State<'_, AppState>,
+    name: String,
+) -> Result<VolumeInfo, String> {
+    info!("💾 Creating volume: {}", name);
+
+    state.sandbox_manager
+        .create_volume(&name)
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn delete_volume(
+    state: State<'_, AppState>,
+    name: String,
+) -> Result<(), String> {
+    info!("🗑️  Deleting volume: {}", name);
+
+    state.sandbox_manager
+        .delete_volume(&name)
+        .map_err(|e| e.to_string())
+}
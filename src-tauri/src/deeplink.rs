@@ -0,0 +1,141 @@
+use common::{
+    errors::{HybridLLMError, Result},
+    types::LockdownState,
+    SecurityEngine,
+};
+use serde::Serialize;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use tauri::{AppHandle, Manager};
+use tracing::{info, warn};
+use url::Url;
+
+use crate::state::AppState;
+use crate::tray;
+
+/// URL scheme registered with the OS
+pub const SCHEME: &str = "hybridllm";
+
+/// Tauri event telling the UI there are links to collect with `take_pending_deep_links`
+pub const DEEP_LINK_EVENT: &str = "deep-link";
+
+/// Longest prompt a link may carry
+const MAX_PROMPT_CHARS: usize = 32_000;
+
+/// What a `hybridllm://` link asks for
+/// Links can come from any web page, so the UI confirms each one with the user before acting on it
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum DeepLink {
+    /// `hybridllm://chat?prompt=...&llm=...`; without `llm` the default LLM from the settings
+    Chat { prompt: String, llm_id: Option<String> },
+    /// `hybridllm://index?path=...`, a file or folder to upload and index
+    Index { path: PathBuf },
+}
+
+impl DeepLink {
+    pub fn parse(link: &str) -> Result<Self> {
+        let invalid = |reason: &str| HybridLLMError::InvalidRequest(format!("Invalid link {:?}: {}", link, reason));
+
+        let url = Url::parse(link).map_err(|e| invalid(&e.to_string()))?;
+        if url.scheme() != SCHEME {
+            return Err(invalid("not a hybridllm:// link"));
+        }
+        let param = |name: &str| {
+            url.query_pairs()
+                .find(|(key, _)| key == name)
+                .map(|(_, value)| value.trim().to_string())
+                .filter(|value| !value.is_empty())
+        };
+
+        // `hybridllm://chat` has the action as its host, `hybridllm:chat` as its path
+        match url.host_str().unwrap_or(url.path()).trim_matches('/') {
+            "chat" => {
+                let prompt = param("prompt").ok_or_else(|| invalid("missing prompt"))?;
+                if prompt.chars().count() > MAX_PROMPT_CHARS {
+                    return Err(invalid(&format!("prompt is longer than {} characters", MAX_PROMPT_CHARS)));
+                }
+                Ok(Self::Chat { prompt, llm_id: param("llm") })
+            }
+            "index" => {
+                let path = PathBuf::from(param("path").ok_or_else(|| invalid("missing path"))?);
+                if !path.is_absolute() {
+                    return Err(invalid("path must be absolute"));
+                }
+                Ok(Self::Index { path })
+            }
+            action => Err(invalid(&format!("unknown action {:?}", action))),
+        }
+    }
+}
+
+/// Links received but not yet collected by the UI, which may still be loading when the app
+/// was launched by one
+#[derive(Default)]
+pub struct DeepLinks {
+    pending: Mutex<Vec<DeepLink>>,
+}
+
+impl DeepLinks {
+    pub fn take(&self) -> Vec<DeepLink> {
+        std::mem::take(&mut *self.pending.lock().unwrap())
+    }
+}
+
+/// Record a link in the audit log and, unless it is invalid or the system is locked down,
+/// bring the window forward and hand the link to the UI
+pub async fn handle(app: &AppHandle, link: String) {
+    let state = app.state::<AppState>();
+
+    let lockdown = state.security_engine.lockdown_state().await.unwrap_or(LockdownState::Normal);
+    let parsed = match DeepLink::parse(&link) {
+        Ok(_) if lockdown != LockdownState::Normal => Err(format!("System is in {:?} mode", lockdown)),
+        Ok(mut parsed) => {
+            if let DeepLink::Chat { llm_id, .. } = &mut parsed {
+                if llm_id.is_none() {
+                    *llm_id = state.settings.read().await.models.default_llm.clone();
+                }
+            }
+            Ok(parsed)
+        }
+        Err(e) => Err(e.to_string()),
+    };
+
+    state.security_engine
+        .audit()
+        .log(
+            None,
+            "Deep link".to_string(),
+            serde_json::json!({ "url": link }),
+            parsed.is_ok(),
+            parsed.as_ref().err().cloned(),
+        )
+        .await;
+
+    match parsed {
+        Ok(parsed) => {
+            info!("🔗 Deep link: {:?}", parsed);
+            state.deep_links.pending.lock().unwrap().push(parsed);
+            tray::show_dashboard(app);
+            let _ = app.emit_all(DEEP_LINK_EVENT, ());
+        }
+        Err(reason) => warn!("🔗 Ignoring deep link: {}", reason),
+    }
+}
+
+/// Register the `hybridllm://` scheme and handle the link the app was launched with, if any
+/// On Windows and Linux a second instance opened by a link passes it here and exits
+pub fn register(app: &AppHandle) -> std::io::Result<()> {
+    let app_handle = app.clone();
+    tauri_plugin_deep_link::register(SCHEME, move |link| {
+        let app = app_handle.clone();
+        tauri::async_runtime::spawn(async move { handle(&app, link).await });
+    })?;
+
+    let prefix = format!("{}:", SCHEME);
+    if let Some(link) = std::env::args().skip(1).find(|arg| arg.starts_with(&prefix)) {
+        let app = app.clone();
+        tauri::async_runtime::spawn(async move { handle(&app, link).await });
+    }
+    Ok(())
+}
@@ -0,0 +1,268 @@
+use api_gateway::{ClaudeAdapter, GeminiAdapter, OpenAIAdapter};
+use common::{
+    errors::{Result, HybridLLMError},
+    traits::LLMProvider,
+    types::LLMProvider as LLMProviderType,
+};
+use llm_pool::LLMPool;
+use serde::{Deserialize, Serialize};
+use std::sync::RwLock;
+use tracing::{info, warn};
+
+use crate::profiles::DEFAULT_PROFILE;
+use crate::settings::{ProviderKey, ProviderSettings};
+use crate::websocket::{constant_time_eq, mint_session_token};
+
+/// Keyring service the API keys are stored under, one entry per provider
+pub const KEYRING_SERVICE: &str = "hybrid-llm-platform";
+
+/// A cloud provider whose API key the app manages
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CloudProvider {
+    Claude,
+    OpenAI,
+    Gemini,
+}
+
+impl CloudProvider {
+    pub const ALL: [CloudProvider; 3] = [Self::Claude, Self::OpenAI, Self::Gemini];
+
+    /// Name used for the keyring entry and in `settings.toml`
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Claude => "claude",
+            Self::OpenAI => "openai",
+            Self::Gemini => "gemini",
+        }
+    }
+
+    fn provider_type(self) -> LLMProviderType {
+        match self {
+            Self::Claude => LLMProviderType::Claude,
+            Self::OpenAI => LLMProviderType::OpenAI,
+            Self::Gemini => LLMProviderType::Gemini,
+        }
+    }
+
+    /// Model registered when the settings don't name one, as in `config.example.toml`
+    fn default_model(self) -> &'static str {
+        match self {
+            Self::Claude => "claude-3-5-sonnet-20241022",
+            Self::OpenAI => "gpt-4-turbo-preview",
+            Self::Gemini => "gemini-1.5-pro",
+        }
+    }
+
+    pub fn settings(self, providers: &ProviderSettings) -> &ProviderKey {
+        match self {
+            Self::Claude => &providers.claude,
+            Self::OpenAI => &providers.openai,
+            Self::Gemini => &providers.gemini,
+        }
+    }
+
+    fn model(self, providers: &ProviderSettings) -> String {
+        self.settings(providers)
+            .model
+            .clone()
+            .unwrap_or_else(|| self.default_model().to_string())
+    }
+
+    fn adapter(self, api_key: String, model: String) -> Box<dyn LLMProvider> {
+        match self {
+            Self::Claude => Box::new(ClaudeAdapter::new(api_key, model)),
+            Self::OpenAI => Box::new(OpenAIAdapter::new(api_key, model)),
+            Self::Gemini => Box::new(GeminiAdapter::new(api_key, model)),
+        }
+    }
+}
+
+/// Where a provider's key came from, if it has one
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum KeySource {
+    Keyring,
+    Environment,
+}
+
+/// Keyring calls can block on the OS secret service, so they run off the async runtime
+pub async fn with_keyring_entry<T: Send + 'static>(
+    entry: String,
+    f: impl FnOnce(keyring::Entry) -> keyring::Result<T> + Send + 'static,
+) -> Result<T> {
+    let name = entry.clone();
+    tokio::task::spawn_blocking(move || keyring::Entry::new(KEYRING_SERVICE, &name).and_then(f))
+        .await
+        .map_err(|e| HybridLLMError::Other(e.into()))?
+        .map_err(|e| HybridLLMError::ConfigError(format!("Keyring entry for {}: {}", entry, e)))
+}
+
+/// The provider's keyring entry in a profile; the default profile keeps the entries from before profiles
+fn key_entry(provider: CloudProvider, profile: &str) -> String {
+    if profile == DEFAULT_PROFILE {
+        provider.name().to_string()
+    } else {
+        format!("{}/{}", profile, provider.name())
+    }
+}
+
+/// The key stored in the OS keyring for a profile, if any
+pub async fn stored_key(provider: CloudProvider, profile: &str) -> Result<Option<String>> {
+    with_keyring_entry(key_entry(provider, profile), |entry| match entry.get_password() {
+        Ok(key) => Ok(Some(key)),
+        Err(keyring::Error::NoEntry) => Ok(None),
+        Err(e) => Err(e),
+    })
+    .await
+}
+
+pub async fn store_key(provider: CloudProvider, profile: &str, api_key: String) -> Result<()> {
+    with_keyring_entry(key_entry(provider, profile), move |entry| entry.set_password(&api_key)).await?;
+    info!("🔑 Stored {} API key for profile {} in the keyring", provider.name(), profile);
+    Ok(())
+}
+
+/// Remove the stored key; returns whether there was one
+pub async fn delete_key(provider: CloudProvider, profile: &str) -> Result<bool> {
+    let deleted = with_keyring_entry(key_entry(provider, profile), |entry| match entry.delete_credential() {
+        Ok(()) => Ok(true),
+        Err(keyring::Error::NoEntry) => Ok(false),
+        Err(e) => Err(e),
+    })
+    .await?;
+    if deleted {
+        info!("🗑️  Removed {} API key for profile {} from the keyring", provider.name(), profile);
+    }
+    Ok(deleted)
+}
+
+/// The key the provider would be registered with: the profile's keyring entry first, then `api_key_env`
+/// Keys from the environment are shared by everything on the machine, so only the default profile uses them
+pub async fn resolve_key(
+    provider: CloudProvider,
+    profile: &str,
+    providers: &ProviderSettings,
+) -> Result<Option<(String, KeySource)>> {
+    if let Some(key) = stored_key(provider, profile).await? {
+        return Ok(Some((key, KeySource::Keyring)));
+    }
+    if profile != DEFAULT_PROFILE {
+        return Ok(None);
+    }
+    Ok(std::env::var(&provider.settings(providers).api_key_env)
+        .ok()
+        .filter(|key| !key.trim().is_empty())
+        .map(|key| (key, KeySource::Environment)))
+}
+
+/// Ask the provider whether it accepts `api_key` for the configured model
+pub async fn probe(provider: CloudProvider, providers: &ProviderSettings, api_key: String) -> Result<bool> {
+    provider
+        .adapter(api_key, provider.model(providers))
+        .health_check()
+        .await
+}
+
+/// Replace the provider's adapter in the pool with one using the profile's current key
+/// Returns the registered LLM id, or `None` when the provider is disabled or has no key
+pub async fn register(
+    pool: &LLMPool,
+    provider: CloudProvider,
+    profile: &str,
+    providers: &ProviderSettings,
+) -> Result<Option<String>> {
+    for id in pool.get_all_ids() {
+        if pool.get(&id).is_some_and(|llm| llm.instance().provider == provider.provider_type()) {
+            pool.unregister(&id)?;
+        }
+    }
+
+    if !provider.settings(providers).enabled {
+        return Ok(None);
+    }
+    let Some((key, _)) = resolve_key(provider, profile, providers).await? else {
+        return Ok(None);
+    };
+
+    let adapter = provider.adapter(key, provider.model(providers));
+    let id = adapter.instance().id.clone();
+    pool.register(adapter)?;
+    Ok(Some(id))
+}
+
+/// Register every enabled provider that has a key in the profile, e.g. at startup, after the settings change
+/// or when switching profiles
+pub async fn register_all(pool: &LLMPool, profile: &str, providers: &ProviderSettings) {
+    for provider in CloudProvider::ALL {
+        if let Err(e) = register(pool, provider, profile, providers).await {
+            warn!("⚠️  Could not register {}: {}", provider.name(), e);
+        }
+    }
+}
+
+/// A bearer token local clients such as the browser extension present, kept in the keyring across restarts
+/// Empty, and so accepting nothing, until loaded
+pub struct LocalToken {
+    entry: &'static str,
+    /// What holds the token, for log messages
+    client: &'static str,
+    token: RwLock<String>,
+}
+
+impl LocalToken {
+    pub fn new(entry: &'static str, client: &'static str) -> Self {
+        Self { entry, client, token: RwLock::new(String::new()) }
+    }
+
+    pub fn get(&self) -> String {
+        self.token.read().unwrap().clone()
+    }
+
+    pub fn authorized(&self, presented: Option<&str>) -> bool {
+        let token = self.token.read().unwrap();
+        !token.is_empty() && presented.is_some_and(|presented| constant_time_eq(presented.as_bytes(), token.as_bytes()))
+    }
+
+    /// Replace the token; clients holding the old one are refused from now on
+    pub async fn reset(&self) -> Result<()> {
+        let token = mint_session_token();
+        self.store(token.clone()).await?;
+        *self.token.write().unwrap() = token;
+        info!("🔑 {} token replaced", self.client);
+        Ok(())
+    }
+
+    /// The stored token, minting one the first time
+    /// Without a usable keyring the token only lasts until the app quits
+    pub async fn load(&self) {
+        let token = match self.stored().await {
+            Ok(Some(token)) => token,
+            Ok(None) => {
+                let token = mint_session_token();
+                if let Err(e) = self.store(token.clone()).await {
+                    warn!("⚠️  {} token not saved, it changes after a restart: {}", self.client, e);
+                }
+                token
+            }
+            Err(e) => {
+                warn!("⚠️  {} token not readable, it changes after a restart: {}", self.client, e);
+                mint_session_token()
+            }
+        };
+        *self.token.write().unwrap() = token;
+    }
+
+    async fn stored(&self) -> Result<Option<String>> {
+        with_keyring_entry(self.entry.to_string(), |entry| match entry.get_password() {
+            Ok(token) => Ok(Some(token)),
+            Err(keyring::Error::NoEntry) => Ok(None),
+            Err(e) => Err(e),
+        })
+        .await
+    }
+
+    async fn store(&self, token: String) -> Result<()> {
+        with_keyring_entry(self.entry.to_string(), move |entry| entry.set_password(&token)).await
+    }
+}
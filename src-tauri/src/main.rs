@@ -1,29 +1,215 @@
 // Prevents additional console window on Windows in release
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
+mod backup;
+mod browser_bridge;
 mod commands;
+mod deeplink;
+mod keys;
+mod models;
+mod notifications;
+mod openai_api;
+mod panic;
+mod profiles;
+mod recrawl;
+mod resources;
+mod screenshot;
+mod security_window;
+mod session;
+mod settings;
 mod state;
+mod transcript;
+mod tray;
+mod uploads;
+mod voice;
 mod websocket;
 
+use sandbox_manager::{PoolConfig, SandboxEvent};
+use security_engine::ApprovalEvent;
 use state::AppState;
+use std::sync::Arc;
+use std::time::Duration;
 use tauri::Manager;
-use tracing::{info, error};
-use tracing_subscriber;
+use tracing::{info, error, warn};
+use websocket::WebSocketMessage;
+
+/// How often every LLM is health checked; cloud checks are network requests
+const HEALTH_CHECK_INTERVAL: Duration = Duration::from_secs(120);
 
 fn main() {
-    // Initialize logging
-    tracing_subscriber::fmt()
-        .with_env_filter("hybrid_llm=debug,info")
-        .init();
+    // Initialize logging, exporting telemetry when the settings name a collector
+    // The settings are read again, with their warnings logged, when the app state is built
+    let startup_settings = settings::load_or_default(std::path::Path::new(settings::SETTINGS_FILE));
+    let _telemetry = common::telemetry::init("hybrid-llm-desktop", &startup_settings.telemetry, std::io::stdout);
 
     info!("🚀 Starting Hybrid LLM Platform Tauri app...");
 
+    // A second instance opened by a link hands it to the running one and exits here
+    tauri_plugin_deep_link::prepare("com.hybridllm.platform");
+
     tauri::Builder::default()
+        .system_tray(tray::build())
+        .on_system_tray_event(tray::handle_event)
+        // Closing the main window leaves the app running in the tray with its safety controls
+        // The security window really closes; it is reopened on demand
+        .on_window_event(|event| match event.event() {
+            tauri::WindowEvent::CloseRequested { api, .. } if event.window().label() == "main" => {
+                let _ = event.window().hide();
+                api.prevent_close();
+            }
+            // Clicking a notification brings the app forward; follow it to what it was about
+            tauri::WindowEvent::Focused(true) if event.window().label() == "main" => {
+                let app = event.window().app_handle();
+                if let Some(state) = app.try_state::<AppState>() {
+                    state.notifier.main_window_focused(&app);
+                }
+            }
+            _ => {}
+        })
         .setup(|app| {
             // Initialize app state
-            let state = AppState::new();
+            let state = AppState::new()?;
+            let sandbox_manager = Arc::clone(&state.sandbox_manager);
+            let security_engine = Arc::clone(&state.security_engine);
+            let audit = security_engine.audit();
+            let approval_engine = Arc::clone(&security_engine);
+            let mut audit_entries = audit.subscribe();
+            let mut approvals = security_engine.subscribe_approvals();
+            let mut alerts = security_engine.subscribe_alerts();
+            let notifier = Arc::clone(&state.notifier);
+            let websocket_events = Arc::clone(&state.websocket_events);
+            let panic_hotkey = state.settings.blocking_read().security.panic_hotkey().map(str::to_string);
+            let warm_per_template = state.settings.blocking_read().sandbox.warm_per_template;
+            let llm_pool = Arc::clone(&state.llm_pool);
+            let providers = state.settings.blocking_read().providers.clone();
+            let models_dir = state.settings.blocking_read().paths.models_dir.clone();
+            let sessions_dir = models::sessions_dir(&state.settings.blocking_read().paths.data_dir);
+            let kv_persistence = Arc::clone(&state.kv_persistence);
+            let profile = state.profiles.active().name;
+            let profiles = Arc::clone(&state.profiles);
+            let session = Arc::clone(&state.session);
             app.manage(state);
 
+            // Approvals the last session left undecided were denied when it closed
+            let interrupted_engine = Arc::clone(&security_engine);
+            let interrupted_session = Arc::clone(&session);
+            tokio::spawn(async move {
+                session::audit_interrupted_approvals(&interrupted_engine, &interrupted_session).await;
+            });
+
+            // Cloud providers with a key in the keyring or the environment and the models in the models directory
+            // are ready to use, documents are embedded with a local embedding model if there is one,
+            // the last session's LLMs are loaded again,
+            // then every LLM's health is checked now and then for `get_system_state`
+            let reload_session = Arc::clone(&session);
+            let app_handle = app.handle();
+            tokio::spawn(async move {
+                // Local models' KV caches only go to disk once the active profile is known not to be encrypted
+                app_handle.state::<AppState>().allow_kv_persistence().await;
+                keys::register_all(&*llm_pool.read().await, &profile, &providers).await;
+                models::register_all(&*llm_pool.read().await, &models_dir, &sessions_dir, &kv_persistence).await;
+                models::pick_embedder(&*llm_pool.read().await, &profiles);
+                session::reload_llms(&*llm_pool.read().await, &reload_session).await;
+                loop {
+                    llm_pool.read().await.health_check_all().await;
+                    tokio::time::sleep(HEALTH_CHECK_INTERVAL).await;
+                }
+            });
+
+            // hybridllm:// links from browsers and automation tools
+            if let Err(e) = deeplink::register(&app.handle()) {
+                error!("❌ Could not register the {}:// scheme: {}", deeplink::SCHEME, e);
+            }
+
+            // The panic path must not depend on finding the window first
+            if let Err(e) = panic::register_hotkey(&app.handle(), None, panic_hotkey.as_deref()) {
+                error!("{}", e);
+            }
+
+            // Reclaim expired sandboxes and forward sandbox events to the UI
+            let app_handle = app.handle();
+            let alert_notifier = Arc::clone(&notifier);
+            let sandbox_events = Arc::clone(&websocket_events);
+            tokio::spawn(async move {
+                let mut events = sandbox_manager.subscribe();
+                sandbox_manager.spawn_reaper(Duration::from_secs(30));
+                if warm_per_template > 0 {
+                    sandbox_manager.spawn_pool(PoolConfig { warm_per_template, ..Default::default() });
+                }
+
+                while let Ok(event) = events.recv().await {
+                    // Flagged samples and connections are written to the audit log
+                    let analysis = match &event {
+                        SandboxEvent::Usage { usage } => Some(security_engine.analyze_sandbox_usage(usage).await),
+                        SandboxEvent::NetworkConnection { sandbox_id, host, port, allowed: true } => {
+                            Some(security_engine.analyze_sandbox_connection(*sandbox_id, host, *port).await)
+                        }
+                        _ => None,
+                    };
+                    if let Some(analysis) = analysis {
+                        alert_notifier.security_alert(&app_handle, &analysis);
+                    }
+                    if let Some(record) = event.audit_record() {
+                        audit
+                            .log(record.llm_id, record.action, record.details, record.approved, record.reason)
+                            .await;
+                    }
+                    let _ = app_handle.emit_all("sandbox-event", &event);
+                    sandbox_events.publish(WebSocketMessage::Sandbox { event });
+                }
+            });
+
+            // Jailbreak scaffolding found in constructed prompts; the audit log already has the details
+            let app_handle = app.handle();
+            let prompt_notifier = Arc::clone(&notifier);
+            tokio::spawn(async move {
+                loop {
+                    match alerts.recv().await {
+                        Ok(analysis) => prompt_notifier.security_alert(&app_handle, &analysis),
+                        Err(tokio::sync::broadcast::error::RecvError::Lagged(_)) => continue,
+                        Err(tokio::sync::broadcast::error::RecvError::Closed) => break,
+                    }
+                }
+            });
+
+            // Tell the UI about approval requests and their outcomes, including timeouts,
+            // and the user too while the app is in the background; the session keeps what is still pending
+            let app_handle = app.handle();
+            tokio::spawn(async move {
+                while let Ok(event) = approvals.recv().await {
+                    match &event {
+                        ApprovalEvent::Requested { approval } => notifier.approval_requested(&app_handle, approval),
+                        ApprovalEvent::Resolved { id, .. } => notifier.approval_resolved(*id),
+                    }
+                    let _ = app_handle.emit_all(commands::APPROVAL_EVENT, &event);
+
+                    let pending = approval_engine.pending_approvals().await;
+                    if let Err(e) = session.update(|session| session.pending_approvals = pending) {
+                        warn!("⚠️  Could not save the session: {}", e);
+                    }
+                }
+            });
+
+            // Stream audit entries to the security window and WebSocket subscribers as they are logged
+            let app_handle = app.handle();
+            tokio::spawn(async move {
+                loop {
+                    match audit_entries.recv().await {
+                        Ok(entry) => {
+                            let _ = app_handle.emit_all(security_window::AUDIT_EVENT, &entry);
+                            websocket_events.publish(WebSocketMessage::AuditLogEntry { entry });
+                        }
+                        Err(tokio::sync::broadcast::error::RecvError::Lagged(_)) => continue,
+                        Err(tokio::sync::broadcast::error::RecvError::Closed) => break,
+                    }
+                }
+            });
+
+            tray::spawn_refresher(app.handle());
+            resources::spawn_reporter(app.handle());
+            backup::spawn_scheduler(app.handle());
+            recrawl::spawn_scheduler(app.handle());
+
             // Start WebSocket server for real-time updates
             let app_handle = app.handle();
             tokio::spawn(async move {
@@ -32,6 +218,22 @@ fn main() {
                 }
             });
 
+            // Pages and selections sent from the companion browser extension
+            let app_handle = app.handle();
+            tokio::spawn(async move {
+                if let Err(e) = browser_bridge::start_server(app_handle).await {
+                    error!("❌ Browser extension bridge error: {}", e);
+                }
+            });
+
+            // OpenAI-style clients such as editors and scripts, when turned on in the settings
+            let app_handle = app.handle();
+            tokio::spawn(async move {
+                if let Err(e) = openai_api::start_server(app_handle).await {
+                    error!("❌ OpenAI-compatible API error: {}", e);
+                }
+            });
+
             info!("✅ Tauri app initialized");
             Ok(())
         })
@@ -40,30 +242,144 @@ fn main() {
             commands::get_system_state,
             commands::trigger_lockdown,
             commands::release_lockdown,
+            commands::enter_read_only,
+            commands::exit_read_only,
+            commands::get_websocket_session,
+            commands::get_resource_usage,
+            commands::get_hardware_info,
+            commands::take_pending_deep_links,
+            commands::get_session,
+            commands::set_open_conversations,
+            commands::get_browser_bridge,
+            commands::reset_browser_bridge_token,
+            commands::get_openai_api,
+            commands::reset_openai_api_key,
+            commands::get_settings,
+            commands::update_settings,
 
             // LLM commands
             commands::get_llms,
             commands::load_llm,
             commands::unload_llm,
-            commands::send_message,
+            commands::send_message_stream,
+            commands::compare_message,
+            commands::cancel_generation,
+            commands::run_benchmark,
+            commands::list_benchmarks,
+            commands::run_evaluation,
+            commands::list_evaluations,
+            commands::get_usage_summary,
+            commands::pause_cloud_providers,
+            commands::set_offline_mode,
+
+            // Provider key commands
+            commands::set_provider_key,
+            commands::test_provider_key,
+            commands::remove_provider_key,
+
+            // Model download commands
+            commands::search_models,
+            commands::download_model,
+            commands::cancel_download,
+            commands::list_local_models,
+            commands::inspect_model,
+
+            // Voice input commands
+            commands::start_voice_input,
+            commands::stop_voice_input,
+            commands::cancel_voice_input,
+
+            // Screenshot commands
+            commands::list_capture_windows,
+            commands::capture_screenshot,
+
+            // Conversation commands
+            commands::create_conversation,
+            commands::list_conversations,
+            commands::get_conversation,
+            commands::get_conversation_usage,
+            commands::rename_conversation,
+            commands::delete_conversation,
+            commands::restore_conversation,
+            commands::export_conversation,
+            commands::list_prompt_templates,
+            commands::get_prompt_template,
+            commands::get_prompt_template_history,
+            commands::create_prompt_template,
+            commands::update_prompt_template,
+            commands::delete_prompt_template,
 
             // Document commands
             commands::upload_document,
+            commands::upload_archive,
+            commands::upload_paths,
             commands::get_documents,
             commands::delete_document,
+            commands::attach_document,
+            commands::detach_document,
+            commands::recrawl_sources,
+
+            // Storage commands
+            commands::get_storage_usage,
+            commands::list_files,
+            commands::find_files_by_hash,
+            commands::create_share_link,
+            commands::list_rag_versions,
+            commands::diff_rag_versions,
+            commands::list_trash,
+            commands::restore_from_trash,
+            commands::empty_trash,
 
             // Permission commands
             commands::get_permissions,
             commands::update_permissions,
 
+            // Profile commands
+            commands::list_profiles,
+            commands::create_profile,
+            commands::switch_profile,
+            commands::delete_profile,
+            commands::get_encryption_status,
+            commands::enable_encryption,
+            commands::create_backup,
+            commands::list_backups,
+            commands::restore_backup,
+            commands::list_plugins,
+
+            // Approval commands
+            commands::list_pending_approvals,
+            commands::simulate_plan,
+            commands::approve_request,
+            commands::deny_request,
+
             // Audit log commands
             commands::get_audit_log,
 
+            // Security window commands
+            commands::open_security_window,
+            commands::close_security_window,
+
             // Sandbox commands
             commands::create_sandbox,
             commands::execute_in_sandbox,
+            commands::start_kernel,
+            commands::execute_cell,
+            commands::reset_kernel,
+            commands::shutdown_kernel,
+            commands::list_kernels,
             commands::get_sandbox_files,
+            commands::get_sandbox_diff,
+            commands::get_sandbox_usage,
             commands::approve_transfer,
+            commands::approve_port_forward,
+            commands::list_port_forwards,
+            commands::close_port_forward,
+            commands::list_sandbox_templates,
+            commands::list_snapshots,
+            commands::delete_snapshot,
+            commands::list_volumes,
+            commands::create_volume,
+            commands::delete_volume,
         ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
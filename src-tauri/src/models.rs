@@ -0,0 +1,398 @@
+use chrono::{DateTime, Utc};
+use common::errors::{Result, HybridLLMError};
+use common::types::Capability;
+use futures_util::StreamExt;
+use llama_cpp_provider::{gguf, LlamaCppProviderBuilder};
+use llm_pool::LLMPool;
+use reqwest::{header, StatusCode};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::AtomicBool;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::io::AsyncWriteExt;
+use tracing::{info, warn};
+use uuid::Uuid;
+
+use crate::profiles::Profiles;
+
+const HUGGING_FACE: &str = "https://huggingface.co";
+
+/// Directory in the data directory for the KV caches of local models
+pub const SESSIONS_DIR: &str = "llm-sessions";
+
+/// Minimum time between progress events of one download
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(250);
+
+/// A Hugging Face repository with GGUF files
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ModelSearchResult {
+    pub repo_id: String,
+    pub downloads: u64,
+    pub likes: u64,
+    /// GGUF files in the repository, e.g. one per quantization
+    pub files: Vec<String>,
+}
+
+/// A model file in the models directory
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LocalModel {
+    pub filename: String,
+    pub path: PathBuf,
+    pub size_bytes: u64,
+    pub modified: DateTime<Utc>,
+    /// GGUF format version from the file header; `None` if it isn't a GGUF file
+    pub gguf_version: Option<u32>,
+}
+
+#[derive(Deserialize)]
+struct HubModel {
+    id: String,
+    #[serde(default)]
+    downloads: u64,
+    #[serde(default)]
+    likes: u64,
+    #[serde(default)]
+    siblings: Vec<HubFile>,
+}
+
+#[derive(Deserialize)]
+struct HubFile {
+    rfilename: String,
+    /// Set for files kept in Git LFS, which GGUF models are
+    #[serde(default)]
+    lfs: Option<HubLfs>,
+}
+
+#[derive(Deserialize)]
+struct HubLfs {
+    sha256: String,
+}
+
+/// Tauri event carrying model download progress
+pub const MODEL_DOWNLOAD_EVENT: &str = "model-download";
+
+/// Progress of one model download, emitted as `model-download` and sent on the `models` WebSocket topic
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ModelDownloadEvent {
+    Progress {
+        download_id: Uuid,
+        repo_id: String,
+        filename: String,
+        downloaded_bytes: u64,
+        total_bytes: Option<u64>,
+    },
+    /// The model is in the models directory and registered with the pool as `llm_id`
+    Done { download_id: Uuid, repo_id: String, filename: String, model: LocalModel, llm_id: String },
+    Error { download_id: Uuid, repo_id: String, filename: String, message: String },
+    Cancelled { download_id: Uuid, repo_id: String, filename: String },
+}
+
+fn network_err(e: reqwest::Error) -> HybridLLMError {
+    HybridLLMError::NetworkError(format!("Hugging Face: {}", e))
+}
+
+/// Search the Hugging Face Hub for repositories with GGUF models, most downloaded first
+pub async fn search(query: &str, limit: usize) -> Result<Vec<ModelSearchResult>> {
+    let limit = limit.clamp(1, 100).to_string();
+    let models: Vec<HubModel> = reqwest::Client::new()
+        .get(format!("{}/api/models", HUGGING_FACE))
+        .query(&[
+            ("search", query),
+            ("filter", "gguf"),
+            ("sort", "downloads"),
+            ("direction", "-1"),
+            ("full", "true"),
+            ("limit", limit.as_str()),
+        ])
+        .send()
+        .await
+        .and_then(|r| r.error_for_status())
+        .map_err(network_err)?
+        .json()
+        .await
+        .map_err(network_err)?;
+
+    Ok(models
+        .into_iter()
+        .map(|model| ModelSearchResult {
+            repo_id: model.id,
+            downloads: model.downloads,
+            likes: model.likes,
+            files: model
+                .siblings
+                .into_iter()
+                .map(|file| file.rfilename)
+                .filter(|name| name.to_lowercase().ends_with(".gguf"))
+                .collect(),
+        })
+        .filter(|result| !result.files.is_empty())
+        .collect())
+}
+
+/// Where `filename` from a repository is saved in `models_dir`
+/// Repositories keep quantizations in subdirectories, so only the file name is used
+pub fn destination(models_dir: &Path, repo_id: &str, filename: &str) -> Result<PathBuf> {
+    let valid_part = |part: &str| {
+        !part.is_empty()
+            && !part.starts_with('.')
+            && part.chars().all(|c| c.is_ascii_alphanumeric() || "-_.".contains(c))
+    };
+    if repo_id.split('/').count() != 2 || !repo_id.split('/').all(valid_part) {
+        return Err(HybridLLMError::InvalidRequest(format!("Invalid model repository: {}", repo_id)));
+    }
+    if filename.split('/').any(|part| part.is_empty() || part == "..") {
+        return Err(HybridLLMError::SecurityViolation(format!("Invalid model path: {}", filename)));
+    }
+
+    let name = Path::new(filename)
+        .file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .filter(|n| n.to_lowercase().ends_with(".gguf") && !n.starts_with('.'))
+        .ok_or_else(|| HybridLLMError::InvalidRequest(format!("Not a GGUF model file: {}", filename)))?;
+
+    Ok(models_dir.join(name))
+}
+
+/// A model file directly in `models_dir`, as named by `list_local`
+pub fn local_path(models_dir: &Path, filename: &str) -> Result<PathBuf> {
+    let name = Path::new(filename);
+    if name.file_name() != Some(name.as_os_str()) || filename.starts_with('.') {
+        return Err(HybridLLMError::SecurityViolation(format!("Invalid model path: {}", filename)));
+    }
+    if !filename.to_lowercase().ends_with(".gguf") {
+        return Err(HybridLLMError::InvalidRequest(format!("Not a GGUF model file: {}", filename)));
+    }
+    Ok(models_dir.join(name))
+}
+
+/// Where the KV caches of local models' conversations are saved, one directory per model
+pub fn sessions_dir(data_dir: &Path) -> PathBuf {
+    data_dir.join(SESSIONS_DIR)
+}
+
+/// The partial file a download writes to until it completes
+pub fn partial_path(destination: &Path) -> PathBuf {
+    let mut name = destination.file_name().unwrap_or_default().to_os_string();
+    name.push(".part");
+    destination.with_file_name(name)
+}
+
+/// SHA-256 Hugging Face lists for a file of a repository; `None` for files outside Git LFS
+async fn published_sha256(client: &reqwest::Client, repo_id: &str, filename: &str) -> Result<Option<String>> {
+    let model: HubModel = client
+        .get(format!("{}/api/models/{}", HUGGING_FACE, repo_id))
+        .query(&[("blobs", "true")])
+        .send()
+        .await
+        .and_then(|r| r.error_for_status())
+        .map_err(network_err)?
+        .json()
+        .await
+        .map_err(network_err)?;
+    let file = model.siblings.into_iter().find(|file| file.rfilename == filename);
+    Ok(file.and_then(|file| file.lfs).map(|lfs| lfs.sha256.to_lowercase()))
+}
+
+/// SHA-256 of a file, in hex
+async fn file_sha256(path: &Path) -> Result<String> {
+    let path = path.to_path_buf();
+    tokio::task::spawn_blocking(move || {
+        let mut file = std::fs::File::open(&path)?;
+        let mut hasher = Sha256::new();
+        std::io::copy(&mut file, &mut hasher)?;
+        Ok::<_, std::io::Error>(format!("{:x}", hasher.finalize()))
+    })
+    .await
+    .map_err(|e| HybridLLMError::FileSystemError(e.to_string()))?
+    .map_err(|e| HybridLLMError::FileSystemError(e.to_string()))
+}
+
+/// Download one file of a repository to `destination`, reporting `(downloaded, total)` bytes
+/// A partial file left by an interrupted download is resumed where it stopped
+/// The file only appears under its name once complete, recognised as GGUF and matching the SHA-256
+/// Hugging Face publishes; a file failing either check is deleted
+pub async fn download(
+    repo_id: &str,
+    filename: &str,
+    destination: &Path,
+    mut on_progress: impl FnMut(u64, Option<u64>),
+) -> Result<u64> {
+    let fs_err = |e: std::io::Error| HybridLLMError::FileSystemError(e.to_string());
+    let partial = partial_path(destination);
+    if let Some(parent) = destination.parent() {
+        tokio::fs::create_dir_all(parent).await.map_err(fs_err)?;
+    }
+
+    let client = reqwest::Client::new();
+    let expected_sha256 = published_sha256(&client, repo_id, filename).await?;
+    let resume_from = tokio::fs::metadata(&partial).await.map_or(0, |metadata| metadata.len());
+
+    info!("⬇️  Downloading model {}/{}", repo_id, filename);
+    let mut request = client.get(format!("{}/{}/resolve/main/{}", HUGGING_FACE, repo_id, filename));
+    if resume_from > 0 {
+        request = request.header(header::RANGE, format!("bytes={}-", resume_from));
+    }
+    let response = request.send().await.map_err(network_err)?;
+
+    // Asking for the bytes past the end means the partial file is already whole
+    let (mut file, mut downloaded, response) = match response.status() {
+        StatusCode::RANGE_NOT_SATISFIABLE if resume_from > 0 => (None, resume_from, None),
+        StatusCode::PARTIAL_CONTENT if resume_from > 0 => {
+            info!("⏯️  Resuming {} at {} bytes", filename, resume_from);
+            let file = tokio::fs::OpenOptions::new().append(true).open(&partial).await.map_err(fs_err)?;
+            (Some(file), resume_from, Some(response))
+        }
+        _ => {
+            let response = response.error_for_status().map_err(network_err)?;
+            (Some(tokio::fs::File::create(&partial).await.map_err(fs_err)?), 0, Some(response))
+        }
+    };
+    let total = response.as_ref().map_or(Some(downloaded), |response| response.content_length().map(|len| downloaded + len));
+
+    let mut last_progress = Instant::now();
+    on_progress(downloaded, total);
+    if let (Some(file), Some(response)) = (file.as_mut(), response) {
+        let mut body = response.bytes_stream();
+        while let Some(chunk) = body.next().await {
+            let chunk = chunk.map_err(network_err)?;
+            file.write_all(&chunk).await.map_err(fs_err)?;
+            downloaded += chunk.len() as u64;
+            if last_progress.elapsed() >= PROGRESS_INTERVAL {
+                on_progress(downloaded, total);
+                last_progress = Instant::now();
+            }
+        }
+        file.flush().await.map_err(fs_err)?;
+    }
+    drop(file);
+    on_progress(downloaded, total);
+
+    if gguf_version(&partial).await.is_none() {
+        let _ = tokio::fs::remove_file(&partial).await;
+        return Err(HybridLLMError::InvalidRequest(format!("{} is not a GGUF model", filename)));
+    }
+    if let Some(expected) = expected_sha256 {
+        let actual = file_sha256(&partial).await?;
+        if actual != expected {
+            let _ = tokio::fs::remove_file(&partial).await;
+            return Err(HybridLLMError::SecurityViolation(format!(
+                "{} failed verification: its SHA-256 is {}, Hugging Face lists {}",
+                filename, actual, expected
+            )));
+        }
+        info!("🔏 Verified {} against its published SHA-256", filename);
+    } else {
+        warn!("⚠️  Hugging Face lists no SHA-256 for {}, only its GGUF header was checked", filename);
+    }
+    tokio::fs::rename(&partial, destination).await.map_err(fs_err)?;
+
+    info!("✅ Downloaded model {:?} ({} bytes)", destination, downloaded);
+    Ok(downloaded)
+}
+
+/// GGUF format version from a file's header, if it starts with the GGUF magic
+async fn gguf_version(path: &Path) -> Option<u32> {
+    use tokio::io::AsyncReadExt;
+
+    let mut header = [0u8; 8];
+    let mut file = tokio::fs::File::open(path).await.ok()?;
+    file.read_exact(&mut header).await.ok()?;
+    (&header[..4] == b"GGUF").then(|| u32::from_le_bytes([header[4], header[5], header[6], header[7]]))
+}
+
+/// Model files in `models_dir`, newest first; unfinished downloads are left out
+pub async fn list_local(models_dir: &Path) -> Result<Vec<LocalModel>> {
+    let fs_err = |e: std::io::Error| HybridLLMError::FileSystemError(e.to_string());
+    let mut entries = match tokio::fs::read_dir(models_dir).await {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(fs_err(e)),
+    };
+
+    let mut models = Vec::new();
+    while let Some(entry) = entries.next_entry().await.map_err(fs_err)? {
+        let path = entry.path();
+        let filename = entry.file_name().to_string_lossy().into_owned();
+        let metadata = entry.metadata().await.map_err(fs_err)?;
+        if !metadata.is_file() || !filename.to_lowercase().ends_with(".gguf") {
+            continue;
+        }
+        models.push(LocalModel {
+            gguf_version: gguf_version(&path).await,
+            filename,
+            path,
+            size_bytes: metadata.len(),
+            modified: metadata.modified().map(DateTime::<Utc>::from).unwrap_or_else(|_| Utc::now()),
+        });
+    }
+
+    models.sort_by_key(|model| std::cmp::Reverse(model.modified));
+    Ok(models)
+}
+
+/// The LLM ID a local model is registered under: its file name without `.gguf`, lowercased
+pub fn llm_id(filename: &str) -> String {
+    let name = Path::new(filename).file_stem().unwrap_or_default().to_string_lossy();
+    name.to_lowercase()
+}
+
+/// Register a model from the models directory with the pool as a general-purpose local LLM, or as an
+/// embedding model if its header says it is one, saving the KV caches of its conversations in `sessions_dir`
+/// when it is unloaded, as long as `persistence` is on
+/// Returns its LLM ID; a model registered before is left as it is
+pub fn register(
+    pool: &LLMPool,
+    model: &LocalModel,
+    sessions_dir: &Path,
+    persistence: &Arc<AtomicBool>,
+) -> Result<String> {
+    let id = llm_id(&model.filename);
+    if pool.get(&id).is_none() {
+        let embedding_only = gguf::inspect(&model.path).is_ok_and(|info| info.embedding_only);
+        let capability = if embedding_only { Capability::Embedding } else { Capability::General };
+        let provider = LlamaCppProviderBuilder::new()
+            .model_id(id.clone())
+            .model_path(model.path.clone())
+            .capability(capability)
+            .session_dir(sessions_dir)
+            .session_persistence(Arc::clone(persistence))
+            .build()?;
+        pool.register(Box::new(provider))?;
+    }
+    Ok(id)
+}
+
+/// Register every GGUF model in `models_dir`, e.g. at startup
+pub async fn register_all(pool: &LLMPool, models_dir: &Path, sessions_dir: &Path, persistence: &Arc<AtomicBool>) {
+    let models = match list_local(models_dir).await {
+        Ok(models) => models,
+        Err(e) => {
+            warn!("⚠️  Could not list the models in {:?}: {}", models_dir, e);
+            return;
+        }
+    };
+    for model in models.iter().filter(|model| model.gguf_version.is_some()) {
+        if let Err(e) = register(pool, model, sessions_dir, persistence) {
+            warn!("⚠️  Could not register {}: {}", model.filename, e);
+        }
+    }
+}
+
+/// Embed documents with a registered local embedding model, if there is one, instead of placeholder vectors
+/// One picked before is kept, as vectors of different models can't be compared
+pub fn pick_embedder(pool: &LLMPool, profiles: &Profiles) {
+    if profiles.has_embedder() {
+        return;
+    }
+    let embedder = pool
+        .find_by_capability(&Capability::Embedding)
+        .into_iter()
+        .find(|provider| !provider.instance().provider.is_cloud());
+    if let Some(embedder) = embedder {
+        info!("🧮 Embedding documents with {}", embedder.instance().id);
+        profiles.set_embedder(embedder);
+    }
+}
@@ -0,0 +1,122 @@
+use common::{messages::PermissionType, SecurityAnalysis};
+use security_engine::{ApprovalRequest, PendingApproval};
+use serde::Serialize;
+use std::sync::Mutex;
+use tauri::{api::notification::Notification, AppHandle, Manager};
+use tracing::{error, warn};
+use uuid::Uuid;
+
+use crate::security_window;
+
+/// Tauri event telling the main window which screen a notification was about
+pub const NOTIFICATION_OPEN_EVENT: &str = "notification-open";
+
+/// Where a notification leads
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "screen", rename_all = "snake_case")]
+pub enum NotificationTarget {
+    /// The security window, which lists pending approvals
+    Approval { approval_id: Uuid },
+    /// The audit log, where the alert was recorded
+    Audit,
+}
+
+/// Native notifications for things that need the user while the app is in the background
+/// Tauri 1 doesn't report clicks on notifications, but clicking one brings the app forward,
+/// so the next focus of the main window goes to the last notification's target
+#[derive(Default)]
+pub struct Notifier {
+    target: Mutex<Option<NotificationTarget>>,
+}
+
+impl Notifier {
+    /// Show a notification unless one of the app's windows has focus
+    fn notify(&self, app: &AppHandle, title: &str, body: &str, target: NotificationTarget) {
+        if app.windows().values().any(|window| window.is_focused().unwrap_or(false)) {
+            return;
+        }
+
+        let shown = Notification::new(&app.config().tauri.bundle.identifier)
+            .title(title)
+            .body(body)
+            .show();
+        match shown {
+            Ok(()) => *self.target.lock().unwrap() = Some(target),
+            Err(e) => warn!("⚠️  Could not show notification: {}", e),
+        }
+    }
+
+    pub fn approval_requested(&self, app: &AppHandle, approval: &PendingApproval) {
+        self.notify(
+            app,
+            "Approval needed",
+            &describe_approval(approval),
+            NotificationTarget::Approval { approval_id: approval.id },
+        );
+    }
+
+    /// A decided approval no longer needs the user, so focusing the app stays where it is
+    pub fn approval_resolved(&self, approval_id: Uuid) {
+        let mut target = self.target.lock().unwrap();
+        if *target == Some(NotificationTarget::Approval { approval_id }) {
+            *target = None;
+        }
+    }
+
+    /// Notify about a flagged sandbox sample or connection, or a jailbreak pattern in a prompt
+    pub fn security_alert(&self, app: &AppHandle, analysis: &SecurityAnalysis) {
+        if analysis.issues.is_empty() {
+            return;
+        }
+        self.notify(
+            app,
+            &format!("⚠️ Security alert ({:?} risk)", analysis.risk_level),
+            &analysis.issues.join("\n"),
+            NotificationTarget::Audit,
+        );
+    }
+
+    /// Send the user to the last notification's target when the main window gains focus
+    pub fn main_window_focused(&self, app: &AppHandle) {
+        let Some(target) = self.target.lock().unwrap().take() else {
+            return;
+        };
+
+        if let NotificationTarget::Approval { .. } = target {
+            // Building a window on the event loop thread deadlocks on Windows
+            let app = app.clone();
+            tauri::async_runtime::spawn(async move {
+                if let Err(e) = security_window::open(&app) {
+                    error!("❌ Could not open the security window: {}", e);
+                }
+            });
+        }
+        let _ = app.emit_all(NOTIFICATION_OPEN_EVENT, target);
+    }
+}
+
+fn describe_approval(approval: &PendingApproval) -> String {
+    let llm_id = &approval.llm_id;
+    match &approval.request {
+        ApprovalRequest::Permission(request) => {
+            let permission = match &request.permission {
+                PermissionType::FileRead { path } => format!("to read {}", path),
+                PermissionType::FileWrite { path } => format!("to write {}", path),
+                PermissionType::FileExecute { path } => format!("to execute {}", path),
+                PermissionType::Command { command } => format!("to run `{}`", command),
+                PermissionType::NetworkAccess { url } => format!("to access {}", url),
+                PermissionType::ResourceIncrease { resource, amount } => format!("{} more {}", amount, resource),
+            };
+            format!("{} asks {}", llm_id, permission)
+        }
+        ApprovalRequest::ArtifactTransfer(transfer) => {
+            format!("{} wants to copy {} to {}", llm_id, transfer.file_path, transfer.destination)
+        }
+        ApprovalRequest::PortForward(request) => format!(
+            "{} wants to forward port {} for {} min",
+            llm_id,
+            request.sandbox_port,
+            request.duration_secs.div_ceil(60)
+        ),
+    }
+}
@@ -0,0 +1,471 @@
+use base64::Engine;
+use common::{
+    errors::{ErrorCode, HybridLLMError, Result},
+    types::{Capability, LockdownState, MessageRole, RequestContext},
+    CompletionMessage, CompletionRequest, ImageInput, SecurityEngine, StreamChunk, Usage,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::time::Duration;
+use tauri::{AppHandle, Manager};
+use tokio::io::AsyncWriteExt;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::mpsc;
+use tracing::{debug, info, warn};
+use uuid::Uuid;
+
+use crate::browser_bridge::{read_request, HttpRequest, Reply};
+use crate::keys::LocalToken;
+use crate::settings::Settings;
+use crate::state::AppState;
+use crate::websocket::http_response;
+
+/// Keyring entry of the API key clients use, so they keep working across restarts
+const TOKEN_ENTRY: &str = "openai-api";
+
+/// Model names that mean "whichever LLM the settings pick"
+const DEFAULT_MODELS: &[&str] = &["", "default", "auto"];
+
+/// How long a non-streaming request may take before the generation is stopped
+const ANSWER_TIMEOUT: Duration = Duration::from_secs(300);
+
+/// `POST /v1/chat/completions`, as far as the platform supports it
+#[derive(Debug, Deserialize)]
+pub struct ChatRequest {
+    /// An LLM id, or "default"/"auto" for the default LLM
+    #[serde(default)]
+    pub model: String,
+    pub messages: Vec<ChatMessage>,
+    #[serde(default)]
+    pub stream: bool,
+    pub stream_options: Option<StreamOptions>,
+    pub max_tokens: Option<u32>,
+    /// The newer name of `max_tokens`
+    pub max_completion_tokens: Option<u32>,
+    pub temperature: Option<f32>,
+    pub top_p: Option<f32>,
+    pub stop: Option<Stop>,
+    pub seed: Option<u64>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct StreamOptions {
+    #[serde(default)]
+    pub include_usage: bool,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+pub enum Stop {
+    One(String),
+    Many(Vec<String>),
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ChatMessage {
+    pub role: String,
+    pub content: Option<ChatContent>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+pub enum ChatContent {
+    Text(String),
+    Parts(Vec<ContentPart>),
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ContentPart {
+    Text { text: String },
+    ImageUrl { image_url: ImageUrl },
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ImageUrl {
+    /// Only `data:` URIs; the server fetches nothing on a client's behalf
+    pub url: String,
+}
+
+/// Where clients reach the API and the key they must send as `Authorization: Bearer`
+#[derive(Debug, Clone, Serialize)]
+pub struct OpenAiApiInfo {
+    pub enabled: bool,
+    /// Base URL to give clients, e.g. "http://127.0.0.1:3032/v1"
+    pub base_url: String,
+    pub api_key: String,
+}
+
+/// API key of the OpenAI-compatible server; empty until the app has started
+pub struct OpenAiApi {
+    token: LocalToken,
+}
+
+impl Default for OpenAiApi {
+    fn default() -> Self {
+        Self { token: LocalToken::new(TOKEN_ENTRY, "OpenAI-compatible API") }
+    }
+}
+
+impl OpenAiApi {
+    pub fn info(&self, settings: &Settings) -> OpenAiApiInfo {
+        OpenAiApiInfo {
+            enabled: settings.openai_api.enabled,
+            base_url: format!("http://127.0.0.1:{}/v1", settings.openai_api.port),
+            api_key: self.token.get(),
+        }
+    }
+
+    /// Replace the API key; clients configured with the old one are refused from now on
+    pub async fn reset_token(&self, settings: &Settings) -> Result<OpenAiApiInfo> {
+        self.token.reset().await?;
+        Ok(self.info(settings))
+    }
+}
+
+/// Serve OpenAI-style clients until the listener fails, if the settings turn the API on
+pub async fn start_server(app: AppHandle) -> anyhow::Result<()> {
+    let state = app.state::<AppState>();
+    // Loaded even while the API is off, so the key can be set up in clients before turning it on
+    state.openai_api.token.load().await;
+    let settings = state.settings.read().await.openai_api.clone();
+    if !settings.enabled {
+        debug!("OpenAI-compatible API is turned off");
+        return Ok(());
+    }
+
+    let listener = TcpListener::bind(("127.0.0.1", settings.port)).await?;
+    info!("🔌 OpenAI-compatible API listening on http://127.0.0.1:{}/v1", settings.port);
+
+    while let Ok((stream, _)) = listener.accept().await {
+        let app_handle = app.clone();
+        tokio::spawn(async move { serve(stream, &app_handle).await });
+    }
+
+    Ok(())
+}
+
+/// A started generation, streamed to the client as server-sent events
+struct Generation {
+    id: String,
+    llm_id: String,
+    include_usage: bool,
+    /// Read before the response starts, so failures to start are still proper errors
+    first: Option<StreamChunk>,
+    chunks: mpsc::Receiver<Result<StreamChunk>>,
+}
+
+enum Answer {
+    Reply(Reply),
+    Stream(Generation),
+}
+
+async fn serve(mut stream: TcpStream, app: &AppHandle) {
+    let answer = match read_request(&mut stream).await {
+        Ok(request) => route(request, app).await,
+        Err(reply) => Answer::Reply(openai_error(reply.status, reply.body["error"].as_str().unwrap_or_default(), None)),
+    };
+
+    let written = match answer {
+        Answer::Reply(reply) => {
+            let body = serde_json::to_vec(&reply.body).unwrap_or_default();
+            stream.write_all(&http_response(reply.status, "application/json", &[], &body)).await
+        }
+        Answer::Stream(generation) => send_events(&mut stream, generation).await,
+    };
+    // Dropping the generation when the client goes away stops it in the pool
+    if let Err(e) = written {
+        debug!("OpenAI API client went away: {}", e);
+    }
+    let _ = stream.shutdown().await;
+}
+
+async fn route(request: HttpRequest, app: &AppHandle) -> Answer {
+    let state = app.state::<AppState>();
+    // Any local program or web page can reach localhost, so nothing is answered without the key
+    if !state.openai_api.token.authorized(request.bearer.as_deref()) {
+        return Answer::Reply(openai_error("401 Unauthorized", "Missing or invalid API key", Some("invalid_api_key")));
+    }
+
+    match (request.method.as_str(), request.path.as_str()) {
+        ("GET", "/v1/models") => {
+            let created = chrono::Utc::now().timestamp();
+            let models: Vec<Value> = state.llm_pool
+                .read()
+                .await
+                .get_all_ids()
+                .into_iter()
+                .map(|id| json!({ "id": id, "object": "model", "created": created, "owned_by": "hybrid-llm" }))
+                .collect();
+            Answer::Reply(Reply::ok(json!({ "object": "list", "data": models })))
+        }
+        ("POST", "/v1/chat/completions") => match serde_json::from_slice::<ChatRequest>(&request.body) {
+            Ok(chat) => handle_chat(chat, &state).await,
+            Err(e) => Answer::Reply(openai_error("400 Bad Request", format!("Invalid chat completion request: {}", e), None)),
+        },
+        (_, "/v1/models" | "/v1/chat/completions") => {
+            Answer::Reply(openai_error("405 Method Not Allowed", "Method not allowed", None))
+        }
+        (_, path) => Answer::Reply(openai_error("404 Not Found", format!("Unknown endpoint {}", path), None)),
+    }
+}
+
+/// Start or run a chat completion, recording it in the audit log whether it is served or refused
+async fn handle_chat(chat: ChatRequest, state: &AppState) -> Answer {
+    let request_id = Uuid::new_v4();
+    let settings = state.settings.read().await.clone();
+    let checked = check_chat(&chat, &settings, state).await;
+    let llm_id = checked.as_ref().ok().map(|(llm_id, _)| llm_id.clone());
+    state.security_engine
+        .audit()
+        .log(
+            llm_id.clone(),
+            "OpenAI API request".to_string(),
+            json!({ "request_id": request_id, "model": chat.model, "messages": chat.messages.len(), "stream": chat.stream }),
+            checked.is_ok(),
+            checked.as_ref().err().map(|e| e.to_string()),
+        )
+        .await;
+    let (llm_id, request) = match checked {
+        Ok(checked) => checked,
+        Err(e) => {
+            warn!("🔌 Refused OpenAI API request for {:?}: {}", chat.model, e);
+            return Answer::Reply(error_reply(&e));
+        }
+    };
+
+    info!("🔌 OpenAI API request {} for {}", request_id, llm_id);
+    let request = request.with_context(RequestContext::user().with_trace_id(request_id).with_llm(&llm_id));
+    let id = format!("chatcmpl-{}", request_id.simple());
+    let started = state.llm_pool.read().await.complete_stream(request_id, &llm_id, request).await;
+    let mut chunks = match started {
+        Ok(chunks) => chunks,
+        Err(e) => return Answer::Reply(error_reply(&e)),
+    };
+
+    if chat.stream {
+        let first = match chunks.recv().await {
+            Some(Ok(chunk)) => Some(chunk),
+            Some(Err(e)) => return Answer::Reply(error_reply(&e)),
+            None => None,
+        };
+        let include_usage = chat.stream_options.is_some_and(|options| options.include_usage);
+        return Answer::Stream(Generation { id, llm_id, include_usage, first, chunks });
+    }
+
+    let generate = async {
+        let mut content = String::new();
+        let mut usage = Usage::default();
+        while let Some(chunk) = chunks.recv().await {
+            match chunk? {
+                StreamChunk::Text(text) => content.push_str(&text),
+                StreamChunk::Usage(reported) => usage = reported,
+            }
+        }
+        Ok::<_, HybridLLMError>((content, usage))
+    };
+    // Dropping the stream on timeout cancels it in the pool
+    let (content, usage) = match tokio::time::timeout(ANSWER_TIMEOUT, generate).await {
+        Ok(Ok(answer)) => answer,
+        Ok(Err(e)) => return Answer::Reply(error_reply(&e)),
+        Err(_) => {
+            let e = HybridLLMError::Timeout(format!("No answer within {} seconds", ANSWER_TIMEOUT.as_secs()));
+            return Answer::Reply(error_reply(&e));
+        }
+    };
+
+    Answer::Reply(Reply::ok(json!({
+        "id": id,
+        "object": "chat.completion",
+        "created": chrono::Utc::now().timestamp(),
+        "model": llm_id,
+        "choices": [{
+            "index": 0,
+            "message": { "role": "assistant", "content": content },
+            "finish_reason": "stop",
+        }],
+        "usage": usage_json(&usage),
+    })))
+}
+
+/// The LLM to use and the request to send it, unless the request can't be served now
+async fn check_chat(chat: &ChatRequest, settings: &Settings, state: &AppState) -> Result<(String, CompletionRequest)> {
+    let lockdown = state.security_engine.lockdown_state().await.unwrap_or(LockdownState::Locked);
+    if lockdown != LockdownState::Normal {
+        return Err(HybridLLMError::LockdownActive(format!("System is in {:?} mode", lockdown)));
+    }
+
+    let messages = chat.messages.iter().map(completion_message).collect::<Result<Vec<_>>>()?;
+    if !messages.iter().any(|message| message.role == MessageRole::User) {
+        return Err(HybridLLMError::InvalidRequest("messages must include a user message".to_string()));
+    }
+    let request = CompletionRequest::messages(messages);
+
+    let pool = state.llm_pool.read().await;
+    let llm_id = if DEFAULT_MODELS.contains(&chat.model.as_str()) {
+        let sees = |llm_id: &str| pool.get(llm_id).is_some_and(|llm| llm.capabilities().contains(&Capability::Vision));
+        match settings.models.default_llm.clone() {
+            // With images, a default LLM that can't see gives way to an available one that can
+            Some(llm_id) if request.has_images() && !sees(&llm_id) => pool
+                .find_by_capability(&Capability::Vision)
+                .into_iter()
+                .map(|llm| llm.instance().id.clone())
+                .find(|vision_id| pool.is_available(vision_id))
+                .unwrap_or(llm_id),
+            // Offline, a cloud default gives way to a loaded local model
+            Some(llm_id) if !pool.is_available(&llm_id) && pool.offline() => pool.local_fallback().unwrap_or(llm_id),
+            Some(llm_id) => llm_id,
+            None => return Err(HybridLLMError::InvalidRequest("No model given and no default LLM configured".to_string())),
+        }
+    } else {
+        chat.model.clone()
+    };
+    if pool.get(&llm_id).is_none() {
+        return Err(HybridLLMError::LLMNotFound(llm_id));
+    }
+    if !pool.is_available(&llm_id) {
+        return Err(HybridLLMError::PermissionDenied(format!("{} is a cloud provider and {}", llm_id, pool.cloud_unavailable_reason())));
+    }
+    drop(pool);
+
+    // The client's limits apply within the platform's budget, never beyond it
+    let mut options = settings.budgets.generation_options();
+    if let Some(max_tokens) = chat.max_completion_tokens.or(chat.max_tokens) {
+        options.max_tokens = Some(options.max_tokens.map_or(max_tokens, |budget| budget.min(max_tokens)));
+    }
+    options.temperature = chat.temperature;
+    options.top_p = chat.top_p;
+    options.seed = chat.seed;
+    options.stop = match &chat.stop {
+        Some(Stop::One(stop)) => vec![stop.clone()],
+        Some(Stop::Many(stops)) => stops.clone(),
+        None => Vec::new(),
+    };
+    Ok((llm_id, request.with_options(options)))
+}
+
+fn completion_message(message: &ChatMessage) -> Result<CompletionMessage> {
+    let role = match message.role.as_str() {
+        "system" | "developer" => MessageRole::System,
+        "user" => MessageRole::User,
+        "assistant" => MessageRole::Assistant,
+        role => return Err(HybridLLMError::InvalidRequest(format!("Messages with role {:?} are not supported", role))),
+    };
+    let (content, images) = match &message.content {
+        None => (String::new(), Vec::new()),
+        Some(ChatContent::Text(text)) => (text.clone(), Vec::new()),
+        Some(ChatContent::Parts(parts)) => {
+            let mut text = Vec::new();
+            let mut images = Vec::new();
+            for part in parts {
+                match part {
+                    ContentPart::Text { text: part } => text.push(part.as_str()),
+                    ContentPart::ImageUrl { image_url } => images.push(image_input(&image_url.url)?),
+                }
+            }
+            (text.join("\n"), images)
+        }
+    };
+    Ok(CompletionMessage { role, content, images, constructed: false })
+}
+
+/// An image from a `data:<type>;base64,<data>` URI
+fn image_input(url: &str) -> Result<ImageInput> {
+    let invalid = || HybridLLMError::InvalidRequest("Images must be base64 data: URIs".to_string());
+    let (media_type, data) = url
+        .strip_prefix("data:")
+        .and_then(|uri| uri.split_once(";base64,"))
+        .filter(|(media_type, _)| media_type.starts_with("image/"))
+        .ok_or_else(invalid)?;
+    base64::engine::general_purpose::STANDARD.decode(data).map_err(|_| invalid())?;
+    Ok(ImageInput { media_type: media_type.to_string(), data: data.to_string() })
+}
+
+/// Write the generation as `chat.completion.chunk` events, ending with `[DONE]`
+async fn send_events(stream: &mut TcpStream, mut generation: Generation) -> std::io::Result<()> {
+    // No Content-Length; the stream ends when the connection closes
+    let head = "HTTP/1.1 200 OK\r\nContent-Type: text/event-stream\r\nCache-Control: no-store\r\nX-Content-Type-Options: nosniff\r\nConnection: close\r\n\r\n";
+    stream.write_all(head.as_bytes()).await?;
+
+    let created = chrono::Utc::now().timestamp();
+    let chunk = |delta: Value, finish_reason: Option<&str>| {
+        json!({
+            "id": generation.id,
+            "object": "chat.completion.chunk",
+            "created": created,
+            "model": generation.llm_id,
+            "choices": [{ "index": 0, "delta": delta, "finish_reason": finish_reason }],
+        })
+    };
+    send_event(stream, &chunk(json!({ "role": "assistant", "content": "" }), None)).await?;
+
+    let mut usage = None;
+    let mut next = generation.first.take().map(Ok);
+    loop {
+        let chunk_or_error = match next.take() {
+            Some(chunk) => chunk,
+            None => match generation.chunks.recv().await {
+                Some(chunk) => chunk,
+                None => break,
+            },
+        };
+        match chunk_or_error {
+            Ok(StreamChunk::Text(text)) => send_event(stream, &chunk(json!({ "content": text }), None)).await?,
+            Ok(StreamChunk::Usage(reported)) => usage = Some(reported),
+            Err(e) => {
+                // Headers are gone, so the failure is reported in the stream
+                send_event(stream, &error_reply(&e).body).await?;
+                return stream.write_all(b"data: [DONE]\n\n").await;
+            }
+        }
+    }
+
+    send_event(stream, &chunk(json!({}), Some("stop"))).await?;
+    if let (true, Some(usage)) = (generation.include_usage, usage) {
+        let mut last = chunk(json!({}), None);
+        last["choices"] = json!([]);
+        last["usage"] = usage_json(&usage);
+        send_event(stream, &last).await?;
+    }
+    stream.write_all(b"data: [DONE]\n\n").await
+}
+
+async fn send_event(stream: &mut TcpStream, data: &Value) -> std::io::Result<()> {
+    stream.write_all(format!("data: {}\n\n", data).as_bytes()).await?;
+    stream.flush().await
+}
+
+fn usage_json(usage: &Usage) -> Value {
+    json!({
+        "prompt_tokens": usage.input_tokens,
+        "completion_tokens": usage.output_tokens,
+        "total_tokens": usage.total_tokens(),
+    })
+}
+
+/// An error in the shape OpenAI clients expect
+fn openai_error(status: &'static str, message: impl ToString, code: Option<&str>) -> Reply {
+    let kind = match status.split(' ').next() {
+        Some("401") => "authentication_error",
+        Some("403") => "permission_error",
+        Some("404") => "not_found_error",
+        Some("429") => "rate_limit_error",
+        Some(status) if status.starts_with('4') => "invalid_request_error",
+        _ => "api_error",
+    };
+    Reply { status, body: json!({ "error": { "message": message.to_string(), "type": kind, "code": code } }) }
+}
+
+fn error_reply(e: &HybridLLMError) -> Reply {
+    let status = match e.code() {
+        ErrorCode::InvalidRequest => "400 Bad Request",
+        ErrorCode::PermissionDenied | ErrorCode::SecurityViolation => "403 Forbidden",
+        ErrorCode::LlmNotFound => "404 Not Found",
+        ErrorCode::RateLimited | ErrorCode::ResourceLimitExceeded => "429 Too Many Requests",
+        ErrorCode::LockdownActive | ErrorCode::ProviderOverloaded => "503 Service Unavailable",
+        ErrorCode::Timeout => "504 Gateway Timeout",
+        _ => "502 Bad Gateway",
+    };
+    let code = serde_json::to_value(e.code()).ok();
+    openai_error(status, e, code.as_ref().and_then(Value::as_str))
+}
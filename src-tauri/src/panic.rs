@@ -0,0 +1,68 @@
+use common::{types::LockdownReason, SecurityEngine};
+use tauri::{AppHandle, GlobalShortcutManager, Manager};
+use tracing::{error, info, warn};
+
+use crate::state::AppState;
+use crate::tray;
+
+/// Lock down, kill whatever is running in sandboxes and bring the window to the front
+/// Shared by the global hotkey and the tray so every panic path does the same thing
+pub async fn panic_lockdown(app: &AppHandle, source: &str) {
+    warn!("🚨 Panic lockdown from the {}", source);
+    let state = app.state::<AppState>();
+
+    if let Err(e) = state.security_engine.trigger_lockdown(LockdownReason::UserPanicButton).await {
+        error!("❌ Panic lockdown failed: {}", e);
+    }
+    let killed = state.sandbox_manager.kill_running().await;
+
+    state.security_engine
+        .audit()
+        .log(
+            None,
+            "Panic lockdown".to_string(),
+            serde_json::json!({ "source": source, "killed_processes": killed }),
+            true,
+            None,
+        )
+        .await;
+
+    tray::show_dashboard(app);
+    tray::changed(app).await;
+}
+
+/// Register `accelerator` (e.g. "CmdOrCtrl+Alt+Shift+P") as the OS-wide panic hotkey, replacing `previous`
+/// On failure the previous hotkey stays registered
+pub fn register_hotkey(app: &AppHandle, previous: Option<&str>, accelerator: Option<&str>) -> Result<(), String> {
+    let mut shortcuts = app.global_shortcut_manager();
+    if previous == accelerator && previous.is_some_and(|p| shortcuts.is_registered(p).unwrap_or(false)) {
+        return Ok(());
+    }
+    if let Some(previous) = previous {
+        let _ = shortcuts.unregister(previous);
+    }
+
+    let Some(accelerator) = accelerator else {
+        info!("⌨️  Panic hotkey disabled");
+        return Ok(());
+    };
+
+    let handle = app.clone();
+    let registered = shortcuts.register(accelerator, move || {
+        let app = handle.clone();
+        tauri::async_runtime::spawn(async move { panic_lockdown(&app, "hotkey").await });
+    });
+
+    match registered {
+        Ok(()) => {
+            info!("⌨️  Panic hotkey: {}", accelerator);
+            Ok(())
+        }
+        Err(e) => {
+            if let Some(previous) = previous {
+                let _ = register_hotkey(app, None, Some(previous));
+            }
+            Err(format!("Could not register panic hotkey {}: {}", accelerator, e))
+        }
+    }
+}
@@ -0,0 +1,380 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use tracing::{info, warn};
+use uuid::Uuid;
+
+use common::errors::{HybridLLMError, Result};
+use common::traits::{ContextManager, LLMProvider};
+use common::types::PermissionScope;
+use context_manager::{ContentCipher, DatabaseContextManager, EmbeddingGenerator, InMemoryContextManager};
+
+use crate::keys::{self, CloudProvider, KEYRING_SERVICE};
+use crate::state::Document;
+use crate::websocket::constant_time_eq;
+
+/// The profile the app starts with, holding everything from before profiles existed
+pub const DEFAULT_PROFILE: &str = "default";
+
+/// Kept in the app's data directory
+pub const PROFILES_FILE: &str = "profiles.json";
+
+/// Shortest passphrase a sensitive profile accepts
+pub const MIN_PASSPHRASE_LEN: usize = 8;
+
+/// A separate set of conversations, RAG documents, permissions and provider keys, e.g. "work" or "personal"
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Profile {
+    /// Lowercase letters, digits and dashes; names its keyring entries and database variable
+    pub name: String,
+    /// Switching to the profile asks for its passphrase
+    #[serde(default)]
+    pub sensitive: bool,
+    /// Permissions granted while the profile is active, kept as they are changed
+    #[serde(default)]
+    pub permissions: PermissionScope,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct NewProfile {
+    pub name: String,
+    #[serde(default)]
+    pub sensitive: bool,
+    /// Required for sensitive profiles; kept in the OS keyring
+    #[serde(default)]
+    pub passphrase: Option<String>,
+    /// Defaults to the built-in permission scope
+    #[serde(default)]
+    pub permissions: Option<PermissionScope>,
+}
+
+/// Every profile and the one in use
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProfileList {
+    pub active: String,
+    pub profiles: Vec<Profile>,
+}
+
+impl Default for ProfileList {
+    fn default() -> Self {
+        Self {
+            active: DEFAULT_PROFILE.to_string(),
+            profiles: vec![Profile {
+                name: DEFAULT_PROFILE.to_string(),
+                sensitive: false,
+                permissions: PermissionScope::default(),
+                created_at: Utc::now(),
+            }],
+        }
+    }
+}
+
+/// Uploaded documents of a profile while another one is active
+#[derive(Default)]
+pub struct StashedDocuments {
+    pub documents: Vec<Document>,
+    pub trashed: HashMap<Uuid, Document>,
+}
+
+/// The profiles, saved in `profiles.json`, and the conversation stores opened for them so far
+pub struct Profiles {
+    path: PathBuf,
+    list: Mutex<ProfileList>,
+    contexts: Mutex<HashMap<String, Arc<dyn ContextManager>>>,
+    /// The same stores as `contexts` for profiles kept in PostgreSQL, which can be encrypted
+    databases: Mutex<HashMap<String, Arc<DatabaseContextManager>>>,
+    stashed: Mutex<HashMap<String, StashedDocuments>>,
+    /// The local model documents are embedded with, in every profile's database
+    embedder: Mutex<Option<Arc<Box<dyn LLMProvider>>>>,
+}
+
+impl Profiles {
+    /// Read the profiles; the app starts in the last active one unless it is sensitive
+    pub fn open(path: &Path) -> Result<Self> {
+        let mut list: ProfileList = match std::fs::read_to_string(path) {
+            Ok(text) => serde_json::from_str(&text)
+                .map_err(|e| HybridLLMError::ConfigError(format!("{}: {}", path.display(), e)))?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => ProfileList::default(),
+            Err(e) => return Err(HybridLLMError::FileSystemError(format!("{}: {}", path.display(), e))),
+        };
+        if !list.profiles.iter().any(|profile| profile.name == DEFAULT_PROFILE) {
+            list.profiles.insert(0, ProfileList::default().profiles.remove(0));
+        }
+        let sensitive = list.profiles.iter().any(|profile| profile.name == list.active && profile.sensitive);
+        if sensitive || !list.profiles.iter().any(|profile| profile.name == list.active) {
+            info!("👤 Starting in the {} profile instead of {}", DEFAULT_PROFILE, list.active);
+            list.active = DEFAULT_PROFILE.to_string();
+        }
+
+        Ok(Self {
+            path: path.to_path_buf(),
+            list: Mutex::new(list),
+            contexts: Mutex::new(HashMap::new()),
+            databases: Mutex::new(HashMap::new()),
+            stashed: Mutex::new(HashMap::new()),
+            embedder: Mutex::new(None),
+        })
+    }
+
+    pub fn list(&self) -> ProfileList {
+        self.list.lock().unwrap().clone()
+    }
+
+    pub fn active(&self) -> Profile {
+        let list = self.list.lock().unwrap();
+        list.profiles.iter().find(|profile| profile.name == list.active).cloned().unwrap()
+    }
+
+    pub fn get(&self, name: &str) -> Result<Profile> {
+        self.list
+            .lock()
+            .unwrap()
+            .profiles
+            .iter()
+            .find(|profile| profile.name == name)
+            .cloned()
+            .ok_or_else(|| HybridLLMError::InvalidRequest(format!("No profile {}", name)))
+    }
+
+    /// Add a profile; a sensitive one keeps its passphrase in the keyring
+    pub async fn create(&self, new: NewProfile) -> Result<Profile> {
+        validate_name(&new.name)?;
+        if self.get(&new.name).is_ok() {
+            return Err(HybridLLMError::InvalidRequest(format!("Profile {} already exists", new.name)));
+        }
+        match (new.sensitive, new.passphrase) {
+            (true, Some(passphrase)) if passphrase.chars().count() >= MIN_PASSPHRASE_LEN => {
+                keys::with_keyring_entry(passphrase_entry(&new.name), move |entry| entry.set_password(&passphrase))
+                    .await?;
+            }
+            (true, _) => {
+                return Err(HybridLLMError::InvalidRequest(format!(
+                    "A sensitive profile needs a passphrase of at least {} characters",
+                    MIN_PASSPHRASE_LEN
+                )))
+            }
+            (false, Some(_)) => {
+                return Err(HybridLLMError::InvalidRequest("Only sensitive profiles have a passphrase".to_string()))
+            }
+            (false, None) => {}
+        }
+
+        let profile = Profile {
+            name: new.name,
+            sensitive: new.sensitive,
+            permissions: new.permissions.unwrap_or_default(),
+            created_at: Utc::now(),
+        };
+        self.update(|list| list.profiles.push(profile.clone()))?;
+        info!("👤 Created profile {}", profile.name);
+        Ok(profile)
+    }
+
+    /// Remove a profile that isn't active, with its keyring entries and the documents it had listed
+    /// Conversations in its own database stay there
+    pub async fn delete(&self, name: &str) -> Result<Profile> {
+        let profile = self.get(name)?;
+        if name == DEFAULT_PROFILE {
+            return Err(HybridLLMError::InvalidRequest("The default profile cannot be deleted".to_string()));
+        }
+        if name == self.active().name {
+            return Err(HybridLLMError::InvalidRequest("Switch to another profile before deleting this one".to_string()));
+        }
+
+        for provider in CloudProvider::ALL {
+            keys::delete_key(provider, name).await?;
+        }
+        for entry in [passphrase_entry(name), content_key_entry(name)] {
+            keys::with_keyring_entry(entry, |entry| match entry.delete_credential() {
+                Ok(()) | Err(keyring::Error::NoEntry) => Ok(()),
+                Err(e) => Err(e),
+            })
+            .await?;
+        }
+        self.update(|list| list.profiles.retain(|profile| profile.name != name))?;
+        self.contexts.lock().unwrap().remove(name);
+        self.databases.lock().unwrap().remove(name);
+        self.stashed.lock().unwrap().remove(name);
+        info!("🗑️  Deleted profile {}", name);
+        Ok(profile)
+    }
+
+    /// Whether `passphrase` unlocks the profile; profiles that aren't sensitive need none
+    pub async fn check_passphrase(&self, profile: &Profile, passphrase: Option<&str>) -> Result<bool> {
+        if !profile.sensitive {
+            return Ok(true);
+        }
+        let Some(passphrase) = passphrase else {
+            return Ok(false);
+        };
+        let stored = keys::with_keyring_entry(passphrase_entry(&profile.name), |entry| match entry.get_password() {
+            Ok(passphrase) => Ok(Some(passphrase)),
+            Err(keyring::Error::NoEntry) => Ok(None),
+            Err(e) => Err(e),
+        })
+        .await?;
+        Ok(stored.is_some_and(|stored| constant_time_eq(stored.as_bytes(), passphrase.as_bytes())))
+    }
+
+    /// Keep the active profile's permissions as they are changed
+    pub fn set_permissions(&self, permissions: PermissionScope) -> Result<()> {
+        self.update(|list| {
+            let active = list.active.clone();
+            if let Some(profile) = list.profiles.iter_mut().find(|profile| profile.name == active) {
+                profile.permissions = permissions;
+            }
+        })
+    }
+
+    pub fn set_active(&self, name: &str) -> Result<()> {
+        self.update(|list| list.active = name.to_string())
+    }
+
+    /// The profile's conversation store, opened the first time it is needed
+    /// The default profile stores conversations in `DATABASE_URL`, others in e.g. `DATABASE_URL_WORK`;
+    /// a database is decrypted with the content key in the keyring, which may block on the OS secret service
+    pub fn context(&self, name: &str) -> Result<Arc<dyn ContextManager>> {
+        let mut contexts = self.contexts.lock().unwrap();
+        if let Some(context) = contexts.get(name) {
+            return Ok(Arc::clone(context));
+        }
+
+        let variable = database_variable(name);
+        let context: Arc<dyn ContextManager> = match std::env::var(&variable) {
+            Ok(url) if !url.is_empty() => {
+                info!("🔌 Storing conversations of profile {} in PostgreSQL", name);
+                let mut database = DatabaseContextManager::connect_lazy(&url)?;
+                match stored_content_key(name) {
+                    Ok(Some(cipher)) => database = database.with_cipher(cipher),
+                    Ok(None) => {}
+                    Err(e) => warn!("⚠️  Content key of profile {} not readable, encrypted content stays locked: {}", name, e),
+                }
+                if let Some(embedder) = self.embedder.lock().unwrap().clone() {
+                    database.set_embeddings(EmbeddingGenerator::with_provider(embedder));
+                }
+                let database = Arc::new(database);
+                self.databases.lock().unwrap().insert(name.to_string(), Arc::clone(&database));
+                database
+            }
+            _ => {
+                warn!("⚠️  {} not set, conversations of profile {} are kept in memory only", variable, name);
+                Arc::new(InMemoryContextManager::new())
+            }
+        };
+        contexts.insert(name.to_string(), Arc::clone(&context));
+        Ok(context)
+    }
+
+    /// The profile's PostgreSQL store, once opened; `None` for profiles kept in memory
+    pub fn database(&self, name: &str) -> Option<Arc<DatabaseContextManager>> {
+        self.databases.lock().unwrap().get(name).cloned()
+    }
+
+    /// Embed documents and searches with `embedder` in the databases opened so far and those opened later
+    pub fn set_embedder(&self, embedder: Arc<Box<dyn LLMProvider>>) {
+        *self.embedder.lock().unwrap() = Some(Arc::clone(&embedder));
+        for database in self.databases.lock().unwrap().values() {
+            database.set_embeddings(EmbeddingGenerator::with_provider(Arc::clone(&embedder)));
+        }
+    }
+
+    pub fn has_embedder(&self) -> bool {
+        self.embedder.lock().unwrap().is_some()
+    }
+
+    /// Encrypt the profile's database with a key derived from `passphrase`, or unlock it if it already is,
+    /// keeping the key in the keyring so it opens unattended from now on
+    pub async fn enable_encryption(&self, name: &str, passphrase: &str) -> Result<()> {
+        let database = self.database(name).ok_or_else(|| {
+            HybridLLMError::InvalidRequest(format!(
+                "Profile {} keeps conversations in memory; set {} to encrypt them at rest",
+                name,
+                database_variable(name)
+            ))
+        })?;
+        if !database.encryption_enabled().await? && passphrase.chars().count() < MIN_PASSPHRASE_LEN {
+            return Err(HybridLLMError::InvalidRequest(format!(
+                "The passphrase must have at least {} characters",
+                MIN_PASSPHRASE_LEN
+            )));
+        }
+
+        let key = database.enable_encryption(passphrase).await?.key_base64();
+        keys::with_keyring_entry(content_key_entry(name), move |entry| entry.set_password(&key)).await
+    }
+
+    /// Put away the documents of a profile being switched away from
+    pub fn stash(&self, name: &str, documents: StashedDocuments) {
+        self.stashed.lock().unwrap().insert(name.to_string(), documents);
+    }
+
+    /// Documents put away for the profile being switched to
+    pub fn take_stash(&self, name: &str) -> StashedDocuments {
+        self.stashed.lock().unwrap().remove(name).unwrap_or_default()
+    }
+
+    /// Change the profiles and save them right away
+    fn update(&self, change: impl FnOnce(&mut ProfileList)) -> Result<()> {
+        let mut list = self.list.lock().unwrap();
+        let mut changed = list.clone();
+        change(&mut changed);
+
+        let fs_err = |e: std::io::Error| HybridLLMError::FileSystemError(format!("{}: {}", self.path.display(), e));
+        let text = serde_json::to_string_pretty(&changed).map_err(|e| HybridLLMError::Other(e.into()))?;
+        if let Some(parent) = self.path.parent() {
+            std::fs::create_dir_all(parent).map_err(fs_err)?;
+        }
+        let mut partial = self.path.as_os_str().to_os_string();
+        partial.push(".tmp");
+        std::fs::write(&partial, text).map_err(fs_err)?;
+        std::fs::rename(&partial, &self.path).map_err(fs_err)?;
+
+        *list = changed;
+        Ok(())
+    }
+}
+
+/// Names become keyring entries, file names and environment variable names
+pub fn validate_name(name: &str) -> Result<()> {
+    let valid = !name.is_empty()
+        && name.len() <= 32
+        && !name.starts_with('-')
+        && name.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-');
+    if valid {
+        Ok(())
+    } else {
+        Err(HybridLLMError::InvalidRequest(format!(
+            "Profile name {:?} must be 1 to 32 lowercase letters, digits and dashes",
+            name
+        )))
+    }
+}
+
+/// Profile names have no colons, so these never collide with a provider key entry
+fn passphrase_entry(name: &str) -> String {
+    format!("{}:passphrase", name)
+}
+
+fn content_key_entry(name: &str) -> String {
+    format!("{}:content-key", name)
+}
+
+fn stored_content_key(name: &str) -> Result<Option<ContentCipher>> {
+    let stored = keyring::Entry::new(KEYRING_SERVICE, &content_key_entry(name)).and_then(|entry| entry.get_password());
+    match stored {
+        Ok(key) => ContentCipher::from_key_base64(&key).map(Some),
+        Err(keyring::Error::NoEntry) => Ok(None),
+        Err(e) => Err(HybridLLMError::ConfigError(format!("Keyring entry for {}: {}", content_key_entry(name), e))),
+    }
+}
+
+fn database_variable(name: &str) -> String {
+    if name == DEFAULT_PROFILE {
+        "DATABASE_URL".to_string()
+    } else {
+        format!("DATABASE_URL_{}", name.to_uppercase().replace('-', "_"))
+    }
+}
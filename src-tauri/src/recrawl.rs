@@ -0,0 +1,50 @@
+use common::errors::{HybridLLMError, Result};
+use common::traits::SecurityEngine;
+use context_manager::RecrawlReport;
+use std::time::Duration;
+use tauri::{AppHandle, Manager};
+use tokio::time::Instant;
+use tracing::{debug, warn};
+
+use crate::state::AppState;
+
+/// How often the scheduler checks whether a re-crawl is due
+const SCHEDULE_CHECK_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Re-crawl the watched folders and pages into the active profile's documents
+pub async fn recrawl(state: &AppState) -> Result<RecrawlReport> {
+    let lockdown = state.security_engine.lockdown_state().await?;
+    if !lockdown.allows_writes() {
+        return Err(HybridLLMError::LockdownActive(format!("Can't re-index documents in {:?} mode", lockdown)));
+    }
+    let settings = state.settings.read().await.recrawl.clone();
+    Ok(state.recrawler.recrawl(state.context().as_ref(), &settings).await)
+}
+
+/// Re-crawl every `recrawl.interval_minutes`, first shortly after startup
+pub fn spawn_scheduler(app: AppHandle) {
+    tokio::spawn(async move {
+        let mut last: Option<Instant> = None;
+        loop {
+            tokio::time::sleep(SCHEDULE_CHECK_INTERVAL).await;
+            let state = app.state::<AppState>();
+            let Some(minutes) = state.settings.read().await.recrawl.interval_minutes else {
+                continue;
+            };
+            if last.is_some_and(|at| at.elapsed() < Duration::from_secs(minutes * 60)) {
+                continue;
+            }
+
+            last = Some(Instant::now());
+            match recrawl(&state).await {
+                Ok(report) => {
+                    for failure in &report.failed {
+                        warn!("⚠️  Re-crawl failed for {}", failure);
+                    }
+                }
+                Err(HybridLLMError::LockdownActive(reason)) => debug!("📚 Skipping the re-crawl: {}", reason),
+                Err(e) => warn!("⚠️  Re-crawl failed: {}", e),
+            }
+        }
+    });
+}
@@ -0,0 +1,158 @@
+use chrono::{DateTime, Utc};
+use llm_pool::{LLMMemory, LLMPool};
+use serde::Serialize;
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::Duration;
+use sysinfo::{Disks, System};
+use tauri::{AppHandle, Manager};
+
+use crate::state::AppState;
+
+/// Tauri event carrying a fresh `ResourceUsage`
+pub const RESOURCE_USAGE_EVENT: &str = "resource-usage";
+
+/// How often `resource-usage` is emitted
+const REPORT_INTERVAL: Duration = Duration::from_secs(5);
+
+const MB: u64 = 1024 * 1024;
+
+/// A snapshot of the machine and what the loaded LLMs take of it
+#[derive(Debug, Clone, Serialize)]
+pub struct ResourceUsage {
+    pub sampled_at: DateTime<Utc>,
+    /// Across all cores, since the previous sample
+    pub cpu_percent: f32,
+    pub ram_total_mb: u64,
+    pub ram_used_mb: u64,
+    pub ram_available_mb: u64,
+    pub gpus: Vec<GpuUsage>,
+    /// Disk holding the data directory; `None` if it couldn't be found
+    pub disk: Option<DiskUsage>,
+    pub llms: Vec<LLMMemory>,
+    /// Unloaded local LLMs whose weights fit in the memory available now
+    pub loadable: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct GpuUsage {
+    pub index: u32,
+    pub name: String,
+    pub total_vram_mb: u64,
+    /// Reserved by loaded models and GPU sandboxes
+    pub reserved_vram_mb: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DiskUsage {
+    pub mount_point: String,
+    pub total_mb: u64,
+    pub available_mb: u64,
+}
+
+impl ResourceUsage {
+    /// Whether a model needing `ram_mb` of memory and `vram_mb` on one GPU would fit right now
+    pub fn fits(&self, ram_mb: u64, vram_mb: u64) -> bool {
+        ram_mb <= self.ram_available_mb
+            && (vram_mb == 0 || self.gpus.iter().any(|gpu| gpu.total_vram_mb.saturating_sub(gpu.reserved_vram_mb) >= vram_mb))
+    }
+}
+
+/// Keeps one `System` so CPU usage is measured between consecutive samples
+pub struct ResourceSampler {
+    system: Mutex<System>,
+}
+
+impl ResourceSampler {
+    pub fn new() -> Self {
+        let mut system = System::new();
+        // CPU usage is a difference, so the first sample needs a baseline
+        system.refresh_cpu();
+        Self { system: Mutex::new(system) }
+    }
+
+    pub fn sample(&self, pool: &LLMPool, data_dir: &Path) -> ResourceUsage {
+        let (cpu_percent, ram_total_mb, ram_used_mb, ram_available_mb) = {
+            let mut system = self.system.lock().unwrap();
+            system.refresh_cpu();
+            system.refresh_memory();
+            (
+                system.global_cpu_info().cpu_usage(),
+                system.total_memory() / MB,
+                system.used_memory() / MB,
+                system.available_memory() / MB,
+            )
+        };
+
+        let governor = pool.governor();
+        let gpus = governor
+            .devices()
+            .iter()
+            .map(|device| GpuUsage {
+                index: device.index,
+                name: device.name.clone(),
+                total_vram_mb: device.total_vram_mb,
+                reserved_vram_mb: device.total_vram_mb.saturating_sub(governor.free_vram_mb(device.index)),
+            })
+            .collect();
+
+        let mut usage = ResourceUsage {
+            sampled_at: Utc::now(),
+            cpu_percent,
+            ram_total_mb,
+            ram_used_mb,
+            ram_available_mb,
+            gpus,
+            disk: disk_usage(data_dir),
+            llms: pool.memory_usage(),
+            loadable: Vec::new(),
+        };
+        usage.loadable = usage
+            .llms
+            .iter()
+            .filter(|llm| !llm.loaded && llm.ram_mb.is_some_and(|ram_mb| usage.fits(ram_mb, llm.vram_mb)))
+            .map(|llm| llm.llm_id.clone())
+            .collect();
+        usage
+    }
+}
+
+impl Default for ResourceSampler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The disk with the longest mount point containing `path`
+fn disk_usage(path: &Path) -> Option<DiskUsage> {
+    let path = path.canonicalize().ok()?;
+    let disks = Disks::new_with_refreshed_list();
+    disks
+        .list()
+        .iter()
+        .filter(|disk| path.starts_with(disk.mount_point()))
+        .max_by_key(|disk| disk.mount_point().as_os_str().len())
+        .map(|disk| DiskUsage {
+            mount_point: disk.mount_point().display().to_string(),
+            total_mb: disk.total_space() / MB,
+            available_mb: disk.available_space() / MB,
+        })
+}
+
+/// Take a sample of the current state
+pub async fn sample(state: &AppState) -> ResourceUsage {
+    let data_dir = state.settings.read().await.paths.data_dir.clone();
+    let pool = state.llm_pool.read().await;
+    state.resources.sample(&pool, &data_dir)
+}
+
+/// Emit `resource-usage` periodically so the dashboard stays current
+pub fn spawn_reporter(app: AppHandle) {
+    tokio::spawn(async move {
+        loop {
+            tokio::time::sleep(REPORT_INTERVAL).await;
+            let usage = sample(&app.state::<AppState>()).await;
+            let _ = app.emit_all(RESOURCE_USAGE_EVENT, usage);
+        }
+    });
+}
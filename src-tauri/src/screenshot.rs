@@ -0,0 +1,139 @@
+use base64::Engine;
+use common::errors::{HybridLLMError, Result};
+use serde::{Deserialize, Serialize};
+use std::io::Cursor;
+use tracing::info;
+use xcap::image::{imageops, ImageFormat, RgbaImage};
+use xcap::{Monitor, Window};
+
+/// Longest side kept; vision models scale larger images down anyway, so sending them only costs tokens
+const MAX_IMAGE_EDGE: u32 = 1568;
+
+/// What to capture
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum CaptureTarget {
+    /// The whole primary monitor
+    Screen,
+    /// A rectangle in screen coordinates, on the monitor holding its top left corner
+    Region { x: i32, y: i32, width: u32, height: u32 },
+    /// A window by its id from `list_capture_windows`
+    Window { window_id: u32 },
+}
+
+/// A window that can be captured
+#[derive(Debug, Clone, Serialize)]
+pub struct CaptureWindow {
+    pub id: u32,
+    pub title: String,
+    pub app_name: String,
+}
+
+/// A captured image, PNG encoded
+pub struct Screenshot {
+    pub png: Vec<u8>,
+    pub width: u32,
+    pub height: u32,
+}
+
+/// A stored screenshot, ready to attach to a message as image content
+#[derive(Debug, Clone, Serialize)]
+pub struct ScreenshotAttachment {
+    /// Name in the uploads folder
+    pub filename: String,
+    pub media_type: String,
+    /// Base64 encoded
+    pub data: String,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Screenshot {
+    pub fn attachment(&self, filename: String) -> ScreenshotAttachment {
+        ScreenshotAttachment {
+            filename,
+            media_type: "image/png".to_string(),
+            data: base64::engine::general_purpose::STANDARD.encode(&self.png),
+            width: self.width,
+            height: self.height,
+        }
+    }
+}
+
+/// Windows on screen, leaving out minimized and untitled ones
+pub fn list_windows() -> Result<Vec<CaptureWindow>> {
+    Ok(Window::all()
+        .map_err(capture_error)?
+        .into_iter()
+        .filter(|window| !window.is_minimized() && !window.title().is_empty())
+        .map(|window| CaptureWindow {
+            id: window.id(),
+            title: window.title().to_string(),
+            app_name: window.app_name().to_string(),
+        })
+        .collect())
+}
+
+/// Capture `target`, scaled down to fit `MAX_IMAGE_EDGE`
+/// Blocks while capturing; call it from a blocking task
+pub fn capture(target: &CaptureTarget) -> Result<Screenshot> {
+    let image = match target {
+        CaptureTarget::Screen => {
+            let monitors = Monitor::all().map_err(capture_error)?;
+            let monitor = monitors
+                .iter()
+                .find(|monitor| monitor.is_primary())
+                .or(monitors.first())
+                .ok_or_else(|| HybridLLMError::InvalidRequest("No monitor to capture".to_string()))?;
+            monitor.capture_image().map_err(capture_error)?
+        }
+        CaptureTarget::Region { x, y, width, height } => {
+            if *width == 0 || *height == 0 {
+                return Err(HybridLLMError::InvalidRequest("Capture region is empty".to_string()));
+            }
+            let monitor = Monitor::from_point(*x, *y).map_err(capture_error)?;
+            let image = monitor.capture_image().map_err(capture_error)?;
+            // Monitor images are in physical pixels, the region in logical ones
+            let scale = monitor.scale_factor();
+            let left = ((x - monitor.x()) as f32 * scale) as u32;
+            let top = ((y - monitor.y()) as f32 * scale) as u32;
+            if left >= image.width() || top >= image.height() {
+                return Err(HybridLLMError::InvalidRequest("Capture region is off screen".to_string()));
+            }
+            let width = ((*width as f32 * scale) as u32).clamp(1, image.width() - left);
+            let height = ((*height as f32 * scale) as u32).clamp(1, image.height() - top);
+            imageops::crop_imm(&image, left, top, width, height).to_image()
+        }
+        CaptureTarget::Window { window_id } => Window::all()
+            .map_err(capture_error)?
+            .into_iter()
+            .find(|window| window.id() == *window_id)
+            .ok_or_else(|| HybridLLMError::InvalidRequest(format!("No window with id {}", window_id)))?
+            .capture_image()
+            .map_err(capture_error)?,
+    };
+
+    let image = fit(image);
+    let mut png = Vec::new();
+    image
+        .write_to(&mut Cursor::new(&mut png), ImageFormat::Png)
+        .map_err(|e| HybridLLMError::Other(anyhow::anyhow!("Failed to encode screenshot: {}", e)))?;
+    info!("📸 Captured {}x{} screenshot ({} bytes)", image.width(), image.height(), png.len());
+
+    Ok(Screenshot { png, width: image.width(), height: image.height() })
+}
+
+fn fit(image: RgbaImage) -> RgbaImage {
+    let longest = image.width().max(image.height());
+    if longest <= MAX_IMAGE_EDGE {
+        return image;
+    }
+    let scale = MAX_IMAGE_EDGE as f32 / longest as f32;
+    let width = ((image.width() as f32 * scale) as u32).max(1);
+    let height = ((image.height() as f32 * scale) as u32).max(1);
+    imageops::resize(&image, width, height, imageops::FilterType::Triangle)
+}
+
+fn capture_error(e: xcap::XCapError) -> HybridLLMError {
+    HybridLLMError::Other(anyhow::anyhow!("Screen capture failed: {}", e))
+}
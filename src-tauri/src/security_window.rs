@@ -0,0 +1,36 @@
+use tauri::{AppHandle, Manager, WindowBuilder, WindowUrl};
+use tracing::info;
+
+/// Label of the always-on-top security window
+pub const SECURITY_WINDOW: &str = "security";
+
+/// Tauri event carrying each `AuditLogEntry` as it is logged
+pub const AUDIT_EVENT: &str = "audit-entry";
+
+/// Show the security window, creating it on first use
+/// The UI picks the security view from the `#security` fragment
+pub fn open(app: &AppHandle) -> tauri::Result<()> {
+    if let Some(window) = app.get_window(SECURITY_WINDOW) {
+        window.show()?;
+        window.unminimize()?;
+        return window.set_focus();
+    }
+
+    WindowBuilder::new(app, SECURITY_WINDOW, WindowUrl::App("index.html#security".into()))
+        .title("Security — Hybrid LLM Platform")
+        .inner_size(460.0, 720.0)
+        .min_inner_size(380.0, 480.0)
+        .always_on_top(true)
+        .build()?;
+    info!("🛡️  Security window opened");
+    Ok(())
+}
+
+/// Close the security window; a no-op when it isn't open
+pub fn close(app: &AppHandle) -> tauri::Result<()> {
+    if let Some(window) = app.get_window(SECURITY_WINDOW) {
+        window.close()?;
+        info!("🛡️  Security window closed");
+    }
+    Ok(())
+}
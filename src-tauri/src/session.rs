@@ -0,0 +1,191 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use tracing::{info, warn};
+use uuid::Uuid;
+
+use common::errors::{HybridLLMError, Result};
+use common::traits::{ContextManager, LLMProvider};
+use llm_pool::LLMPool;
+use security_engine::{PendingApproval, SecurityEngineImpl};
+
+/// Kept in the app's data directory
+pub const SESSION_FILE: &str = "session.json";
+
+/// What the app was doing, saved as it changes and restored on the next launch
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Session {
+    /// LLMs loaded with `load_llm` and not unloaded since, loaded again in the background at startup
+    #[serde(default)]
+    pub loaded_llms: Vec<String>,
+    /// Conversations open in the UI, in the order it shows them
+    #[serde(default)]
+    pub open_conversations: Vec<Uuid>,
+    #[serde(default)]
+    pub active_conversation: Option<Uuid>,
+    /// Approvals still undecided; the actions waiting on them end with the app
+    #[serde(default)]
+    pub pending_approvals: Vec<PendingApproval>,
+    #[serde(default)]
+    pub saved_at: Option<DateTime<Utc>>,
+}
+
+/// The session as the UI picks it back up after a restart
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RestoredSession {
+    pub open_conversations: Vec<Uuid>,
+    pub active_conversation: Option<Uuid>,
+    /// LLMs being loaded again in the background
+    pub loaded_llms: Vec<String>,
+    /// Approvals left undecided when the app closed, denied since nothing waits for them anymore
+    pub interrupted_approvals: Vec<PendingApproval>,
+    /// When the previous session was last saved; `None` on a first launch
+    pub saved_at: Option<DateTime<Utc>>,
+}
+
+/// The current session and the one the app started from
+pub struct SessionStore {
+    path: PathBuf,
+    previous: Session,
+    current: Mutex<Session>,
+}
+
+impl SessionStore {
+    /// Read the previous session; a broken file is reported and the app starts blank
+    pub fn open(path: &Path) -> Self {
+        let previous = match std::fs::read_to_string(path) {
+            Ok(text) => serde_json::from_str(&text).unwrap_or_else(|e| {
+                warn!("⚠️  Ignoring unreadable session {:?}: {}", path, e);
+                Session::default()
+            }),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Session::default(),
+            Err(e) => {
+                warn!("⚠️  Could not read session {:?}: {}", path, e);
+                Session::default()
+            }
+        };
+
+        // Carried over until the UI and the restore say otherwise, so a quick restart loses nothing
+        let current = Session {
+            loaded_llms: previous.loaded_llms.clone(),
+            open_conversations: previous.open_conversations.clone(),
+            active_conversation: previous.active_conversation,
+            ..Session::default()
+        };
+        Self { path: path.to_path_buf(), previous, current: Mutex::new(current) }
+    }
+
+    pub fn previous(&self) -> &Session {
+        &self.previous
+    }
+
+    /// Change the session and save it right away
+    pub fn update(&self, change: impl FnOnce(&mut Session)) -> Result<()> {
+        let session = {
+            let mut current = self.current.lock().unwrap();
+            change(&mut current);
+            current.saved_at = Some(Utc::now());
+            current.clone()
+        };
+        self.save(&session)
+    }
+
+    fn save(&self, session: &Session) -> Result<()> {
+        let text = serde_json::to_string_pretty(session).map_err(|e| HybridLLMError::Other(e.into()))?;
+
+        let fs_err = |e: std::io::Error| HybridLLMError::FileSystemError(format!("{}: {}", self.path.display(), e));
+        if let Some(parent) = self.path.parent() {
+            std::fs::create_dir_all(parent).map_err(fs_err)?;
+        }
+        let mut partial = self.path.as_os_str().to_os_string();
+        partial.push(".tmp");
+        std::fs::write(&partial, text).map_err(fs_err)?;
+        std::fs::rename(&partial, &self.path).map_err(fs_err)
+    }
+
+    pub fn llm_loaded(&self, llm_id: &str) -> Result<()> {
+        self.update(|session| {
+            if !session.loaded_llms.iter().any(|id| id == llm_id) {
+                session.loaded_llms.push(llm_id.to_string());
+            }
+        })
+    }
+
+    pub fn llm_unloaded(&self, llm_id: &str) -> Result<()> {
+        self.update(|session| session.loaded_llms.retain(|id| id != llm_id))
+    }
+
+    /// What the UI reopens: conversations that still exist, and the previous session's leftovers
+    pub async fn restored(&self, context: &dyn ContextManager) -> RestoredSession {
+        // Conversations kept in memory are gone after a restart, trashed ones stay closed
+        let existing: Vec<Uuid> = match context.list_conversations(false).await {
+            Ok(conversations) => conversations.iter().map(|conversation| conversation.id).collect(),
+            Err(e) => {
+                warn!("⚠️  Could not list conversations to reopen: {}", e);
+                Vec::new()
+            }
+        };
+        let open_conversations: Vec<Uuid> = self
+            .previous
+            .open_conversations
+            .iter()
+            .filter(|id| existing.contains(id))
+            .copied()
+            .collect();
+        let active_conversation = self.previous.active_conversation.filter(|id| open_conversations.contains(id));
+
+        RestoredSession {
+            open_conversations,
+            active_conversation,
+            loaded_llms: self.previous.loaded_llms.clone(),
+            interrupted_approvals: self.previous.pending_approvals.clone(),
+            saved_at: self.previous.saved_at,
+        }
+    }
+}
+
+/// Load the previous session's LLMs again once the providers are registered, one after another
+pub async fn reload_llms(pool: &LLMPool, session: &SessionStore) {
+    for llm_id in &session.previous().loaded_llms {
+        match pool.get(llm_id) {
+            Some(llm) if llm.is_loaded() => continue,
+            Some(_) => match pool.load(llm_id).await {
+                Ok(()) => info!("♻️  Reloaded {} from the last session", llm_id),
+                Err(e) => {
+                    warn!("⚠️  Could not reload {} from the last session: {}", llm_id, e);
+                    let _ = session.llm_unloaded(llm_id);
+                }
+            },
+            None => {
+                warn!("⚠️  {} from the last session is no longer registered", llm_id);
+                let _ = session.llm_unloaded(llm_id);
+            }
+        }
+    }
+}
+
+/// Record approvals the previous session never answered; they count as denied
+pub async fn audit_interrupted_approvals(security_engine: &SecurityEngineImpl, session: &SessionStore) {
+    for approval in &session.previous().pending_approvals {
+        security_engine
+            .audit()
+            .log(
+                Some(approval.llm_id.clone()),
+                "Approval interrupted".to_string(),
+                json!({
+                    "approval_id": approval.id,
+                    "requested_at": approval.requested_at,
+                    "request": approval.request,
+                }),
+                false,
+                Some("The app closed before the request was decided".to_string()),
+            )
+            .await;
+    }
+    // Saved now so the next launch doesn't report them again
+    if let Err(e) = session.update(|_| {}) {
+        warn!("⚠️  Could not save the session: {}", e);
+    }
+}
@@ -0,0 +1,15 @@
+use std::path::Path;
+use tracing::warn;
+
+/// The app reads the same file as the headless orchestrator
+pub use common::config::{
+    PlatformConfig as Settings, ProviderKey, ProviderSettings, DEFAULT_CONFIG_FILE as SETTINGS_FILE,
+};
+
+/// Load settings at startup; a broken file is reported and left untouched rather than overwritten
+pub fn load_or_default(path: &Path) -> Settings {
+    Settings::load(path).unwrap_or_else(|e| {
+        warn!("⚠️  Ignoring settings: {}", e);
+        Settings::default()
+    })
+}
@@ -1,18 +1,62 @@
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
 use tokio::sync::RwLock;
+use serde_json::json;
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
-use common::types::{LLMInstance, PermissionScope, LockdownState};
-use llm_pool::LLMPool;
-use security_engine::SecurityEngineImpl;
-use context_manager::DatabaseContextManager;
+use api_gateway::PageFetcher;
+use common::config::SandboxBackend;
+use common::errors::HybridLLMError;
+use common::traits::{ContextManager, PromptGuard, SecurityEngine};
+use common::types::{Capability, LLMInstance, PermissionScope, LockdownState};
+use context_manager::Recrawler;
+use llm_pool::{BenchmarkStore, EvaluationStore, LLMPool, LLMStatus, UsageLedger};
+use security_engine::{AuditLogger, SecurityEngineImpl};
+use sandbox_manager::{PluginConfig, PluginHost, SandboxManager};
+use filesystem_interface::{FileSystemInterface, ManagedFolder};
+use tracing::{debug, info, warn};
 
+use crate::browser_bridge::BrowserBridge;
+use crate::openai_api::OpenAiApi;
+use crate::deeplink::DeepLinks;
+use crate::notifications::Notifier;
+use crate::profiles::{self, Profile, Profiles, StashedDocuments};
+use crate::resources::ResourceSampler;
+use crate::session::{self, SessionStore};
+use crate::settings::{self, Settings};
+use crate::uploads::IndexQueue;
+use crate::voice::VoiceInput;
+use crate::websocket::Broadcaster;
+
+/// Everything the dashboard and the tray show about the running system, in one snapshot
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SystemState {
+    /// Name of the active profile
+    pub profile: String,
+    #[serde(rename = "lockdown_state")]
     pub lockdown: LockdownState,
+    /// Loaded LLMs
     pub active_llms: Vec<String>,
+    /// Health, load state and requests of every registered LLM
+    pub llms: Vec<LLMStatus>,
     pub pending_approvals: usize,
+    /// Cloud providers take no requests while paused, e.g. from the tray
+    pub cloud_paused: bool,
+    /// Only local models answer and network access is refused
+    pub offline: bool,
+    /// Capabilities registered LLMs have but none available right now does, e.g. vision while offline
+    pub unavailable_capabilities: Vec<Capability>,
+    pub budget: BudgetState,
+}
+
+/// Cloud spending this calendar month against the cap in the settings
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BudgetState {
+    pub monthly_cloud_usd: Option<f64>,
+    pub spent_usd: f64,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -20,19 +64,28 @@ pub struct Document {
     pub id: Uuid,
     pub filename: String,
     pub size: usize,
+    pub sha256: String,
     pub uploaded_at: chrono::DateTime<chrono::Utc>,
     pub indexed: bool,
     pub chunk_count: Option<usize>,
+    /// Page the document was taken from, for pages sent over from the browser
+    #[serde(default)]
+    pub url: Option<String>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct AuditLogEntry {
-    pub id: Uuid,
-    pub timestamp: chrono::DateTime<chrono::Utc>,
-    pub llm_id: Option<String>,
-    pub action: String,
-    pub approved: bool,
-    pub reason: Option<String>,
+/// A streaming completion started by `send_message_stream`
+pub struct MessageStream {
+    pub llm_id: String,
+    pub task: tokio::task::AbortHandle,
+}
+
+/// A model download started by `download_model`
+pub struct ModelDownload {
+    pub repo_id: String,
+    pub filename: String,
+    /// Partial file removed if the download is cancelled
+    pub partial: std::path::PathBuf,
+    pub task: tokio::task::AbortHandle,
 }
 
 /// Application state shared across Tauri commands
@@ -41,20 +94,254 @@ pub struct AppState {
     pub security_engine: Arc<SecurityEngineImpl>,
     pub permissions: Arc<RwLock<PermissionScope>>,
     pub documents: Arc<RwLock<Vec<Document>>>,
-    pub audit_log: Arc<RwLock<Vec<AuditLogEntry>>>,
+    /// Deleted documents by trash entry, re-listed if their file is restored
+    pub trashed_documents: Arc<RwLock<HashMap<Uuid, Document>>>,
+    /// Uploaded documents not indexed yet
+    pub index_queue: Arc<IndexQueue>,
+    /// Keeps the documents of `recrawl.folders` and `recrawl.urls` current
+    pub recrawler: Arc<Recrawler>,
+    pub sandbox_manager: Arc<SandboxManager>,
+    pub filesystem: Arc<FileSystemInterface>,
+    /// WASM plugins found in `paths.plugins_dir` at startup
+    pub plugins: Arc<PluginHost>,
+    /// The active profile's conversation store, swapped when switching profiles
+    context: std::sync::RwLock<Arc<dyn ContextManager>>,
+    /// Profiles with their own conversations, documents, permissions and provider keys
+    pub profiles: Arc<Profiles>,
+    /// Streaming completions in flight, by request id, so they can be cancelled
+    pub message_streams: Arc<RwLock<HashMap<Uuid, MessageStream>>>,
+    /// Model downloads in flight, by download id, so they can be cancelled
+    pub model_downloads: Arc<RwLock<HashMap<Uuid, ModelDownload>>>,
+    /// Token the UI presents to the WebSocket server, minted at startup
+    pub websocket_token: String,
+    /// Topic messages for WebSocket clients, relayed to those subscribed to them
+    pub websocket_events: Arc<Broadcaster>,
+    /// Settings as last saved; `paths.data_dir` and `sandbox` changes only apply after a restart
+    pub settings: Arc<RwLock<Settings>>,
+    /// CPU, memory and disk sampling for `get_resource_usage` and `resource-usage` events
+    pub resources: Arc<ResourceSampler>,
+    /// Native notifications for approvals and security alerts while the app is in the background
+    pub notifier: Arc<Notifier>,
+    /// `hybridllm://` links waiting for the UI to collect them
+    pub deep_links: Arc<DeepLinks>,
+    /// Microphone capture and local transcription for voice input
+    pub voice: Arc<VoiceInput>,
+    /// Pairing token of the local bridge the companion browser extension talks to
+    pub browser_bridge: Arc<BrowserBridge>,
+    /// API key of the local OpenAI-compatible server
+    pub openai_api: Arc<OpenAiApi>,
+    /// Benchmark runs, kept with the app's data
+    pub benchmarks: Arc<BenchmarkStore>,
+    /// A/B evaluation reports, kept with the app's data
+    pub evaluations: Arc<EvaluationStore>,
+    /// Every finished completion, for cost and usage analytics
+    pub usage: Arc<UsageLedger>,
+    /// Loaded LLMs, open conversations and pending approvals, restored on the next launch
+    pub session: Arc<SessionStore>,
+    /// Whether local models may save conversations' KV caches to disk, which they would hold in plaintext
+    pub kv_persistence: Arc<AtomicBool>,
+    /// Set once `kv_persistence` must stay off for the rest of the run, as replies in a profile that encrypts
+    /// its content may still be finishing after switching away from it
+    kv_persistence_revoked: std::sync::Mutex<bool>,
 }
 
 impl AppState {
-    pub fn new() -> Self {
-        Self {
-            llm_pool: Arc::new(RwLock::new(LLMPool::new())),
-            security_engine: Arc::new(SecurityEngineImpl::new()),
-            permissions: Arc::new(RwLock::new(PermissionScope::default())),
+    pub fn new() -> common::errors::Result<Self> {
+        let settings = settings::load_or_default(Path::new(settings::SETTINGS_FILE));
+        // Kept with the app's data so the audit trail survives restarts
+        let audit = AuditLogger::open(&settings.paths.data_dir.join("audit.log"))?;
+        let security_engine = Arc::new(SecurityEngineImpl::new().with_audit_logger(audit));
+        security_engine.set_max_failed_requests(settings.security.max_failed_requests);
+        security_engine.set_jailbreak_action(settings.security.jailbreak_action);
+        security_engine.set_offline(settings.offline);
+
+        // Spending is read back from the ledger, so the monthly budget holds across restarts
+        let usage = Arc::new(UsageLedger::open(&settings.paths.data_dir.join("usage.jsonl"))?);
+        let llm_pool = LLMPool::new()
+            .with_usage_ledger(Arc::clone(&usage))
+            .with_prompt_guard(Arc::clone(&security_engine) as Arc<dyn PromptGuard>);
+        llm_pool.set_offline(settings.offline);
+        let benchmarks = BenchmarkStore::open(&settings.paths.data_dir.join("benchmarks.jsonl"))?;
+        let evaluations = EvaluationStore::open(&settings.paths.data_dir.join("evaluations.jsonl"))?;
+        let session = SessionStore::open(&settings.paths.data_dir.join(session::SESSION_FILE));
+        if settings.sandbox.backend == SandboxBackend::Firecracker {
+            warn!("⚠️  Firecracker sandboxes are not available yet, running sandboxes as processes");
+        }
+        let sandbox_manager = SandboxManager::new(settings.paths.data_dir.join("sandboxes"))?
+            .with_max_sandboxes(settings.sandbox.max_sandboxes)
+            .with_max_queued(settings.sandbox.max_queued)
+            .with_gpu_allocator(llm_pool.governor())
+            .with_security_engine(Arc::clone(&security_engine) as Arc<dyn SecurityEngine>);
+        let filesystem = FileSystemInterface::new(&settings.paths.data_dir)?;
+        // Keeps file listings served from the metadata index instead of rescanning
+        for folder in ManagedFolder::ALL {
+            filesystem.watch_folder(folder, |path| debug!("📝 Changed on disk: {:?}", path))?;
+        }
+        let plugins = PluginHost::discover(PluginConfig {
+            plugins_dir: settings.paths.plugins_dir.clone(),
+            folders: ManagedFolder::ALL
+                .into_iter()
+                .map(|folder| (folder.name().to_string(), filesystem.folder_path(folder).to_path_buf()))
+                .collect(),
+            ..Default::default()
+        })?;
+        for analyzer in plugins.analyzers() {
+            security_engine.add_analyzer(analyzer);
+        }
+
+        let profiles = Profiles::open(&settings.paths.data_dir.join(profiles::PROFILES_FILE))?;
+        let profile = profiles.active();
+        let context = profiles.context(&profile.name)?;
+        info!("👤 Using profile {}", profile.name);
+
+        Ok(Self {
+            llm_pool: Arc::new(RwLock::new(llm_pool)),
+            security_engine,
+            permissions: Arc::new(RwLock::new(profile.permissions)),
             documents: Arc::new(RwLock::new(Vec::new())),
-            audit_log: Arc::new(RwLock::new(Vec::new())),
+            trashed_documents: Arc::new(RwLock::new(HashMap::new())),
+            index_queue: Arc::new(IndexQueue::default()),
+            recrawler: Arc::new(Recrawler::new(Arc::new(PageFetcher::new(
+                (settings.fetch.max_kb * 1024) as usize,
+                std::time::Duration::from_secs(settings.fetch.timeout_secs),
+                settings.fetch.respect_robots,
+            )))),
+            sandbox_manager: Arc::new(sandbox_manager),
+            filesystem: Arc::new(filesystem),
+            plugins: Arc::new(plugins),
+            context: std::sync::RwLock::new(context),
+            profiles: Arc::new(profiles),
+            message_streams: Arc::new(RwLock::new(HashMap::new())),
+            model_downloads: Arc::new(RwLock::new(HashMap::new())),
+            websocket_token: crate::websocket::mint_session_token(),
+            websocket_events: Arc::new(Broadcaster::default()),
+            settings: Arc::new(RwLock::new(settings)),
+            resources: Arc::new(ResourceSampler::new()),
+            notifier: Arc::new(Notifier::default()),
+            deep_links: Arc::new(DeepLinks::default()),
+            voice: Arc::new(VoiceInput::default()),
+            browser_bridge: Arc::new(BrowserBridge::default()),
+            openai_api: Arc::new(OpenAiApi::default()),
+            benchmarks: Arc::new(benchmarks),
+            evaluations: Arc::new(evaluations),
+            usage,
+            session: Arc::new(session),
+            // Off until `allow_kv_persistence` has checked the active profile
+            kv_persistence: Arc::new(AtomicBool::new(false)),
+            kv_persistence_revoked: std::sync::Mutex::new(false),
+        })
+    }
+
+    /// Let local models save KV caches to disk unless the active profile encrypts its content, e.g. at startup
+    pub async fn allow_kv_persistence(&self) {
+        if self.encrypts_content(&self.profiles.active().name).await {
+            self.revoke_kv_persistence();
+            return;
+        }
+        let revoked = self.kv_persistence_revoked.lock().unwrap();
+        if !*revoked {
+            self.kv_persistence.store(true, Ordering::SeqCst);
         }
     }
 
+    /// Keep local models' KV caches off disk for the rest of the run
+    pub fn revoke_kv_persistence(&self) {
+        *self.kv_persistence_revoked.lock().unwrap() = true;
+        self.kv_persistence.store(false, Ordering::SeqCst);
+    }
+
+    /// Whether a profile encrypts its content at rest, taken to be so when its database can't tell
+    async fn encrypts_content(&self, name: &str) -> bool {
+        match self.profiles.database(name) {
+            Some(database) => database.encryption_enabled().await.unwrap_or(true),
+            None => false,
+        }
+    }
+
+    /// The active profile's conversation store
+    pub fn context(&self) -> Arc<dyn ContextManager> {
+        Arc::clone(&self.context.read().unwrap())
+    }
+
+    /// Swap in another profile's conversations, documents, permissions and provider keys
+    /// Sensitive profiles need their passphrase; replies still streaming finish in the profile they started in
+    pub async fn switch_profile(&self, name: &str, passphrase: Option<&str>) -> common::errors::Result<Profile> {
+        let profile = self.profiles.get(name)?;
+        let current = self.profiles.active();
+        if profile.name == current.name {
+            return Ok(profile);
+        }
+        if !self.security_engine.lockdown_state().await?.allows_reads() {
+            return Err(HybridLLMError::LockdownActive("Profiles cannot be switched during a lockdown".to_string()));
+        }
+        if !self.profiles.check_passphrase(&profile, passphrase).await? {
+            self.security_engine
+                .audit()
+                .log(
+                    None,
+                    "Profile switch".to_string(),
+                    json!({ "from": current.name, "to": profile.name }),
+                    false,
+                    Some("Wrong passphrase".to_string()),
+                )
+                .await;
+            return Err(HybridLLMError::PermissionDenied(format!("Wrong passphrase for profile {}", profile.name)));
+        }
+
+        // Opening a store reads its content key from the keyring, which can block
+        let profiles = Arc::clone(&self.profiles);
+        let name = profile.name.clone();
+        let context = tokio::task::spawn_blocking(move || profiles.context(&name))
+            .await
+            .map_err(|e| HybridLLMError::Other(e.into()))??;
+        if self.encrypts_content(&profile.name).await {
+            self.revoke_kv_persistence();
+        }
+        self.profiles.set_active(&profile.name)?;
+        *self.context.write().unwrap() = context;
+        *self.permissions.write().await = profile.permissions.clone();
+        let incoming = self.profiles.take_stash(&profile.name);
+        let documents = std::mem::replace(&mut *self.documents.write().await, incoming.documents);
+        let trashed = std::mem::replace(&mut *self.trashed_documents.write().await, incoming.trashed);
+        self.profiles.stash(&current.name, StashedDocuments { documents, trashed });
+
+        let providers = self.settings.read().await.providers.clone();
+        crate::keys::register_all(&*self.llm_pool.read().await, &profile.name, &providers).await;
+        // The open conversations belonged to the other profile
+        if let Err(e) = self.session.update(|session| {
+            session.open_conversations.clear();
+            session.active_conversation = None;
+        }) {
+            warn!("⚠️  Could not save the session: {}", e);
+        }
+
+        self.security_engine
+            .audit()
+            .log(
+                None,
+                "Profile switch".to_string(),
+                json!({ "from": current.name, "to": profile.name, "sensitive": profile.sensitive }),
+                true,
+                None,
+            )
+            .await;
+        info!("👤 Switched from profile {} to {}", current.name, profile.name);
+        Ok(profile)
+    }
+
+    /// Go offline or back online, keeping the switch in the settings so it holds across restarts
+    pub async fn set_offline(&self, offline: bool) -> common::errors::Result<()> {
+        let mut settings = self.settings.write().await;
+        if settings.offline != offline {
+            let updated = Settings { offline, ..settings.clone() };
+            updated.save(Path::new(settings::SETTINGS_FILE))?;
+            *settings = updated;
+        }
+        self.llm_pool.read().await.set_offline(offline);
+        self.security_engine.set_offline(offline);
+        Ok(())
+    }
+
     pub async fn get_system_state(&self) -> SystemState {
         let pool = self.llm_pool.read().await;
         let lockdown = self.security_engine
@@ -62,13 +349,25 @@ impl AppState {
             .await
             .unwrap_or(LockdownState::Normal);
 
+        let llms = pool.status();
+        let budget = BudgetState {
+            monthly_cloud_usd: self.settings.read().await.budgets.monthly_cloud_usd,
+            spent_usd: llms.iter().filter(|llm| llm.cloud).map(|llm| llm.spent_usd).sum(),
+        };
+
         SystemState {
+            profile: self.profiles.active().name,
             lockdown,
-            active_llms: pool.get_all_loaded()
-                .iter()
-                .map(|llm| llm.instance().id.clone())
+            active_llms: llms.iter()
+                .filter(|llm| llm.loaded)
+                .map(|llm| llm.llm_id.clone())
                 .collect(),
-            pending_approvals: 0, // TODO: Track pending approvals
+            llms,
+            pending_approvals: self.security_engine.pending_approvals().await.len(),
+            cloud_paused: pool.cloud_paused(),
+            offline: pool.offline(),
+            unavailable_capabilities: pool.unavailable_capabilities(),
+            budget,
         }
     }
 }
@@ -0,0 +1,282 @@
+use chrono::{DateTime, Utc};
+use common::types::{Conversation, Message, MessageRole};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Write;
+use uuid::Uuid;
+
+/// Formats a conversation can be exported in
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    Markdown,
+    Json,
+    Html,
+}
+
+impl ExportFormat {
+    pub fn extension(self) -> &'static str {
+        match self {
+            Self::Markdown => "md",
+            Self::Json => "json",
+            Self::Html => "html",
+        }
+    }
+}
+
+/// A source a reply drew on, recorded in the message's `citations` metadata
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Citation {
+    /// Document name or URL
+    pub source: String,
+    #[serde(default)]
+    pub document_id: Option<Uuid>,
+    #[serde(default)]
+    pub excerpt: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TranscriptMessage {
+    pub role: MessageRole,
+    /// LLM that wrote the reply; `None` for the user's messages
+    pub llm_id: Option<String>,
+    pub model: Option<String>,
+    pub timestamp: DateTime<Utc>,
+    pub content: String,
+    pub citations: Vec<Citation>,
+}
+
+/// Replies and spending of one LLM, from the usage recorded with its replies
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ModelCost {
+    pub llm_id: String,
+    pub replies: usize,
+    /// `None` when no reply recorded it
+    pub tokens: Option<u64>,
+    pub cost_usd: Option<f64>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Transcript {
+    pub conversation: Conversation,
+    pub exported_at: DateTime<Utc>,
+    pub messages: Vec<TranscriptMessage>,
+    pub cost: Vec<ModelCost>,
+    /// Across all models; `None` when no reply recorded a cost
+    pub total_cost_usd: Option<f64>,
+}
+
+impl Transcript {
+    /// `models` maps LLM ids to model names for the LLMs still registered
+    pub fn new(conversation: Conversation, messages: Vec<Message>, models: &HashMap<String, String>) -> Self {
+        let mut cost: BTreeMap<String, ModelCost> = BTreeMap::new();
+        let messages: Vec<TranscriptMessage> = messages
+            .into_iter()
+            .map(|message| {
+                let llm_id = message.metadata.get("llm_id").and_then(|v| v.as_str()).map(str::to_string);
+                if let Some(llm_id) = &llm_id {
+                    let model = cost.entry(llm_id.clone()).or_insert_with(|| ModelCost {
+                        llm_id: llm_id.clone(),
+                        ..Default::default()
+                    });
+                    model.replies += 1;
+                    if let Some(usage) = message.usage() {
+                        *model.tokens.get_or_insert(0) += usage.total_tokens();
+                        if let Some(usd) = usage.cost_usd {
+                            *model.cost_usd.get_or_insert(0.0) += usd;
+                        }
+                    }
+                }
+
+                TranscriptMessage {
+                    model: llm_id.as_ref().map(|id| models.get(id).cloned().unwrap_or_else(|| id.clone())),
+                    llm_id,
+                    role: message.role,
+                    timestamp: message.timestamp,
+                    citations: message
+                        .metadata
+                        .get("citations")
+                        .and_then(|v| serde_json::from_value(v.clone()).ok())
+                        .unwrap_or_default(),
+                    // Images, tool calls and attached files are left out of the export
+                    content: message.content.text().into_owned(),
+                }
+            })
+            .collect();
+
+        let cost: Vec<ModelCost> = cost.into_values().collect();
+        let total_cost_usd = cost
+            .iter()
+            .filter_map(|model| model.cost_usd)
+            .fold(None, |total: Option<f64>, usd| Some(total.unwrap_or(0.0) + usd));
+
+        Self { conversation, exported_at: Utc::now(), messages, cost, total_cost_usd }
+    }
+
+    /// File name in the downloads folder, e.g. `conversation-log-rotation-20240101-120000.md`
+    pub fn filename(&self, format: ExportFormat) -> String {
+        let mut slug = String::new();
+        for c in self.conversation.title.chars().flat_map(char::to_lowercase) {
+            if c.is_ascii_alphanumeric() {
+                slug.push(c);
+            } else if !slug.is_empty() && !slug.ends_with('-') {
+                slug.push('-');
+            }
+        }
+        let slug: String = slug.trim_end_matches('-').chars().take(40).collect();
+        let slug = slug.trim_end_matches('-');
+
+        format!(
+            "conversation-{}{}{}.{}",
+            slug,
+            if slug.is_empty() { "" } else { "-" },
+            self.exported_at.format("%Y%m%d-%H%M%S"),
+            format.extension()
+        )
+    }
+
+    pub fn render(&self, format: ExportFormat) -> String {
+        match format {
+            ExportFormat::Markdown => self.markdown(),
+            ExportFormat::Json => serde_json::to_string_pretty(self).unwrap_or_default(),
+            ExportFormat::Html => self.html(),
+        }
+    }
+
+    fn speaker(message: &TranscriptMessage) -> String {
+        match message.role {
+            MessageRole::User => "You".to_string(),
+            MessageRole::System => "System".to_string(),
+            MessageRole::Assistant => message.model.clone().unwrap_or_else(|| "Assistant".to_string()),
+        }
+    }
+
+    fn markdown(&self) -> String {
+        let mut out = String::new();
+        let _ = writeln!(out, "# {}\n", self.conversation.title);
+        let _ = writeln!(
+            out,
+            "_Exported {} · {} messages_\n",
+            self.exported_at.format("%Y-%m-%d %H:%M UTC"),
+            self.messages.len()
+        );
+
+        for message in &self.messages {
+            let _ = writeln!(
+                out,
+                "## {} · {}\n",
+                Self::speaker(message),
+                message.timestamp.format("%Y-%m-%d %H:%M:%S UTC")
+            );
+            let _ = writeln!(out, "{}\n", message.content.trim_end());
+            if !message.citations.is_empty() {
+                let _ = writeln!(out, "**Sources**\n");
+                for (i, citation) in message.citations.iter().enumerate() {
+                    let _ = write!(out, "{}. {}", i + 1, citation.source);
+                    if let Some(excerpt) = &citation.excerpt {
+                        let _ = write!(out, ": \"{}\"", excerpt.trim());
+                    }
+                    out.push('\n');
+                }
+                out.push('\n');
+            }
+        }
+
+        let _ = writeln!(out, "## Cost summary\n");
+        let _ = writeln!(out, "| Model | Replies | Tokens | Cost (USD) |");
+        let _ = writeln!(out, "|-------|---------|--------|------------|");
+        for model in &self.cost {
+            let _ = writeln!(
+                out,
+                "| {} | {} | {} | {} |",
+                model.llm_id,
+                model.replies,
+                model.tokens.map_or("-".to_string(), |t| t.to_string()),
+                format_usd(model.cost_usd)
+            );
+        }
+        let _ = writeln!(out, "| **Total** | | | {} |", format_usd(self.total_cost_usd));
+        out
+    }
+
+    fn html(&self) -> String {
+        let mut out = String::new();
+        let _ = write!(
+            out,
+            "<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n<meta charset=\"utf-8\">\n<title>{}</title>\n\
+             <style>body{{font-family:system-ui,sans-serif;max-width:50rem;margin:2rem auto;padding:0 1rem}}\
+             .message{{margin:1.5rem 0}}.meta{{color:#666;font-size:.85rem}}\
+             .content{{white-space:pre-wrap}}table{{border-collapse:collapse}}\
+             td,th{{border:1px solid #ccc;padding:.25rem .75rem;text-align:left}}</style>\n</head>\n<body>\n",
+            escape(&self.conversation.title)
+        );
+        let _ = writeln!(out, "<h1>{}</h1>", escape(&self.conversation.title));
+        let _ = writeln!(
+            out,
+            "<p class=\"meta\">Exported {} · {} messages</p>",
+            self.exported_at.format("%Y-%m-%d %H:%M UTC"),
+            self.messages.len()
+        );
+
+        for message in &self.messages {
+            let _ = writeln!(out, "<section class=\"message\">");
+            let _ = writeln!(
+                out,
+                "<p class=\"meta\"><strong>{}</strong> · {}</p>",
+                escape(&Self::speaker(message)),
+                message.timestamp.format("%Y-%m-%d %H:%M:%S UTC")
+            );
+            let _ = writeln!(out, "<div class=\"content\">{}</div>", escape(message.content.trim_end()));
+            if !message.citations.is_empty() {
+                let _ = writeln!(out, "<ol class=\"meta\">");
+                for citation in &message.citations {
+                    let _ = write!(out, "<li>{}", escape(&citation.source));
+                    if let Some(excerpt) = &citation.excerpt {
+                        let _ = write!(out, ": “{}”", escape(excerpt.trim()));
+                    }
+                    let _ = writeln!(out, "</li>");
+                }
+                let _ = writeln!(out, "</ol>");
+            }
+            let _ = writeln!(out, "</section>");
+        }
+
+        let _ = writeln!(out, "<h2>Cost summary</h2>\n<table>");
+        let _ = writeln!(out, "<tr><th>Model</th><th>Replies</th><th>Tokens</th><th>Cost (USD)</th></tr>");
+        for model in &self.cost {
+            let _ = writeln!(
+                out,
+                "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>",
+                escape(&model.llm_id),
+                model.replies,
+                model.tokens.map_or("-".to_string(), |t| t.to_string()),
+                format_usd(model.cost_usd)
+            );
+        }
+        let _ = writeln!(
+            out,
+            "<tr><th>Total</th><td></td><td></td><th>{}</th></tr>\n</table>\n</body>\n</html>",
+            format_usd(self.total_cost_usd)
+        );
+        out
+    }
+}
+
+fn format_usd(usd: Option<f64>) -> String {
+    usd.map_or("not recorded".to_string(), |usd| format!("${:.4}", usd))
+}
+
+fn escape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&#39;"),
+            c => out.push(c),
+        }
+    }
+    out
+}
@@ -0,0 +1,150 @@
+use common::types::LockdownState;
+use std::time::Duration;
+use tauri::{
+    AppHandle, CustomMenuItem, Manager, SystemTray, SystemTrayEvent, SystemTrayMenu, SystemTrayMenuItem,
+};
+use tracing::{error, info};
+
+use crate::panic;
+use crate::security_window;
+use crate::state::{AppState, SystemState};
+
+/// Tauri event carrying a fresh `SystemState` every few seconds and after the tray or the panic
+/// hotkey changed it
+pub const SYSTEM_STATE_EVENT: &str = "system-state";
+
+/// How often the tray and the UI pick up changes made elsewhere
+const REFRESH_INTERVAL: Duration = Duration::from_secs(2);
+
+const STATUS: &str = "status";
+const PANIC: &str = "panic";
+const PAUSE_CLOUD: &str = "pause_cloud";
+const OFFLINE: &str = "offline";
+const OPEN_DASHBOARD: &str = "open_dashboard";
+const OPEN_SECURITY: &str = "open_security";
+const QUIT: &str = "quit";
+
+/// Tray icon with the safety controls, so they stay reachable while the window is closed
+pub fn build() -> SystemTray {
+    let menu = SystemTrayMenu::new()
+        .add_item(CustomMenuItem::new(STATUS, "Starting…").disabled())
+        .add_native_item(SystemTrayMenuItem::Separator)
+        .add_item(CustomMenuItem::new(PANIC, "🚨 Panic lockdown"))
+        .add_item(CustomMenuItem::new(PAUSE_CLOUD, "Pause cloud providers"))
+        .add_item(CustomMenuItem::new(OFFLINE, "Go offline"))
+        .add_item(CustomMenuItem::new(OPEN_DASHBOARD, "Open dashboard"))
+        .add_item(CustomMenuItem::new(OPEN_SECURITY, "Open security window"))
+        .add_native_item(SystemTrayMenuItem::Separator)
+        .add_item(CustomMenuItem::new(QUIT, "Quit"));
+
+    SystemTray::new()
+        .with_menu(menu)
+        .with_tooltip("Hybrid LLM Platform")
+}
+
+/// Runs on the event loop, so anything async is handed to Tauri's runtime
+pub fn handle_event(app: &AppHandle, event: SystemTrayEvent) {
+    match event {
+        SystemTrayEvent::LeftClick { .. } => show_dashboard(app),
+        SystemTrayEvent::MenuItemClick { id, .. } => match id.as_str() {
+            PANIC => {
+                let app = app.clone();
+                tauri::async_runtime::spawn(async move { panic::panic_lockdown(&app, "tray").await });
+            }
+            PAUSE_CLOUD => {
+                let app = app.clone();
+                tauri::async_runtime::spawn(async move {
+                    {
+                        let state = app.state::<AppState>();
+                        let pool = state.llm_pool.read().await;
+                        pool.pause_cloud(!pool.cloud_paused());
+                    }
+                    changed(&app).await;
+                });
+            }
+            OFFLINE => {
+                let app = app.clone();
+                tauri::async_runtime::spawn(async move {
+                    {
+                        let state = app.state::<AppState>();
+                        let offline = !state.llm_pool.read().await.offline();
+                        if let Err(e) = state.set_offline(offline).await {
+                            error!("❌ Could not switch offline mode: {}", e);
+                        }
+                    }
+                    changed(&app).await;
+                });
+            }
+            OPEN_DASHBOARD => show_dashboard(app),
+            OPEN_SECURITY => {
+                // Building a window on the event loop thread deadlocks on Windows
+                let app = app.clone();
+                tauri::async_runtime::spawn(async move {
+                    if let Err(e) = security_window::open(&app) {
+                        error!("❌ Could not open the security window: {}", e);
+                    }
+                });
+            }
+            QUIT => {
+                info!("👋 Quitting from the tray");
+                app.exit(0);
+            }
+            _ => {}
+        },
+        _ => {}
+    }
+}
+
+/// Keep the tray's status line and the UI's request counts current with changes made from the UI,
+/// by the security engine or by requests starting and finishing
+pub fn spawn_refresher(app: AppHandle) {
+    tokio::spawn(async move {
+        loop {
+            changed(&app).await;
+            tokio::time::sleep(REFRESH_INTERVAL).await;
+        }
+    });
+}
+
+async fn refresh(app: &AppHandle) -> SystemState {
+    let state = app.state::<AppState>();
+    let system = state.get_system_state().await;
+
+    let lockdown = match system.lockdown {
+        LockdownState::Normal => "🟢 Normal",
+        LockdownState::ReadOnly => "🟡 Read-only",
+        LockdownState::Locked => "🔴 Locked down",
+    };
+    let loaded = system.active_llms.len();
+    let mut status = format!("{} · {} model{} loaded", lockdown, loaded, if loaded == 1 { "" } else { "s" });
+    if system.offline {
+        status.push_str(" · 📴 Offline");
+    }
+
+    let tray = app.tray_handle();
+    let _ = tray.set_tooltip(&format!("Hybrid LLM Platform: {}", status));
+    let _ = tray.get_item(STATUS).set_title(status);
+    let _ = tray.get_item(PANIC).set_enabled(system.lockdown != LockdownState::Locked);
+    let _ = tray.get_item(PAUSE_CLOUD).set_title(if system.cloud_paused {
+        "Resume cloud providers"
+    } else {
+        "Pause cloud providers"
+    });
+    let _ = tray.get_item(PAUSE_CLOUD).set_enabled(!system.offline);
+    let _ = tray.get_item(OFFLINE).set_title(if system.offline { "Go online" } else { "Go offline" });
+    system
+}
+
+/// Update the tray right away and tell the UI, which may be hidden but still running
+pub async fn changed(app: &AppHandle) {
+    let system = refresh(app).await;
+    let _ = app.emit_all(SYSTEM_STATE_EVENT, system);
+}
+
+pub fn show_dashboard(app: &AppHandle) {
+    if let Some(window) = app.get_window("main") {
+        let _ = window.show();
+        let _ = window.unminimize();
+        let _ = window.set_focus();
+    }
+}
@@ -0,0 +1,108 @@
+use common::errors::{HybridLLMError, Result};
+use filesystem_interface::SkippedEntry;
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::path::PathBuf;
+use tokio::sync::Mutex;
+use uuid::Uuid;
+
+/// Tauri event carrying `UploadProgress` while dropped files are copied
+pub const UPLOAD_PROGRESS_EVENT: &str = "upload-progress";
+
+/// Most files one drop may add, so a mistaken drop of a home directory fails fast
+pub const MAX_UPLOAD_FILES: usize = 5000;
+
+/// A file found under a dropped path
+#[derive(Debug, Clone)]
+pub struct DroppedFile {
+    pub source: PathBuf,
+    /// Path in the uploads folder, starting with the dropped folder's name
+    pub name: String,
+    pub size_bytes: u64,
+}
+
+/// Progress across every file of one upload
+#[derive(Debug, Clone, Serialize)]
+pub struct UploadProgress {
+    pub upload_id: Uuid,
+    pub files_total: usize,
+    /// Uploaded or skipped so far
+    pub files_done: usize,
+    pub bytes_total: u64,
+    pub bytes_done: u64,
+    /// File being copied; `None` once the upload is finished
+    pub current: Option<String>,
+}
+
+/// Files under the dropped `paths`, which may be files or folders
+/// Hidden files and folders are left out, and symlinks are skipped rather than followed
+pub fn enumerate(paths: &[PathBuf]) -> Result<(Vec<DroppedFile>, Vec<SkippedEntry>)> {
+    let mut files = Vec::new();
+    let mut skipped = Vec::new();
+
+    for path in paths {
+        // Names are relative to the parent, so a dropped folder keeps its own name
+        let parent = path.parent().unwrap_or(path);
+        let entries = walkdir::WalkDir::new(path)
+            .sort_by_file_name()
+            .into_iter()
+            .filter_entry(|entry| entry.depth() == 0 || !entry.file_name().to_string_lossy().starts_with('.'));
+
+        for entry in entries {
+            let entry = match entry {
+                Ok(entry) => entry,
+                Err(e) => {
+                    let path = e.path().unwrap_or(path);
+                    skipped.push(SkippedEntry { path: path.display().to_string(), reason: e.to_string() });
+                    continue;
+                }
+            };
+            let name = entry.path().strip_prefix(parent).unwrap_or(entry.path()).to_string_lossy().into_owned();
+
+            if entry.path_is_symlink() {
+                skipped.push(SkippedEntry { path: name, reason: "symbolic links are not followed".to_string() });
+                continue;
+            }
+            if !entry.file_type().is_file() {
+                continue;
+            }
+            let size_bytes = match entry.metadata() {
+                Ok(metadata) => metadata.len(),
+                Err(e) => {
+                    skipped.push(SkippedEntry { path: name, reason: e.to_string() });
+                    continue;
+                }
+            };
+
+            files.push(DroppedFile { source: entry.into_path(), name, size_bytes });
+            if files.len() > MAX_UPLOAD_FILES {
+                return Err(HybridLLMError::InvalidRequest(format!(
+                    "More than {} files dropped; upload fewer at a time",
+                    MAX_UPLOAD_FILES
+                )));
+            }
+        }
+    }
+
+    Ok((files, skipped))
+}
+
+/// Uploaded documents waiting to be indexed for RAG, oldest first
+#[derive(Default)]
+pub struct IndexQueue {
+    pending: Mutex<VecDeque<Uuid>>,
+}
+
+impl IndexQueue {
+    /// Queue documents behind those already waiting; returns how many are waiting now
+    pub async fn push(&self, documents: impl IntoIterator<Item = Uuid>) -> usize {
+        let mut pending = self.pending.lock().await;
+        pending.extend(documents);
+        pending.len()
+    }
+
+    /// Drop a document that no longer needs indexing, e.g. because it was deleted
+    pub async fn remove(&self, document_id: &Uuid) {
+        self.pending.lock().await.retain(|id| id != document_id);
+    }
+}
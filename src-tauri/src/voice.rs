@@ -0,0 +1,222 @@
+use common::errors::{HybridLLMError, Result};
+use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+use cpal::{FromSample, SizedSample};
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+use std::sync::{mpsc, Arc, Mutex};
+use std::time::{Duration, Instant};
+use tracing::{error, info};
+use whisper_rs::{FullParams, SamplingStrategy, WhisperContext, WhisperContextParameters};
+
+/// Longest recording kept; capture stops by itself after this
+const MAX_RECORDING: Duration = Duration::from_secs(300);
+
+/// Sample rate Whisper expects
+const WHISPER_SAMPLE_RATE: u32 = 16_000;
+
+/// Text transcribed from one recording
+#[derive(Debug, Clone, Serialize)]
+pub struct VoiceTranscript {
+    pub text: String,
+    pub duration_ms: u64,
+}
+
+/// Mono audio captured from the microphone
+struct Audio {
+    samples: Vec<f32>,
+    sample_rate: u32,
+}
+
+/// A capture running on its own thread, which owns the input stream because streams aren't `Send` everywhere
+struct Recording {
+    stop: mpsc::Sender<()>,
+    thread: std::thread::JoinHandle<()>,
+    samples: Arc<Mutex<Vec<f32>>>,
+    sample_rate: u32,
+}
+
+/// Microphone capture and on-device transcription; audio never leaves the machine
+#[derive(Default)]
+pub struct VoiceInput {
+    recording: Mutex<Option<Recording>>,
+    /// Loaded Whisper model, kept between transcriptions
+    model: Mutex<Option<(PathBuf, Arc<WhisperContext>)>>,
+}
+
+impl VoiceInput {
+    /// Start capturing from the default input device
+    pub fn start(&self) -> Result<()> {
+        let mut recording = self.recording.lock().unwrap();
+        if recording.is_some() {
+            return Err(HybridLLMError::InvalidRequest("Already recording".to_string()));
+        }
+
+        let samples = Arc::new(Mutex::new(Vec::new()));
+        let (stop, stopped) = mpsc::channel();
+        let (ready, started) = mpsc::channel();
+        let buffer = Arc::clone(&samples);
+        let thread = std::thread::spawn(move || match open_input(buffer) {
+            Ok((stream, sample_rate)) => {
+                let _ = ready.send(Ok(sample_rate));
+                // Ends on stop, on cancel (the sender is dropped) or at the time limit
+                let _ = stopped.recv_timeout(MAX_RECORDING);
+                drop(stream);
+            }
+            Err(e) => {
+                let _ = ready.send(Err(e));
+            }
+        });
+
+        let sample_rate = started
+            .recv()
+            .map_err(|_| HybridLLMError::Other(anyhow::anyhow!("Microphone capture thread exited")))??;
+        info!("🎙️  Recording from the microphone at {} Hz", sample_rate);
+        *recording = Some(Recording { stop, thread, samples, sample_rate });
+        Ok(())
+    }
+
+    /// Stop capturing and return what was recorded
+    fn stop(&self) -> Result<Audio> {
+        let recording = self
+            .recording
+            .lock()
+            .unwrap()
+            .take()
+            .ok_or_else(|| HybridLLMError::InvalidRequest("Not recording".to_string()))?;
+
+        let _ = recording.stop.send(());
+        let _ = recording.thread.join();
+        let samples = std::mem::take(&mut *recording.samples.lock().unwrap());
+        Ok(Audio { samples, sample_rate: recording.sample_rate })
+    }
+
+    /// Stop capturing and discard the audio; returns whether anything was being recorded
+    pub fn cancel(&self) -> bool {
+        let cancelled = self.stop().is_ok();
+        if cancelled {
+            info!("🎙️  Recording discarded");
+        }
+        cancelled
+    }
+
+    /// Stop capturing and transcribe the recording with the Whisper model at `model_path`
+    /// Blocks while transcribing; call it from a blocking task
+    pub fn stop_and_transcribe(&self, model_path: &Path, language: Option<&str>) -> Result<VoiceTranscript> {
+        let audio = self.stop()?;
+        let duration_ms = audio.samples.len() as u64 * 1000 / u64::from(audio.sample_rate.max(1));
+        if audio.samples.is_empty() {
+            return Ok(VoiceTranscript { text: String::new(), duration_ms });
+        }
+
+        let started = Instant::now();
+        let model = self.model(model_path)?;
+        let whisper_err = |e: whisper_rs::WhisperError| HybridLLMError::Other(anyhow::anyhow!("Transcription failed: {}", e));
+        let mut state = model.create_state().map_err(whisper_err)?;
+
+        let mut params = FullParams::new(SamplingStrategy::Greedy { best_of: 1 });
+        params.set_language(Some(language.unwrap_or("auto")));
+        params.set_print_progress(false);
+        params.set_print_realtime(false);
+        params.set_print_special(false);
+        params.set_print_timestamps(false);
+        state
+            .full(params, &resample(&audio.samples, audio.sample_rate, WHISPER_SAMPLE_RATE))
+            .map_err(whisper_err)?;
+
+        let mut text = String::new();
+        for segment in 0..state.full_n_segments().map_err(whisper_err)? {
+            text.push_str(&state.full_get_segment_text(segment).map_err(whisper_err)?);
+        }
+        info!("🎙️  Transcribed {} ms of audio in {:?}", duration_ms, started.elapsed());
+        Ok(VoiceTranscript { text: text.trim().to_string(), duration_ms })
+    }
+
+    fn model(&self, path: &Path) -> Result<Arc<WhisperContext>> {
+        let mut model = self.model.lock().unwrap();
+        if let Some((loaded, context)) = model.as_ref() {
+            if loaded == path {
+                return Ok(Arc::clone(context));
+            }
+        }
+
+        if !path.is_file() {
+            return Err(HybridLLMError::ConfigError(format!("Speech model not found: {}", path.display())));
+        }
+        info!("📥 Loading speech model from {:?}", path);
+        let context = WhisperContext::new_with_params(&path.to_string_lossy(), WhisperContextParameters::default())
+            .map_err(|e| HybridLLMError::ConfigError(format!("Could not load speech model {}: {}", path.display(), e)))?;
+        let context = Arc::new(context);
+        *model = Some((path.to_path_buf(), Arc::clone(&context)));
+        Ok(context)
+    }
+}
+
+/// Open the default input device, appending mono samples to `buffer` until the stream is dropped
+fn open_input(buffer: Arc<Mutex<Vec<f32>>>) -> Result<(cpal::Stream, u32)> {
+    let audio_err = |e: &dyn std::fmt::Display| HybridLLMError::Other(anyhow::anyhow!("Microphone: {}", e));
+
+    let device = cpal::default_host()
+        .default_input_device()
+        .ok_or_else(|| audio_err(&"no input device"))?;
+    let supported = device.default_input_config().map_err(|e| audio_err(&e))?;
+    let config: cpal::StreamConfig = supported.clone().into();
+    let sample_rate = config.sample_rate.0;
+    let max_samples = MAX_RECORDING.as_secs() as usize * sample_rate as usize;
+
+    let stream = match supported.sample_format() {
+        cpal::SampleFormat::F32 => build_input::<f32>(&device, &config, buffer, max_samples),
+        cpal::SampleFormat::I16 => build_input::<i16>(&device, &config, buffer, max_samples),
+        cpal::SampleFormat::U16 => build_input::<u16>(&device, &config, buffer, max_samples),
+        format => return Err(audio_err(&format!("unsupported sample format {:?}", format))),
+    }
+    .map_err(|e| audio_err(&e))?;
+    stream.play().map_err(|e| audio_err(&e))?;
+    Ok((stream, sample_rate))
+}
+
+fn build_input<S>(
+    device: &cpal::Device,
+    config: &cpal::StreamConfig,
+    buffer: Arc<Mutex<Vec<f32>>>,
+    max_samples: usize,
+) -> std::result::Result<cpal::Stream, cpal::BuildStreamError>
+where
+    S: SizedSample,
+    f32: FromSample<S>,
+{
+    let channels = usize::from(config.channels.max(1));
+    device.build_input_stream(
+        config,
+        move |data: &[S], _: &cpal::InputCallbackInfo| {
+            let mut buffer = buffer.lock().unwrap();
+            // Channels are averaged into one
+            for frame in data.chunks(channels) {
+                if buffer.len() >= max_samples {
+                    break;
+                }
+                let sum: f32 = frame.iter().map(|&sample| f32::from_sample(sample)).sum();
+                buffer.push(sum / frame.len() as f32);
+            }
+        },
+        |e| error!("❌ Microphone stream error: {}", e),
+        None,
+    )
+}
+
+/// Linear resampling; good enough for speech
+fn resample(samples: &[f32], from: u32, to: u32) -> Vec<f32> {
+    if from == to || samples.is_empty() {
+        return samples.to_vec();
+    }
+    let ratio = f64::from(from) / f64::from(to);
+    let len = (samples.len() as f64 / ratio) as usize;
+    (0..len)
+        .map(|i| {
+            let position = i as f64 * ratio;
+            let index = position as usize;
+            let next = samples.get(index + 1).copied().unwrap_or(samples[index]);
+            let fraction = (position - index as f64) as f32;
+            samples[index] + (next - samples[index]) * fraction
+        })
+        .collect()
+}
@@ -1,13 +1,49 @@
 use tauri::{AppHandle, Manager};
-use tokio::net::TcpListener;
-use tokio_tungstenite::accept_async;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::{broadcast, mpsc};
+use tokio_tungstenite::{
+    accept_hdr_async,
+    tungstenite::{
+        handshake::server::{ErrorResponse, Request, Response},
+        http::StatusCode,
+        Message,
+    },
+};
 use futures_util::{StreamExt, SinkExt};
 use serde::{Deserialize, Serialize};
-use tracing::{info, error, debug};
+use std::collections::{BTreeSet, HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tracing::{info, error, debug, warn};
+use uuid::Uuid;
+
+use common::{types::AuditLogEntry, SecurityEngine};
+use sandbox_manager::{ExecutionEvent, PtyEvent, PtyRecording, PtySession, SandboxEvent};
+
+use crate::models::ModelDownloadEvent;
+use crate::state::AppState;
+
+/// Event streams a client chooses to receive with `subscribe`
+/// PTY messages aren't a topic; they always go to the connection that opened the terminal
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Topic {
+    LlmStatus,
+    Audit,
+    Indexing,
+    Sandbox,
+    /// Model download progress
+    Models,
+}
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(tag = "type", rename_all = "snake_case")]
 pub enum WebSocketMessage {
+    /// Sent on connect and after every `subscribe` or `unsubscribe`, with the topics now received
+    Subscribed {
+        topics: Vec<Topic>,
+    },
     LlmStatus {
         llm_id: String,
         status: String,
@@ -18,30 +54,234 @@ pub enum WebSocketMessage {
         content: String,
         is_final: bool,
     },
+    /// Uploaded documents added to the index queue
+    DocumentsQueued {
+        document_ids: Vec<Uuid>,
+        /// Documents waiting to be indexed, these included
+        waiting: usize,
+    },
     DocumentIndexed {
         document_id: String,
         chunk_count: usize,
     },
     AuditLogEntry {
-        action: String,
-        approved: bool,
+        entry: AuditLogEntry,
     },
     LockdownTriggered {
         reason: String,
     },
+    Sandbox {
+        event: SandboxEvent,
+    },
+    /// Output of `execute_in_sandbox`, as also emitted in `sandbox-output` events
+    SandboxOutput {
+        execution_id: Uuid,
+        sandbox_id: Uuid,
+        event: ExecutionEvent,
+    },
+    /// Progress of `download_model`, as also emitted in `model-download` events
+    ModelDownload {
+        event: ModelDownloadEvent,
+    },
+    PtyOpened {
+        session_id: Uuid,
+        sandbox_id: Uuid,
+    },
+    PtyOutput {
+        session_id: Uuid,
+        data: String,
+    },
+    PtyExited {
+        session_id: Uuid,
+        exit_code: i32,
+    },
+    PtyError {
+        session_id: Option<Uuid>,
+        message: String,
+    },
+}
+
+impl WebSocketMessage {
+    /// The topic a client must subscribe to for this message; `None` for replies to the client itself
+    pub fn topic(&self) -> Option<Topic> {
+        match self {
+            Self::LlmStatus { .. } | Self::LlmResponse { .. } => Some(Topic::LlmStatus),
+            Self::AuditLogEntry { .. } | Self::LockdownTriggered { .. } => Some(Topic::Audit),
+            Self::DocumentsQueued { .. } | Self::DocumentIndexed { .. } => Some(Topic::Indexing),
+            Self::Sandbox { .. } | Self::SandboxOutput { .. } => Some(Topic::Sandbox),
+            Self::ModelDownload { .. } => Some(Topic::Models),
+            Self::Subscribed { .. }
+            | Self::PtyOpened { .. }
+            | Self::PtyOutput { .. }
+            | Self::PtyExited { .. }
+            | Self::PtyError { .. } => None,
+        }
+    }
+}
+
+/// Messages sent by WebSocket clients
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ClientMessage {
+    /// Start receiving messages of these topics; clients receive none until they subscribe
+    Subscribe {
+        topics: Vec<Topic>,
+    },
+    Unsubscribe {
+        topics: Vec<Topic>,
+    },
+    PtyOpen {
+        sandbox_id: Uuid,
+        cols: u16,
+        rows: u16,
+    },
+    PtyInput {
+        session_id: Uuid,
+        data: String,
+    },
+    PtyResize {
+        session_id: Uuid,
+        cols: u16,
+        rows: u16,
+    },
+    PtyClose {
+        session_id: Uuid,
+    },
+}
+
+/// Broadcast messages buffered per client; a client that falls further behind misses the oldest
+const BROADCAST_CAPACITY: usize = 256;
+
+/// Where the WebSocket server listens; share links are served over plain HTTP on the same port
+pub const SERVER_ADDR: &str = "127.0.0.1:3030";
+
+/// Longest request head accepted for a share link
+const MAX_SHARE_REQUEST_BYTES: usize = 8 * 1024;
+
+/// Page origins allowed to open the WebSocket: the bundled app and the dev server
+const ALLOWED_ORIGINS: &[&str] = &["tauri://localhost", "https://tauri.localhost", "http://localhost:1420"];
+
+/// Rejected WebSocket handshakes audited per window; later ones are refused unaudited
+/// Authorized handshakes never count, so failed attempts can't lock the app out
+const MAX_REJECTED_HANDSHAKES: usize = 30;
+const HANDSHAKE_WINDOW: Duration = Duration::from_secs(60);
+
+/// Where the app's WebSocket connects, and the token it must present as `?token=`
+#[derive(Debug, Clone, Serialize)]
+pub struct WebSocketSession {
+    pub url: String,
+    pub token: String,
+}
+
+/// Mint the token WebSocket clients must present; a new one every time the app starts
+pub fn mint_session_token() -> String {
+    format!("{}{}", Uuid::new_v4().simple(), Uuid::new_v4().simple())
+}
+
+/// Topic messages for every connected client, each relaying only the topics it subscribed to
+pub struct Broadcaster {
+    sender: broadcast::Sender<WebSocketMessage>,
+}
+
+impl Default for Broadcaster {
+    fn default() -> Self {
+        Self { sender: broadcast::channel(BROADCAST_CAPACITY).0 }
+    }
+}
+
+impl Broadcaster {
+    pub fn publish(&self, message: WebSocketMessage) {
+        debug_assert!(message.topic().is_some(), "only topic messages are broadcast");
+        // No receivers just means no client is connected
+        let _ = self.sender.send(message);
+    }
+
+    fn subscribe(&self) -> broadcast::Receiver<WebSocketMessage> {
+        self.sender.subscribe()
+    }
+}
+
+/// Sliding-window limit on failed connection attempts
+struct RateLimiter {
+    max: usize,
+    window: Duration,
+    attempts: VecDeque<Instant>,
+}
+
+impl RateLimiter {
+    fn new(max: usize, window: Duration) -> Self {
+        Self { max, window, attempts: VecDeque::new() }
+    }
+
+    fn allow(&mut self) -> bool {
+        let now = Instant::now();
+        while self.attempts.front().is_some_and(|t| now.duration_since(*t) >= self.window) {
+            self.attempts.pop_front();
+        }
+        if self.attempts.len() >= self.max {
+            return false;
+        }
+        self.attempts.push_back(now);
+        true
+    }
 }
 
 pub async fn start_server(app: AppHandle) -> anyhow::Result<()> {
-    let addr = "127.0.0.1:3030";
-    let listener = TcpListener::bind(addr).await?;
+    let listener = TcpListener::bind(SERVER_ADDR).await?;
+    let limiter = Arc::new(Mutex::new(RateLimiter::new(MAX_REJECTED_HANDSHAKES, HANDSHAKE_WINDOW)));
 
-    info!("🌐 WebSocket server listening on ws://{}", addr);
+    info!("🌐 WebSocket server listening on ws://{}", SERVER_ADDR);
 
     while let Ok((stream, _)) = listener.accept().await {
         let app_handle = app.clone();
+        let limiter = Arc::clone(&limiter);
 
         tokio::spawn(async move {
-            match accept_async(stream).await {
+            if let Some(token) = share_request(&stream).await {
+                serve_share(stream, &token, &app_handle).await;
+                return;
+            }
+
+            let token = app_handle.state::<AppState>().websocket_token.clone();
+            let mut rejection = None;
+            let mut throttled = false;
+            let handshake = accept_hdr_async(stream, |request: &Request, response: Response| {
+                authorize(request, &token).map(|()| response).map_err(|(status, reason)| {
+                    let origin = request.headers().get("origin").and_then(|o| o.to_str().ok());
+                    rejection = Some((origin.map(str::to_string), reason));
+                    let (status, reason) = if limiter.lock().unwrap().allow() {
+                        (status, reason)
+                    } else {
+                        throttled = true;
+                        (StatusCode::TOO_MANY_REQUESTS, "Too many connection attempts")
+                    };
+                    let mut error = ErrorResponse::new(Some(reason.to_string()));
+                    *error.status_mut() = status;
+                    error
+                })
+            })
+            .await;
+
+            if throttled {
+                debug!("Too many rejected WebSocket connection attempts, refusing unaudited");
+                return;
+            }
+            if let Some((origin, reason)) = rejection {
+                warn!("🚫 Rejected WebSocket connection from {:?}: {}", origin, reason);
+                app_handle.state::<AppState>().security_engine
+                    .audit()
+                    .log(
+                        None,
+                        "WebSocket connection rejected".to_string(),
+                        serde_json::json!({ "origin": origin }),
+                        false,
+                        Some(reason.to_string()),
+                    )
+                    .await;
+                return;
+            }
+
+            match handshake {
                 Ok(ws_stream) => {
                     debug!("✅ New WebSocket connection");
                     handle_connection(ws_stream, app_handle).await;
@@ -54,31 +294,155 @@ pub async fn start_server(app: AppHandle) -> anyhow::Result<()> {
     Ok(())
 }
 
+/// Only the app's own pages, presenting this session's token, may connect
+fn authorize(request: &Request, token: &str) -> Result<(), (StatusCode, &'static str)> {
+    let origin = request.headers().get("origin").and_then(|o| o.to_str().ok());
+    if !origin.is_some_and(|origin| ALLOWED_ORIGINS.contains(&origin)) {
+        return Err((StatusCode::FORBIDDEN, "Origin not allowed"));
+    }
+
+    let presented = request
+        .uri()
+        .query()
+        .into_iter()
+        .flat_map(|query| query.split('&'))
+        .find_map(|pair| pair.strip_prefix("token="));
+    match presented {
+        Some(presented) if constant_time_eq(presented.as_bytes(), token.as_bytes()) => Ok(()),
+        _ => Err((StatusCode::UNAUTHORIZED, "Missing or invalid session token")),
+    }
+}
+
+/// Compare without returning early, so timing doesn't reveal how much of the token matched
+pub fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+/// The token of a `GET /share/<token>` request
+/// Only peeks at the request line, so WebSocket handshakes reach `accept_async` untouched
+async fn share_request(stream: &TcpStream) -> Option<String> {
+    let mut buf = [0u8; 512];
+    let peeked = tokio::time::timeout(Duration::from_secs(5), async {
+        loop {
+            let n = stream.peek(&mut buf).await.ok()?;
+            if n == 0 {
+                return None;
+            }
+            if n == buf.len() || buf[..n].contains(&b'\n') {
+                return Some(n);
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    })
+    .await
+    .ok()??;
+
+    let request_line = std::str::from_utf8(&buf[..peeked]).ok()?.lines().next()?;
+    let target = request_line.strip_prefix("GET ")?.split(' ').next()?;
+    let token = target.strip_prefix("/share/")?;
+    Some(token.split('?').next().unwrap_or(token).to_string())
+}
+
+/// Answer a share link request with the file it points to
+async fn serve_share(mut stream: TcpStream, token: &str, app: &AppHandle) {
+    // Consume the request head so closing the connection doesn't reset it before the client reads
+    let mut head = Vec::new();
+    let mut buf = [0u8; 1024];
+    while !head.windows(4).any(|w| w == b"\r\n\r\n") && head.len() < MAX_SHARE_REQUEST_BYTES {
+        match tokio::time::timeout(Duration::from_secs(5), stream.read(&mut buf)).await {
+            Ok(Ok(n)) if n > 0 => head.extend_from_slice(&buf[..n]),
+            _ => return,
+        }
+    }
+
+    let state = app.state::<AppState>();
+    let locked = state.security_engine
+        .lockdown_state()
+        .await
+        .map(|s| !s.allows_reads())
+        .unwrap_or(true);
+
+    let response = if locked {
+        http_response("503 Service Unavailable", "text/plain", &[], b"System is locked down")
+    } else {
+        match state.filesystem.open_share(token).await {
+            Ok(file) => {
+                info!("🔗 Serving shared download {}", file.filename);
+                let disposition = format!("attachment; filename=\"{}\"", file.filename.replace(['"', '\\'], "_"));
+                http_response("200 OK", &file.mime_type, &[("Content-Disposition", disposition)], &file.content)
+            }
+            Err(e) => {
+                warn!("⚠️  Refused share link: {}", e);
+                http_response("404 Not Found", "text/plain", &[], b"This link is invalid or has expired")
+            }
+        }
+    };
+
+    if let Err(e) = stream.write_all(&response).await {
+        debug!("Share link client went away: {}", e);
+    }
+    let _ = stream.shutdown().await;
+}
+
+pub fn http_response(status: &str, content_type: &str, headers: &[(&str, String)], body: &[u8]) -> Vec<u8> {
+    let mut response = format!(
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nCache-Control: no-store\r\nX-Content-Type-Options: nosniff\r\nConnection: close\r\n",
+        status,
+        content_type,
+        body.len()
+    );
+    for (name, value) in headers {
+        response.push_str(&format!("{}: {}\r\n", name, value));
+    }
+    response.push_str("\r\n");
+
+    let mut response = response.into_bytes();
+    response.extend_from_slice(body);
+    response
+}
+
 async fn handle_connection(
     ws_stream: tokio_tungstenite::WebSocketStream<tokio::net::TcpStream>,
     app: AppHandle,
 ) {
     let (mut write, mut read) = ws_stream.split();
 
-    // Send initial connection message
-    let msg = WebSocketMessage::LlmStatus {
-        llm_id: "system".to_string(),
-        status: "connected".to_string(),
-        current_task: None,
-    };
+    // Terminal output arrives from several tasks, so all writes go through one channel
+    let (outgoing, mut outgoing_rx) = mpsc::channel::<WebSocketMessage>(256);
+    let writer = tokio::spawn(async move {
+        while let Some(msg) = outgoing_rx.recv().await {
+            if let Ok(json) = serde_json::to_string(&msg) {
+                if write.send(Message::Text(json)).await.is_err() {
+                    break;
+                }
+            }
+        }
+    });
 
-    if let Ok(json) = serde_json::to_string(&msg) {
-        let _ = write.send(tokio_tungstenite::tungstenite::Message::Text(json)).await;
-    }
+    // Broadcast messages are relayed only for the topics this client subscribed to, none at first
+    let topics = Arc::new(Mutex::new(BTreeSet::new()));
+    let _ = outgoing.send(WebSocketMessage::Subscribed { topics: Vec::new() }).await;
+    let relay = tokio::spawn(relay_topics(
+        app.state::<AppState>().websocket_events.subscribe(),
+        Arc::clone(&topics),
+        outgoing.clone(),
+    ));
+
+    let mut sessions: HashMap<Uuid, PtySession> = HashMap::new();
 
     // Listen for messages from client
     while let Some(msg) = read.next().await {
         match msg {
-            Ok(tokio_tungstenite::tungstenite::Message::Text(text)) => {
+            Ok(Message::Text(text)) => {
                 debug!("📨 Received: {}", text);
-                // TODO: Handle incoming messages
+                match serde_json::from_str::<ClientMessage>(&text) {
+                    Ok(message) => {
+                        handle_client_message(message, &app, &mut sessions, &topics, &outgoing).await;
+                    }
+                    Err(e) => debug!("Ignoring unrecognized message: {}", e),
+                }
             }
-            Ok(tokio_tungstenite::tungstenite::Message::Close(_)) => {
+            Ok(Message::Close(_)) => {
                 debug!("👋 WebSocket connection closed");
                 break;
             }
@@ -89,10 +453,147 @@ async fn handle_connection(
             _ => {}
         }
     }
+
+    // Terminals do not outlive the connection that opened them
+    for (_, session) in sessions.drain() {
+        record_pty_session(&app, session.close()).await;
+    }
+    relay.abort();
+    writer.abort();
 }
 
-/// Broadcast a message to all connected WebSocket clients
-pub async fn broadcast_message(_app: &AppHandle, _message: WebSocketMessage) {
-    // TODO: Implement broadcast to all connected clients
-    // Will need to maintain a list of active connections
+/// Pass broadcast messages of the subscribed topics on to the client
+async fn relay_topics(
+    mut events: broadcast::Receiver<WebSocketMessage>,
+    topics: Arc<Mutex<BTreeSet<Topic>>>,
+    outgoing: mpsc::Sender<WebSocketMessage>,
+) {
+    loop {
+        match events.recv().await {
+            Ok(message) => {
+                let subscribed = message.topic().is_some_and(|topic| topics.lock().unwrap().contains(&topic));
+                if subscribed && outgoing.send(message).await.is_err() {
+                    break;
+                }
+            }
+            Err(broadcast::error::RecvError::Lagged(missed)) => {
+                warn!("⚠️  WebSocket client fell behind and missed {} messages", missed);
+            }
+            Err(broadcast::error::RecvError::Closed) => break,
+        }
+    }
+}
+
+async fn handle_client_message(
+    message: ClientMessage,
+    app: &AppHandle,
+    sessions: &mut HashMap<Uuid, PtySession>,
+    topics: &Mutex<BTreeSet<Topic>>,
+    outgoing: &mpsc::Sender<WebSocketMessage>,
+) {
+    let state = app.state::<AppState>();
+
+    let result = match message {
+        ClientMessage::Subscribe { topics: added } => {
+            let now: Vec<Topic> = {
+                let mut topics = topics.lock().unwrap();
+                topics.extend(added);
+                topics.iter().copied().collect()
+            };
+            debug!("📡 WebSocket client subscribed to {:?}", now);
+            let _ = outgoing.send(WebSocketMessage::Subscribed { topics: now }).await;
+            Ok(())
+        }
+        ClientMessage::Unsubscribe { topics: removed } => {
+            let now: Vec<Topic> = {
+                let mut topics = topics.lock().unwrap();
+                topics.retain(|topic| !removed.contains(topic));
+                topics.iter().copied().collect()
+            };
+            debug!("📡 WebSocket client subscribed to {:?}", now);
+            let _ = outgoing.send(WebSocketMessage::Subscribed { topics: now }).await;
+            Ok(())
+        }
+        ClientMessage::PtyOpen { sandbox_id, cols, rows } => {
+            if terminals_locked(&state).await {
+                Err((None, "System is locked down".to_string()))
+            } else {
+                match state.sandbox_manager.open_pty(sandbox_id, cols, rows).await {
+                    Ok((session, events)) => {
+                        let session_id = session.id();
+                        info!("🖥️  PTY session {} opened in sandbox {}", session_id, sandbox_id);
+                        sessions.insert(session_id, session);
+                        tokio::spawn(forward_pty_events(session_id, events, outgoing.clone()));
+                        let _ = outgoing
+                            .send(WebSocketMessage::PtyOpened { session_id, sandbox_id })
+                            .await;
+                        Ok(())
+                    }
+                    Err(e) => Err((None, e.to_string())),
+                }
+            }
+        }
+        // Terminals opened before a lockdown stay open but take no more input
+        ClientMessage::PtyInput { session_id, .. } if terminals_locked(&state).await => {
+            Err((Some(session_id), "System is locked down".to_string()))
+        }
+        ClientMessage::PtyInput { session_id, data } => match sessions.get_mut(&session_id) {
+            Some(session) => session.write(&data).await.map_err(|e| (Some(session_id), e.to_string())),
+            None => Err((Some(session_id), "Unknown PTY session".to_string())),
+        },
+        ClientMessage::PtyResize { session_id, cols, rows } => match sessions.get(&session_id) {
+            Some(session) => session.resize(cols, rows).map_err(|e| (Some(session_id), e.to_string())),
+            None => Err((Some(session_id), "Unknown PTY session".to_string())),
+        },
+        ClientMessage::PtyClose { session_id } => {
+            if let Some(session) = sessions.remove(&session_id) {
+                record_pty_session(app, session.close()).await;
+            }
+            Ok(())
+        }
+    };
+
+    if let Err((session_id, message)) = result {
+        warn!("⚠️  PTY request failed: {}", message);
+        let _ = outgoing.send(WebSocketMessage::PtyError { session_id, message }).await;
+    }
+}
+
+/// A terminal runs commands, which a lockdown and read-only mode refuse
+async fn terminals_locked(state: &AppState) -> bool {
+    state.security_engine.lockdown_state().await.map(|s| !s.allows_writes()).unwrap_or(true)
+}
+
+/// Relay terminal output for one session to the client
+async fn forward_pty_events(
+    session_id: Uuid,
+    mut events: mpsc::Receiver<PtyEvent>,
+    outgoing: mpsc::Sender<WebSocketMessage>,
+) {
+    while let Some(event) = events.recv().await {
+        let message = match event {
+            PtyEvent::Output { data } => WebSocketMessage::PtyOutput { session_id, data },
+            PtyEvent::Exited { exit_code } => WebSocketMessage::PtyExited { session_id, exit_code },
+        };
+        if outgoing.send(message).await.is_err() {
+            break;
+        }
+    }
+}
+
+/// Store a finished terminal session in the audit trail
+async fn record_pty_session(app: &AppHandle, recording: PtyRecording) {
+    info!("📼 Recording PTY session {} ({}ms)", recording.session_id, recording.duration_ms);
+
+    let state = app.state::<AppState>();
+    state.security_engine
+        .audit()
+        .log(
+            None,
+            "PTY session".to_string(),
+            serde_json::to_value(&recording).unwrap_or_default(),
+            true,
+            None,
+        )
+        .await;
 }
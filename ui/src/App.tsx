@@ -1,5 +1,6 @@
 import { useState, useEffect } from 'react';
 import { LLMInstance, SystemState, Document, AuditLogEntry } from './types';
+import { SystemState as BackendSystemState } from './types/api';
 import Dashboard from './pages/Dashboard';
 import { AlertCircle } from 'lucide-react';
 import { useTauriAPI } from './hooks/useTauriAPI';
@@ -10,7 +11,11 @@ function App() {
   const [systemState, setSystemState] = useState<SystemState>({
     lockdown: 'normal',
     active_llms: [],
+    llms: [],
     pending_approvals: 0,
+    offline: false,
+    unavailable_capabilities: [],
+    budget: { monthly_cloud_usd: null, spent_usd: 0 },
   });
   const [llms, setLlms] = useState<LLMInstance[]>([]);
   const [documents, setDocuments] = useState<Document[]>([]);
@@ -24,16 +29,16 @@ function App() {
       console.log('LLM status update:', message);
       loadLLMs();
     },
-    onDocumentUploaded: (message) => {
-      console.log('Document uploaded:', message);
+    onDocumentsQueued: (message) => {
+      console.log('Documents queued for indexing:', message);
       loadDocuments();
     },
-    onLockdownChanged: (message) => {
-      console.log('Lockdown changed:', message);
+    onLockdownTriggered: (reason) => {
+      console.log('Lockdown triggered:', reason);
       loadSystemState();
     },
-    onAuditLog: (message) => {
-      console.log('Audit log entry:', message);
+    onAuditLog: (entry) => {
+      console.log('Audit log entry:', entry);
       loadAuditLog();
     },
   });
@@ -42,14 +47,30 @@ function App() {
     loadInitialData();
   }, []);
 
+  // Keep the pending approval count current as requests arrive and are decided,
+  // and the lockdown state and per-LLM requests current from the periodic `system-state` event
+  useEffect(() => {
+    const unlisten = [api.onApprovalEvent(() => loadSystemState()), api.onSystemState(applySystemState)];
+    return () => {
+      unlisten.forEach((pending) => pending.then((stop) => stop()));
+    };
+  }, []);
+
+  const applySystemState = (state: BackendSystemState) => {
+    setSystemState({
+      lockdown: state.lockdown_state.toLowerCase() as 'normal' | 'readonly' | 'locked',
+      active_llms: state.active_llms,
+      llms: state.llms,
+      pending_approvals: state.pending_approvals,
+      offline: state.offline,
+      unavailable_capabilities: state.unavailable_capabilities,
+      budget: state.budget,
+    });
+  };
+
   const loadSystemState = async () => {
     try {
-      const state = await api.getSystemState();
-      setSystemState({
-        lockdown: state.lockdown_state.toLowerCase() as 'normal' | 'readonly' | 'locked',
-        active_llms: [], // Will be populated from LLMs list
-        pending_approvals: 0, // TODO: Add to backend
-      });
+      applySystemState(await api.getSystemState());
     } catch (err) {
       console.error('Failed to load system state:', err);
     }
@@ -59,10 +80,6 @@ function App() {
     try {
       const llmList = await api.getLLMs();
       setLlms(llmList);
-      setSystemState(prev => ({
-        ...prev,
-        active_llms: llmList.filter(llm => llm.loaded).map(llm => llm.id),
-      }));
     } catch (err) {
       console.error('Failed to load LLMs:', err);
     }
@@ -79,8 +96,8 @@ function App() {
 
   const loadAuditLog = async () => {
     try {
-      const log = await api.getAuditLog();
-      setAuditLog(log);
+      const page = await api.getAuditLog();
+      setAuditLog(page.entries);
     } catch (err) {
       console.error('Failed to load audit log:', err);
     }
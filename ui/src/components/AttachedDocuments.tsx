@@ -0,0 +1,55 @@
+import { Paperclip, X } from 'lucide-react';
+import { Document } from '../types';
+import { Conversation } from '../types/api';
+
+interface Props {
+  conversation: Conversation;
+  documents: Document[];
+  onAttach: (documentId: string) => Promise<void>;
+  onDetach: (documentId: string) => Promise<void>;
+}
+
+// Chips for the documents retrieved first in a conversation, with a picker for the rest
+export default function AttachedDocuments({ conversation, documents, onAttach, onDetach }: Props) {
+  const attached = conversation.document_ids.map((id) => ({
+    id,
+    filename: documents.find((doc) => doc.id === id)?.filename ?? 'Deleted document',
+  }));
+  const available = documents.filter((doc) => !conversation.document_ids.includes(doc.id));
+
+  return (
+    <div className="flex flex-wrap items-center gap-2">
+      {attached.map((doc) => (
+        <span
+          key={doc.id}
+          className="flex items-center gap-1 px-2 py-1 bg-gray-800 rounded-full text-xs text-gray-300"
+        >
+          <Paperclip size={12} className="text-gray-500" />
+          <span className="truncate max-w-[12rem]">{doc.filename}</span>
+          <button
+            onClick={() => onDetach(doc.id)}
+            className="text-gray-500 hover:text-gray-200"
+            title="Detach"
+          >
+            <X size={12} />
+          </button>
+        </span>
+      ))}
+
+      {available.length > 0 && (
+        <select
+          value=""
+          onChange={(e) => e.target.value && onAttach(e.target.value)}
+          className="bg-gray-800 rounded-full px-2 py-1 text-xs text-gray-400"
+        >
+          <option value="">Attach document…</option>
+          {available.map((doc) => (
+            <option key={doc.id} value={doc.id}>
+              {doc.filename}
+            </option>
+          ))}
+        </select>
+      )}
+    </div>
+  );
+}
@@ -0,0 +1,71 @@
+import { useState, useEffect } from 'react';
+import { Copy, Eye, EyeOff, Globe, RefreshCw } from 'lucide-react';
+import { ask } from '@tauri-apps/api/dialog';
+import { BrowserBridgeInfo } from '../types/api';
+import { useTauriAPI } from '../hooks/useTauriAPI';
+
+interface Props {
+  api: ReturnType<typeof useTauriAPI>;
+}
+
+// Pairing token for the companion extension in browser-extension/
+export default function BrowserExtensionPairing({ api }: Props) {
+  const [bridge, setBridge] = useState<BrowserBridgeInfo | null>(null);
+  const [revealed, setRevealed] = useState(false);
+  const [error, setError] = useState<string | null>(null);
+
+  useEffect(() => {
+    api.getBrowserBridge().then(setBridge).catch((err) => setError(String(err)));
+  }, []);
+
+  const handleReset = async () => {
+    const confirmed = await ask('Browsers paired with the current token will have to be paired again.', {
+      title: 'Reset pairing token?',
+      type: 'warning',
+    });
+    if (!confirmed) return;
+    try {
+      setBridge(await api.resetBrowserBridgeToken());
+      setError(null);
+    } catch (err) {
+      setError(String(err));
+    }
+  };
+
+  return (
+    <div className="card space-y-3">
+      <h2 className="text-xl font-bold flex items-center gap-2">
+        <Globe size={20} />
+        Browser Extension
+      </h2>
+      <p className="text-sm text-gray-400">
+        The extension sends the current page or selection to the default LLM and shows the answer. Paste this token in
+        its options to pair it; every request is recorded in the audit log.
+      </p>
+
+      {bridge && !bridge.token && <p className="text-sm text-gray-500">The bridge is starting…</p>}
+      {bridge?.token && (
+        <div className="flex items-center gap-2">
+          <code className="flex-1 bg-gray-800 border border-gray-700 rounded-lg px-3 py-2 text-sm truncate">
+            {revealed ? bridge.token : '•'.repeat(32)}
+          </code>
+          <button onClick={() => setRevealed(!revealed)} className="btn btn-sm btn-secondary" title="Show token">
+            {revealed ? <EyeOff size={14} /> : <Eye size={14} />}
+          </button>
+          <button
+            onClick={() => navigator.clipboard.writeText(bridge.token)}
+            className="btn btn-sm btn-secondary"
+            title="Copy token"
+          >
+            <Copy size={14} />
+          </button>
+          <button onClick={handleReset} className="btn btn-sm btn-secondary" title="Reset token">
+            <RefreshCw size={14} />
+          </button>
+        </div>
+      )}
+      {bridge && <p className="text-xs text-gray-500">Listening on {bridge.url}</p>}
+      {error && <p className="text-sm text-danger-500">{error}</p>}
+    </div>
+  );
+}
@@ -3,6 +3,15 @@ import { Code, Save, Download, Play, Terminal as TerminalIcon } from 'lucide-rea
 import { Prism as SyntaxHighlighter } from 'react-syntax-highlighter';
 import { vscDarkPlus } from 'react-syntax-highlighter/dist/esm/styles/prism';
 import { useTauriAPI } from '../hooks/useTauriAPI';
+import { CodeLanguage } from '../types/api';
+
+// Empty language runs the editor contents as a shell command
+const LANGUAGES: { value: CodeLanguage | ''; label: string }[] = [
+  { value: 'java_script', label: 'JavaScript' },
+  { value: 'python', label: 'Python' },
+  { value: 'rust', label: 'Rust' },
+  { value: '', label: 'Shell' },
+];
 
 interface Props {
   api: ReturnType<typeof useTauriAPI>;
@@ -10,7 +19,7 @@ interface Props {
 
 export default function CodingCanvas({ api }: Props) {
   const [code, setCode] = useState('// Write your code here...\n\n');
-  const [language, setLanguage] = useState('javascript');
+  const [language, setLanguage] = useState<CodeLanguage | ''>('java_script');
   const [filename, setFilename] = useState('untitled.js');
   const [sandboxId, setSandboxId] = useState<string | null>(null);
   const [output, setOutput] = useState<string>('');
@@ -20,12 +29,7 @@ export default function CodingCanvas({ api }: Props) {
   useEffect(() => {
     const createSandbox = async () => {
       try {
-        const result = await api.createSandbox('coding-canvas', {
-          cpu_limit: 2,
-          memory_limit_mb: 512,
-          disk_limit_mb: 1024,
-          timeout_seconds: 30,
-        });
+        const result = await api.createSandbox('coding-canvas');
         setSandboxId(result.sandbox_id);
         setOutput(`Sandbox created: ${result.sandbox_id}\n`);
       } catch (err) {
@@ -63,12 +67,15 @@ export default function CodingCanvas({ api }: Props) {
     setOutput((prev) => prev + `\n--- Running ${filename} ---\n`);
 
     try {
-      const result = await api.executeInSandbox(sandboxId, code, language);
+      // Output is shown line by line as the program prints it
+      const result = await api.executeInSandbox(sandboxId, code, language || undefined, (event) => {
+        if (event.type !== 'finished') setOutput((prev) => prev + event.line + '\n');
+      });
       setOutput(
         (prev) =>
           prev +
-          result.output +
-          `\n--- Completed in ${result.execution_time_ms}ms (exit code: ${result.exit_code}) ---\n`
+          (result.timed_out ? '\n--- Timed out ---\n' : '') +
+          `\n--- Completed in ${result.duration_ms}ms (exit code: ${result.exit_code}) ---\n`
       );
     } catch (err) {
       setOutput((prev) => prev + `Error: ${err}\n`);
@@ -90,15 +97,14 @@ export default function CodingCanvas({ api }: Props) {
           />
           <select
             value={language}
-            onChange={(e) => setLanguage(e.target.value)}
+            onChange={(e) => setLanguage(e.target.value as CodeLanguage | '')}
             className="bg-gray-800 border border-gray-700 rounded px-3 py-1 text-sm"
           >
-            <option value="javascript">JavaScript</option>
-            <option value="typescript">TypeScript</option>
-            <option value="python">Python</option>
-            <option value="rust">Rust</option>
-            <option value="go">Go</option>
-            <option value="java">Java</option>
+            {LANGUAGES.map(({ value, label }) => (
+              <option key={label} value={value}>
+                {label}
+              </option>
+            ))}
           </select>
         </div>
 
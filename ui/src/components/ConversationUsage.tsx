@@ -0,0 +1,36 @@
+import { useState, useEffect } from 'react';
+import { Activity } from 'lucide-react';
+import { Usage } from '../types';
+import { useTauriAPI } from '../hooks/useTauriAPI';
+
+interface Props {
+  api: ReturnType<typeof useTauriAPI>;
+  conversationId: string;
+  // Bump to reload, e.g. when a reply finishes
+  refreshKey?: number;
+}
+
+// Tokens, cost and generation time summed over a conversation's replies
+export default function ConversationUsage({ api, conversationId, refreshKey }: Props) {
+  const [usage, setUsage] = useState<Usage | null>(null);
+
+  useEffect(() => {
+    api.getConversationUsage(conversationId).then(setUsage).catch(() => setUsage(null));
+  }, [conversationId, refreshKey]);
+
+  if (!usage) return null;
+
+  const cost =
+    usage.cost_usd === null ? 'cost unknown' : usage.cost_usd === 0 ? 'free' : `$${usage.cost_usd.toFixed(4)}`;
+
+  return (
+    <span
+      className="flex items-center gap-1 text-xs text-gray-500"
+      title={`${usage.input_tokens} prompt tokens, ${usage.output_tokens} generated`}
+    >
+      <Activity size={12} />
+      {(usage.input_tokens + usage.output_tokens).toLocaleString()} tokens · {cost} ·{' '}
+      {(usage.latency_ms / 1000).toFixed(1)} s
+    </span>
+  );
+}
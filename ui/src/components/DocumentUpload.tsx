@@ -1,18 +1,40 @@
-import { useCallback } from 'react';
+import { useCallback, useEffect, useState } from 'react';
 import { useDropzone } from 'react-dropzone';
+import { listen } from '@tauri-apps/api/event';
 import { Upload, File, CheckCircle, Loader } from 'lucide-react';
 import { Document } from '../types';
+import { UploadProgress } from '../types/api';
 
 interface Props {
   documents: Document[];
   onUpload: (files: File[]) => Promise<void>;
+  // Files and folders dropped on the window, by path
+  onUploadPaths: (paths: string[]) => Promise<void>;
+  progress: UploadProgress | null;
 }
 
-export default function DocumentUpload({ documents, onUpload }: Props) {
+export default function DocumentUpload({ documents, onUpload, onUploadPaths, progress }: Props) {
+  const [isFileHover, setIsFileHover] = useState(false);
+
   const onDrop = useCallback(async (acceptedFiles: File[]) => {
     await onUpload(acceptedFiles);
   }, [onUpload]);
 
+  // Tauri takes over drops onto the window and hands over paths, which also works for folders
+  useEffect(() => {
+    const unlisten = [
+      listen<string[]>('tauri://file-drop', ({ payload }) => {
+        setIsFileHover(false);
+        if (payload.length > 0) onUploadPaths(payload);
+      }),
+      listen('tauri://file-drop-hover', () => setIsFileHover(true)),
+      listen('tauri://file-drop-cancelled', () => setIsFileHover(false)),
+    ];
+    return () => {
+      unlisten.forEach((pending) => pending.then((stop) => stop()));
+    };
+  }, [onUploadPaths]);
+
   const { getRootProps, getInputProps, isDragActive } = useDropzone({
     onDrop,
     accept: {
@@ -31,17 +53,17 @@ export default function DocumentUpload({ documents, onUpload }: Props) {
 
       <div
         {...getRootProps()}
-        className={`dropzone ${isDragActive ? 'dropzone-active' : ''}`}
+        className={`dropzone ${isDragActive || isFileHover ? 'dropzone-active' : ''}`}
       >
         <input {...getInputProps()} />
         <div className="text-center">
           <Upload size={48} className="mx-auto mb-4 text-gray-500" />
-          {isDragActive ? (
-            <p className="text-primary-400 font-medium">Drop files here...</p>
+          {isDragActive || isFileHover ? (
+            <p className="text-primary-400 font-medium">Drop files or folders here...</p>
           ) : (
             <>
               <p className="text-gray-300 font-medium mb-2">
-                Drag & drop documents here
+                Drag & drop documents or folders here
               </p>
               <p className="text-gray-500 text-sm">
                 or click to browse
@@ -54,6 +76,23 @@ export default function DocumentUpload({ documents, onUpload }: Props) {
         </div>
       </div>
 
+      {progress && progress.current !== null && (
+        <div className="mt-4">
+          <div className="flex justify-between text-xs text-gray-400 mb-1">
+            <span className="truncate">{progress.current}</span>
+            <span className="flex-shrink-0 ml-2">
+              {progress.files_done} / {progress.files_total} files
+            </span>
+          </div>
+          <div className="h-2 bg-gray-800 rounded-full overflow-hidden">
+            <div
+              className="h-full bg-primary-500 transition-all"
+              style={{ width: `${progress.bytes_total > 0 ? (progress.bytes_done / progress.bytes_total) * 100 : 0}%` }}
+            />
+          </div>
+        </div>
+      )}
+
       {documents.length > 0 && (
         <div className="mt-6">
           <h3 className="text-sm font-semibold text-gray-400 mb-3">
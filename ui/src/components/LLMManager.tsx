@@ -24,16 +24,31 @@ export default function LLMManager({ llms, statuses, onLoadModel, onUnloadModel
     }
   };
 
-  const getStatusColor = (status?: string) => {
-    switch (status) {
-      case 'processing':
-        return 'text-primary-500 animate-pulse';
-      case 'error':
-        return 'text-danger-500';
-      case 'idle':
-      default:
-        return 'text-success-500';
+  const getStatusColor = (status?: LLMStatus) => {
+    if (status?.healthy === false) {
+      return 'text-danger-500';
+    }
+    if (status && status.in_flight > 0) {
+      return 'text-primary-500 animate-pulse';
+    }
+    if (status && !status.available) {
+      return 'text-gray-500';
+    }
+    return 'text-success-500';
+  };
+
+  const describeRequests = (status: LLMStatus) => {
+    const parts = [];
+    if (status.in_flight > 0) {
+      parts.push(`${status.in_flight} running`);
+    }
+    if (status.queued > 0) {
+      parts.push(`${status.queued} queued`);
+    }
+    if (status.cloud && status.spent_usd > 0) {
+      parts.push(`$${status.spent_usd.toFixed(2)} this month`);
     }
+    return parts.join(' · ');
   };
 
   return (
@@ -62,7 +77,7 @@ export default function LLMManager({ llms, statuses, onLoadModel, onUnloadModel
                     <div className="flex items-center gap-2 mb-1">
                       <Circle
                         size={8}
-                        className={getStatusColor(status?.status)}
+                        className={getStatusColor(status)}
                         fill="currentColor"
                       />
                       <h3 className="font-semibold">{llm.id}</h3>
@@ -84,7 +99,7 @@ export default function LLMManager({ llms, statuses, onLoadModel, onUnloadModel
                     className={`btn btn-sm ${
                       llm.is_loaded ? 'btn-secondary' : 'btn-primary'
                     }`}
-                    disabled={status?.status === 'processing'}
+                    disabled={(status?.in_flight ?? 0) > 0}
                   >
                     {llm.is_loaded ? (
                       <>
@@ -113,8 +128,8 @@ export default function LLMManager({ llms, statuses, onLoadModel, onUnloadModel
 
                 <div className="flex items-center justify-between text-xs text-gray-500">
                   <span>Context: {(llm.max_context / 1024).toFixed(0)}K tokens</span>
-                  {status?.current_task && (
-                    <span className="text-primary-400">{status.current_task}</span>
+                  {status && describeRequests(status) && (
+                    <span className="text-primary-400">{describeRequests(status)}</span>
                   )}
                 </div>
               </div>
@@ -0,0 +1,182 @@
+import { useState, useEffect, useRef } from 'react';
+import { Columns, Play, Square } from 'lucide-react';
+import { LLMInstance, Usage } from '../types';
+import { MessageStreamEvent } from '../types/api';
+import { useTauriAPI } from '../hooks/useTauriAPI';
+import VoiceInputButton from './VoiceInputButton';
+import { UnlistenFn } from '@tauri-apps/api/event';
+
+// Matches MAX_COMPARED_LLMS in the backend
+const MAX_COMPARED = 4;
+
+// Spelled out so Tailwind keeps the classes
+const COLUMNS = ['lg:grid-cols-1', 'lg:grid-cols-2', 'lg:grid-cols-3', 'lg:grid-cols-4'];
+
+interface Reply {
+  llm_id: string;
+  request_id: string;
+  content: string;
+  status: 'streaming' | 'done' | 'error' | 'cancelled';
+  error?: string;
+  first_chunk_ms?: number | null;
+  usage?: Usage;
+}
+
+interface Props {
+  llms: LLMInstance[];
+  api: ReturnType<typeof useTauriAPI>;
+}
+
+export default function ModelComparison({ llms, api }: Props) {
+  const [selected, setSelected] = useState<string[]>([]);
+  const [prompt, setPrompt] = useState('');
+  const [replies, setReplies] = useState<Reply[]>([]);
+  const [error, setError] = useState<string | null>(null);
+  const unlistenRef = useRef<UnlistenFn | null>(null);
+
+  useEffect(() => () => unlistenRef.current?.(), []);
+
+  const toggle = (llmId: string) => {
+    setSelected((prev) =>
+      prev.includes(llmId) ? prev.filter((id) => id !== llmId) : [...prev, llmId].slice(0, MAX_COMPARED)
+    );
+  };
+
+  const applyEvent = (reply: Reply, event: MessageStreamEvent): Reply => {
+    switch (event.type) {
+      case 'chunk':
+        return { ...reply, content: reply.content + event.content };
+      case 'done':
+        return {
+          ...reply,
+          status: 'done',
+          first_chunk_ms: event.first_chunk_ms,
+          usage: event.usage,
+        };
+      case 'error':
+        return {
+          ...reply,
+          status: 'error',
+          error: event.retryable ? `${event.message} (temporary, try again shortly)` : event.message,
+        };
+      case 'cancelled':
+        return { ...reply, status: 'cancelled' };
+    }
+  };
+
+  // Events can arrive before `compareMessage` returns, so a reply is added by whichever comes first
+  const handleEvent = (event: MessageStreamEvent) => {
+    setReplies((prev) => {
+      const empty: Reply = { llm_id: event.llm_id, request_id: event.request_id, content: '', status: 'streaming' };
+      const existing = prev.find((reply) => reply.request_id === event.request_id);
+      return existing
+        ? prev.map((reply) => (reply === existing ? applyEvent(reply, event) : reply))
+        : [...prev, applyEvent(empty, event)];
+    });
+  };
+
+  const handleCompare = async () => {
+    setError(null);
+    unlistenRef.current?.();
+    setReplies([]);
+    try {
+      const { requests, unlisten } = await api.compareMessage(selected, prompt, handleEvent);
+      unlistenRef.current = unlisten;
+      setReplies((prev) => {
+        const missing = requests.filter((request) => !prev.some((reply) => reply.request_id === request.request_id));
+        return [...prev, ...missing.map((request) => ({ ...request, content: '', status: 'streaming' as const }))];
+      });
+    } catch (err) {
+      setError(err instanceof Error ? err.message : String(err));
+    }
+  };
+
+  const formatCost = (cost?: number | null) => {
+    if (cost === null || cost === undefined) return 'cost unknown';
+    return cost === 0 ? 'free' : `$${cost.toFixed(4)}`;
+  };
+
+  const running = replies.some((reply) => reply.status === 'streaming');
+
+  return (
+    <div className="card space-y-4">
+      <h2 className="text-xl font-bold flex items-center gap-2">
+        <Columns size={20} />
+        Compare Models
+      </h2>
+
+      <div className="flex flex-wrap gap-2">
+        {llms.map((llm) => (
+          <label
+            key={llm.id}
+            className={`text-sm px-3 py-1 rounded-full border cursor-pointer ${
+              selected.includes(llm.id) ? 'border-primary-500 text-primary-400' : 'border-gray-700 text-gray-400'
+            }`}
+          >
+            <input
+              type="checkbox"
+              className="hidden"
+              checked={selected.includes(llm.id)}
+              onChange={() => toggle(llm.id)}
+            />
+            {llm.id}
+          </label>
+        ))}
+      </div>
+
+      <div className="relative">
+        <textarea
+          value={prompt}
+          onChange={(e) => setPrompt(e.target.value)}
+          placeholder="Prompt to send to every selected model"
+          className="w-full h-24 bg-gray-800 border border-gray-700 rounded-lg p-3 pr-12 text-sm"
+        />
+        <div className="absolute top-2 right-2">
+          <VoiceInputButton
+            api={api}
+            onTranscript={(text) => setPrompt((prev) => (prev ? `${prev} ${text}` : text))}
+            onError={setError}
+          />
+        </div>
+      </div>
+
+      <button
+        onClick={handleCompare}
+        className="btn btn-primary flex items-center gap-2"
+        disabled={selected.length < 2 || !prompt.trim() || running}
+      >
+        <Play size={14} />
+        Compare {selected.length} models
+      </button>
+
+      {error && <p className="text-sm text-danger-500">{error}</p>}
+
+      {replies.length > 0 && (
+        <div className={`grid gap-4 grid-cols-1 ${COLUMNS[Math.min(replies.length, MAX_COMPARED) - 1]}`}>
+          {replies.map((reply) => (
+            <div key={reply.request_id} className="p-3 rounded-lg bg-gray-800 border border-gray-700 flex flex-col">
+              <div className="flex items-center justify-between mb-2">
+                <span className="font-semibold text-sm">{reply.llm_id}</span>
+                {reply.status === 'streaming' && (
+                  <button onClick={() => api.cancelGeneration(reply.request_id)} className="btn btn-sm btn-secondary">
+                    <Square size={12} className="mr-1" />
+                    Stop
+                  </button>
+                )}
+              </div>
+              <pre className="whitespace-pre-wrap text-sm text-gray-300 flex-1">{reply.content}</pre>
+              <div className="mt-2 text-xs text-gray-500">
+                {reply.status === 'done' &&
+                  reply.usage &&
+                  `${reply.first_chunk_ms ?? '–'} ms to first token · ${reply.usage.latency_ms} ms total · ` +
+                    `${reply.usage.output_tokens} tokens · ${formatCost(reply.usage.cost_usd)}`}
+                {reply.status === 'error' && <span className="text-danger-500">{reply.error}</span>}
+                {reply.status === 'cancelled' && 'Stopped'}
+              </div>
+            </div>
+          ))}
+        </div>
+      )}
+    </div>
+  );
+}
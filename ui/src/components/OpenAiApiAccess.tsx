@@ -0,0 +1,75 @@
+import { useState, useEffect } from 'react';
+import { Copy, Eye, EyeOff, Plug, RefreshCw } from 'lucide-react';
+import { ask } from '@tauri-apps/api/dialog';
+import { OpenAiApiInfo } from '../types/api';
+import { useTauriAPI } from '../hooks/useTauriAPI';
+
+interface Props {
+  api: ReturnType<typeof useTauriAPI>;
+}
+
+// Base URL and API key for editors and scripts that speak the OpenAI API
+export default function OpenAiApiAccess({ api }: Props) {
+  const [info, setInfo] = useState<OpenAiApiInfo | null>(null);
+  const [revealed, setRevealed] = useState(false);
+  const [error, setError] = useState<string | null>(null);
+
+  useEffect(() => {
+    api.getOpenAiApi().then(setInfo).catch((err) => setError(String(err)));
+  }, []);
+
+  const handleReset = async () => {
+    const confirmed = await ask('Clients using the current key will be refused until given the new one.', {
+      title: 'Reset API key?',
+      type: 'warning',
+    });
+    if (!confirmed) return;
+    try {
+      setInfo(await api.resetOpenAiApiKey());
+      setError(null);
+    } catch (err) {
+      setError(String(err));
+    }
+  };
+
+  return (
+    <div className="card space-y-3">
+      <h2 className="text-xl font-bold flex items-center gap-2">
+        <Plug size={20} />
+        OpenAI API
+      </h2>
+      <p className="text-sm text-gray-400">
+        Tools built for OpenAI can use the platform's models: set their base URL to the address below and their API key
+        to this key. Every request is recorded in the audit log.
+      </p>
+
+      {info && !info.enabled && (
+        <p className="text-sm text-gray-500">
+          Off. Set <code>openai_api.enabled</code> in the settings and restart the app to turn it on.
+        </p>
+      )}
+      {info?.api_key && (
+        <div className="flex items-center gap-2">
+          <code className="flex-1 bg-gray-800 border border-gray-700 rounded-lg px-3 py-2 text-sm truncate">
+            {revealed ? info.api_key : '•'.repeat(32)}
+          </code>
+          <button onClick={() => setRevealed(!revealed)} className="btn btn-sm btn-secondary" title="Show key">
+            {revealed ? <EyeOff size={14} /> : <Eye size={14} />}
+          </button>
+          <button
+            onClick={() => navigator.clipboard.writeText(info.api_key)}
+            className="btn btn-sm btn-secondary"
+            title="Copy key"
+          >
+            <Copy size={14} />
+          </button>
+          <button onClick={handleReset} className="btn btn-sm btn-secondary" title="Reset key">
+            <RefreshCw size={14} />
+          </button>
+        </div>
+      )}
+      {info?.enabled && <p className="text-xs text-gray-500">Base URL {info.base_url}</p>}
+      {error && <p className="text-sm text-danger-500">{error}</p>}
+    </div>
+  );
+}
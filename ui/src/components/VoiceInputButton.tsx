@@ -0,0 +1,65 @@
+import { useState, useEffect, useRef } from 'react';
+import { Mic, Square, Loader } from 'lucide-react';
+import { useTauriAPI } from '../hooks/useTauriAPI';
+
+interface Props {
+  api: ReturnType<typeof useTauriAPI>;
+  // Called with the transcript so the caller can drop it into its text box
+  onTranscript: (text: string) => void;
+  onError?: (message: string) => void;
+}
+
+// Click to record, click again to transcribe with the local speech model
+export default function VoiceInputButton({ api, onTranscript, onError }: Props) {
+  const [state, setState] = useState<'idle' | 'recording' | 'transcribing'>('idle');
+  const recording = useRef(false);
+
+  // A recording left running when the component goes away is discarded
+  useEffect(
+    () => () => {
+      if (recording.current) api.cancelVoiceInput().catch(() => {});
+    },
+    []
+  );
+
+  const fail = (err: unknown) => {
+    setState('idle');
+    onError?.(err instanceof Error ? err.message : String(err));
+  };
+
+  const handleClick = async () => {
+    if (state === 'idle') {
+      try {
+        await api.startVoiceInput();
+        recording.current = true;
+        setState('recording');
+      } catch (err) {
+        fail(err);
+      }
+    } else if (state === 'recording') {
+      recording.current = false;
+      setState('transcribing');
+      try {
+        const transcript = await api.stopVoiceInput();
+        setState('idle');
+        if (transcript.text) onTranscript(transcript.text);
+      } catch (err) {
+        fail(err);
+      }
+    }
+  };
+
+  return (
+    <button
+      type="button"
+      onClick={handleClick}
+      disabled={state === 'transcribing'}
+      title={state === 'recording' ? 'Stop and transcribe' : 'Voice input'}
+      className={`btn btn-sm ${state === 'recording' ? 'btn-danger animate-pulse' : 'btn-secondary'}`}
+    >
+      {state === 'idle' && <Mic size={14} />}
+      {state === 'recording' && <Square size={14} />}
+      {state === 'transcribing' && <Loader size={14} className="animate-spin" />}
+    </button>
+  );
+}
@@ -1,32 +1,101 @@
 import { invoke } from '@tauri-apps/api/tauri';
 import { open } from '@tauri-apps/api/dialog';
 import { readBinaryFile } from '@tauri-apps/api/fs';
+import { listen, UnlistenFn } from '@tauri-apps/api/event';
 import {
   SystemState,
+  ResourceUsage,
+  HardwareInfo,
   LockdownRequest,
   LockdownResponse,
+  Settings,
+  UpdateSettingsResponse,
+  BrowserBridgeInfo,
+  OpenAiApiInfo,
+  RestoredSession,
   SendMessageRequest,
+  CompareMessageRequest,
+  ComparedRequest,
+  BenchmarkOptions,
+  BenchmarkRun,
+  EvaluationRequest,
+  EvaluationReport,
+  UsageQuery,
+  UsageSummary,
+  VoiceTranscript,
+  CaptureTarget,
+  CaptureWindow,
+  ImageInput,
+  ScreenshotAttachment,
   SendMessageResponse,
+  MessageStreamEvent,
+  Conversation,
+  ExportFormat,
+  ExportConversationResponse,
+  NewPromptTemplate,
+  PromptTemplate,
+  PromptTemplateRef,
+  ModelSearchResult,
+  LocalModel,
+  ModelInfo,
+  ModelDownloadEvent,
   LoadLLMRequest,
   LoadLLMResponse,
   UnloadLLMRequest,
   UnloadLLMResponse,
   UploadDocumentRequest,
   UploadDocumentResponse,
+  UploadArchiveResponse,
+  UploadPathsResponse,
+  UploadProgress,
   DeleteDocumentRequest,
   DeleteDocumentResponse,
+  RecrawlReport,
+  FolderUsage,
+  FileHash,
+  FileMetadata,
+  FileQuery,
+  ShareLink,
+  TrashEntry,
+  FileVersion,
   UpdatePermissionsRequest,
   UpdatePermissionsResponse,
   CreateSandboxRequest,
   CreateSandboxResponse,
   ExecuteInSandboxRequest,
   ExecuteInSandboxResponse,
+  SandboxOutputEvent,
   GetSandboxFilesRequest,
   GetSandboxFilesResponse,
+  SandboxFileChange,
+  SandboxUsage,
+  PortForward,
   ApproveTransferRequest,
-  ApproveTransferResponse,
+  PendingApproval,
+  PlannedAction,
+  SimulatedDecision,
+  ApprovalEvent,
+  NotificationTarget,
+  DeepLink,
+  Profile,
+  NewProfile,
+  ProfileList,
+  EncryptionStatus,
+  BackupInfo,
+  RestoreReport,
+  PluginList,
+  AuditQuery,
+  AuditPage,
+  CloudProvider,
+  ProviderKeyResponse,
+  SandboxSnapshot,
+  SandboxTemplate,
+  SandboxVolume,
+  CodeLanguage,
+  KernelInfo,
+  CellOutput,
 } from '../types/api';
-import { LLMInstance, Document, Permissions, AuditLogEntry } from '../types';
+import { LLMInstance, Message, Document, Permissions, AuditLogEntry, Usage } from '../types';
 
 export function useTauriAPI() {
   // System Commands
@@ -43,6 +112,77 @@ export function useTauriAPI() {
     return await invoke<LockdownResponse>('release_lockdown', { password });
   };
 
+  // Writes, code execution and network access stop; chat and search keep working
+  const enterReadOnly = async (reason: string): Promise<void> => {
+    await invoke('enter_read_only', { reason });
+  };
+
+  const exitReadOnly = async (): Promise<void> => {
+    await invoke('exit_read_only');
+  };
+
+  // Emitted when the tray locks down or pauses cloud providers, even while the window is hidden
+  const onSystemState = (onState: (state: SystemState) => void): Promise<UnlistenFn> => {
+    return listen<SystemState>('system-state', ({ payload }) => onState(payload));
+  };
+
+  const getResourceUsage = async (): Promise<ResourceUsage> => {
+    return await invoke<ResourceUsage>('get_resource_usage');
+  };
+
+  const getHardwareInfo = async (): Promise<HardwareInfo> => {
+    return await invoke<HardwareInfo>('get_hardware_info');
+  };
+
+  const onResourceUsage = (onUsage: (usage: ResourceUsage) => void): Promise<UnlistenFn> => {
+    return listen<ResourceUsage>('resource-usage', ({ payload }) => onUsage(payload));
+  };
+
+  const takePendingDeepLinks = async (): Promise<DeepLink[]> => {
+    return await invoke<DeepLink[]>('take_pending_deep_links');
+  };
+
+  // Called once on startup to reopen what the last session had open
+  const getSession = async (): Promise<RestoredSession> => {
+    return await invoke<RestoredSession>('get_session');
+  };
+
+  // Called whenever tabs open, close or switch, so the next launch reopens them
+  const setOpenConversations = async (conversationIds: string[], active: string | null): Promise<void> => {
+    await invoke('set_open_conversations', { conversationIds, active });
+  };
+
+  // Sent when links arrive; collect them with `takePendingDeepLinks`
+  const onDeepLink = (onLink: () => void): Promise<UnlistenFn> => {
+    return listen('deep-link', () => onLink());
+  };
+
+  const getBrowserBridge = async (): Promise<BrowserBridgeInfo> => {
+    return await invoke<BrowserBridgeInfo>('get_browser_bridge');
+  };
+
+  // Extensions paired with the old token stop working until paired again
+  const resetBrowserBridgeToken = async (): Promise<BrowserBridgeInfo> => {
+    return await invoke<BrowserBridgeInfo>('reset_browser_bridge_token');
+  };
+
+  const getOpenAiApi = async (): Promise<OpenAiApiInfo> => {
+    return await invoke<OpenAiApiInfo>('get_openai_api');
+  };
+
+  // Clients configured with the old key are refused until given the new one
+  const resetOpenAiApiKey = async (): Promise<OpenAiApiInfo> => {
+    return await invoke<OpenAiApiInfo>('reset_openai_api_key');
+  };
+
+  const getSettings = async (): Promise<Settings> => {
+    return await invoke<Settings>('get_settings');
+  };
+
+  const updateSettings = async (settings: Settings): Promise<UpdateSettingsResponse> => {
+    return await invoke<UpdateSettingsResponse>('update_settings', { settings });
+  };
+
   // LLM Commands
   const getLLMs = async (): Promise<LLMInstance[]> => {
     return await invoke<LLMInstance[]>('get_llms');
@@ -58,13 +198,279 @@ export function useTauriAPI() {
     return await invoke<UnloadLLMResponse>('unload_llm', { request });
   };
 
-  const sendMessage = async (
+  // Streams a completion; `onEvent` gets every event for this request until done, error or cancelled
+  const sendMessageStream = async (
     llmId: string,
     content: string,
-    context?: Record<string, any>
+    onEvent: (event: MessageStreamEvent) => void,
+    context?: Record<string, any>,
+    conversationId?: string,
+    images?: ImageInput[],
+    template?: PromptTemplateRef,
+    variables?: Record<string, string>
+  ): Promise<{ requestId: string; unlisten: UnlistenFn }> => {
+    const requestId = crypto.randomUUID();
+    // Subscribed before invoking so no chunk is missed
+    const unlisten = await listen<MessageStreamEvent>('message-stream', ({ payload }) => {
+      if (payload.request_id !== requestId) return;
+      onEvent(payload);
+      if (payload.type !== 'chunk') unlisten();
+    });
+
+    const request: SendMessageRequest = {
+      llm_id: llmId,
+      content,
+      context,
+      conversation_id: conversationId,
+      request_id: requestId,
+      images,
+      template,
+      variables,
+    };
+    try {
+      await invoke<string>('send_message_stream', { request });
+    } catch (error) {
+      unlisten();
+      throw error;
+    }
+    return { requestId, unlisten };
+  };
+
+  // Sends one prompt to several LLMs at once; `onEvent` gets every event of every reply, each
+  // ending with done, error or cancelled, and `done` carries the reply's latency and cost
+  const compareMessage = async (
+    llmIds: string[],
+    content: string,
+    onEvent: (event: MessageStreamEvent) => void
+  ): Promise<{ requests: ComparedRequest[]; unlisten: UnlistenFn }> => {
+    const requestIds = llmIds.map(() => crypto.randomUUID());
+    const pending = new Set(requestIds);
+    // Subscribed before invoking so no chunk is missed
+    const unlisten = await listen<MessageStreamEvent>('message-stream', ({ payload }) => {
+      if (!pending.has(payload.request_id)) return;
+      onEvent(payload);
+      if (payload.type !== 'chunk') {
+        pending.delete(payload.request_id);
+        if (pending.size === 0) unlisten();
+      }
+    });
+
+    const request: CompareMessageRequest = { llm_ids: llmIds, content, request_ids: requestIds };
+    try {
+      const requests = await invoke<ComparedRequest[]>('compare_message', { request });
+      return { requests, unlisten };
+    } catch (error) {
+      unlisten();
+      throw error;
+    }
+  };
+
+  // Stops the stream and frees the LLM; the stream's last event is `cancelled`
+  const cancelGeneration = async (requestId: string): Promise<boolean> => {
+    return await invoke<boolean>('cancel_generation', { requestId });
+  };
+
+  // Resolves once every LLM has run the suite, which can take minutes for local models
+  const runBenchmark = async (options: BenchmarkOptions): Promise<BenchmarkRun> => {
+    return await invoke<BenchmarkRun>('run_benchmark', { options });
+  };
+
+  const listBenchmarks = async (): Promise<BenchmarkRun[]> => {
+    return await invoke<BenchmarkRun[]>('list_benchmarks');
+  };
+
+  // Both candidates answer every prompt and the judge LLM scores each pair; can take many minutes
+  const runEvaluation = async (request: EvaluationRequest): Promise<EvaluationReport> => {
+    return await invoke<EvaluationReport>('run_evaluation', { request });
+  };
+
+  // Newest first
+  const listEvaluations = async (): Promise<EvaluationReport[]> => {
+    return await invoke<EvaluationReport[]>('list_evaluations');
+  };
+
+  // Tokens and dollars of finished completions, by day, LLM or conversation
+  const getUsageSummary = async (query: UsageQuery): Promise<UsageSummary> => {
+    return await invoke<UsageSummary>('get_usage_summary', { query });
+  };
+
+  const pauseCloudProviders = async (paused: boolean): Promise<void> => {
+    await invoke('pause_cloud_providers', { paused });
+  };
+
+  const setOfflineMode = async (offline: boolean): Promise<void> => {
+    await invoke('set_offline_mode', { offline });
+  };
+
+  // Provider Key Commands
+  const setProviderKey = async (provider: CloudProvider, apiKey: string): Promise<ProviderKeyResponse> => {
+    return await invoke<ProviderKeyResponse>('set_provider_key', { provider, apiKey });
+  };
+
+  const testProviderKey = async (provider: CloudProvider, apiKey?: string): Promise<boolean> => {
+    return await invoke<boolean>('test_provider_key', { provider, apiKey });
+  };
+
+  const removeProviderKey = async (provider: CloudProvider): Promise<ProviderKeyResponse> => {
+    return await invoke<ProviderKeyResponse>('remove_provider_key', { provider });
+  };
+
+  // Resolves with the whole completion once the stream finishes
+  const sendMessage = (
+    llmId: string,
+    content: string,
+    context?: Record<string, any>,
+    conversationId?: string
   ): Promise<SendMessageResponse> => {
-    const request: SendMessageRequest = { llm_id: llmId, content, context };
-    return await invoke<SendMessageResponse>('send_message', { request });
+    return new Promise((resolve, reject) => {
+      let response = '';
+      sendMessageStream(
+        llmId,
+        content,
+        (event) => {
+          if (event.type === 'chunk') response += event.content;
+          else if (event.type === 'done') resolve({ content: response, llm_id: llmId });
+          else if (event.type === 'error') reject(new Error(event.message));
+          else reject(new Error('Message was cancelled'));
+        },
+        context,
+        conversationId
+      ).catch(reject);
+    });
+  };
+
+  // Voice Input Commands
+  const startVoiceInput = async (): Promise<void> => {
+    await invoke('start_voice_input');
+  };
+
+  // Stops recording and returns the transcript; the audio never leaves the machine
+  const stopVoiceInput = async (): Promise<VoiceTranscript> => {
+    return await invoke<VoiceTranscript>('stop_voice_input');
+  };
+
+  const cancelVoiceInput = async (): Promise<boolean> => {
+    return await invoke<boolean>('cancel_voice_input');
+  };
+
+  // Screenshot Commands
+  const listCaptureWindows = async (): Promise<CaptureWindow[]> => {
+    return await invoke<CaptureWindow[]>('list_capture_windows');
+  };
+
+  // Captures and stores the image; send it with `sendMessageStream(..., images)` to a vision-capable LLM
+  const captureScreenshot = async (target: CaptureTarget): Promise<ScreenshotAttachment> => {
+    return await invoke<ScreenshotAttachment>('capture_screenshot', { target });
+  };
+
+  // Model Download Commands
+  const searchModels = async (query: string, limit?: number): Promise<ModelSearchResult[]> => {
+    return await invoke<ModelSearchResult[]>('search_models', { query, limit });
+  };
+
+  // Starts a download; `onEvent` gets every event for it until done, error or cancelled
+  const downloadModel = async (
+    repoId: string,
+    filename: string,
+    onEvent: (event: ModelDownloadEvent) => void
+  ): Promise<{ downloadId: string; unlisten: UnlistenFn }> => {
+    // Only one download per file runs at a time, so events are matched by file until the id is known
+    const unlisten = await listen<ModelDownloadEvent>('model-download', ({ payload }) => {
+      if (payload.repo_id !== repoId || payload.filename !== filename) return;
+      onEvent(payload);
+      if (payload.type !== 'progress') unlisten();
+    });
+
+    try {
+      const downloadId = await invoke<string>('download_model', { repoId, filename });
+      return { downloadId, unlisten };
+    } catch (error) {
+      unlisten();
+      throw error;
+    }
+  };
+
+  const cancelDownload = async (downloadId: string): Promise<boolean> => {
+    return await invoke<boolean>('cancel_download', { downloadId });
+  };
+
+  const listLocalModels = async (): Promise<LocalModel[]> => {
+    return await invoke<LocalModel[]>('list_local_models');
+  };
+
+  const inspectModel = async (filename: string): Promise<ModelInfo> => {
+    return await invoke<ModelInfo>('inspect_model', { filename });
+  };
+
+  // Conversation Commands
+  const createConversation = async (title?: string): Promise<Conversation> => {
+    return await invoke<Conversation>('create_conversation', { title });
+  };
+
+  const listConversations = async (trashed = false): Promise<Conversation[]> => {
+    return await invoke<Conversation[]>('list_conversations', { trashed });
+  };
+
+  const getConversation = async (conversationId: string): Promise<Message[]> => {
+    const messages = await invoke<(Omit<Message, 'llm_id'> & { metadata: Record<string, any> })[]>(
+      'get_conversation',
+      { conversationId }
+    );
+    return messages.map(({ metadata, ...message }) => ({ ...message, llm_id: metadata.llm_id, usage: metadata.usage }));
+  };
+
+  // Summed over the conversation's replies
+  const getConversationUsage = async (conversationId: string): Promise<Usage> => {
+    return await invoke<Usage>('get_conversation_usage', { conversationId });
+  };
+
+  const renameConversation = async (conversationId: string, title: string): Promise<Conversation> => {
+    return await invoke<Conversation>('rename_conversation', { conversationId, title });
+  };
+
+  // Moves the conversation to the trash
+  const deleteConversation = async (conversationId: string): Promise<Conversation> => {
+    return await invoke<Conversation>('delete_conversation', { conversationId });
+  };
+
+  const restoreConversation = async (conversationId: string): Promise<Conversation> => {
+    return await invoke<Conversation>('restore_conversation', { conversationId });
+  };
+
+  // Transcript with model names, timestamps, citations and a cost summary
+  const exportConversation = async (
+    conversationId: string,
+    format: ExportFormat
+  ): Promise<ExportConversationResponse> => {
+    return await invoke<ExportConversationResponse>('export_conversation', { conversationId, format });
+  };
+
+  // Prompt Template Commands
+  const listPromptTemplates = async (): Promise<PromptTemplate[]> => {
+    return await invoke<PromptTemplate[]>('list_prompt_templates');
+  };
+
+  // The latest version unless `version` is given
+  const getPromptTemplate = async (templateId: string, version?: number): Promise<PromptTemplate> => {
+    return await invoke<PromptTemplate>('get_prompt_template', { templateId, version });
+  };
+
+  // Newest first
+  const getPromptTemplateHistory = async (templateId: string): Promise<PromptTemplate[]> => {
+    return await invoke<PromptTemplate[]>('get_prompt_template_history', { templateId });
+  };
+
+  const createPromptTemplate = async (template: NewPromptTemplate): Promise<PromptTemplate> => {
+    return await invoke<PromptTemplate>('create_prompt_template', { template });
+  };
+
+  // Saved as the next version; earlier versions are kept
+  const updatePromptTemplate = async (templateId: string, template: NewPromptTemplate): Promise<PromptTemplate> => {
+    return await invoke<PromptTemplate>('update_prompt_template', { templateId, template });
+  };
+
+  const deletePromptTemplate = async (templateId: string): Promise<void> => {
+    await invoke('delete_prompt_template', { templateId });
   };
 
   // Document Commands
@@ -83,6 +489,41 @@ export function useTauriAPI() {
     return await invoke<UploadDocumentResponse>('upload_document', { request });
   };
 
+  const uploadArchive = async (file: File): Promise<UploadArchiveResponse> => {
+    const arrayBuffer = await file.arrayBuffer();
+    const base64 = btoa(
+      new Uint8Array(arrayBuffer).reduce((data, byte) => data + String.fromCharCode(byte), '')
+    );
+
+    const request: UploadDocumentRequest = {
+      name: file.name,
+      content: base64,
+      mime_type: file.type || 'application/octet-stream',
+    };
+
+    return await invoke<UploadArchiveResponse>('upload_archive', { request });
+  };
+
+  // Paths come from a Tauri file drop, so folders are read on the backend instead of in the browser
+  const uploadPaths = async (
+    paths: string[],
+    onProgress?: (progress: UploadProgress) => void
+  ): Promise<UploadPathsResponse> => {
+    const uploadId = crypto.randomUUID();
+    // Subscribed before invoking so no progress is missed
+    const unlisten = onProgress
+      ? await listen<UploadProgress>('upload-progress', ({ payload }) => {
+          if (payload.upload_id === uploadId) onProgress(payload);
+        })
+      : undefined;
+
+    try {
+      return await invoke<UploadPathsResponse>('upload_paths', { request: { paths, upload_id: uploadId } });
+    } finally {
+      unlisten?.();
+    }
+  };
+
   const uploadDocumentFromDialog = async (): Promise<UploadDocumentResponse | null> => {
     const selected = await open({
       multiple: false,
@@ -122,6 +563,57 @@ export function useTauriAPI() {
     return await invoke<DeleteDocumentResponse>('delete_document', { request });
   };
 
+  // Attached documents are retrieved first when answering in that conversation
+  const attachDocument = async (conversationId: string, documentId: string): Promise<Conversation> => {
+    return await invoke<Conversation>('attach_document', { conversationId, documentId });
+  };
+
+  const detachDocument = async (conversationId: string, documentId: string): Promise<Conversation> => {
+    return await invoke<Conversation>('detach_document', { conversationId, documentId });
+  };
+
+  // Also runs every recrawl.interval_minutes when set
+  const recrawlSources = async (): Promise<RecrawlReport> => {
+    return await invoke<RecrawlReport>('recrawl_sources');
+  };
+
+  // Storage Commands
+  const getStorageUsage = async (): Promise<FolderUsage[]> => {
+    return await invoke<FolderUsage[]>('get_storage_usage');
+  };
+
+  const listTrash = async (): Promise<TrashEntry[]> => {
+    return await invoke<TrashEntry[]>('list_trash');
+  };
+
+  const restoreFromTrash = async (entryId: string): Promise<TrashEntry> => {
+    return await invoke<TrashEntry>('restore_from_trash', { entryId });
+  };
+
+  const emptyTrash = async (): Promise<number> => {
+    return await invoke<number>('empty_trash');
+  };
+
+  const listRagVersions = async (filename: string): Promise<FileVersion[]> => {
+    return await invoke<FileVersion[]>('list_rag_versions', { filename });
+  };
+
+  const diffRagVersions = async (filename: string, from: number, to: number): Promise<string> => {
+    return await invoke<string>('diff_rag_versions', { filename, from, to });
+  };
+
+  const listFiles = async (query?: FileQuery): Promise<FileMetadata[]> => {
+    return await invoke<FileMetadata[]>('list_files', { query });
+  };
+
+  const findFilesByHash = async (sha256: string): Promise<FileHash[]> => {
+    return await invoke<FileHash[]>('find_files_by_hash', { sha256 });
+  };
+
+  const createShareLink = async (filename: string, ttlSecs?: number): Promise<ShareLink> => {
+    return await invoke<ShareLink>('create_share_link', { filename, ttlSecs });
+  };
+
   // Permission Commands
   const getPermissions = async (): Promise<Permissions> => {
     return await invoke<Permissions>('get_permissions');
@@ -132,31 +624,133 @@ export function useTauriAPI() {
     return await invoke<UpdatePermissionsResponse>('update_permissions', { request });
   };
 
+  // Profile Commands
+  const listProfiles = async (): Promise<ProfileList> => {
+    return await invoke<ProfileList>('list_profiles');
+  };
+
+  const createProfile = async (profile: NewProfile): Promise<Profile> => {
+    return await invoke<Profile>('create_profile', { profile });
+  };
+
+  // Conversations, documents, permissions and provider keys change with the profile; reload them after
+  const switchProfile = async (name: string, passphrase?: string): Promise<Profile> => {
+    return await invoke<Profile>('switch_profile', { name, passphrase });
+  };
+
+  const deleteProfile = async (name: string, passphrase?: string): Promise<void> => {
+    await invoke('delete_profile', { name, passphrase });
+  };
+
+  const getEncryptionStatus = async (): Promise<EncryptionStatus> => {
+    return await invoke<EncryptionStatus>('get_encryption_status');
+  };
+
+  // Turns encryption on, encrypting what is already stored, or unlocks it where the keyring lost the key
+  const enableEncryption = async (passphrase: string): Promise<EncryptionStatus> => {
+    return await invoke<EncryptionStatus>('enable_encryption', { passphrase });
+  };
+
+  // Backup Commands
+  const createBackup = async (): Promise<BackupInfo> => {
+    return await invoke<BackupInfo>('create_backup');
+  };
+
+  const listBackups = async (): Promise<BackupInfo[]> => {
+    return await invoke<BackupInfo[]>('list_backups');
+  };
+
+  // Ask for a restart afterwards; restored settings and files are read at startup
+  const restoreBackup = async (path: string): Promise<RestoreReport> => {
+    return await invoke<RestoreReport>('restore_backup', { path });
+  };
+
+  // Plugin Commands
+  const listPlugins = async (): Promise<PluginList> => {
+    return await invoke<PluginList>('list_plugins');
+  };
+
+  // Approval Commands
+  const listPendingApprovals = async (): Promise<PendingApproval[]> => {
+    return await invoke<PendingApproval[]>('list_pending_approvals');
+  };
+
+  const simulatePlan = async (plan: PlannedAction[], llmId?: string): Promise<SimulatedDecision[]> => {
+    return await invoke<SimulatedDecision[]>('simulate_plan', { plan, llmId });
+  };
+
+  const approveRequest = async (requestId: string): Promise<void> => {
+    await invoke('approve_request', { requestId });
+  };
+
+  const denyRequest = async (requestId: string): Promise<void> => {
+    await invoke('deny_request', { requestId });
+  };
+
+  const onApprovalEvent = (onEvent: (event: ApprovalEvent) => void): Promise<UnlistenFn> => {
+    return listen<ApprovalEvent>('approval', ({ payload }) => onEvent(payload));
+  };
+
+  // Native notifications are only shown while no app window has focus
+  const onNotificationOpen = (onOpen: (target: NotificationTarget) => void): Promise<UnlistenFn> => {
+    return listen<NotificationTarget>('notification-open', ({ payload }) => onOpen(payload));
+  };
+
   // Audit Commands
-  const getAuditLog = async (): Promise<AuditLogEntry[]> => {
-    return await invoke<AuditLogEntry[]>('get_audit_log');
+  const getAuditLog = async (query?: AuditQuery): Promise<AuditPage> => {
+    return await invoke<AuditPage>('get_audit_log', { query });
+  };
+
+  // Each entry as the security engine logs it
+  const onAuditEntry = (onEntry: (entry: AuditLogEntry) => void): Promise<UnlistenFn> => {
+    return listen<AuditLogEntry>('audit-entry', ({ payload }) => onEntry(payload));
+  };
+
+  // Security Window Commands
+  // Always on top; opening it again brings it to the front
+  const openSecurityWindow = async (): Promise<void> => {
+    await invoke('open_security_window');
+  };
+
+  const closeSecurityWindow = async (): Promise<void> => {
+    await invoke('close_security_window');
   };
 
   // Sandbox Commands
   const createSandbox = async (
-    name: string,
-    config?: CreateSandboxRequest['config']
+    purpose: string,
+    template?: SandboxTemplate
   ): Promise<CreateSandboxResponse> => {
-    const request: CreateSandboxRequest = { name, config };
+    const request: CreateSandboxRequest = { purpose, template };
     return await invoke<CreateSandboxResponse>('create_sandbox', { request });
   };
 
+  // Omit `language` to run `code` as a shell command; `onOutput` gets each line as it is printed
   const executeInSandbox = async (
     sandboxId: string,
     code: string,
-    language: string
+    language?: CodeLanguage,
+    onOutput?: (event: SandboxOutputEvent) => void
   ): Promise<ExecuteInSandboxResponse> => {
+    const executionId = crypto.randomUUID();
+    // Subscribed before invoking so no output is missed
+    const unlisten = onOutput
+      ? await listen<SandboxOutputEvent>('sandbox-output', ({ payload }) => {
+          if (payload.execution_id === executionId) onOutput(payload);
+        })
+      : undefined;
+
     const request: ExecuteInSandboxRequest = {
       sandbox_id: sandboxId,
       code,
       language,
+      execution_id: executionId,
     };
-    return await invoke<ExecuteInSandboxResponse>('execute_in_sandbox', { request });
+    try {
+      return await invoke<ExecuteInSandboxResponse>('execute_in_sandbox', { request });
+    } finally {
+      unlisten?.();
+    }
   };
 
   const getSandboxFiles = async (
@@ -167,17 +761,79 @@ export function useTauriAPI() {
     return await invoke<GetSandboxFilesResponse>('get_sandbox_files', { request });
   };
 
+  const getSandboxDiff = async (sandboxId: string): Promise<SandboxFileChange[]> => {
+    return await invoke<SandboxFileChange[]>('get_sandbox_diff', { sandboxId });
+  };
+
+  const getSandboxUsage = async (sandboxId?: string): Promise<SandboxUsage[]> => {
+    return await invoke<SandboxUsage[]>('get_sandbox_usage', { sandboxId });
+  };
+
   const approveTransfer = async (
-    sandboxId: string,
-    filePath: string,
-    destination: string
-  ): Promise<ApproveTransferResponse> => {
+    transferId: string,
+    approved: boolean
+  ): Promise<void> => {
     const request: ApproveTransferRequest = {
-      sandbox_id: sandboxId,
-      file_path: filePath,
-      destination,
+      transfer_id: transferId,
+      approved,
     };
-    return await invoke<ApproveTransferResponse>('approve_transfer', { request });
+    await invoke('approve_transfer', { request });
+  };
+
+  const approvePortForward = async (requestId: string, approved: boolean): Promise<void> => {
+    await invoke('approve_port_forward', { requestId, approved });
+  };
+
+  const listPortForwards = async (): Promise<PortForward[]> => {
+    return await invoke<PortForward[]>('list_port_forwards');
+  };
+
+  const closePortForward = async (forwardId: string): Promise<void> => {
+    await invoke('close_port_forward', { forwardId });
+  };
+
+  const listSandboxTemplates = async (): Promise<SandboxTemplate[]> => {
+    return await invoke<SandboxTemplate[]>('list_sandbox_templates');
+  };
+
+  const listSnapshots = async (): Promise<SandboxSnapshot[]> => {
+    return await invoke<SandboxSnapshot[]>('list_snapshots');
+  };
+
+  const deleteSnapshot = async (snapshotId: string): Promise<void> => {
+    await invoke('delete_snapshot', { snapshotId });
+  };
+
+  const startKernel = async (sandboxId: string, language: CodeLanguage): Promise<KernelInfo> => {
+    return await invoke<KernelInfo>('start_kernel', { sandboxId, language });
+  };
+
+  const executeCell = async (kernelId: string, code: string): Promise<CellOutput> => {
+    return await invoke<CellOutput>('execute_cell', { kernelId, code });
+  };
+
+  const resetKernel = async (kernelId: string): Promise<void> => {
+    await invoke('reset_kernel', { kernelId });
+  };
+
+  const shutdownKernel = async (kernelId: string): Promise<void> => {
+    await invoke('shutdown_kernel', { kernelId });
+  };
+
+  const listKernels = async (sandboxId: string): Promise<KernelInfo[]> => {
+    return await invoke<KernelInfo[]>('list_kernels', { sandboxId });
+  };
+
+  const listVolumes = async (): Promise<SandboxVolume[]> => {
+    return await invoke<SandboxVolume[]>('list_volumes');
+  };
+
+  const createVolume = async (name: string): Promise<SandboxVolume> => {
+    return await invoke<SandboxVolume>('create_volume', { name });
+  };
+
+  const deleteVolume = async (name: string): Promise<void> => {
+    await invoke('delete_volume', { name });
   };
 
   return {
@@ -185,25 +841,138 @@ export function useTauriAPI() {
     getSystemState,
     triggerLockdown,
     releaseLockdown,
+    enterReadOnly,
+    exitReadOnly,
+    onSystemState,
+    getResourceUsage,
+    getHardwareInfo,
+    onResourceUsage,
+    takePendingDeepLinks,
+    getSession,
+    setOpenConversations,
+    onDeepLink,
+    getBrowserBridge,
+    resetBrowserBridgeToken,
+    getOpenAiApi,
+    resetOpenAiApiKey,
+    getSettings,
+    updateSettings,
     // LLMs
     getLLMs,
     loadLLM,
     unloadLLM,
     sendMessage,
+    sendMessageStream,
+    compareMessage,
+    cancelGeneration,
+    runBenchmark,
+    listBenchmarks,
+    runEvaluation,
+    listEvaluations,
+    getUsageSummary,
+    pauseCloudProviders,
+    setOfflineMode,
+    // Voice input
+    startVoiceInput,
+    stopVoiceInput,
+    cancelVoiceInput,
+    // Screenshots
+    listCaptureWindows,
+    captureScreenshot,
+    // Provider keys
+    setProviderKey,
+    testProviderKey,
+    removeProviderKey,
+    // Models
+    searchModels,
+    downloadModel,
+    cancelDownload,
+    listLocalModels,
+    inspectModel,
+    // Conversations
+    createConversation,
+    listConversations,
+    getConversation,
+    getConversationUsage,
+    renameConversation,
+    deleteConversation,
+    restoreConversation,
+    exportConversation,
+    // Prompt templates
+    listPromptTemplates,
+    getPromptTemplate,
+    getPromptTemplateHistory,
+    createPromptTemplate,
+    updatePromptTemplate,
+    deletePromptTemplate,
     // Documents
     uploadDocument,
     uploadDocumentFromDialog,
+    uploadArchive,
+    uploadPaths,
     getDocuments,
     deleteDocument,
+    attachDocument,
+    detachDocument,
+    recrawlSources,
+    // Storage
+    getStorageUsage,
+    listFiles,
+    findFilesByHash,
+    createShareLink,
+    listTrash,
+    restoreFromTrash,
+    emptyTrash,
+    listRagVersions,
+    diffRagVersions,
     // Permissions
     getPermissions,
     updatePermissions,
+    listProfiles,
+    createProfile,
+    switchProfile,
+    deleteProfile,
+    getEncryptionStatus,
+    enableEncryption,
+    // Backups
+    createBackup,
+    listBackups,
+    restoreBackup,
+    // Plugins
+    listPlugins,
+    // Approvals
+    listPendingApprovals,
+    simulatePlan,
+    approveRequest,
+    denyRequest,
+    onApprovalEvent,
+    onNotificationOpen,
     // Audit
     getAuditLog,
+    onAuditEntry,
+    // Security window
+    openSecurityWindow,
+    closeSecurityWindow,
     // Sandbox
     createSandbox,
     executeInSandbox,
     getSandboxFiles,
+    getSandboxDiff,
+    getSandboxUsage,
     approveTransfer,
+    approvePortForward,
+    listPortForwards,
+    closePortForward,
+    listSandboxTemplates,
+    listSnapshots,
+    deleteSnapshot,
+    listVolumes,
+    createVolume,
+    deleteVolume,
+    startKernel,
+    executeCell,
+    resetKernel,
+    shutdownKernel,
+    listKernels,
   };
 }
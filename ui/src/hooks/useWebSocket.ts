@@ -1,22 +1,40 @@
 import { useEffect, useRef, useState, useCallback } from 'react';
+import { invoke } from '@tauri-apps/api/tauri';
 import {
   WebSocketMessage,
+  WebSocketClientMessage,
+  WebSocketTopic,
   LLMStatusMessage,
-  DocumentUploadedMessage,
-  LockdownChangedMessage,
+  DocumentsQueuedMessage,
   SandboxOutputMessage,
+  SandboxEvent,
+  PtyClientMessage,
+  PtyServerMessage,
+  WebSocketSession,
 } from '../types/api';
+import { AuditLogEntry } from '../types';
 
-const WS_URL = 'ws://127.0.0.1:3030';
 const RECONNECT_DELAY = 3000;
 const MAX_RECONNECT_ATTEMPTS = 10;
 
 export interface WebSocketCallbacks {
   onLLMStatus?: (message: LLMStatusMessage) => void;
-  onDocumentUploaded?: (message: DocumentUploadedMessage) => void;
-  onLockdownChanged?: (message: LockdownChangedMessage) => void;
+  onDocumentsQueued?: (message: DocumentsQueuedMessage) => void;
+  onLockdownTriggered?: (reason: string) => void;
+  onSandboxEvent?: (event: SandboxEvent) => void;
   onSandboxOutput?: (message: SandboxOutputMessage) => void;
-  onAuditLog?: (message: any) => void;
+  onAuditLog?: (entry: AuditLogEntry) => void;
+  onPty?: (message: PtyServerMessage) => void;
+}
+
+// The server only sends the topics a client subscribes to, so subscribe to those with a callback
+function topicsFor(callbacks: WebSocketCallbacks): WebSocketTopic[] {
+  const topics: WebSocketTopic[] = [];
+  if (callbacks.onLLMStatus) topics.push('llm_status');
+  if (callbacks.onAuditLog || callbacks.onLockdownTriggered) topics.push('audit');
+  if (callbacks.onDocumentsQueued) topics.push('indexing');
+  if (callbacks.onSandboxEvent || callbacks.onSandboxOutput) topics.push('sandbox');
+  return topics;
 }
 
 export function useWebSocket(callbacks: WebSocketCallbacks) {
@@ -26,41 +44,61 @@ export function useWebSocket(callbacks: WebSocketCallbacks) {
   const reconnectAttemptsRef = useRef(0);
   const reconnectTimeoutRef = useRef<NodeJS.Timeout | null>(null);
 
-  const connect = useCallback(() => {
+  const connect = useCallback(async () => {
     if (wsRef.current?.readyState === WebSocket.OPEN) {
       return;
     }
 
     try {
-      const ws = new WebSocket(WS_URL);
+      // The server only accepts the app's pages presenting this session's token
+      const session = await invoke<WebSocketSession>('get_websocket_session');
+      const ws = new WebSocket(`${session.url}/?token=${encodeURIComponent(session.token)}`);
 
       ws.onopen = () => {
         console.log('WebSocket connected');
         setIsConnected(true);
         reconnectAttemptsRef.current = 0;
+
+        const topics = topicsFor(callbacks);
+        if (topics.length > 0) {
+          const subscribe: WebSocketClientMessage = { type: 'subscribe', topics };
+          ws.send(JSON.stringify(subscribe));
+        }
       };
 
       ws.onmessage = (event) => {
         try {
           const message: WebSocketMessage = JSON.parse(event.data);
+
+          // Terminal traffic is high-volume and not kept as lastMessage
+          if (message.type.startsWith('pty_')) {
+            callbacks.onPty?.(message as unknown as PtyServerMessage);
+            return;
+          }
+
           setLastMessage(message);
 
           // Route message to appropriate callback
           switch (message.type) {
+            case 'subscribed':
+              break;
             case 'llm_status':
-              callbacks.onLLMStatus?.(message.payload as LLMStatusMessage);
+              callbacks.onLLMStatus?.(message);
               break;
-            case 'document_uploaded':
-              callbacks.onDocumentUploaded?.(message.payload as DocumentUploadedMessage);
+            case 'documents_queued':
+              callbacks.onDocumentsQueued?.(message);
               break;
-            case 'lockdown_changed':
-              callbacks.onLockdownChanged?.(message.payload as LockdownChangedMessage);
+            case 'audit_log_entry':
+              callbacks.onAuditLog?.(message.entry);
               break;
-            case 'sandbox_output':
-              callbacks.onSandboxOutput?.(message.payload as SandboxOutputMessage);
+            case 'lockdown_triggered':
+              callbacks.onLockdownTriggered?.(message.reason);
               break;
-            case 'audit_log':
-              callbacks.onAuditLog?.(message.payload);
+            case 'sandbox':
+              callbacks.onSandboxEvent?.(message.event);
+              break;
+            case 'sandbox_output':
+              callbacks.onSandboxOutput?.(message);
               break;
             default:
               console.warn('Unknown WebSocket message type:', message.type);
@@ -111,7 +149,7 @@ export function useWebSocket(callbacks: WebSocketCallbacks) {
     setIsConnected(false);
   }, []);
 
-  const send = useCallback((message: any) => {
+  const send = useCallback((message: WebSocketClientMessage) => {
     if (wsRef.current?.readyState === WebSocket.OPEN) {
       wsRef.current.send(JSON.stringify(message));
     } else {
@@ -119,6 +157,13 @@ export function useWebSocket(callbacks: WebSocketCallbacks) {
     }
   }, []);
 
+  const sendPty = useCallback(
+    (message: PtyClientMessage) => {
+      send(message);
+    },
+    [send]
+  );
+
   useEffect(() => {
     connect();
 
@@ -131,6 +176,7 @@ export function useWebSocket(callbacks: WebSocketCallbacks) {
     isConnected,
     lastMessage,
     send,
+    sendPty,
     connect,
     disconnect,
   };
@@ -143,15 +189,15 @@ export function useLLMStatus(callback: (message: LLMStatusMessage) => void) {
   });
 }
 
-export function useDocumentUpdates(callback: (message: DocumentUploadedMessage) => void) {
+export function useDocumentUpdates(callback: (message: DocumentsQueuedMessage) => void) {
   return useWebSocket({
-    onDocumentUploaded: callback,
+    onDocumentsQueued: callback,
   });
 }
 
-export function useLockdownUpdates(callback: (message: LockdownChangedMessage) => void) {
+export function useLockdownUpdates(callback: (reason: string) => void) {
   return useWebSocket({
-    onLockdownChanged: callback,
+    onLockdownTriggered: callback,
   });
 }
 
@@ -1,10 +1,14 @@
 import React from 'react';
 import ReactDOM from 'react-dom/client';
 import App from './App';
+import SecurityWindow from './pages/SecurityWindow';
 import './styles/globals.css';
 
+// The security window loads the same bundle with a `#security` fragment
+const isSecurityWindow = window.location.hash === '#security';
+
 ReactDOM.createRoot(document.getElementById('root')!).render(
   <React.StrictMode>
-    <App />
+    {isSecurityWindow ? <SecurityWindow /> : <App />}
   </React.StrictMode>,
 );
@@ -1,12 +1,17 @@
-import { useState, useEffect } from 'react';
-import { AlertOctagon, RefreshCw, Terminal, Wifi, WifiOff } from 'lucide-react';
+import { useState, useEffect, useCallback, useMemo } from 'react';
+import { AlertOctagon, Cloud, CloudOff, RefreshCw, Shield, Terminal, Wifi, WifiOff } from 'lucide-react';
 import { SystemState, LLMInstance, Document, AuditLogEntry, PermissionScope, LLMStatus } from '../types';
+import { ask, message } from '@tauri-apps/api/dialog';
+import { DeepLink, UploadProgress } from '../types/api';
 import { useTauriAPI } from '../hooks/useTauriAPI';
 import DocumentUpload from '../components/DocumentUpload';
 import LLMManager from '../components/LLMManager';
 import PermissionControl from '../components/PermissionControl';
 import CodingCanvas from '../components/CodingCanvas';
+import ModelComparison from '../components/ModelComparison';
 import AuditLog from '../components/AuditLog';
+import BrowserExtensionPairing from '../components/BrowserExtensionPairing';
+import OpenAiApiAccess from '../components/OpenAiApiAccess';
 
 interface Props {
   systemState: SystemState;
@@ -29,8 +34,12 @@ export default function Dashboard({
   isConnected,
   api,
 }: Props) {
-  const [activeView, setActiveView] = useState<'overview' | 'canvas' | 'permissions' | 'audit'>('overview');
-  const [llmStatuses] = useState<Map<string, LLMStatus>>(new Map());
+  const [activeView, setActiveView] = useState<'overview' | 'compare' | 'canvas' | 'permissions' | 'audit'>('overview');
+  const llmStatuses = useMemo(
+    () => new Map<string, LLMStatus>(systemState.llms.map((status) => [status.llm_id, status])),
+    [systemState.llms]
+  );
+  const [uploadProgress, setUploadProgress] = useState<UploadProgress | null>(null);
   const [permissions, setPermissions] = useState<PermissionScope>({
     file_system: {
       read_paths: ['/home/*/downloads/*', '/rag/*'],
@@ -54,6 +63,16 @@ export default function Dashboard({
     },
   });
 
+  // A security alert notification leads to the audit log
+  useEffect(() => {
+    const unlisten = api.onNotificationOpen((target) => {
+      if (target.screen === 'audit') setActiveView('audit');
+    });
+    return () => {
+      unlisten.then((stop) => stop());
+    };
+  }, []);
+
   // Load permissions from backend on mount
   useEffect(() => {
     const loadPermissions = async () => {
@@ -78,6 +97,57 @@ export default function Dashboard({
     }
   };
 
+  const handleUploadPaths = useCallback(async (paths: string[]) => {
+    try {
+      const result = await api.uploadPaths(paths, setUploadProgress);
+      result.skipped.forEach((entry) => console.warn(`Skipped ${entry.path}: ${entry.reason}`));
+      onRefresh(); // Refresh to show new documents
+    } catch (err) {
+      console.error('Failed to upload dropped files:', err);
+    } finally {
+      setUploadProgress(null);
+    }
+  }, [api, onRefresh]);
+
+  // Nothing a link asks for runs without the user's say-so
+  const handleDeepLink = async (link: DeepLink) => {
+    if (link.action === 'index') {
+      if (await ask(`Upload and index ${link.path}?`, { title: 'Open link', type: 'warning' })) {
+        await handleUploadPaths([link.path]);
+      }
+      return;
+    }
+
+    if (!link.llm_id) {
+      await message('The link names no LLM and no default LLM is set.', { title: 'Open link', type: 'error' });
+      return;
+    }
+    if (!(await ask(`Send this prompt to ${link.llm_id}?\n\n${link.prompt}`, { title: 'Open link', type: 'warning' }))) {
+      return;
+    }
+    try {
+      const conversation = await api.createConversation();
+      const reply = await api.sendMessage(link.llm_id, link.prompt, undefined, conversation.id);
+      await message(reply.content, { title: link.llm_id });
+    } catch (err) {
+      console.error('Failed to send prompt from link:', err);
+    }
+  };
+
+  // Links that launched the app arrive before this mounts, so they are collected rather than pushed
+  useEffect(() => {
+    const collect = async () => {
+      for (const link of await api.takePendingDeepLinks()) {
+        await handleDeepLink(link);
+      }
+    };
+    collect();
+    const unlisten = api.onDeepLink(collect);
+    return () => {
+      unlisten.then((stop) => stop());
+    };
+  }, []);
+
   const handleLoadModel = async (id: string) => {
     try {
       await api.loadLLM(id);
@@ -120,6 +190,7 @@ export default function Dashboard({
               <nav className="flex gap-2 ml-8">
                 {[
                   { id: 'overview', label: 'Overview' },
+                  { id: 'compare', label: 'Compare' },
                   { id: 'canvas', label: 'Coding Canvas' },
                   { id: 'permissions', label: 'Permissions' },
                   { id: 'audit', label: 'Audit Log' },
@@ -154,6 +225,16 @@ export default function Dashboard({
                 )}
               </div>
 
+              <button
+                onClick={() =>
+                  api.setOfflineMode(!systemState.offline).then(onRefresh, (err) => console.error('Failed to switch offline mode:', err))
+                }
+                className="btn btn-secondary"
+                title={systemState.offline ? 'Go online' : 'Go offline'}
+              >
+                {systemState.offline ? <CloudOff size={16} /> : <Cloud size={16} />}
+              </button>
+
               <button
                 onClick={onRefresh}
                 className="btn btn-secondary"
@@ -162,6 +243,14 @@ export default function Dashboard({
                 <RefreshCw size={16} />
               </button>
 
+              <button
+                onClick={() => api.openSecurityWindow().catch((err) => console.error('Failed to open security window:', err))}
+                className="btn btn-secondary"
+                title="Open security window"
+              >
+                <Shield size={16} />
+              </button>
+
               <button
                 onClick={onPanicButton}
                 className="btn btn-danger flex items-center gap-2"
@@ -181,6 +270,17 @@ export default function Dashboard({
               </span>
             </div>
           )}
+
+          {systemState.offline && (
+            <div className="mt-4 p-3 bg-yellow-500/10 border border-yellow-500 rounded-lg flex items-center gap-2">
+              <CloudOff size={16} className="text-yellow-500" />
+              <span className="text-yellow-500 font-medium">
+                Offline: only local models answer and web access is blocked
+                {systemState.unavailable_capabilities.length > 0 &&
+                  `. Unavailable: ${systemState.unavailable_capabilities.join(', ')}`}
+              </span>
+            </div>
+          )}
         </div>
       </header>
 
@@ -197,20 +297,30 @@ export default function Dashboard({
             <DocumentUpload
               documents={documents}
               onUpload={handleDocumentUpload}
+              onUploadPaths={handleUploadPaths}
+              progress={uploadProgress}
             />
           </div>
         )}
 
+        {activeView === 'compare' && (
+          <ModelComparison llms={llms} api={api} />
+        )}
+
         {activeView === 'canvas' && (
           <CodingCanvas api={api} />
         )}
 
         {activeView === 'permissions' && (
-          <PermissionControl
-            permissions={permissions}
-            onUpdate={handlePermissionUpdate}
-            lockdownState={systemState.lockdown}
-          />
+          <div className="space-y-6">
+            <PermissionControl
+              permissions={permissions}
+              onUpdate={handlePermissionUpdate}
+              lockdownState={systemState.lockdown}
+            />
+            <BrowserExtensionPairing api={api} />
+            <OpenAiApiAccess api={api} />
+          </div>
         )}
 
         {activeView === 'audit' && (
@@ -224,6 +334,10 @@ export default function Dashboard({
           <div className="flex items-center gap-6">
             <span>Active LLMs: {systemState.active_llms.length}</span>
             <span>Pending Approvals: {systemState.pending_approvals}</span>
+            <span>
+              Cloud spend: ${systemState.budget.spent_usd.toFixed(2)}
+              {systemState.budget.monthly_cloud_usd !== null && ` / $${systemState.budget.monthly_cloud_usd.toFixed(2)}`}
+            </span>
             <span>Documents: {documents.length}</span>
           </div>
           <div>
@@ -0,0 +1,152 @@
+import { useState, useEffect } from 'react';
+import { AlertOctagon, Shield, Check, X } from 'lucide-react';
+import { AuditLogEntry } from '../types';
+import { PendingApproval, SystemState } from '../types/api';
+import { useTauriAPI } from '../hooks/useTauriAPI';
+import AuditLog from '../components/AuditLog';
+
+// Entries kept in the live view; older ones stay in the main window's audit log
+const MAX_LIVE_ENTRIES = 200;
+
+function describe(approval: PendingApproval): string {
+  switch (approval.kind) {
+    case 'permission':
+      return `Permission: ${approval.permission}`;
+    case 'artifact_transfer':
+      return `Transfer ${approval.file_path} to ${approval.destination}`;
+    case 'port_forward':
+      return `Forward port ${approval.sandbox_port} for ${Math.round(approval.duration_secs / 60)} min`;
+  }
+}
+
+// Always-on-top window opened with `open_security_window`; the main window's state isn't shared
+export default function SecurityWindow() {
+  const api = useTauriAPI();
+  const [entries, setEntries] = useState<AuditLogEntry[]>([]);
+  const [approvals, setApprovals] = useState<PendingApproval[]>([]);
+  const [lockdown, setLockdown] = useState<SystemState['lockdown_state']>('Normal');
+  const [error, setError] = useState<string | null>(null);
+
+  const loadApprovals = async () => {
+    try {
+      setApprovals(await api.listPendingApprovals());
+    } catch (err) {
+      console.error('Failed to load pending approvals:', err);
+    }
+  };
+
+  const loadLockdown = async () => {
+    try {
+      setLockdown((await api.getSystemState()).lockdown_state);
+    } catch (err) {
+      console.error('Failed to load system state:', err);
+    }
+  };
+
+  useEffect(() => {
+    api
+      .getAuditLog({ limit: MAX_LIVE_ENTRIES })
+      .then((page) => setEntries(page.entries))
+      .catch((err) => console.error('Failed to load audit log:', err));
+    loadApprovals();
+    loadLockdown();
+
+    const unlisten = [
+      api.onAuditEntry((entry) => setEntries((prev) => [entry, ...prev].slice(0, MAX_LIVE_ENTRIES))),
+      api.onApprovalEvent(() => loadApprovals()),
+      api.onSystemState((state) => setLockdown(state.lockdown_state)),
+    ];
+    return () => {
+      unlisten.forEach((pending) => pending.then((stop) => stop()));
+    };
+  }, []);
+
+  const decide = async (approval: PendingApproval, approved: boolean) => {
+    try {
+      if (approved) {
+        await api.approveRequest(approval.id);
+      } else {
+        await api.denyRequest(approval.id);
+      }
+      setApprovals((prev) => prev.filter((a) => a.id !== approval.id));
+    } catch (err) {
+      setError(err instanceof Error ? err.message : String(err));
+    }
+  };
+
+  const handleLockdown = async () => {
+    try {
+      const result = await api.triggerLockdown('User pressed panic button in the security window');
+      setLockdown(result.new_state);
+    } catch (err) {
+      setError('Failed to trigger lockdown');
+      console.error(err);
+    }
+  };
+
+  return (
+    <div className="min-h-screen bg-gray-950 p-4 space-y-4">
+      <header className="flex items-center justify-between">
+        <h1 className="text-lg font-bold flex items-center gap-2">
+          <Shield size={20} className="text-primary-500" />
+          Security
+        </h1>
+        <span
+          className={`text-xs px-2 py-1 rounded-full ${
+            lockdown === 'Normal' ? 'bg-success-500/10 text-success-500' : 'bg-danger-500/10 text-danger-500'
+          }`}
+        >
+          {lockdown}
+        </span>
+      </header>
+
+      <button
+        onClick={handleLockdown}
+        className="btn btn-danger w-full flex items-center justify-center gap-2"
+        disabled={lockdown === 'Locked'}
+      >
+        <AlertOctagon size={16} />
+        PANIC LOCKDOWN
+      </button>
+
+      {error && (
+        <p className="text-sm text-danger-500" onClick={() => setError(null)}>
+          {error}
+        </p>
+      )}
+
+      <div className="card">
+        <h2 className="font-bold mb-3">Pending Approvals ({approvals.length})</h2>
+        {approvals.length === 0 ? (
+          <p className="text-sm text-gray-500">Nothing is waiting for you</p>
+        ) : (
+          <div className="space-y-2">
+            {approvals.map((approval) => (
+              <div key={approval.id} className="p-3 rounded-lg bg-gray-800 border border-gray-700">
+                <div className="flex items-center justify-between gap-2 mb-1">
+                  <span className="text-sm font-medium">{describe(approval)}</span>
+                  <span className="text-xs px-2 py-0.5 bg-gray-700 rounded-full text-gray-400">
+                    {approval.llm_id}
+                  </span>
+                </div>
+                <p className="text-xs text-gray-400 mb-2">{approval.explanation}</p>
+                <div className="flex gap-2">
+                  <button onClick={() => decide(approval, true)} className="btn btn-primary flex-1 flex items-center justify-center gap-1">
+                    <Check size={14} />
+                    Approve
+                  </button>
+                  <button onClick={() => decide(approval, false)} className="btn btn-secondary flex-1 flex items-center justify-center gap-1">
+                    <X size={14} />
+                    Deny
+                  </button>
+                </div>
+              </div>
+            ))}
+          </div>
+        )}
+      </div>
+
+      <AuditLog entries={entries} />
+    </div>
+  );
+}
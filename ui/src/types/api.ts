@@ -1,12 +1,55 @@
 // Tauri API Request/Response Types
 
+import { AuditLogEntry, BudgetState, Capability, Document, LLMInstance, LLMStatus, Permissions, Usage } from './index';
+
 // System Commands
+export interface WebSocketSession {
+  url: string;
+  token: string; // Sent as `?token=`; minted each time the app starts
+}
+
 export interface SystemState {
+  profile: string; // Name of the active profile
   lockdown_state: 'Normal' | 'ReadOnly' | 'Locked';
-  active_llms: number;
-  total_documents: number;
-  sandbox_count: number;
-  uptime_seconds: number;
+  active_llms: string[]; // Loaded LLMs
+  llms: LLMStatus[];
+  pending_approvals: number;
+  cloud_paused: boolean; // Cloud providers take no requests while paused
+  offline: boolean; // Only local models answer and network access is refused
+  unavailable_capabilities: Capability[]; // Registered but with no available LLM, e.g. vision while offline
+  budget: BudgetState;
+}
+
+// Memory one registered LLM takes, or would take once loaded
+export interface LLMMemory {
+  llm_id: string;
+  loaded: boolean;
+  ram_mb: number | null; // null for cloud providers
+  vram_mb: number;
+}
+
+// Snapshot from get_resource_usage, also sent every few seconds (Tauri `resource-usage`)
+export interface ResourceUsage {
+  sampled_at: string;
+  cpu_percent: number;
+  ram_total_mb: number;
+  ram_used_mb: number;
+  ram_available_mb: number;
+  gpus: { index: number; name: string; total_vram_mb: number; reserved_vram_mb: number }[];
+  disk: { mount_point: string; total_mb: number; available_mb: number } | null; // Disk with the data directory
+  llms: LLMMemory[];
+  loadable: string[]; // Unloaded local LLMs that fit in the memory available now
+}
+
+// GPUs found by get_hardware_info; local models offload as many layers as fit unless configured
+export interface HardwareInfo {
+  gpu_offload: boolean; // Whether this llama.cpp build can use a GPU at all
+  gpus: {
+    name: string;
+    backend: 'cuda' | 'metal' | 'vulkan';
+    total_vram_mb: number | null; // null for Vulkan devices
+    free_vram_mb: number | null;
+  }[];
 }
 
 export interface LockdownRequest {
@@ -18,11 +61,282 @@ export interface LockdownResponse {
   new_state: 'Normal' | 'ReadOnly' | 'Locked';
 }
 
+export interface ProviderKey {
+  enabled: boolean;
+  api_key_env: string; // Environment variable with the key, used when the OS keyring has none
+  model?: string; // The provider's default model when unset
+}
+
+// Persisted in settings.toml and validated on save
+export interface Settings {
+  version: number; // Config layout version; files from newer builds are refused
+  offline: boolean; // Refuse cloud providers and network access; also switched from the tray
+  providers: { claude: ProviderKey; openai: ProviderKey; gemini: ProviderKey };
+  models: {
+    default_llm?: string;
+    speech_model?: string; // Whisper GGML file for voice input, relative to paths.models_dir
+    speech_language?: string; // ISO 639-1 code; detected when unset
+  };
+  paths: { data_dir: string; models_dir: string; plugins_dir: string };
+  budgets: { monthly_cloud_usd?: number; max_tokens_per_request?: number };
+  security: {
+    max_failed_requests: number;
+    panic_hotkey: string; // OS-wide panic lockdown shortcut, e.g. 'CmdOrCtrl+Alt+Shift+P'; empty disables it
+    jailbreak_action: 'flag' | 'strip'; // For jailbreak scaffolding in prompts built from pages or other LLMs' answers
+  };
+  sandbox: {
+    backend: 'process' | 'firecracker';
+    max_sandboxes: number; // Warm ones included
+    max_queued: number; // Creations waiting for a slot; 0 refuses instead
+    warm_per_template: number; // 0 disables the warm pool
+    wasm: {
+      runtimes_dir: string; // python.wasm, quickjs.wasm
+      fuel: number; // Instruction budget per run
+      max_memory_mb: number;
+      timeout_secs: number;
+      max_snippet_kb: number; // Larger snippets run in a full sandbox
+    };
+  };
+  search: {
+    backend?: 'searxng' | 'brave'; // LLMs get no web_search tool when unset
+    searxng_url?: string;
+    brave_api_key_env: string;
+    cache_secs: number; // 0 turns the result cache off
+  };
+  fetch: {
+    max_kb: number; // Pages fetch_url downloads are cut off past this
+    timeout_secs: number;
+    respect_robots: boolean;
+  };
+  openai_api: {
+    enabled: boolean; // Off by default; serves the OpenAI chat-completions API on localhost
+    port: number;
+  };
+  telemetry: {
+    otlp_endpoint?: string; // OTLP/HTTP collector, e.g. 'http://localhost:4318'; nothing is exported when unset
+    headers: Record<string, string>; // Secrets belong in OTEL_EXPORTER_OTLP_HEADERS, not here
+    traces: boolean;
+    metrics: boolean;
+    sample_ratio: number; // 0 to 1
+    metrics_interval_secs: number;
+  };
+  backup: {
+    interval_hours?: number; // No scheduled backups when unset
+    keep: number; // Scheduled backups kept; ones made on demand are never removed
+    dir?: string; // 'backups' in paths.data_dir when unset
+  };
+  recrawl: {
+    interval_minutes?: number; // Only re-crawled on demand when unset
+    folders: string[]; // Absolute paths; text files are indexed into the uploads collection
+    urls: string[]; // Indexed into the browsing collection
+    stale_after_hours: number; // RAG results from sources not re-crawled for this long are flagged stale
+  };
+}
+
+export interface UpdateSettingsResponse {
+  settings: Settings;
+  restart_required: boolean; // e.g. after changing paths.data_dir, paths.plugins_dir, sandbox, search, fetch, openai_api or telemetry
+}
+
+// Provider Key Commands
+export type CloudProvider = 'claude' | 'openai' | 'gemini';
+
+export interface ProviderKeyResponse {
+  llm_id?: string; // LLM now serving the provider; unset when disabled or without a key
+}
+
 // LLM Commands
 export interface SendMessageRequest {
-  llm_id: string;
+  llm_id?: string; // Defaults to settings.models.default_llm, or a loaded model suited to the message's language when it isn't
   content: string;
   context?: Record<string, any>;
+  conversation_id?: string; // The message and the reply are appended to this conversation
+  request_id?: string; // Chosen by the caller so it can listen before the first chunk
+  images?: ImageInput[]; // Needs an LLM with the `vision` capability
+  template?: PromptTemplateRef; // Sent instead of `content`, which fills its {{input}}
+  variables?: Record<string, string>; // Values for the template's other placeholders
+}
+
+export interface ImageInput {
+  media_type: string; // e.g. "image/png"
+  data: string; // Base64
+}
+
+export interface CompareMessageRequest {
+  llm_ids: string[]; // 2 to 4 distinct LLMs
+  content: string;
+  request_ids?: string[]; // One per LLM in the same order, so the caller can listen before the first chunk
+}
+
+export interface ComparedRequest {
+  llm_id: string;
+  request_id: string;
+}
+
+// What `run_benchmark` measures; each LLM gets the standard prompt suite `repetitions` times
+export interface BenchmarkOptions {
+  llm_ids: string[];
+  repetitions?: number; // 1 to 10, default 1
+}
+
+export interface BenchmarkResult {
+  llm_id: string;
+  provider: LLMInstance['provider'];
+  model_name: string; // For local models the GGUF file, which names the quantization
+  runs: number;
+  failures: number;
+  failure_rate: number; // 0 to 1
+  mean_latency_ms: number; // Latency figures cover successful runs only
+  p95_latency_ms: number;
+  mean_first_token_ms: number;
+  tokens_per_second: number; // Output tokens per second after the first token
+  input_tokens: number;
+  output_tokens: number;
+  cost_usd?: number; // Unset when the provider didn't report its cost
+  errors: string[]; // Distinct failure messages, at most 5
+}
+
+export interface BenchmarkRun {
+  id: string;
+  started_at: string;
+  finished_at: string;
+  prompts: number; // Per LLM, repetitions included
+  results: BenchmarkResult[];
+}
+
+export interface UsageQuery {
+  group_by: 'day' | 'llm' | 'conversation'; // Days are UTC
+  from?: string; // Defaults to the start of the current month
+  to?: string; // Defaults to now
+  llm_id?: string;
+  cloud_only?: boolean; // Only paid cloud providers
+}
+
+export interface UsageSummary {
+  from: string;
+  to: string;
+  requests: number;
+  total: Usage;
+  // Days oldest first; LLMs and conversations by cost, highest first
+  groups: {
+    key: string | null; // `2026-03-14`, an LLM id or a conversation id; null outside any conversation
+    requests: number;
+    usage: Usage;
+  }[];
+}
+
+// Unset fields take the provider's defaults
+export interface GenerationOptions {
+  max_tokens?: number;
+  temperature?: number;
+  top_p?: number;
+  stop?: string[];
+  seed?: number;
+  response_format?: ResponseFormat; // Local models are held to it while sampling; others ignore it
+}
+
+// A shape the reply must take
+export type ResponseFormat =
+  | { type: 'json_object' }
+  | { type: 'json_schema'; schema: Record<string, unknown> }
+  | { type: 'grammar'; grammar: string }; // GBNF, starting at its `root` rule
+
+// A model and the settings it answers with
+export interface EvalCandidate {
+  llm_id: string;
+  system?: string;
+  options?: GenerationOptions;
+}
+
+export interface EvalCriterion {
+  name: string;
+  description: string; // What a high score means, shown to the judge
+}
+
+export interface EvalCase {
+  name: string;
+  messages: { role: 'user' | 'assistant' | 'system'; content: string }[]; // Ends with the user message to answer
+  reference?: string; // A known good answer
+}
+
+export type EvaluationSource =
+  | { type: 'conversations'; conversation_ids: string[] } // Every user message, with the conversation before it
+  | { type: 'test_set'; cases: EvalCase[] };
+
+export interface EvaluationRequest {
+  a: EvalCandidate;
+  b: EvalCandidate;
+  judge_llm_id: string;
+  criteria?: EvalCriterion[]; // Default correctness, helpfulness and clarity
+  source: EvaluationSource; // At most 100 prompts
+}
+
+export interface EvalAnswer {
+  content: string | null; // null when the candidate failed
+  error: string | null;
+  usage: Usage;
+}
+
+export interface EvalVerdict {
+  winner: 'a' | 'b' | 'tie';
+  scores_a: Record<string, number>; // 1 to 10 by criterion; empty when the other candidate failed
+  scores_b: Record<string, number>;
+  reasoning: string;
+}
+
+export interface EvaluationReport {
+  id: string;
+  started_at: string;
+  finished_at: string;
+  a: EvalCandidate;
+  b: EvalCandidate;
+  judge_llm_id: string;
+  criteria: EvalCriterion[];
+  cases: {
+    name: string;
+    a: EvalAnswer;
+    b: EvalAnswer;
+    verdict: EvalVerdict | null; // null when neither answered or the judge failed
+    judge_error: string | null;
+  }[];
+  summary: {
+    wins_a: number;
+    wins_b: number;
+    ties: number;
+    unjudged: number;
+    failures_a: number;
+    failures_b: number;
+    mean_scores_a: Record<string, number>;
+    mean_scores_b: Record<string, number>;
+    usage_a: Usage;
+    usage_b: Usage;
+    judge_usage: Usage;
+  };
+}
+
+// What `capture_screenshot` captures; regions are in screen coordinates
+export type CaptureTarget =
+  | { kind: 'screen' }
+  | { kind: 'region'; x: number; y: number; width: number; height: number }
+  | { kind: 'window'; window_id: number };
+
+export interface CaptureWindow {
+  id: number;
+  title: string;
+  app_name: string;
+}
+
+// A captured screenshot, stored in uploads; pass it as an `ImageInput` to attach it to a message
+export interface ScreenshotAttachment extends ImageInput {
+  filename: string;
+  width: number;
+  height: number;
+}
+
+// Voice input, transcribed on-device by `stop_voice_input`
+export interface VoiceTranscript {
+  text: string;
+  duration_ms: number;
 }
 
 export interface SendMessageResponse {
@@ -61,6 +375,33 @@ export interface UploadDocumentResponse {
   uploaded_at: string;
 }
 
+export interface SkippedEntry {
+  path: string;
+  reason: string;
+}
+
+export interface UploadArchiveResponse {
+  documents: Document[]; // One per extracted file
+  skipped: SkippedEntry[];
+}
+
+// Dropped files and folders, copied from disk in chunks
+export interface UploadPathsResponse {
+  documents: Document[]; // One per uploaded file, named after the dropped folder
+  skipped: SkippedEntry[];
+  queued_for_indexing: number; // Including earlier uploads
+}
+
+// Progress across all files of one upload (Tauri `upload-progress`)
+export interface UploadProgress {
+  upload_id: string;
+  files_total: number;
+  files_done: number;
+  bytes_total: number;
+  bytes_done: number;
+  current: string | null; // null once finished
+}
+
 export interface DeleteDocumentRequest {
   document_id: string;
 }
@@ -69,6 +410,148 @@ export interface DeleteDocumentResponse {
   success: boolean;
 }
 
+export interface RecrawlReport {
+  added: number;
+  changed: number; // Re-indexed as a new version
+  unchanged: number;
+  failed: string[]; // '<source>: <reason>'; their documents turn stale
+}
+
+// Storage Commands
+export type ManagedFolder = 'downloads' | 'uploads' | 'rag';
+
+export interface FolderUsage {
+  folder: ManagedFolder;
+  used_bytes: number;
+  quota_bytes?: number;
+  file_count: number;
+  near_quota: boolean; // At or above 90% of the quota
+}
+
+// Model Download Commands
+export interface ModelSearchResult {
+  repo_id: string; // e.g. "Qwen/Qwen2.5-Coder-7B-Instruct-GGUF"
+  downloads: number;
+  likes: number;
+  files: string[]; // GGUF files, e.g. one per quantization
+}
+
+export interface LocalModel {
+  filename: string;
+  path: string;
+  size_bytes: number;
+  modified: string;
+  gguf_version: number | null; // null if the file isn't a valid GGUF model
+}
+
+// Read from the GGUF header, without loading the model
+export interface ModelInfo {
+  name: string | null;
+  architecture: string | null; // e.g. "llama", "qwen2"
+  quantization: string | null; // e.g. "Q4_K_M"
+  parameter_count: number;
+  context_length: number | null;
+  layer_count: number | null; // Transformer blocks, offloaded to the GPU one by one
+  embedding_length: number | null;
+  embedding_only: boolean; // Registered as an embedding model for RAG rather than for chat
+  chat_template: string | null;
+  gguf_version: number;
+  file_size_bytes: number;
+}
+
+// Model download progress (Tauri `model-download`, WebSocket topic `models`)
+export type ModelDownloadEvent =
+  | { type: 'progress'; download_id: string; repo_id: string; filename: string; downloaded_bytes: number; total_bytes: number | null }
+  | { type: 'done'; download_id: string; repo_id: string; filename: string; model: LocalModel; llm_id: string } // Registered with the pool
+  | { type: 'error'; download_id: string; repo_id: string; filename: string; message: string } // Downloading again resumes
+  | { type: 'cancelled'; download_id: string; repo_id: string; filename: string };
+
+// Conversation Commands
+export interface Conversation {
+  id: string;
+  title: string; // Set by the user, otherwise the start of the first message
+  created_at: string;
+  last_activity: string;
+  message_count: number;
+  trashed_at: string | null;
+  document_ids: string[]; // Attached documents, retrieved first for this conversation
+}
+
+// Reusable prompt with {{variable}} placeholders, as saved by the user
+export interface NewPromptTemplate {
+  name: string; // Unique
+  description: string;
+  body: string;
+  model_overrides: Record<string, string>; // LLM id -> body used instead for that LLM
+}
+
+// One saved version of a prompt template; every edit adds a version
+export interface PromptTemplate extends NewPromptTemplate {
+  id: string;
+  version: number;
+  created_at: string;
+}
+
+export interface PromptTemplateRef {
+  id: string;
+  version?: number; // Latest when unset
+}
+
+export type ExportFormat = 'markdown' | 'json' | 'html';
+
+// Written to the downloads folder
+export interface ExportConversationResponse {
+  filename: string;
+  path: string;
+}
+
+export interface TrashEntry {
+  id: string;
+  folder: ManagedFolder;
+  filename: string;
+  size_bytes: number;
+  deleted_at: string;
+  expires_at: string; // Purged for good after this
+}
+
+export interface FileVersion {
+  version: number; // 1-based, increasing with every change
+  sha256: string;
+  size_bytes: number;
+  created_at: string;
+}
+
+export interface FileMetadata {
+  folder: ManagedFolder;
+  filename: string; // Relative to the folder
+  size_bytes: number;
+  modified: string;
+  sha256: string;
+  indexed: boolean; // Cleared whenever the content changes
+}
+
+export interface FileQuery {
+  folder?: ManagedFolder;
+  extension?: string; // Case-insensitive, with or without the leading dot
+  modified_after?: string;
+  modified_before?: string;
+  indexed?: boolean;
+}
+
+export interface ShareLink {
+  filename: string;
+  token: string;
+  expires_at: string;
+  url: string; // Served by the local WebSocket/HTTP server until it expires
+}
+
+export interface FileHash {
+  folder: ManagedFolder;
+  filename: string; // Relative to the folder
+  sha256: string;
+  size_bytes: number;
+}
+
 // Permission Commands
 export interface UpdatePermissionsRequest {
   permissions: Permissions;
@@ -78,17 +561,112 @@ export interface UpdatePermissionsResponse {
   success: boolean;
 }
 
+// Profile Commands
+// Each profile has its own conversations, RAG documents, permissions and provider keys
+export interface Profile {
+  name: string; // Lowercase letters, digits and dashes
+  sensitive: boolean; // Switching to it asks for its passphrase
+  permissions: Permissions;
+  created_at: string;
+}
+
+export interface NewProfile {
+  name: string;
+  sensitive?: boolean;
+  passphrase?: string; // Required for sensitive profiles, at least 8 characters; kept in the OS keyring
+  permissions?: Permissions; // Defaults to the built-in scope
+}
+
+export interface ProfileList {
+  active: string;
+  profiles: Profile[];
+}
+
+// Encryption at rest of the active profile's conversations and RAG content
+export interface EncryptionStatus {
+  available: boolean; // Only conversations kept in PostgreSQL can be encrypted
+  enabled: boolean; // A passphrase has been set
+  unlocked: boolean; // The key is at hand, from the keyring or the passphrase
+}
+
+// Backup Commands
+export interface BackupManifest {
+  format_version: number; // Archives from newer builds are refused
+  created_at: string;
+  app_version: string;
+  databases: string[]; // Profiles whose PostgreSQL tables are archived; conversations kept in memory are not
+  files: number; // From the data directory: audit log, ledgers, profiles, session and managed folders
+  scheduled: boolean; // Only scheduled backups are pruned past backup.keep
+}
+
+export interface BackupInfo {
+  path: string;
+  size: number;
+  manifest: BackupManifest;
+}
+
+export interface RestoreReport {
+  manifest: BackupManifest;
+  databases: string[]; // Profiles whose tables were replaced
+  skipped: string[]; // '<profile>: <reason>' for databases left alone
+  files: number;
+  restart_required: boolean; // Restored settings and files are read at startup
+}
+
+// Plugin Commands
+export type PluginKind = 'tool' | 'parser' | 'analyzer';
+
+export interface PluginManifest {
+  name: string;
+  version: string;
+  description: string;
+  kind: PluginKind;
+  component: string; // Relative to the plugin's directory
+  capabilities: { read_folders: string[] }; // Managed folders the plugin may read
+}
+
+export interface PluginToolInfo {
+  name: string;
+  description: string;
+  parameters: Record<string, unknown>; // JSON schema
+  read_only: boolean;
+}
+
+export interface PluginInfo {
+  manifest: PluginManifest;
+  directory: string;
+  tools: PluginToolInfo[]; // Only for tool plugins
+  extensions: string[]; // Only for parser plugins, e.g. 'docx'
+}
+
+export interface PluginFailure {
+  directory: string;
+  error: string;
+}
+
+export interface PluginList {
+  plugins: PluginInfo[];
+  failures: PluginFailure[];
+}
+
 // Sandbox Commands
+export type SandboxTemplate = 'python-data' | 'node' | 'rust' | 'shell-minimal';
+
 export interface CreateSandboxRequest {
+  llm_id?: string; // A sandbox for the user when omitted
+  purpose: string;
+  template?: SandboxTemplate;
+}
+
+// Mounted at `volumes/<name>` inside the sandbox
+export interface VolumeMount {
   name: string;
-  config?: SandboxConfig;
+  read_only?: boolean;
 }
 
-export interface SandboxConfig {
-  cpu_limit?: number;
-  memory_limit_mb?: number;
-  disk_limit_mb?: number;
-  timeout_seconds?: number;
+export interface GpuRequest {
+  vram_mb: number;
+  device?: number;
 }
 
 export interface CreateSandboxResponse {
@@ -98,15 +676,18 @@ export interface CreateSandboxResponse {
 
 export interface ExecuteInSandboxRequest {
   sandbox_id: string;
-  code: string;
-  language: string;
+  code: string; // A shell command when `language` is omitted
+  language?: CodeLanguage;
+  execution_id?: string; // Chosen by the caller so it can listen before the first output line
 }
 
 export interface ExecuteInSandboxResponse {
-  sandbox_id: string;
-  output: string;
+  stdout: string;
+  stderr: string;
   exit_code: number;
-  execution_time_ms: number;
+  duration_ms: number;
+  /** Killed for exceeding the sandbox's execution timeout */
+  timed_out: boolean;
 }
 
 export interface GetSandboxFilesRequest {
@@ -117,7 +698,8 @@ export interface GetSandboxFilesRequest {
 export interface SandboxFile {
   name: string;
   path: string;
-  size: number;
+  size_bytes: number;
+  modified: string;
   is_directory: boolean;
 }
 
@@ -126,41 +708,297 @@ export interface GetSandboxFilesResponse {
   files: SandboxFile[];
 }
 
-export interface ApproveTransferRequest {
+export interface SandboxFileChange {
+  path: string;
+  kind: 'created' | 'modified' | 'deleted';
+  /** Absent for deleted files */
+  size_bytes?: number;
+}
+
+export type CodeLanguage = 'python' | 'java_script' | 'rust';
+
+// Stateful interpreter sessions; Rust has no kernel
+export interface KernelInfo {
+  id: string;
   sandbox_id: string;
-  file_path: string;
-  destination: string;
+  language: CodeLanguage;
+  execution_count: number;
+  started_at: string;
+  alive: boolean;
 }
 
-export interface ApproveTransferResponse {
-  success: boolean;
-  transferred_path: string;
+export interface CellOutput {
+  execution_count: number;
+  stdout: string;
+  stderr: string;
+  /** Value of the cell's trailing expression */
+  result: string | null;
+  /** Traceback when the cell raised */
+  error: string | null;
+  duration_ms: number;
+  timed_out: boolean;
 }
 
-// WebSocket Message Types
-export interface WebSocketMessage {
-  type: 'llm_status' | 'document_uploaded' | 'lockdown_changed' | 'audit_log' | 'sandbox_output';
-  payload: any;
+export interface SandboxUsage {
+  sandbox_id: string;
+  timestamp: string;
+  /** Percentage of one core over the last sample interval */
+  cpu_percent: number;
+  memory_bytes: number;
+  disk_bytes: number;
+  network_sent_bytes: number;
+  network_received_bytes: number;
+  processes: number;
 }
 
-export interface LLMStatusMessage {
-  llm_id: string;
-  status: 'loaded' | 'unloaded' | 'processing';
+export interface PortForward {
+  id: string;
+  sandbox_id: string;
+  sandbox_port: number;
+  /** Port on 127.0.0.1 that reaches the sandbox port */
+  host_port: number;
+  created_at: string;
+  expires_at: string;
 }
 
-export interface DocumentUploadedMessage {
-  document_id: string;
+export type PortForwardCloseReason = 'expired' | 'closed' | 'sandbox_destroyed';
+
+export interface SandboxSnapshot {
+  id: string;
+  parent_sandbox: string;
+  size_bytes: number;
+  created_at: string;
+  config: {
+    id: string;
+    network_mode: 'none' | 'proxy_only' | 'full';
+    cpu_limit: number;
+    memory_limit_gb: number;
+    disk_limit_gb: number;
+    allowed_commands: string[];
+  };
+}
+
+export interface SandboxVolume {
   name: string;
+  created_at: string;
+  size_bytes: number;
+  attached_to: string[];
 }
 
-export interface LockdownChangedMessage {
-  old_state: 'Normal' | 'ReadOnly' | 'Locked';
-  new_state: 'Normal' | 'ReadOnly' | 'Locked';
-  reason: string;
+export interface ApproveTransferRequest {
+  transfer_id: string;
+  approved: boolean;
+}
+
+export type PermissionType =
+  | { file_read: { path: string } }
+  | { file_write: { path: string } }
+  | { file_execute: { path: string } }
+  | { command: { command: string } }
+  | { network_access: { url: string } }
+  | { resource_increase: { resource: string; amount: number } };
+
+// A step of a plan to check before running it
+export type PlannedAction =
+  | { kind: 'permission'; permission: PermissionType }
+  | { kind: 'command'; command: string };
+
+// What the security engine would decide on a planned step
+export type SimulatedDecision =
+  | { outcome: 'allowed' }
+  | { outcome: 'needs_approval'; reason: string } // The user would be asked
+  | { outcome: 'denied'; reason: string }; // Refused regardless, e.g. during a lockdown
+
+// A request waiting for the user; denied automatically at `expires_at`
+export type PendingApproval = {
+  id: string;
+  llm_id: string;
+  requested_at: string;
+  expires_at: string;
+} & (
+  | { kind: 'permission'; permission: PermissionType; explanation: string }
+  | { kind: 'artifact_transfer'; sandbox_id: string; file_path: string; destination: string; explanation: string }
+  | { kind: 'port_forward'; sandbox_id: string; sandbox_port: number; duration_secs: number; explanation: string }
+);
+
+// Approval requests and their outcomes (Tauri `approval`)
+export type ApprovalEvent =
+  | { type: 'requested'; approval: PendingApproval }
+  | { type: 'resolved'; id: string; approved: boolean };
+
+// Where a notification leads, sent when the main window is focused after one (Tauri `notification-open`)
+// Approvals open the security window; alerts lead to the audit log
+export type NotificationTarget =
+  | { screen: 'approval'; approval_id: string }
+  | { screen: 'audit' };
+
+// A `hybridllm://` link, collected with `take_pending_deep_links` after a `deep-link` event
+// Links can come from any web page, so each is confirmed with the user before it runs
+export type DeepLink =
+  | { action: 'chat'; prompt: string; llm_id: string | null } // null when no default LLM is set
+  | { action: 'index'; path: string };
+
+// What the last session had open, collected with `get_session` on startup
+export interface RestoredSession {
+  open_conversations: string[]; // Only those that still exist, in the order they were shown
+  active_conversation: string | null;
+  loaded_llms: string[]; // Being loaded again in the background
+  interrupted_approvals: PendingApproval[]; // Undecided when the app closed, so denied
+  saved_at: string | null; // null on a first launch
+}
+
+// Where the companion browser extension connects, and the token it is paired with
+export interface BrowserBridgeInfo {
+  url: string;
+  token: string; // Sent as `Authorization: Bearer`; kept in the OS keyring across restarts
+}
+
+// Where OpenAI-compatible clients such as editors and scripts connect
+export interface OpenAiApiInfo {
+  enabled: boolean; // Turned on with `openai_api.enabled` in the settings, after a restart
+  base_url: string; // e.g. 'http://127.0.0.1:3032/v1'
+  api_key: string; // The client's OpenAI API key; kept in the OS keyring across restarts
+}
+
+// Audit Commands
+// Unset filters match everything; timestamps are RFC 3339
+export interface AuditQuery {
+  llm_id?: string;
+  since?: string;
+  until?: string;
+  approved?: boolean;
+  // Only entries logged for one user action; a reply's trace is its request id
+  trace_id?: string;
+  offset?: number;
+  limit?: number;
+}
+
+// Newest first; `total` counts matches across all pages
+export interface AuditPage {
+  entries: AuditLogEntry[];
+  total: number;
+}
+
+// Streamed completion output (Tauri `message-stream`)
+export type MessageStreamEvent =
+  | { type: 'chunk'; request_id: string; llm_id: string; content: string }
+  | {
+      type: 'done';
+      request_id: string;
+      llm_id: string;
+      first_chunk_ms: number | null; // null when the reply was empty
+      usage: Usage;
+    }
+  | {
+      type: 'error';
+      request_id: string;
+      llm_id: string;
+      message: string;
+      code: ErrorCode;
+      retryable: boolean; // Sending the prompt again later may work, e.g. after a rate limit
+    }
+  | { type: 'cancelled'; request_id: string; llm_id: string };
+
+// Stable name of a backend error (`ErrorCode` in common)
+export type ErrorCode =
+  | 'llm_error'
+  | 'permission_denied'
+  | 'security_violation'
+  | 'lockdown_active'
+  | 'sandbox_error'
+  | 'database_error'
+  | 'file_system_error'
+  | 'network_error'
+  | 'config_error'
+  | 'llm_not_found'
+  | 'invalid_request'
+  | 'resource_limit_exceeded'
+  | 'file_rejected'
+  | 'duplicate_file'
+  | 'quota_exceeded'
+  | 'timeout'
+  | 'rate_limited'
+  | 'provider_overloaded'
+  | 'authentication_failed'
+  | 'other';
+
+// Sandbox execution output (Tauri `sandbox-output`)
+export type SandboxOutputEvent = { execution_id: string; sandbox_id: string } & (
+  | { type: 'stdout'; line: string }
+  | { type: 'stderr'; line: string }
+  | { type: 'finished'; result: ExecuteInSandboxResponse }
+);
+
+// Sandbox events (Tauri `sandbox-event`)
+export type SandboxEvent =
+  | { type: 'created'; sandbox_id: string; llm_id: string | null; template: SandboxTemplate | null }
+  | { type: 'queued'; sandbox_id: string; llm_id: string | null; position: number }
+  | { type: 'command_executed'; sandbox_id: string; llm_id: string | null; command: string; exit_code: number; duration_ms: number }
+  | { type: 'cell_executed'; sandbox_id: string; kernel_id: string; llm_id: string | null; code: string; execution_count: number; succeeded: boolean; duration_ms: number }
+  | { type: 'browser_action'; sandbox_id: string; browser_id: string; llm_id: string | null; action: string; url: string | null; error: string | null }
+  | { type: 'destroyed'; sandbox_id: string; llm_id: string | null }
+  | { type: 'port_forward_opened'; forward: PortForward; llm_id: string | null }
+  | { type: 'port_forward_closed'; forward: PortForward; llm_id: string | null; reason: PortForwardCloseReason }
+  | { type: 'resource_exceeded'; sandbox_id: string; resource: string; limit: number; actual: number }
+  | { type: 'network_connection'; sandbox_id: string; host: string; port: number; allowed: boolean }
+  | { type: 'execution_timed_out'; sandbox_id: string; llm_id: string | null; command: string; timeout_secs: number }
+  | { type: 'usage'; usage: SandboxUsage }
+  | { type: 'reclaimed'; sandbox_id: string; reason: 'expired' | 'idle' | 'orphaned' };
+
+// PTY sessions over the WebSocket (flat messages tagged by `type`)
+export type PtyClientMessage =
+  | { type: 'pty_open'; sandbox_id: string; cols: number; rows: number }
+  | { type: 'pty_input'; session_id: string; data: string }
+  | { type: 'pty_resize'; session_id: string; cols: number; rows: number }
+  | { type: 'pty_close'; session_id: string };
+
+export type PtyServerMessage =
+  | { type: 'pty_opened'; session_id: string; sandbox_id: string }
+  | { type: 'pty_output'; session_id: string; data: string }
+  | { type: 'pty_exited'; session_id: string; exit_code: number }
+  | { type: 'pty_error'; session_id: string | null; message: string };
+
+// WebSocket Message Types
+// Broadcast messages arrive only for topics the client subscribed to; PTY messages always do
+export type WebSocketTopic = 'llm_status' | 'audit' | 'indexing' | 'sandbox' | 'models';
+
+export type WebSocketClientMessage =
+  | { type: 'subscribe'; topics: WebSocketTopic[] }
+  | { type: 'unsubscribe'; topics: WebSocketTopic[] }
+  | PtyClientMessage;
+
+export type WebSocketMessage =
+  | { type: 'subscribed'; topics: WebSocketTopic[] } // On connect and after every (un)subscribe
+  | LLMStatusMessage
+  | { type: 'llm_response'; llm_id: string; content: string; is_final: boolean }
+  | DocumentsQueuedMessage
+  | { type: 'document_indexed'; document_id: string; chunk_count: number }
+  | { type: 'audit_log_entry'; entry: AuditLogEntry }
+  | { type: 'lockdown_triggered'; reason: string }
+  | { type: 'sandbox'; event: SandboxEvent }
+  | SandboxOutputMessage
+  | { type: 'model_download'; event: ModelDownloadEvent }
+  | PtyServerMessage;
+
+export interface LLMStatusMessage {
+  type: 'llm_status';
+  llm_id: string;
+  status: 'loaded' | 'unloaded';
+  current_task: string | null;
+}
+
+export interface DocumentsQueuedMessage {
+  type: 'documents_queued';
+  document_ids: string[];
+  waiting: number; // Documents waiting to be indexed, these included
 }
 
 export interface SandboxOutputMessage {
+  type: 'sandbox_output';
+  execution_id: string;
   sandbox_id: string;
-  output: string;
-  stream_type: 'stdout' | 'stderr';
+  event:
+    | { type: 'stdout'; line: string }
+    | { type: 'stderr'; line: string }
+    | { type: 'finished'; result: ExecuteInSandboxResponse };
 }
@@ -8,21 +8,66 @@ export interface LLMInstance {
   max_context: number;
 }
 
-export type Capability = 'code' | 'security' | 'general' | 'analysis' | 'creative';
+// Any other string is a tag of the user's own
+export type Capability =
+  | 'code'
+  | 'security'
+  | 'general'
+  | 'analysis'
+  | 'creative'
+  | 'embedding'
+  | 'vision'
+  | 'audio'
+  | 'multilingual'
+  | (string & {});
 
+// What one registered LLM is doing, from `get_system_state`
 export interface LLMStatus {
-  id: string;
-  status: 'idle' | 'processing' | 'error';
-  current_task?: string;
+  llm_id: string;
+  cloud: boolean;
+  loaded: boolean;
+  available: boolean; // False while cloud providers are paused
+  healthy: boolean | null; // null until the first health check
+  health_checked_at: string | null;
+  in_flight: number;
+  queued: number; // Waiting for the LLM to finish other requests
+  spent_usd: number; // This calendar month
+  usage: Usage; // Completed requests since the app started
+}
+
+// Cloud spending this calendar month against the cap in the settings
+export interface BudgetState {
+  monthly_cloud_usd: number | null;
+  spent_usd: number;
+}
+
+// Tokens, cost and time of one reply, or summed over several
+export interface Usage {
+  input_tokens: number;
+  output_tokens: number;
+  cost_usd: number | null; // 0 for local models; null when the provider doesn't say
+  latency_ms: number;
 }
 
 // Message Types
+// One part of a multi-part message
+export type ContentPart =
+  | { type: 'text'; text: string }
+  | { type: 'image'; media_type: string; data: string } // Base64 encoded
+  | { type: 'tool_call'; id: string; name: string; arguments: unknown }
+  | { type: 'tool_result'; call_id: string; name: string; output?: unknown; error?: string }
+  | { type: 'file'; document_id: string; name: string };
+
+// Plain text, or parts for images, tool calls and attached files
+export type MessageContent = string | ContentPart[];
+
 export interface Message {
   id: string;
   role: 'user' | 'assistant' | 'system';
-  content: string;
+  content: MessageContent;
   timestamp: string;
   llm_id?: string;
+  usage?: Usage; // Replies only
 }
 
 // Document Types
@@ -30,9 +75,12 @@ export interface Document {
   id: string;
   filename: string;
   size: number;
+  sha256: string;
   uploaded_at: string;
   indexed: boolean;
   chunk_count?: number;
+  // Set for pages added from the browser extension
+  url?: string;
 }
 
 // Permission Types
@@ -46,6 +94,7 @@ export interface PermissionScope {
     inbound: boolean;
     outbound: boolean;
     require_approval: boolean;
+    allowed_domains?: string[];
   };
   commands: {
     whitelist: string[];
@@ -57,6 +106,11 @@ export interface PermissionScope {
     max_memory_gb: number;
     max_disk_gb: number;
   };
+  /** Packages sandbox runners may install, keyed by language */
+  packages?: {
+    allowed: Partial<Record<'python' | 'java_script' | 'rust', string[]>>;
+    registries: Partial<Record<'python' | 'java_script' | 'rust', string>>;
+  };
 }
 
 // Audit Log Types
@@ -65,15 +119,30 @@ export interface AuditLogEntry {
   timestamp: string;
   llm_id?: string;
   action: string;
+  details: unknown;
   approved: boolean;
   reason?: string;
+  // Absent from entries logged outside a user action or background job
+  context?: RequestContext;
+}
+
+// Who and what an action was for; everything one user action causes shares its trace_id
+export interface RequestContext {
+  trace_id: string;
+  conversation_id: string | null;
+  llm_id: string | null;
+  user_initiated: boolean;
 }
 
 // System State
 export interface SystemState {
   lockdown: 'normal' | 'readonly' | 'locked';
   active_llms: string[];
+  llms: LLMStatus[];
   pending_approvals: number;
+  offline: boolean;
+  unavailable_capabilities: Capability[];
+  budget: BudgetState;
 }
 
 // Sandbox Types
@@ -85,9 +154,11 @@ export interface Sandbox {
 }
 
 export interface SandboxFile {
+  name: string;
   path: string;
-  size: number;
+  size_bytes: number;
   modified: string;
+  is_directory: boolean;
 }
 
 // File Transfer
@@ -97,5 +168,20 @@ export interface FileTransfer {
   source: string;
   destination: string;
   explanation: string;
+  size_bytes?: number;
+  sha256?: string;
+  scan?: ArtifactScanReport;
   approved?: boolean;
 }
+
+export interface ArtifactScanReport {
+  mime_type: string;
+  size_bytes: number;
+  findings: {
+    kind: 'secret' | 'disallowed_type' | 'type_mismatch' | 'size_limit' | 'malware';
+    description: string;
+    line?: number;
+  }[];
+  malware_scanned: boolean;
+  verdict: 'clean' | 'warning' | 'blocked';
+}